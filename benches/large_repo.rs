@@ -0,0 +1,211 @@
+//! How gibra copes with large repositories: a synthetic repository with 10k branches (or
+//! `GIBRA_BENCH_BRANCHES`), half of them tracking a remote-tracking branch they diverged from, is
+//! generated once under the target directory, with its refs and objects packed, and `gibra
+//! --filter` is timed on it.
+//!
+//! gibra is a binary, so it is run as a process, with a config and a cache of its own:
+//!
+//! - `collect` lists every branch with an empty query, which is mostly reading the branches and
+//!   counting how far they diverged from their upstream,
+//! - `enrich` adds the columns computed per branch, like `--age` and `--size`,
+//! - `match` adds typing queries of a few characters to `collect`, so the difference is the
+//!   matching.
+//!
+//! Save a baseline before a change with `cargo bench --bench large_repo -- --save-baseline main`
+//! and compare with `cargo bench --bench large_repo -- --baseline main`; criterion reports the
+//! benchmarks that regressed.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use git2::{BranchType, Oid, Repository, Signature, Time};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+const DEFAULT_BRANCHES: usize = 10_000;
+
+/// How many commits the default branch has, which the branches start from
+const HISTORY: usize = 200;
+
+const AREAS: [&str; 8] = [
+    "parser", "billing", "auth", "search", "ui", "sync", "cli", "docs",
+];
+
+const TOPICS: [&str; 8] = [
+    "fix-crash",
+    "add-retry",
+    "cleanup",
+    "speed-up",
+    "refactor",
+    "migrate",
+    "support-proxy",
+    "handle-timeout",
+];
+
+fn branch_count() -> usize {
+    env::var("GIBRA_BENCH_BRANCHES")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_BRANCHES)
+}
+
+/// A commit changing `file` to `content` on top of `parent`
+fn commit(repo: &Repository, parent: Option<Oid>, file: &str, content: &str, message: &str) -> Oid {
+    let signature =
+        Signature::new("bench", "bench@example.com", &Time::new(1_700_000_000, 0)).unwrap();
+    let parent = parent.map(|oid| repo.find_commit(oid).unwrap());
+    let mut tree = repo
+        .treebuilder(
+            parent
+                .as_ref()
+                .map(|parent| parent.tree().unwrap())
+                .as_ref(),
+        )
+        .unwrap();
+    let blob = repo.blob(content.as_bytes()).unwrap();
+    tree.insert(file, blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(None, &signature, &signature, message, &tree, &parents)
+        .unwrap()
+}
+
+/// The synthetic repository with `count` branches, generated on first use
+fn synthetic_repo(count: usize) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("large-repo-{}", count));
+    if dir.join(".git/gibra-bench-ready").exists() {
+        return dir;
+    }
+    let _ = fs::remove_dir_all(&dir);
+    let repo = Repository::init(&dir).unwrap();
+    repo.remote("origin", "https://example.com/bench.git")
+        .unwrap();
+
+    let mut history = vec![];
+    let mut tip = None;
+    for index in 0..HISTORY {
+        let oid = commit(
+            &repo,
+            tip,
+            "main.txt",
+            &format!("{}\n", index),
+            &format!("main {}", index),
+        );
+        history.push(oid);
+        tip = Some(oid);
+    }
+    let main = tip.unwrap();
+    repo.reference("refs/heads/main", main, true, "bench")
+        .unwrap();
+    repo.reference("refs/remotes/origin/main", main, true, "bench")
+        .unwrap();
+    repo.reference_symbolic(
+        "refs/remotes/origin/HEAD",
+        "refs/remotes/origin/main",
+        true,
+        "bench",
+    )
+    .unwrap();
+    repo.set_head("refs/heads/main").unwrap();
+    repo.checkout_head(None).unwrap();
+
+    for index in 0..count {
+        let name = format!(
+            "feature/{}-{}-{}",
+            AREAS[index % AREAS.len()],
+            index,
+            TOPICS[(index / AREAS.len()) % TOPICS.len()]
+        );
+        let base = history[index * 7 % HISTORY];
+        let oid = commit(
+            &repo,
+            Some(base),
+            &format!("{}.txt", index % 50),
+            &format!("{}\n", name),
+            &name,
+        );
+        let mut branch = repo
+            .branch(&name, &repo.find_commit(oid).unwrap(), true)
+            .unwrap();
+        // half of them pushed before the last commit
+        if index % 2 == 0 {
+            repo.reference(
+                &format!("refs/remotes/origin/{}", name),
+                base,
+                true,
+                "bench",
+            )
+            .unwrap();
+            branch
+                .set_upstream(Some(&format!("origin/{}", name)))
+                .unwrap();
+        }
+    }
+    assert!(repo.find_branch("main", BranchType::Local).is_ok());
+    // packed, as in a repository maintained by git gc
+    for args in [&["pack-refs", "--all"][..], &["repack", "-adq"]] {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(&dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    fs::write(dir.join(".git/gibra-bench-ready"), "").unwrap();
+    dir
+}
+
+/// Run gibra in `repo` with `args`, away from the config and the cache of the user
+fn gibra(repo: &Path, home: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_gibra"))
+        .args(args)
+        .current_dir(repo)
+        .env("XDG_CONFIG_HOME", home.join("config"))
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    // 11 when nothing matched
+    assert!(
+        status.code().is_some_and(|code| code == 0 || code == 11),
+        "{:?}",
+        args
+    );
+}
+
+fn large_repo(c: &mut Criterion) {
+    let count = branch_count();
+    let repo = synthetic_repo(count);
+    let home = Path::new(env!("CARGO_TARGET_TMPDIR")).join("large-repo-home");
+    fs::create_dir_all(&home).unwrap();
+
+    let mut group = c.benchmark_group(format!("{}-branches", count));
+    group
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(20));
+
+    group.bench_function("collect/local", |b| {
+        b.iter(|| gibra(&repo, &home, &["--local-only", "--filter", ""]))
+    });
+    group.bench_function("collect/all", |b| {
+        b.iter(|| gibra(&repo, &home, &["--filter", ""]))
+    });
+    for column in ["--age", "--size"] {
+        group.bench_with_input(BenchmarkId::new("enrich", column), column, |b, column| {
+            b.iter(|| gibra(&repo, &home, &[column, "--filter", ""]))
+        });
+    }
+    for query in ["pars", "bil 12 retry", "'auth-4", "zzzz"] {
+        group.bench_with_input(BenchmarkId::new("match", query), query, |b, query| {
+            b.iter(|| gibra(&repo, &home, &["--filter", query]))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, large_repo);
+criterion_main!(benches);
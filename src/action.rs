@@ -0,0 +1,190 @@
+use crate::bus::BranchAction;
+use crate::config::KeysConfig;
+use crate::skim::MenuEntry;
+
+/// An action that is run on the picked branches instead of checking them out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    FastForward,
+    Delete,
+    /// Merge gitflow branches back, see [`crate::gitflow::finish`]
+    Finish,
+    /// Open the picked branches in the browser
+    Web,
+    /// Track the same-named branches of a remote, see [`crate::upstream::track_all`]
+    SetUpstream,
+    /// Make a branch the default branch of its remote, see [`crate::default_branch::run`]
+    SetDefault,
+    /// Push the picked local branches to another remote than their upstream's, see
+    /// [`crate::push_remote::set`]
+    SetPushRemote,
+    /// Create a branch at a commit of the picked one, see [`crate::history::branch_off`]
+    BranchOff,
+    /// Fetch just the picked remote branches
+    FetchSelected,
+}
+
+impl Action {
+    const ALL: [Action; 9] = [
+        Action::FastForward,
+        Action::Delete,
+        Action::Finish,
+        Action::Web,
+        Action::SetUpstream,
+        Action::SetDefault,
+        Action::SetPushRemote,
+        Action::BranchOff,
+        Action::FetchSelected,
+    ];
+
+    fn key(self, keys: &KeysConfig) -> &str {
+        match self {
+            Action::FastForward => &keys.fast_forward,
+            Action::Delete => &keys.delete,
+            Action::Finish => &keys.finish,
+            Action::Web => &keys.web,
+            Action::SetUpstream => &keys.set_upstream,
+            Action::SetDefault => &keys.set_default,
+            Action::SetPushRemote => &keys.set_push_remote,
+            Action::BranchOff => &keys.branch_off,
+            Action::FetchSelected => &keys.fetch_selected,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Action::FastForward => "Fast-forward",
+            Action::Delete => "Delete",
+            Action::Finish => "Finish",
+            Action::Web => "Open in the browser",
+            Action::SetUpstream => "Set upstream",
+            Action::SetDefault => "Make the default branch",
+            Action::SetPushRemote => "Set push remote",
+            Action::BranchOff => "Branch off a commit",
+            Action::FetchSelected => "Fetch",
+        }
+    }
+
+    /// The name of the action on [`crate::bus`], for those that run while the picker stays open
+    fn emitted(self) -> Option<&'static str> {
+        match self {
+            Action::FastForward => Some(BranchAction::FAST_FORWARD),
+            Action::Delete => Some(BranchAction::DELETE),
+            Action::FetchSelected => Some(BranchAction::FETCH_SELECTED),
+            _ => None,
+        }
+    }
+
+    fn offered(gitflow: bool) -> impl Iterator<Item = Action> {
+        Action::ALL
+            .into_iter()
+            .filter(move |&action| gitflow || action != Action::Finish)
+    }
+
+    /// The keys to pass to skim's `expect` option, for the actions that close the picker.
+    /// Finishing is only offered with `--gitflow`.
+    pub fn expect_keys(keys: &KeysConfig, gitflow: bool) -> String {
+        Action::offered(gitflow)
+            .filter(|action| action.emitted().is_none())
+            .map(|action| action.key(keys))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// The bindings of the keys of the actions that run while the picker stays open
+    pub fn bindings(keys: &KeysConfig) -> Vec<String> {
+        Action::ALL
+            .into_iter()
+            .filter_map(|action| Some(BranchAction::binding(action.key(keys), action.emitted()?)))
+            .collect()
+    }
+
+    /// The entries of the menu opened with the menu key: checking out, then the actions
+    pub fn menu(keys: &KeysConfig, gitflow: bool) -> Vec<MenuEntry> {
+        std::iter::once(MenuEntry::new("Check out", None))
+            .chain(
+                Action::offered(gitflow).map(|action| {
+                    MenuEntry::new(action.label(), Some(action.key(keys).to_string()))
+                }),
+            )
+            .collect()
+    }
+
+    /// The action bound to `key`, which was reported by skim on accept
+    pub fn from_key(keys: &KeysConfig, key: &str) -> Option<Action> {
+        Action::ALL
+            .into_iter()
+            .find(|action| action.key(keys) == key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> KeysConfig {
+        KeysConfig {
+            fast_forward: "ctrl-o".to_string(),
+            delete: "ctrl-x".to_string(),
+            finish: "alt-m".to_string(),
+            web: "ctrl-w".to_string(),
+            set_upstream: "alt-t".to_string(),
+            set_default: "alt-h".to_string(),
+            set_push_remote: "alt-p".to_string(),
+            branch_off: "alt-n".to_string(),
+            fetch_selected: "alt-e".to_string(),
+            ..KeysConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_expect_keys() {
+        assert_eq!(
+            Action::expect_keys(&keys(), false),
+            "ctrl-w,alt-t,alt-h,alt-p,alt-n"
+        );
+        assert_eq!(
+            Action::expect_keys(&keys(), true),
+            "alt-m,ctrl-w,alt-t,alt-h,alt-p,alt-n"
+        );
+    }
+
+    #[test]
+    fn test_bindings() {
+        assert_eq!(
+            Action::bindings(&keys()),
+            [
+                "ctrl-o:emit(fast-forward)",
+                "ctrl-x:emit(delete)",
+                "alt-e:emit(fetch-selected)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_key() {
+        let keys = keys();
+        assert_eq!(Action::from_key(&keys, "alt-t"), Some(Action::SetUpstream));
+        assert_eq!(Action::from_key(&keys, "alt-h"), Some(Action::SetDefault));
+        assert_eq!(
+            Action::from_key(&keys, "alt-p"),
+            Some(Action::SetPushRemote)
+        );
+        assert_eq!(Action::from_key(&keys, "alt-n"), Some(Action::BranchOff));
+        assert_eq!(Action::from_key(&keys, "ctrl-o"), Some(Action::FastForward));
+        assert_eq!(Action::from_key(&keys, "ctrl-x"), Some(Action::Delete));
+        assert_eq!(Action::from_key(&keys, "alt-m"), Some(Action::Finish));
+        assert_eq!(Action::from_key(&keys, "alt-u"), None);
+    }
+
+    #[test]
+    fn test_menu() {
+        let menu = Action::menu(&keys(), false);
+        assert_eq!(menu[0], MenuEntry::new("Check out", None));
+        assert_eq!(
+            menu[2],
+            MenuEntry::new("Delete", Some("ctrl-x".to_string()))
+        );
+        assert_eq!(menu.len(), 9);
+    }
+}
@@ -0,0 +1,146 @@
+//! The commits per week of a branch as a sparkline, shown at the top of the preview
+//! (`--activity`), to tell an active branch from an abandoned one at a glance.
+use crate::diffstat;
+use crate::meta::RepoMeta;
+use anyhow::{Context, Result};
+use git2::{Oid, Repository, Sort};
+
+/// The number of weeks shown when `--activity` is given without a number
+pub const DEFAULT_WEEKS: &str = "12";
+
+const WEEK_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// The bars, from the fewest commits to the most
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Shown for the weeks without commits, so that they stand apart from the quiet ones
+const NO_COMMITS: char = '·';
+
+/// The number of commits of `tip` in each of the last `weeks` weeks before `now`, the oldest week
+/// first. The commits it shares with `base` don't count, unless `tip` is part of `base`, e.g. for
+/// the default branch itself.
+fn weeks(
+    repo: &Repository,
+    tip: Oid,
+    base: Option<Oid>,
+    weeks: usize,
+    now: i64,
+) -> Result<Vec<usize>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TIME)?;
+    revwalk.push(tip)?;
+    if let Some(base) = base {
+        let fork_point = repo
+            .merge_base(tip, base)
+            .with_context(|| "Failed to find the merge base")?;
+        if fork_point != tip {
+            revwalk.hide(fork_point)?;
+        }
+    }
+
+    let start = now - weeks as i64 * WEEK_SECS;
+    let mut counts = vec![0; weeks];
+    for oid in revwalk {
+        let time = repo.find_commit(oid?)?.time().seconds();
+        if time < start {
+            break;
+        }
+        // commits dated in the future count for the current week
+        let week = ((time - start) / WEEK_SECS).min(weeks as i64 - 1);
+        counts[week as usize] += 1;
+    }
+
+    Ok(counts)
+}
+
+/// The bars of `counts`, scaled to the busiest week
+fn sparkline(counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0);
+    counts
+        .iter()
+        .map(|&count| match count {
+            0 => NO_COMMITS,
+            _ => BARS[(count * BARS.len()).div_ceil(max) - 1],
+        })
+        .collect()
+}
+
+/// The line shown above the preview of the branch `name`, local or remote, e.g.
+/// `Last 12 weeks  ··▁▃█▂······  9 commits`
+pub fn of_branch(meta: &RepoMeta, name: &str, weeks_shown: usize) -> Result<String> {
+    let repo = meta.repo();
+    let tip = repo
+        .refname_to_id(&format!("refs/heads/{}", name))
+        .or_else(|_| repo.refname_to_id(&format!("refs/remotes/{}", name)))
+        .with_context(|| format!("Failed to find {}", name))?;
+    let now = chrono::Local::now().timestamp();
+    let counts = weeks(repo, tip, diffstat::base_commit(meta), weeks_shown, now)?;
+
+    let total: usize = counts.iter().sum();
+    Ok(format!(
+        "Last {} weeks  {}  {} {}",
+        weeks_shown,
+        sparkline(&counts),
+        total,
+        if total == 1 { "commit" } else { "commits" }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 1, 2, 4, 8, 0]), "·▁▂▄█·");
+        assert_eq!(sparkline(&[3, 3]), "██");
+        assert_eq!(sparkline(&[0, 0]), "··");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    const NOW: i64 = 100 * WEEK_SECS;
+
+    /// `main` with a commit 10 weeks and one 2 weeks ago, and `feature` on top of it with a
+    /// commit 3 weeks ago and two this week. Returns the tip of `main` and of `feature`.
+    fn history(repo: &Repository) -> (Oid, Oid) {
+        let commit = |refname: &str, weeks_ago: i64, parent: &[Oid]| {
+            let time = NOW - weeks_ago * WEEK_SECS - 60;
+            testing::commit_at(repo, Some(refname), "commit", time, parent)
+        };
+        let old = commit("refs/heads/main", 10, &[]);
+        let base = commit("refs/heads/main", 2, &[old]);
+        let first = commit("refs/heads/feature", 3, &[base]);
+        let second = commit("refs/heads/feature", 0, &[first]);
+        (base, commit("refs/heads/feature", 0, &[second]))
+    }
+
+    #[test]
+    fn test_weeks_since_base() {
+        let repo = TempRepo::new("activity");
+        let (base, tip) = history(&repo);
+        assert_eq!(
+            weeks(&repo, tip, Some(base), 4, NOW).unwrap(),
+            vec![1, 0, 0, 2]
+        );
+    }
+
+    #[test]
+    fn test_weeks_of_default_branch() {
+        let repo = TempRepo::new("activity-default");
+        let (base, _) = history(&repo);
+        // the default branch itself shows all of its commits
+        assert_eq!(
+            weeks(&repo, base, Some(base), 4, NOW).unwrap(),
+            vec![0, 1, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_weeks_without_base() {
+        let repo = TempRepo::new("activity-no-base");
+        let (_, tip) = history(&repo);
+        let weeks = weeks(&repo, tip, None, 12, NOW).unwrap();
+        assert_eq!(weeks.iter().sum::<usize>(), 5);
+    }
+}
@@ -0,0 +1,249 @@
+//! The age column (`--age`, or `age = true` under `[layout]`): how long ago the tip of each
+//! branch was committed, e.g. `3d` or `5w`, in the units `--active-since` takes. Keys of the
+//! picker switch it to the dates themselves, and from the committer time to the author time, e.g.
+//! to tell the branches rebased lately from the ones worked on lately.
+use crate::diffstat;
+use crate::{Branch, LocalBranch, RefBranch, RemoteBranch};
+use chrono::{DateTime, Local, TimeZone};
+use git2::Repository;
+use std::env;
+
+/// Switches between how long ago and the dates
+const FORMAT_KEY: &str = "alt-g";
+
+/// Switches between the committer time and the author time
+const TIME_KEY: &str = "alt-w";
+
+/// How the age column shows the time of the tip of each branch
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct View {
+    /// The dates instead of how long ago
+    pub absolute: bool,
+    /// The author time instead of the committer time
+    pub author: bool,
+    /// The format of the dates, see [`date_format`]
+    pub format: String,
+}
+
+/// How long ago `time` was at `now` in its largest unit, e.g. `3d`
+fn compact(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    let minutes = (now - time).num_minutes().max(0);
+    let (hours, days) = (minutes / 60, minutes / (24 * 60));
+    if minutes < 1 {
+        "now".to_string()
+    } else if hours < 1 {
+        format!("{}m", minutes)
+    } else if days < 1 {
+        format!("{}h", hours)
+    } else if days < 14 {
+        format!("{}d", days)
+    } else if days < 60 {
+        format!("{}w", days / 7)
+    } else if days < 365 {
+        format!("{}mo", days / 30)
+    } else {
+        format!("{}y", days / 365)
+    }
+}
+
+/// The order of the date in `locale`, e.g. `de_DE.UTF-8`, as git and chrono only write dates in
+/// English or as ISO 8601, which is what the other locales get
+fn locale_format(locale: &str) -> &'static str {
+    let name = locale.split(['.', '@']).next().unwrap_or("");
+    let language = name.split('_').next().unwrap_or("");
+    match (name, language) {
+        ("en_US", _) => "%m/%d/%Y %I:%M %p",
+        ("en_CA", _) => "%Y-%m-%d %H:%M",
+        (_, "ja" | "zh") => "%Y/%m/%d %H:%M",
+        (_, "ko" | "hu") => "%Y. %m. %d. %H:%M",
+        (_, "de" | "ru" | "pl" | "cs" | "fi" | "nb" | "da" | "tr" | "uk") => "%d.%m.%Y %H:%M",
+        (_, "nl") => "%d-%m-%Y %H:%M",
+        (_, "en" | "fr" | "es" | "it" | "pt" | "el") => "%d/%m/%Y %H:%M",
+        _ => "%Y-%m-%d %H:%M",
+    }
+}
+
+/// The format of the dates: `date_format` under `[layout]` when set, e.g. `%d %b %Y`, or else
+/// the one of the locale of `LC_ALL`, `LC_TIME` or `LANG`
+pub fn date_format(configured: Option<&str>) -> String {
+    if let Some(format) = configured {
+        return format.to_string();
+    }
+    let locale = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_default();
+    locale_format(&locale).to_string()
+}
+
+impl View {
+    /// The keys to pass to skim's `expect` option
+    pub fn expect_keys() -> [&'static str; 2] {
+        [FORMAT_KEY, TIME_KEY]
+    }
+
+    /// The view after pressing `key`, `None` when it is no key of the age column
+    pub fn toggled(&self, key: &str) -> Option<View> {
+        match key {
+            FORMAT_KEY => Some(View {
+                absolute: !self.absolute,
+                ..self.clone()
+            }),
+            TIME_KEY => Some(View {
+                author: !self.author,
+                ..self.clone()
+            }),
+            _ => None,
+        }
+    }
+
+    /// How the info line of the picker tells the view, e.g. `ages by author time · alt-g: dates`
+    pub fn describe(&self) -> String {
+        let (time, other_time) = match self.author {
+            true => ("author", "committer"),
+            false => ("committer", "author"),
+        };
+        let other_format = match self.absolute {
+            true => "ages",
+            false => "dates",
+        };
+        format!(
+            "{} by {} time · {}: {} · {}: {} time",
+            if self.absolute { "dates" } else { "ages" },
+            time,
+            FORMAT_KEY,
+            other_format,
+            TIME_KEY,
+            other_time
+        )
+    }
+
+    fn label(&self, time: DateTime<Local>, now: DateTime<Local>) -> String {
+        match self.absolute {
+            true => time.format(&self.format).to_string(),
+            false => compact(time, now),
+        }
+    }
+}
+
+/// Attach the age of the tip of each branch, as `view` shows it
+pub fn attach(repo: &Repository, view: &View, branches: &mut [Branch]) {
+    let now = Local::now();
+    for branch in branches {
+        let age = diffstat::tip(repo, branch)
+            .and_then(|tip| repo.find_commit(tip).ok())
+            .and_then(|commit| {
+                let seconds = match view.author {
+                    true => commit.author().when().seconds(),
+                    false => commit.time().seconds(),
+                };
+                Local.timestamp_opt(seconds, 0).single()
+            })
+            .map(|time| view.label(time, now));
+        match branch {
+            Branch::Local(LocalBranch { age: slot, .. })
+            | Branch::Remote(RemoteBranch { age: slot, .. })
+            | Branch::Ref(RefBranch { age: slot, .. }) => *slot = age,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+    use chrono::Duration;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap()
+    }
+
+    /// The view toggled to the dates, by committer time
+    fn dates() -> View {
+        let view = View {
+            format: "%Y-%m-%d".to_string(),
+            ..View::default()
+        };
+        view.toggled(FORMAT_KEY).unwrap()
+    }
+
+    #[test]
+    fn test_compact() {
+        let ago = |duration: Duration| compact(now() - duration, now());
+        assert_eq!(ago(Duration::seconds(30)), "now");
+        assert_eq!(ago(Duration::minutes(45)), "45m");
+        assert_eq!(ago(Duration::hours(5)), "5h");
+        assert_eq!(ago(Duration::days(3)), "3d");
+        assert_eq!(ago(Duration::days(35)), "5w");
+        assert_eq!(ago(Duration::days(100)), "3mo");
+        assert_eq!(ago(Duration::days(800)), "2y");
+        // a clock that is behind
+        assert_eq!(ago(Duration::hours(-1)), "now");
+    }
+
+    #[test]
+    fn test_date_format() {
+        assert_eq!(locale_format("de_DE.UTF-8"), "%d.%m.%Y %H:%M");
+        assert_eq!(locale_format("en_US.UTF-8"), "%m/%d/%Y %I:%M %p");
+        assert_eq!(locale_format("en_GB"), "%d/%m/%Y %H:%M");
+        assert_eq!(locale_format("ja_JP.UTF-8"), "%Y/%m/%d %H:%M");
+        assert_eq!(locale_format("C.UTF-8"), "%Y-%m-%d %H:%M");
+        assert_eq!(date_format(Some("%d %b %Y")), "%d %b %Y");
+    }
+
+    #[test]
+    fn test_toggled() {
+        let dates = dates();
+        assert!(dates.absolute && !dates.author);
+        assert!(dates.toggled(TIME_KEY).unwrap().author);
+        assert_eq!(View::default().toggled("enter"), None);
+        assert_eq!(
+            dates.describe(),
+            "dates by committer time · alt-g: ages · alt-w: author time"
+        );
+    }
+
+    #[test]
+    fn test_attach() {
+        let repo = TempRepo::new("age");
+        let tree = repo
+            .find_tree(repo.treebuilder(None).unwrap().write().unwrap())
+            .unwrap();
+        // written a year before, and rebased two days before `now`, which testing::commit can't
+        // tell apart
+        let signature = |duration: Duration| {
+            let time = git2::Time::new((now() - duration).timestamp(), 0);
+            git2::Signature::new("test", "test@example.com", &time).unwrap()
+        };
+        let tip = repo
+            .commit(
+                None,
+                &signature(Duration::days(365)),
+                &signature(Duration::days(2)),
+                "rebased",
+                &tree,
+                &[],
+            )
+            .unwrap();
+        repo.reference("refs/heads/topic", tip, false, "test")
+            .unwrap();
+
+        let age_of = |view: &View| {
+            let mut branches = [Branch::Local(LocalBranch {
+                name: "topic".to_string(),
+                ..LocalBranch::default()
+            })];
+            attach(&repo, view, &mut branches);
+            match &branches[0] {
+                Branch::Local(branch) => branch.age.clone(),
+                _ => None,
+            }
+        };
+        let day = |duration: Duration| (now() - duration).format("%Y-%m-%d").to_string();
+        assert_eq!(age_of(&dates()), Some(day(Duration::days(2))));
+        assert_eq!(
+            age_of(&dates().toggled(TIME_KEY).unwrap()),
+            Some(day(Duration::days(365)))
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! Objects kept in another repository: the object directories listed in
+//! `objects/info/alternates`, as `git clone --shared`, `git clone --reference` and `repo` set them
+//! up, and those in `GIT_ALTERNATE_OBJECT_DIRECTORIES`.
+//!
+//! libgit2 reads `objects/info/alternates` by itself, but only `Repository::open_from_env` honors
+//! the environment variable, so repositories are opened with [`open`], which adds those too.
+use crate::hooks;
+use git2::Repository;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// Object directories to read from besides those of the repository, separated like `PATH`
+const ENV: &str = "GIT_ALTERNATE_OBJECT_DIRECTORIES";
+
+/// The object directories in `GIT_ALTERNATE_OBJECT_DIRECTORIES`
+fn from_env() -> Vec<PathBuf> {
+    env::var_os(ENV)
+        .map(|dirs| {
+            env::split_paths(&dirs)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Read the objects of `repo` from `dirs` too
+fn add(repo: &Repository, dirs: &[PathBuf]) -> Result<(), git2::Error> {
+    if dirs.is_empty() {
+        return Ok(());
+    }
+
+    let odb = repo.odb()?;
+    for dir in dirs {
+        match dir.to_str() {
+            Some(dir) => odb.add_disk_alternate(dir)?,
+            None => warn!("Skipping alternate object directory {}", dir.display()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Open the repository at `path`, which also reads objects from the directories in
+/// `GIT_ALTERNATE_OBJECT_DIRECTORIES`
+pub fn open(path: impl AsRef<Path>) -> Result<Repository, git2::Error> {
+    let repo = Repository::open(path)?;
+    add(&repo, &from_env())?;
+
+    Ok(repo)
+}
+
+/// Same as [`open`], for the repository that `path` is in
+pub fn discover(path: impl AsRef<Path>) -> Result<Repository, git2::Error> {
+    let repo = Repository::discover(path)?;
+    add(&repo, &from_env())?;
+
+    Ok(repo)
+}
+
+/// The object directories listed in `objects/info/alternates` of `repo`. Relative ones are
+/// relative to the `objects` directory, like git resolves them.
+fn listed(repo: &Repository) -> Vec<PathBuf> {
+    let objects = hooks::common_dir(repo).join("objects");
+    let alternates = match fs::read_to_string(objects.join("info").join("alternates")) {
+        Ok(alternates) => alternates,
+        Err(_) => return vec![],
+    };
+
+    alternates
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| objects.join(line))
+        .collect()
+}
+
+/// The alternate object directories of `repo`, from `objects/info/alternates` and the
+/// environment, that don't exist (anymore). The commits stored there can't be read.
+pub fn missing(repo: &Repository) -> Vec<PathBuf> {
+    listed(repo)
+        .into_iter()
+        .chain(from_env())
+        .filter(|dir| !dir.is_dir())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir};
+    use git2::{BranchType, Oid};
+
+    /// The subject of the commit of the local branch `name`, as the columns of the picker look it
+    /// up
+    fn subject(repo: &Repository, name: &str) -> Option<String> {
+        let commit = repo
+            .find_branch(name, BranchType::Local)
+            .and_then(|branch| branch.get().peel_to_commit())
+            .ok()?;
+        commit.summary().map(str::to_string)
+    }
+
+    /// A repository lending its objects, with a commit on `main`
+    fn lender(dir: &Path) -> (Repository, Oid) {
+        let lender = Repository::init(dir.join("lender")).unwrap();
+        let oid = testing::commit(&lender, Some("refs/heads/main"), "shared", &[], &[]);
+        (lender, oid)
+    }
+
+    /// A repository borrowing the objects of [`lender`] with a relative path, as `repo` writes
+    /// them, and a branch at its commit
+    fn borrower(dir: &Path, oid: Oid) -> Repository {
+        let borrower = Repository::init(dir.join("borrower")).unwrap();
+        fs::write(
+            borrower.path().join("objects/info/alternates"),
+            "../../../lender/.git/objects\n",
+        )
+        .unwrap();
+        let borrower = open(dir.join("borrower")).unwrap();
+        borrower
+            .reference("refs/heads/feature", oid, false, "test")
+            .unwrap();
+        borrower
+    }
+
+    #[test]
+    fn test_relative_alternates() {
+        let dir = TempDir::new("alternates");
+        let (_, oid) = lender(&dir);
+        let borrower = borrower(&dir, oid);
+        assert_eq!(subject(&borrower, "feature"), Some("shared".to_string()));
+        assert_eq!(missing(&borrower), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn test_added_alternates() {
+        let dir = TempDir::new("alternates-added");
+        let (lender, oid) = lender(&dir);
+        // as with `GIT_ALTERNATE_OBJECT_DIRECTORIES`
+        let env = Repository::init(dir.join("env")).unwrap();
+        // libgit2 won't point a reference at an object it can't find
+        fs::write(env.path().join("refs/heads/feature"), format!("{}\n", oid)).unwrap();
+        assert_eq!(subject(&env, "feature"), None);
+        add(&env, &[lender.path().join("objects")]).unwrap();
+        assert_eq!(subject(&env, "feature"), Some("shared".to_string()));
+    }
+
+    #[test]
+    fn test_missing_alternates() {
+        let dir = TempDir::new("alternates-missing");
+        let (_, oid) = lender(&dir);
+        let borrower = borrower(&dir, oid);
+        fs::rename(dir.join("lender"), dir.join("moved")).unwrap();
+        assert_eq!(
+            missing(&borrower),
+            vec![borrower.path().join("objects/../../../lender/.git/objects")]
+        );
+    }
+}
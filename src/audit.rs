@@ -0,0 +1,484 @@
+//! A log of what gibra destroys, in `.git/gibra/audit.log`: the deleted branches, local and on
+//! the remotes, the branches reset by a checkout, and the local changes discarded by `--force`,
+//! each with the commit it pointed to. One entry per line, e.g.
+//! `[1700000000] delete-branch feature 1a2b… origin/feature`. `gibra undo` brings back what an
+//! entry lost, as long as git hasn't collected its commits.
+use crate::{age, git};
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use git2::{BranchType, Oid, Repository};
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const LOG_FILE_NAME: &str = "audit.log";
+
+/// What a destructive operation lost
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Loss {
+    /// A deleted local branch, with its upstream
+    Branch {
+        name: String,
+        tip: Oid,
+        upstream: Option<String>,
+    },
+    /// A local branch moved to another commit by `git checkout -B`
+    Reset { name: String, tip: Oid },
+    /// The local changes discarded by a forced checkout on top of `head`, kept as a commit like
+    /// those of the stash, which has no untracked files
+    Changes { head: Oid, stash: Option<Oid> },
+    /// A branch deleted from a remote
+    RemoteBranch {
+        remote: String,
+        name: String,
+        tip: Oid,
+    },
+}
+
+impl Loss {
+    /// The local branch `name` as it is before deleting it
+    pub fn branch(repo: &Repository, name: &str) -> Option<Self> {
+        let branch = repo.find_branch(name, BranchType::Local).ok()?;
+        let upstream = branch
+            .upstream()
+            .ok()
+            .and_then(|upstream| upstream.name().ok().flatten().map(str::to_string));
+        Some(Loss::Branch {
+            name: name.to_string(),
+            tip: branch.get().target()?,
+            upstream,
+        })
+    }
+
+    /// The local branch `name` as it is before resetting it
+    pub fn reset(repo: &Repository, name: &str) -> Option<Self> {
+        let tip = repo.refname_to_id(&format!("refs/heads/{}", name)).ok()?;
+        Some(Loss::Reset {
+            name: name.to_string(),
+            tip,
+        })
+    }
+
+    /// The local changes in `workdir` before discarding them, kept with `git stash create`
+    pub fn changes(repo: &Repository, workdir: &Path) -> Option<Self> {
+        let head = repo.head().ok()?.target()?;
+        let stash = git::command(workdir)
+            .args(["stash", "create"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| Oid::from_str(String::from_utf8_lossy(&output.stdout).trim()).ok());
+        Some(Loss::Changes { head, stash })
+    }
+
+    /// The branch `name` of `remote` as it was fetched, before deleting it from the remote
+    pub fn remote_branch(repo: &Repository, remote: &str, name: &str) -> Option<Self> {
+        let tip = repo
+            .refname_to_id(&format!("refs/remotes/{}/{}", remote, name))
+            .ok()?;
+        Some(Loss::RemoteBranch {
+            remote: remote.to_string(),
+            name: name.to_string(),
+            tip,
+        })
+    }
+
+    /// Whether the entry is about the branch `name`, e.g. `feature` or `origin/feature`
+    fn is_about(&self, name: &str) -> bool {
+        match self {
+            Loss::Branch { name: branch, .. } | Loss::Reset { name: branch, .. } => branch == name,
+            Loss::Changes { .. } => false,
+            Loss::RemoteBranch {
+                remote,
+                name: branch,
+                ..
+            } => branch == name || format!("{}/{}", remote, branch) == name,
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let oid = |field: &str| Oid::from_str(field).ok();
+        match fields.as_slice() {
+            ["delete-branch", name, tip, upstream @ ..] if upstream.len() <= 1 => {
+                Some(Loss::Branch {
+                    name: name.to_string(),
+                    tip: oid(tip)?,
+                    upstream: upstream.first().map(|upstream| upstream.to_string()),
+                })
+            }
+            ["reset-branch", name, tip] => Some(Loss::Reset {
+                name: name.to_string(),
+                tip: oid(tip)?,
+            }),
+            ["discard-changes", head, stash @ ..] if stash.len() <= 1 => Some(Loss::Changes {
+                head: oid(head)?,
+                stash: match stash.first() {
+                    Some(stash) => Some(oid(stash)?),
+                    None => None,
+                },
+            }),
+            ["delete-remote-branch", remote, name, tip] => Some(Loss::RemoteBranch {
+                remote: remote.to_string(),
+                name: name.to_string(),
+                tip: oid(tip)?,
+            }),
+            _ => None,
+        }
+    }
+
+    /// What was lost, e.g. `deleted feature at 1a2b3c4`
+    fn describe(&self) -> String {
+        match self {
+            Loss::Branch { name, tip, .. } => format!("deleted {} at {:.7}", name, tip),
+            Loss::Reset { name, tip } => format!("reset {}, which was at {:.7}", name, tip),
+            Loss::Changes {
+                head,
+                stash: Some(_),
+            } => format!("discarded the local changes on {:.7}", head),
+            Loss::Changes { head, stash: None } => {
+                format!("discarded the untracked files on {:.7}", head)
+            }
+            Loss::RemoteBranch { remote, name, tip } => {
+                format!("deleted {} from {} at {:.7}", name, remote, tip)
+            }
+        }
+    }
+}
+
+/// Branch and remote names have no spaces, so that the fields are split on them
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Loss::Branch {
+                name,
+                tip,
+                upstream,
+            } => {
+                write!(f, "delete-branch {} {}", name, tip)?;
+                if let Some(upstream) = upstream {
+                    write!(f, " {}", upstream)?;
+                }
+                Ok(())
+            }
+            Loss::Reset { name, tip } => write!(f, "reset-branch {} {}", name, tip),
+            Loss::Changes { head, stash } => {
+                write!(f, "discard-changes {}", head)?;
+                if let Some(stash) = stash {
+                    write!(f, " {}", stash)?;
+                }
+                Ok(())
+            }
+            Loss::RemoteBranch { remote, name, tip } => {
+                write!(f, "delete-remote-branch {} {} {}", remote, name, tip)
+            }
+        }
+    }
+}
+
+/// An entry of the log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// When it was lost, in seconds since the epoch
+    pub time: u64,
+    pub loss: Loss,
+}
+
+impl Entry {
+    fn parse(line: &str) -> Option<Self> {
+        let (time, loss) = line.strip_prefix('[')?.split_once("] ")?;
+        Some(Entry {
+            time: time.parse().ok()?,
+            loss: Loss::parse(loss)?,
+        })
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}", self.time, self.loss)
+    }
+}
+
+fn log_path(repo: &Repository) -> PathBuf {
+    repo.path().join("gibra").join(LOG_FILE_NAME)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// Add `losses` to the log. The operation went through already, so failing to write is only
+/// warned about.
+pub fn record(repo: &Repository, losses: impl IntoIterator<Item = Loss>) {
+    let path = log_path(repo);
+    let time = now();
+    for loss in losses {
+        let entry = Entry { time, loss };
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+            .and_then(|mut log| writeln!(log, "{}", entry));
+        if let Err(err) = written {
+            eprintln!(
+                "warning: failed to write to {}: {}, {}",
+                path.display(),
+                err,
+                entry.loss.describe()
+            );
+        }
+    }
+}
+
+/// The entries of the log, the oldest first
+pub fn entries(repo: &Repository) -> Result<Vec<Entry>> {
+    let path = log_path(repo);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(content.lines().filter_map(Entry::parse).collect())
+}
+
+/// Fail when the commit `oid` that `what` needs is no longer in the repository
+fn ensure_commit(repo: &Repository, oid: Oid, what: &str) -> Result<()> {
+    if repo.find_commit(oid).is_err() {
+        bail!(
+            "The commit {:.7} of {} is gone, git collected it as nothing pointed to it",
+            oid,
+            what
+        );
+    }
+    Ok(())
+}
+
+/// Bring back what `loss` lost
+fn restore(repo: &Repository, workdir: &Path, loss: &Loss) -> Result<()> {
+    match loss {
+        Loss::Branch {
+            name,
+            tip,
+            upstream,
+        } => {
+            if repo.find_branch(name, BranchType::Local).is_ok() {
+                bail!(
+                    "{} exists already, rename or delete it to restore the one at {:.7}",
+                    name,
+                    tip
+                );
+            }
+            ensure_commit(repo, *tip, name)?;
+            git::run(workdir, &["branch", name, &tip.to_string()])?;
+            if let Some(upstream) = upstream {
+                if repo.find_branch(upstream, BranchType::Remote).is_ok() {
+                    git::run(
+                        workdir,
+                        &["branch", &format!("--set-upstream-to={}", upstream), name],
+                    )?;
+                } else {
+                    eprintln!("{} is gone, {} has no upstream", upstream, name);
+                }
+            }
+            eprintln!("Restored {} at {:.7}", name, tip);
+        }
+        Loss::Reset { name, tip } => {
+            ensure_commit(repo, *tip, name)?;
+            // git refuses to move the checked out branch
+            git::run(workdir, &["branch", "--force", name, &tip.to_string()])?;
+            eprintln!("Moved {} back to {:.7}", name, tip);
+        }
+        Loss::Changes { stash: None, .. } => {
+            bail!("Only untracked files were discarded, which git doesn't keep")
+        }
+        Loss::Changes {
+            stash: Some(stash), ..
+        } => {
+            ensure_commit(repo, *stash, "the discarded changes")?;
+            let message = "gibra: discarded by a forced checkout";
+            git::run(
+                workdir,
+                &["stash", "store", "-m", message, &stash.to_string()],
+            )?;
+            eprintln!("The discarded changes are in the stash, apply them with `git stash pop`");
+        }
+        Loss::RemoteBranch { remote, name, tip } => {
+            ensure_commit(repo, *tip, name)?;
+            let refspec = format!("{}:refs/heads/{}", tip, name);
+            git::run(workdir, &["push", remote, &refspec])?;
+            eprintln!("Pushed {} back to {} at {:.7}", name, remote, tip);
+        }
+    }
+    Ok(())
+}
+
+/// Undo the latest entry of the log, or the latest about the branch `name`. With `list`, the
+/// entries are only printed, the latest first.
+pub fn undo(repo: &Repository, workdir: &Path, name: Option<&str>, list: bool) -> Result<()> {
+    let entries = entries(repo)?;
+    if list {
+        let format = age::date_format(None);
+        for entry in entries.iter().rev() {
+            let time = match Local.timestamp_opt(entry.time as i64, 0).single() {
+                Some(time) => time.format(&format).to_string(),
+                None => entry.time.to_string(),
+            };
+            println!("{}  {}", time, entry.loss.describe());
+        }
+        return Ok(());
+    }
+
+    let entry = entries.iter().rev().find(|entry| match name {
+        Some(name) => entry.loss.is_about(name),
+        None => true,
+    });
+    match (entry, name) {
+        (Some(entry), _) => restore(repo, workdir, &entry.loss),
+        (None, Some(name)) => bail!(
+            "gibra lost nothing of {}, see {}",
+            name,
+            log_path(repo).display()
+        ),
+        (None, None) => bail!("gibra lost nothing in this repository yet"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    fn tip() -> Oid {
+        Oid::from_str("1a2b3c4d5e6f7a8b9c0d1e2f3a4b5c6d7e8f9a0b").unwrap()
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let tip = tip();
+        let losses = [
+            Loss::Branch {
+                name: "feature/login".to_string(),
+                tip,
+                upstream: Some("origin/feature/login".to_string()),
+            },
+            Loss::Branch {
+                name: "local".to_string(),
+                tip,
+                upstream: None,
+            },
+            Loss::Reset {
+                name: "main".to_string(),
+                tip,
+            },
+            Loss::Changes {
+                head: tip,
+                stash: Some(tip),
+            },
+            Loss::Changes {
+                head: tip,
+                stash: None,
+            },
+            Loss::RemoteBranch {
+                remote: "origin".to_string(),
+                name: "old".to_string(),
+                tip,
+            },
+        ];
+        for loss in losses {
+            let entry = Entry {
+                time: 1700000000,
+                loss,
+            };
+            assert_eq!(Entry::parse(&entry.to_string()), Some(entry));
+        }
+    }
+
+    #[test]
+    fn test_entry_parse() {
+        assert_eq!(
+            Entry::parse(&format!("[1700000000] reset-branch main {}", tip()))
+                .unwrap()
+                .loss
+                .describe(),
+            "reset main, which was at 1a2b3c4"
+        );
+        assert_eq!(
+            Entry::parse("[1700000000] delete-branch feature nope"),
+            None
+        );
+        assert_eq!(Entry::parse("delete-branch feature"), None);
+    }
+
+    #[test]
+    fn test_is_about() {
+        let remote = Loss::RemoteBranch {
+            remote: "origin".to_string(),
+            name: "old".to_string(),
+            tip: tip(),
+        };
+        assert!(remote.is_about("old"));
+        assert!(remote.is_about("origin/old"));
+        assert!(!remote.is_about("origin"));
+    }
+
+    /// A repository with `feature` at the checked out commit, which is returned
+    fn repo_with_feature(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, Some("HEAD"), "root", &[], &[]);
+        repo.reference("refs/heads/feature", tip, false, "test")
+            .unwrap();
+        (repo, tip)
+    }
+
+    #[test]
+    fn test_loss_of_missing_ref() {
+        let (repo, _) = repo_with_feature("audit-missing");
+        assert!(Loss::branch(&repo, "feature").is_some());
+        assert_eq!(Loss::branch(&repo, "nope"), None);
+        assert_eq!(Loss::reset(&repo, "nope"), None);
+    }
+
+    #[test]
+    fn test_undo_without_entries() {
+        let (repo, _) = repo_with_feature("audit-empty");
+        assert!(undo(&repo, repo.dir(), None, false).is_err());
+    }
+
+    #[test]
+    fn test_undo_deleted_branch() {
+        let (repo, tip) = repo_with_feature("audit");
+        let dir = repo.dir();
+        let loss = Loss::branch(&repo, "feature").unwrap();
+        repo.find_branch("feature", BranchType::Local)
+            .unwrap()
+            .delete()
+            .unwrap();
+        record(
+            &repo,
+            [
+                loss,
+                Loss::Changes {
+                    head: tip,
+                    stash: None,
+                },
+            ],
+        );
+        assert_eq!(entries(&repo).unwrap().len(), 2);
+
+        // the latest entry only lost untracked files
+        assert!(undo(&repo, dir, None, false).is_err());
+        assert!(undo(&repo, dir, Some("other"), false).is_err());
+        undo(&repo, dir, Some("feature"), false).unwrap();
+        assert_eq!(repo.refname_to_id("refs/heads/feature").unwrap(), tip);
+        // it is back already
+        assert!(undo(&repo, dir, Some("feature"), false).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! A time budget for counting how far the branches are ahead of and behind their upstream or the
+//! base, with `--budget MS` or `budget` under `[picker]`. Counting walks the history of every
+//! branch, which on slow filesystems like NFS delays the picker by seconds. Once the budget is
+//! spent, the remaining counts are shown as `…` and made in the background, and the picker lists
+//! the branches again when they are done.
+//!
+//! The counts of a tip and a base never change, so those made once are kept for the rest of the
+//! invocation, whether within a budget or not.
+use crate::alternates;
+use crate::upstream::Divergence;
+use crossbeam::channel::Sender;
+use git2::{Oid, Repository};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+lazy_static! {
+    static ref COUNTED: Mutex<HashMap<(Oid, Oid), Divergence>> = Mutex::new(HashMap::new());
+}
+
+/// How far `tip` diverged from `base`, counted once
+pub fn count(repo: &Repository, tip: Oid, base: Oid) -> Option<Divergence> {
+    if let Some(counted) = COUNTED
+        .lock()
+        .ok()
+        .and_then(|counted| counted.get(&(tip, base)).copied())
+    {
+        return Some(counted);
+    }
+    let divergence = Divergence::between(repo, tip, base)?;
+    if let Ok(mut counted) = COUNTED.lock() {
+        counted.insert((tip, base), divergence);
+    }
+    Some(divergence)
+}
+
+fn is_counted(tip: Oid, base: Oid) -> bool {
+    COUNTED
+        .lock()
+        .is_ok_and(|counted| counted.contains_key(&(tip, base)))
+}
+
+/// The time left for counting, and the counts left for later
+pub struct Budget {
+    deadline: Instant,
+    deferred: RefCell<Vec<(Oid, Oid)>>,
+}
+
+impl Budget {
+    pub fn new(limit: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + limit,
+            deferred: RefCell::new(vec![]),
+        }
+    }
+
+    /// How far `tip` diverged from `base`, or pending once the budget is spent
+    pub fn divergence(&self, repo: &Repository, tip: Oid, base: Oid) -> Option<Divergence> {
+        if Instant::now() < self.deadline || is_counted(tip, base) {
+            return count(repo, tip, base);
+        }
+        self.deferred.borrow_mut().push((tip, base));
+        Some(Divergence::pending())
+    }
+
+    /// Make the counts left for later in the background, sending on `done` once they are made.
+    /// Returns whether any was left.
+    pub fn count_in_background(&self, git_dir: &Path, done: Sender<()>) -> bool {
+        let deferred = self.deferred.take();
+        if deferred.is_empty() {
+            return false;
+        }
+
+        let git_dir = git_dir.to_path_buf();
+        thread::spawn(move || match alternates::open(&git_dir) {
+            Ok(repo) => {
+                for (tip, base) in deferred {
+                    count(&repo, tip, base);
+                }
+                let _ = done.send(());
+            }
+            Err(err) => debug!("{:#}", err),
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use crossbeam::channel::unbounded;
+
+    /// A base and a tip one commit ahead of it, whose messages keep their counts apart from those
+    /// of the other tests, as they are kept for the whole process
+    fn history(repo: &Repository, name: &str) -> (Oid, Oid) {
+        let base = testing::commit(repo, None, &format!("{} base", name), &[], &[]);
+        let tip = testing::commit(repo, None, &format!("{} tip", name), &[], &[base]);
+        (base, tip)
+    }
+
+    #[test]
+    fn test_spent_budget() {
+        let repo = TempRepo::new("budget");
+        let (base, tip) = history(&repo, "spent");
+
+        let spent = Budget::new(Duration::ZERO);
+        let pending = spent.divergence(&repo, tip, base).unwrap();
+        assert!(pending.pending);
+        assert_eq!(pending.label(), "…");
+        assert!(!pending.is_in_sync());
+
+        let (tx, rx) = unbounded();
+        assert!(spent.count_in_background(repo.path(), tx.clone()));
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        // nothing is left
+        assert!(!spent.count_in_background(repo.path(), tx));
+
+        // counted in the background, so there even when the budget is spent
+        let counted = spent.divergence(&repo, tip, base).unwrap();
+        assert_eq!((counted.ahead, counted.behind), (1, 0));
+        assert!(!counted.pending);
+    }
+
+    #[test]
+    fn test_budget_left() {
+        let repo = TempRepo::new("budget-left");
+        let (base, tip) = history(&repo, "left");
+        assert_eq!(
+            Budget::new(Duration::from_secs(60))
+                .divergence(&repo, base, tip)
+                .map(|divergence| (divergence.ahead, divergence.behind)),
+            Some((0, 1))
+        );
+    }
+}
@@ -0,0 +1,368 @@
+//! Running the actions of the picker while it stays open. The keys bound to `emit(<name>)` send
+//! the picked branches to a worker thread, which runs the git operations one after the other and
+//! sends back what they did, shown on the info line, and whether they succeeded, after which the
+//! branches are listed again and the keys chaining more actions after them go on. The picker
+//! keeps answering the keys meanwhile, even while a fetch waits on the network.
+//!
+//! The actions that ask questions or take the terminal, like finishing a gitflow branch, close
+//! the picker first and run in [`crate::run_action`].
+use crate::alternates;
+use crate::delete;
+use crate::meta::RepoMeta;
+use crate::policy::Policy;
+use crate::pulls::OpenPulls;
+use crate::remote;
+use crate::skim::Emitted;
+use crate::upstream;
+use crate::Branch;
+use anyhow::Result;
+use crossbeam::channel::{unbounded, Sender};
+use git2::Repository;
+use std::{
+    path::PathBuf,
+    thread::{self, JoinHandle},
+};
+
+/// An action on branches that runs without closing the picker
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BranchAction {
+    /// Fetch all the remotes
+    Fetch,
+    /// Fetch just the remote branches, e.g. on a slow connection
+    FetchSelected(Vec<String>),
+    /// Fast-forward the local branches to their upstreams
+    FastForward(Vec<String>),
+    /// Delete the local branches, see [`delete::delete_quietly`]
+    Delete(Vec<String>),
+}
+
+impl BranchAction {
+    pub const FETCH: &'static str = "fetch";
+    pub const FETCH_SELECTED: &'static str = "fetch-selected";
+    pub const FAST_FORWARD: &'static str = "fast-forward";
+    pub const DELETE: &'static str = "delete";
+
+    /// The binding of `key` to the action of `name`, e.g. `ctrl-r:emit(fetch)`
+    pub fn binding(key: &str, name: &str) -> String {
+        format!("{}:emit({})", key, name)
+    }
+
+    /// The action sent by a key, and why some of the picked branches are left out of it
+    fn of(emitted: &Emitted) -> (Option<BranchAction>, Vec<String>) {
+        let branches = emitted
+            .items
+            .iter()
+            .filter_map(|item| (**item).as_any().downcast_ref::<Branch>());
+        // fetching selectively takes the remote branches, the other actions the local ones
+        let remote = emitted.name == BranchAction::FETCH_SELECTED;
+        let mut names = vec![];
+        let mut skipped = vec![];
+        for branch in branches {
+            match branch {
+                Branch::Local(branch) if remote => {
+                    skipped.push(format!("{} is a local branch, skipping", branch.name))
+                }
+                Branch::Local(branch) => names.push(branch.name.clone()),
+                Branch::Remote(branch) if remote => names.push(branch.name.clone()),
+                Branch::Remote(branch) => {
+                    skipped.push(format!("{} is a remote branch, skipping", branch.name))
+                }
+                Branch::Ref(reference) => {
+                    skipped.push(format!("{} is not a branch, skipping", reference.name))
+                }
+            }
+        }
+
+        let action = match emitted.name.as_str() {
+            BranchAction::FETCH => return (Some(BranchAction::Fetch), vec![]),
+            _ if names.is_empty() => None,
+            BranchAction::FETCH_SELECTED => Some(BranchAction::FetchSelected(names)),
+            BranchAction::FAST_FORWARD => Some(BranchAction::FastForward(names)),
+            BranchAction::DELETE => Some(BranchAction::Delete(names)),
+            _ => None,
+        };
+        (action, skipped)
+    }
+
+    /// What is shown while the action runs
+    fn running(&self) -> String {
+        match self {
+            BranchAction::Fetch => "fetching the remotes…".to_string(),
+            BranchAction::FetchSelected(names) => format!("fetching {}…", names.join(", ")),
+            BranchAction::FastForward(names) => format!("fast-forwarding {}…", names.join(", ")),
+            BranchAction::Delete(names) => format!("deleting {}…", names.join(", ")),
+        }
+    }
+
+    /// Run the action, telling what it did and whether it was done for all the branches, and
+    /// sending on `status` how far it got
+    fn run(
+        &self,
+        repo: &Repository,
+        context: &Context,
+        status: &Sender<String>,
+    ) -> Result<(Vec<String>, bool)> {
+        let workdir = context.workdir.as_path();
+        match self {
+            BranchAction::Fetch => {
+                let remotes = repo.remotes()?;
+                let failed: Vec<String> = remotes
+                    .iter()
+                    .flatten()
+                    .filter_map(|name| {
+                        let err = remote::fetch(repo, name, true).err()?;
+                        Some(format!("{:#}", err))
+                    })
+                    .collect();
+                Ok(if failed.is_empty() {
+                    (vec!["Fetched the remotes".to_string()], true)
+                } else {
+                    (failed, false)
+                })
+            }
+            BranchAction::FetchSelected(names) => Ok(fetch_selected(repo, names, |progress| {
+                let _ = status.send(progress);
+            })),
+            BranchAction::FastForward(names) => {
+                let mut synced = true;
+                let mut report = vec![];
+                for name in names {
+                    let skip_hooks = context.skip_hooks;
+                    let fast_forward =
+                        upstream::fast_forward_quietly(repo, workdir, name, skip_hooks)?;
+                    synced &= fast_forward.is_synced();
+                    report.push(fast_forward.describe(name));
+                }
+                Ok((report, synced))
+            }
+            BranchAction::Delete(names) => {
+                let deleted =
+                    delete::delete_quietly(repo, workdir, names, &context.pulls, &context.policy);
+                let all = deleted.iter().all(Result::is_ok);
+                let report = deleted
+                    .into_iter()
+                    .map(|deleted| deleted.unwrap_or_else(|kept| kept))
+                    .collect();
+                Ok((report, all))
+            }
+        }
+    }
+}
+
+/// Fetch just the remote branches `names`, e.g. `origin/feature`, telling `progress` which one is
+/// fetched. Tells what was fetched, with how far the local branches tracking it are ahead and
+/// behind now, and whether all the branches were.
+pub fn fetch_selected(
+    repo: &Repository,
+    names: &[String],
+    progress: impl Fn(String),
+) -> (Vec<String>, bool) {
+    let mut fetched = vec![];
+    let mut report = vec![];
+    for (index, name) in names.iter().enumerate() {
+        progress(format!(
+            "fetching {} ({}/{})…",
+            name,
+            index + 1,
+            names.len()
+        ));
+        let fetch = remote::split(repo, name)
+            .and_then(|(remote, branch)| remote::fetch_branch(repo, &remote, &branch));
+        match fetch {
+            Ok(()) => fetched.push(name),
+            Err(err) => report.push(format!("{:#}", err)),
+        }
+    }
+    let all = report.is_empty();
+
+    let meta = RepoMeta::new(repo);
+    for name in fetched {
+        let tracking: Vec<String> = meta
+            .local_branches()
+            .iter()
+            .filter(|local| local.upstream.as_ref() == Some(name))
+            .map(
+                |local| match local.divergence.map(|divergence| divergence.label()) {
+                    Some(label) if !label.is_empty() => format!("{} {}", local.name, label),
+                    _ => format!("{} up to date", local.name),
+                },
+            )
+            .collect();
+        report.push(if tracking.is_empty() {
+            format!("fetched {}", name)
+        } else {
+            format!("fetched {}: {}", name, tracking.join(", "))
+        });
+    }
+    (report, all)
+}
+
+/// What the worker needs to run the actions, as the repository can't be shared with its thread
+pub struct Context {
+    pub git_dir: PathBuf,
+    pub workdir: PathBuf,
+    pub skip_hooks: bool,
+    pub pulls: OpenPulls,
+    pub policy: Policy,
+}
+
+/// The worker running the actions sent by the picker
+pub struct Bus {
+    emit: Sender<Emitted>,
+    worker: JoinHandle<()>,
+}
+
+impl Bus {
+    /// Start the worker, which sends what the actions did on `status`, and on `done` whether
+    /// each succeeded, i.e. was done for all the picked branches, once it is over
+    pub fn spawn(context: Context, status: Sender<String>, done: Sender<bool>) -> Self {
+        let (emit, emitted) = unbounded::<Emitted>();
+        let worker = thread::spawn(move || {
+            for emitted in emitted {
+                let (action, mut report) = BranchAction::of(&emitted);
+                // the skipped branches fail it
+                let mut succeeded = report.is_empty();
+                match action {
+                    Some(action) => {
+                        let _ = status.send(action.running());
+                        let ran = alternates::open(&context.git_dir)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|repo| action.run(&repo, &context, &status));
+                        match ran {
+                            Ok((ran, all)) => {
+                                report.extend(ran);
+                                succeeded &= all;
+                            }
+                            Err(err) => {
+                                report.push(format!("{:#}", err));
+                                succeeded = false;
+                            }
+                        }
+                    }
+                    None => succeeded = false,
+                }
+                if !report.is_empty() {
+                    let _ = status.send(report.join(" · "));
+                }
+                let _ = done.send(succeeded);
+            }
+        });
+
+        Self { emit, worker }
+    }
+
+    /// Where the picker sends the actions, see `SkimOptions::emit`
+    pub fn emitter(&self) -> Sender<Emitted> {
+        self.emit.clone()
+    }
+
+    /// Wait for the last action to be done once the picker was closed, so that checking out the
+    /// picked branches or acting on them doesn't race with it
+    pub fn finish(self) {
+        drop(self.emit);
+        let _ = self.worker.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::skim::SkimItem;
+    use crate::testing::{self, TempDir};
+    use crate::{LocalBranch, RemoteBranch};
+    use std::{cell::RefCell, sync::Arc};
+
+    fn local(name: &str) -> Arc<dyn SkimItem> {
+        Arc::new(Branch::Local(LocalBranch {
+            name: name.to_string(),
+            ..LocalBranch::default()
+        }))
+    }
+
+    #[test]
+    fn test_of() {
+        let emitted = |name: &str, items: Vec<Arc<dyn SkimItem>>| Emitted {
+            name: name.to_string(),
+            items,
+        };
+        let remote: Arc<dyn SkimItem> = Arc::new(Branch::Remote(RemoteBranch {
+            name: "origin/main".to_string(),
+            ..RemoteBranch::default()
+        }));
+
+        assert_eq!(
+            BranchAction::of(&emitted(
+                "delete",
+                vec![local("a"), remote.clone(), local("b")]
+            )),
+            (
+                Some(BranchAction::Delete(vec!["a".to_string(), "b".to_string()])),
+                vec!["origin/main is a remote branch, skipping".to_string()]
+            )
+        );
+        assert_eq!(
+            BranchAction::of(&emitted("fast-forward", vec![remote.clone()])),
+            (
+                None,
+                vec!["origin/main is a remote branch, skipping".to_string()]
+            )
+        );
+        assert_eq!(
+            BranchAction::of(&emitted("fetch-selected", vec![local("a"), remote.clone()])),
+            (
+                Some(BranchAction::FetchSelected(vec!["origin/main".to_string()])),
+                vec!["a is a local branch, skipping".to_string()]
+            )
+        );
+        assert_eq!(
+            BranchAction::of(&emitted("fetch", vec![remote])),
+            (Some(BranchAction::Fetch), vec![])
+        );
+        assert_eq!(
+            BranchAction::binding("ctrl-r", BranchAction::FETCH),
+            "ctrl-r:emit(fetch)"
+        );
+    }
+
+    #[test]
+    fn test_fetch_selected() {
+        let dir = TempDir::new("fetch-selected");
+        let upstream = Repository::init_bare(dir.join("upstream.git")).unwrap();
+        let old = testing::commit(&upstream, Some("refs/heads/feature"), "old", &[], &[]);
+        upstream
+            .reference("refs/heads/main", old, false, "test")
+            .unwrap();
+
+        let repo = Repository::init(dir.join("clone")).unwrap();
+        repo.remote("origin", dir.join("upstream.git").to_str().unwrap())
+            .unwrap();
+        remote::fetch_branch(&repo, "origin", "feature").unwrap();
+        repo.branch("feature", &repo.find_commit(old).unwrap(), false)
+            .unwrap()
+            .set_upstream(Some("origin/feature"))
+            .unwrap();
+        // the remote moved on since
+        testing::commit(&upstream, Some("refs/heads/feature"), "new", &[], &[old]);
+
+        let progress = RefCell::new(vec![]);
+        let names = ["origin/feature", "nowhere/main", "origin/main"].map(str::to_string);
+        let (report, all) = fetch_selected(&repo, &names, |line| progress.borrow_mut().push(line));
+        assert!(!all);
+        assert_eq!(
+            report,
+            [
+                "There is no remote to fetch nowhere/main from",
+                "fetched origin/feature: feature ↓1",
+                "fetched origin/main",
+            ]
+        );
+        assert_eq!(
+            progress.into_inner(),
+            [
+                "fetching origin/feature (1/3)…",
+                "fetching nowhere/main (2/3)…",
+                "fetching origin/main (3/3)…",
+            ]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+//! Collation of branch names for sorting and grouping.
+use std::cmp::Ordering;
+
+/// Compare branch names case-insensitively, with numbers compared by value and accented letters
+/// next to their base letter, so that `feature/Item2` < `feature/item10` and `ä` sorts with `a`.
+///
+/// Names that only differ in case fall back to byte order, so the result is still a total order.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    lexical_sort::natural_lexical_cmp(a, b).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let mut names = vec![
+            "feature/item10",
+            "Zebra",
+            "feature/Item2",
+            "äpfel",
+            "bugfix/x",
+            "feature/item2",
+        ];
+        names.sort_by(|a, b| compare(a, b));
+        assert_eq!(
+            names,
+            vec![
+                "äpfel",
+                "bugfix/x",
+                "feature/Item2",
+                "feature/item2",
+                "feature/item10",
+                "Zebra",
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Checking out a remote branch that no local branch tracks yet creates a local branch of its
+//! short name tracking it, e.g. `feature` for `origin/feature`. When a local branch of that name
+//! exists already and doesn't track it, what to do is asked first: check out the local branch as
+//! it is, create one with the name of the remote added (`feature-origin`), or reset the local
+//! branch to the remote one, once told how many commits it would lose.
+use crate::{prompt, state, RemoteBranch};
+use anyhow::{bail, Result};
+use git2::{BranchType, Repository};
+use std::io::{self, IsTerminal};
+
+/// How a remote branch is checked out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Plan {
+    /// Create the local branch `name` from `start`, tracking it
+    Create { name: String, start: String },
+    /// Check out the local branch `name` as it is
+    Existing(String),
+    /// Point the local branch `name` to `start`, tracking it
+    Reset { name: String, start: String },
+}
+
+impl Plan {
+    /// The local branch checked out
+    pub fn local_name(&self) -> &str {
+        match self {
+            Plan::Create { name, .. } | Plan::Existing(name) | Plan::Reset { name, .. } => name,
+        }
+    }
+
+    /// What the step of the checkout is logged as, e.g. `create and check out feature`
+    pub fn description(&self) -> String {
+        match self {
+            Plan::Create { name, .. } => format!("create and check out {}", name),
+            Plan::Existing(name) => format!("check out {}", name),
+            Plan::Reset { name, start } => format!("reset {} to {} and check it out", name, start),
+        }
+    }
+
+    /// The arguments of `git checkout`
+    pub fn checkout_args(&self) -> Vec<&str> {
+        match self {
+            Plan::Create { name, start } => vec!["-b", name, "--track", start],
+            Plan::Existing(name) => vec![name],
+            Plan::Reset { name, start } => vec!["-B", name, "--track", start],
+        }
+    }
+}
+
+/// The name of `branch` without its remote, e.g. `feature/login` for `origin/feature/login`, and
+/// the remote
+fn split(repo: &Repository, branch: &str) -> (String, String) {
+    let remote = repo
+        .branch_remote_name(&format!("refs/remotes/{}", branch))
+        .ok()
+        .and_then(|remote| remote.as_str().map(str::to_string));
+    match remote {
+        Some(remote) => match branch.strip_prefix(&format!("{}/", remote)) {
+            Some(short) => (short.to_string(), remote),
+            None => (branch.to_string(), remote),
+        },
+        None => match branch.split_once('/') {
+            Some((remote, short)) => (short.to_string(), remote.to_string()),
+            None => (branch.to_string(), String::new()),
+        },
+    }
+}
+
+/// A name for a second local branch of `short`, e.g. `feature-origin`, or `feature-origin-2`
+/// when that one is taken as well
+fn suffixed(repo: &Repository, short: &str, remote: &str) -> String {
+    let base = format!("{}-{}", short, remote);
+    let taken = |name: &str| repo.find_branch(name, BranchType::Local).is_ok();
+    if !taken(&base) {
+        return base;
+    }
+    (2..)
+        .map(|number| format!("{}-{}", base, number))
+        .find(|name| !taken(name))
+        .unwrap_or(base)
+}
+
+/// How many commits of the local branch `name` the remote branch `start` doesn't have
+fn lost_commits(repo: &Repository, name: &str, start: &str) -> usize {
+    let tip = |refname: String| repo.refname_to_id(&refname).ok();
+    let local = tip(format!("refs/heads/{}", name));
+    let remote = tip(format!("refs/remotes/{}", start));
+    match (local, remote) {
+        (Some(local), Some(remote)) => repo
+            .graph_ahead_behind(local, remote)
+            .map(|(ahead, _)| ahead)
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+/// Whether the local branch `name` is checked out, here or in another worktree
+fn checked_out(repo: &Repository, name: &str) -> bool {
+    let refname = format!("refs/heads/{}", name);
+    let here = repo
+        .head()
+        .is_ok_and(|head| head.name() == Some(refname.as_str()));
+    here || state::worktree_holding(repo, &refname).is_some()
+}
+
+/// How to check out `branch`, a remote branch that no local branch tracks, asking when a local
+/// branch has its name already
+pub fn plan(repo: &Repository, branch: &RemoteBranch) -> Result<Plan> {
+    let start = branch.name.clone();
+    let (short, remote) = split(repo, &start);
+    if repo.find_branch(&short, BranchType::Local).is_err() {
+        return Ok(Plan::Create { name: short, start });
+    }
+    if !io::stdin().is_terminal() {
+        bail!(
+            "A local branch {} exists already and doesn't track {}. Check it out, or pick {} in a \
+             terminal to create another branch or reset {}",
+            short,
+            start,
+            start,
+            short
+        );
+    }
+
+    let mut plans = vec![
+        Plan::Existing(short.clone()),
+        Plan::Create {
+            name: suffixed(repo, &short, &remote),
+            start: start.clone(),
+        },
+    ];
+    let mut choices = vec![
+        format!("Check out the local {} as it is", short),
+        format!("Create {} from {}", plans[1].local_name(), start),
+    ];
+    if !checked_out(repo, &short) {
+        plans.push(Plan::Reset {
+            name: short.clone(),
+            start: start.clone(),
+        });
+        choices.push(format!("Reset {} to {}", short, start));
+    }
+    let question = format!(
+        "A local branch {} exists already and doesn't track {}:",
+        short, start
+    );
+    let plan = match prompt::choose(&question, &choices)? {
+        Some(index) => plans.swap_remove(index),
+        None => bail!("Cancelled, nothing was checked out"),
+    };
+
+    if let Plan::Reset { name, start } = &plan {
+        let lost = lost_commits(repo, name, start);
+        let question = format!(
+            "{} has {} commits that {} doesn't have, which would be lost. Reset it?",
+            name, lost, start
+        );
+        if lost > 0 && !prompt::confirm(&question, false)? {
+            bail!("Cancelled, nothing was checked out");
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `origin/feature/login` colliding with a local `feature/login` two commits ahead of it, and
+    /// with `feature/login-origin` too, and `origin/fresh` colliding with nothing
+    fn colliding(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let root = testing::commit(&repo, None, "root", &[], &[]);
+        let middle = testing::commit(&repo, None, "middle", &[], &[root]);
+        let local = testing::commit(&repo, None, "local", &[], &[middle]);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        for (refname, tip) in [
+            ("refs/remotes/origin/feature/login", root),
+            ("refs/remotes/origin/fresh", root),
+            ("refs/heads/feature/login", local),
+            ("refs/heads/feature/login-origin", root),
+        ] {
+            repo.reference(refname, tip, false, "test").unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_split() {
+        let repo = colliding("collision-split");
+        assert_eq!(
+            split(&repo, "origin/feature/login"),
+            ("feature/login".to_string(), "origin".to_string())
+        );
+    }
+
+    #[test]
+    fn test_suffixed() {
+        let repo = colliding("collision-suffixed");
+        assert_eq!(
+            suffixed(&repo, "feature/login", "origin"),
+            "feature/login-origin-2"
+        );
+        assert_eq!(suffixed(&repo, "fresh", "origin"), "fresh-origin");
+    }
+
+    #[test]
+    fn test_lost_commits() {
+        let repo = colliding("collision-lost");
+        assert_eq!(
+            lost_commits(&repo, "feature/login", "origin/feature/login"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_plan_without_collision() {
+        let repo = colliding("collision");
+        let fresh = plan(
+            &repo,
+            &RemoteBranch {
+                name: "origin/fresh".to_string(),
+                ..RemoteBranch::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            fresh.checkout_args(),
+            ["-b", "fresh", "--track", "origin/fresh"]
+        );
+    }
+}
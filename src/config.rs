@@ -0,0 +1,1154 @@
+use crate::secrets::Secret;
+use crate::shortcut;
+use crate::template::Template;
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE_NAME: &str = "config.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub workspace: WorkspaceConfig,
+    pub keys: KeysConfig,
+    pub naming: NamingConfig,
+    pub issues: IssuesConfig,
+    pub checkout: CheckoutConfig,
+    pub gitflow: GitflowConfig,
+    pub web: WebConfig,
+    pub layout: LayoutConfig,
+    pub perf: PerfConfig,
+    pub picker: PickerConfig,
+
+    /// The changes `gibra new --template <name>` commits on a new branch, by name
+    pub templates: HashMap<String, TemplateConfig>,
+
+    /// Commands listing items besides the branches, see [`crate::source`]
+    pub sources: Vec<SourceConfig>,
+
+    /// Commands run on the picked branches from the menu or with their key, by name, see
+    /// [`crate::custom`]
+    pub commands: BTreeMap<String, CommandConfig>,
+
+    /// Branches created by typing a shortcut and their name in the picker, by shortcut, see
+    /// [`crate::shortcut`]
+    pub shortcuts: BTreeMap<String, ShortcutConfig>,
+
+    /// Rules blocking, or asking to confirm, operations on some branches, see [`crate::policy`]
+    pub policies: PoliciesConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Directories that are scanned for git repositories by `gibra ws`
+    pub roots: Vec<PathBuf>,
+
+    /// How many directory levels below each root are scanned
+    pub max_depth: usize,
+
+    /// How long (in seconds) the list of scanned repositories is reused
+    pub cache_ttl: u64,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            roots: vec![],
+            max_depth: 3,
+            cache_ttl: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Keys that run an action on the picked branches instead of checking them out
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct KeysConfig {
+    /// Fast-forward the picked branches to their upstream
+    pub fast_forward: String,
+
+    /// Delete the picked local branches
+    pub delete: String,
+
+    /// Merge the picked feature, release or hotfix branches back (with `--gitflow`)
+    pub finish: String,
+
+    /// Open the picked branches in the browser
+    pub web: String,
+
+    /// Make the same-named branches of a remote the upstreams of the picked local branches
+    pub set_upstream: String,
+
+    /// Make the picked remote branch, or the upstream of the picked local branch, the default
+    /// branch of its remote
+    pub set_default: String,
+
+    /// Push the picked local branches to a remote picked next, keeping their upstreams
+    pub set_push_remote: String,
+
+    /// Pick a commit of the picked branch and create a new branch there
+    pub branch_off: String,
+
+    /// Fetch just the picked remote branches, rather than every remote
+    pub fetch_selected: String,
+
+    /// Open a menu of the actions next to the branch under the cursor
+    pub menu: String,
+
+    /// The named set of navigation keys of the pickers
+    pub keymap: Keymap,
+
+    /// More bindings of the branch picker, as `<key>:<action>+<action>…`, e.g.
+    /// `ctrl-d:emit(delete)+down`. They win over the keys above.
+    pub bind: Vec<String>,
+}
+
+/// The navigation keys of the pickers, on top of the keys above
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Keymap {
+    /// The keys of skim, like fzf
+    #[default]
+    Default,
+
+    /// `esc` switches to moving with `j`/`k` and `gg`/`G`, `/` back to typing the query
+    Vim,
+
+    /// `ctrl-k` kills the rest of the query, `ctrl-w` the word before the cursor, and `ctrl-v`
+    /// and `alt-v` page through the list
+    Emacs,
+}
+
+impl Default for KeysConfig {
+    fn default() -> Self {
+        Self {
+            fast_forward: "alt-u".to_string(),
+            delete: "ctrl-d".to_string(),
+            finish: "alt-m".to_string(),
+            web: "ctrl-o".to_string(),
+            set_upstream: "alt-t".to_string(),
+            set_default: "alt-h".to_string(),
+            set_push_remote: "alt-p".to_string(),
+            branch_off: "alt-n".to_string(),
+            fetch_selected: "alt-e".to_string(),
+            menu: "alt-enter".to_string(),
+            keymap: Keymap::Default,
+            bind: vec![],
+        }
+    }
+}
+
+/// How the picker opens
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PickerConfig {
+    /// Open the picker where it was left in the repository: with the same query, the same kind
+    /// of branches shown, and the cursor on the same branch
+    pub resume: bool,
+    pub initial_cursor: InitialCursor,
+    /// Open the picker after at most this many milliseconds of counting how far the branches are
+    /// ahead and behind, filling the rest in the background, e.g. on NFS
+    pub budget: Option<u64>,
+    /// Show the tabs of the branches, the tags, the stashes, the worktrees and the pull requests
+    /// in the header of the picker, like `--tabs`
+    pub tabs: bool,
+}
+
+/// Where the cursor of the picker starts, unless `resume` puts it where it was left
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialCursor {
+    /// On the first branch, which is the current one
+    #[default]
+    Top,
+
+    /// On the branch checked out before the current one, like `git checkout -`
+    Previous,
+}
+
+/// Timing what gibra does, see `gibra perf`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PerfConfig {
+    /// Record how long listing the branches and checking out take, in a file in the cache
+    /// directory that never leaves the machine
+    pub record: bool,
+}
+
+/// How the branches are drawn in the picker
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub truncate: Truncate,
+
+    /// Show how long ago the tip of each branch was committed, e.g. `3d`, like `--age`
+    pub age: bool,
+
+    /// The format of the dates of the age column, e.g. `%d %b %Y %H:%M`, by default after the
+    /// locale of `LC_TIME`
+    pub date_format: Option<String>,
+
+    /// The colors of the pickers, like `--color`, e.g. `preview_bg:#fdf6e3,diff_add:28`
+    pub color: Option<String>,
+
+    /// The border around the preview, like `--border`. A line between it and the branches when
+    /// not set.
+    pub border: Option<Border>,
+}
+
+/// How the preview is set apart from the branches
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Border {
+    /// A box with round corners
+    Rounded,
+
+    /// A box with square corners
+    Sharp,
+
+    /// A blank column
+    None,
+}
+
+/// The part of a branch name that is cut when it is wider than the picker
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Truncate {
+    /// Keep the prefix and the suffix, e.g. `feature/…-login`
+    #[default]
+    Middle,
+
+    /// Keep the beginning, e.g. `feature/JIRA-12…`
+    End,
+
+    /// Keep the end, e.g. `…1234-rework-login`
+    Start,
+}
+
+/// The convention that names of newly created branches have to follow
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConfig {
+    /// A regex that new branch names have to match, e.g. `^(feat|fix)/[A-Z]+-[0-9]+-[a-z0-9-]+$`
+    pub branch_name_pattern: Option<String>,
+
+    /// A human-readable description of the pattern, shown when a name doesn't match it
+    pub convention: Option<String>,
+
+    /// Put in front of the last segment of a new branch name when an issue is given, with
+    /// `{issue}` replaced by its ID
+    pub issue_prefix: String,
+}
+
+impl Default for NamingConfig {
+    fn default() -> Self {
+        Self {
+            branch_name_pattern: None,
+            convention: None,
+            issue_prefix: "{issue}-".to_string(),
+        }
+    }
+}
+
+/// Extra steps around checking out a branch. When a step fails, the ones before it are undone.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CheckoutConfig {
+    /// Stash local changes before checking out, and put them back afterwards
+    pub autostash: bool,
+
+    /// Run `git submodule update --init --recursive` after checking out
+    pub update_submodules: bool,
+
+    /// Don't run the `post-checkout` and `post-merge` hooks of the repository
+    pub skip_hooks: bool,
+
+    /// Start new branches from the default branch of the remote, fetched right before, instead
+    /// of from HEAD
+    pub fresh_base: bool,
+
+    /// Fetch a picked remote branch that has no local branch before checking it out, as
+    /// `--ls-remote` lists branches that may have never been fetched. Only set by `--ls-remote`
+    #[serde(skip)]
+    pub fetch_picked: bool,
+
+    /// Switch branches even while a rebase, merge and the like is in progress, and discard the
+    /// local changes in the way of a checkout. Only set by `--force`, never by the config file
+    #[serde(skip)]
+    pub force: bool,
+
+    /// Discard local changes with `force` without asking. Only set by `--yes`
+    #[serde(skip)]
+    pub yes: bool,
+
+    /// The directories to check out for the branches matching a pattern, the first matching
+    /// entry winning
+    pub sparse: Vec<SparseConfig>,
+}
+
+/// The directories of the working tree that branches matching `branches` need, which are set
+/// with `git sparse-checkout` after checking out one of them
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SparseConfig {
+    /// e.g. `payments/*`, where `*` matches any part of a name and `?` one character
+    pub branches: String,
+
+    /// The directories to check out, or none for the whole tree
+    pub dirs: Vec<String>,
+}
+
+/// A command whose output lists items for the picker, one per line as
+/// `<id>\t<display>\t<metadata>`, e.g. `git tag --format='%(refname)%09%(refname:short)'`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SourceConfig {
+    /// How warnings tell the source when the command fails, e.g. `tags`
+    pub name: String,
+
+    /// Run with `sh -c` in the working tree
+    pub command: String,
+}
+
+/// A command run on each picked branch, given as the command line alone, e.g.
+/// `deploy = "ci deploy {branch}"`, or as a table
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "CommandEntry")]
+pub struct CommandConfig {
+    /// Run with `sh -c`, with the placeholders replaced by the quoted values of the branch
+    pub run: String,
+
+    /// The key running it in the picker, besides the menu
+    pub key: Option<String>,
+
+    /// Where it runs, relative to the root of the working tree, e.g. `{worktree}` for the
+    /// worktree the branch is checked out in. The root of the working tree by default.
+    pub cwd: Option<String>,
+
+    /// Ask before running it
+    pub confirm: bool,
+
+    /// Show its output in a scrollable view once it is done, instead of on the terminal
+    pub capture: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CommandEntry {
+    Line(String),
+    Table(CommandTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandTable {
+    run: String,
+    key: Option<String>,
+    cwd: Option<String>,
+    #[serde(default)]
+    confirm: bool,
+    #[serde(default)]
+    capture: bool,
+}
+
+impl From<CommandEntry> for CommandConfig {
+    fn from(entry: CommandEntry) -> Self {
+        match entry {
+            CommandEntry::Line(run) => Self {
+                run,
+                ..Self::default()
+            },
+            CommandEntry::Table(table) => Self {
+                run: table.run,
+                key: table.key,
+                cwd: table.cwd,
+                confirm: table.confirm,
+                capture: table.capture,
+            },
+        }
+    }
+}
+
+/// A branch created from the picker, given as the template of its name alone, e.g.
+/// `f = "feature/{words}"`, or as a table
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ShortcutEntry")]
+pub struct ShortcutConfig {
+    /// The name of the branch, where `{words}` are the words typed after the shortcut joined with
+    /// dashes, `{first}` the first of them and `{rest}` the others
+    pub template: String,
+
+    /// Where the branch starts, e.g. `develop`. The default branch by default.
+    pub base: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ShortcutEntry {
+    Line(String),
+    Table(ShortcutTable),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShortcutTable {
+    template: String,
+    base: Option<String>,
+}
+
+impl TryFrom<ShortcutEntry> for ShortcutConfig {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: ShortcutEntry) -> Result<Self> {
+        let config = match entry {
+            ShortcutEntry::Line(template) => Self {
+                template,
+                base: None,
+            },
+            ShortcutEntry::Table(table) => Self {
+                template: table.template,
+                base: table.base,
+            },
+        };
+        Template::parse_strict(
+            &config.template,
+            &shortcut::PLACEHOLDERS,
+            "the template of a shortcut",
+        )?;
+        Ok(config)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoliciesConfig {
+    /// Patterns of the names of the protected branches, e.g. `^release/`, for the rules with
+    /// `is_protected`
+    pub protected: Vec<String>,
+
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A rule of the team, e.g. that the frozen release branches aren't checked out
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    /// The operations it applies to
+    pub on: Vec<Operation>,
+
+    /// A pattern of the names of the branches it applies to, all of them by default
+    pub branches: Option<String>,
+
+    /// What must hold for the branch besides, e.g. `["has_open_pr"]`
+    #[serde(default)]
+    pub when: Vec<Condition>,
+
+    #[serde(default)]
+    pub level: Level,
+
+    /// Why, shown when the rule applies
+    pub message: String,
+}
+
+/// An operation on branches that rules can apply to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Checkout,
+    Delete,
+    Rename,
+    /// Merging a gitflow branch back
+    Finish,
+}
+
+/// What a rule can require of a branch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// Its name matches a pattern of `protected`
+    IsProtected,
+
+    /// It has an open pull request on GitHub
+    HasOpenPr,
+
+    /// It is the default branch of a remote
+    IsDefault,
+}
+
+/// What happens when a rule applies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The operation is asked to confirm, with the message
+    #[default]
+    Warn,
+
+    /// The operation isn't done on the branch
+    Block,
+}
+
+/// Files written on a newly created branch and committed, e.g. a section of the changelog or a
+/// feature flag. `{branch}`, `{issue}` and `{date}` are replaced in the paths, the contents and
+/// the message.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateConfig {
+    pub files: Vec<TemplateFileConfig>,
+
+    /// The message of the commit, e.g. `chore: start {branch}`
+    pub message: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateFileConfig {
+    /// Relative to the root of the working tree, e.g. `flags/{branch}.toml`
+    pub path: String,
+
+    pub content: String,
+
+    #[serde(default)]
+    pub mode: TemplateMode,
+}
+
+/// How the content of a template file is written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateMode {
+    /// Into a new file, failing when it exists
+    #[default]
+    Create,
+
+    /// At the end of the file, creating it when missing
+    Append,
+
+    /// At the top of the file, or below its first line when that is a `# ` heading, like the
+    /// title of a changelog
+    Prepend,
+}
+
+/// The branch names of the gitflow branching model, used with `--gitflow`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitflowConfig {
+    /// The production branch. By default `main`, or `master` when there is no `main`
+    pub main: Option<String>,
+
+    /// The integration branch that features are merged into
+    pub develop: String,
+
+    pub feature_prefix: String,
+    pub release_prefix: String,
+    pub hotfix_prefix: String,
+}
+
+impl Default for GitflowConfig {
+    fn default() -> Self {
+        Self {
+            main: None,
+            develop: "develop".to_string(),
+            feature_prefix: "feature/".to_string(),
+            release_prefix: "release/".to_string(),
+            hotfix_prefix: "hotfix/".to_string(),
+        }
+    }
+}
+
+/// Which page of a branch is opened in the browser
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WebView {
+    /// The branch itself, with its files and last commit
+    #[default]
+    Branch,
+
+    /// The changes of the branch against the default branch
+    Compare,
+}
+
+/// Opening branches on the web page of the remote's host
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebConfig {
+    pub view: WebView,
+
+    /// URL templates of self-hosted servers by host name, which are used instead of the
+    /// GitHub, GitLab and Bitbucket ones
+    pub hosts: HashMap<String, WebHostConfig>,
+}
+
+/// URL templates of a host, with `{host}`, `{repo}` (e.g. `owner/name`), `{branch}` and
+/// `{base}` (the default branch) replaced
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebHostConfig {
+    /// e.g. `https://{host}/{repo}/src/branch/{branch}`
+    pub branch: String,
+
+    /// e.g. `https://{host}/{repo}/compare/{base}...{branch}`
+    pub compare: String,
+}
+
+/// Where `gibra issue` gets the issues assigned to you from
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IssuesConfig {
+    pub github: Option<GithubConfig>,
+    pub jira: Option<JiraConfig>,
+
+    /// The name of a branch created for an issue, with `{key}` and `{title}` replaced
+    pub branch_template: String,
+
+    /// How long (in seconds) the fetched issues are reused
+    pub cache_ttl: u64,
+}
+
+impl Default for IssuesConfig {
+    fn default() -> Self {
+        Self {
+            github: None,
+            jira: None,
+            branch_template: "{key}-{title}".to_string(),
+            cache_ttl: 10 * 60,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GithubConfig {
+    /// Deprecated, as it is kept in plain text: the token is looked up in the environment, the
+    /// keychain (`gibra auth login github`) and the GitHub CLI first
+    pub token: Option<Secret>,
+
+    /// The API of a GitHub Enterprise server, e.g. `https://github.example.com/api/v3`
+    #[serde(default = "default_github_api_url")]
+    pub api_url: String,
+}
+
+fn default_github_api_url() -> String {
+    "https://api.github.com".to_string()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JiraConfig {
+    /// e.g. `https://example.atlassian.net`
+    pub url: String,
+
+    /// The account of an API token on Jira Cloud; leave it out for a personal access token
+    pub email: Option<String>,
+
+    /// Deprecated, as it is kept in plain text: the token is looked up in the environment and the
+    /// keychain (`gibra auth login jira`) first
+    pub token: Option<Secret>,
+}
+
+impl Config {
+    /// Load the user config, falling back to the defaults when there is no config file, with the
+    /// `gibra.*` settings of the git config of `repo` on top, or of the global git config outside
+    /// of a repository.
+    pub fn load(repo: Option<&Repository>) -> Result<Config> {
+        let path = config_path().unwrap_or_default();
+        let content = match path.exists() {
+            true => fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?,
+            false => String::new(),
+        };
+
+        // the config of the repository includes the global and the system ones
+        let git_config = match repo {
+            Some(repo) => repo.config(),
+            None => git2::Config::open_default(),
+        };
+        let entries = match git_config {
+            Ok(mut git_config) => git_entries(&mut git_config)?,
+            Err(_) => vec![],
+        };
+        if entries.is_empty() {
+            return Config::parse(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()));
+        }
+
+        let mut table: toml::Table = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let config = overlay(&mut table, &entries)
+            .and_then(|()| Ok(table.try_into()?))
+            .with_context(|| "Failed to apply the gibra settings of the git config")?;
+        Ok(Config::expanded(config))
+    }
+
+    fn parse(content: &str) -> Result<Config> {
+        Ok(Config::expanded(toml::from_str(content)?))
+    }
+
+    /// `config` with the `~` of its paths expanded
+    fn expanded(mut config: Config) -> Config {
+        config.workspace.roots = config
+            .workspace
+            .roots
+            .iter()
+            .map(|root| expand_tilde(root))
+            .collect();
+        config
+    }
+}
+
+/// The `gibra.*` settings of a git config, e.g. `gibra.naming.convention` from the `convention`
+/// of a `[gibra "naming"]` section, in the order git reads them
+fn git_entries(config: &mut git2::Config) -> Result<Vec<(String, Option<String>)>> {
+    let config = config.snapshot()?;
+    let mut entries = vec![];
+    let mut iter = config.entries(Some("^gibra\\."))?;
+    while let Some(entry) = iter.next() {
+        let entry = entry?;
+        let name = entry.name().unwrap_or_default().to_string();
+        // `value` panics on a variable without a value
+        let value = entry
+            .has_value()
+            .then(|| String::from_utf8_lossy(entry.value_bytes()).into_owned());
+        entries.push((name, value));
+    }
+    Ok(entries)
+}
+
+/// A value of the git config as TOML: `true` and the like, numbers, and strings otherwise. A
+/// variable without a value, like `autostash` alone on its line, is true.
+fn git_value(value: Option<&str>) -> toml::Value {
+    let value = match value {
+        Some(value) => value,
+        None => return toml::Value::Boolean(true),
+    };
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" => toml::Value::Boolean(true),
+        "false" | "no" | "off" => toml::Value::Boolean(false),
+        _ => match value.parse() {
+            Ok(number) => toml::Value::Integer(number),
+            Err(_) => toml::Value::String(value.to_string()),
+        },
+    }
+}
+
+/// Set the git config `entries` in the tables of the config file, replacing what they set. Like
+/// with git, the last value of a variable given more than once wins, e.g. that of the repository
+/// over that of an included file.
+fn overlay(table: &mut toml::Table, entries: &[(String, Option<String>)]) -> Result<()> {
+    for (name, value) in entries {
+        let setting = name.strip_prefix("gibra.").unwrap_or(name);
+        let (section, variable) = match setting.rsplit_once('.') {
+            Some((section, variable)) if !section.contains('.') => (section, variable),
+            _ => bail!(
+                "{} is not a setting of gibra, which are named like gibra.<section>.<key>",
+                name
+            ),
+        };
+        let section = section.to_lowercase();
+        // git doesn't allow `_` in names, and the keys of `[keys]` are written with `-` anyway
+        let key = if section == "keys" {
+            variable.to_string()
+        } else {
+            variable.replace('-', "_")
+        };
+        let table = table
+            .entry(section.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        let table = match table {
+            toml::Value::Table(table) => table,
+            _ => bail!("{} is not a section of the config", section),
+        };
+        table.insert(key, git_value(value.as_deref()));
+    }
+    Ok(())
+}
+
+/// Where the config file is looked for
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// `$XDG_CONFIG_HOME/gibra`, or `~/.config/gibra`
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_CACHE_HOME/gibra`, or `~/.cache/gibra`
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir()?.join(fallback),
+    };
+
+    Some(base.join("gibra"))
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Replace a leading `~` with the home directory
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = Config::parse("").unwrap();
+        assert!(config.workspace.roots.is_empty());
+        assert_eq!(config.workspace.max_depth, 3);
+    }
+
+    #[test]
+    fn test_parse_workspace() {
+        let config = Config::parse(
+            r#"
+            [workspace]
+            roots = ["/src", "/work"]
+            max_depth = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.workspace.roots,
+            vec![PathBuf::from("/src"), PathBuf::from("/work")]
+        );
+        assert_eq!(config.workspace.max_depth, 2);
+        assert_eq!(config.workspace.cache_ttl, 24 * 60 * 60);
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let config = Config::parse("[keys]\nfast-forward = \"ctrl-o\"").unwrap();
+        assert_eq!(config.keys.fast_forward, "ctrl-o");
+        assert_eq!(Config::parse("").unwrap().keys.fast_forward, "alt-u");
+        assert_eq!(Config::parse("").unwrap().keys.delete, "ctrl-d");
+        assert_eq!(Config::parse("").unwrap().keys.set_upstream, "alt-t");
+        assert_eq!(Config::parse("").unwrap().keys.keymap, Keymap::Default);
+        let config = Config::parse("[keys]\nkeymap = \"vim\"").unwrap();
+        assert_eq!(config.keys.keymap, Keymap::Vim);
+        let config = Config::parse("[keys]\nbind = [\"ctrl-d:emit(delete)+down\"]").unwrap();
+        assert_eq!(config.keys.bind, ["ctrl-d:emit(delete)+down"]);
+    }
+
+    #[test]
+    fn test_parse_web() {
+        let config = Config::parse(
+            r#"
+            [web]
+            view = "compare"
+
+            [web.hosts."git.example.com"]
+            branch = "https://{host}/{repo}/src/branch/{branch}"
+            compare = "https://{host}/{repo}/compare/{base}...{branch}"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.web.view, WebView::Compare);
+        assert_eq!(
+            config.web.hosts["git.example.com"].branch,
+            "https://{host}/{repo}/src/branch/{branch}"
+        );
+        assert_eq!(Config::parse("").unwrap().web.view, WebView::Branch);
+        assert_eq!(Config::parse("").unwrap().keys.web, "ctrl-o");
+    }
+
+    #[test]
+    fn test_parse_naming() {
+        let config = Config::parse(
+            r#"
+            [naming]
+            branch_name_pattern = "^(feat|fix)/"
+            convention = "type/description"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.naming.branch_name_pattern.as_deref(),
+            Some("^(feat|fix)/")
+        );
+        assert_eq!(
+            config.naming.convention.as_deref(),
+            Some("type/description")
+        );
+        assert_eq!(config.naming.issue_prefix, "{issue}-");
+    }
+
+    #[test]
+    fn test_parse_issues() {
+        let config = Config::parse(
+            r#"
+            [issues]
+            branch_template = "feat/{key}-{title}"
+
+            [issues.github]
+
+            [issues.jira]
+            url = "https://example.atlassian.net"
+            email = "me@example.com"
+            token = "x"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.issues.branch_template, "feat/{key}-{title}");
+        assert_eq!(
+            config.issues.github.as_ref().unwrap().api_url,
+            "https://api.github.com"
+        );
+        assert!(config.issues.github.as_ref().unwrap().token.is_none());
+        let jira = config.issues.jira.unwrap();
+        assert_eq!(jira.email.as_deref(), Some("me@example.com"));
+        assert_eq!(jira.token.unwrap().expose(), "x");
+        assert!(Config::parse("").unwrap().issues.github.is_none());
+    }
+
+    #[test]
+    fn test_parse_checkout() {
+        let config = Config::parse("[checkout]\nautostash = true").unwrap();
+        assert!(config.checkout.autostash);
+        assert!(!config.checkout.update_submodules);
+        assert!(!config.checkout.skip_hooks);
+
+        let config = Config::parse(
+            r#"
+            [[checkout.sparse]]
+            branches = "payments/*"
+            dirs = ["services/payments", "libs/common"]
+
+            [[checkout.sparse]]
+            branches = "*"
+            dirs = []
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.checkout.sparse.len(), 2);
+        assert_eq!(config.checkout.sparse[0].branches, "payments/*");
+        assert!(config.checkout.sparse[1].dirs.is_empty());
+    }
+
+    #[test]
+    fn test_parse_picker() {
+        assert!(
+            Config::parse("[picker]\nresume = true")
+                .unwrap()
+                .picker
+                .resume
+        );
+        assert!(!Config::parse("").unwrap().picker.resume);
+        assert_eq!(
+            Config::parse("[picker]\ninitial_cursor = \"previous\"")
+                .unwrap()
+                .picker
+                .initial_cursor,
+            InitialCursor::Previous
+        );
+        assert_eq!(
+            Config::parse("").unwrap().picker.initial_cursor,
+            InitialCursor::Top
+        );
+        assert_eq!(
+            Config::parse("[picker]\nbudget = 200")
+                .unwrap()
+                .picker
+                .budget,
+            Some(200)
+        );
+        assert_eq!(Config::parse("").unwrap().picker.budget, None);
+        assert!(Config::parse("[picker]\ntabs = true").unwrap().picker.tabs);
+    }
+
+    #[test]
+    fn test_parse_policies() {
+        let config = Config::parse(
+            r#"
+            [policies]
+            protected = ["^release/"]
+
+            [[policies.rules]]
+            on = ["checkout"]
+            branches = "^release/1\\."
+            level = "block"
+            message = "1.x is frozen"
+
+            [[policies.rules]]
+            on = ["delete", "rename"]
+            when = ["has_open_pr", "is_protected"]
+            message = "it is under review"
+            "#,
+        )
+        .unwrap();
+        let rules = &config.policies.rules;
+        assert_eq!(config.policies.protected, vec!["^release/"]);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].branches.as_deref(), Some("^release/1\\."));
+        assert_eq!(rules[0].level, Level::Block);
+        assert_eq!(rules[1].on, vec![Operation::Delete, Operation::Rename]);
+        assert_eq!(
+            rules[1].when,
+            vec![Condition::HasOpenPr, Condition::IsProtected]
+        );
+        assert_eq!(rules[1].level, Level::Warn);
+
+        assert!(Config::parse("[[policies.rules]]\non = [\"push\"]\nmessage = \"x\"").is_err());
+        assert!(Config::parse("[[policies.rules]]\non = [\"checkout\"]").is_err());
+    }
+
+    #[test]
+    fn test_parse_templates() {
+        let config = Config::parse(
+            r#"
+            [templates.feature]
+            message = "chore: start {branch}"
+
+            [[templates.feature.files]]
+            path = "CHANGELOG.md"
+            content = '## {branch}'
+            mode = "prepend"
+
+            [[templates.feature.files]]
+            path = "flags/{branch}.toml"
+            content = "enabled = false\n"
+            "#,
+        )
+        .unwrap();
+        let template = &config.templates["feature"];
+        assert_eq!(template.message, "chore: start {branch}");
+        assert_eq!(template.files[0].mode, TemplateMode::Prepend);
+        assert_eq!(template.files[1].mode, TemplateMode::Create);
+        assert!(Config::parse("").unwrap().templates.is_empty());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let config = Config::parse(
+            r#"
+            [commands]
+            deploy = "ci deploy {branch}"
+
+            [commands.test]
+            run = "cargo test"
+            key = "alt-r"
+            cwd = "{worktree}"
+            confirm = true
+            capture = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.commands["deploy"],
+            CommandConfig {
+                run: "ci deploy {branch}".to_string(),
+                ..CommandConfig::default()
+            }
+        );
+        let test = &config.commands["test"];
+        assert_eq!(test.key.as_deref(), Some("alt-r"));
+        assert_eq!(test.cwd.as_deref(), Some("{worktree}"));
+        assert!(test.confirm && test.capture);
+        assert!(Config::parse("[commands.test]\nrun = \"true\"\nwait = true").is_err());
+    }
+
+    #[test]
+    fn test_parse_shortcuts() {
+        let config = Config::parse(
+            r#"
+            [shortcuts]
+            f = "feature/{words}"
+
+            [shortcuts.h]
+            template = "hotfix/{first}-{rest:lower}"
+            base = "release"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.shortcuts["f"],
+            ShortcutConfig {
+                template: "feature/{words}".to_string(),
+                base: None,
+            }
+        );
+        assert_eq!(config.shortcuts["h"].base.as_deref(), Some("release"));
+        // a placeholder that isn't one of the words
+        assert!(Config::parse("[shortcuts]\nf = \"feature/{branch}\"").is_err());
+        assert!(Config::parse("[shortcuts.f]\ntemplate = \"f/{words}\"\nkey = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        assert!(Config::parse("[workspace]\nroot = []").is_err());
+    }
+
+    #[test]
+    fn test_git_config() {
+        let dir = TempDir::new("git-config");
+        // the shared defaults of a team, included by the config of the repository
+        fs::write(
+            dir.join("team.gitconfig"),
+            "[gibra \"naming\"]\n\tconvention = feat/<issue>-<what>\n\
+             [gibra \"checkout\"]\n\tautostash\n\tskip-hooks = no\n\
+             [gibra \"keys\"]\n\tfast-forward = ctrl-f\n\
+             [gibra \"workspace\"]\n\tmax-depth = 2\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("config"),
+            "[include]\n\tpath = team.gitconfig\n[gibra \"keys\"]\n\tfast-forward = alt-f\n",
+        )
+        .unwrap();
+        let mut git_config = git2::Config::open(&dir.join("config")).unwrap();
+        let entries = git_entries(&mut git_config).unwrap();
+
+        let mut table: toml::Table =
+            toml::from_str("[naming]\nconvention = \"mine\"\nissue_prefix = \"#{issue}-\"")
+                .unwrap();
+        overlay(&mut table, &entries).unwrap();
+        let config: Config = table.try_into().unwrap();
+        assert_eq!(
+            config.naming.convention.as_deref(),
+            Some("feat/<issue>-<what>")
+        );
+        assert_eq!(config.naming.issue_prefix, "#{issue}-");
+        assert!(config.checkout.autostash);
+        assert!(!config.checkout.skip_hooks);
+        // the repository overrides the team
+        assert_eq!(config.keys.fast_forward, "alt-f");
+        assert_eq!(config.workspace.max_depth, 2);
+    }
+
+    #[test]
+    fn test_git_config_of_unknown_option() {
+        for name in ["gibra.protected", "gibra.web.hosts.example.branch"] {
+            let entries = [(name.to_string(), Some("x".to_string()))];
+            assert!(overlay(&mut toml::Table::new(), &entries).is_err());
+        }
+    }
+
+    #[test]
+    fn test_expand_tilde() {
+        assert_eq!(expand_tilde(Path::new("/src")), PathBuf::from("/src"));
+        if let Some(home) = home_dir() {
+            assert_eq!(expand_tilde(Path::new("~/src")), home.join("src"));
+            assert_eq!(expand_tilde(Path::new("~")), home);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Hints that merging a branch may run into the same conflicts as before, shown before finishing
+//! it: the files that conflicted when the two branches were merged earlier, in either direction,
+//! and the conflicts that git rerere recorded a resolution for.
+use crate::error::GibraError;
+use crate::hooks;
+use crate::prompt;
+use anyhow::Result;
+use git2::{Oid, Repository, Sort};
+use std::{collections::BTreeSet, fs};
+
+/// How many commits since the branches forked are looked at for earlier merges
+const MAX_COMMITS: usize = 5000;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Hint {
+    /// `path` conflicted when the branches were merged in `merge`
+    Conflicted { path: String, merge: Oid },
+    /// `path` conflicts, and rerere recorded a resolution for it, which it will apply again
+    Recorded { path: String },
+}
+
+impl Hint {
+    pub fn describe(&self, name: &str, target: &str) -> String {
+        match self {
+            Hint::Conflicted { path, merge } => format!(
+                "{} and {} have conflicted before in {} (merge {:.7})",
+                name, target, path, merge
+            ),
+            Hint::Recorded { path } => format!(
+                "Merging {} into {} conflicts in {}, which rerere resolves as before",
+                name, target, path
+            ),
+        }
+    }
+}
+
+/// A file that conflicts in a merge, with its contents on both sides
+struct Conflict {
+    path: String,
+    ours: Vec<u8>,
+    theirs: Vec<u8>,
+}
+
+/// The files that conflict when merging `ours` and `theirs`
+fn merge_conflicts(repo: &Repository, ours: Oid, theirs: Oid) -> Result<Vec<Conflict>> {
+    let index = repo.merge_commits(&repo.find_commit(ours)?, &repo.find_commit(theirs)?, None)?;
+    if !index.has_conflicts() {
+        return Ok(vec![]);
+    }
+
+    let content = |entry: &Option<git2::IndexEntry>| {
+        entry
+            .as_ref()
+            .and_then(|entry| repo.find_blob(entry.id).ok())
+            .map(|blob| blob.content().to_vec())
+            .unwrap_or_default()
+    };
+    let mut conflicts = vec![];
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict
+            .our
+            .as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref());
+        if let Some(entry) = entry {
+            conflicts.push(Conflict {
+                path: String::from_utf8_lossy(&entry.path).to_string(),
+                ours: content(&conflict.our),
+                theirs: content(&conflict.their),
+            });
+        }
+    }
+
+    Ok(conflicts)
+}
+
+/// The merges since `branch` and `target` forked that joined the two, found by one parent being
+/// part of each
+fn earlier_merges(repo: &Repository, branch: Oid, target: Oid) -> Result<Vec<(Oid, Oid, Oid)>> {
+    let is_part_of =
+        |oid: Oid, tip: Oid| oid == tip || repo.graph_descendant_of(tip, oid).unwrap_or(false);
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL)?;
+    revwalk.push(branch)?;
+    revwalk.push(target)?;
+    if let Ok(fork_point) = repo.merge_base(branch, target) {
+        revwalk.hide(fork_point)?;
+    }
+
+    let mut merges = vec![];
+    for oid in revwalk.take(MAX_COMMITS) {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() != 2 {
+            continue;
+        }
+        let (first, second) = (commit.parent_id(0)?, commit.parent_id(1)?);
+        let joins = (is_part_of(first, target) && is_part_of(second, branch))
+            || (is_part_of(first, branch) && is_part_of(second, target));
+        if joins {
+            merges.push((commit.id(), first, second));
+        }
+    }
+
+    Ok(merges)
+}
+
+/// The conflicts recorded by rerere with a resolution, as the two sides of each of their hunks
+fn rerere_records(repo: &Repository) -> Vec<Vec<(String, String)>> {
+    let entries = match fs::read_dir(hooks::common_dir(repo).join("rr-cache")) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .flatten()
+        .filter(|entry| entry.path().join("postimage").is_file())
+        .filter_map(|entry| fs::read_to_string(entry.path().join("preimage")).ok())
+        .map(|preimage| parse_preimage(&preimage))
+        .filter(|hunks| !hunks.is_empty())
+        .collect()
+}
+
+/// The two sides of each conflict hunk of a rerere preimage
+fn parse_preimage(preimage: &str) -> Vec<(String, String)> {
+    let mut hunks = vec![];
+    let mut sides: Option<(String, Option<String>)> = None;
+    for line in preimage.split_inclusive('\n') {
+        if line.starts_with("<<<<<<<") {
+            sides = Some((String::new(), None));
+        } else if line.starts_with("=======") {
+            if let Some((_, theirs)) = &mut sides {
+                *theirs = Some(String::new());
+            }
+        } else if line.starts_with(">>>>>>>") {
+            if let Some((ours, Some(theirs))) = sides.take() {
+                hunks.push((ours, theirs));
+            }
+        } else if let Some((ours, theirs)) = &mut sides {
+            theirs.as_mut().unwrap_or(ours).push_str(line);
+        }
+    }
+
+    hunks
+}
+
+/// Whether every hunk of a rerere record shows up in the two sides of a conflict. rerere sorts
+/// the sides of its hunks, so they may be in either order.
+fn matches_record(record: &[(String, String)], ours: &str, theirs: &str) -> bool {
+    record.iter().all(|(a, b)| {
+        (ours.contains(a.as_str()) && theirs.contains(b.as_str()))
+            || (ours.contains(b.as_str()) && theirs.contains(a.as_str()))
+    })
+}
+
+/// The hints for merging the local branch `name` into the local branch `target`
+pub fn hints(repo: &Repository, name: &str, target: &str) -> Result<Vec<Hint>> {
+    let branch = repo
+        .refname_to_id(&format!("refs/heads/{}", name))
+        .map_err(|_| GibraError::RefMissing {
+            name: name.to_string(),
+        })?;
+    let target = repo
+        .refname_to_id(&format!("refs/heads/{}", target))
+        .map_err(|_| GibraError::RefMissing {
+            name: target.to_string(),
+        })?;
+
+    let mut hints = BTreeSet::new();
+    for (merge, first, second) in earlier_merges(repo, branch, target)? {
+        for conflict in merge_conflicts(repo, first, second)? {
+            hints.insert(Hint::Conflicted {
+                path: conflict.path,
+                merge,
+            });
+        }
+    }
+
+    let records = rerere_records(repo);
+    if !records.is_empty() {
+        for conflict in merge_conflicts(repo, target, branch)? {
+            let ours = String::from_utf8_lossy(&conflict.ours);
+            let theirs = String::from_utf8_lossy(&conflict.theirs);
+            if records
+                .iter()
+                .any(|record| matches_record(record, &ours, &theirs))
+            {
+                hints.insert(Hint::Recorded {
+                    path: conflict.path,
+                });
+            }
+        }
+    }
+
+    Ok(hints.into_iter().collect())
+}
+
+/// Show the hints for merging `name` into each of `targets`, and ask whether to go ahead when
+/// there are any
+pub fn confirm(repo: &Repository, name: &str, targets: &[&str]) -> Result<bool> {
+    let mut warned = false;
+    for target in targets {
+        let hints = match hints(repo, name, target) {
+            Ok(hints) => hints,
+            Err(err) => {
+                debug!("{:#}", err);
+                continue;
+            }
+        };
+        for hint in hints {
+            eprintln!("{}", hint.describe(name, target));
+            warned = true;
+        }
+    }
+    if !warned {
+        return Ok(true);
+    }
+
+    prompt::confirm(&format!("Finish {} anyway?", name), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_parse_preimage() {
+        let preimage = "a\n<<<<<<<\nfoo\n=======\nbar\nbaz\n>>>>>>>\nb\n";
+        let hunks = parse_preimage(preimage);
+        assert_eq!(hunks, vec![("foo\n".to_string(), "bar\nbaz\n".to_string())]);
+        assert!(matches_record(&hunks, "x\nbar\nbaz\n", "foo\ny\n"));
+        assert!(!matches_record(&hunks, "x\nbar\n", "foo\n"));
+    }
+
+    /// `feature/x` and `develop` changing `parser.rs` apart, with `develop` merged into the
+    /// feature to resolve the conflict, then both changing `lexer.rs` apart. Returns the merge.
+    fn conflicting(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/develop"),
+            "commit",
+            &[("parser.rs", "1\n"), ("lexer.rs", "1\n")],
+            &[],
+        );
+        let feature = testing::commit(
+            &repo,
+            Some("refs/heads/feature/x"),
+            "commit",
+            &[("parser.rs", "2\n")],
+            &[base],
+        );
+        let develop = testing::commit(
+            &repo,
+            Some("refs/heads/develop"),
+            "commit",
+            &[("parser.rs", "3\n")],
+            &[base],
+        );
+        let merge = testing::commit(
+            &repo,
+            Some("refs/heads/feature/x"),
+            "commit",
+            &[("parser.rs", "23\n")],
+            &[feature, develop],
+        );
+        testing::commit(
+            &repo,
+            Some("refs/heads/feature/x"),
+            "commit",
+            &[("lexer.rs", "feature\n")],
+            &[merge],
+        );
+        testing::commit(
+            &repo,
+            Some("refs/heads/develop"),
+            "commit",
+            &[("lexer.rs", "develop\n")],
+            &[develop],
+        );
+        (repo, merge)
+    }
+
+    #[test]
+    fn test_hints_of_resolved_conflict() {
+        let (repo, merge) = conflicting("conflicts");
+        assert_eq!(
+            hints(&repo, "feature/x", "develop").unwrap(),
+            vec![Hint::Conflicted {
+                path: "parser.rs".to_string(),
+                merge
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hints_of_recorded_resolution() {
+        let (repo, _) = conflicting("conflicts-recorded");
+        let record = repo.path().join("rr-cache").join("0123");
+        fs::create_dir_all(&record).unwrap();
+        fs::write(
+            record.join("preimage"),
+            "<<<<<<<\ndevelop\n=======\nfeature\n>>>>>>>\n",
+        )
+        .unwrap();
+        fs::write(record.join("postimage"), "both\n").unwrap();
+        assert!(hints(&repo, "feature/x", "develop")
+            .unwrap()
+            .contains(&Hint::Recorded {
+                path: "lexer.rs".to_string()
+            }));
+    }
+
+    #[test]
+    fn test_hints_of_missing_branch() {
+        let (repo, _) = conflicting("conflicts-missing");
+        let err = hints(&repo, "feature/x", "main").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<GibraError>(),
+            Some(&GibraError::RefMissing {
+                name: "main".to_string()
+            })
+        );
+    }
+}
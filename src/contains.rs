@@ -0,0 +1,139 @@
+//! Listing only the branches that contain a commit (`--contains`), or that don't
+//! (`--no-contains`), like `git branch --contains`, e.g. to tell which branches a fix has landed
+//! on.
+use crate::diffstat;
+use crate::error::GibraError;
+use crate::Branch;
+use anyhow::Result;
+use git2::{Oid, Repository};
+
+/// The commits given to `--contains` and `--no-contains`
+#[derive(Debug)]
+pub struct Containment {
+    contains: Vec<Oid>,
+    no_contains: Vec<Oid>,
+}
+
+/// The commit `rev` points to, e.g. a tag or `HEAD~2`
+fn resolve(repo: &Repository, rev: &str) -> Result<Oid> {
+    let commit = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|_| GibraError::RefMissing {
+            name: rev.to_string(),
+        })?;
+    Ok(commit.id())
+}
+
+/// Whether the history of `tip` has `commit`
+fn has(repo: &Repository, tip: Oid, commit: Oid) -> bool {
+    tip == commit || repo.graph_descendant_of(tip, commit).unwrap_or(false)
+}
+
+impl Containment {
+    pub fn new(repo: &Repository, contains: &[String], no_contains: &[String]) -> Result<Self> {
+        let resolve_all = |revs: &[String]| -> Result<Vec<Oid>> {
+            revs.iter().map(|rev| resolve(repo, rev)).collect()
+        };
+        Ok(Self {
+            contains: resolve_all(contains)?,
+            no_contains: resolve_all(no_contains)?,
+        })
+    }
+
+    /// Whether a branch at `tip` contains any of the commits of `--contains`, and none of those
+    /// of `--no-contains`
+    fn matches(&self, repo: &Repository, tip: Oid) -> bool {
+        (self.contains.is_empty() || self.contains.iter().any(|&commit| has(repo, tip, commit)))
+            && !self
+                .no_contains
+                .iter()
+                .any(|&commit| has(repo, tip, commit))
+    }
+}
+
+/// Drop the `branches` that don't match `containment`, the current one included
+pub fn apply(repo: &Repository, containment: &Containment, branches: &mut Vec<Branch>) {
+    branches.retain(|branch| {
+        diffstat::tip(repo, branch).is_some_and(|tip| containment.matches(repo, tip))
+    });
+}
+
+/// How the info line of the picker tells the commits, e.g. `containing v1.2 · not containing
+/// abc1234`
+pub fn describe(contains: &[String], no_contains: &[String]) -> Vec<String> {
+    [("containing", contains), ("not containing", no_contains)]
+        .into_iter()
+        .filter(|(_, revs)| !revs.is_empty())
+        .map(|(what, revs)| format!("{} {}", what, revs.join(", ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use crate::LocalBranch;
+
+    /// root ← fix ← release, and root ← feature, with the tag `v1` at `fix`
+    fn history(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let root = testing::commit(&repo, None, "root", &[], &[]);
+        let fix = testing::commit(&repo, Some("refs/heads/fix"), "fix", &[], &[root]);
+        testing::commit(&repo, Some("refs/heads/release"), "release", &[], &[fix]);
+        testing::commit(&repo, Some("refs/heads/feature"), "feature", &[], &[root]);
+        repo.tag_lightweight("v1", &repo.find_object(fix, None).unwrap(), false)
+            .unwrap();
+        repo
+    }
+
+    /// The branches of [`history`] kept, and `gone`, which isn't there
+    fn listed(repo: &Repository, contains: &[&str], no_contains: &[&str]) -> Vec<String> {
+        let revs = |revs: &[&str]| revs.iter().map(|rev| rev.to_string()).collect::<Vec<_>>();
+        let containment = Containment::new(repo, &revs(contains), &revs(no_contains)).unwrap();
+        let mut branches: Vec<Branch> = ["fix", "release", "feature", "gone"]
+            .iter()
+            .map(|name| {
+                Branch::Local(LocalBranch {
+                    name: name.to_string(),
+                    ..LocalBranch::default()
+                })
+            })
+            .collect();
+        apply(repo, &containment, &mut branches);
+        branches.into_iter().map(Branch::name).collect()
+    }
+
+    #[test]
+    fn test_contains() {
+        let repo = history("contains");
+        assert_eq!(listed(&repo, &["v1"], &[]), ["fix", "release"]);
+        assert_eq!(listed(&repo, &["release"], &[]), ["release"]);
+        // any of them
+        assert_eq!(
+            listed(&repo, &["release", "feature"], &[]),
+            ["release", "feature"]
+        );
+    }
+
+    #[test]
+    fn test_no_contains() {
+        let repo = history("no-contains");
+        assert_eq!(listed(&repo, &[], &["v1"]), ["feature"]);
+        assert_eq!(listed(&repo, &["fix~1"], &["release"]), ["fix", "feature"]);
+    }
+
+    #[test]
+    fn test_unknown_revision() {
+        let repo = history("contains-unknown");
+        assert!(Containment::new(&repo, &["nowhere".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe(&["v1".to_string()], &["a".to_string(), "b".to_string()]),
+            ["containing v1", "not containing a, b"]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! The repository gibra runs in, discovered from the current directory and opened once at
+//! startup. The subcommands share its handle rather than each opening the repository again, and
+//! the config is read through it, so that the `gibra.*` settings of the repository apply.
+//!
+//! Only the threads in the background open handles of their own, as a handle can't be shared
+//! between threads.
+use crate::alternates;
+use crate::error::GibraError;
+use anyhow::{Context, Result};
+use git2::{ErrorCode, Oid, Repository};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// `path` with its symlinks resolved, as the filesystem spells it on a case-insensitive one, so
+/// that the same directory reached through a symlink or typed in another case compares equal.
+/// `path` as is when it doesn't exist.
+pub fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// HEAD when gibra started
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Head {
+    /// The branch checked out, also when it has no commit yet. `None` when HEAD is detached.
+    pub branch: Option<String>,
+    /// The commit HEAD points to, `None` when there is none yet
+    pub oid: Option<Oid>,
+}
+
+impl Head {
+    fn of(repo: &Repository) -> Self {
+        let branch = repo
+            .find_reference("HEAD")
+            .ok()
+            .and_then(|head| head.symbolic_target().map(str::to_string))
+            .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_string));
+        let oid = repo.head().ok().and_then(|head| head.target());
+        Self { branch, oid }
+    }
+}
+
+/// The repository, its working tree, and its HEAD when gibra started
+pub struct RepoContext {
+    pub repo: Repository,
+    /// The working tree, which isn't the parent of the git directory for a linked worktree.
+    /// Canonical, see [`canonical`].
+    pub workdir: PathBuf,
+    pub head: Head,
+}
+
+impl RepoContext {
+    /// The repository that `path` is in. A bare repository has no working tree to check out in,
+    /// which is an error.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let repo = alternates::discover(canonical(path))?;
+        let workdir = canonical(
+            repo.workdir()
+                .with_context(|| "The repository has no work tree")?,
+        );
+        let head = Head::of(&repo);
+        Ok(Self {
+            repo,
+            workdir,
+            head,
+        })
+    }
+
+    /// The repository of the current directory, [`GibraError::RepoNotFound`] when it is in none
+    pub fn current() -> Result<Self> {
+        let path = env::current_dir()?;
+        Self::discover(&path).map_err(|err| match err.downcast_ref::<git2::Error>() {
+            Some(git_err) if git_err.code() == ErrorCode::NotFound => {
+                GibraError::RepoNotFound { path }.into()
+            }
+            _ => err.context("Failed to find git root"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, TempRepo};
+    use std::fs;
+
+    #[test]
+    fn test_discover_unborn() {
+        let repo = TempRepo::new("context-unborn");
+        let dir = repo.dir();
+        fs::create_dir_all(dir.join("src/nested")).unwrap();
+
+        let context = RepoContext::discover(&dir.join("src/nested")).unwrap();
+        assert_eq!(context.workdir, dir.canonicalize().unwrap());
+        assert!(context.head.branch.is_some());
+        assert_eq!(context.head.oid, None);
+    }
+
+    #[test]
+    fn test_discover_head() {
+        let repo = TempRepo::new("context");
+        let dir = repo.dir();
+        let unborn = RepoContext::discover(dir).unwrap().head.branch;
+        let tip = testing::commit(&repo, Some("HEAD"), "commit", &[], &[]);
+        assert_eq!(
+            RepoContext::discover(dir).unwrap().head,
+            Head {
+                branch: unborn,
+                oid: Some(tip),
+            }
+        );
+    }
+
+    #[test]
+    fn test_discover_detached_head() {
+        let repo = TempRepo::new("context-detached");
+        let tip = testing::commit(&repo, Some("HEAD"), "commit", &[], &[]);
+        repo.set_head_detached(tip).unwrap();
+        let head = RepoContext::discover(repo.dir()).unwrap().head;
+        assert_eq!(head.branch, None);
+        assert_eq!(head.oid, Some(tip));
+    }
+
+    #[test]
+    fn test_discover_bare() {
+        let dir = TempDir::new("context-bare");
+        Repository::init_bare(dir.join("bare.git")).unwrap();
+        assert!(RepoContext::discover(&dir.join("bare.git")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_discover_through_symlink() {
+        let repo = TempRepo::new("context-symlinked");
+        let dir = repo.dir();
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let links = TempDir::new("context-links");
+        let link = links.join("repo");
+        std::os::unix::fs::symlink(dir, &link).unwrap();
+
+        // the working tree is where it really is
+        let context = RepoContext::discover(&link.join("src")).unwrap();
+        assert_eq!(context.workdir, dir.canonicalize().unwrap());
+        assert_eq!(canonical(&link.join("src")), context.workdir.join("src"));
+    }
+
+    #[test]
+    fn test_canonical_of_missing_path() {
+        let dir = TempDir::new("canonical");
+        assert_eq!(canonical(&dir.join("missing")), dir.join("missing"));
+    }
+}
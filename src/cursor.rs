@@ -0,0 +1,110 @@
+//! Where the cursor of the picker starts (`initial_cursor` under `[picker]`): on the current
+//! branch at the top, or on the branch checked out before it, which is the one most often picked
+//! when going back and forth between two branches.
+use crate::config::InitialCursor;
+use crate::skim::SkimItem;
+use crate::Branch;
+use git2::Repository;
+
+/// The branch that a reflog message of HEAD says was left, e.g. `main` for `checkout: moving
+/// from main to feature/login`
+fn moved_from(message: &str) -> Option<&str> {
+    let moves = message.strip_prefix("checkout: moving from ")?;
+    let (from, _to) = moves.split_once(" to ")?;
+    Some(from)
+}
+
+/// The branches left by checking out another one, most recent first
+pub fn left_branches(repo: &Repository) -> Vec<String> {
+    let reflog = match repo.reflog("HEAD") {
+        Ok(reflog) => reflog,
+        Err(_) => return vec![],
+    };
+    reflog
+        .iter()
+        .filter_map(|entry| Some(moved_from(entry.message()?)?.to_string()))
+        .collect()
+}
+
+/// The text of the item the cursor starts on, `None` for the top. With `previous`, it's the
+/// branch most recently left among the listed local branches other than the current one.
+pub fn initial(repo: &Repository, cursor: InitialCursor, branches: &[Branch]) -> Option<String> {
+    if cursor == InitialCursor::Top {
+        return None;
+    }
+    let current = repo.head().ok()?.shorthand()?.to_string();
+    left_branches(repo)
+        .into_iter()
+        .filter(|name| *name != current)
+        .find_map(|name| {
+            branches.iter().find_map(|branch| match branch {
+                Branch::Local(local) if local.name == name => Some(branch.text().into_owned()),
+                _ => None,
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use crate::LocalBranch;
+
+    /// Back on `main` after checking out `topic`, then `gone`, of which `main` and `topic` are
+    /// listed
+    fn visited(name: &str) -> (TempRepo, Vec<Branch>) {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, None, "commit", &[], &[]);
+        for name in ["main", "topic", "gone"] {
+            repo.reference(&format!("refs/heads/{}", name), tip, false, "test")
+                .unwrap();
+        }
+        repo.set_head("refs/heads/main").unwrap();
+        let signature = git2::Signature::now("test", "test@example.com").unwrap();
+        let mut reflog = repo.reflog("HEAD").unwrap();
+        for message in [
+            "checkout: moving from main to topic",
+            "checkout: moving from topic to gone",
+            "checkout: moving from gone to main",
+        ] {
+            reflog.append(tip, &signature, Some(message)).unwrap();
+        }
+        reflog.write().unwrap();
+
+        let branches = ["main", "topic"]
+            .iter()
+            .map(|name| {
+                Branch::Local(LocalBranch {
+                    name: name.to_string(),
+                    ..LocalBranch::default()
+                })
+            })
+            .collect();
+        (repo, branches)
+    }
+
+    #[test]
+    fn test_moved_from() {
+        assert_eq!(
+            moved_from("checkout: moving from main to feature/login"),
+            Some("main")
+        );
+        assert_eq!(moved_from("commit: fix the typo"), None);
+    }
+
+    #[test]
+    fn test_initial_previous() {
+        let (repo, branches) = visited("cursor");
+        // `gone` was left last, but isn't listed
+        assert_eq!(
+            initial(&repo, InitialCursor::Previous, &branches),
+            Some("topic".to_string())
+        );
+    }
+
+    #[test]
+    fn test_initial_top() {
+        let (repo, branches) = visited("cursor-top");
+        assert_eq!(initial(&repo, InitialCursor::Top, &branches), None);
+    }
+}
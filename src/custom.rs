@@ -0,0 +1,279 @@
+//! The commands of `[commands]`, run on the picked branches from the menu of the picker or with
+//! a key of their own, e.g. `deploy = "ci deploy {branch}"`. Each runs with `sh -c` once per
+//! picked branch, with the placeholders replaced by the values of the branch, quoted for the
+//! shell:
+//!
+//! - `{branch}`: the name of the branch, e.g. `origin/feature`
+//! - `{ref}`, `{kind}`, `{upstream}`, `{sha}` and `{short_sha}`, as in `--print-format`
+//! - `{repo}` and `{head}`: the name of the repository and the checked out branch
+//! - `{worktree}`: the worktree the branch is checked out in, else the current one
+//!
+//! They take the filters of the templates, e.g. `{ref:short}`. Other text in braces is left as it
+//! is, for the shell.
+use crate::config::CommandConfig;
+use crate::skim::prelude::*;
+use crate::template::{BranchValues, Placeholders, Template};
+use crate::{exec, keymap, prompt, state, PickerOptions};
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+/// What the menu entry of a command without a key reports, before the name of the command
+const MENU_ID_PREFIX: &str = "command:";
+
+/// A command of `[commands]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomCommand {
+    pub name: String,
+    pub config: CommandConfig,
+}
+
+impl CustomCommand {
+    /// The commands of the config, in the order of their names
+    pub fn all(commands: &BTreeMap<String, CommandConfig>) -> Vec<CustomCommand> {
+        commands
+            .iter()
+            .map(|(name, config)| CustomCommand {
+                name: name.clone(),
+                config: config.clone(),
+            })
+            .collect()
+    }
+
+    /// What the picker reports when the command is picked: its key, or its menu entry
+    fn id(&self) -> String {
+        match &self.config.key {
+            Some(key) => key.clone(),
+            None => format!("{}{}", MENU_ID_PREFIX, self.name),
+        }
+    }
+
+    /// The keys to pass to skim's `expect` option, for the commands having one
+    pub fn expect_keys(commands: &[CustomCommand]) -> Vec<&str> {
+        commands
+            .iter()
+            .filter_map(|command| command.config.key.as_deref())
+            .collect()
+    }
+
+    /// The entries of the menu of the picker, after the actions
+    pub fn menu(commands: &[CustomCommand]) -> Vec<MenuEntry> {
+        commands
+            .iter()
+            .map(|command| match &command.config.key {
+                Some(key) => MenuEntry::new(&command.name, Some(key.clone())),
+                None => MenuEntry::unbound(&command.name, command.id()),
+            })
+            .collect()
+    }
+
+    /// The index of the command picked with `key`, which was reported by skim on accept
+    pub fn from_key(commands: &[CustomCommand], key: &str) -> Option<usize> {
+        commands.iter().position(|command| command.id() == key)
+    }
+}
+
+/// The placeholders of a command, with their values for `branch`
+fn values(repo: &Repository, workdir: &Path, branch: &BranchValues) -> Vec<(&'static str, String)> {
+    let placeholders = Placeholders::of(repo);
+    let sha = branch.tip.map(|tip| tip.to_string()).unwrap_or_default();
+    let head = repo.head().ok();
+    let worktree = match head.as_ref().and_then(|head| head.name()) == Some(&branch.refname) {
+        true => Some(workdir.to_path_buf()),
+        false => state::worktree_holding(repo, &branch.refname),
+    };
+    vec![
+        ("branch", branch.name.clone()),
+        ("ref", branch.refname.clone()),
+        ("kind", branch.kind.to_string()),
+        ("upstream", branch.upstream.clone().unwrap_or_default()),
+        ("short_sha", sha.chars().take(7).collect()),
+        ("sha", sha),
+        ("repo", placeholders.repo),
+        ("head", placeholders.branch),
+        (
+            "worktree",
+            worktree
+                .unwrap_or_else(|| workdir.to_path_buf())
+                .to_string_lossy()
+                .into_owned(),
+        ),
+    ]
+}
+
+/// Replace the placeholders of `values` in `template`, quoting the values for the shell with
+/// `quote`
+fn expand(template: &str, values: &[(&str, String)], quote: bool) -> String {
+    let template = Template::parse(template);
+    match quote {
+        true => template.render_with(values, exec::shell_quote),
+        false => template.render(values),
+    }
+}
+
+/// A line of the output of the commands
+struct OutputLine {
+    raw: String,
+}
+
+impl SkimItem for OutputLine {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Owned(AnsiString::parse(&self.raw).stripped().to_string())
+    }
+
+    fn display<'a>(&'a self, _context: DisplayContext<'a>) -> AnsiString<'a> {
+        AnsiString::parse(&self.raw)
+    }
+}
+
+/// Show `lines` in a picker that only scrolls and searches through them, until it is closed
+fn show(header: &str, lines: Vec<String>, options: &PickerOptions) -> Result<()> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for raw in lines {
+        let _ = tx.send(Arc::new(OutputLine { raw }));
+    }
+    drop(tx);
+
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, options.keys.keymap, vec![]);
+    options.theme.apply(&mut builder);
+    let skim_options = builder
+        .header(Some(header))
+        .nosort(true)
+        .reverse(true)
+        .accessible(options.accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+    // whichever way it is closed, there is nothing more to do
+    Skim::run_with(&skim_options, Some(rx));
+    Ok(())
+}
+
+/// Run `command` on each of `branches` in turn, after asking with `confirm`. The output goes to
+/// the terminal, or with `capture` to a view shown once all are done. Fails when it failed on
+/// one of them.
+pub fn run(
+    repo: &Repository,
+    workdir: &Path,
+    command: &CustomCommand,
+    branches: &[BranchValues],
+    options: &PickerOptions,
+) -> Result<()> {
+    let names = branches
+        .iter()
+        .map(|branch| branch.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let question = format!("Run {} on {}?", command.name, names);
+    if command.config.confirm && !prompt::confirm(&question, false)? {
+        bail!("Cancelled, {} didn't run", command.name);
+    }
+
+    let mut failed = vec![];
+    let mut lines = vec![];
+    for branch in branches {
+        let values = values(repo, workdir, branch);
+        let line = expand(&command.config.run, &values, true);
+        let cwd = match &command.config.cwd {
+            Some(cwd) => workdir.join(expand(cwd, &values, false)),
+            None => workdir.to_path_buf(),
+        };
+        let mut shell = Command::new("sh");
+        shell.current_dir(&cwd);
+        let code = if command.config.capture {
+            // the errors interleaved with the output, as on the terminal
+            let output = shell
+                .arg("-c")
+                .arg(format!("{{ {}\n}} 2>&1", line))
+                .output()
+                .with_context(|| failed_to_run(&line, &cwd))?;
+            lines.push(format!("\x1b[1m$ {}\x1b[0m", line));
+            lines.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(str::to_string),
+            );
+            exec::exit_code(output.status)
+        } else {
+            let status = shell
+                .arg("-c")
+                .arg(&line)
+                .status()
+                .with_context(|| failed_to_run(&line, &cwd))?;
+            exec::exit_code(status)
+        };
+        if code != 0 {
+            lines.push(format!("exit code {}", code));
+            failed.push(branch.name.as_str());
+        }
+    }
+
+    if command.config.capture {
+        let header = format!("{} on {} · esc: close", command.name, names);
+        show(&header, lines, options)?;
+    }
+    if !failed.is_empty() {
+        bail!("{} failed on {}", command.name, failed.join(", "));
+    }
+    Ok(())
+}
+
+fn failed_to_run(line: &str, cwd: &Path) -> String {
+    format!("Failed to run {} in {}", line, cwd.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let values = vec![
+            ("branch", "it's/{sha}".to_string()),
+            ("sha", "4f2a9c1".to_string()),
+        ];
+        assert_eq!(
+            expand("ci deploy {branch} --at={sha} ${HOME}", &values, true),
+            "ci deploy 'it'\\''s/{sha}' --at='4f2a9c1' ${HOME}"
+        );
+        assert_eq!(expand("trees/{branch}", &values, false), "trees/it's/{sha}");
+        assert_eq!(expand("{unclosed", &values, true), "{unclosed");
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut config = BTreeMap::new();
+        config.insert(
+            "deploy".to_string(),
+            CommandConfig {
+                run: "ci deploy {branch}".to_string(),
+                ..CommandConfig::default()
+            },
+        );
+        config.insert(
+            "test".to_string(),
+            CommandConfig {
+                run: "cargo test".to_string(),
+                key: Some("alt-r".to_string()),
+                ..CommandConfig::default()
+            },
+        );
+        let commands = CustomCommand::all(&config);
+        assert_eq!(CustomCommand::expect_keys(&commands), ["alt-r"]);
+        assert_eq!(
+            CustomCommand::menu(&commands),
+            [
+                MenuEntry::unbound("deploy", "command:deploy".to_string()),
+                MenuEntry::new("test", Some("alt-r".to_string())),
+            ]
+        );
+        assert_eq!(
+            CustomCommand::from_key(&commands, "command:deploy"),
+            Some(0)
+        );
+        assert_eq!(CustomCommand::from_key(&commands, "alt-r"), Some(1));
+        assert_eq!(CustomCommand::from_key(&commands, "ctrl-d"), None);
+    }
+}
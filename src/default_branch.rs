@@ -0,0 +1,127 @@
+//! Making the picked branch the default branch of its remote (`set-default` under `[keys]`), e.g.
+//! after renaming `master` to `main`. `refs/remotes/<remote>/HEAD` is pointed at it, like `git
+//! remote set-head` does, so the sizes, the columns and the compare pages count from it the next
+//! time the branches are listed. With GitHub configured, the default branch of the repository on
+//! GitHub can be changed too.
+use crate::config::GithubConfig;
+use crate::prompt;
+use crate::pulls;
+use crate::remote;
+use crate::secrets::{self, Provider};
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+
+/// The remote and the name of the remote-tracking branch `refname`, e.g. `origin` and `main` for
+/// `refs/remotes/origin/main`
+fn split(repo: &Repository, refname: &str) -> Option<(String, String)> {
+    let remote = repo.branch_remote_name(refname).ok()?.as_str()?.to_string();
+    let name = refname
+        .strip_prefix(&format!("refs/remotes/{}/", remote))?
+        .to_string();
+    Some((remote, name))
+}
+
+/// Point `refs/remotes/<remote>/HEAD` at `refs/remotes/<remote>/<name>`
+fn set_head(repo: &Repository, remote: &str, name: &str) -> Result<()> {
+    repo.reference_symbolic(
+        &format!("refs/remotes/{}/HEAD", remote),
+        &format!("refs/remotes/{}/{}", remote, name),
+        true,
+        &format!("gibra: set {}/HEAD to {}", remote, name),
+    )
+    .with_context(|| format!("Failed to set {}/HEAD", remote))?;
+    Ok(())
+}
+
+/// Change the default branch of the GitHub repository `slug` to `name`
+fn set_on_github(config: &GithubConfig, slug: &str, name: &str) -> Result<()> {
+    let token = secrets::token(Provider::Github, config.token.as_ref())?;
+    remote::http_agent()
+        .request(
+            "PATCH",
+            &format!("{}/repos/{}", config.api_url.trim_end_matches('/'), slug),
+        )
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &format!("Bearer {}", token.expose()))
+        .send_json(serde_json::json!({ "default_branch": name }))
+        .with_context(|| format!("Failed to change the default branch of {}", slug))?;
+    Ok(())
+}
+
+/// Make the remote-tracking branch `refname` the default branch of its remote, and on GitHub too
+/// when confirmed
+pub fn run(repo: &Repository, github: Option<&GithubConfig>, refname: &str) -> Result<()> {
+    let (remote, name) = match split(repo, refname) {
+        Some(split) => split,
+        None => bail!("{} is not a branch of a remote", refname),
+    };
+    let previous = remote::default_branch(repo, &remote);
+    if previous == name {
+        eprintln!("{} is the default branch of {} already", name, remote);
+    } else {
+        set_head(repo, &remote, &name)?;
+        eprintln!("{}/HEAD points to {} instead of {}", remote, name, previous);
+    }
+
+    let github = github.and_then(|config| {
+        let url = remote::resolve(repo, &remote).ok()?.url;
+        Some((config, pulls::slug(&url)?))
+    });
+    if let Some((config, slug)) = github {
+        let question = format!(
+            "Make {} the default branch of {} on GitHub too?",
+            name, slug
+        );
+        if prompt::confirm(&question, false)? {
+            set_on_github(config, &slug, &name)?;
+            eprintln!("{} is the default branch of {} on GitHub", name, slug);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `origin` with the branches `master`, `main` and `release/1.0`
+    fn remote_branches(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        repo.remote("origin", "https://github.com/owner/repo.git")
+            .unwrap();
+        let tip = testing::commit(&repo, None, "commit", &[], &[]);
+        for name in ["master", "main", "release/1.0"] {
+            repo.reference(&format!("refs/remotes/origin/{}", name), tip, false, "test")
+                .unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_set_head() {
+        let repo = remote_branches("default");
+        set_head(&repo, "origin", "master").unwrap();
+        assert_eq!(remote::default_branch(&repo, "origin"), "master");
+    }
+
+    #[test]
+    fn test_split() {
+        let repo = remote_branches("default-split");
+        assert_eq!(
+            split(&repo, "refs/remotes/origin/release/1.0"),
+            Some(("origin".to_string(), "release/1.0".to_string()))
+        );
+        assert_eq!(split(&repo, "refs/heads/main"), None);
+    }
+
+    #[test]
+    fn test_run() {
+        let repo = remote_branches("default-run");
+        run(&repo, None, "refs/remotes/origin/main").unwrap();
+        assert_eq!(remote::default_branch(&repo, "origin"), "main");
+        // a local branch
+        assert!(run(&repo, None, "refs/heads/main").is_err());
+    }
+}
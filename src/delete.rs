@@ -0,0 +1,255 @@
+//! Deleting local branches, with a warning for those that still have an open pull request, and
+//! the rules of [`crate::policy`] applied.
+use crate::audit::{self, Loss};
+use crate::config::Operation;
+use crate::git::{self, GitError};
+use crate::meta::RepoMeta;
+use crate::policy::{Policy, Target};
+use crate::prompt;
+use crate::pulls::OpenPulls;
+use anyhow::{Context, Result};
+use git2::Repository;
+use std::{io, path::Path, process::Stdio};
+
+fn delete_branch(workdir: &Path, name: &str, force: bool) -> Result<bool> {
+    let status = git::command(workdir)
+        .args(["branch", if force { "-D" } else { "-d" }, name])
+        .stdout(io::stderr())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| "Failed to execute branch command")?;
+
+    Ok(status.success())
+}
+
+/// Delete the local branches `names` with `git branch -d`, so unmerged work is never lost.
+///
+/// The checked out branch is skipped, and branches with an open pull request are only deleted
+/// after confirming, unless a rule of `policy` asked about them already.
+pub fn delete(
+    repo: &Repository,
+    workdir: &Path,
+    names: &[String],
+    pulls: &OpenPulls,
+    policy: &Policy,
+) -> Result<()> {
+    delete_with(repo, workdir, names, pulls, policy, false)
+}
+
+/// Delete the local branches `names`, found by [`merged_branches`]. `git branch -d` only accepts
+/// branches merged into HEAD or their upstream, so those merged into `--base` are deleted with
+/// `-D`, which loses nothing as the base has their commits.
+pub fn delete_merged(
+    meta: &RepoMeta,
+    workdir: &Path,
+    names: &[String],
+    pulls: &OpenPulls,
+    policy: &Policy,
+) -> Result<()> {
+    delete_with(
+        meta.repo(),
+        workdir,
+        names,
+        pulls,
+        policy,
+        meta.base().is_some(),
+    )
+}
+
+fn delete_with(
+    repo: &Repository,
+    workdir: &Path,
+    names: &[String],
+    pulls: &OpenPulls,
+    policy: &Policy,
+    force: bool,
+) -> Result<()> {
+    let head = repo.head().ok();
+    let current = head.as_ref().and_then(|head| head.shorthand());
+
+    for name in names {
+        if Some(name.as_str()) == current {
+            eprintln!("{} is checked out, skipping", name);
+            continue;
+        }
+
+        let target = Target {
+            name,
+            pull: pulls.of_local(name),
+        };
+        let ruled = !policy.check(Operation::Delete, target).is_empty();
+        if ruled && !policy.allow(Operation::Delete, target)? {
+            eprintln!("Keeping {}", name);
+            continue;
+        }
+        if let (false, Some(number)) = (ruled, target.pull) {
+            let question = format!(
+                "{} has an open pull request #{}. Delete it anyway?",
+                name, number
+            );
+            if !prompt::confirm(&question, false)? {
+                eprintln!("Keeping {}", name);
+                continue;
+            }
+        }
+
+        let loss = Loss::branch(repo, name);
+        if delete_branch(workdir, name, force)? {
+            eprintln!("Deleted {}", name);
+            audit::record(repo, loss);
+        }
+    }
+
+    Ok(())
+}
+
+/// Same as [`delete`], telling what happened to each branch instead of writing to the terminal,
+/// which the picker has while the actions of [`crate::bus`] run, as an error for the branches
+/// that were kept. As there is no asking, the branches with an open pull request are kept, and
+/// so are those a rule of `policy` applies to.
+pub fn delete_quietly(
+    repo: &Repository,
+    workdir: &Path,
+    names: &[String],
+    pulls: &OpenPulls,
+    policy: &Policy,
+) -> Vec<Result<String, String>> {
+    let head = repo.head().ok();
+    let current = head.as_ref().and_then(|head| head.shorthand());
+
+    names
+        .iter()
+        .map(|name| {
+            if Some(name.as_str()) == current {
+                return Err(format!("{} is checked out, skipping", name));
+            }
+            let target = Target {
+                name,
+                pull: pulls.of_local(name),
+            };
+            policy.allow_quietly(Operation::Delete, target)?;
+            if let Some(number) = pulls.of_local(name) {
+                return Err(format!(
+                    "Kept {}, which has an open pull request #{}",
+                    name, number
+                ));
+            }
+            let loss = Loss::branch(repo, name);
+            match git::run_quietly(workdir, &["branch", "-d", name]) {
+                Ok(()) => {
+                    audit::record(repo, loss);
+                    Ok(format!("Deleted {}", name))
+                }
+                Err(err) => Err(match err.downcast_ref::<GitError>() {
+                    Some(git_err) => format!("Kept {}: {}", name, git_err.summary()),
+                    None => format!("Kept {}: {:#}", name, err),
+                }),
+            }
+        })
+        .collect()
+}
+
+/// The local branches, other than the checked out one, that are fully merged into HEAD, or into
+/// the base with `--base`
+pub fn merged_branches(meta: &RepoMeta) -> Result<Vec<String>> {
+    let repo = meta.repo();
+    let into = match meta.base() {
+        Some(base) => base.oid,
+        None => repo
+            .head()
+            .with_context(|| "Failed to get HEAD")?
+            .target()
+            .with_context(|| "HEAD does not point to a commit")?,
+    };
+    let base_name = meta.base().map(|base| base.name.as_str());
+
+    let mut names = vec![];
+    for branch in meta.local_branches() {
+        if branch.is_head || Some(branch.name.as_str()) == base_name {
+            continue;
+        }
+
+        let target = match branch.tip {
+            Some(target) => target,
+            None => continue,
+        };
+        let is_merged = target == into || repo.graph_descendant_of(into, target).unwrap_or(false);
+        if is_merged {
+            names.push(branch.name.clone());
+        }
+    }
+
+    Ok(names)
+}
+
+/// Delete the local branches that are fully merged into HEAD, or into the base with `--base`, after
+/// listing them and confirming.
+pub fn clean(meta: &RepoMeta, workdir: &Path, pulls: &OpenPulls, policy: &Policy) -> Result<()> {
+    let repo = meta.repo();
+    if repo.is_shallow() {
+        eprintln!(
+            "This is a shallow clone, so branches merged before its oldest commit are not found (run with --deepen)"
+        );
+    }
+
+    let names = merged_branches(meta)?;
+    if names.is_empty() {
+        eprintln!("No merged branches to delete");
+        return Ok(());
+    }
+
+    for name in &names {
+        match pulls.of_local(name) {
+            Some(number) => eprintln!("  {} (open pull request #{})", name, number),
+            None => eprintln!("  {}", name),
+        }
+    }
+    let question = format!("Delete {} merged branch(es)?", names.len());
+    if !prompt::confirm(&question, false)? {
+        return Ok(());
+    }
+
+    delete_merged(meta, workdir, &names, pulls, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `main` with `merged` merged into it, `same` at its base, and `unmerged` a commit ahead of
+    /// the base
+    fn branches(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.reference("refs/heads/same", base, false, "test")
+            .unwrap();
+        testing::commit(&repo, Some("refs/heads/unmerged"), "unmerged", &[], &[base]);
+        let merged = testing::commit(&repo, Some("refs/heads/merged"), "merged", &[], &[base]);
+        testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "merge",
+            &[],
+            &[base, merged],
+        );
+        repo.set_head("refs/heads/main").unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_merged_branches() {
+        let repo = branches("delete");
+        let mut names = merged_branches(&RepoMeta::new(&repo)).unwrap();
+        names.sort();
+        assert_eq!(names, vec!["merged", "same"]);
+    }
+
+    #[test]
+    fn test_merged_branches_into_base() {
+        let repo = branches("delete-base");
+        // the base itself is kept
+        let meta = RepoMeta::new(&repo).with_base("unmerged").unwrap();
+        assert_eq!(merged_branches(&meta).unwrap(), vec!["same"]);
+    }
+}
@@ -0,0 +1,263 @@
+//! How much each branch changes compared to the default branch (`--size`).
+//!
+//! Like the pull requests, the sizes never hold up the picker: it shows the cached ones, and the
+//! missing ones are computed in the background, after which the picker reloads its branches. A
+//! size is cached by the commits it was computed for, so it never goes stale.
+use crate::alternates;
+use crate::meta::RepoMeta;
+use crate::Branch;
+use anyhow::{Context, Result};
+use crossbeam::channel::Sender;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+const CACHE_FILE_NAME: &str = "diffstat.toml";
+
+/// The changes of a branch since it forked from the default branch, like `git diff --shortstat
+/// base...branch`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStat {
+    pub files: usize,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// `1234` as `1.2k`
+fn abbreviate(count: usize) -> String {
+    match count {
+        0..=999 => count.to_string(),
+        1000..=9999 => format!("{:.1}k", count as f64 / 1000.0),
+        _ => format!("{}k", count / 1000),
+    }
+}
+
+impl DiffStat {
+    /// e.g. `+1.2k/-300 · 14 files`
+    pub fn label(&self) -> String {
+        format!(
+            "+{}/-{} · {} {}",
+            abbreviate(self.insertions),
+            abbreviate(self.deletions),
+            self.files,
+            if self.files == 1 { "file" } else { "files" }
+        )
+    }
+
+    /// e.g. `1234 lines added, 300 removed in 14 files`
+    pub fn describe(&self) -> String {
+        format!(
+            "{} lines added, {} removed in {} {}",
+            self.insertions,
+            self.deletions,
+            self.files,
+            if self.files == 1 { "file" } else { "files" }
+        )
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DiffStatCache {
+    /// By `<branch commit>..<default branch commit>`
+    stats: HashMap<String, DiffStat>,
+}
+
+fn cache_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("gibra").join(CACHE_FILE_NAME)
+}
+
+fn load_cache(git_dir: &Path) -> DiffStatCache {
+    fs::read_to_string(cache_path(git_dir))
+        .ok()
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(git_dir: &Path, cache: &DiffStatCache) -> Result<()> {
+    let path = cache_path(git_dir);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(cache).with_context(|| "Failed to serialize diffstats")?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn key(tip: Oid, base: Oid) -> String {
+    format!("{}..{}", tip, base)
+}
+
+/// The commit of `--base`, or else of the default branch, preferring the one of `origin` as it is
+/// the most recent
+pub fn base_commit(meta: &RepoMeta) -> Option<Oid> {
+    if let Some(base) = meta.base() {
+        return Some(base.oid);
+    }
+    let repo = meta.repo();
+    if let Some(remote) = meta.primary_remote() {
+        let name = meta.default_branch(remote);
+        if let Ok(oid) = repo.refname_to_id(&format!("refs/remotes/{}/{}", remote, name)) {
+            return Some(oid);
+        }
+    }
+    ["main", "master"]
+        .iter()
+        .find_map(|name| repo.refname_to_id(&format!("refs/heads/{}", name)).ok())
+}
+
+/// The commit `branch` is at now, peeled from an annotated tag, `None` when it is gone. The refs
+/// of the tabs may be revisions like `stash@{0}` or an id.
+pub fn tip(repo: &Repository, branch: &Branch) -> Option<Oid> {
+    let object = repo.revparse_single(&branch.refname()).ok()?;
+    object.peel_to_commit().ok().map(|commit| commit.id())
+}
+
+/// The changes of `tip` since it forked from `base`
+fn compute(repo: &Repository, tip: Oid, base: Oid) -> Result<DiffStat> {
+    let fork_point = repo
+        .merge_base(tip, base)
+        .with_context(|| "Failed to find the merge base")?;
+    let old_tree = repo.find_commit(fork_point)?.tree()?;
+    let new_tree = repo.find_commit(tip)?.tree()?;
+    let stats = repo
+        .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?
+        .stats()?;
+
+    Ok(DiffStat {
+        files: stats.files_changed(),
+        insertions: stats.insertions(),
+        deletions: stats.deletions(),
+    })
+}
+
+fn set(branch: &mut Branch, diffstat: Option<DiffStat>) {
+    match branch {
+        Branch::Local(branch) => branch.diffstat = diffstat,
+        Branch::Remote(branch) => branch.diffstat = diffstat,
+        Branch::Ref(reference) => reference.diffstat = diffstat,
+    }
+}
+
+/// Attach the cached sizes to `branches`. Nothing is computed.
+pub fn attach_cached(meta: &RepoMeta, branches: &mut [Branch]) {
+    let repo = meta.repo();
+    let base = match base_commit(meta) {
+        Some(base) => base,
+        None => return,
+    };
+    let cache = load_cache(repo.path());
+    for branch in branches {
+        let diffstat = tip(repo, branch).and_then(|tip| cache.stats.get(&key(tip, base)).copied());
+        set(branch, diffstat);
+    }
+}
+
+/// Compute the sizes of `branches` that aren't cached yet in the background, sending on `done`
+/// once they are cached. Returns whether anything is computed.
+pub fn compute_in_background(meta: &RepoMeta, branches: &[Branch], done: Sender<()>) -> bool {
+    let repo = meta.repo();
+    let base = match base_commit(meta) {
+        Some(base) => base,
+        None => return false,
+    };
+    let tips: Vec<Oid> = branches
+        .iter()
+        .filter_map(|branch| tip(repo, branch))
+        .collect();
+    let cache = load_cache(repo.path());
+    if tips
+        .iter()
+        .all(|&tip| cache.stats.contains_key(&key(tip, base)))
+    {
+        return false;
+    }
+
+    let git_dir = repo.path().to_path_buf();
+    thread::spawn(move || {
+        let computed = alternates::open(&git_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|repo| {
+                // only the sizes of the current branches are kept, so the cache doesn't grow
+                let mut cache = load_cache(&git_dir);
+                let mut stats = HashMap::new();
+                for tip in tips {
+                    let key = key(tip, base);
+                    let diffstat = match cache.stats.remove(&key) {
+                        Some(diffstat) => diffstat,
+                        None => compute(&repo, tip, base)?,
+                    };
+                    stats.insert(key, diffstat);
+                }
+                save_cache(&git_dir, &DiffStatCache { stats })
+            });
+        match computed {
+            Ok(()) => {
+                let _ = done.send(());
+            }
+            Err(err) => debug!("{:#}", err),
+        }
+    });
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_label() {
+        let diffstat = DiffStat {
+            files: 14,
+            insertions: 1234,
+            deletions: 300,
+        };
+        assert_eq!(diffstat.label(), "+1.2k/-300 · 14 files");
+        assert_eq!(
+            diffstat.describe(),
+            "1234 lines added, 300 removed in 14 files"
+        );
+        assert_eq!(abbreviate(25_600), "25k");
+    }
+
+    #[test]
+    fn test_compute() {
+        let repo = TempRepo::new("diffstat");
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "base",
+            &[("a", "1\n2\n")],
+            &[],
+        );
+        let tip = testing::commit(
+            &repo,
+            Some("refs/heads/feature"),
+            "feature",
+            &[("a", "1\n3\n"), ("b", "new\n")],
+            &[base],
+        );
+        // changes on main after the fork don't count
+        let newer_base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "newer",
+            &[("c", "x\ny\n")],
+            &[base],
+        );
+
+        assert_eq!(
+            compute(&repo, tip, newer_base).unwrap(),
+            DiffStat {
+                files: 2,
+                insertions: 2,
+                deletions: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Checking out over local changes that are in the way, with `--force`.
+//!
+//! git refuses to check out a branch when that would overwrite local changes. With `--force`,
+//! gibra lists the files that would lose their changes, asks whether to discard them, and then
+//! checks out with `git checkout --force`. When nothing is in the way, the changes are carried
+//! over as usual and nothing is asked.
+use crate::error::GibraError;
+use crate::prompt;
+use anyhow::{Context, Result};
+use git2::{
+    build::CheckoutBuilder, CheckoutNotificationType, ErrorCode, Repository, Status, StatusOptions,
+};
+use std::collections::BTreeSet;
+
+/// A file whose local changes are discarded by a forced checkout
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Loss {
+    pub path: String,
+    /// e.g. `modified` or `staged`
+    pub change: &'static str,
+}
+
+/// The paths that keep a checkout of `refname` from going through, reported by a dry run
+fn conflicts(repo: &Repository, refname: &str) -> Result<BTreeSet<String>> {
+    let tree = repo
+        .find_reference(refname)
+        .and_then(|reference| reference.peel_to_tree())
+        .map_err(|_| GibraError::RefMissing {
+            name: refname.to_string(),
+        })?;
+
+    let mut conflicts = BTreeSet::new();
+    let mut builder = CheckoutBuilder::new();
+    builder
+        .dry_run()
+        .notify_on(CheckoutNotificationType::CONFLICT)
+        .notify(|_, path, _, _, _| {
+            if let Some(path) = path {
+                conflicts.insert(path.to_string_lossy().to_string());
+            }
+            true
+        });
+    match repo.checkout_tree(tree.as_object(), Some(&mut builder)) {
+        Err(err) if err.code() != ErrorCode::Conflict => {
+            return Err(err).with_context(|| format!("Failed to check out {}", refname))
+        }
+        _ => {}
+    }
+    drop(builder);
+
+    Ok(conflicts)
+}
+
+fn describe(status: Status) -> Option<&'static str> {
+    if status.intersects(Status::CONFLICTED) {
+        Some("conflicted")
+    } else if status.intersects(Status::WT_DELETED | Status::INDEX_DELETED) {
+        Some("deleted")
+    } else if status.intersects(Status::WT_MODIFIED | Status::WT_TYPECHANGE | Status::WT_RENAMED) {
+        Some("modified")
+    } else if status.intersects(
+        Status::INDEX_NEW
+            | Status::INDEX_MODIFIED
+            | Status::INDEX_RENAMED
+            | Status::INDEX_TYPECHANGE,
+    ) {
+        Some("staged")
+    } else {
+        None
+    }
+}
+
+/// The files whose changes a forced checkout of `refname` discards: all changes to tracked files,
+/// and the untracked files the branch overwrites. Empty when the checkout goes through without
+/// forcing it.
+pub fn losses(repo: &Repository, refname: &str) -> Result<Vec<Loss>> {
+    let conflicts = conflicts(repo, refname)?;
+    if conflicts.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .with_context(|| "Failed to get the status of the working tree")?;
+
+    let mut losses: Vec<Loss> = statuses
+        .iter()
+        .filter_map(|entry| {
+            let path = entry.path()?.to_string();
+            let change = match describe(entry.status()) {
+                Some(change) => change,
+                None if conflicts.contains(&path) => "untracked, overwritten",
+                None => return None,
+            };
+            Some(Loss { path, change })
+        })
+        .collect();
+    losses.sort();
+
+    Ok(losses)
+}
+
+/// Whether to check out `refname` (named `name`) with `--force`: `false` when the local changes
+/// aren't in the way. Otherwise the files that lose their changes are listed, and unless `yes`
+/// it is asked whether to discard them.
+pub fn confirm(repo: &Repository, refname: &str, name: &str, yes: bool) -> Result<bool> {
+    let losses = losses(repo, refname)?;
+    if losses.is_empty() {
+        return Ok(false);
+    }
+
+    eprintln!(
+        "Checking out {} discards the local changes to {} {}:",
+        name,
+        losses.len(),
+        if losses.len() == 1 { "file" } else { "files" }
+    );
+    let width = losses
+        .iter()
+        .map(|loss| loss.change.len())
+        .max()
+        .unwrap_or(0);
+    for loss in &losses {
+        eprintln!("  {:width$}  {}", loss.change, loss.path, width = width);
+    }
+    if !yes && !prompt::confirm("Discard them?", false)? {
+        eprintln!(
+            "Not checking out {}, the local changes are kept. Pass --yes to discard them without asking",
+            name
+        );
+        return Err(GibraError::DirtyTree.into());
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use std::fs;
+
+    /// Checked out at `a` and `b`, with `feature` changing `a` and adding `c`
+    fn checked_out(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(
+            &repo,
+            Some("HEAD"),
+            "base",
+            &[("a", "1\n"), ("b", "1\n")],
+            &[],
+        );
+        testing::commit(
+            &repo,
+            Some("refs/heads/feature"),
+            "feature",
+            &[("a", "2\n"), ("c", "1\n")],
+            &[base],
+        );
+        repo.checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+        repo
+    }
+
+    #[test]
+    fn test_losses_of_change_carried_over() {
+        let repo = checked_out("discard-carried");
+        // a change that doesn't get in the way
+        fs::write(repo.dir().join("b"), "local\n").unwrap();
+        assert!(losses(&repo, "refs/heads/feature").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_losses_of_changes_in_the_way() {
+        let repo = checked_out("discard");
+        let dir = repo.dir();
+        fs::write(dir.join("a"), "local\n").unwrap();
+        fs::write(dir.join("b"), "local\n").unwrap();
+        fs::write(dir.join("c"), "untracked\n").unwrap();
+        fs::write(dir.join("d"), "untracked\n").unwrap();
+        assert_eq!(
+            losses(&repo, "refs/heads/feature").unwrap(),
+            vec![
+                Loss {
+                    path: "a".to_string(),
+                    change: "modified",
+                },
+                Loss {
+                    path: "b".to_string(),
+                    change: "modified",
+                },
+                Loss {
+                    path: "c".to_string(),
+                    change: "untracked, overwritten",
+                },
+            ]
+        );
+    }
+}
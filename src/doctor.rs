@@ -0,0 +1,508 @@
+//! Checking the environment gibra runs in (`gibra doctor`): the git and libgit2 it uses, the
+//! shell and terminal the picker runs in, the config file, the tokens of the issue trackers, and
+//! the objects and hooks of the current repository. Its output is meant to be pasted into bug
+//! reports.
+use crate::alternates;
+use crate::config::{self, Config};
+use crate::headless::TTY;
+use crate::hooks;
+use crate::maintenance::Counts;
+use crate::secrets::{self, Provider, Source};
+use anyhow::{bail, Result};
+use git2::Repository;
+use std::{env, fs, path::Path, process::Command};
+
+/// `git stash push`, which `--autostash` runs, came with git 2.13
+const MIN_GIT_VERSION: (u32, u32) = (2, 13);
+
+/// `display-popup`, which `--tmux` runs, came with tmux 3.2
+const MIN_TMUX_VERSION: (u32, u32) = (3, 2);
+
+/// The hooks git runs for the commands gibra runs, or that gibra runs itself
+const HOOKS: [&str; 5] = [
+    "post-checkout",
+    "post-merge",
+    "pre-push",
+    "reference-transaction",
+    "post-rewrite",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Level {
+    Ok,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn label(self) -> &'static str {
+        match self {
+            Level::Ok => "ok",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// The outcome of a check, with what to do about it when it isn't ok
+#[derive(Debug)]
+struct Check {
+    level: Level,
+    message: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn ok(message: impl Into<String>) -> Self {
+        Self {
+            level: Level::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            level: Level::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn error(message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            level: Level::Error,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// The major and minor version in the output of `git --version` or `tmux -V`, e.g. `(2, 39)` for
+/// `git version 2.39.3 (Apple Git-145)` or `(3, 3)` for `tmux 3.3a`
+fn parse_version(output: &str) -> Option<(u32, u32)> {
+    let version = output
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut numbers = version.split('.').map(|number| {
+        number
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+    });
+    let major = numbers.next()?.ok()?;
+    let minor = numbers.next().and_then(Result::ok).unwrap_or(0);
+    Some((major, minor))
+}
+
+/// The trimmed stdout of `program args`, when it ran
+fn version_of(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn check_git() -> Check {
+    let output =
+        match version_of("git", &["--version"]) {
+            Some(output) => output,
+            None => return Check::error(
+                "git was not found",
+                "gibra runs git to check out, push and stash; install it and put it on the PATH",
+            ),
+        };
+    match parse_version(&output) {
+        Some(version) if version < MIN_GIT_VERSION => Check::warn(
+            output,
+            format!(
+                "--autostash needs git {}.{} or newer",
+                MIN_GIT_VERSION.0, MIN_GIT_VERSION.1
+            ),
+        ),
+        _ => Check::ok(output),
+    }
+}
+
+fn check_libgit2() -> Check {
+    let version = git2::Version::get();
+    let (major, minor, patch) = version.libgit2_version();
+    let missing: Vec<&str> = [("https", version.https()), ("ssh", version.ssh())]
+        .into_iter()
+        .filter(|(_, supported)| !supported)
+        .map(|(feature, _)| feature)
+        .collect();
+    let message = format!("libgit2 {}.{}.{}", major, minor, patch);
+    if missing.is_empty() {
+        Check::ok(format!("{} with https and ssh", message))
+    } else {
+        Check::warn(
+            format!("{} without {}", message, missing.join(" and ")),
+            format!(
+                "--fetch fails for remotes over {}; install a build of gibra with {} support",
+                missing.join(" or "),
+                missing.join(" and ")
+            ),
+        )
+    }
+}
+
+fn check_shell() -> Check {
+    let shell = match env::var("SHELL") {
+        Ok(shell) if !shell.is_empty() => shell,
+        _ => return Check::ok("SHELL is not set, commands and previews run with sh"),
+    };
+    let path = Path::new(&shell);
+    if path.is_absolute() && !hooks::is_executable(path) {
+        return Check::error(
+            format!("SHELL is {}, which can't be run", shell),
+            "gibra exec and --preview run commands with $SHELL; point it at an installed shell",
+        );
+    }
+    Check::ok(format!("SHELL is {}", shell))
+}
+
+/// Whether the locale set by `LC_ALL`, `LC_CTYPE` or `LANG`, in that order, is UTF-8
+fn is_utf8_locale(var: impl Fn(&str) -> Option<String>) -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|&name| var(name).filter(|value| !value.is_empty()))
+        .map(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+        .unwrap_or(false)
+}
+
+fn check_terminal() -> Vec<Check> {
+    let mut checks = vec![];
+    let tty = fs::OpenOptions::new().read(true).write(true).open(TTY);
+    match tty {
+        Ok(_) => checks.push(Check::ok(format!("{} can be opened", TTY))),
+        Err(err) => checks.push(Check::warn(
+            format!("{} can't be opened: {}", TTY, err),
+            "the picker draws on the controlling terminal; run gibra from a terminal, or pass \
+             --filter to print the matching branches instead",
+        )),
+    }
+
+    match env::var("TERM") {
+        Ok(term) if term.is_empty() || term == "dumb" => checks.push(Check::warn(
+            format!("TERM is {:?}", term),
+            "the picker can't move the cursor on such a terminal; set TERM, e.g. to xterm-256color",
+        )),
+        Ok(term) => checks.push(Check::ok(format!("TERM is {}", term))),
+        Err(_) => checks.push(Check::warn(
+            "TERM is not set",
+            "the picker can't tell what the terminal supports; set TERM, e.g. to xterm-256color",
+        )),
+    }
+
+    if is_utf8_locale(|name| env::var(name).ok()) {
+        checks.push(Check::ok("the locale is UTF-8"));
+    } else {
+        checks.push(Check::warn(
+            "the locale is not UTF-8",
+            "the prompt and non-ASCII branch names may be garbled; set LANG, e.g. to \
+             en_US.UTF-8, or pass --accessible",
+        ));
+    }
+
+    if env::var_os("TMUX").is_some_and(|tmux| !tmux.is_empty()) {
+        checks.push(match version_of("tmux", &["-V"]) {
+            Some(output) => match parse_version(&output) {
+                Some(version) if version < MIN_TMUX_VERSION => Check::warn(
+                    output,
+                    format!(
+                        "--tmux needs tmux {}.{} or newer for popups",
+                        MIN_TMUX_VERSION.0, MIN_TMUX_VERSION.1
+                    ),
+                ),
+                _ => Check::ok(format!("inside {}", output)),
+            },
+            None => Check::warn(
+                "TMUX is set, but tmux was not found",
+                "--tmux runs tmux to open a popup; put it on the PATH",
+            ),
+        });
+    }
+
+    checks
+}
+
+fn check_config(repo: Option<&Repository>) -> (Check, Option<Config>) {
+    let path = match config::config_path() {
+        Some(path) => path,
+        None => {
+            return (
+                Check::ok("no config file, as HOME is not set"),
+                Some(Config::default()),
+            )
+        }
+    };
+    if !path.exists() {
+        return (
+            Check::ok(format!("no config file at {}", path.display())),
+            Some(Config::default()),
+        );
+    }
+
+    match Config::load(repo) {
+        Ok(config) => (
+            Check::ok(format!("{} is valid", path.display())),
+            Some(config),
+        ),
+        Err(err) => (
+            Check::error(
+                format!("{:#}", err),
+                "fix or remove the config file, gibra doesn't start with it",
+            ),
+            None,
+        ),
+    }
+}
+
+fn check_tokens(config: Option<&Config>) -> Vec<Check> {
+    let issues = config.map(|config| &config.issues);
+    Provider::ALL
+        .into_iter()
+        .map(|provider| {
+            let configured = match provider {
+                Provider::Github => issues
+                    .and_then(|issues| issues.github.as_ref())
+                    .and_then(|github| github.token.clone()),
+                Provider::Jira => issues
+                    .and_then(|issues| issues.jira.as_ref())
+                    .and_then(|jira| jira.token.clone()),
+            };
+            let used = match provider {
+                Provider::Github => true,
+                Provider::Jira => issues.is_some_and(|issues| issues.jira.is_some()),
+            };
+            match secrets::lookup(provider, configured.as_ref()) {
+                Some((token, Source::Config)) => Check::warn(
+                    format!(
+                        "{} token {} {}",
+                        provider.name(),
+                        token.redacted(),
+                        Source::Config.describe()
+                    ),
+                    format!(
+                        "run `gibra auth login {}` and remove it from the config file",
+                        provider.name()
+                    ),
+                ),
+                Some((token, source)) => Check::ok(format!(
+                    "{} token {} {}",
+                    provider.name(),
+                    token.redacted(),
+                    source.describe()
+                )),
+                None if used => Check::warn(
+                    format!("no {} token", provider.name()),
+                    format!(
+                        "pull requests and issues aren't shown; run `gibra auth login {}`",
+                        provider.name()
+                    ),
+                ),
+                None => Check::ok(format!(
+                    "no {} token, Jira is not configured",
+                    provider.name()
+                )),
+            }
+        })
+        .collect()
+}
+
+/// The hooks of the repository in `workdir` that git skips: those in `.git/hooks` while
+/// `core.hooksPath` points elsewhere, and those that aren't executable
+fn check_hooks(repo: &Repository, workdir: &Path) -> Vec<Check> {
+    let hooks_dir = hooks::hooks_dir(repo, workdir);
+    let default_dir = hooks::common_dir(repo).join("hooks");
+    let mut checks = vec![];
+
+    if hooks_dir != default_dir {
+        let ignored: Vec<&str> = HOOKS
+            .iter()
+            .copied()
+            .filter(|name| hooks::is_executable(&default_dir.join(name)))
+            .filter(|name| !hooks_dir.join(name).exists())
+            .collect();
+        if !ignored.is_empty() {
+            checks.push(Check::warn(
+                format!(
+                    "{} in {} don't run, core.hooksPath is {}",
+                    ignored.join(", "),
+                    default_dir.display(),
+                    hooks_dir.display()
+                ),
+                "move them to core.hooksPath, or unset it if a hook manager left it behind",
+            ));
+        }
+    }
+
+    let not_executable: Vec<&str> = HOOKS
+        .iter()
+        .copied()
+        .filter(|name| {
+            let path = hooks_dir.join(name);
+            path.is_file() && !hooks::is_executable(&path)
+        })
+        .collect();
+    if !not_executable.is_empty() {
+        checks.push(Check::warn(
+            format!(
+                "{} in {} can't be run",
+                not_executable.join(", "),
+                hooks_dir.display()
+            ),
+            "git skips hooks that aren't executable; chmod +x them, or remove them",
+        ));
+    }
+
+    if checks.is_empty() {
+        checks.push(Check::ok(format!("hooks in {}", hooks_dir.display())));
+    }
+    checks
+}
+
+/// The alternate object directories of the repository that are gone, which leaves the commits
+/// kept there unreadable, and the loose refs and objects that slow down listing the branches
+fn check_objects(repo: &Repository) -> Vec<Check> {
+    let missing = alternates::missing(repo);
+    let mut checks: Vec<Check> = missing
+        .iter()
+        .map(|dir| {
+            Check::error(
+                format!("alternate object directory {} is missing", dir.display()),
+                "the commits kept there can't be shown; restore it, or remove it from \
+                 objects/info/alternates or GIT_ALTERNATE_OBJECT_DIRECTORIES and fetch again",
+            )
+        })
+        .collect();
+    if checks.is_empty() {
+        let objects = hooks::common_dir(repo).join("objects");
+        checks.push(Check::ok(format!("objects in {}", objects.display())));
+    }
+
+    let excess = Counts::of(repo).excess();
+    if !excess.is_empty() {
+        checks.push(Check::warn(
+            excess.join(", "),
+            "listing the branches gets slow; run `gibra maintenance` to pack them",
+        ));
+    }
+    checks
+}
+
+/// Print the checks, and fail when one of them did
+pub fn run() -> Result<()> {
+    let mut sections: Vec<(&str, Vec<Check>)> = vec![
+        ("git", vec![check_git(), check_libgit2()]),
+        ("shell", vec![check_shell()]),
+        ("terminal", check_terminal()),
+    ];
+    let repo = Repository::open_from_env().ok();
+    let (config_check, config) = check_config(repo.as_ref());
+    sections.push(("config", vec![config_check]));
+    sections.push(("tokens", check_tokens(config.as_ref())));
+    if let Some(repo) = &repo {
+        sections.push(("objects", check_objects(repo)));
+        if let Some(workdir) = repo.workdir() {
+            let workdir = workdir.to_path_buf();
+            sections.push(("hooks", check_hooks(repo, &workdir)));
+        }
+    }
+
+    for (name, checks) in &sections {
+        println!("{}", name);
+        for check in checks {
+            println!("  {:5}  {}", check.level.label(), check.message);
+            if let Some(hint) = &check.hint {
+                println!("         {}", hint);
+            }
+        }
+    }
+
+    let checks = || sections.iter().flat_map(|(_, checks)| checks);
+    let errors = checks().filter(|check| check.level == Level::Error).count();
+    let warnings = checks().filter(|check| check.level == Level::Warn).count();
+    println!();
+    match (errors, warnings) {
+        (0, 0) => println!("No problems found"),
+        (0, warnings) => println!("{} warning(s)", warnings),
+        (errors, _) => bail!("{} check(s) failed", errors),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+    use std::fs;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("git version 2.39.3 (Apple Git-145)"),
+            Some((2, 39))
+        );
+        assert_eq!(parse_version("git version 2.45.1.windows.1"), Some((2, 45)));
+        assert_eq!(parse_version("tmux 3.3a"), Some((3, 3)));
+        assert_eq!(parse_version("tmux next-3.4"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn test_is_utf8_locale() {
+        let locale =
+            |value: &'static str| move |name: &str| (name == "LANG").then(|| value.to_string());
+        assert!(is_utf8_locale(locale("en_US.UTF-8")));
+        assert!(is_utf8_locale(locale("ja_JP.utf8")));
+        assert!(!is_utf8_locale(locale("C")));
+    }
+
+    /// A repository with a `post-checkout` hook that isn't executable and a `post-merge` one
+    /// that is, both in `.git/hooks`
+    #[cfg(unix)]
+    fn hooked(name: &str) -> TempRepo {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repo = TempRepo::new(name);
+        let hooks = repo.dir().join(".git").join("hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        fs::write(hooks.join("post-checkout"), "#!/bin/sh\n").unwrap();
+        fs::write(hooks.join("post-merge"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(hooks.join("post-merge"), fs::Permissions::from_mode(0o755)).unwrap();
+        repo
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_hooks() {
+        let repo = hooked("doctor");
+        let checks = check_hooks(&repo, repo.dir());
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].level, Level::Warn);
+        assert!(checks[0].message.starts_with("post-checkout in "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_check_hooks_elsewhere() {
+        let repo = hooked("doctor-hooks-path");
+        let dir = repo.dir();
+        repo.config()
+            .unwrap()
+            .set_str("core.hooksPath", ".husky")
+            .unwrap();
+        let checks = check_hooks(&repo, dir);
+        assert_eq!(checks.len(), 1);
+        assert!(checks[0].message.starts_with("post-merge in "));
+        assert!(checks[0].message.ends_with(&format!(
+            "core.hooksPath is {}",
+            dir.join(".husky").display()
+        )));
+    }
+}
@@ -0,0 +1,163 @@
+//! What the picker shows when no branch is listed, e.g. with `--remote-only` before the remotes
+//! were fetched: why the list is empty, and the keys that fill it.
+use crate::bus::BranchAction;
+use crate::skim::prelude::*;
+use git2::BranchType;
+
+/// Fetches the remotes and lists the branches again
+const FETCH_KEY: &str = "ctrl-r";
+
+/// Lists the local and remote branches of all remotes
+const SHOW_ALL_KEY: &str = "alt-a";
+
+/// A way to list more branches, offered while the list is filtered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Widen {
+    Fetch,
+    ShowAll,
+}
+
+impl Widen {
+    fn key(self) -> &'static str {
+        match self {
+            Widen::Fetch => FETCH_KEY,
+            Widen::ShowAll => SHOW_ALL_KEY,
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Widen::Fetch => "fetch the remotes",
+            Widen::ShowAll => "show all branches",
+        }
+    }
+
+    /// The ways offered with these filters: fetching when remote branches are listed, and showing
+    /// all branches when only some are
+    pub fn offered(branch_filter: Option<BranchType>, has_remotes: bool) -> Vec<Widen> {
+        let mut offered = vec![];
+        if has_remotes && branch_filter != Some(BranchType::Local) {
+            offered.push(Widen::Fetch);
+        }
+        if branch_filter.is_some() {
+            offered.push(Widen::ShowAll);
+        }
+        offered
+    }
+
+    pub fn from_key(offered: &[Widen], key: &str) -> Option<Widen> {
+        offered.iter().copied().find(|widen| widen.key() == key)
+    }
+
+    /// The keys to pass to skim's `expect` option. Fetching isn't one of them, as it runs while
+    /// the picker stays open, see [`Widen::bindings`].
+    pub fn expect_keys(offered: &[Widen]) -> Vec<&'static str> {
+        offered
+            .iter()
+            .filter(|&&widen| widen != Widen::Fetch)
+            .map(|widen| widen.key())
+            .collect()
+    }
+
+    /// The bindings of the ways that run on [`crate::bus`]
+    pub fn bindings(offered: &[Widen]) -> Vec<String> {
+        offered
+            .iter()
+            .filter(|&&widen| widen == Widen::Fetch)
+            .map(|widen| BranchAction::binding(widen.key(), BranchAction::FETCH))
+            .collect()
+    }
+}
+
+/// A line of the message, which can't be picked
+struct Notice {
+    text: String,
+}
+
+impl SkimItem for Notice {
+    /// Nothing to match, so the message is hidden while searching
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed("")
+    }
+
+    fn display<'a>(&'a self, _context: DisplayContext<'a>) -> AnsiString<'a> {
+        AnsiString::from(self.text.as_str())
+    }
+
+    fn is_selectable(&self) -> bool {
+        false
+    }
+}
+
+/// Why nothing is listed, e.g. `No remote branches — run with --fetch?`
+fn message(branch_filter: Option<BranchType>, remote: Option<&str>, has_remotes: bool) -> String {
+    match (branch_filter, remote) {
+        (_, Some(remote)) => format!("No branches on {} — run with --fetch?", remote),
+        (Some(BranchType::Remote), None) if !has_remotes => {
+            "No remote branches — this repository has no remotes".to_string()
+        }
+        (Some(BranchType::Remote), None) => "No remote branches — run with --fetch?".to_string(),
+        (Some(BranchType::Local), None) => "No local branches".to_string(),
+        (None, None) => "No branches".to_string(),
+    }
+}
+
+/// The lines shown instead of the branches when there are none: the message and the keys of
+/// `offered`
+pub fn items(
+    branch_filter: Option<BranchType>,
+    remote: Option<&str>,
+    has_remotes: bool,
+    offered: &[Widen],
+) -> Vec<Arc<dyn SkimItem>> {
+    let mut lines = vec![message(branch_filter, remote, has_remotes)];
+    lines.extend(
+        offered
+            .iter()
+            .map(|widen| format!("  {}: {}", widen.key(), widen.hint())),
+    );
+
+    lines
+        .into_iter()
+        .map(|text| Arc::new(Notice { text }) as Arc<dyn SkimItem>)
+        .collect()
+}
+
+/// The line shown instead of the items of a tab without any, see [`crate::tabs`]
+pub fn notice(text: &str) -> Vec<Arc<dyn SkimItem>> {
+    vec![Arc::new(Notice {
+        text: text.to_string(),
+    })]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_state() {
+        let offered = Widen::offered(Some(BranchType::Remote), true);
+        assert_eq!(offered, vec![Widen::Fetch, Widen::ShowAll]);
+        assert_eq!(Widen::expect_keys(&offered), vec!["alt-a"]);
+        assert_eq!(Widen::bindings(&offered), vec!["ctrl-r:emit(fetch)"]);
+        assert_eq!(Widen::from_key(&offered, "alt-a"), Some(Widen::ShowAll));
+        assert_eq!(
+            Widen::offered(Some(BranchType::Local), true),
+            vec![Widen::ShowAll]
+        );
+        assert!(Widen::offered(None, false).is_empty());
+
+        assert_eq!(
+            message(Some(BranchType::Remote), None, true),
+            "No remote branches — run with --fetch?"
+        );
+        assert_eq!(
+            message(Some(BranchType::Remote), Some("upstream"), true),
+            "No branches on upstream — run with --fetch?"
+        );
+        assert_eq!(
+            items(Some(BranchType::Remote), None, true, &offered).len(),
+            3
+        );
+    }
+}
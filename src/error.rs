@@ -0,0 +1,239 @@
+//! The failures gibra expects, like a branch that doesn't exist or local changes in the way, and
+//! how they are reported: what went wrong, what git said when it was git that failed, and what to
+//! do next. Each exits with a status of its own that scripts can rely on. Anything else is an
+//! unexpected error, reported as it is and exiting with 1.
+use crate::git::GitError;
+use std::{
+    env, fmt,
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
+
+/// The status gibra exits with after an unexpected error, after a command of `[commands]`
+/// failed, and when `gibra doctor` found a problem
+const INTERNAL: i32 = 1;
+
+/// The status of `gibra verify` when the branch doesn't exist, the same as
+/// [`GibraError::RefMissing`]
+pub const MISSING: i32 = 7;
+
+/// The status of `--filter` when no branch matches
+pub const NO_MATCH: i32 = 11;
+
+/// A failure gibra expects, reported with a hint at what to do next
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GibraError {
+    /// `path` is in no git repository
+    RepoNotFound { path: PathBuf },
+    /// Checking out would overwrite local changes
+    DirtyTree,
+    /// Checking out would overwrite untracked files
+    CheckoutConflict,
+    /// A merge or rebase left conflicts that aren't resolved
+    UnresolvedConflicts,
+    /// A merge, rebase or other `operation` is in progress, which switching branches would lose
+    OperationInProgress { operation: String },
+    /// `name` is no branch, tag or commit
+    RefMissing { name: String },
+    /// A branch of that name exists already
+    BranchExists { name: String },
+    /// The branch is checked out in the worktree at `path`, where git won't check it out again
+    CheckedOutElsewhere { branch: String, path: PathBuf },
+    /// The remote turned down the credentials, or there were none
+    NetworkAuth { remote: String },
+}
+
+impl GibraError {
+    /// The status gibra exits with, which stays the same from one version to the next
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GibraError::RepoNotFound { .. } => 3,
+            GibraError::DirtyTree => 4,
+            GibraError::CheckoutConflict => 5,
+            GibraError::UnresolvedConflicts => 6,
+            GibraError::RefMissing { .. } => MISSING,
+            GibraError::BranchExists { .. } => 8,
+            GibraError::CheckedOutElsewhere { .. } => 9,
+            GibraError::NetworkAuth { .. } => 10,
+            GibraError::OperationInProgress { .. } => 12,
+        }
+    }
+
+    /// What to do next
+    pub fn hint(&self) -> &'static str {
+        match self {
+            GibraError::RepoNotFound { .. } => {
+                "Run gibra in the working tree of a git repository, or create one with git init"
+            }
+            GibraError::DirtyTree => {
+                "Commit or stash your changes first, or pass --autostash to stash them while \
+                 checking out"
+            }
+            GibraError::CheckoutConflict => {
+                "Move or remove the untracked files first, or pass --autostash to stash them \
+                 while checking out"
+            }
+            GibraError::UnresolvedConflicts => {
+                "Resolve the conflicts and commit, or abort the merge or rebase first"
+            }
+            GibraError::RefMissing { .. } => {
+                "Check the name, and fetch it first if it only exists on a remote"
+            }
+            GibraError::BranchExists { .. } => {
+                "A branch of that name exists already, pick it from the local branches instead"
+            }
+            GibraError::CheckedOutElsewhere { .. } => {
+                "Go to that worktree, or pass --temp to check the branch out in a throwaway one"
+            }
+            GibraError::OperationInProgress { .. } => {
+                "Finish or abort it first, or pass --force to switch anyway"
+            }
+            GibraError::NetworkAuth { .. } => {
+                "Check that git fetch works with your credentials, e.g. that the SSH agent holds \
+                 the key or that the credential helper has a valid token"
+            }
+        }
+    }
+}
+
+impl fmt::Display for GibraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GibraError::RepoNotFound { path } => {
+                write!(f, "{} is not in a git repository", path.display())
+            }
+            GibraError::DirtyTree => write!(f, "Checking out would overwrite your local changes"),
+            GibraError::CheckoutConflict => {
+                write!(f, "Checking out would overwrite untracked files")
+            }
+            GibraError::UnresolvedConflicts => write!(f, "There are unresolved conflicts"),
+            GibraError::RefMissing { name } => {
+                write!(f, "There is no branch, tag or commit {}", name)
+            }
+            GibraError::BranchExists { name } => {
+                write!(f, "A branch named {} exists already", name)
+            }
+            GibraError::CheckedOutElsewhere { branch, path } => {
+                write!(f, "{} is already checked out at {}", branch, path.display())
+            }
+            GibraError::NetworkAuth { remote } => {
+                write!(f, "Failed to authenticate with {}", remote)
+            }
+            GibraError::OperationInProgress { operation } => {
+                write!(
+                    f,
+                    "Cannot switch branches while a {} is in progress",
+                    operation
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GibraError {}
+
+/// `err` of connecting to `remote`, as [`GibraError::NetworkAuth`] when the credentials were the
+/// problem
+pub fn remote_error(remote: &str, err: git2::Error) -> anyhow::Error {
+    let auth = err.code() == git2::ErrorCode::Auth
+        || (err.class() == git2::ErrorClass::Ssh && err.message().contains("authenticat"));
+    if auth {
+        return GibraError::NetworkAuth {
+            remote: remote.to_string(),
+        }
+        .into();
+    }
+    err.into()
+}
+
+/// The expected failure behind `err`, and the git command that failed with it, if any
+fn expected(err: &anyhow::Error) -> Option<(GibraError, Option<&GitError>)> {
+    err.chain().find_map(|cause| {
+        if let Some(error) = cause.downcast_ref::<GibraError>() {
+            return Some((error.clone(), None));
+        }
+        let git_err = cause.downcast_ref::<GitError>()?;
+        Some((git_err.kind()?, Some(git_err)))
+    })
+}
+
+/// `text` in the color of the SGR `code`, when stderr is a terminal and `NO_COLOR` isn't set
+fn paint(code: &str, text: &str) -> String {
+    if io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Report `err` on stderr, and return the status gibra exits with
+pub fn report(err: &anyhow::Error) -> i32 {
+    let (error, git_err) = match expected(err) {
+        Some(expected) => expected,
+        None => {
+            eprintln!("Error: {:?}", err);
+            return INTERNAL;
+        }
+    };
+
+    eprintln!("{} {}", paint("1;31", "error:"), error);
+    if let Some(git_err) = git_err {
+        for line in git_err.stderr().trim_end().lines() {
+            eprintln!("  {}", paint("2", line));
+        }
+    }
+    eprintln!("{} {}", paint("36", "hint:"), error.hint());
+    error.exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_expected() {
+        let err = Err::<(), _>(GibraError::RefMissing {
+            name: "feature/x".to_string(),
+        })
+        .with_context(|| "Failed to checkout branch")
+        .unwrap_err();
+        let (error, git_err) = expected(&err).unwrap();
+        assert_eq!(
+            error.to_string(),
+            "There is no branch, tag or commit feature/x"
+        );
+        assert!(git_err.is_none());
+        assert_eq!(report(&err), 7);
+
+        let unexpected = anyhow::anyhow!("Failed to read the index");
+        assert!(expected(&unexpected).is_none());
+        assert_eq!(report(&unexpected), INTERNAL);
+
+        let auth = git2::Error::new(
+            git2::ErrorCode::Auth,
+            git2::ErrorClass::Http,
+            "authentication required",
+        );
+        let err = remote_error("origin", auth).context("Failed to fetch from origin");
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to fetch from origin: Failed to authenticate with origin"
+        );
+        assert_eq!(report(&err), 10);
+        let err = remote_error("origin", git2::Error::from_str("timed out"));
+        assert!(expected(&err).is_none());
+    }
+
+    #[test]
+    fn test_operation_in_progress() {
+        let err = anyhow::Error::from(GibraError::OperationInProgress {
+            operation: "rebase".to_string(),
+        });
+        assert_eq!(
+            err.to_string(),
+            "Cannot switch branches while a rebase is in progress"
+        );
+        assert_eq!(report(&err), 12);
+    }
+}
@@ -0,0 +1,117 @@
+use anyhow::{Context, Result};
+use std::{
+    env,
+    os::unix::process::ExitStatusExt,
+    process::{Command, ExitStatus},
+};
+
+const PLACEHOLDER: &str = "{}";
+
+/// Substitute the branch names into the arguments of a command that is run without a shell.
+///
+/// Every argument containing the placeholder is repeated once per branch. When no argument
+/// contains it, the branch names are appended.
+fn expand_args(command: &[String], names: &[String]) -> Vec<String> {
+    if !command.iter().any(|arg| arg.contains(PLACEHOLDER)) {
+        return command.iter().chain(names.iter()).cloned().collect();
+    }
+
+    command
+        .iter()
+        .flat_map(|arg| {
+            if arg.contains(PLACEHOLDER) {
+                names
+                    .iter()
+                    .map(|name| arg.replace(PLACEHOLDER, name))
+                    .collect()
+            } else {
+                vec![arg.clone()]
+            }
+        })
+        .collect()
+}
+
+/// Quote `text` so that the shell passes it through as a single word.
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Substitute the quoted, space separated branch names into a shell command line.
+fn expand_shell_command(command: &str, names: &[String]) -> String {
+    let quoted = names
+        .iter()
+        .map(|name| shell_quote(name))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if command.contains(PLACEHOLDER) {
+        command.replace(PLACEHOLDER, &quoted)
+    } else {
+        format!("{} {}", command, quoted)
+    }
+}
+
+/// The exit code of a process, or 128 and the number of the signal that killed it, as shells
+/// report it
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+/// Run `command` with the branch names substituted, and return its exit code.
+///
+/// A command given as a single argument is run by `$SHELL` so that pipes and the like work.
+pub fn run(command: &[String], names: &[String]) -> Result<i32> {
+    let status = if let [command_line] = command {
+        let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+        Command::new(shell)
+            .arg("-c")
+            .arg(expand_shell_command(command_line, names))
+            .status()
+    } else {
+        let args = expand_args(command, names);
+        Command::new(&args[0]).args(&args[1..]).status()
+    }
+    .with_context(|| format!("Failed to run {}", command.join(" ")))?;
+
+    Ok(exit_code(status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_expand_args() {
+        let command = strings(&["git", "diff", "{}..HEAD"]);
+        assert_eq!(
+            expand_args(&command, &strings(&["main"])),
+            strings(&["git", "diff", "main..HEAD"])
+        );
+        assert_eq!(
+            expand_args(&command, &strings(&["a", "origin/b"])),
+            strings(&["git", "diff", "a..HEAD", "origin/b..HEAD"])
+        );
+        assert_eq!(
+            expand_args(&strings(&["git", "log"]), &strings(&["a", "b"])),
+            strings(&["git", "log", "a", "b"])
+        );
+    }
+
+    #[test]
+    fn test_expand_shell_command() {
+        assert_eq!(
+            expand_shell_command("git log {} | head", &strings(&["it's", "b"])),
+            "git log 'it'\\''s' 'b' | head"
+        );
+        assert_eq!(
+            expand_shell_command("git show", &strings(&["main"])),
+            "git show 'main'"
+        );
+    }
+}
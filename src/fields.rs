@@ -0,0 +1,116 @@
+//! Limiting the parts of the branch names that are shown and matched, like `--with-nth` and
+//! `--nth` of fzf and skim.
+//!
+//! The fields of a name are the parts between its slashes: in `feature/ABC-123/login`, field 1 is
+//! `feature`, field 2 is `ABC-123` and field -1 is `login`. Fields are given as a comma-separated
+//! list of skim's ranges, e.g. `2..` or `1,-1`.
+use crate::skim::field::{parse_matching_fields, parse_transform_fields, FieldRange};
+use crate::Branch;
+use anyhow::{bail, Result};
+use regex::Regex;
+
+lazy_static! {
+    static ref DELIMITER: Regex = Regex::new("/").unwrap();
+}
+
+/// The fields to show and to match
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fields {
+    /// The fields of the name that are shown, all when empty
+    with_nth: Vec<FieldRange>,
+    /// The fields of the shown text that the query is matched against, all when empty
+    nth: Vec<FieldRange>,
+}
+
+/// What is left of a name: the text that is shown, and the byte ranges of it that are matched
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Projection {
+    pub text: String,
+    pub matching: Option<Vec<(usize, usize)>>,
+}
+
+/// Parse a list of fields like `1,3..`
+pub fn parse(spec: &str) -> Result<Vec<FieldRange>> {
+    spec.split(',')
+        .map(|range| match FieldRange::from_str(range.trim()) {
+            // an empty range would mean all fields, which is more likely a typo
+            Some(field) if !range.trim().is_empty() => Ok(field),
+            _ => bail!(
+                "Invalid field range `{}`, expected e.g. `2`, `2..`, `..-2` or `1..3`",
+                range
+            ),
+        })
+        .collect()
+}
+
+impl Fields {
+    pub fn new(with_nth: Option<&str>, nth: Option<&str>) -> Result<Self> {
+        Ok(Self {
+            with_nth: with_nth.map(parse).transpose()?.unwrap_or_default(),
+            nth: nth.map(parse).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.with_nth.is_empty() && self.nth.is_empty()
+    }
+
+    pub fn project(&self, name: &str) -> Projection {
+        let text = if self.with_nth.is_empty() {
+            name.to_string()
+        } else {
+            let text = parse_transform_fields(&DELIMITER, name, &self.with_nth);
+            // the fields keep the slash that ends them, which is noise at the end
+            text.trim_end_matches('/').to_string()
+        };
+        let matching =
+            (!self.nth.is_empty()).then(|| parse_matching_fields(&DELIMITER, &text, &self.nth));
+
+        Projection { text, matching }
+    }
+}
+
+/// Attach what is shown and matched of each branch
+pub fn attach(fields: &Fields, branches: &mut [Branch]) {
+    if fields.is_empty() {
+        return;
+    }
+
+    for branch in branches {
+        match branch {
+            Branch::Local(branch) => branch.projection = Some(fields.project(&branch.name)),
+            Branch::Remote(branch) => branch.projection = Some(fields.project(&branch.name)),
+            Branch::Ref(reference) => reference.projection = Some(fields.project(&reference.name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project() {
+        let name = "feature/ABC-123/login";
+
+        let fields = Fields::new(Some("2.."), None).unwrap();
+        assert_eq!(fields.project(name).text, "ABC-123/login");
+        assert_eq!(fields.project(name).matching, None);
+
+        let fields = Fields::new(Some("1"), None).unwrap();
+        assert_eq!(fields.project(name).text, "feature");
+
+        let fields = Fields::new(None, Some("-1")).unwrap();
+        let projection = fields.project(name);
+        assert_eq!(projection.text, name);
+        assert_eq!(projection.matching, Some(vec![(16, 21)]));
+        assert_eq!(&name[16..21], "login");
+
+        let fields = Fields::new(Some("2.."), Some("1")).unwrap();
+        assert_eq!(fields.project(name).matching, Some(vec![(0, 8)]));
+
+        assert!(Fields::new(None, Some("a")).is_err());
+        assert!(Fields::new(Some("1,"), None).is_err());
+        assert!(Fields::default().is_empty());
+    }
+}
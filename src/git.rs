@@ -0,0 +1,347 @@
+//! Running git commands, turning their failures into errors that say how to resolve them.
+use crate::error::GibraError;
+use anyhow::{Context, Result};
+use std::{
+    fmt,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    process::{Command, ExitStatus, Stdio},
+};
+
+/// What git says when it failed to authenticate with a remote
+const AUTH_FAILURES: [&str; 4] = [
+    "Authentication failed for",
+    "Permission denied (publickey",
+    "could not read Username for",
+    "terminal prompts disabled",
+];
+
+/// The first text in single quotes in `text`, e.g. `main` in `a branch named 'main' already
+/// exists`
+fn quoted(text: &str) -> Option<&str> {
+    let (_, rest) = text.split_once('\'')?;
+    Some(rest.split_once('\'')?.0)
+}
+
+/// A git command that exited with an error
+#[derive(Debug)]
+pub struct GitError {
+    command: String,
+    status: ExitStatus,
+    stderr: String,
+}
+
+impl GitError {
+    /// What went wrong, when it is a common failure, recognized by the message of git
+    pub fn kind(&self) -> Option<GibraError> {
+        let stderr = self.stderr.as_str();
+        let name = || quoted(stderr).unwrap_or(self.summary()).to_string();
+        let kind = if stderr.contains("local changes to the following files would be overwritten") {
+            GibraError::DirtyTree
+        } else if stderr.contains("untracked working tree files would be overwritten") {
+            GibraError::CheckoutConflict
+        } else if stderr.contains("did not match any file(s) known to git") {
+            GibraError::RefMissing { name: name() }
+        } else if let Some((_, rest)) = stderr.split_once("invalid reference: ") {
+            let name = rest.lines().next().unwrap_or_default().to_string();
+            GibraError::RefMissing { name }
+        } else if stderr.to_lowercase().contains("a branch named '") {
+            GibraError::BranchExists { name: name() }
+        } else if stderr.contains("resolve your current index first") {
+            GibraError::UnresolvedConflicts
+        } else if AUTH_FAILURES.iter().any(|failure| stderr.contains(failure)) {
+            let remote = quoted(stderr).unwrap_or("the remote").to_string();
+            GibraError::NetworkAuth { remote }
+        } else {
+            return None;
+        };
+        Some(kind)
+    }
+
+    /// How to resolve the failure, when it is a common one
+    pub fn hint(&self) -> Option<&'static str> {
+        self.kind().map(|kind| kind.hint())
+    }
+
+    /// What git wrote to stderr
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    /// The first line git wrote, without its `error: ` prefix, e.g. `the branch 'x' is not fully
+    /// merged`
+    pub fn summary(&self) -> &str {
+        let line = self.stderr.lines().next().unwrap_or_default();
+        line.strip_prefix("error: ").unwrap_or(line)
+    }
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "git {} failed ({})", self.command, self.status)?;
+        let stderr = self.stderr.trim_end();
+        if !stderr.is_empty() {
+            write!(f, ":\n{}", stderr)?;
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "\nhint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GitError {}
+
+/// Makes git look for hooks where there are none
+pub const NO_HOOKS: [&str; 2] = [
+    "-c",
+    if cfg!(windows) {
+        "core.hooksPath=NUL"
+    } else {
+        "core.hooksPath=/dev/null"
+    },
+];
+
+/// `git -C <workdir>`, so that git runs in `workdir` whatever the current directory, e.g. the
+/// same working tree reached through a symlink
+pub fn command(workdir: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(workdir);
+    command
+}
+
+/// Run `git <args>` in `workdir`. Its messages are shown when it succeeds, and put into a
+/// [`GitError`] when it fails.
+pub fn run<S: AsRef<str>>(workdir: &Path, args: &[S]) -> Result<()> {
+    execute(workdir, &[], args, false)
+}
+
+/// Same as [`run`], but git runs none of the hooks of the repository
+pub fn run_without_hooks<S: AsRef<str>>(workdir: &Path, args: &[S]) -> Result<()> {
+    execute(workdir, &NO_HOOKS, args, false)
+}
+
+/// Same as [`run`], but the messages of git are only kept for the error, as the picker has the
+/// terminal while the actions of [`crate::bus`] run
+pub fn run_quietly<S: AsRef<str>>(workdir: &Path, args: &[S]) -> Result<()> {
+    execute(workdir, &[], args, true)
+}
+
+/// Same as [`run_quietly`], with `input` written to the standard input of git, e.g. the paths
+/// for `--stdin`
+pub fn run_with_input<S: AsRef<str>>(workdir: &Path, args: &[S], input: &[u8]) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    let mut child = command(workdir)
+        .args(&args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input)?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(GitError {
+        command: args.join(" "),
+        status: output.status,
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    }
+    .into())
+}
+
+/// Whether git wrote `line` to tell how far it got, e.g. `Updating files:  45% (4500/10000)`
+fn is_progress(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(_, rest)| {
+        let rest = rest.trim_start();
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+        digits > 0 && rest[digits..].starts_with('%')
+    })
+}
+
+/// Same as [`run_without_hooks`], but the progress lines git writes, e.g. `Updating files:  45%
+/// (4500/10000)`, are passed to `progress` as they come instead of being shown
+pub fn run_with_progress<S: AsRef<str>>(
+    workdir: &Path,
+    args: &[S],
+    mut progress: impl FnMut(&str),
+) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    let mut child = command(workdir)
+        .args(NO_HOOKS)
+        .args(&args)
+        .stdout(io::stderr())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+
+    // git redraws a progress line after a `\r`, and ends it with a `\n`
+    let mut stderr = String::new();
+    let mut line = vec![];
+    let mut bytes = child
+        .stderr
+        .take()
+        .map(BufReader::new)
+        .into_iter()
+        .flat_map(Read::bytes);
+    while let Some(Ok(byte)) = bytes.next() {
+        if byte != b'\r' && byte != b'\n' {
+            line.push(byte);
+            continue;
+        }
+        let text = String::from_utf8_lossy(&line);
+        if is_progress(&text) {
+            progress(&text);
+        } else if !text.is_empty() {
+            stderr.push_str(&text);
+            stderr.push('\n');
+        }
+        line.clear();
+    }
+    stderr.push_str(&String::from_utf8_lossy(&line));
+
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+    if status.success() {
+        eprint!("{}", stderr);
+        return Ok(());
+    }
+
+    Err(GitError {
+        command: args.join(" "),
+        status,
+        stderr,
+    }
+    .into())
+}
+
+fn execute<S: AsRef<str>>(workdir: &Path, config: &[&str], args: &[S], quiet: bool) -> Result<()> {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_ref()).collect();
+    let output = command(workdir)
+        .args(config)
+        .args(&args)
+        .stdout(if quiet {
+            Stdio::null()
+        } else {
+            io::stderr().into()
+        })
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to execute git {}", args.join(" ")))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if output.status.success() {
+        if !quiet {
+            eprint!("{}", stderr);
+        }
+        return Ok(());
+    }
+
+    Err(GitError {
+        command: args.join(" "),
+        status: output.status,
+        stderr,
+    }
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempDir, TempRepo};
+
+    #[test]
+    fn test_run() {
+        let repo = TempRepo::new("git");
+        let dir = repo.dir();
+
+        run(dir, &["status", "--short"]).unwrap();
+
+        let err = run(dir, &["checkout", "does-not-exist"]).unwrap_err();
+        let git_err = err.downcast_ref::<GitError>().unwrap();
+        assert!(!git_err.status.success());
+        assert!(git_err
+            .summary()
+            .starts_with("pathspec 'does-not-exist' did not match"));
+        assert_eq!(
+            git_err.hint(),
+            Some("Check the name, and fetch it first if it only exists on a remote")
+        );
+        assert!(err
+            .to_string()
+            .starts_with("git checkout does-not-exist failed"));
+        assert!(err.to_string().contains("\nhint: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_through_symlink() {
+        let repo = TempRepo::new("git-symlinked");
+        let links = TempDir::new("git-links");
+        let link = links.join("repo");
+        std::os::unix::fs::symlink(repo.dir(), &link).unwrap();
+
+        // in the repository reached through the symlink, not in the current directory
+        let output = command(&link)
+            .args(["rev-parse", "--show-toplevel"])
+            .output()
+            .unwrap();
+        assert_eq!(
+            Path::new(String::from_utf8_lossy(&output.stdout).trim()),
+            repo.dir().canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hint() {
+        let error = |stderr: &str| GitError {
+            command: "checkout main".to_string(),
+            status: Command::new("false").status().unwrap(),
+            stderr: stderr.to_string(),
+        };
+        let dirty = error(
+            "error: Your local changes to the following files would be overwritten by checkout:\n\
+             \tsrc/main.rs\n\
+             Please commit your changes or stash them before you switch branches.\n\
+             Aborting",
+        );
+        assert_eq!(dirty.kind(), Some(GibraError::DirtyTree));
+        assert!(dirty.hint().unwrap().contains("--autostash"));
+        assert_eq!(
+            error("fatal: a branch named 'main' already exists").kind(),
+            Some(GibraError::BranchExists {
+                name: "main".to_string()
+            })
+        );
+        assert_eq!(
+            error("fatal: invalid reference: feature/x\n").kind(),
+            Some(GibraError::RefMissing {
+                name: "feature/x".to_string()
+            })
+        );
+        assert_eq!(
+            error("fatal: Authentication failed for 'https://example.com/repo.git/'").kind(),
+            Some(GibraError::NetworkAuth {
+                remote: "https://example.com/repo.git/".to_string()
+            })
+        );
+        // a worktree, not a branch
+        assert_eq!(error("fatal: '/tmp/x' already exists").kind(), None);
+        assert_eq!(error("fatal: something else").hint(), None);
+    }
+
+    #[test]
+    fn test_is_progress() {
+        assert!(is_progress("Updating files:  45% (4500/10000)"));
+        assert!(is_progress("Updating files: 100% (10000/10000), done."));
+        assert!(!is_progress("Switched to branch 'main'"));
+        assert!(!is_progress("error: 3 files: 40 % done"));
+    }
+}
@@ -0,0 +1,307 @@
+//! The gitflow branching model, used with `--gitflow`: features start from and are merged back
+//! into `develop`, releases start from `develop` and hotfixes from the main branch, and both are
+//! merged into the main branch and `develop` when finished.
+use crate::audit::{self, Loss};
+use crate::config::GitflowConfig;
+use crate::meta::RepoMeta;
+use crate::resolve;
+use crate::skim::prelude::*;
+use crate::transaction::Transaction;
+use crate::{group, Branch, PickerOptions};
+use anyhow::{bail, Context, Result};
+use git2::{BranchType, Repository};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Main,
+    Develop,
+    Feature,
+    Release,
+    Hotfix,
+    Other,
+}
+
+impl Role {
+    fn label(self) -> &'static str {
+        match self {
+            Role::Main => "Main",
+            Role::Develop => "Develop",
+            Role::Feature => "Features",
+            Role::Release => "Releases",
+            Role::Hotfix => "Hotfixes",
+            Role::Other => "Other",
+        }
+    }
+}
+
+/// The gitflow branch names of a repository
+#[derive(Clone, Debug)]
+pub struct Gitflow {
+    main: String,
+    develop: String,
+    feature_prefix: String,
+    release_prefix: String,
+    hotfix_prefix: String,
+}
+
+impl Gitflow {
+    pub fn new(repo: &Repository, config: &GitflowConfig) -> Self {
+        let has_branch = |name: &str| repo.find_branch(name, BranchType::Local).is_ok();
+        let main = match &config.main {
+            Some(main) => main.clone(),
+            None if !has_branch("main") && has_branch("master") => "master".to_string(),
+            None => "main".to_string(),
+        };
+
+        Self {
+            main,
+            develop: config.develop.clone(),
+            feature_prefix: config.feature_prefix.clone(),
+            release_prefix: config.release_prefix.clone(),
+            hotfix_prefix: config.hotfix_prefix.clone(),
+        }
+    }
+
+    pub fn role(&self, name: &str) -> Role {
+        if name == self.main {
+            Role::Main
+        } else if name == self.develop {
+            Role::Develop
+        } else if name.starts_with(&self.feature_prefix) {
+            Role::Feature
+        } else if name.starts_with(&self.release_prefix) {
+            Role::Release
+        } else if name.starts_with(&self.hotfix_prefix) {
+            Role::Hotfix
+        } else {
+            Role::Other
+        }
+    }
+
+    /// The branch that branches of `role` start from
+    pub fn base(&self, role: Role) -> Option<&str> {
+        match role {
+            Role::Feature | Role::Release => Some(&self.develop),
+            Role::Hotfix => Some(&self.main),
+            _ => None,
+        }
+    }
+
+    /// The branches that branches of `role` are merged into when finished, in order
+    pub fn targets(&self, role: Role) -> Vec<&str> {
+        match role {
+            Role::Feature => vec![&self.develop],
+            Role::Release | Role::Hotfix => vec![&self.main, &self.develop],
+            _ => vec![],
+        }
+    }
+}
+
+/// Put `branches` in groups by their gitflow role, each under a header. Within a group, the
+/// branches keep their order.
+pub fn grouped(gitflow: &Gitflow, branches: Vec<Branch>) -> Vec<Arc<dyn SkimItem>> {
+    let branches: Vec<(Role, Branch)> = branches
+        .into_iter()
+        .map(|branch| {
+            let role = match &branch {
+                Branch::Local(local_branch) => gitflow.role(&local_branch.name),
+                Branch::Remote(remote_branch) => match remote_branch.name.split_once('/') {
+                    Some((_, name)) => gitflow.role(name),
+                    None => Role::Other,
+                },
+                Branch::Ref(_) => Role::Other,
+            };
+            (role, branch)
+        })
+        .collect();
+
+    group::grouped(branches, Role::label)
+}
+
+/// Where a new branch `name` starts from: the local base branch of its role, or the base branch
+/// of a remote when there is no local one. `None` for branches that have no gitflow role.
+pub fn start_point(meta: &RepoMeta, gitflow: &Gitflow, name: &str) -> Result<Option<String>> {
+    let repo = meta.repo();
+    let base = match gitflow.base(gitflow.role(name)) {
+        Some(base) => base,
+        None => return Ok(None),
+    };
+    if repo.find_branch(base, BranchType::Local).is_ok() {
+        return Ok(Some(base.to_string()));
+    }
+
+    for remote in meta.remotes() {
+        let remote_base = format!("{}/{}", remote, base);
+        if repo.find_branch(&remote_base, BranchType::Remote).is_ok() {
+            return Ok(Some(remote_base));
+        }
+    }
+
+    bail!("There is no {} branch to start {} from", base, name)
+}
+
+/// Merge the feature, release or hotfix branch `name` into its targets with merge commits, then
+/// delete it. HEAD is left on the last target. Everything is undone when a merge fails.
+pub fn finish(
+    repo: &Repository,
+    workdir: &Path,
+    gitflow: &Gitflow,
+    name: &str,
+    skip_hooks: bool,
+    options: &PickerOptions,
+) -> Result<()> {
+    let targets = gitflow.targets(gitflow.role(name));
+    if targets.is_empty() {
+        bail!("{} is not a feature, release or hotfix branch", name);
+    }
+    let oid = repo
+        .refname_to_id(&format!("refs/heads/{}", name))
+        .with_context(|| format!("{} is not a local branch", name))?;
+
+    let loss = Loss::branch(repo, name);
+    let mut transaction = Transaction::begin(repo, workdir, &format!("finish {}", name));
+    if skip_hooks {
+        transaction.skip_hooks();
+    }
+    for target in targets {
+        let target_oid = repo
+            .refname_to_id(&format!("refs/heads/{}", target))
+            .with_context(|| format!("There is no {} branch to merge {} into", target, name))?;
+        transaction.checkout(&format!("check out {}", target), &[target])?;
+
+        let message = format!("Merge branch '{}' into {}", name, target);
+        let undo = vec![
+            vec!["checkout".to_string(), target.to_string()],
+            vec![
+                "reset".to_string(),
+                "--hard".to_string(),
+                target_oid.to_string(),
+            ],
+        ];
+        let what = format!("merge {} into {}", name, target);
+        let merged = transaction.run(
+            &what,
+            &["merge", "--no-ff", "-m", &message, name],
+            undo.clone(),
+        );
+        if let Err(err) = merged {
+            resolve::conclude(&mut transaction, repo, workdir, &what, undo, options, err)?;
+        }
+        eprintln!("Merged {} into {}", name, target);
+    }
+
+    transaction.run(
+        &format!("delete {}", name),
+        &["branch", "-d", name],
+        vec![vec![
+            "branch".to_string(),
+            name.to_string(),
+            oid.to_string(),
+        ]],
+    )?;
+    eprintln!("Deleted {}", name);
+    transaction.commit()?;
+    audit::record(repo, loss);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Oid;
+
+    fn gitflow() -> Gitflow {
+        Gitflow {
+            main: "main".to_string(),
+            develop: "develop".to_string(),
+            feature_prefix: "feature/".to_string(),
+            release_prefix: "release/".to_string(),
+            hotfix_prefix: "hotfix/".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_roles() {
+        let gitflow = gitflow();
+        assert_eq!(gitflow.role("main"), Role::Main);
+        assert_eq!(gitflow.role("feature/login"), Role::Feature);
+        assert_eq!(gitflow.role("hotfix/1.2.1"), Role::Hotfix);
+        assert_eq!(gitflow.role("experiment"), Role::Other);
+        assert_eq!(gitflow.base(Role::Feature), Some("develop"));
+        assert_eq!(gitflow.base(Role::Hotfix), Some("main"));
+        assert_eq!(gitflow.base(Role::Other), None);
+        assert_eq!(gitflow.targets(Role::Feature), vec!["develop"]);
+        assert_eq!(gitflow.targets(Role::Release), vec!["main", "develop"]);
+        assert!(gitflow.targets(Role::Develop).is_empty());
+    }
+
+    /// `feature/login` checked out a commit ahead of `main` and `develop`. Returns the base and
+    /// the tip of the feature.
+    fn feature(name: &str) -> (TempRepo, Oid, Oid) {
+        let repo = TempRepo::new(name);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "base",
+            &[("base", "base")],
+            &[],
+        );
+        repo.reference("refs/heads/develop", base, false, "test")
+            .unwrap();
+        let feature = testing::commit(
+            &repo,
+            Some("refs/heads/feature/login"),
+            "login",
+            &[("login", "login")],
+            &[base],
+        );
+        repo.set_head("refs/heads/feature/login").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        (repo, base, feature)
+    }
+
+    #[test]
+    fn test_start_point() {
+        let (repo, _, _) = feature("gitflow-start");
+        assert_eq!(
+            start_point(&RepoMeta::new(&repo), &gitflow(), "feature/signup").unwrap(),
+            Some("develop".to_string())
+        );
+    }
+
+    #[test]
+    fn test_finish_other_branch() {
+        let (repo, _, _) = feature("gitflow-other");
+        let options = PickerOptions::default();
+        assert!(finish(&repo, repo.dir(), &gitflow(), "experiment", false, &options).is_err());
+    }
+
+    #[test]
+    fn test_finish_feature() {
+        let (repo, base, feature) = feature("gitflow");
+        let options = PickerOptions::default();
+        finish(
+            &repo,
+            repo.dir(),
+            &gitflow(),
+            "feature/login",
+            false,
+            &options,
+        )
+        .unwrap();
+        let develop = repo.refname_to_id("refs/heads/develop").unwrap();
+        assert!(repo.graph_descendant_of(develop, feature).unwrap());
+        assert_eq!(repo.refname_to_id("refs/heads/main").unwrap(), base);
+        assert!(repo
+            .find_branch("feature/login", BranchType::Local)
+            .is_err());
+        assert_eq!(repo.head().unwrap().shorthand(), Some("develop"));
+    }
+}
@@ -0,0 +1,55 @@
+//! Section headers that split the branches in the picker into groups.
+use crate::skim::prelude::*;
+use crate::Branch;
+use tuikit::attr::Effect;
+
+/// A section header in the picker, which can't be picked
+struct GroupHeader {
+    label: &'static str,
+}
+
+impl SkimItem for GroupHeader {
+    /// Nothing to match, so the headers are hidden while searching
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed("")
+    }
+
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let mut display = AnsiString::from("");
+        let attr = if context.accessible {
+            Attr::default()
+        } else {
+            Attr {
+                effect: Effect::BOLD,
+                ..Attr::default()
+            }
+        };
+        display.append(&format!("{}:", self.label), attr);
+        display
+    }
+
+    fn is_selectable(&self) -> bool {
+        false
+    }
+}
+
+/// Put the branches in groups of the same key, ordered by key, each under a header labelled by
+/// `label`. Within a group, the branches keep their order.
+pub fn grouped<K: Ord + Copy>(
+    mut branches: Vec<(K, Branch)>,
+    label: impl Fn(K) -> &'static str,
+) -> Vec<Arc<dyn SkimItem>> {
+    branches.sort_by_key(|(key, _)| *key);
+
+    let mut items: Vec<Arc<dyn SkimItem>> = vec![];
+    let mut current = None;
+    for (key, branch) in branches {
+        if current != Some(key) {
+            current = Some(key);
+            items.push(Arc::new(GroupHeader { label: label(key) }));
+        }
+        items.push(Arc::new(branch));
+    }
+
+    items
+}
@@ -0,0 +1,91 @@
+//! Running without a terminal, or from within git: in a hook, e.g. a `post-checkout` hook calling
+//! `gibra --filter`, or in `git rebase --exec`. The picker draws on the controlling terminal, which
+//! hooks run by an editor or cron don't have, and switching branches needs the locks git holds
+//! while it runs a hook, and would derail a rebase. These are checked up front, so that gibra
+//! fails at once telling why and what to run instead, rather than panicking or waiting on a lock.
+use anyhow::{bail, Result};
+use git2::Repository;
+use std::{env, fs, path::PathBuf};
+
+/// The terminal the picker draws on and reads the keys from, whatever stdin and stdout are
+pub const TTY: &str = "/dev/tty";
+
+/// The lock files git holds while it changes the working tree or HEAD
+const LOCKS: [&str; 2] = ["index.lock", "HEAD.lock"];
+
+/// The git command gibra runs within
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Within {
+    Hook,
+    RebaseExec,
+}
+
+impl Within {
+    pub fn current() -> Option<Self> {
+        Self::from_env(|name| env::var_os(name).is_some())
+    }
+
+    /// From the variables git sets for what it runs, by whether `is_set`. Aliases run with
+    /// neither, and work as from the shell.
+    fn from_env(is_set: impl Fn(&str) -> bool) -> Option<Self> {
+        if is_set("GIT_CHERRY_PICK_HELP") {
+            Some(Self::RebaseExec)
+        } else if is_set("GIT_INDEX_FILE") {
+            Some(Self::Hook)
+        } else {
+            None
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Hook => "a git hook",
+            Self::RebaseExec => "git rebase --exec",
+        }
+    }
+}
+
+/// Fail when the picker can't be shown, as there is no terminal to draw it on
+pub fn ensure_terminal() -> Result<()> {
+    let err = match fs::OpenOptions::new().read(true).write(true).open(TTY) {
+        Ok(_) => return Ok(()),
+        Err(err) => err,
+    };
+    let within = Within::current()
+        .map(|within| format!(", as gibra runs within {}", within.describe()))
+        .unwrap_or_default();
+    bail!(
+        "The picker needs a terminal, but {} can't be opened ({}){}. Pass --filter QUERY to print the matching branches instead",
+        TTY,
+        err,
+        within
+    )
+}
+
+/// The lock git holds on the repository, e.g. `.git/index.lock` while it runs `pre-commit`
+pub fn held_lock(repo: &Repository) -> Option<PathBuf> {
+    LOCKS
+        .iter()
+        .map(|lock| repo.path().join(lock))
+        .find(|path| path.exists())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_within() {
+        let within = |set: &[&str]| Within::from_env(|name| set.contains(&name));
+        assert_eq!(within(&[]), None);
+        assert_eq!(within(&["GIT_EXEC_PATH"]), None);
+        assert_eq!(
+            within(&["GIT_EXEC_PATH", "GIT_INDEX_FILE"]),
+            Some(Within::Hook)
+        );
+        assert_eq!(
+            within(&["GIT_EXEC_PATH", "GIT_CHERRY_PICK_HELP"]),
+            Some(Within::RebaseExec)
+        );
+    }
+}
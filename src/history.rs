@@ -0,0 +1,345 @@
+//! Checking out a branch as it was at a past point (`--at`), picked from the places its reflog
+//! recorded and from its commits, and branching off one of its commits, e.g. to start again from
+//! before a regression.
+use crate::error::GibraError;
+use crate::skim::{event::Event, prelude::*};
+use crate::transaction::Transaction;
+use crate::{keymap, naming, prompt, Branch, PickerOptions};
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local, TimeZone};
+use git2::{Oid, Repository, Sort};
+use std::{collections::HashSet, path::Path};
+
+/// How many commits are listed besides the reflog
+const COMMIT_LIMIT: usize = 500;
+
+/// A commit the branch pointed to at `time`
+#[derive(Clone, Debug)]
+struct PastPoint {
+    oid: Oid,
+    time: i64,
+    summary: String,
+    /// What moved the branch there, e.g. `rebase (finish)`, when it comes from the reflog
+    reflog: Option<String>,
+    text: String,
+}
+
+impl PastPoint {
+    fn new(oid: Oid, time: i64, summary: String, reflog: Option<String>) -> Self {
+        Self {
+            oid,
+            time,
+            summary,
+            reflog,
+            text: String::new(),
+        }
+    }
+
+    fn render(mut self, now: DateTime<Local>) -> Self {
+        let time = Local.timestamp_opt(self.time, 0).single().unwrap_or(now);
+        let mut oid = self.oid.to_string();
+        oid.truncate(7);
+        self.text = format!(
+            "{:<15} {}  {}  {}",
+            relative(time, now),
+            time.format("%a %Y-%m-%d %H:%M"),
+            oid,
+            self.summary
+        );
+        if let Some(reflog) = &self.reflog {
+            self.text.push_str(&format!("  [{}]", reflog));
+        }
+        self
+    }
+}
+
+impl SkimItem for PastPoint {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.text)
+    }
+}
+
+/// `time` relative to `now`, e.g. `3 hours ago`, `yesterday` or `last Tuesday`
+pub fn relative(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    let elapsed = now - time;
+    let plural = |count: i64, unit: &str| {
+        if count == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", count, unit)
+        }
+    };
+    let days = (now.date_naive() - time.date_naive()).num_days();
+
+    if elapsed < Duration::minutes(1) {
+        "just now".to_string()
+    } else if elapsed < Duration::hours(1) {
+        plural(elapsed.num_minutes(), "minute")
+    } else if days == 0 {
+        plural(elapsed.num_hours(), "hour")
+    } else if days == 1 {
+        "yesterday".to_string()
+    } else if days < 7 {
+        format!("last {}", time.format("%A"))
+    } else if days < 35 {
+        plural(days / 7, "week")
+    } else if days < 365 {
+        plural(days / 30, "month")
+    } else {
+        plural(days / 365, "year")
+    }
+}
+
+/// Where `refname` pointed to according to its reflog, and its first-parent history, newest
+/// first. A commit is listed once, at the time the branch last pointed to it.
+fn past_points(repo: &Repository, refname: &str) -> Result<Vec<PastPoint>> {
+    let mut points = vec![];
+
+    if let Ok(reflog) = repo.reflog(refname) {
+        for entry in reflog.iter() {
+            let oid = entry.id_new();
+            let summary = match repo.find_commit(oid) {
+                Ok(commit) => commit.summary().unwrap_or("").to_string(),
+                Err(_) => continue,
+            };
+            let reflog = entry
+                .message()
+                .and_then(|message| message.split(':').next())
+                .map(|action| action.trim().to_string());
+            points.push(PastPoint::new(
+                oid,
+                entry.committer().when().seconds(),
+                summary,
+                reflog,
+            ));
+        }
+    }
+
+    let tip = repo
+        .refname_to_id(refname)
+        .map_err(|_| GibraError::RefMissing {
+            name: refname.to_string(),
+        })?;
+    let mut walk = repo.revwalk().with_context(|| "Failed to walk history")?;
+    walk.push(tip)?;
+    walk.simplify_first_parent()?;
+    for oid in walk.take(COMMIT_LIMIT).flatten() {
+        let commit = repo.find_commit(oid)?;
+        points.push(PastPoint::new(
+            oid,
+            commit.time().seconds(),
+            commit.summary().unwrap_or("").to_string(),
+            None,
+        ));
+    }
+
+    points.sort_by_key(|point| std::cmp::Reverse(point.time));
+    let mut seen = HashSet::new();
+    points.retain(|point| seen.insert(point.oid));
+    Ok(points)
+}
+
+/// The commits of `refname`, those of the branches merged into it included, newest first
+fn commits(repo: &Repository, refname: &str) -> Result<Vec<PastPoint>> {
+    let tip = repo
+        .refname_to_id(refname)
+        .map_err(|_| GibraError::RefMissing {
+            name: refname.to_string(),
+        })?;
+    let mut walk = repo.revwalk().with_context(|| "Failed to walk history")?;
+    walk.set_sorting(Sort::TIME)?;
+    walk.push(tip)?;
+    walk.take(COMMIT_LIMIT)
+        .flatten()
+        .map(|oid| {
+            let commit = repo.find_commit(oid)?;
+            Ok(PastPoint::new(
+                oid,
+                commit.time().seconds(),
+                commit.summary().unwrap_or("").to_string(),
+                None,
+            ))
+        })
+        .collect()
+}
+
+fn select_point(
+    points: Vec<PastPoint>,
+    header: &str,
+    options: &PickerOptions,
+) -> Result<PastPoint> {
+    let now = Local::now();
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for point in points {
+        let _ = tx.send(Arc::new(point.render(now)));
+    }
+    drop(tx);
+
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, options.keys.keymap, vec![]);
+    options.theme.apply(&mut builder);
+    let skim_options = builder
+        .header(Some(header))
+        .accessible(options.accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    Skim::run_with(&skim_options, Some(rx))
+        .map(|out| match out.final_event {
+            Event::EvActAbort => std::process::exit(130),
+            _ => out.selected_items,
+        })
+        .unwrap_or_default()
+        .first()
+        .and_then(|selected_item| (**selected_item).as_any().downcast_ref::<PastPoint>())
+        .cloned()
+        .with_context(|| "Failed to get selected commit")
+}
+
+/// The name of `branch` and its refname
+fn names(branch: &Branch) -> (&str, String) {
+    match branch {
+        Branch::Local(branch) => (&branch.name, format!("refs/heads/{}", branch.name)),
+        Branch::Remote(branch) => (&branch.name, format!("refs/remotes/{}", branch.name)),
+        Branch::Ref(reference) => (&reference.name, reference.refname.clone()),
+    }
+}
+
+/// A checkout following the `[checkout]` config
+fn begin<'a>(
+    repo: &'a Repository,
+    workdir: &Path,
+    options: &PickerOptions,
+) -> Result<Transaction<'a>> {
+    let mut transaction = Transaction::begin(repo, workdir, "checkout");
+    if options.checkout.skip_hooks {
+        transaction.skip_hooks();
+    }
+    if options.checkout.autostash {
+        transaction.stash()?;
+    }
+    Ok(transaction)
+}
+
+/// Pick a past point of `branch` and check it out, detached or as a new branch
+pub fn run(
+    repo: &Repository,
+    workdir: &Path,
+    branch: &Branch,
+    options: &PickerOptions,
+) -> Result<()> {
+    let (name, refname) = names(branch);
+    let header = format!("Check out {} as of", name);
+    let point = select_point(past_points(repo, &refname)?, &header, options)?;
+    let oid = point.oid.to_string();
+
+    let new_branch = prompt::input(&format!(
+        "Name a new branch at {} (leave empty to detach HEAD):",
+        &oid[..7]
+    ))?;
+
+    let mut transaction = begin(repo, workdir, options)?;
+    if new_branch.is_empty() {
+        transaction.checkout(&format!("check out {}", oid), &["--detach", &oid])?;
+    } else {
+        transaction.checkout(
+            &format!("create {} at {}", new_branch, oid),
+            &["-b", &new_branch, &oid],
+        )?;
+    }
+    transaction.commit()
+}
+
+/// Pick a commit of `branch` and check out a new branch created there, asking for its name
+pub fn branch_off(
+    repo: &Repository,
+    workdir: &Path,
+    branch: &Branch,
+    options: &PickerOptions,
+) -> Result<()> {
+    let (name, refname) = names(branch);
+    let header = format!("Branch off {} at", name);
+    let point = select_point(commits(repo, &refname)?, &header, options)?;
+    let oid = point.oid.to_string();
+
+    let new_branch = prompt::input(&format!("Name the new branch at {}:", &oid[..7]))?;
+    if new_branch.is_empty() {
+        bail!("Cancelled, no branch was created");
+    }
+    naming::validate(&options.naming, &new_branch)?;
+
+    let mut transaction = begin(repo, workdir, options)?;
+    transaction.checkout(
+        &format!("create {} at {}", new_branch, oid),
+        &["-b", &new_branch, &oid],
+    )?;
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_relative() {
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 18, 0, 0).unwrap();
+        let at = |day, hour, minute| {
+            Local
+                .with_ymd_and_hms(2024, 5, day, hour, minute, 0)
+                .unwrap()
+        };
+        assert_eq!(relative(at(15, 17, 59), now), "1 minute ago");
+        assert_eq!(relative(at(15, 15, 0), now), "3 hours ago");
+        assert_eq!(relative(at(14, 23, 0), now), "yesterday");
+        assert_eq!(relative(at(9, 12, 0), now), "last Thursday");
+        assert_eq!(relative(at(1, 12, 0), now), "2 weeks ago");
+        assert_eq!(
+            relative(Local.with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap(), now),
+            "5 months ago"
+        );
+    }
+
+    #[test]
+    fn test_past_points() {
+        let repo = TempRepo::new("history");
+        let feature = Some("refs/heads/feature");
+        let first = testing::commit_at(&repo, feature, "first", 1_700_000_000, &[]);
+        let second = testing::commit_at(&repo, feature, "second", 1_700_000_100, &[first]);
+
+        let points = past_points(&repo, "refs/heads/feature").unwrap();
+        let oids: Vec<Oid> = points.iter().map(|point| point.oid).collect();
+        assert_eq!(oids, vec![second, first]);
+        assert_eq!(points[0].summary, "second");
+    }
+
+    #[test]
+    fn test_commits_of_merged_branches() {
+        let repo = TempRepo::new("branch-off");
+        let feature = Some("refs/heads/feature");
+        let first = testing::commit_at(&repo, feature, "first", 1_700_000_000, &[]);
+        let second = testing::commit_at(&repo, feature, "second", 1_700_000_100, &[first]);
+        // a commit of a branch merged into it, which isn't on the ref until the merge
+        let side = testing::commit_at(&repo, None, "side", 1_700_000_050, &[first]);
+        let merge = testing::commit_at(&repo, feature, "merge", 1_700_000_200, &[second, side]);
+
+        let oids: Vec<Oid> = commits(&repo, "refs/heads/feature")
+            .unwrap()
+            .iter()
+            .map(|point| point.oid)
+            .collect();
+        assert_eq!(oids, vec![merge, second, side, first]);
+    }
+
+    #[test]
+    fn test_commits_of_missing_ref() {
+        let repo = TempRepo::new("branch-off-missing");
+        let err = commits(&repo, "refs/heads/gone").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<GibraError>(),
+            Some(&GibraError::RefMissing {
+                name: "refs/heads/gone".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Running the hooks of a repository after gibra changed the working tree, the way git runs them.
+//!
+//! Checkouts run `post-checkout` from here instead of from within git, so that the hook runs the
+//! same whichever way the working tree is changed, and its output shows while it runs.
+use crate::template::Placeholders;
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// The directory shared by all worktrees of the repository, where the hooks are by default
+pub fn common_dir(repo: &Repository) -> PathBuf {
+    let git_dir = repo.path();
+    if !repo.is_worktree() {
+        return git_dir.to_path_buf();
+    }
+
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+/// `core.hooksPath`, relative to `workdir` like git resolves it, or the `hooks` directory
+pub fn hooks_dir(repo: &Repository, workdir: &Path) -> PathBuf {
+    let configured = repo
+        .config()
+        .ok()
+        .and_then(|config| config.get_path("core.hooksPath").ok());
+
+    match configured {
+        Some(dir) => workdir.join(dir),
+        None => common_dir(repo).join("hooks"),
+    }
+}
+
+#[cfg(unix)]
+pub fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+pub fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Run the hook `name` with `args` in `workdir`, if the repository has it. Like git does, the
+/// output of the hook goes to stderr as it is written.
+pub fn run(repo: &Repository, workdir: &Path, name: &str, args: &[&str]) -> Result<()> {
+    let path = hooks_dir(repo, workdir).join(name);
+    if !is_executable(&path) {
+        return Ok(());
+    }
+
+    // hooks are shell scripts more often than not, which Windows can't run by themselves
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("sh");
+        command.arg(&path);
+        command
+    } else {
+        Command::new(&path)
+    };
+    let status = command
+        .args(args)
+        .envs(Placeholders::of(repo).env())
+        .current_dir(workdir)
+        .stdin(Stdio::null())
+        .stdout(io::stderr())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("Failed to run the {} hook", name))?;
+    if !status.success() {
+        bail!("The {} hook failed ({})", name, status);
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Install a hook `name` that appends its arguments to `<name>.log` in the working tree
+    fn install_logging_hook(repo: &Repository, name: &str) -> PathBuf {
+        let dir = repo.path().join("hooks");
+        fs::create_dir_all(&dir).unwrap();
+        let hook = dir.join(name);
+        fs::write(&hook, format!("#!/bin/sh\necho \"$@\" >> {}.log\n", name)).unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        repo.workdir().unwrap().join(format!("{}.log", name))
+    }
+
+    #[test]
+    fn test_run() {
+        let repo = TempRepo::new("hooks");
+        let dir = repo.dir();
+
+        run(&repo, dir, "post-checkout", &["a", "b", "1"]).unwrap();
+
+        let log = install_logging_hook(&repo, "post-checkout");
+        run(&repo, dir, "post-checkout", &["a", "b", "1"]).unwrap();
+        assert_eq!(fs::read_to_string(&log).unwrap(), "a b 1\n");
+
+        let failing = repo.path().join("hooks").join("post-merge");
+        fs::write(&failing, "#!/bin/sh\nexit 3\n").unwrap();
+        fs::set_permissions(&failing, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(run(&repo, dir, "post-merge", &["0"]).is_err());
+    }
+}
@@ -0,0 +1,329 @@
+//! Creating branches for the issues assigned to you on GitHub or Jira.
+use crate::config::{self, GithubConfig, IssuesConfig, JiraConfig, Keymap, NamingConfig};
+use crate::context::RepoContext;
+use crate::keymap;
+use crate::naming;
+use crate::remote;
+use crate::secrets::{self, Provider};
+use crate::skim::{event::Event, prelude::*};
+use crate::template;
+use crate::PickerOptions;
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_FILE_NAME: &str = "issues.toml";
+
+/// How long a title may get in a branch name before it is cut at a word boundary
+const MAX_TITLE_LEN: usize = 50;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Issue {
+    /// `ABC-123` for Jira, the number for GitHub
+    key: String,
+    title: String,
+    /// Where the issue lives, e.g. `owner/repo` for GitHub, shown next to the title
+    source: String,
+}
+
+impl SkimItem for Issue {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("{} {} ({})", self.key, self.title, self.source))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IssueCache {
+    fetched_at: u64,
+    issues: Vec<Issue>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+fn load_cache() -> Option<IssueCache> {
+    let content = fs::read_to_string(cache_path()?).ok()?;
+    toml::from_str(&content).ok()
+}
+
+fn save_cache(issues: &[Issue]) -> Result<()> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let cache = IssueCache {
+        fetched_at: now(),
+        issues: issues.to_vec(),
+    };
+    let content = toml::to_string(&cache).with_context(|| "Failed to serialize cache")?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct GithubIssue {
+    number: u64,
+    title: String,
+    repository: Option<GithubRepository>,
+    pull_request: Option<serde::de::IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct GithubRepository {
+    full_name: String,
+}
+
+/// The open issues assigned to the owner of the token, across all repositories
+fn fetch_github(agent: &ureq::Agent, config: &GithubConfig) -> Result<Vec<Issue>> {
+    let token = secrets::token(Provider::Github, config.token.as_ref())?;
+    let issues: Vec<GithubIssue> = agent
+        .get(&format!("{}/issues", config.api_url.trim_end_matches('/')))
+        .query("filter", "assigned")
+        .query("state", "open")
+        .query("per_page", "100")
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &format!("Bearer {}", token.expose()))
+        .call()
+        .with_context(|| "Failed to fetch issues from GitHub")?
+        .into_json()
+        .with_context(|| "Failed to parse issues from GitHub")?;
+
+    Ok(issues
+        .into_iter()
+        .filter(|issue| issue.pull_request.is_none())
+        .map(|issue| Issue {
+            key: issue.number.to_string(),
+            title: issue.title,
+            source: issue
+                .repository
+                .map(|repository| repository.full_name)
+                .unwrap_or_else(|| "GitHub".to_string()),
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct JiraSearch {
+    issues: Vec<JiraIssue>,
+}
+
+#[derive(Deserialize)]
+struct JiraIssue {
+    key: String,
+    fields: JiraFields,
+}
+
+#[derive(Deserialize)]
+struct JiraFields {
+    summary: String,
+}
+
+/// The unresolved issues assigned to the owner of the token, most recently updated first
+fn fetch_jira(agent: &ureq::Agent, config: &JiraConfig) -> Result<Vec<Issue>> {
+    let token = secrets::token(Provider::Jira, config.token.as_ref())?;
+    let authorization = match &config.email {
+        Some(email) => format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", email, token.expose()))
+        ),
+        None => format!("Bearer {}", token.expose()),
+    };
+
+    let search: JiraSearch = agent
+        .get(&format!(
+            "{}/rest/api/2/search",
+            config.url.trim_end_matches('/')
+        ))
+        .query(
+            "jql",
+            "assignee = currentUser() AND resolution = Unresolved ORDER BY updated DESC",
+        )
+        .query("fields", "summary")
+        .query("maxResults", "100")
+        .set("Accept", "application/json")
+        .set("Authorization", &authorization)
+        .call()
+        .with_context(|| "Failed to fetch issues from Jira")?
+        .into_json()
+        .with_context(|| "Failed to parse issues from Jira")?;
+
+    Ok(search
+        .issues
+        .into_iter()
+        .map(|issue| Issue {
+            key: issue.key,
+            title: issue.fields.summary,
+            source: "Jira".to_string(),
+        })
+        .collect())
+}
+
+fn fetch(config: &IssuesConfig) -> Result<Vec<Issue>> {
+    let agent = remote::http_agent();
+    let mut issues = vec![];
+    if let Some(github) = &config.github {
+        issues.extend(fetch_github(&agent, github)?);
+    }
+    if let Some(jira) = &config.jira {
+        issues.extend(fetch_jira(&agent, jira)?);
+    }
+
+    Ok(issues)
+}
+
+/// The assigned issues, from the cache while it is fresh. When fetching fails, e.g. while
+/// offline, the cached issues are used no matter how old they are.
+fn issues(config: &IssuesConfig, refresh: bool) -> Result<Vec<Issue>> {
+    let cache = load_cache();
+    if let Some(cache) = &cache {
+        let is_fresh = now().saturating_sub(cache.fetched_at) < config.cache_ttl;
+        if is_fresh && !refresh {
+            return Ok(cache.issues.clone());
+        }
+    }
+
+    match fetch(config) {
+        Ok(issues) => {
+            save_cache(&issues)?;
+            Ok(issues)
+        }
+        Err(err) => match cache {
+            Some(cache) => {
+                eprintln!("warning: {:#}, showing the cached issues", err);
+                Ok(cache.issues)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// Turn an issue title into something that fits in a branch name, e.g. `Fix "login" page` becomes
+/// `fix-login-page`.
+fn slugify(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_TITLE_LEN {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word);
+    }
+
+    slug
+}
+
+fn branch_name(template: &str, issue: &Issue) -> String {
+    template::expand(
+        template,
+        &[("key", issue.key.clone()), ("title", slugify(&issue.title))],
+    )
+}
+
+fn select_issue(issues: Vec<Issue>, accessible: bool, keymap: Keymap) -> Result<Issue> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for issue in issues {
+        let _ = tx.send(Arc::new(issue));
+    }
+    drop(tx);
+
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, keymap, vec![]);
+    let options = builder
+        .header(Some("Select an issue"))
+        .accessible(accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    Skim::run_with(&options, Some(rx))
+        .map(|out| match out.final_event {
+            Event::EvActAbort => std::process::exit(130),
+            _ => out.selected_items,
+        })
+        .unwrap_or_default()
+        .first()
+        .and_then(|selected_item| (**selected_item).as_any().downcast_ref::<Issue>())
+        .cloned()
+        .with_context(|| "Failed to get selected issue")
+}
+
+/// Pick one of the issues assigned to you, then create a branch named after it and check it out.
+///
+/// If the branch already exists, it is checked out instead.
+pub fn run(
+    config: &IssuesConfig,
+    naming_config: &NamingConfig,
+    context: &RepoContext,
+    options: &PickerOptions,
+    refresh: bool,
+) -> Result<()> {
+    if config.github.is_none() && config.jira.is_none() {
+        bail!(
+            "No issue tracker configured. Add one to the config file:\n\n[issues.github]\n\nand log in with `gibra auth login github`"
+        );
+    }
+
+    let issues = issues(config, refresh)?;
+    if issues.is_empty() {
+        bail!("No issues are assigned to you");
+    }
+
+    let issue = select_issue(issues, options.accessible, options.keys.keymap)?;
+    let name = branch_name(&config.branch_template, &issue);
+    naming::validate(naming_config, &name)?;
+
+    crate::create_or_checkout_branch(context, &name, &options.checkout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Fix \"login\" page"), "fix-login-page");
+        assert_eq!(slugify("  Über-Größe: 2x  "), "über-größe-2x");
+        assert_eq!(
+            slugify("a very long title that goes on and on about everything it wants"),
+            "a-very-long-title-that-goes-on-and-on-about"
+        );
+    }
+
+    #[test]
+    fn test_branch_name() {
+        let issue = Issue {
+            key: "ABC-123".to_string(),
+            title: "Add login page".to_string(),
+            source: "Jira".to_string(),
+        };
+        assert_eq!(
+            branch_name("{key}-{title}", &issue),
+            "ABC-123-add-login-page"
+        );
+        assert_eq!(branch_name("feat/{key}", &issue), "feat/ABC-123");
+    }
+}
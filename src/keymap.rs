@@ -0,0 +1,21 @@
+//! The named sets of navigation keys of the pickers, chosen with `--keymap` or `keymap` under
+//! `[keys]`. The action keys are bound on top of them, so they win when both use a key.
+use crate::config::Keymap;
+use crate::skim::prelude::*;
+
+const EMACS_BINDINGS: [&str; 4] = [
+    "ctrl-k:kill-line",
+    "ctrl-w:backward-kill-word",
+    "ctrl-v:page-down",
+    "alt-v:page-up",
+];
+
+/// Set up `builder` for `keymap`, along with the other `bindings` of the picker
+pub fn apply<'a>(builder: &mut SkimOptionsBuilder<'a>, keymap: Keymap, bindings: Vec<&'a str>) {
+    // the bindings coming later win
+    let bindings = match keymap {
+        Keymap::Emacs => EMACS_BINDINGS.into_iter().chain(bindings).collect(),
+        _ => bindings,
+    };
+    builder.bind(bindings).vi_mode(keymap == Keymap::Vim);
+}
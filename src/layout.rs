@@ -0,0 +1,452 @@
+//! Fitting a branch and its columns into the width of the picker.
+//!
+//! As the terminal narrows, the subject is truncated and the optional columns are dropped one by
+//! one, least important first. A name that doesn't fit on its own loses its middle by default, so
+//! that both its prefix (`feature/`) and its suffix (a ticket number) stay visible; `truncate`
+//! under `[layout]` keeps its beginning or its end instead.
+//!
+//! Widths are counted in terminal columns, so that CJK characters and emojis, which take two,
+//! line up. Names are only cut between the symbols they draw, never between a letter and its
+//! accent or inside an emoji, and a match highlights the whole symbol.
+//!
+//! The age, upstream and subject columns are shown when git's `column.branch` or `column.ui` is
+//! `always` or `auto`, with the names padded so that the columns line up.
+use crate::config;
+use crate::history;
+use crate::lazy::{self, DetailStore};
+use crate::skim::prelude::*;
+use crate::Branch;
+use chrono::{DateTime, Local, TimeZone};
+use git2::Repository;
+use std::ops::Range;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+const ELLIPSIS: char = '…';
+
+/// Joins the emojis around it into one, e.g. a family
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+
+/// The modifiers giving an emoji a skin tone
+const SKIN_TONES: Range<char> = '\u{1f3fb}'..'\u{1f400}';
+
+/// Two of them make a flag, e.g. 🇯🇵
+const REGIONAL_INDICATORS: Range<char> = '\u{1f1e6}'..'\u{1f200}';
+
+/// A subject is dropped rather than truncated to less than this
+const MIN_SUBJECT_WIDTH: usize = 12;
+
+/// The names take at most this share of the width when they are padded for alignment
+const MAX_NAME_SHARE: usize = 2;
+
+/// The columns shown with `column.ui`, besides the name
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Details {
+    /// e.g. `3 days ago`
+    pub age: String,
+    pub subject: String,
+    /// The width the names are padded to, the widest of the listed ones
+    pub name_width: usize,
+}
+
+/// The details of a branch: attached when it was listed, or read when it is drawn for the
+/// gigantic ref sets, see [`lazy`]
+#[derive(Clone, Debug)]
+pub enum BranchDetails {
+    Attached(Details),
+    OnDemand(Arc<DetailStore>),
+}
+
+impl BranchDetails {
+    /// The details of the branch `refname`
+    pub fn get(&self, refname: &str) -> Option<Details> {
+        match self {
+            BranchDetails::Attached(details) => Some(details.clone()),
+            BranchDetails::OnDemand(store) => store.details(refname),
+        }
+    }
+}
+
+/// A column after the name. `priority` decides which columns are dropped first.
+pub struct Column {
+    pub text: String,
+    pub attr: Attr,
+    pub priority: u8,
+    /// Truncated to fit before it is dropped, like a commit subject
+    pub truncatable: bool,
+}
+
+impl Column {
+    pub fn new(text: String, attr: Attr, priority: u8) -> Self {
+        Self {
+            text,
+            attr,
+            priority,
+            truncatable: false,
+        }
+    }
+
+    pub fn truncatable(mut self) -> Self {
+        self.truncatable = true;
+        self
+    }
+}
+
+/// Whether git is configured to show branches in columns, see `git help config`
+pub fn columns_enabled(repo: &Repository) -> bool {
+    let config = match repo.config() {
+        Ok(config) => config,
+        Err(_) => return false,
+    };
+    let setting = config
+        .get_string("column.branch")
+        .or_else(|_| config.get_string("column.ui"))
+        .unwrap_or_default();
+
+    // the picker always draws on a terminal, so `auto` is the same as `always`
+    setting
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .any(|token| token == "always" || token == "auto")
+}
+
+/// The age and subject of the last commit of the branch `refname`, as of `now`
+pub fn details_of(
+    repo: &Repository,
+    refname: &str,
+    name_width: usize,
+    now: DateTime<Local>,
+) -> Option<Details> {
+    let commit = repo
+        .find_reference(refname)
+        .and_then(|reference| reference.peel_to_commit())
+        .ok()?;
+    let time = Local
+        .timestamp_opt(commit.time().seconds(), 0)
+        .single()
+        .unwrap_or(now);
+    Some(Details {
+        age: history::relative(time, now),
+        subject: commit.summary().unwrap_or("").to_string(),
+        name_width,
+    })
+}
+
+/// Attach the age and subject of the last commit of each branch, or beyond
+/// [`lazy::EAGER_LIMIT`] branches a store reading them when they are drawn
+pub fn attach_details(repo: &Repository, branches: &mut [Branch]) {
+    let now = Local::now();
+    let name_width = branches
+        .iter()
+        .map(|branch| branch.text().width())
+        .max()
+        .unwrap_or(0);
+    let store =
+        (branches.len() > lazy::EAGER_LIMIT).then(|| Arc::new(DetailStore::new(repo, name_width)));
+
+    for branch in branches {
+        let details = match &store {
+            Some(store) => Some(BranchDetails::OnDemand(store.clone())),
+            None => {
+                details_of(repo, &branch.refname(), name_width, now).map(BranchDetails::Attached)
+            }
+        };
+
+        match branch {
+            Branch::Local(branch) => branch.details = details,
+            Branch::Remote(branch) => branch.details = details,
+            Branch::Ref(reference) => reference.details = details,
+        }
+    }
+}
+
+impl From<config::Truncate> for Truncate {
+    fn from(truncate: config::Truncate) -> Self {
+        match truncate {
+            config::Truncate::Middle => Truncate::Middle,
+            config::Truncate::End => Truncate::End,
+            config::Truncate::Start => Truncate::Start,
+        }
+    }
+}
+
+/// The ranges of `chars` that are drawn as one symbol: a letter with its accents, an emoji with
+/// its skin tone or the emojis joined into one, and the two letters of a flag. A close enough
+/// take on Unicode's grapheme clusters for branch names.
+fn clusters(chars: &[char]) -> Vec<Range<usize>> {
+    let mut clusters: Vec<Range<usize>> = vec![];
+    for (index, &c) in chars.iter().enumerate() {
+        let joined = match clusters.last() {
+            Some(last) => {
+                let previous = chars[index - 1];
+                c.width() == Some(0)
+                    || previous == ZERO_WIDTH_JOINER
+                    || SKIN_TONES.contains(&c)
+                    || (REGIONAL_INDICATORS.contains(&c)
+                        && REGIONAL_INDICATORS.contains(&previous)
+                        && last.len() == 1)
+            }
+            None => false,
+        };
+        match clusters.last_mut() {
+            Some(last) if joined => last.end = index + 1,
+            _ => clusters.push(index..index + 1),
+        }
+    }
+    clusters
+}
+
+/// The columns `chars` take on the terminal, the way the picker draws them
+fn width_of(chars: &[char]) -> usize {
+    chars.iter().map(|c| c.width().unwrap_or(0)).sum()
+}
+
+/// `text` cut to `width` columns, ending with an ellipsis
+fn truncate_end(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut truncated = String::new();
+    let mut used = 0;
+    for cluster in clusters(&chars) {
+        let cluster_width = width_of(&chars[cluster.clone()]);
+        if used + cluster_width + 1 > width {
+            break;
+        }
+        used += cluster_width;
+        truncated.extend(&chars[cluster]);
+    }
+    truncated.truncate(truncated.trim_end().len());
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+/// The characters to keep of `text` to fit it in `width` columns with an ellipsis where
+/// `truncate` says: the first `head` and those from `tail` on. `None` when it fits.
+fn cut(text: &str, width: usize, truncate: Truncate) -> Option<(usize, usize)> {
+    if text.width() <= width {
+        return None;
+    }
+
+    let chars: Vec<char> = text.chars().collect();
+    let clusters = clusters(&chars);
+    let cluster_width = |index: usize| width_of(&chars[clusters[index].clone()]);
+    let budget = width.saturating_sub(1);
+    let head_budget = match truncate {
+        Truncate::Middle => budget.div_ceil(2),
+        Truncate::End => budget,
+        Truncate::Start => 0,
+    };
+
+    let mut used = 0;
+    let mut head = 0;
+    while head < clusters.len() && used + cluster_width(head) <= head_budget {
+        used += cluster_width(head);
+        head += 1;
+    }
+    let mut tail = clusters.len();
+    while truncate != Truncate::End && tail > head && used + cluster_width(tail - 1) <= budget {
+        used += cluster_width(tail - 1);
+        tail -= 1;
+    }
+
+    let start = |index: usize| {
+        clusters
+            .get(index)
+            .map_or(chars.len(), |cluster| cluster.start)
+    };
+    Some((start(head), start(tail)))
+}
+
+/// The characters of the text of `context` to highlight: those matching the query, along with
+/// the rest of the symbols they are part of
+fn highlighted(context: &DisplayContext, clusters: &[Range<usize>]) -> Vec<usize> {
+    let matched: Vec<usize> = match context.matches {
+        Matches::CharIndices(indices) => indices.to_vec(),
+        Matches::ByteRange(start, end) => {
+            let first = context.text[..start].chars().count();
+            (first..first + context.text[start..end].chars().count()).collect()
+        }
+        Matches::None => vec![],
+    };
+
+    clusters
+        .iter()
+        .filter(|cluster| matched.iter().any(|index| cluster.contains(index)))
+        .flat_map(|cluster| cluster.clone())
+        .collect()
+}
+
+/// Decide which `columns` fit next to a name `name_width` wide in `width`, truncating or
+/// dropping the least important ones. Returns the kept columns in their order.
+fn fit_columns(mut columns: Vec<Column>, name_width: usize, width: usize) -> Vec<Column> {
+    let total = |columns: &[Column]| {
+        name_width
+            + columns
+                .iter()
+                .map(|column| 1 + column.text.width())
+                .sum::<usize>()
+    };
+
+    while total(&columns) > width {
+        let least = match (0..columns.len()).min_by_key(|&index| columns[index].priority) {
+            Some(least) => least,
+            None => break,
+        };
+        let overflow = total(&columns) - width;
+        let column = &mut columns[least];
+        let column_width = column.text.width();
+        if column.truncatable && column_width >= overflow + MIN_SUBJECT_WIDTH {
+            column.text = truncate_end(&column.text, column_width - overflow);
+            column.truncatable = false;
+        } else {
+            columns.remove(least);
+        }
+    }
+
+    columns
+}
+
+/// Draw the branch name of `context` followed by the `columns` that fit. With `align`, the name
+/// is padded to that width, so that the columns of all branches line up.
+pub fn render<'a>(
+    context: DisplayContext<'a>,
+    align: Option<usize>,
+    columns: Vec<Column>,
+) -> AnsiString<'a> {
+    let width = context.container_width;
+    let name_width = context.text.width();
+    let slot = align
+        .unwrap_or(0)
+        .min(width / MAX_NAME_SHARE)
+        .max(name_width);
+    let columns = fit_columns(columns, slot, width);
+
+    let chars: Vec<char> = context.text.chars().collect();
+    let highlighted = highlighted(&context, &clusters(&chars));
+    let (text, highlighted) = match cut(context.text, width, context.truncate) {
+        None => (context.text.to_string(), highlighted),
+        Some((head, tail)) => {
+            let mut text: String = chars[..head].iter().collect();
+            text.push(ELLIPSIS);
+            text.extend(&chars[tail..]);
+
+            let highlighted = highlighted
+                .into_iter()
+                .filter_map(|index| match index {
+                    index if index < head => Some(index),
+                    index if index >= tail => Some(index - tail + head + 1),
+                    _ => None,
+                })
+                .collect();
+            (text, highlighted)
+        }
+    };
+    let fragments = highlighted
+        .into_iter()
+        .map(|index| (context.highlight_attr, (index as u32, index as u32 + 1)))
+        .collect();
+    let mut display = AnsiString::new_string(text, fragments);
+
+    if !columns.is_empty() && slot > name_width {
+        display.append(&" ".repeat(slot - name_width), Attr::default());
+    }
+    for column in columns {
+        display.append(&format!(" {}", column.text), column.attr);
+    }
+
+    display
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(columns: &[Column]) -> Vec<&str> {
+        columns.iter().map(|column| column.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fit_columns() {
+        let columns = || {
+            vec![
+                Column::new("↑2".to_string(), Attr::default(), 3),
+                Column::new("3 days ago".to_string(), Attr::default(), 1),
+                Column::new("Fix the login form".to_string(), Attr::default(), 0).truncatable(),
+            ]
+        };
+
+        assert_eq!(
+            texts(&fit_columns(columns(), 10, 80)),
+            vec!["↑2", "3 days ago", "Fix the login form"]
+        );
+        assert_eq!(
+            texts(&fit_columns(columns(), 10, 40)),
+            vec!["↑2", "3 days ago", "Fix the login…"]
+        );
+        assert_eq!(
+            texts(&fit_columns(columns(), 10, 30)),
+            vec!["↑2", "3 days ago"]
+        );
+        assert_eq!(texts(&fit_columns(columns(), 10, 15)), vec!["↑2"]);
+        assert!(fit_columns(columns(), 10, 12).is_empty());
+    }
+
+    fn cut_to(name: &str, width: usize, truncate: Truncate) -> String {
+        let (head, tail) = cut(name, width, truncate).unwrap();
+        let chars: Vec<char> = name.chars().collect();
+        chars[..head]
+            .iter()
+            .chain(&[ELLIPSIS])
+            .chain(&chars[tail..])
+            .collect()
+    }
+
+    #[test]
+    fn test_cut() {
+        let name = "feature/JIRA-1234-rework-login";
+        assert_eq!(cut(name, 40, Truncate::Middle), None);
+
+        let middle = cut_to(name, 16, Truncate::Middle);
+        assert_eq!(middle, "feature/…k-login");
+        assert_eq!(middle.width(), 16);
+        assert_eq!(cut_to(name, 16, Truncate::End), "feature/JIRA-12…");
+        assert_eq!(cut_to(name, 16, Truncate::Start), "…34-rework-login");
+
+        // wide characters are never split, the cut is a column short instead
+        let name = "feature/ログイン画面";
+        assert_eq!(cut_to(name, 14, Truncate::End), "feature/ログ…");
+        assert_eq!(cut_to(name, 13, Truncate::Start), "…ログイン画面");
+        assert_eq!(cut_to(name, 12, Truncate::Start), "…グイン画面");
+    }
+
+    #[test]
+    fn test_clusters() {
+        let chars: Vec<char> = "e\u{301}👍🏽👨\u{200d}👩🇯🇵🇫🇷x".chars().collect();
+        assert_eq!(
+            clusters(&chars),
+            vec![0..2, 2..4, 4..7, 7..9, 9..11, 11..12]
+        );
+        assert_eq!(truncate_end("fix/👨\u{200d}👩-bug", 7), "fix/…");
+        assert_eq!(truncate_end("fix/👨\u{200d}👩-bug", 9), "fix/👨\u{200d}👩…");
+    }
+
+    #[test]
+    fn test_render() {
+        let name = "feature/JIRA-1234-rework-login";
+        let indices = [0, 29];
+        let context = DisplayContext {
+            text: name,
+            score: 0,
+            matches: Matches::CharIndices(&indices),
+            container_width: 16,
+            highlight_attr: Attr::default(),
+            accessible: false,
+            truncate: Truncate::Middle,
+        };
+        let columns = vec![Column::new("↑2".to_string(), Attr::default(), 3)];
+
+        let display = render(context, Some(40), columns);
+        assert_eq!(display.stripped(), "feature/…k-login");
+    }
+}
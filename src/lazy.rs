@@ -0,0 +1,157 @@
+//! Gigantic ref sets, e.g. mirrors with 100k+ remote branches. Attaching the columns of
+//! `column.ui` to each listed branch reads the commit of every one of them and keeps all their
+//! subjects in memory, although only a screenful is ever drawn. Beyond [`EAGER_LIMIT`] branches,
+//! they share a [`DetailStore`] instead, which reads the details of the rows as they are drawn
+//! and keeps those of the [`CAPACITY`] rows drawn last, so that the memory stays flat however
+//! many refs there are.
+use crate::alternates;
+use crate::layout::{self, Details};
+use chrono::{DateTime, Local};
+use git2::Repository;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Up to this many branches, their details are attached when they are listed
+pub const EAGER_LIMIT: usize = 5000;
+
+/// How many rows keep their details once drawn, enough to scroll back through a few screens
+const CAPACITY: usize = 1024;
+
+/// A map keeping at most `capacity` entries, dropping the one used least recently to make room
+pub struct Lru<K, V> {
+    capacity: usize,
+    /// Counts the uses, telling which entry was used last
+    tick: u64,
+    entries: HashMap<K, (V, u64)>,
+}
+
+impl<K: Eq + Hash + Clone, V> Lru<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            tick: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.tick += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        *used = self.tick;
+        Some(value)
+    }
+
+    /// Add `value`, dropping the entry used least recently when full. It takes a pass over the
+    /// entries, which only happens when a row is drawn for the first time.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.tick += 1;
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Reads the details of the branches as they are drawn, by refname
+pub struct DetailStore {
+    git_dir: PathBuf,
+    /// The width the names are padded to, the widest of all listed ones
+    name_width: usize,
+    /// When the branches were listed, which the ages are counted from
+    now: DateTime<Local>,
+    /// Opened by the thread drawing the rows when it first needs it
+    repo: Mutex<Option<Repository>>,
+    cache: Mutex<Lru<String, Option<Details>>>,
+}
+
+impl DetailStore {
+    pub fn new(repo: &Repository, name_width: usize) -> Self {
+        Self {
+            git_dir: repo.path().to_path_buf(),
+            name_width,
+            now: Local::now(),
+            repo: Mutex::new(None),
+            cache: Mutex::new(Lru::new(CAPACITY)),
+        }
+    }
+
+    /// The details of the branch `refname`, `None` when it has no commit
+    pub fn details(&self, refname: &str) -> Option<Details> {
+        let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(details) = cache.get(&refname.to_string()) {
+            return details.clone();
+        }
+
+        let mut repo = self.repo.lock().unwrap_or_else(|err| err.into_inner());
+        if repo.is_none() {
+            *repo = alternates::open(&self.git_dir).ok();
+        }
+        let details = repo
+            .as_ref()
+            .and_then(|repo| layout::details_of(repo, refname, self.name_width, self.now));
+        cache.insert(refname.to_string(), details.clone());
+        details
+    }
+}
+
+impl fmt::Debug for DetailStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cached = self.cache.lock().map(|cache| cache.len()).unwrap_or(0);
+        f.debug_struct("DetailStore")
+            .field("git_dir", &self.git_dir)
+            .field("cached", &cached)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_lru() {
+        let mut lru = Lru::new(2);
+        lru.insert("a", 1);
+        lru.insert("b", 2);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        // b was used least recently
+        lru.insert("c", 3);
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&"b"), None);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        assert_eq!(lru.get(&"c"), Some(&3));
+        lru.insert("c", 4);
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(&"c"), Some(&4));
+    }
+
+    #[test]
+    fn test_detail_store() {
+        let repo = TempRepo::new("lazy");
+        let tip = testing::commit(&repo, None, "fix: the subject", &[], &[]);
+        repo.reference("refs/remotes/origin/feature", tip, false, "test")
+            .unwrap();
+
+        let store = DetailStore::new(&repo, 20);
+        let details = store.details("refs/remotes/origin/feature").unwrap();
+        assert_eq!(details.subject, "fix: the subject");
+        assert_eq!(details.name_width, 20);
+        assert_eq!(store.details("refs/remotes/origin/missing"), None);
+        assert_eq!(store.cache.lock().unwrap().len(), 2);
+    }
+}
@@ -3,76 +3,1186 @@ extern crate lazy_static;
 #[macro_use]
 extern crate log;
 
+use crate::action::Action;
+use crate::bus::Bus;
+use crate::config::{
+    Border, CheckoutConfig, Config, GitflowConfig, GithubConfig, InitialCursor, Keymap, KeysConfig,
+    NamingConfig, Operation, PoliciesConfig, SourceConfig, Truncate, WebConfig, WebView,
+};
+use crate::context::RepoContext;
+use crate::custom::CustomCommand;
+use crate::diffstat::DiffStat;
+use crate::empty::Widen;
+use crate::error::GibraError;
+use crate::fields::{Fields, Projection};
+use crate::gitflow::Gitflow;
+use crate::layout::{BranchDetails, Column};
+use crate::meta::{Base, RepoMeta};
+use crate::perf::Stage;
+use crate::policy::{Policy, Target};
+use crate::pulls::OpenPulls;
+use crate::secrets::Provider;
+use crate::shortcut::{NewBranch, Shortcut};
+use crate::signing::Signing;
+use crate::since::Since;
 use crate::skim::{event::Event, prelude::*};
-use anyhow::{Context, Result};
-use clap::Parser;
-use git2::{BranchType, Repository};
+use crate::tabs::Tab;
+use crate::template::{BranchValues, Format, Placeholders};
+use crate::theme::Theme;
+use crate::transaction::Transaction;
+use crate::upstream::Divergence;
+use crate::watch::{BranchCollector, Watcher};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use git2::{BranchType, Oid, Repository};
 use std::{
+    cell::RefCell,
+    cmp::Reverse,
     collections::HashMap,
-    path::PathBuf,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
 };
 
+mod action;
+mod activity;
+mod age;
+mod alternates;
+mod audit;
+mod budget;
+mod bus;
+mod collate;
+mod collision;
+mod config;
+mod conflicts;
+mod contains;
+mod context;
+mod cursor;
+mod custom;
+mod default_branch;
+mod delete;
+mod diffstat;
+mod discard;
+mod doctor;
+mod empty;
+mod error;
+mod exec;
+mod fields;
+mod git;
+mod gitflow;
+mod group;
+mod headless;
+mod history;
+mod hooks;
+mod issue;
+mod keymap;
+mod layout;
+mod lazy;
+mod maintenance;
+mod merge;
+mod meta;
+mod migrate;
+mod naming;
+mod owners;
+mod perf;
+mod policy;
+mod prefix;
+mod progress;
+mod prompt;
+mod pulls;
+mod push_remote;
+mod recency;
+mod refs;
+mod remote;
+mod rename;
+mod resolve;
+mod resume;
+mod review;
+mod scope;
+mod secrets;
+mod seed;
+mod segment;
+mod sets;
+mod shortcut;
+mod signing;
+mod since;
 mod skim;
+mod source;
+mod sparse;
+mod state;
+mod tabs;
+mod temp;
+mod template;
+#[cfg(test)]
+mod testing;
+mod theme;
+mod tmux;
+mod todos;
+mod transaction;
+mod unpushed;
+mod upstream;
+mod verify;
+mod watch;
+mod web;
+mod workspace;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
     /// Show only remote branches
-    #[clap(short = 'r', long)]
+    #[clap(short = 'r', long, global = true, conflicts_with = "local_only")]
     remote_only: bool,
 
     /// Show only local branches
-    #[clap(short = 'l', long)]
+    #[clap(short = 'l', long, global = true)]
     local_only: bool,
+
+    /// Fetch and prune all remotes before listing branches
+    #[clap(short = 'f', long, global = true)]
+    fetch: bool,
+
+    /// Fetch the full history first when the repository is a shallow clone, so that ahead/behind
+    /// counts and merge checks are accurate
+    #[clap(long, global = true)]
+    deepen: bool,
+
+    /// Pick a remote (or local) first, then only the branches from it
+    #[clap(long, global = true)]
+    by_remote: bool,
+
+    /// How the branches are ordered
+    #[clap(long, global = true, value_enum, default_value_t = SortOrder::Name)]
+    sort: SortOrder,
+
+    /// Show the picker in a tmux popup when running in tmux, e.g. `--tmux=80%` or `--tmux=80%,60%`
+    #[clap(
+        long,
+        global = true,
+        value_name = "SIZE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = tmux::DEFAULT_SIZE
+    )]
+    tmux: Option<String>,
+
+    /// Reload the branches while the picker is open when they change, checking every SECS seconds
+    #[clap(
+        long,
+        global = true,
+        value_name = "SECS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = watch::DEFAULT_INTERVAL,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    watch: Option<u64>,
+
+    /// Open the picker after at most MS milliseconds of counting how far the branches are ahead
+    /// and behind, showing `…` for the counts left and filling them in the background, like
+    /// `budget` under `[picker]`
+    #[clap(long, global = true, value_name = "MS")]
+    budget: Option<u64>,
+
+    /// Show tabs in the header of the picker: the branches, the tags, the stashes, the other
+    /// worktrees and the branches with an open pull request, switched with tab and shift-tab or
+    /// alt-1 to alt-5. Like `tabs` under `[picker]`
+    #[clap(long)]
+    tabs: bool,
+
+    /// The prompt of the picker, e.g. `--prompt '⎇ '`. `{repo}` and `{branch}` are replaced with
+    /// the repository name and the current branch
+    #[clap(long, global = true)]
+    prompt: Option<String>,
+
+    /// A header shown above the branches, e.g. `--header 'Switch branch (ctrl-d: delete)'`.
+    /// Supports the same placeholders as `--prompt`
+    #[clap(long, global = true)]
+    header: Option<String>,
+
+    /// Open the picked branch in the browser instead of checking it out, e.g. `--web=compare` for
+    /// its changes against the default branch
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        value_name = "VIEW",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "branch"
+    )]
+    web: Option<WebView>,
+
+    /// After picking a branch, pick a past point of it from its reflog and commits, e.g. to check
+    /// it out as it was last Tuesday. It is checked out detached, or as a new branch
+    #[clap(long, global = true)]
+    at: bool,
+
+    /// Check the picked branch out on a detached HEAD in a new throwaway worktree under the temp
+    /// directory, and print its path, leaving the working tree alone. `gibra temp --gc` removes
+    /// the old ones
+    #[clap(long, conflicts_with = "at")]
+    temp: bool,
+
+    /// Stash local changes before checking out and put them back afterwards, like `autostash`
+    /// under `[checkout]` in the config file
+    #[clap(long, global = true)]
+    autostash: bool,
+
+    /// Don't run the `post-checkout` and `post-merge` hooks of the repository, like `skip_hooks`
+    /// under `[checkout]` in the config file
+    #[clap(long, global = true)]
+    no_verify: bool,
+
+    /// Start new branches from the default branch of the remote, fetched right before, instead of
+    /// from HEAD, like `fresh_base` under `[checkout]` in the config file
+    #[clap(long, global = true)]
+    fresh_base: bool,
+
+    /// Switch branches even while a rebase, merge, cherry-pick or bisect is in progress, and
+    /// discard the local changes that are in the way of a checkout, after listing them
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Don't ask before discarding local changes with `--force`
+    #[clap(long, global = true)]
+    yes: bool,
+
+    /// Follow the gitflow branching model: group the branches by role, start new branches from
+    /// their base (e.g. `feature/*` from `develop`) and offer a key to finish them
+    #[clap(long, global = true)]
+    gitflow: bool,
+
+    /// Save the picked branches as a named set, e.g. `--save-set release-review`. Picking several
+    /// branches is enabled, and they are not checked out
+    #[clap(long, global = true, value_name = "NAME")]
+    save_set: Option<String>,
+
+    /// Pre-select the branches of a set saved with `--save-set`, e.g. to delete them or run
+    /// `gibra exec` on them
+    #[clap(long, global = true, value_name = "NAME")]
+    load_set: Option<String>,
+
+    /// Show the recent commits of the branch under the cursor and the files they changed. Press
+    /// alt-/ to search within them
+    #[clap(long, global = true)]
+    preview: bool,
+
+    /// Show the commits per week of the branch under the cursor over the last WEEKS weeks as a
+    /// sparkline at the top of the preview, e.g. `--activity=26`. Implies `--preview`
+    #[clap(
+        long,
+        global = true,
+        value_name = "WEEKS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = activity::DEFAULT_WEEKS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    activity: Option<usize>,
+
+    /// Show how much each branch changed compared to the default branch, e.g. `+1.2k/-300 · 14
+    /// files`. The sizes are computed in the background and cached
+    #[clap(long, global = true)]
+    size: bool,
+
+    /// Show whether the tip of each branch is signed, with GPG or SSH, by a key git trusts:
+    /// `✓ signed`, `✗ bad signature`, `? unknown key` or `unsigned`. The signatures are checked
+    /// in the background and cached
+    #[clap(long, global = true)]
+    signatures: bool,
+
+    /// Only list the branches whose tip is signed by a key git trusts, checking the signatures
+    /// before listing them
+    #[clap(long, global = true)]
+    verified_only: bool,
+
+    /// Show how long ago the tip of each branch was committed, e.g. `3d`, like `age` under
+    /// `[layout]`. alt-g switches to the dates and alt-w to the author time
+    #[clap(long, global = true)]
+    age: bool,
+
+    /// Compare the branches with REF, a branch, tag or commit, e.g. `--base origin/release-2.1`:
+    /// ahead/behind, `--size`, the preview, and which branches `clean` and `prefix` count as
+    /// merged, instead of their upstream, the default branch and HEAD
+    #[clap(long, global = true, value_name = "REF")]
+    base: Option<String>,
+
+    /// Keep the branches in the order they are listed while typing, e.g. newest first with `--sort
+    /// date`, instead of putting the best matches first
+    #[clap(long, global = true)]
+    no_sort: bool,
+
+    /// List the branches in reverse order
+    #[clap(long, global = true)]
+    tac: bool,
+
+    /// Only list the branches whose changes touch PATH, e.g. `--scope services/billing` in a
+    /// monorepo, and show which directories below it each branch touches
+    #[clap(long, global = true, value_name = "PATH")]
+    scope: Option<PathBuf>,
+
+    /// Show who owns the changes of each branch, after the CODEOWNERS file of the default branch
+    #[clap(long, global = true)]
+    owners: bool,
+
+    /// Only list the branches that change what OWNER owns in CODEOWNERS, e.g. `--owned-by
+    /// @acme/payments`, and show their owners
+    #[clap(long, global = true, value_name = "OWNER")]
+    owned_by: Option<String>,
+
+    /// Only list the branches that contain REF, a commit, tag or branch, e.g. `--contains v1.2` to
+    /// tell where a fix has landed. Can be given more than once to list those containing any
+    #[clap(long, global = true, value_name = "REF")]
+    contains: Vec<String>,
+
+    /// Only list the branches that don't contain REF. Can be given more than once to list those
+    /// containing none
+    #[clap(long, global = true, value_name = "REF")]
+    no_contains: Vec<String>,
+
+    /// Only list the branches committed to since WHEN, a date like `2024-05-01` or a duration
+    /// like `2w`, `3 days` or `6mo`
+    #[clap(long, global = true, value_name = "WHEN", value_parser = since::parse)]
+    active_since: Option<Since>,
+
+    /// Only list the branches not committed to since WHEN, e.g. `--inactive-since 3mo` to find
+    /// the abandoned ones
+    #[clap(long, global = true, value_name = "WHEN", value_parser = since::parse)]
+    inactive_since: Option<Since>,
+
+    /// Only list the local branches having commits that aren't pushed: those ahead of their
+    /// upstream, and those without one, which are marked `unpushed`
+    #[clap(long, global = true, conflicts_with = "remote_only")]
+    unpushed: bool,
+
+    /// List the branches on the remotes as they list them, like `git ls-remote`, rather than the
+    /// remote-tracking branches, and fetch just the picked one before checking it out. For
+    /// single-branch clones and remotes never fetched
+    #[clap(long, global = true, conflicts_with = "local_only")]
+    ls_remote: bool,
+
+    /// Also list the refs matching PATTERN besides the branches, e.g. `--ref-glob 'refs/pull/*/head'`,
+    /// to check them out on a detached HEAD. Can be given more than once. The changes of Gerrit
+    /// below `refs/changes/` are listed anyway, and with `GIT_NAMESPACE` set, the branches of that
+    /// namespace
+    #[clap(long, global = true, value_name = "PATTERN")]
+    ref_glob: Vec<String>,
+
+    /// Also list the pseudo-refs like `HEAD`, `FETCH_HEAD` and `MERGE_HEAD`, and the notes refs,
+    /// to see the state of the repository. Only the pseudo-refs can be checked out, on a
+    /// detached HEAD, and the actions run on several items leave them out
+    #[clap(long, global = true)]
+    special_refs: bool,
+
+    /// Only match the query against these fields of the names, separated by `/`, e.g. `--nth -1`
+    /// for the last part or `--nth 2..` to skip the prefix. Fields are ranges like `2`, `2..`,
+    /// `..-2` or `1..3`, separated by commas
+    #[clap(long, global = true, value_name = "FIELDS")]
+    nth: Option<String>,
+
+    /// Only show these fields of the names, e.g. `--with-nth 2..` to hide `feature/`. `--nth`
+    /// then counts the fields of what is shown
+    #[clap(long, global = true, value_name = "FIELDS")]
+    with_nth: Option<String>,
+
+    /// Print the branches matching QUERY, best match first, instead of opening the picker. Exits
+    /// with 11 when no branch matches
+    #[clap(long, value_name = "QUERY")]
+    filter: Option<String>,
+
+    /// Print each branch matched by `--filter` as TEMPLATE, e.g. `--print-format
+    /// '{name}\t{upstream}\t{sha}'`. The placeholders are {name}, {ref}, {kind}, {upstream},
+    /// {sha}, {short_sha}, {ahead}, {behind}, {repo} and {branch}, the checked out branch
+    #[clap(long, value_name = "TEMPLATE", requires = "filter")]
+    print_format: Option<String>,
+
+    /// Use a monochrome picker with text markers (`>>` cursor, `[x]` selected) for screen readers
+    #[clap(long, global = true)]
+    accessible: bool,
+
+    /// The navigation keys of the pickers: `vim` switches to moving with j/k, gg and G on esc,
+    /// `emacs` adds ctrl-k, ctrl-w, ctrl-v and alt-v. Overrides `keymap` under `[keys]`
+    #[clap(long, global = true, value_enum, value_name = "KEYMAP")]
+    keymap: Option<Keymap>,
+
+    /// Which part of a branch name too wide for the picker is cut: `middle` keeps its prefix and
+    /// suffix, `end` its beginning and `start` its end. Overrides `truncate` under `[layout]`
+    #[clap(long, global = true, value_enum, value_name = "PART")]
+    truncate: Option<Truncate>,
+
+    /// The colors of the pickers, as skim takes them, e.g. `preview_bg:#fdf6e3,diff_add:28`. The
+    /// preview has `preview_fg`, `preview_bg` and `preview_border`, and the diffs of the built-in
+    /// previews `diff_add` and `diff_remove`. Overrides `color` under `[layout]`
+    #[clap(long, global = true, value_name = "COLORS")]
+    color: Option<String>,
+
+    /// The border around the preview, instead of a line between it and the branches. Overrides
+    /// `border` under `[layout]`
+    #[clap(long, global = true, value_enum, value_name = "STYLE")]
+    border: Option<Border>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum SortOrder {
+    /// Alphabetically, local branches first
+    #[default]
+    Name,
+
+    /// By the date of the last commit, newest first
+    Date,
+
+    /// Alphabetically ignoring case, with numbers compared by value (`item2` < `item10`)
+    Natural,
+
+    /// By the date of the last commit, in groups: Today, This week and Older
+    Recent,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Pick a repository from the configured workspace roots, then switch branches within it
+    Ws {
+        /// Rescan the workspace roots instead of using the cached repository list
+        #[clap(long)]
+        rescan: bool,
+    },
+
+    /// Run a command with the picked branches substituted for `{}`
+    ///
+    /// With multiple picked branches, every argument containing `{}` is repeated once per branch.
+    /// A single argument is run by the shell, with the branch names quoted.
+    Exec {
+        /// The command to run, e.g. `gibra exec -- git diff {}..HEAD`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+
+    /// Create a branch from HEAD and check it out, following the configured naming convention
+    New {
+        /// The name of the new branch, e.g. `feat/add-login`
+        name: String,
+
+        /// Put the ID of an issue in front of the name, e.g. `--issue ABC-123`
+        #[clap(long)]
+        issue: Option<String>,
+
+        /// Commit the changes of a template of the config file on the new branch, e.g.
+        /// `--template feature`
+        #[clap(long)]
+        template: Option<String>,
+    },
+
+    /// Pick one of the issues assigned to you and create a branch for it
+    Issue {
+        /// Fetch the issues again instead of using the cached ones
+        #[clap(long)]
+        refresh: bool,
+    },
+
+    /// Delete the local branches that are fully merged into HEAD
+    Clean,
+
+    /// Bring back what gibra destroyed last, or last of the branch NAME
+    ///
+    /// The deleted branches, local or on a remote, the branches reset by a checkout and the
+    /// changes discarded by `--force` are recorded in `.git/gibra/audit.log` with their commits.
+    Undo {
+        /// The branch to bring back, e.g. `feature` or `origin/feature`
+        name: Option<String>,
+
+        /// Only print the entries of the log, the latest first
+        #[clap(long, conflicts_with = "name")]
+        list: bool,
+    },
+
+    /// Show the local branches grouped by prefix, e.g. `feature/`, with how many are merged and
+    /// stale, or act on all the branches with a prefix at once
+    ///
+    /// The branches an action affects are listed, and nothing happens until confirmed.
+    Prefix {
+        /// List the branches starting with PREFIX instead, e.g. `feature/` or `user/alice/`
+        prefix: Option<String>,
+
+        /// What to do with the branches starting with PREFIX
+        #[clap(value_enum)]
+        action: Option<prefix::BulkAction>,
+    },
+
+    /// Merge the picked branches into the current branch with one octopus merge commit, after
+    /// checking that none of them conflict with each other
+    Merge {
+        /// Merge the branches one after another instead, with a merge commit each
+        #[clap(long)]
+        no_ff: bool,
+    },
+
+    /// Show how long listing the branches and checking out took in each repository, as recorded
+    /// with `record = true` under `[perf]`
+    Perf,
+
+    /// Check git, the terminal, the config file, the tokens and the hooks of the repository, and
+    /// print what to do about the problems found. Include its output in bug reports
+    Doctor,
+
+    /// Pack the refs and run the maintenance tasks of git, when so many refs and objects are loose
+    /// that listing the branches got slow
+    Maintenance,
+
+    /// Pick a remote branch and make it the upstream of the current branch
+    ///
+    /// Branches named like the current branch are listed first. Branches that were not fetched yet
+    /// are fetched before they are set as upstream.
+    Upstream,
+
+    /// Rename the current branch, and with it the branch on its remote
+    ///
+    /// The new name is pushed and set as upstream, and the old name is deleted from the remote.
+    /// When a step fails, the ones before it are undone.
+    Rename {
+        /// The new name, asked for when not given
+        new: Option<String>,
+
+        /// Rename the branch on its remote too without asking
+        #[clap(long)]
+        push: bool,
+    },
+
+    /// Pick one of the pull requests waiting for your review on GitHub, oldest first, and check
+    /// out its branch
+    ///
+    /// The preview shows the description and the files changed. The web key opens the pull request
+    /// in the browser instead.
+    Review {
+        /// Fetch the review requests again instead of using the cached ones
+        #[clap(long)]
+        refresh: bool,
+    },
+
+    /// Check that a branch exists, locally or on a remote, and print its tip, without a picker
+    ///
+    /// Prints `key=value` lines for scripts: branch, exists, kind, ref, sha, upstream, ahead,
+    /// behind and fetched. Exits with 7 when the branch doesn't exist. With `--fetch`, only the
+    /// branch is fetched first, from the remote it names or its upstream, or else every remote.
+    Verify {
+        /// The branch, e.g. `feature/login` or `origin/feature/login`
+        branch: String,
+
+        /// Print a JSON object instead
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List the throwaway worktrees created with `--temp`, or remove the old ones
+    Temp {
+        /// Remove those created before `--older-than`, or whose directory is gone. Those with
+        /// changes are kept, unless with `--force`
+        #[clap(long)]
+        gc: bool,
+
+        /// With `--gc`, how long ago they were created at the latest, e.g. `2h` or `3 days`
+        #[clap(
+            long,
+            value_name = "WHEN",
+            value_parser = since::parse,
+            default_value = "1d",
+            requires = "gc"
+        )]
+        older_than: Since,
+    },
+
+    /// Show how far the local branches diverged from their upstream
+    Sync {
+        /// Fast-forward every branch that is strictly behind its upstream
+        #[clap(long)]
+        ff_all: bool,
+    },
+
+    /// Move the local branches from a remote that was renamed or got a new URL to another one
+    Remote {
+        #[command(subcommand)]
+        command: RemoteCommand,
+    },
+
+    /// Manage the tokens of the GitHub and Jira integrations, which are kept in the keychain
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommand,
+    },
+
+    /// Print the preview of a branch: its activity with `--activity`, the TODO and FIXME comments it
+    /// added, then its commits
+    #[command(hide = true)]
+    Preview {
+        /// The working directory of the repository
+        workdir: PathBuf,
+
+        /// The local or remote branch, e.g. `origin/main`
+        branch: String,
+    },
+
+    /// Print the current branch for the shell prompt, e.g. `main* ↑1 · wip`: `*` when its tracked
+    /// files have changes, how far it diverged from its upstream, then the branches checked out
+    /// before it
+    ///
+    /// Waits no longer than 10ms for what is slow to find out: what takes longer is taken from the
+    /// last prompt, or shown as `…`, and found in the background for the next one. Prints nothing
+    /// outside a repository.
+    PromptSegment {
+        /// Wrap the colors so that the shell doesn't count them in the width of the prompt
+        #[clap(long, value_enum)]
+        shell: Option<segment::Shell>,
+
+        /// Print without colors
+        #[clap(long)]
+        plain: bool,
+
+        /// How many of the branches checked out before the current one are shown
+        #[clap(long, value_name = "N", default_value_t = 1)]
+        recent: usize,
+
+        /// Find the segment and cache it for the next prompt, without printing it
+        #[clap(long, hide = true)]
+        refresh: bool,
+    },
+
+    /// Print the preview of a pull request waiting for review
+    #[command(hide = true)]
+    ReviewPreview {
+        /// The working directory of the repository
+        workdir: PathBuf,
+
+        /// The number of the pull request, e.g. `#12`
+        number: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum RemoteCommand {
+    /// Make the local branches tracking OLD track NEW, by rewriting their `branch.<name>.remote`
+    /// and `branch.<name>.pushRemote`, and `remote.pushDefault`
+    Migrate {
+        /// The remote the branches track, or the URL they push to, e.g. `origin`
+        old: String,
+
+        /// The remote to track instead, e.g. `upstream`
+        new: String,
+
+        /// Print the settings that would be rewritten, without rewriting them
+        #[clap(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AuthCommand {
+    /// Store the token of a provider in the keychain, reading it from stdin
+    Login { provider: Provider },
+
+    /// Remove the token of a provider from the keychain
+    Logout { provider: Provider },
+
+    /// Show where the token of each provider is found
+    Status,
+}
+
+/// What is listed besides the branches, and what drops some of them
+#[derive(Clone, Debug, Default)]
+struct Listing {
+    /// `--scope`, see [`scope`]
+    scope: Option<PathBuf>,
+    /// `--ref-glob`, see [`refs`]
+    ref_globs: Vec<String>,
+    /// `--special-refs`, see [`refs`]
+    special_refs: bool,
+    /// `--owners`, see [`owners`]
+    owners: bool,
+    owned_by: Option<String>,
+    /// `--contains` and `--no-contains`, see [`contains`]
+    contains: Vec<String>,
+    no_contains: Vec<String>,
+    /// `--active-since` and `--inactive-since`, see [`since`]
+    active_since: Option<Since>,
+    inactive_since: Option<Since>,
+    /// `--unpushed`, see [`unpushed`]
+    unpushed: bool,
+    /// `--verified-only`, see [`signing`]
+    verified_only: bool,
+    /// `--ls-remote`, see [`source`]
+    ls_remote: bool,
+    /// The commands of `[[sources]]`, see [`source`]
+    sources: Vec<SourceConfig>,
+    /// The tab of the picker whose items are listed, see [`tabs`]
+    tab: Tab,
+}
+
+/// Options that control which branches are offered by the picker
+#[derive(Clone, Debug, Default)]
+struct PickerOptions {
+    branch_filter: Option<BranchType>,
+    fetch: bool,
+    by_remote: bool,
+    sort: SortOrder,
+    watch: Option<Duration>,
+    save_set: Option<String>,
+    load_set: Option<String>,
+    gitflow: Option<GitflowConfig>,
+    open_web: bool,
+    web: WebConfig,
+    at: bool,
+    /// Check out in a throwaway worktree, see [`temp`]
+    temp: bool,
+    preview: bool,
+    activity: Option<usize>,
+    size: bool,
+    /// `--signatures`, see [`signing`]
+    signatures: bool,
+    /// `--age`, see [`age`]
+    age: Option<age::View>,
+    base: Option<String>,
+    no_sort: bool,
+    tac: bool,
+    fields: Fields,
+    listing: Listing,
+    accessible: bool,
+    prompt: Option<String>,
+    header: Option<String>,
+    keys: KeysConfig,
+    truncate: Truncate,
+    /// `--color` and `--border`, see [`theme`]
+    theme: Theme,
+    checkout: CheckoutConfig,
+    github: Option<GithubConfig>,
+    /// Record the timings, see [`perf`]
+    perf: bool,
+    /// Open the picker where it was left, see [`resume`]
+    resume: bool,
+    /// Where the cursor starts otherwise, see [`cursor`]
+    initial_cursor: InitialCursor,
+    /// How long counting how far the branches diverged may delay the picker, see [`budget`]
+    budget: Option<Duration>,
+    /// The commands of `[commands]`, see [`custom`]
+    commands: Vec<CustomCommand>,
+    /// The convention the branches created from the picker follow
+    naming: NamingConfig,
+    /// The shortcuts creating branches named after the query, see [`shortcut`]
+    shortcuts: Vec<Shortcut>,
+    /// Show the tabs in the picker that checks out, see [`tabs`]
+    tabs: bool,
+    /// The rules of the team, see [`policy`]
+    policies: PoliciesConfig,
+}
+
+/// The branches picked by the user, and the action to run on them if one of its keys was pressed
+struct Picked {
+    action: Option<Action>,
+    /// The index of the command of `[commands]` to run on them instead, see [`custom`]
+    command: Option<usize>,
+    branches: Vec<Branch>,
+    /// The branch to create instead, made from the query with a shortcut, see [`shortcut`]
+    create: Option<NewBranch>,
+    /// The worktree picked in the tab of the worktrees, whose path is printed instead, see
+    /// [`tabs`]
+    worktree: Option<PathBuf>,
+}
+
+/// Runs skim sessions on a shared terminal, so that chained pickers don't flicker in between.
+#[derive(Default)]
+struct Picker {
+    term: Option<Arc<Term>>,
+    /// Where the tabs were left, see [`tabs`]
+    tabs: tabs::Memory,
+}
+
+impl Picker {
+    fn run(&mut self, options: &SkimOptions, source: SkimItemReceiver) -> Option<SkimOutput> {
+        self.run_and_then(options, source, |_| {})
+    }
+
+    /// Same as [`Picker::run`], passing the output to `closed` first, even when the picker was
+    /// aborted
+    fn run_and_then(
+        &mut self,
+        options: &SkimOptions,
+        source: SkimItemReceiver,
+        closed: impl FnOnce(&SkimOutput),
+    ) -> Option<SkimOutput> {
+        let term = self
+            .term
+            .get_or_insert_with(|| Skim::init_term(options))
+            .clone();
+
+        let output = Skim::run_with_term(term, options, Some(source));
+        if let Some(output) = &output {
+            closed(output);
+        }
+        if let Some(Event::EvActAbort) = output.as_ref().map(|out| &out.final_event) {
+            std::process::exit(130);
+        }
+
+        output
+    }
+}
+
+#[derive(Clone, Debug, Default)]
 struct LocalBranch {
     name: String,
     remote_name: Option<String>,
+    /// The commit of the branch when it was listed
+    tip: Option<Oid>,
+    divergence: Option<Divergence>,
+    pull_request: Option<u64>,
+    diffstat: Option<DiffStat>,
+    /// The signature of the tip, with `--signatures` or `--verified-only`
+    signing: Option<Signing>,
+    details: Option<BranchDetails>,
+    projection: Option<Projection>,
+    /// The components touched below `--scope`
+    components: Option<Vec<String>>,
+    /// The owners of the changes, with `--owners` or `--owned-by`
+    owners: Option<Vec<String>>,
+    /// How long ago the tip was committed, or when, with `--age`
+    age: Option<String>,
+    /// Has commits to push, with `--unpushed`
+    unpushed: bool,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 struct RemoteBranch {
     name: String,
     local_name: Option<String>,
+    /// The commit of the branch when it was listed
+    tip: Option<Oid>,
+    /// Only with `--base`, remote branches having no upstream
+    divergence: Option<Divergence>,
+    pull_request: Option<u64>,
+    diffstat: Option<DiffStat>,
+    /// The signature of the tip, with `--signatures` or `--verified-only`
+    signing: Option<Signing>,
+    details: Option<BranchDetails>,
+    projection: Option<Projection>,
+    /// The components touched below `--scope`
+    components: Option<Vec<String>>,
+    /// The owners of the changes, with `--owners` or `--owned-by`
+    owners: Option<Vec<String>>,
+    /// How long ago the tip was committed, or when, with `--age`
+    age: Option<String>,
+}
+
+/// A ref that is not a branch, see [`refs`]
+#[derive(Clone, Debug)]
+struct RefBranch {
+    /// How it is shown, e.g. `changes/1234/2`
+    name: String,
+    refname: String,
+    /// The commit of the ref when it was listed
+    tip: Option<Oid>,
+    /// Shown after the name, for the items of the commands of `[[sources]]`, see [`source`]
+    note: Option<String>,
+    /// With `--special-refs`, for the pseudo-refs and the notes refs
+    special: Option<refs::Special>,
+    divergence: Option<Divergence>,
+    diffstat: Option<DiffStat>,
+    /// The signature of the tip, with `--signatures` or `--verified-only`
+    signing: Option<Signing>,
+    details: Option<BranchDetails>,
+    projection: Option<Projection>,
+    /// The components touched below `--scope`
+    components: Option<Vec<String>>,
+    /// The owners of the changes, with `--owners` or `--owned-by`
+    owners: Option<Vec<String>>,
+    /// How long ago the tip was committed, or when, with `--age`
+    age: Option<String>,
 }
 
 #[derive(Clone, Debug)]
 enum Branch {
     Local(LocalBranch),
     Remote(RemoteBranch),
+    Ref(RefBranch),
 }
 
 impl Branch {
+    /// The name git knows it by, the full refname for the refs that are not branches
     fn name(self) -> String {
         match self {
             Branch::Local(local_branch) => local_branch.name,
             Branch::Remote(remote_branch) => remote_branch.name,
+            Branch::Ref(reference) => reference.refname,
+        }
+    }
+
+    fn refname(&self) -> String {
+        match self {
+            Branch::Local(branch) => format!("refs/heads/{}", branch.name),
+            Branch::Remote(branch) => format!("refs/remotes/{}", branch.name),
+            Branch::Ref(reference) => reference.refname.clone(),
+        }
+    }
+}
+
+impl Branch {
+    /// What `--print-format` prints of the branch
+    fn print_values(&self) -> BranchValues {
+        let (kind, upstream, tip, divergence) = match self {
+            Branch::Local(branch) => (
+                "local",
+                branch.remote_name.clone(),
+                branch.tip,
+                branch.divergence,
+            ),
+            Branch::Remote(branch) => ("remote", None, branch.tip, branch.divergence),
+            Branch::Ref(reference) => ("ref", None, reference.tip, reference.divergence),
+        };
+        BranchValues {
+            name: self.output().into_owned(),
+            refname: self.refname(),
+            kind,
+            upstream,
+            tip,
+            divergence: divergence
+                .filter(|divergence| !divergence.pending)
+                .map(|divergence| (divergence.ahead, divergence.behind)),
+        }
+    }
+
+    fn projection(&self) -> Option<&Projection> {
+        match self {
+            Branch::Local(local_branch) => local_branch.projection.as_ref(),
+            Branch::Remote(remote_branch) => remote_branch.projection.as_ref(),
+            Branch::Ref(reference) => reference.projection.as_ref(),
         }
     }
 }
 
 impl SkimItem for Branch {
     fn text(&self) -> Cow<str> {
+        match (self.projection(), self) {
+            (Some(projection), _) => Cow::Borrowed(&projection.text),
+            (None, Branch::Ref(reference)) => Cow::Borrowed(&reference.name),
+            (None, _) => self.output(),
+        }
+    }
+
+    fn output(&self) -> Cow<'_, str> {
         match self {
             Branch::Local(local_branch) => Cow::Borrowed(&local_branch.name),
             Branch::Remote(remote_branch) => Cow::Borrowed(&remote_branch.name),
+            Branch::Ref(reference) => Cow::Borrowed(&reference.refname),
+        }
+    }
+
+    fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
+        self.projection()?.matching.as_deref()
+    }
+
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let accessible = context.accessible;
+        let colored = |fg: Color| Attr {
+            fg,
+            ..Attr::default()
+        };
+        let mut columns = vec![];
+
+        let (pull_request, divergence, diffstat, signing, details, components, owners, age) =
+            match self {
+                Branch::Local(branch) => (
+                    branch.pull_request,
+                    branch.divergence,
+                    branch.diffstat,
+                    branch.signing,
+                    &branch.details,
+                    &branch.components,
+                    &branch.owners,
+                    &branch.age,
+                ),
+                Branch::Remote(branch) => (
+                    branch.pull_request,
+                    branch.divergence,
+                    branch.diffstat,
+                    branch.signing,
+                    &branch.details,
+                    &branch.components,
+                    &branch.owners,
+                    &branch.age,
+                ),
+                Branch::Ref(reference) => (
+                    None,
+                    reference.divergence,
+                    reference.diffstat,
+                    reference.signing,
+                    &reference.details,
+                    &reference.components,
+                    &reference.owners,
+                    &reference.age,
+                ),
+            };
+        if let Branch::Local(branch) = self {
+            if unpushed::has_badge(branch) {
+                columns.push(if accessible {
+                    Column::new("(not pushed)".to_string(), Attr::default(), 5)
+                } else {
+                    Column::new("unpushed".to_string(), colored(Color::YELLOW), 5)
+                });
+            }
+        }
+
+        if let Some(number) = pull_request {
+            columns.push(if accessible {
+                Column::new(format!("PR #{}", number), Attr::default(), 4)
+            } else {
+                Column::new(format!("#{}", number), colored(Color::GREEN), 4)
+            });
+        }
+
+        if let Some(divergence) = divergence {
+            if accessible {
+                let description = divergence.describe();
+                if !description.is_empty() {
+                    columns.push(Column::new(
+                        format!("({})", description),
+                        Attr::default(),
+                        5,
+                    ));
+                }
+            } else {
+                let label = divergence.label();
+                if !label.is_empty() {
+                    columns.push(Column::new(label, colored(Color::YELLOW), 5));
+                }
+            }
+        }
+
+        if let Some(components) = components
+            .as_ref()
+            .filter(|components| !components.is_empty())
+        {
+            columns.push(if accessible {
+                Column::new(
+                    format!("(touches {})", components.join(", ")),
+                    Attr::default(),
+                    3,
+                )
+            } else {
+                Column::new(scope::label(components), colored(Color::BLUE), 3)
+            });
+        }
+
+        if let Some(owners) = owners.as_ref().filter(|owners| !owners.is_empty()) {
+            columns.push(if accessible {
+                Column::new(
+                    format!("(owned by {})", owners.join(", ")),
+                    Attr::default(),
+                    3,
+                )
+            } else {
+                Column::new(owners::label(owners), colored(Color::MAGENTA), 3)
+            });
+        }
+
+        if let Branch::Ref(RefBranch {
+            special: Some(special),
+            ..
+        }) = self
+        {
+            columns.push(if accessible {
+                Column::new(format!("({})", special.badge()), Attr::default(), 5)
+            } else {
+                Column::new(
+                    special.badge().to_string(),
+                    colored(Color::LIGHT_MAGENTA),
+                    5,
+                )
+            });
+        }
+
+        if let Branch::Ref(RefBranch {
+            note: Some(note), ..
+        }) = self
+        {
+            columns.push(if accessible {
+                Column::new(format!("({})", note), Attr::default(), 2)
+            } else {
+                Column::new(note.clone(), colored(Color::LIGHT_BLACK), 2)
+            });
+        }
+
+        if let Some(diffstat) = diffstat.filter(|diffstat| diffstat.files > 0) {
+            columns.push(if accessible {
+                Column::new(format!("({})", diffstat.describe()), Attr::default(), 3)
+            } else {
+                Column::new(diffstat.label(), colored(Color::CYAN), 3)
+            });
         }
+
+        if let Some(signing) = signing {
+            columns.push(if accessible {
+                Column::new(format!("({})", signing.describe()), Attr::default(), 3)
+            } else {
+                let fg = match signing {
+                    Signing::Good => Color::GREEN,
+                    Signing::Bad => Color::RED,
+                    Signing::UnknownKey => Color::YELLOW,
+                    Signing::Unsigned => Color::LIGHT_BLACK,
+                };
+                Column::new(signing.label().to_string(), colored(fg), 3)
+            });
+        }
+
+        // it takes the place of the age of the details
+        if let Some(age) = age {
+            columns.push(if accessible {
+                Column::new(format!("({})", age), Attr::default(), 3)
+            } else {
+                Column::new(age.clone(), colored(Color::LIGHT_BLACK), 3)
+            });
+        }
+
+        let details = details
+            .as_ref()
+            .and_then(|details| details.get(&self.refname()));
+        if let Some(details) = &details {
+            if let Branch::Local(LocalBranch {
+                remote_name: Some(upstream),
+                ..
+            }) = self
+            {
+                columns.push(if accessible {
+                    Column::new(format!("(tracks {})", upstream), Attr::default(), 2)
+                } else {
+                    Column::new(format!("→ {}", upstream), colored(Color::MAGENTA), 2)
+                });
+            }
+            if age.is_none() {
+                columns.push(if accessible {
+                    Column::new(format!("({})", details.age), Attr::default(), 1)
+                } else {
+                    Column::new(details.age.clone(), colored(Color::LIGHT_BLACK), 1)
+                });
+            }
+            if !details.subject.is_empty() {
+                columns
+                    .push(Column::new(details.subject.clone(), Attr::default(), 0).truncatable());
+            }
+        }
+
+        let align = details.as_ref().map(|details| details.name_width);
+        layout::render(context, align, columns)
     }
 }
 
-fn find_git_root() -> Result<PathBuf> {
-    let current_dir = std::env::current_dir()?;
-    let repo = Repository::discover(&current_dir)?;
-    let git_dir = repo
-        .path()
-        .parent()
-        .with_context(|| "Failed to get parent")?
-        .to_path_buf();
+fn fetch_remotes(repo: &Repository) -> Result<()> {
+    let remotes = repo.remotes().with_context(|| "Failed to get remotes")?;
+    for name in remotes.iter().flatten() {
+        eprintln!("Fetching {}...", name);
+        if let Err(err) = remote::fetch(repo, name, true) {
+            eprintln!("warning: {:#}", err);
+        }
+    }
 
-    Ok(git_dir)
+    Ok(())
 }
 
 fn get_current_branch(repo: &Repository) -> Result<Branch> {
@@ -84,45 +1194,64 @@ fn get_current_branch(repo: &Repository) -> Result<Branch> {
     Ok(Branch::Local(LocalBranch {
         name: current_branch.to_string(),
         remote_name: None,
+        // a detached HEAD has no branch to move
+        tip: head.is_branch().then(|| head.target()).flatten(),
+        divergence: None,
+        pull_request: None,
+        diffstat: None,
+        signing: None,
+        details: None,
+        projection: None,
+        components: None,
+        owners: None,
+        age: None,
+        unpushed: false,
     }))
 }
 
-fn get_branches(repo: &Repository, branch_filter: Option<BranchType>) -> Result<Vec<Branch>> {
-    let local_branches: Vec<Branch> = repo
-        .branches(Some(BranchType::Local))
-        .with_context(|| "Failed to get local branches")?
-        .filter_map(|branch| {
-            let branch = match branch {
-                Ok((branch, _)) => branch,
-                Err(_) => return None,
-            };
-
-            let branch_name = match branch.name() {
-                Ok(Some(name)) => name.to_string(),
-                Ok(None) => return None,
-                Err(_) => return None,
-            };
-
-            let remote_branch_name = match branch.upstream() {
-                Ok(upstream) => match upstream.name() {
-                    Ok(Some(name)) => Some(name.to_string()),
-                    Ok(None) => None,
-                    Err(_) => return None,
-                },
-                Err(_) => return None,
+/// The local branches having an upstream, or all of them with `untracked`, and the remote
+/// branches, see [`source`] for the other items
+fn get_branches(
+    meta: &RepoMeta,
+    branch_filter: Option<BranchType>,
+    untracked: bool,
+) -> Result<Vec<Branch>> {
+    let repo = meta.repo();
+    let base = meta.base();
+    // only branches with an upstream are listed, besides the current one
+    let local_branches: Vec<Branch> = meta
+        .local_branches()
+        .iter()
+        .filter(|branch| untracked || branch.upstream.is_some())
+        .map(|branch| {
+            let divergence = match base {
+                Some(base) => branch.tip.and_then(|tip| meta.divergence(tip, base.oid)),
+                None => branch.divergence,
             };
-
-            Some(Branch::Local(LocalBranch {
-                name: branch_name,
-                remote_name: remote_branch_name,
-            }))
+            Branch::Local(LocalBranch {
+                name: branch.name.clone(),
+                remote_name: branch.upstream.clone(),
+                tip: branch.tip,
+                divergence,
+                pull_request: None,
+                diffstat: None,
+                signing: None,
+                details: None,
+                projection: None,
+                components: None,
+                owners: None,
+                age: None,
+                unpushed: false,
+            })
         })
         .collect();
 
     let remote_to_local_map: HashMap<_, _> = local_branches
         .iter()
         .filter_map(|branch| match branch {
-            Branch::Local(LocalBranch { name, remote_name }) => match remote_name {
+            Branch::Local(LocalBranch {
+                name, remote_name, ..
+            }) => match remote_name {
                 Some(remote_name) => Some((remote_name, name)),
                 _ => None,
             },
@@ -153,9 +1282,25 @@ fn get_branches(repo: &Repository, branch_filter: Option<BranchType>) -> Result<
                 .get(&branch_name)
                 .map(|name| name.to_string());
 
+            // peeled, as a symbolic ref like `origin/HEAD` has no target of its own
+            let tip = branch.get().peel_to_commit().ok().map(|commit| commit.id());
+            let divergence = base
+                .zip(tip)
+                .and_then(|(base, tip)| meta.divergence(tip, base.oid));
+
             Some(Branch::Remote(RemoteBranch {
                 name: branch_name,
                 local_name: local_branch_name,
+                tip,
+                divergence,
+                pull_request: None,
+                diffstat: None,
+                signing: None,
+                details: None,
+                projection: None,
+                components: None,
+                owners: None,
+                age: None,
             }))
         })
         .collect();
@@ -172,103 +1317,1904 @@ fn get_branches(repo: &Repository, branch_filter: Option<BranchType>) -> Result<
     Ok(branches)
 }
 
-fn checkout_local_branch(branch: &LocalBranch) -> Result<()> {
-    Command::new("git")
-        .args(&["checkout", &branch.name])
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .with_context(|| "Failed to execute checkout command")?;
+/// `git checkout` over the local changes in the way, see [`discard::confirm`]
+const FORCE_ARG: &str = "--force";
 
-    Ok(())
+fn checkout_local_branch(
+    transaction: &mut Transaction,
+    branch: &LocalBranch,
+    force: bool,
+) -> Result<()> {
+    let mut args = vec![branch.name.as_str()];
+    if force {
+        args.push(FORCE_ARG);
+    }
+    transaction.checkout(&format!("check out {}", branch.name), &args)
 }
 
-fn checkout_remote_branch(branch: &RemoteBranch) -> Result<()> {
-    match branch.local_name.clone() {
-        Some(local_branch_name) => {
-            Command::new("git")
-                .args(&["checkout", &local_branch_name])
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .with_context(|| "Failed to execute checkout command")?;
-        }
-        None => {
-            Command::new("git")
-                .args(&["checkout", "-b", &branch.name])
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .output()
-                .with_context(|| "Failed to execute checkout command")?;
-        }
+/// Check out the local branch tracking `branch`, or else as `plan` says, see [`collision`]
+fn checkout_remote_branch(
+    transaction: &mut Transaction,
+    branch: &RemoteBranch,
+    plan: Option<&collision::Plan>,
+    force: bool,
+) -> Result<()> {
+    let (description, mut args) = match (&branch.local_name, plan) {
+        (Some(local_branch_name), _) => (
+            format!("check out {}", local_branch_name),
+            vec![local_branch_name.as_str()],
+        ),
+        (None, Some(plan)) => (plan.description(), plan.checkout_args()),
+        (None, None) => bail!("There is no local branch to check out {} as", branch.name),
+    };
+    if force {
+        args.push(FORCE_ARG);
     }
+    transaction.checkout(&description, &args)
+}
 
-    Ok(())
+fn checkout_ref(transaction: &mut Transaction, reference: &RefBranch, force: bool) -> Result<()> {
+    let mut args = vec!["--detach", reference.refname.as_str()];
+    if force {
+        args.push(FORCE_ARG);
+    }
+    transaction.checkout(&format!("check out {}", reference.name), &args)
 }
 
-fn checkout(branch: &Branch) -> Result<()> {
-    match branch {
-        Branch::Local(branch) => checkout_local_branch(branch),
-        Branch::Remote(branch) => checkout_remote_branch(branch),
+/// Create the branch `name` from `start_point`, or from HEAD, and check it out.
+fn create_branch(workdir: &Path, name: &str, start_point: Option<&str>) -> Result<()> {
+    let mut args = vec!["checkout", "-b", name];
+    if let Some(start_point) = start_point {
+        args.extend(["--no-track", start_point]);
     }
+    git::run(workdir, &args)
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Check out the local branch `name`, creating it from HEAD, or with `fresh_base` from the
+/// default branch of the remote, when it doesn't exist yet.
+fn create_or_checkout_branch(
+    context: &RepoContext,
+    name: &str,
+    config: &CheckoutConfig,
+) -> Result<()> {
+    let (repo, git_root) = (&context.repo, context.workdir.as_path());
+    state::ensure_no_operation_in_progress(repo, git_root, config.force)?;
 
-    let branch_filter;
-    if args.remote_only && args.local_only {
-        panic!("Cannot specify both --remote-only and --local-only");
-    } else if args.remote_only {
-        branch_filter = Some(BranchType::Remote);
-    } else if args.local_only {
-        branch_filter = Some(BranchType::Local);
+    let branch = LocalBranch {
+        name: name.to_string(),
+        remote_name: None,
+        tip: None,
+        divergence: None,
+        pull_request: None,
+        diffstat: None,
+        signing: None,
+        details: None,
+        projection: None,
+        components: None,
+        owners: None,
+        age: None,
+        unpushed: false,
+    };
+    if repo.find_branch(name, BranchType::Local).is_ok() {
+        let branch = Branch::Local(branch);
+        ensure_not_checked_out_elsewhere(repo, &branch)?;
+        return checkout(repo, git_root, &branch, config)
+            .with_context(|| "Failed to checkout branch");
+    }
+
+    let start_point = config
+        .fresh_base
+        .then(|| remote::fresh_base(repo))
+        .transpose()?;
+    create_branch(git_root, name, start_point.as_deref()).with_context(|| "Failed to create branch")
+}
+
+/// Create `branch`, picked from the query with a shortcut, and check it out
+fn create_from_shortcut(
+    repo: &Repository,
+    workdir: &Path,
+    branch: &NewBranch,
+    naming: &NamingConfig,
+) -> Result<()> {
+    naming::validate(naming, &branch.name)?;
+    let base = if branch.fresh {
+        remote::fresh_base(repo)?
     } else {
-        branch_filter = None;
+        branch.base.clone()
+    };
+    create_branch(workdir, &branch.name, Some(&base)).with_context(|| "Failed to create branch")
+}
+
+/// What the rules of [`policy`] are checked on for `branch`, `None` for the refs that aren't
+/// branches
+fn policy_target<'a>(branch: &'a Branch, pulls: &OpenPulls) -> Option<Target<'a>> {
+    match branch {
+        Branch::Local(branch) => Some(Target {
+            name: &branch.name,
+            pull: pulls.of_local(&branch.name),
+        }),
+        Branch::Remote(branch) => Some(Target {
+            name: branch
+                .name
+                .split_once('/')
+                .map_or(branch.name.as_str(), |(_, name)| name),
+            pull: pulls.of_remote(&branch.name),
+        }),
+        Branch::Ref(_) => None,
     }
+}
 
-    let git_root = find_git_root().with_context(|| "Failed to find git root")?;
-    let repo = Repository::open(git_root.clone()).with_context(|| "Failed to open repository")?;
+/// Refuse to check out a branch that is already checked out in another worktree.
+fn ensure_not_checked_out_elsewhere(repo: &Repository, branch: &Branch) -> Result<()> {
+    let local_name = match branch {
+        Branch::Local(branch) => Some(&branch.name),
+        Branch::Remote(branch) => branch.local_name.as_ref(),
+        Branch::Ref(_) => None,
+    };
 
-    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    if let Some(local_name) = local_name {
+        let refname = format!("refs/heads/{}", local_name);
+        if let Some(path) = state::worktree_holding(repo, &refname) {
+            return Err(GibraError::CheckedOutElsewhere {
+                branch: local_name.clone(),
+                path,
+            }
+            .into());
+        }
+    }
 
-    let current_branch =
-        get_current_branch(&repo).with_context(|| "Failed to get current branch")?;
-    if !args.remote_only {
-        let _ = tx.send(Arc::new(current_branch.clone()));
+    Ok(())
+}
+
+/// Check out `branch`, with the extra steps of `config`. If any step fails, the ones before it
+/// are undone.
+fn checkout(
+    repo: &Repository,
+    workdir: &Path,
+    branch: &Branch,
+    config: &CheckoutConfig,
+) -> Result<()> {
+    if let Branch::Ref(RefBranch {
+        name,
+        special: Some(special),
+        ..
+    }) = branch
+    {
+        if !special.checkout() {
+            bail!(
+                "{} is a {} ref, it can't be checked out",
+                name,
+                special.badge()
+            );
+        }
+    }
+    // listed by the remote, it may have never been fetched
+    if let Branch::Remote(branch) = branch {
+        if config.fetch_picked && branch.local_name.is_none() {
+            remote::fetch_listed(repo, &branch.name)?;
+        }
+    }
+    // asked before anything changes
+    let plan = match branch {
+        Branch::Remote(branch) if branch.local_name.is_none() => {
+            Some(collision::plan(repo, branch)?)
+        }
+        _ => None,
+    };
+    let force = config.force && !config.autostash && {
+        let (refname, name) = match (branch, &plan) {
+            (Branch::Local(branch), _) => (format!("refs/heads/{}", branch.name), &branch.name),
+            (Branch::Remote(branch), _) => match &branch.local_name {
+                Some(local_name) => (format!("refs/heads/{}", local_name), local_name),
+                None => match &plan {
+                    Some(collision::Plan::Existing(name)) => (format!("refs/heads/{}", name), name),
+                    _ => (format!("refs/remotes/{}", branch.name), &branch.name),
+                },
+            },
+            (Branch::Ref(reference), _) => (reference.refname.clone(), &reference.name),
+        };
+        discard::confirm(repo, &refname, name, config.yes)?
+    };
+    // recorded once the checkout went through
+    let mut losses = vec![];
+    if force {
+        losses.extend(audit::Loss::changes(repo, workdir));
+    }
+    if let Some(collision::Plan::Reset { name, .. }) = &plan {
+        losses.extend(audit::Loss::reset(repo, name));
     }
 
-    get_branches(&repo, branch_filter)
-        .with_context(|| "Failed to get branches")?
-        .into_iter()
-        .filter(|branch| (*branch).clone().name() != current_branch.clone().name())
-        .for_each(|branch| {
-            let _ = tx.send(Arc::new(branch.clone()));
-        });
+    let mut transaction = Transaction::begin(repo, workdir, "checkout");
+    if config.skip_hooks {
+        transaction.skip_hooks();
+    }
+    if config.autostash {
+        transaction.stash()?;
+    }
+
+    match branch {
+        Branch::Local(branch) => checkout_local_branch(&mut transaction, branch, force)?,
+        Branch::Remote(branch) => {
+            checkout_remote_branch(&mut transaction, branch, plan.as_ref(), force)?
+        }
+        Branch::Ref(reference) => checkout_ref(&mut transaction, reference, force)?,
+    }
+
+    sparse::apply(&mut transaction, repo, &config.sparse)?;
+
+    if config.update_submodules && workdir.join(".gitmodules").exists() {
+        transaction.run(
+            "update submodules",
+            &["submodule", "update", "--init", "--recursive"],
+            vec![],
+        )?;
+    }
+
+    transaction.commit()?;
+    audit::record(repo, losses);
+    Ok(())
+}
+
+const LOCAL_SOURCE: &str = "local";
+
+/// Show a picker of the remotes (plus "local") and return the picked one.
+///
+/// `None` stands for the local branches.
+fn select_source(
+    meta: &RepoMeta,
+    picker: &mut Picker,
+    branch_filter: Option<BranchType>,
+    accessible: bool,
+    keymap: Keymap,
+) -> Result<Option<String>> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
 
+    if branch_filter != Some(BranchType::Remote) {
+        let _ = tx.send(Arc::new(LOCAL_SOURCE.to_string()));
+    }
+    for name in meta.remotes() {
+        let _ = tx.send(Arc::new(name.clone()));
+    }
     drop(tx);
 
-    let options = SkimOptionsBuilder::default()
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, keymap, vec![]);
+    let options = builder
+        .header(Some("Select a remote"))
+        .accessible(accessible)
         .build()
         .with_context(|| "Failed to set up")?;
 
-    let selected_branch = Skim::run_with(&options, Some(rx))
-        .map(|out| match out.final_event {
-            Event::EvActAbort => std::process::exit(130),
-            _ => out.selected_items,
-        })
-        .unwrap_or_else(Vec::new)
+    let source = picker
+        .run(&options, rx)
+        .map(|out| out.selected_items)
+        .unwrap_or_default()
         .first()
-        .map(|selected_item| {
-            (**selected_item)
-                .as_any()
-                .downcast_ref::<Branch>()
-                .with_context(|| "Failed to get selected branch")
-                .map(|selected_item| selected_item.to_owned())
-        })
-        .with_context(|| "Failed to get selected branch")??;
+        .map(|selected_item| selected_item.output().to_string())
+        .with_context(|| "Failed to get selected remote")?;
 
-    checkout(&selected_branch).with_context(|| "Failed to checkout branch")?;
+    Ok(Some(source).filter(|source| source != LOCAL_SOURCE))
+}
 
-    Ok(())
+fn is_from_remote(repo: &Repository, branch: &Branch, remote: &str) -> bool {
+    match branch {
+        Branch::Local(_) | Branch::Ref(_) => false,
+        Branch::Remote(remote_branch) => repo
+            .branch_remote_name(&format!("refs/remotes/{}", remote_branch.name))
+            .map(|name| name.as_str() == Some(remote))
+            .unwrap_or(false),
+    }
+}
+
+fn last_commit_time(repo: &Repository, branch: &Branch) -> i64 {
+    repo.revparse_single(&branch.refname())
+        .and_then(|object| object.peel_to_commit())
+        .map(|commit| commit.time().seconds())
+        .unwrap_or(0)
+}
+
+fn sort_branches(repo: &Repository, branches: &mut [Branch], sort: SortOrder) {
+    match sort {
+        SortOrder::Name => {}
+        SortOrder::Date | SortOrder::Recent => {
+            branches.sort_by_cached_key(|branch| Reverse(last_commit_time(repo, branch)))
+        }
+        SortOrder::Natural => branches.sort_by(|a, b| collate::compare(&a.output(), &b.output())),
+    }
+}
+
+/// The header note telling why only local branches are listed although none were filtered out
+fn no_remotes_note(has_remotes: bool, branch_filter: Option<BranchType>) -> Option<&'static str> {
+    (!has_remotes && branch_filter.is_none()).then_some("no remotes configured")
+}
+
+/// Describe the listed branches for the info line, e.g. `branches · local+remote · sorted by date`
+fn describe_branches(
+    branch_filter: Option<BranchType>,
+    remote: Option<&str>,
+    sort: SortOrder,
+) -> String {
+    let source = match (branch_filter, remote) {
+        (_, Some(remote)) => remote,
+        (Some(BranchType::Local), _) => "local",
+        (Some(BranchType::Remote), _) => "remote",
+        (None, _) => "local+remote",
+    };
+    let sort = match sort {
+        SortOrder::Name => "name",
+        SortOrder::Date => "date",
+        SortOrder::Natural => "natural",
+        SortOrder::Recent => "recency",
+    };
+
+    format!("branches · {} · sorted by {}", source, sort)
+}
+
+/// List the branches offered by the picker, in the order they are shown
+fn list_branches(
+    meta: &RepoMeta,
+    branch_filter: Option<BranchType>,
+    remote: Option<&str>,
+    sort: SortOrder,
+    fields: &Fields,
+    listing: &Listing,
+) -> Result<Vec<Branch>> {
+    let mut branches = collect_branches(meta, branch_filter, remote, sort, listing)?;
+    enrich_branches(meta, &mut branches, fields);
+    Ok(branches)
+}
+
+/// The branches to list, the current one first
+fn collect_branches(
+    meta: &RepoMeta,
+    branch_filter: Option<BranchType>,
+    remote: Option<&str>,
+    sort: SortOrder,
+    listing: &Listing,
+) -> Result<Vec<Branch>> {
+    let repo = meta.repo();
+    let current_branch =
+        get_current_branch(repo).with_context(|| "Failed to get current branch")?;
+
+    let mut branches: Vec<Branch> = source::items(meta, branch_filter, listing)?
+        .into_iter()
+        .filter(|branch| match remote {
+            Some(remote) => is_from_remote(repo, branch, remote),
+            None => true,
+        })
+        .collect();
+    if let Some(path) = &listing.scope {
+        scope::apply(meta, &scope::Scope::new(repo, path)?, &mut branches);
+    }
+    if listing.owners || listing.owned_by.is_some() {
+        owners::apply(meta, listing.owned_by.as_deref(), &mut branches)?;
+    }
+    sort_branches(repo, &mut branches, sort);
+
+    // the current branch is always listed first
+    let current_index = branches
+        .iter()
+        .position(|branch| (*branch).clone().name() == current_branch.clone().name());
+    match current_index {
+        Some(index) => {
+            let current_branch = branches.remove(index);
+            branches.insert(0, current_branch);
+        }
+        None if branch_filter != Some(BranchType::Remote) && listing.tab == Tab::Branches => {
+            branches.insert(0, current_branch)
+        }
+        None => {}
+    }
+    if !listing.contains.is_empty() || !listing.no_contains.is_empty() {
+        let containment =
+            contains::Containment::new(repo, &listing.contains, &listing.no_contains)?;
+        contains::apply(repo, &containment, &mut branches);
+    }
+    if listing.active_since.is_some() || listing.inactive_since.is_some() {
+        let (active, inactive) = (&listing.active_since, &listing.inactive_since);
+        since::apply(repo, active.as_ref(), inactive.as_ref(), &mut branches);
+    }
+    if listing.unpushed {
+        unpushed::apply(meta, &mut branches);
+    }
+    if listing.verified_only {
+        signing::retain_verified(meta, &mut branches)?;
+    }
+
+    Ok(branches)
+}
+
+/// Add what is shown next to the names of the branches: their pull requests, the fields of the
+/// names, and the columns
+fn enrich_branches(meta: &RepoMeta, branches: &mut [Branch], fields: &Fields) {
+    let repo = meta.repo();
+    let pulls = pulls::cached(meta);
+    for branch in branches.iter_mut() {
+        match branch {
+            Branch::Local(branch) => branch.pull_request = pulls.of_local(&branch.name),
+            Branch::Remote(branch) => branch.pull_request = pulls.of_remote(&branch.name),
+            Branch::Ref(_) => {}
+        }
+    }
+    fields::attach(fields, branches);
+    if layout::columns_enabled(repo) {
+        layout::attach_details(repo, branches);
+    }
+}
+
+/// The items of the picker: the branches, under recency headers when sorted by recency
+fn picker_items(
+    repo: &Repository,
+    branches: Vec<Branch>,
+    sort: SortOrder,
+    gitflow: Option<&GitflowConfig>,
+) -> Vec<Arc<dyn SkimItem>> {
+    if let Some(config) = gitflow {
+        return gitflow::grouped(&Gitflow::new(repo, config), branches);
+    }
+
+    match sort {
+        SortOrder::Recent => recency::grouped(repo, branches),
+        _ => branches
+            .into_iter()
+            .map(|branch| Arc::new(branch) as Arc<dyn SkimItem>)
+            .collect(),
+    }
+}
+
+/// The command showing the branch under the cursor in the preview. It is run by gibra itself, so
+/// that the TODOs of the branch are listed above its commits, and `exec`'d so that the picker can
+/// kill it when the cursor moves on.
+fn preview_command(
+    workdir: &Path,
+    activity: Option<usize>,
+    base: Option<&Base>,
+    theme: &Theme,
+) -> String {
+    let workdir = exec::shell_quote(&workdir.to_string_lossy());
+    let mut flags = activity
+        .map(|weeks| format!(" --activity={}", weeks))
+        .unwrap_or_default();
+    if let Some(base) = base {
+        flags.push_str(&format!(" --base={}", base.oid));
+    }
+    flags.push_str(&theme.preview_flag());
+    match std::env::current_exe() {
+        Ok(exe) => format!(
+            "exec {} preview{} {} {{}}",
+            exec::shell_quote(&exe.to_string_lossy()),
+            flags,
+            workdir
+        ),
+        Err(_) => {
+            let range = base
+                .map(|base| format!("{}..", base.oid))
+                .unwrap_or_default();
+            format!(
+                "git -C {} log --color=always --stat -n 20 {}{{}} --",
+                workdir, range
+            )
+        }
+    }
+}
+
+/// Print the activity of `branch` over `activity` weeks and the TODOs it added, then run `git log`
+/// for it in place of gibra, for the commits that are not in `base` when given, in the diff
+/// colors of `color`
+fn preview(
+    workdir: &Path,
+    branch: &str,
+    activity: Option<usize>,
+    base: Option<&str>,
+    color: Option<&str>,
+) -> Result<()> {
+    let repo = alternates::open(workdir).with_context(|| "Failed to open repository")?;
+    let meta = repo_meta(&repo, base)?;
+    if let Some(weeks) = activity {
+        match activity::of_branch(&meta, branch, weeks) {
+            Ok(line) => println!("{}\n", line),
+            Err(err) => debug!("{:#}", err),
+        }
+    }
+    if let Some(line) = push_remote::summary(&repo, branch) {
+        println!("{}\n", line);
+    }
+    match todos::of_branch(&meta, branch) {
+        Ok(todos) => {
+            let lines = todos::summary(&todos);
+            if !lines.is_empty() {
+                println!("{}\n", lines.join("\n"));
+            }
+        }
+        Err(err) => debug!("{:#}", err),
+    }
+    std::io::Write::flush(&mut std::io::stdout())?;
+
+    let revision = match meta.base() {
+        Some(base) => format!("{}..{}", base.oid, branch),
+        None => branch.to_string(),
+    };
+    let mut command = git::command(workdir);
+    command.args(theme::diff_options(color)).args([
+        "log",
+        "--color=always",
+        "--stat",
+        "-n",
+        "20",
+        &revision,
+        "--",
+    ]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(command.exec()).with_context(|| "Failed to run git log")
+    }
+    #[cfg(not(unix))]
+    {
+        command
+            .status()
+            .with_context(|| "Failed to run git log")
+            .map(|_| ())
+    }
+}
+
+/// Moves the keyboard focus to the preview to search within it, see `--preview`
+const PREVIEW_SEARCH_KEY: &str = "alt-/:preview-search";
+
+/// Show the branch picker and return the picked branches.
+///
+/// With `--by-remote`, a remote is picked first and only its branches are listed.
+/// No branches are returned when the user declined to pick from a fallback list.
+fn select_branches(meta: &RepoMeta, options: &PickerOptions, multi: bool) -> Result<Picked> {
+    let mut picker = Picker::default();
+    if !options.resume {
+        return select_branches_with(meta, options, multi, &mut picker, None);
+    }
+
+    // `--local-only` and `--remote-only` win over the kind of branches shown last time
+    let state = resume::load(meta.repo());
+    let options = PickerOptions {
+        branch_filter: options.branch_filter.or(state.branch_filter()),
+        ..options.clone()
+    };
+    select_branches_with(meta, &options, multi, &mut picker, Some(&state))
+}
+
+/// Show the picker of the branches, starting where `resumed` was left if given
+fn select_branches_with(
+    meta: &RepoMeta,
+    options: &PickerOptions,
+    multi: bool,
+    picker: &mut Picker,
+    resumed: Option<&resume::State>,
+) -> Result<Picked> {
+    let repo = meta.repo();
+    let mut branch_filter = options.branch_filter;
+    let multi = multi || options.save_set.is_some() || options.load_set.is_some();
+    // only in the picker that checks out, as tab toggles the selection of the others
+    let tabbed = options.tabs && !multi && !options.open_web && !options.at;
+    let tab = options.listing.tab;
+    let preset = match &options.load_set {
+        Some(name) => sets::load(repo, name)?,
+        None => vec![],
+    };
+
+    let has_remotes = !meta.remotes().is_empty();
+    if !has_remotes && branch_filter == Some(BranchType::Remote) {
+        eprintln!(
+            "This repository has no remotes configured, so there are no remote branches to show."
+        );
+        if !prompt::confirm("Show local branches instead?", true)? {
+            return Ok(Picked {
+                action: None,
+                command: None,
+                branches: vec![],
+                create: None,
+                worktree: None,
+            });
+        }
+        branch_filter = Some(BranchType::Local);
+    }
+
+    let remote = if options.by_remote && has_remotes && branch_filter != Some(BranchType::Local) {
+        let remote = select_source(
+            meta,
+            picker,
+            branch_filter,
+            options.accessible,
+            options.keys.keymap,
+        )?;
+        branch_filter = Some(match remote {
+            Some(_) => BranchType::Remote,
+            None => BranchType::Local,
+        });
+        remote
+    } else {
+        None
+    };
+
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    // --unpushed keeps the branches by how far they are ahead, so it waits for every count
+    if let Some(limit) = options.budget.filter(|_| !options.listing.unpushed) {
+        meta.set_budget(limit);
+    }
+    let timer = perf::Timer::start();
+    let mut branches = collect_branches(
+        meta,
+        branch_filter,
+        remote.as_deref(),
+        options.sort,
+        &options.listing,
+    )?;
+    let slow = timer.elapsed() >= maintenance::SLOW;
+    timer.record(options.perf, repo, Stage::Collect, Some(branches.len()));
+    let timer = perf::Timer::start();
+    enrich_branches(meta, &mut branches, &options.fields);
+    if options.size {
+        diffstat::attach_cached(meta, &mut branches);
+    }
+    if options.signatures {
+        signing::attach_cached(meta, &mut branches);
+    }
+    if let Some(view) = &options.age {
+        age::attach(repo, view, &mut branches);
+    }
+    timer.record(options.perf, repo, Stage::Enrich, Some(branches.len()));
+    for name in &preset {
+        if !branches
+            .iter()
+            .any(|branch| branch.output() == name.as_str())
+        {
+            eprintln!("warning: {} from the set is not in the list", name);
+        }
+    }
+    // those whose sizes and signatures are looked up in the background
+    let looked_up = if options.size || options.signatures {
+        branches.clone()
+    } else {
+        vec![]
+    };
+    let initial_cursor = match resumed {
+        Some(_) => None,
+        None => cursor::initial(repo, options.initial_cursor, &branches),
+    };
+    let widen = match tab {
+        Tab::Branches => Widen::offered(branch_filter, has_remotes),
+        _ => vec![],
+    };
+    let items = if branches.is_empty() && tab != Tab::Branches {
+        empty::notice(tab.empty())
+    } else if branches.is_empty() {
+        empty::items(branch_filter, remote.as_deref(), has_remotes, &widen)
+    } else {
+        picker_items(repo, branches, options.sort, options.gitflow.as_ref())
+    };
+    for item in items {
+        let _ = tx.send(item);
+    }
+    drop(tx);
+
+    let placeholders = Placeholders::of(repo);
+    let prompt = options
+        .prompt
+        .as_deref()
+        .map(|prompt| placeholders.expand(prompt));
+    let mut header: Vec<String> = tabbed
+        .then(|| tab.header())
+        .into_iter()
+        .chain(
+            options
+                .header
+                .as_deref()
+                .map(|header| placeholders.expand(header)),
+        )
+        .collect();
+    header.extend(no_remotes_note(has_remotes, branch_filter).map(str::to_string));
+    let badges = state::badges(repo);
+    if !badges.is_empty() {
+        header.push(badges.join(" "));
+    }
+    let header = header.join("\n");
+
+    let mut info = match tab {
+        Tab::Branches => describe_branches(branch_filter, remote.as_deref(), options.sort),
+        tab => tab.title().to_string(),
+    };
+    if repo.is_shallow() {
+        info.push_str(" · shallow clone, --deepen for ahead/behind");
+    }
+    if slow && !maintenance::Counts::of(repo).excess().is_empty() {
+        info.push_str(" · slow, gibra maintenance packs the loose refs");
+    }
+    if let Some(base) = meta.base() {
+        info.push_str(&format!(" · compared with {}", base.name));
+    }
+    for containment in contains::describe(&options.listing.contains, &options.listing.no_contains) {
+        info.push_str(&format!(" · {}", containment));
+    }
+    let (active, inactive) = (
+        &options.listing.active_since,
+        &options.listing.inactive_since,
+    );
+    for window in since::describe(active.as_ref(), inactive.as_ref()) {
+        info.push_str(&format!(" · {}", window));
+    }
+    if options.listing.unpushed {
+        info.push_str(" · unpushed");
+    }
+    if options.listing.ls_remote {
+        info.push_str(" · ls-remote");
+    }
+    if options.listing.special_refs {
+        info.push_str(" · special refs");
+    }
+    if let Some(view) = &options.age {
+        info.push_str(&format!(" · {}", view.describe()));
+    }
+
+    let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+    let preview = preview_command(workdir, options.activity, meta.base(), &options.theme);
+
+    let mut builder = SkimOptionsBuilder::default();
+    if let Some(prompt) = &prompt {
+        builder.prompt(Some(prompt));
+    }
+    if !preset.is_empty() {
+        builder.selector(Some(Rc::new(DefaultSkimSelector::default().preset(preset))));
+    }
+    if let Some(state) = resumed {
+        builder
+            .query(Some(&state.query))
+            .current(state.current.as_deref());
+    } else if initial_cursor.is_some() {
+        builder.current(initial_cursor.as_deref());
+    }
+    let menu_key = format!("{}:menu", options.keys.menu);
+    let emitted: Vec<String> = Action::bindings(&options.keys)
+        .into_iter()
+        .chain(Widen::bindings(&widen))
+        .collect();
+    let mut bindings = vec![menu_key.as_str()];
+    bindings.extend(emitted.iter().map(String::as_str));
+    if options.preview {
+        builder.preview(Some(&preview));
+        bindings.push(PREVIEW_SEARCH_KEY);
+    }
+    bindings.extend(options.keys.bind.iter().map(String::as_str));
+    keymap::apply(&mut builder, options.keys.keymap, bindings);
+    options.theme.apply(&mut builder);
+    let mut skim_options = builder
+        .header(Some(&header))
+        .info(Some(&info))
+        .expect(Some(
+            std::iter::once(Action::expect_keys(
+                &options.keys,
+                options.gitflow.is_some(),
+            ))
+            .chain(Widen::expect_keys(&widen).into_iter().map(String::from))
+            .chain(
+                CustomCommand::expect_keys(&options.commands)
+                    .into_iter()
+                    .map(String::from),
+            )
+            .chain(
+                options
+                    .age
+                    .iter()
+                    .flat_map(|_| age::View::expect_keys().map(String::from)),
+            )
+            .chain(
+                tabbed
+                    .then(Tab::expect_keys)
+                    .into_iter()
+                    .flatten()
+                    .map(String::from),
+            )
+            .collect::<Vec<_>>()
+            .join(","),
+        ))
+        .multi(multi)
+        .nosort(options.no_sort)
+        .tac(options.tac)
+        .accessible(options.accessible)
+        .truncate(options.truncate.into())
+        .menu(
+            Action::menu(&options.keys, options.gitflow.is_some())
+                .into_iter()
+                .chain(CustomCommand::menu(&options.commands))
+                .collect(),
+        )
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    // the branches are listed again when they change, when newer pull requests were fetched, when
+    // their sizes or the counts left by the budget were computed, or once an action of the bus
+    // is done
+    let (tx_reload, rx_reload) = unbounded();
+    let (tx_done, rx_done) = unbounded();
+    let (tx_status, rx_status) = unbounded();
+    let _watcher = options
+        .watch
+        .map(|interval| Watcher::spawn(repo.path(), interval, tx_reload.clone()));
+    if let Some(github) = &options.github {
+        pulls::refresh_in_background(meta, github, tx_reload.clone());
+    }
+    if options.size {
+        diffstat::compute_in_background(meta, &looked_up, tx_reload.clone());
+    }
+    if options.signatures {
+        signing::check_in_background(meta, &looked_up, tx_reload.clone());
+    }
+    if let Some(budget) = meta.budget() {
+        budget.count_in_background(repo.path(), tx_reload.clone());
+    }
+    let bus = Bus::spawn(
+        bus::Context {
+            git_dir: repo.path().to_path_buf(),
+            workdir: workdir.to_path_buf(),
+            skip_hooks: options.checkout.skip_hooks,
+            pulls: pulls::cached(meta),
+            policy: Policy::new(&options.policies, repo)?,
+        },
+        tx_status,
+        tx_done,
+    );
+    skim_options.emit = Some(bus.emitter());
+    skim_options.done = Some(rx_done);
+    skim_options.status = Some(rx_status);
+    skim_options.reload = Some(rx_reload);
+    // only when a single branch is picked to check it out
+    if !multi && !options.open_web && !options.at && tab == Tab::Branches {
+        skim_options.query_item = shortcut::query_item(
+            meta,
+            &options.shortcuts,
+            &options.naming,
+            options.checkout.fresh_base,
+        );
+    }
+    skim_options.cmd_collector = Rc::new(RefCell::new(BranchCollector {
+        git_dir: repo.path().to_path_buf(),
+        branch_filter,
+        remote: remote.clone(),
+        sort: options.sort,
+        gitflow: options.gitflow.clone(),
+        size: options.size,
+        signatures: options.signatures,
+        age: options.age.clone(),
+        fields: options.fields.clone(),
+        listing: options.listing.clone(),
+        base: options.base.clone(),
+    }));
+
+    let output = picker.run_and_then(&skim_options, rx, |output| {
+        if options.resume {
+            resume::save(repo, &resume::State::of(output, options.branch_filter));
+        }
+    });
+    skim_options.emit = None;
+    bus.finish();
+    let key = match output.as_ref().map(|out| &out.final_event) {
+        Some(Event::EvActAccept(Some(key))) => Some(key.as_str()),
+        _ => None,
+    };
+    if key.and_then(|key| Widen::from_key(&widen, key)) == Some(Widen::ShowAll) {
+        let options = PickerOptions {
+            branch_filter: None,
+            by_remote: false,
+            ..options.clone()
+        };
+        return select_branches_with(meta, &options, multi, picker, None);
+    }
+    let toggled = options
+        .age
+        .as_ref()
+        .zip(key)
+        .and_then(|(view, key)| view.toggled(key));
+    if let (Some(view), Some(output)) = (toggled, &output) {
+        // the same picker again, with the query and the cursor kept
+        let state = resume::State::of(output, branch_filter);
+        let options = PickerOptions {
+            branch_filter,
+            age: Some(view),
+            ..options.clone()
+        };
+        return select_branches_with(meta, &options, multi, picker, Some(&state));
+    }
+    let switched = key.filter(|_| tabbed).and_then(|key| tab.switched(key));
+    if let (Some(switched), Some(output)) = (switched, &output) {
+        // the other tab where it was left, this one kept for coming back
+        picker
+            .tabs
+            .leave(tab, resume::State::of(output, branch_filter));
+        let state = picker.tabs.state(switched).cloned();
+        let mut options = options.clone();
+        options.listing.tab = switched;
+        return select_branches_with(meta, &options, multi, picker, state.as_ref());
+    }
+    let action = key.and_then(|key| Action::from_key(&options.keys, key));
+    let command = key
+        .filter(|_| action.is_none())
+        .and_then(|key| CustomCommand::from_key(&options.commands, key));
+
+    let selected_items = output.map(|out| out.selected_items).unwrap_or_default();
+    let create = selected_items
+        .iter()
+        .find_map(|selected_item| (**selected_item).as_any().downcast_ref::<NewBranch>());
+    if let (Some(create), None, None) = (create, action, command) {
+        return Ok(Picked {
+            action: None,
+            command: None,
+            branches: vec![],
+            create: Some(create.clone()),
+            worktree: None,
+        });
+    }
+    let selected_branches: Vec<Branch> = selected_items
+        .iter()
+        .filter_map(|selected_item| (**selected_item).as_any().downcast_ref::<Branch>())
+        .cloned()
+        .collect();
+
+    if selected_branches.is_empty() {
+        bail!("Failed to get selected branch");
+    }
+    // the path of a worktree is its note, see [`source::ItemSource::Worktrees`]
+    let worktree = (tab == Tab::Worktrees)
+        .then(|| match &selected_branches[0] {
+            Branch::Ref(branch) => branch.note.as_ref().map(PathBuf::from),
+            _ => None,
+        })
+        .flatten();
+
+    if let Some(name) = &options.save_set {
+        let names: Vec<String> = selected_branches
+            .iter()
+            .cloned()
+            .map(Branch::name)
+            .collect();
+        let path = sets::save(repo, name, &names)?;
+        eprintln!(
+            "Saved {} branches as {} ({})",
+            names.len(),
+            name,
+            path.display()
+        );
+    }
+
+    Ok(Picked {
+        action,
+        command,
+        branches: selected_branches,
+        create: None,
+        worktree,
+    })
+}
+
+/// Run the command of `[commands]` at `index` on the picked branches, see [`custom`]
+fn run_command(
+    repo: &Repository,
+    workdir: &Path,
+    options: &PickerOptions,
+    index: usize,
+    branches: &[Branch],
+) -> Result<()> {
+    let branches = batch(branches);
+    let values: Vec<BranchValues> = branches.iter().map(Branch::print_values).collect();
+    custom::run(repo, workdir, &options.commands[index], &values, options)
+}
+
+fn run_action(
+    meta: &RepoMeta,
+    workdir: &Path,
+    options: &PickerOptions,
+    action: Action,
+    branches: &[Branch],
+) -> Result<()> {
+    let repo = meta.repo();
+    match action {
+        Action::FastForward => {
+            let mut names = vec![];
+            for branch in branches {
+                match branch {
+                    Branch::Local(branch) => names.push(branch.name.clone()),
+                    Branch::Remote(branch) => {
+                        eprintln!("{} is a remote branch, skipping", branch.name)
+                    }
+                    Branch::Ref(reference) => {
+                        eprintln!("{} is not a branch, skipping", reference.name)
+                    }
+                }
+            }
+            upstream::fast_forward_each(repo, workdir, &names, options.checkout.skip_hooks)?;
+        }
+        Action::FetchSelected => {
+            let mut names = vec![];
+            for branch in branches {
+                match branch {
+                    Branch::Remote(branch) => names.push(branch.name.clone()),
+                    Branch::Local(branch) => {
+                        eprintln!("{} is a local branch, skipping", branch.name)
+                    }
+                    Branch::Ref(reference) => {
+                        eprintln!("{} is not a branch, skipping", reference.name)
+                    }
+                }
+            }
+            let (report, _) =
+                bus::fetch_selected(repo, &names, |progress| eprintln!("{}", progress));
+            for line in report {
+                eprintln!("{}", line);
+            }
+        }
+        Action::Delete => {
+            let mut names = vec![];
+            for branch in branches {
+                match branch {
+                    Branch::Local(branch) => names.push(branch.name.clone()),
+                    Branch::Remote(branch) => {
+                        eprintln!("{} is a remote branch, skipping", branch.name)
+                    }
+                    Branch::Ref(reference) => {
+                        eprintln!("{} is not a branch, skipping", reference.name)
+                    }
+                }
+            }
+
+            let pulls = pulls::current(meta, options.github.as_ref());
+            let policy = Policy::new(&options.policies, repo)?;
+            delete::delete(repo, workdir, &names, &pulls, &policy)?;
+        }
+        Action::Finish => {
+            let gitflow = Gitflow::new(repo, &options.gitflow.clone().unwrap_or_default());
+            let (policy, pulls) = (Policy::new(&options.policies, repo)?, pulls::cached(meta));
+            for branch in branches {
+                match branch {
+                    Branch::Local(branch) => {
+                        let target = Target {
+                            name: &branch.name,
+                            pull: pulls.of_local(&branch.name),
+                        };
+                        if !policy.allow(Operation::Finish, target)? {
+                            eprintln!("Keeping {}", branch.name);
+                            continue;
+                        }
+                        let targets = gitflow.targets(gitflow.role(&branch.name));
+                        if !conflicts::confirm(repo, &branch.name, &targets)? {
+                            eprintln!("Keeping {}", branch.name);
+                            continue;
+                        }
+                        let skip_hooks = options.checkout.skip_hooks;
+                        gitflow::finish(repo, workdir, &gitflow, &branch.name, skip_hooks, options)?
+                    }
+                    Branch::Remote(branch) => {
+                        eprintln!("{} is a remote branch, skipping", branch.name)
+                    }
+                    Branch::Ref(reference) => {
+                        eprintln!("{} is not a branch, skipping", reference.name)
+                    }
+                }
+            }
+        }
+        Action::Web => web::open(meta, &options.web, branches)?,
+        Action::SetUpstream => {
+            let names = local_names(branches);
+            if names.is_empty() {
+                return Ok(());
+            }
+
+            let remote = select_source(
+                meta,
+                &mut Picker::default(),
+                Some(BranchType::Remote),
+                options.accessible,
+                options.keys.keymap,
+            )?
+            .with_context(|| "Failed to get selected remote")?;
+            upstream::track_all(repo, &remote, &names)?;
+        }
+        Action::SetDefault => {
+            let refname = match branches {
+                [Branch::Local(LocalBranch {
+                    remote_name: Some(upstream),
+                    ..
+                })] => format!("refs/remotes/{}", upstream),
+                [Branch::Local(branch)] => bail!("{} has no upstream", branch.name),
+                [Branch::Remote(branch)] => format!("refs/remotes/{}", branch.name),
+                [Branch::Ref(reference)] => bail!("{} is not a branch", reference.name),
+                _ => bail!("Pick a single branch to make it the default branch"),
+            };
+            default_branch::run(repo, options.github.as_ref(), &refname)?;
+        }
+        Action::SetPushRemote => {
+            let names = local_names(branches);
+            if names.is_empty() {
+                return Ok(());
+            }
+
+            let remote = select_source(
+                meta,
+                &mut Picker::default(),
+                Some(BranchType::Remote),
+                options.accessible,
+                options.keys.keymap,
+            )?
+            .with_context(|| "Failed to get selected remote")?;
+            push_remote::set(repo, &names, &remote)?;
+        }
+        Action::BranchOff => match branches {
+            [branch] => history::branch_off(repo, workdir, branch, options)?,
+            _ => bail!("Pick a single branch to branch off one of its commits"),
+        },
+    }
+
+    Ok(())
+}
+
+/// The picked `branches` a command runs on. With several, the special refs are left out, as they
+/// are only listed to see the state of the repository, see [`refs::Special`]
+fn batch(branches: &[Branch]) -> Vec<Branch> {
+    if branches.len() < 2 {
+        return branches.to_vec();
+    }
+    branches
+        .iter()
+        .filter(|branch| match branch {
+            Branch::Ref(RefBranch {
+                name,
+                special: Some(special),
+                ..
+            }) => {
+                eprintln!("{} is a {} ref, skipping", name, special.badge());
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// The names of the local `branches`, telling which of the others are skipped
+fn local_names(branches: &[Branch]) -> Vec<String> {
+    let mut names = vec![];
+    for branch in branches {
+        match branch {
+            Branch::Local(branch) => names.push(branch.name.clone()),
+            Branch::Remote(branch) => eprintln!("{} is a remote branch, skipping", branch.name),
+            Branch::Ref(reference) => eprintln!("{} is not a branch, skipping", reference.name),
+        }
+    }
+    names
+}
+
+/// Whether `command` opens a picker, which without one is the branch picker unless `filter`
+fn opens_picker(command: &Option<Commands>, filter: bool) -> bool {
+    match command {
+        None => !filter,
+        Some(command) => matches!(
+            command,
+            Commands::Ws { .. }
+                | Commands::Exec { .. }
+                | Commands::Issue { .. }
+                | Commands::Merge { .. }
+                | Commands::Upstream
+                | Commands::Review { .. }
+        ),
+    }
+}
+
+/// The repository opened at startup, or why there is none, after fetching its remotes with
+/// `fetch`
+fn open_repository(context: &Result<RepoContext>, fetch: bool) -> Result<&RepoContext> {
+    // the expected failures are kept, so that they are reported with their hint
+    let context = context
+        .as_ref()
+        .map_err(|err| match err.downcast_ref::<GibraError>() {
+            Some(error) => anyhow!(error.clone()),
+            None => anyhow!("{:#}", err),
+        })?;
+
+    if fetch {
+        fetch_remotes(&context.repo)?;
+    }
+
+    Ok(context)
+}
+
+/// The facts about `repo`, comparing the branches with `--base` when given
+fn repo_meta<'a>(repo: &'a Repository, base: Option<&str>) -> Result<RepoMeta<'a>> {
+    let meta = RepoMeta::new(repo);
+    match base {
+        Some(base) => meta.with_base(base),
+        None => Ok(meta),
+    }
+}
+
+/// What happened to the picked `branches` since they were listed, as another process may have
+/// deleted them or moved them to another commit in the meantime
+fn changed_since_listed(repo: &Repository, branches: &[Branch]) -> Vec<String> {
+    branches
+        .iter()
+        .filter_map(|branch| {
+            let (name, listed) = match branch {
+                Branch::Local(branch) => (&branch.name, branch.tip?),
+                Branch::Remote(branch) => (&branch.name, branch.tip?),
+                Branch::Ref(reference) => (&reference.name, reference.tip?),
+            };
+            match diffstat::tip(repo, branch) {
+                Some(tip) if tip == listed => None,
+                Some(tip) => Some(format!("{} moved from {:.7} to {:.7}", name, listed, tip)),
+                None => Some(format!("{} was deleted", name)),
+            }
+        })
+        .collect()
+}
+
+/// Pick branches like [`select_branches`], making sure that they are still where they were
+/// listed. When one of them was deleted or moved while the picker was open, nothing is done with
+/// them, and the picker is offered again with the branches as they are now.
+fn select_unchanged_branches<'a>(
+    repo: &'a Repository,
+    options: &PickerOptions,
+    multi: bool,
+) -> Result<(RepoMeta<'a>, Picked)> {
+    loop {
+        let meta = repo_meta(repo, options.base.as_deref())?;
+        let picked = select_branches(&meta, options, multi)?;
+        let changes = changed_since_listed(repo, &picked.branches);
+        if changes.is_empty() {
+            return Ok((meta, picked));
+        }
+
+        for change in &changes {
+            eprintln!("{} since it was listed", change);
+        }
+        if !prompt::confirm("The branches changed, list them again?", true)? {
+            bail!("Nothing was done, as the picked branches changed");
+        }
+    }
+}
+
+fn switch_branch(context: &RepoContext, options: &PickerOptions) -> Result<()> {
+    let (repo, git_root) = (&context.repo, context.workdir.as_path());
+    state::ensure_no_operation_in_progress(repo, git_root, options.checkout.force)?;
+
+    let (meta, picked) = select_unchanged_branches(repo, options, false)?;
+    if let Some(branch) = &picked.create {
+        return create_from_shortcut(repo, git_root, branch, &options.naming);
+    }
+    // printed for the shell to cd into, as a process can't change its directory
+    if let (Some(path), None, None) = (&picked.worktree, picked.action, picked.command) {
+        println!("{}", path.display());
+        return Ok(());
+    }
+    if let Some(action) = picked.action {
+        return run_action(&meta, git_root, options, action, &picked.branches);
+    }
+    if let Some(index) = picked.command {
+        return run_command(repo, git_root, options, index, &picked.branches);
+    }
+    if options.save_set.is_some() {
+        return Ok(());
+    }
+    if options.open_web {
+        return web::open(&meta, &options.web, &picked.branches);
+    }
+    if picked.branches.len() > 1 {
+        bail!("Pick a single branch to check out, or press the key of an action to run it on all");
+    }
+    if let (true, Some(branch)) = (options.at, picked.branches.first()) {
+        return history::run(repo, git_root, branch, options);
+    }
+    if let (true, Some(branch)) = (options.temp, picked.branches.first()) {
+        let tip = diffstat::tip(repo, branch).ok_or_else(|| GibraError::RefMissing {
+            name: branch.output().into_owned(),
+        })?;
+        let path = temp::create(git_root, &branch.output(), tip)?;
+        println!("{}", path.display());
+        return Ok(());
+    }
+
+    if let Some(selected_branch) = picked.branches.first() {
+        ensure_not_checked_out_elsewhere(repo, selected_branch)?;
+        let policy = Policy::new(&options.policies, repo)?;
+        if let Some(target) = policy_target(selected_branch, &pulls::cached(&meta)) {
+            if !policy.allow(Operation::Checkout, target)? {
+                bail!("Cancelled, nothing was checked out");
+            }
+        }
+        let timer = perf::Timer::start();
+        checkout(repo, git_root, selected_branch, &options.checkout)
+            .with_context(|| "Failed to checkout branch")?;
+        timer.record(options.perf, repo, Stage::Checkout, None);
+    }
+
+    Ok(())
+}
+
+/// Print the branches matching `query` with the matcher of the picker, best match first, as
+/// `format` when given, and return whether any matched
+fn filter_branches(
+    meta: &RepoMeta,
+    options: &PickerOptions,
+    query: &str,
+    format: Option<&Format>,
+) -> Result<bool> {
+    let branches: Vec<Arc<dyn SkimItem>> = list_branches(
+        meta,
+        options.branch_filter,
+        None,
+        options.sort,
+        &options.fields,
+        &options.listing,
+    )?
+    .into_iter()
+    .map(|branch| Arc::new(branch) as Arc<dyn SkimItem>)
+    .collect();
+    let skim_options = SkimOptionsBuilder::default()
+        .nosort(options.no_sort)
+        .tac(options.tac)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    let matched = Skim::filter(&skim_options, query, branches);
+    let placeholders = Placeholders::of(meta.repo());
+    for item in &matched {
+        match (format, (**item).as_any().downcast_ref::<Branch>()) {
+            (Some(format), Some(branch)) => {
+                println!("{}", format.render(&placeholders, &branch.print_values()))
+            }
+            _ => println!("{}", item.output()),
+        }
+    }
+
+    Ok(!matched.is_empty())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        std::process::exit(error::report(&err));
+    }
+}
+
+fn run() -> Result<()> {
+    let args = Args::parse();
+
+    // before anything that could fail for one of the reasons it checks, like a broken config file
+    if let Some(Commands::Doctor) = args.command {
+        return doctor::run();
+    }
+
+    if let Some(size) = &args.tmux {
+        if tmux::is_available() {
+            let code = tmux::run_in_popup(size)?;
+            std::process::exit(code);
+        }
+    }
+
+    let branch_filter;
+    if args.remote_only {
+        branch_filter = Some(BranchType::Remote);
+    } else if args.local_only {
+        branch_filter = Some(BranchType::Local);
+    } else {
+        branch_filter = None;
+    }
+
+    // opened once for all that follows, the subcommands that need no repository run without one
+    let context = RepoContext::current();
+    // before the config, which the segment doesn't need, as it has to be quick
+    if let Some(Commands::PromptSegment {
+        shell,
+        plain,
+        recent,
+        refresh,
+    }) = args.command
+    {
+        return match &context {
+            Ok(context) => {
+                segment::run(&context.repo, &context.head, shell, plain, recent, refresh)
+            }
+            Err(_) => Ok(()),
+        };
+    }
+    if args.deepen {
+        let context = open_repository(&context, false)?;
+        remote::unshallow(&context.repo, &context.workdir)?;
+    }
+
+    let config = Config::load(context.as_ref().ok().map(|context| &context.repo))?;
+    let options = PickerOptions {
+        branch_filter,
+        fetch: args.fetch,
+        by_remote: args.by_remote,
+        sort: args.sort,
+        watch: args.watch.map(Duration::from_secs),
+        save_set: args.save_set,
+        load_set: args.load_set,
+        gitflow: args.gitflow.then(|| config.gitflow.clone()),
+        open_web: args.web.is_some(),
+        at: args.at,
+        temp: args.temp,
+        web: WebConfig {
+            view: args.web.unwrap_or(config.web.view),
+            ..config.web.clone()
+        },
+        preview: args.preview || args.activity.is_some(),
+        activity: args.activity,
+        size: args.size,
+        signatures: args.signatures,
+        age: (args.age || config.layout.age).then(|| age::View {
+            format: age::date_format(config.layout.date_format.as_deref()),
+            ..age::View::default()
+        }),
+        base: args.base,
+        no_sort: args.no_sort,
+        tac: args.tac,
+        fields: Fields::new(args.with_nth.as_deref(), args.nth.as_deref())?,
+        listing: Listing {
+            scope: args.scope,
+            ref_globs: args.ref_glob,
+            special_refs: args.special_refs,
+            owners: args.owners,
+            owned_by: args.owned_by,
+            contains: args.contains,
+            no_contains: args.no_contains,
+            active_since: args.active_since,
+            inactive_since: args.inactive_since,
+            unpushed: args.unpushed,
+            verified_only: args.verified_only,
+            ls_remote: args.ls_remote,
+            sources: config.sources.clone(),
+            tab: Tab::Branches,
+        },
+        accessible: args.accessible,
+        prompt: args.prompt,
+        header: args.header,
+        keys: KeysConfig {
+            keymap: args.keymap.unwrap_or(config.keys.keymap),
+            ..config.keys
+        },
+        truncate: args.truncate.unwrap_or(config.layout.truncate),
+        theme: Theme {
+            color: args.color.or_else(|| config.layout.color.clone()),
+            border: args.border.or(config.layout.border),
+        },
+        checkout: CheckoutConfig {
+            autostash: config.checkout.autostash || args.autostash,
+            skip_hooks: config.checkout.skip_hooks || args.no_verify,
+            fresh_base: config.checkout.fresh_base || args.fresh_base,
+            fetch_picked: args.ls_remote,
+            force: args.force,
+            yes: args.yes,
+            ..config.checkout.clone()
+        },
+        github: config.issues.github.clone(),
+        perf: config.perf.record,
+        resume: config.picker.resume,
+        initial_cursor: config.picker.initial_cursor,
+        budget: args
+            .budget
+            .or(config.picker.budget)
+            .map(Duration::from_millis),
+        commands: CustomCommand::all(&config.commands),
+        naming: config.naming.clone(),
+        shortcuts: Shortcut::all(&config.shortcuts)?,
+        tabs: args.tabs || config.picker.tabs,
+        policies: config.policies.clone(),
+    };
+
+    // rather than failing to set up the terminal, or waiting for keys that can't come
+    if opens_picker(&args.command, args.filter.is_some()) {
+        headless::ensure_terminal()?;
+    }
+
+    match args.command {
+        Some(Commands::Ws { rescan }) => workspace::run(&config.workspace, &options, rescan),
+        Some(Commands::Exec { command }) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            let (meta, picked) = select_unchanged_branches(repo, &options, true)?;
+            if let Some(action) = picked.action {
+                return run_action(&meta, git_root, &options, action, &picked.branches);
+            }
+            if let Some(index) = picked.command {
+                return run_command(repo, git_root, &options, index, &picked.branches);
+            }
+            if picked.branches.is_empty() {
+                return Ok(());
+            }
+
+            let names: Vec<String> = batch(&picked.branches)
+                .into_iter()
+                .map(Branch::name)
+                .collect();
+            let code = exec::run(&command, &names)?;
+            std::process::exit(code)
+        }
+        Some(Commands::New {
+            name,
+            issue,
+            template,
+        }) => {
+            let name = match &issue {
+                Some(issue) => naming::with_issue_prefix(&config.naming, &name, issue),
+                None => name,
+            };
+            naming::validate(&config.naming, &name)?;
+            let template = template
+                .map(|template| seed::find(&config.templates, &template))
+                .transpose()?;
+
+            let context = open_repository(&context, false)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            state::ensure_no_operation_in_progress(repo, git_root, options.checkout.force)?;
+            let start_point = match &options.gitflow {
+                Some(config) => {
+                    let meta = RepoMeta::new(repo);
+                    gitflow::start_point(&meta, &Gitflow::new(repo, config), &name)?
+                }
+                None if options.checkout.fresh_base => Some(remote::fresh_base(repo)?),
+                None => None,
+            };
+            create_branch(git_root, &name, start_point.as_deref())
+                .with_context(|| "Failed to create branch")?;
+            match template {
+                Some(template) => seed::apply(
+                    git_root,
+                    template,
+                    &seed::Values::new(&name, issue.as_deref()),
+                )
+                .with_context(|| format!("Failed to seed {} from the template", name)),
+                None => Ok(()),
+            }
+        }
+        Some(Commands::Issue { refresh }) => {
+            let context = open_repository(&context, false)?;
+            issue::run(&config.issues, &config.naming, context, &options, refresh)
+        }
+        Some(Commands::Remote { command }) => match command {
+            RemoteCommand::Migrate { old, new, dry_run } => {
+                let context = open_repository(&context, false)?;
+                migrate::run(&context.repo, &old, &new, dry_run)
+            }
+        },
+        Some(Commands::Auth { command }) => match command {
+            AuthCommand::Login { provider } => secrets::login(provider),
+            AuthCommand::Logout { provider } => secrets::logout(provider),
+            AuthCommand::Status => {
+                secrets::status(|provider| match provider {
+                    Provider::Github => config
+                        .issues
+                        .github
+                        .as_ref()
+                        .and_then(|github| github.token.clone()),
+                    Provider::Jira => config
+                        .issues
+                        .jira
+                        .as_ref()
+                        .and_then(|jira| jira.token.clone()),
+                });
+                Ok(())
+            }
+        },
+        Some(Commands::Preview { workdir, branch }) => preview(
+            &workdir,
+            &branch,
+            options.activity,
+            options.base.as_deref(),
+            options.theme.color.as_deref(),
+        ),
+        Some(Commands::Review { refresh }) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            let meta = RepoMeta::new(repo);
+            review::run(&meta, git_root, options.github.as_ref(), &options, refresh)
+        }
+        Some(Commands::Verify { branch, json }) => {
+            let context = open_repository(&context, false)?;
+            if !verify::run(&context.repo, &branch, options.fetch, json)? {
+                std::process::exit(error::MISSING);
+            }
+            Ok(())
+        }
+        Some(Commands::ReviewPreview { workdir, number }) => {
+            review::preview(&workdir, &number, options.theme.color.as_deref())
+        }
+        Some(Commands::Merge { no_ff }) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            state::ensure_no_operation_in_progress(repo, git_root, false)?;
+            let (meta, picked) = select_unchanged_branches(repo, &options, true)?;
+            if let Some(action) = picked.action {
+                return run_action(&meta, git_root, &options, action, &picked.branches);
+            }
+            if let Some(index) = picked.command {
+                return run_command(repo, git_root, &options, index, &picked.branches);
+            }
+
+            let names: Vec<String> = picked.branches.into_iter().map(Branch::name).collect();
+            merge::merge(
+                repo,
+                git_root,
+                &names,
+                no_ff,
+                options.checkout.skip_hooks,
+                &options,
+            )
+        }
+        Some(Commands::Perf) => perf::run(options.perf),
+        Some(Commands::Maintenance) => {
+            let context = open_repository(&context, false)?;
+            maintenance::run(&context.repo, &context.workdir)
+        }
+        Some(Commands::Doctor) => unreachable!("gibra doctor runs before the config is loaded"),
+        Some(Commands::PromptSegment { .. }) => {
+            unreachable!("gibra prompt-segment runs before the config is loaded")
+        }
+        Some(Commands::Clean) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            let meta = repo_meta(repo, options.base.as_deref())?;
+            let pulls = pulls::current(&meta, options.github.as_ref());
+            let policy = Policy::new(&options.policies, repo)?;
+            delete::clean(&meta, git_root, &pulls, &policy)
+        }
+        Some(Commands::Undo { name, list }) => {
+            let context = open_repository(&context, false)?;
+            audit::undo(&context.repo, &context.workdir, name.as_deref(), list)
+        }
+        Some(Commands::Prefix { prefix, action }) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            let meta = repo_meta(repo, options.base.as_deref())?;
+            let pulls = pulls::current(&meta, options.github.as_ref());
+            let policy = Policy::new(&options.policies, repo)?;
+            prefix::run(&meta, git_root, prefix.as_deref(), action, &pulls, &policy)
+        }
+        Some(Commands::Upstream) => {
+            let context = open_repository(&context, options.fetch)?;
+            upstream::run(&RepoMeta::new(&context.repo), &options)
+        }
+        Some(Commands::Rename { new, push }) => {
+            let context = open_repository(&context, false)?;
+            state::ensure_no_operation_in_progress(
+                &context.repo,
+                &context.workdir,
+                options.checkout.force,
+            )?;
+            let policy = Policy::new(&options.policies, &context.repo)?;
+            rename::run(
+                context,
+                &config.naming,
+                &policy,
+                new,
+                push,
+                options.checkout.skip_hooks,
+            )
+        }
+        Some(Commands::Temp { gc, older_than }) => {
+            let context = open_repository(&context, false)?;
+            temp::run(
+                &context.repo,
+                &context.workdir,
+                gc,
+                &older_than,
+                options.checkout.force,
+            )
+        }
+        Some(Commands::Sync { ff_all }) => {
+            let context = open_repository(&context, options.fetch)?;
+            let (repo, git_root) = (&context.repo, context.workdir.as_path());
+            let meta = RepoMeta::new(repo);
+            if ff_all {
+                return upstream::fast_forward_all(&meta, git_root, options.checkout.skip_hooks);
+            }
+
+            for branch in meta.local_branches() {
+                let name = &branch.name;
+                match branch.divergence {
+                    Some(divergence) if divergence.label().is_empty() => {
+                        println!("{}\tup to date", name)
+                    }
+                    Some(divergence) => println!("{}\t{}", name, divergence.label()),
+                    None => println!("{}\tno upstream", name),
+                }
+            }
+
+            Ok(())
+        }
+        None => {
+            let context = open_repository(&context, options.fetch)?;
+            if let Some(query) = &args.filter {
+                let format = args
+                    .print_format
+                    .as_deref()
+                    .map(Format::parse)
+                    .transpose()?;
+                let meta = repo_meta(&context.repo, options.base.as_deref())?;
+                if !filter_branches(&meta, &options, query, format.as_ref())? {
+                    std::process::exit(error::NO_MATCH);
+                }
+                return Ok(());
+            }
+            switch_branch(context, &options)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_no_remotes_note() {
+        assert_eq!(no_remotes_note(false, None), Some("no remotes configured"));
+        // `--remote-only` fell back to the local branches, which the prompt already told
+        assert_eq!(no_remotes_note(false, Some(BranchType::Local)), None);
+        assert_eq!(no_remotes_note(true, None), None);
+    }
+
+    #[test]
+    fn test_collect_branches_of_remote() {
+        let repo = TempRepo::new("by-remote");
+        let tip = testing::commit(&repo, Some("refs/heads/main"), "initial", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        // a remote named like the start of another one doesn't list the other's branches
+        for remote in ["origin", "origin-fork"] {
+            repo.remote(remote, "https://example.com/repo.git").unwrap();
+            let refname = format!("refs/remotes/{}/feature", remote);
+            repo.reference(&refname, tip, false, "test").unwrap();
+        }
+
+        let meta = RepoMeta::new(&repo);
+        let listing = Listing::default();
+        let names = |remote| -> Vec<String> {
+            collect_branches(
+                &meta,
+                Some(BranchType::Remote),
+                Some(remote),
+                SortOrder::Name,
+                &listing,
+            )
+            .unwrap()
+            .into_iter()
+            .map(Branch::name)
+            .collect()
+        };
+        assert_eq!(names("origin"), vec!["origin/feature"]);
+        assert_eq!(names("origin-fork"), vec!["origin-fork/feature"]);
+    }
+
+    #[test]
+    fn test_describe_branches() {
+        assert_eq!(
+            describe_branches(None, None, SortOrder::Date),
+            "branches · local+remote · sorted by date"
+        );
+        assert_eq!(
+            describe_branches(Some(BranchType::Local), None, SortOrder::Natural),
+            "branches · local · sorted by natural"
+        );
+        // the remote picked with --by-remote rather than all of them
+        assert_eq!(
+            describe_branches(Some(BranchType::Remote), Some("upstream"), SortOrder::Name),
+            "branches · upstream · sorted by name"
+        );
+    }
+
+    #[test]
+    fn test_watch_interval() {
+        let watch = |args: &[&str]| Args::try_parse_from(args).map(|args| args.watch);
+        assert_eq!(watch(&["gibra"]).unwrap(), None);
+        assert_eq!(watch(&["gibra", "--watch"]).unwrap(), Some(2));
+        assert_eq!(watch(&["gibra", "--watch=5"]).unwrap(), Some(5));
+        // checking all the time would keep a core busy
+        assert!(watch(&["gibra", "--watch=0"]).is_err());
+    }
+
+    #[test]
+    fn test_get_branches_against_base() {
+        let repo = TempRepo::new("base");
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        testing::commit(&repo, Some("refs/heads/release"), "release", &[], &[base]);
+        testing::commit(&repo, Some("refs/heads/feature"), "feature", &[], &[base]);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/feature", base, false, "test")
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("branch.feature.remote", "origin").unwrap();
+        config
+            .set_str("branch.feature.merge", "refs/heads/feature")
+            .unwrap();
+
+        let divergences = |meta: &RepoMeta| -> Vec<(String, Option<(usize, usize)>)> {
+            get_branches(meta, None, false)
+                .unwrap()
+                .iter()
+                .map(|branch| {
+                    let values = branch.print_values();
+                    (values.name, values.divergence)
+                })
+                .collect()
+        };
+        // from the upstream, which the remote branches have none of
+        assert_eq!(
+            divergences(&RepoMeta::new(&repo)),
+            vec![
+                ("feature".to_string(), Some((1, 0))),
+                ("origin/feature".to_string(), None),
+            ]
+        );
+        assert_eq!(
+            divergences(&RepoMeta::new(&repo).with_base("release").unwrap()),
+            vec![
+                ("feature".to_string(), Some((1, 1))),
+                ("origin/feature".to_string(), Some((0, 1))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_changed_since_listed() {
+        let repo = TempRepo::new("changed");
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        for name in ["kept", "moved", "deleted"] {
+            repo.reference(&format!("refs/heads/{}", name), base, false, "test")
+                .unwrap();
+        }
+        let meta = RepoMeta::new(&repo);
+        let listed = get_branches(&meta, Some(BranchType::Local), true).unwrap();
+        assert!(changed_since_listed(&repo, &listed).is_empty());
+
+        let moved = testing::commit(&repo, Some("refs/heads/moved"), "moved", &[], &[base]);
+        repo.find_reference("refs/heads/deleted")
+            .unwrap()
+            .delete()
+            .unwrap();
+        assert_eq!(
+            changed_since_listed(&repo, &listed),
+            vec![
+                "deleted was deleted".to_string(),
+                format!("moved moved from {:.7} to {:.7}", base, moved),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_print_format_of_symbolic_remote_ref() {
+        let repo = TempRepo::new("print-format");
+        let tip = testing::commit(&repo, Some("refs/heads/main"), "initial", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/main", tip, false, "test")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+            false,
+            "test",
+        )
+        .unwrap();
+
+        let meta = RepoMeta::new(&repo);
+        let format = Format::parse("{name} {sha}").unwrap();
+        let printed: Vec<String> = get_branches(&meta, Some(BranchType::Remote), false)
+            .unwrap()
+            .iter()
+            .map(|branch| format.render(&Placeholders::of(&repo), &branch.print_values()))
+            .collect();
+        // `origin/HEAD` has no target of its own, but the commit it points to
+        assert_eq!(
+            printed,
+            vec![
+                format!("origin/HEAD {}", tip),
+                format!("origin/main {}", tip)
+            ]
+        );
+    }
 }
@@ -0,0 +1,154 @@
+//! Packing the refs and objects of a repository (`gibra maintenance`), for when so many of them
+//! are loose that listing the branches gets slow. The picker says so on its info line when
+//! listing was slow, and `gibra doctor` among its checks.
+use crate::git;
+use crate::hooks;
+use anyhow::Result;
+use git2::Repository;
+use std::{fs, path::Path, time::Duration};
+
+/// Listing the branches taking this long is worth looking into
+pub const SLOW: Duration = Duration::from_millis(500);
+
+/// Loose refs are read one file each, unlike those in `packed-refs`
+const MAX_LOOSE_REFS: usize = 1000;
+
+/// The limits of `git gc --auto`, see `gc.auto` and `gc.autoPackLimit`
+const MAX_LOOSE_OBJECTS: usize = 6700;
+const MAX_PACKS: usize = 50;
+
+/// The loose refs and objects of a repository, and its packs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    loose_refs: usize,
+    loose_objects: usize,
+    packs: usize,
+}
+
+/// The files below `dir`, at any depth
+fn count_files(dir: &Path) -> usize {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => count_files(&entry.path()),
+            Ok(file_type) if file_type.is_file() => 1,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// The loose objects in `objects`, which git keeps in directories named after their first two
+/// hex digits
+fn count_loose_objects(objects: &Path) -> usize {
+    let entries = match fs::read_dir(objects) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.len() == 2 && name.chars().all(|ch| ch.is_ascii_hexdigit())
+        })
+        .map(|entry| count_files(&entry.path()))
+        .sum()
+}
+
+fn count_packs(objects: &Path) -> usize {
+    fs::read_dir(objects.join("pack"))
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "pack"))
+                .count()
+        })
+        .unwrap_or(0)
+}
+
+impl Counts {
+    pub fn of(repo: &Repository) -> Self {
+        let common_dir = hooks::common_dir(repo);
+        let objects = common_dir.join("objects");
+        Self {
+            loose_refs: count_files(&common_dir.join("refs")),
+            loose_objects: count_loose_objects(&objects),
+            packs: count_packs(&objects),
+        }
+    }
+
+    /// What there is too much of, e.g. `1500 loose refs`
+    pub fn excess(&self) -> Vec<String> {
+        [
+            (self.loose_refs, MAX_LOOSE_REFS, "loose refs"),
+            (self.loose_objects, MAX_LOOSE_OBJECTS, "loose objects"),
+            (self.packs, MAX_PACKS, "packs"),
+        ]
+        .into_iter()
+        .filter(|(count, max, _)| count > max)
+        .map(|(count, _, what)| format!("{} {}", count, what))
+        .collect()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "loose refs: {}, loose objects: {}, packs: {}",
+            self.loose_refs, self.loose_objects, self.packs
+        )
+    }
+}
+
+/// Pack the refs and run the maintenance tasks of git in the repository at `workdir`, telling
+/// how many loose refs and objects there were before and after
+pub fn run(repo: &Repository, workdir: &Path) -> Result<()> {
+    eprintln!("Before: {}", Counts::of(repo).describe());
+    git::run(workdir, &["pack-refs", "--all"])?;
+    git::run(workdir, &["maintenance", "run"])?;
+    eprintln!("After: {}", Counts::of(repo).describe());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_counts() {
+        let repo = TempRepo::new("maintenance");
+        let tip = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "commit",
+            &[("file", "content")],
+            &[],
+        );
+        repo.reference("refs/heads/feature/a", tip, false, "test")
+            .unwrap();
+
+        let counts = Counts::of(&repo);
+        assert_eq!(
+            counts,
+            Counts {
+                loose_refs: 2,
+                loose_objects: 3,
+                packs: 0,
+            }
+        );
+        assert!(counts.excess().is_empty());
+    }
+
+    #[test]
+    fn test_excess() {
+        let many = Counts {
+            loose_refs: 1500,
+            loose_objects: 0,
+            packs: 51,
+        };
+        assert_eq!(many.excess(), vec!["1500 loose refs", "51 packs"]);
+    }
+}
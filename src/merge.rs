@@ -0,0 +1,302 @@
+//! Merging several branches into the current one at once (`gibra merge`).
+//!
+//! The picked branches are merged with a single octopus merge commit, or one after another with a
+//! merge commit each (`--no-ff`). Before anything is merged, every pair of them, and each with the
+//! current branch, is merged in memory: git's octopus strategy gives up on any conflict, so the
+//! branches that would conflict are named up front instead.
+use crate::error::GibraError;
+use crate::resolve;
+use crate::transaction::Transaction;
+use crate::PickerOptions;
+use anyhow::{bail, Context, Result};
+use git2::{Oid, Repository};
+use std::path::Path;
+
+/// Two of the branches that conflict when merged, and where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Clash {
+    pub ours: String,
+    pub theirs: String,
+    pub paths: Vec<String>,
+}
+
+/// The files that conflict when merging `ours` and `theirs`
+fn conflicting_paths(repo: &Repository, ours: Oid, theirs: Oid) -> Result<Vec<String>> {
+    let index = repo.merge_commits(&repo.find_commit(ours)?, &repo.find_commit(theirs)?, None)?;
+    if !index.has_conflicts() {
+        return Ok(vec![]);
+    }
+
+    let mut paths = vec![];
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict
+            .our
+            .as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref());
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+    paths.sort();
+    paths.dedup();
+
+    Ok(paths)
+}
+
+/// The pairs of `branches`, the current branch being the first, that conflict when merged
+pub fn clashes(repo: &Repository, branches: &[(String, Oid)]) -> Result<Vec<Clash>> {
+    let mut clashes = vec![];
+    for (index, (ours, ours_oid)) in branches.iter().enumerate() {
+        for (theirs, theirs_oid) in &branches[index + 1..] {
+            let paths = conflicting_paths(repo, *ours_oid, *theirs_oid)
+                .with_context(|| format!("Failed to merge {} and {}", ours, theirs))?;
+            if !paths.is_empty() {
+                clashes.push(Clash {
+                    ours: ours.clone(),
+                    theirs: theirs.clone(),
+                    paths,
+                });
+            }
+        }
+    }
+
+    Ok(clashes)
+}
+
+/// e.g. `3 files changed, +40 -5`
+fn diffstat(repo: &Repository, old: Oid, new: Oid) -> Result<String> {
+    let old_tree = repo.find_commit(old)?.tree()?;
+    let new_tree = repo.find_commit(new)?.tree()?;
+    let stats = repo
+        .diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?
+        .stats()?;
+    let files = stats.files_changed();
+    Ok(format!(
+        "{} {} changed, +{} -{}",
+        files,
+        if files == 1 { "file" } else { "files" },
+        stats.insertions(),
+        stats.deletions()
+    ))
+}
+
+/// Merge `names` into the current branch, with one octopus merge commit, or with `no_ff` one
+/// merge commit per branch. The branches already merged are skipped. Nothing is merged when two
+/// of them conflict, and everything is undone when a merge fails.
+pub fn merge(
+    repo: &Repository,
+    workdir: &Path,
+    names: &[String],
+    no_ff: bool,
+    skip_hooks: bool,
+    options: &PickerOptions,
+) -> Result<()> {
+    let head = repo.head().with_context(|| "Failed to get HEAD")?;
+    if !head.is_branch() {
+        bail!("HEAD is detached, check out the branch to merge into first");
+    }
+    let current = head
+        .shorthand()
+        .with_context(|| "Failed to get the current branch")?
+        .to_string();
+    let head_oid = head
+        .target()
+        .with_context(|| "Failed to get the current commit")?;
+
+    let mut branches = vec![(current.clone(), head_oid)];
+    for name in names {
+        let oid = repo
+            .revparse_single(name)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| GibraError::RefMissing {
+                name: name.to_string(),
+            })?
+            .id();
+        if oid == head_oid || repo.graph_descendant_of(head_oid, oid)? {
+            eprintln!("{} is merged into {} already, skipping", name, current);
+        } else {
+            branches.push((name.clone(), oid));
+        }
+    }
+    if branches.len() == 1 {
+        bail!("There is nothing to merge into {}", current);
+    }
+
+    let clashes = clashes(repo, &branches)?;
+    if !clashes.is_empty() {
+        for clash in &clashes {
+            eprintln!(
+                "{} and {} conflict in {}",
+                clash.ours,
+                clash.theirs,
+                clash.paths.join(", ")
+            );
+        }
+        bail!("Not merging, resolve the conflicts between the branches first");
+    }
+
+    let merged: Vec<&str> = branches[1..]
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let mut transaction = Transaction::begin(repo, workdir, "merge");
+    if skip_hooks {
+        transaction.skip_hooks();
+    }
+    let undo = vec![vec![
+        "reset".to_string(),
+        "--hard".to_string(),
+        head_oid.to_string(),
+    ]];
+    let steps: Vec<Vec<&str>> = if no_ff {
+        merged.iter().map(|&name| vec![name]).collect()
+    } else {
+        vec![merged.clone()]
+    };
+    for step in steps {
+        let mut args = vec!["merge", "--no-edit"];
+        if no_ff {
+            args.push("--no-ff");
+        }
+        args.extend(&step);
+        let what = format!("merge {} into {}", step.join(", "), current);
+        if let Err(err) = transaction.run(&what, &args, undo.clone()) {
+            resolve::conclude(
+                &mut transaction,
+                repo,
+                workdir,
+                &what,
+                undo.clone(),
+                options,
+                err,
+            )?;
+        }
+    }
+    transaction.commit()?;
+
+    let new_head = repo.refname_to_id("HEAD")?;
+    let commit = repo.find_commit(new_head)?;
+    eprintln!(
+        "Merged {} into {}: {:.7} {}",
+        merged.join(", "),
+        current,
+        new_head,
+        commit.summary().unwrap_or("")
+    );
+    if !no_ff {
+        eprintln!("  {} parents", commit.parent_count());
+    }
+    eprintln!("  {}", diffstat(repo, head_oid, new_head)?);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `main` checked out, with `a` and `b` adding a file each, and `c` and `d` changing the same
+    /// one. Returns the tip of `main`.
+    fn branches(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "base",
+            &[("shared", "base\n")],
+            &[],
+        );
+        repo.set_head("refs/heads/main").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        for (name, file) in [("a", "a"), ("b", "b"), ("c", "shared"), ("d", "shared")] {
+            branch(&repo, name, file, base);
+        }
+        (repo, base)
+    }
+
+    /// The branch `name` a commit ahead of `parent`, writing its name into `file`
+    fn branch(repo: &Repository, name: &str, file: &str, parent: Oid) -> Oid {
+        let refname = format!("refs/heads/{}", name);
+        let content = format!("{}\n", name);
+        testing::commit(repo, Some(&refname), name, &[(file, &content)], &[parent])
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_clashing_branches() {
+        let (repo, base) = branches("merge-clashing");
+        let options = PickerOptions::default();
+        assert!(merge(
+            &repo,
+            repo.dir(),
+            &names(&["a", "c", "d"]),
+            false,
+            true,
+            &options
+        )
+        .is_err());
+        assert_eq!(repo.refname_to_id("HEAD").unwrap(), base);
+    }
+
+    #[test]
+    fn test_clashes() {
+        let (repo, _) = branches("merge-clashes");
+        let oid = |name: &str| repo.refname_to_id(&format!("refs/heads/{}", name)).unwrap();
+        assert_eq!(
+            clashes(
+                &repo,
+                &[("c".to_string(), oid("c")), ("d".to_string(), oid("d"))]
+            )
+            .unwrap(),
+            vec![Clash {
+                ours: "c".to_string(),
+                theirs: "d".to_string(),
+                paths: vec!["shared".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_octopus_merge() {
+        let (repo, _) = branches("merge");
+        let options = PickerOptions::default();
+        merge(
+            &repo,
+            repo.dir(),
+            &names(&["a", "b", "c"]),
+            false,
+            true,
+            &options,
+        )
+        .unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_count(), 3);
+        assert_eq!(repo.head().unwrap().shorthand(), Some("main"));
+
+        // merged branches are skipped
+        assert!(merge(&repo, repo.dir(), &names(&["a", "b"]), true, true, &options).is_err());
+    }
+
+    #[test]
+    fn test_merge_one_by_one() {
+        let (repo, base) = branches("merge-no-ff");
+        let options = PickerOptions::default();
+        branch(&repo, "e", "e", base);
+        branch(&repo, "f", "f", base);
+        merge(&repo, repo.dir(), &names(&["e", "f"]), true, true, &options).unwrap();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_count(), 2);
+        assert_eq!(head.parent(0).unwrap().parent(0).unwrap().id(), base);
+    }
+}
@@ -0,0 +1,366 @@
+//! Facts about a repository that several features need, computed on first use and kept for the
+//! rest of the invocation, so that listing, columns, filters and `clean` don't each ask git2 again.
+//!
+//! A [`RepoMeta`] is a snapshot: code that changes branches or remotes and looks at them again
+//! afterwards starts a new one.
+use crate::budget::{self, Budget};
+use crate::error::GibraError;
+use crate::remote;
+use crate::upstream::Divergence;
+use anyhow::{Context, Result};
+use git2::{BranchType, Direction, Oid, Remote, Repository};
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    time::Duration,
+};
+
+/// A local branch and how it relates to its upstream
+#[derive(Clone, Debug)]
+pub struct LocalBranchMeta {
+    pub name: String,
+    pub tip: Option<Oid>,
+    pub is_head: bool,
+    /// e.g. `origin/main`
+    pub upstream: Option<String>,
+    pub divergence: Option<Divergence>,
+}
+
+/// The branch, tag or commit the branches are compared with instead of their upstream and the
+/// default branch (`--base`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Base {
+    /// As given, e.g. `origin/release-2.1`
+    pub name: String,
+    pub oid: Oid,
+}
+
+pub struct RepoMeta<'a> {
+    repo: &'a Repository,
+    base: Option<Base>,
+    remotes: OnceCell<Vec<String>>,
+    default_branches: RefCell<HashMap<String, String>>,
+    local_branches: OnceCell<Vec<LocalBranchMeta>>,
+    budget: OnceCell<Budget>,
+}
+
+impl<'a> RepoMeta<'a> {
+    pub fn new(repo: &'a Repository) -> Self {
+        Self {
+            repo,
+            base: None,
+            remotes: OnceCell::new(),
+            default_branches: RefCell::new(HashMap::new()),
+            local_branches: OnceCell::new(),
+            budget: OnceCell::new(),
+        }
+    }
+
+    pub fn repo(&self) -> &'a Repository {
+        self.repo
+    }
+
+    /// Compare the branches with `name`, a branch, tag or commit, instead of their upstream and
+    /// the default branch
+    pub fn with_base(mut self, name: &str) -> Result<Self> {
+        let oid = self
+            .repo
+            .revparse_single(name)
+            .and_then(|object| object.peel_to_commit())
+            .map_err(|_| GibraError::RefMissing {
+                name: name.to_string(),
+            })
+            .with_context(|| format!("Failed to find the base {}", name))?
+            .id();
+        self.base = Some(Base {
+            name: name.to_string(),
+            oid,
+        });
+
+        Ok(self)
+    }
+
+    /// What the branches are compared with, when given with `--base`
+    pub fn base(&self) -> Option<&Base> {
+        self.base.as_ref()
+    }
+
+    /// The names of the remotes, in the order git lists them
+    pub fn remotes(&self) -> &[String] {
+        self.remotes.get_or_init(|| match self.repo.remotes() {
+            Ok(remotes) => remotes.iter().flatten().map(str::to_string).collect(),
+            Err(err) => {
+                warn!("Failed to get remotes: {:#}", err);
+                vec![]
+            }
+        })
+    }
+
+    /// `origin`, or the first remote when there is no `origin`
+    pub fn primary_remote(&self) -> Option<&str> {
+        let remotes = self.remotes();
+        remotes
+            .iter()
+            .find(|name| *name == "origin")
+            .or_else(|| remotes.first())
+            .map(String::as_str)
+    }
+
+    /// The branch that `HEAD` of `remote` points to, see [`remote::default_branch`]
+    pub fn default_branch(&self, remote: &str) -> String {
+        self.default_branches
+            .borrow_mut()
+            .entry(remote.to_string())
+            .or_insert_with(|| remote::default_branch(self.repo, remote))
+            .clone()
+    }
+
+    /// Count how far the branches diverged within `limit` from now on, leaving the rest for later
+    pub fn set_budget(&self, limit: Duration) {
+        let _ = self.budget.set(Budget::new(limit));
+    }
+
+    pub fn budget(&self) -> Option<&Budget> {
+        self.budget.get()
+    }
+
+    /// How far `tip` diverged from `base`, pending once the budget, if any, is spent
+    pub fn divergence(&self, tip: Oid, base: Oid) -> Option<Divergence> {
+        match self.budget.get() {
+            Some(budget) => budget.divergence(self.repo, tip, base),
+            None => budget::count(self.repo, tip, base),
+        }
+    }
+
+    /// The local branches, with their upstream and how far they diverged from it
+    pub fn local_branches(&self) -> &[LocalBranchMeta] {
+        self.local_branches.get_or_init(|| {
+            let branches = match self.repo.branches(Some(BranchType::Local)) {
+                Ok(branches) => branches,
+                Err(err) => {
+                    warn!("Failed to get local branches: {:#}", err);
+                    return vec![];
+                }
+            };
+            let upstreams = upstream_refnames(self.repo);
+
+            branches
+                .filter_map(|branch| {
+                    let (branch, _) = branch.ok()?;
+                    let name = branch.name().ok()??.to_string();
+                    let upstream_ref = upstreams
+                        .get(&name)
+                        .and_then(|refname| self.repo.find_reference(refname).ok());
+                    let upstream = upstream_ref
+                        .as_ref()
+                        .and_then(|upstream| upstream.shorthand().map(str::to_string));
+                    let divergence = branch
+                        .get()
+                        .target()
+                        .zip(upstream_ref.and_then(|upstream| upstream.resolve().ok()?.target()))
+                        .and_then(|(tip, upstream)| self.divergence(tip, upstream));
+
+                    Some(LocalBranchMeta {
+                        name,
+                        tip: branch.get().target(),
+                        is_head: branch.is_head(),
+                        upstream,
+                        divergence,
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+/// The refname of the upstream of each local branch having one, e.g. `refs/remotes/origin/main`
+/// for `main`, from `branch.<name>.remote` and `branch.<name>.merge`. The config is read once for
+/// all the branches, as [`git2::Branch::upstream`] reads it again for each of them.
+fn upstream_refnames(repo: &Repository) -> HashMap<String, String> {
+    let config = match repo.config().and_then(|mut config| config.snapshot()) {
+        Ok(config) => config,
+        Err(err) => {
+            warn!("Failed to read the config: {:#}", err);
+            return HashMap::new();
+        }
+    };
+    let mut remotes = HashMap::new();
+    let mut merges = HashMap::new();
+    if let Ok(mut entries) = config.entries(Some("^branch\\..*\\.(remote|merge)$")) {
+        while let Some(Ok(entry)) = entries.next() {
+            let variable = entry
+                .name()
+                .and_then(|name| name.strip_prefix("branch."))
+                .and_then(|name| name.rsplit_once('.'));
+            // `value` panics on a variable without a value
+            let value = entry.has_value().then(|| entry.value()).flatten();
+            // the last one wins, like git
+            match (variable, value) {
+                (Some((branch, "remote")), Some(remote)) => {
+                    remotes.insert(branch.to_string(), remote.to_string());
+                }
+                (Some((branch, "merge")), Some(merge)) => {
+                    merges.insert(branch.to_string(), merge.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the branch on the remote is where a fetch refspec of the remote puts it
+    let mut found: HashMap<String, Option<Remote>> = HashMap::new();
+    let mut upstreams = HashMap::new();
+    for (branch, merge) in merges {
+        let remote = match remotes.get(&branch) {
+            Some(remote) => remote,
+            None => continue,
+        };
+        if remote == "." {
+            upstreams.insert(branch, merge);
+            continue;
+        }
+        let remote = found
+            .entry(remote.clone())
+            .or_insert_with(|| repo.find_remote(remote).ok());
+        let refname = remote.as_ref().and_then(|remote| {
+            let refspec = remote.refspecs().find(|refspec| {
+                refspec.direction() == Direction::Fetch && refspec.src_matches(&merge)
+            })?;
+            Some(refspec.transform(&merge).ok()?.as_str()?.to_string())
+        });
+        if let Some(refname) = refname {
+            upstreams.insert(branch, refname);
+        }
+    }
+    upstreams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `main` checked out and tracking `origin/trunk`, the default branch of `origin`, and
+    /// `topic` tracking `main`, besides the remote `upstream`. Returns the commit they are all at.
+    fn tracking(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        repo.remote("upstream", "https://example.com/upstream.git")
+            .unwrap();
+        repo.remote("origin", "https://example.com/origin.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/trunk", base, false, "test")
+            .unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/trunk",
+            false,
+            "test",
+        )
+        .unwrap();
+        repo.reference("refs/heads/topic", base, false, "test")
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        for (name, value) in [
+            ("branch.main.remote", "origin"),
+            ("branch.main.merge", "refs/heads/trunk"),
+            ("branch.topic.remote", "."),
+            ("branch.topic.merge", "refs/heads/main"),
+        ] {
+            config.set_str(name, value).unwrap();
+        }
+        (repo, base)
+    }
+
+    #[test]
+    fn test_remotes() {
+        let (repo, _) = tracking("meta-remotes");
+        let meta = RepoMeta::new(&repo);
+        assert_eq!(meta.primary_remote(), Some("origin"));
+        assert_eq!(meta.default_branch("origin"), "trunk");
+    }
+
+    #[test]
+    fn test_local_branches() {
+        let (repo, _) = tracking("meta");
+        let meta = RepoMeta::new(&repo);
+        let main = &meta.local_branches()[0];
+        assert_eq!(main.name, "main");
+        assert!(main.is_head);
+        assert_eq!(main.upstream.as_deref(), Some("origin/trunk"));
+        assert!(main
+            .divergence
+            .is_some_and(|divergence| divergence.is_in_sync()));
+        // tracking a local branch
+        let topic = &meta.local_branches()[1];
+        assert_eq!(topic.name, "topic");
+        assert_eq!(topic.upstream.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_with_base() {
+        let (repo, base) = tracking("meta-base");
+        assert_eq!(RepoMeta::new(&repo).base(), None);
+        let with_base = RepoMeta::new(&repo).with_base("origin/trunk").unwrap();
+        assert_eq!(
+            with_base.base(),
+            Some(&Base {
+                name: "origin/trunk".to_string(),
+                oid: base
+            })
+        );
+        assert!(RepoMeta::new(&repo).with_base("release").is_err());
+    }
+
+    #[test]
+    fn test_facts_kept() {
+        let (repo, _) = tracking("meta-kept");
+        let meta = RepoMeta::new(&repo);
+        assert_eq!(meta.primary_remote(), Some("origin"));
+        // once they are known
+        repo.remote_delete("origin").unwrap();
+        assert_eq!(meta.primary_remote(), Some("origin"));
+        assert_eq!(RepoMeta::new(&repo).primary_remote(), Some("upstream"));
+    }
+
+    #[test]
+    fn test_upstream_refnames() {
+        let repo = TempRepo::new("upstream-refnames");
+        repo.remote_with_fetch(
+            "mirror",
+            "https://example.com/mirror.git",
+            "+refs/heads/*:refs/remotes/backup/*",
+        )
+        .unwrap();
+        let mut config = repo.config().unwrap();
+        let mut track = |branch: &str, remote: &str, merge: Option<&str>| {
+            config
+                .set_str(&format!("branch.{}.remote", branch), remote)
+                .unwrap();
+            if let Some(merge) = merge {
+                config
+                    .set_str(&format!("branch.{}.merge", branch), merge)
+                    .unwrap();
+            }
+        };
+        // where the refspec of the remote puts it, whatever the dots in the name of the branch
+        track("release.1", "mirror", Some("refs/heads/release.1"));
+        track("topic", ".", Some("refs/heads/main"));
+        track("no-merge", "mirror", None);
+        track("no-remote", "gone", Some("refs/heads/no-remote"));
+
+        let mut upstreams: Vec<_> = upstream_refnames(&repo).into_iter().collect();
+        upstreams.sort();
+        assert_eq!(
+            upstreams,
+            [
+                (
+                    "release.1".to_string(),
+                    "refs/remotes/backup/release.1".to_string()
+                ),
+                ("topic".to_string(), "refs/heads/main".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Moving the local branches to another remote after the remote was renamed or its URL changed
+//! (`gibra remote migrate OLD NEW`), e.g. when `origin` was removed and added again as `upstream`.
+//! The `branch.<name>.remote` and `branch.<name>.pushRemote` settings naming the old remote, or
+//! the URL it had, are rewritten in one go, and `remote.pushDefault` with them. With `--dry-run`,
+//! they are only printed.
+use anyhow::{bail, Context, Result};
+use git2::{ConfigLevel, Repository};
+
+/// A setting of the repository's config to rewrite
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+    /// e.g. `branch.feature.remote`
+    pub key: String,
+    pub old: String,
+    pub new: String,
+    /// Whether the upstream it sets isn't on the new remote, as far as it was fetched
+    pub missing: bool,
+}
+
+/// The settings of the repository that name `from`, rewritten to name `to`
+pub fn plan(repo: &Repository, from: &str, to: &str) -> Result<Vec<Change>> {
+    if from == to {
+        bail!("The branches already track {}", to);
+    }
+    if repo.find_remote(to).is_err() {
+        let remotes = repo.remotes()?;
+        let names: Vec<&str> = remotes.iter().flatten().collect();
+        if names.is_empty() {
+            bail!("There is no remote {}, the repository has none", to);
+        }
+        bail!(
+            "There is no remote {}, the remotes are {}",
+            to,
+            names.join(", ")
+        );
+    }
+
+    let config = repo
+        .config()
+        .and_then(|mut config| config.snapshot())
+        .with_context(|| "Failed to read the config")?;
+    let mut changes = vec![];
+    let mut entries = config.entries(Some(
+        r"^(branch\..+\.(remote|pushremote)|remote\.pushdefault)$",
+    ))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        // the global settings are about every repository
+        if entry.level() != ConfigLevel::Local {
+            continue;
+        }
+        let (key, value) = match (entry.name(), entry.value()) {
+            (Some(key), Some(value)) if value == from => (key, value),
+            _ => continue,
+        };
+        let missing = key
+            .strip_prefix("branch.")
+            .and_then(|key| key.strip_suffix(".remote"))
+            .and_then(|branch| config.get_string(&format!("branch.{}.merge", branch)).ok())
+            .and_then(|merge| {
+                let name = merge.strip_prefix("refs/heads/")?.to_string();
+                Some(
+                    repo.find_reference(&format!("refs/remotes/{}/{}", to, name))
+                        .is_err(),
+                )
+            })
+            .unwrap_or(false);
+        changes.push(Change {
+            key: key.to_string(),
+            old: value.to_string(),
+            new: to.to_string(),
+            missing,
+        });
+    }
+    Ok(changes)
+}
+
+/// Write `changes` to the config of the repository
+pub fn apply(repo: &Repository, changes: &[Change]) -> Result<()> {
+    let mut config = repo
+        .config()
+        .and_then(|config| config.open_level(ConfigLevel::Local))
+        .with_context(|| "Failed to open the config of the repository")?;
+    for change in changes {
+        config
+            .set_str(&change.key, &change.new)
+            .with_context(|| format!("Failed to set {}", change.key))?;
+    }
+    Ok(())
+}
+
+/// Print what moving from `from` to `to` changes, and change it unless `dry_run`
+pub fn run(repo: &Repository, from: &str, to: &str, dry_run: bool) -> Result<()> {
+    let changes = plan(repo, from, to)?;
+    if changes.is_empty() {
+        eprintln!("No branch tracks {}, there is nothing to migrate", from);
+        return Ok(());
+    }
+
+    for change in &changes {
+        let note = match change.missing {
+            true => format!(" (not on {} yet, fetch it)", to),
+            false => String::new(),
+        };
+        eprintln!("  {}: {} → {}{}", change.key, change.old, change.new, note);
+    }
+    if dry_run {
+        eprintln!(
+            "Would rewrite {} setting(s), nothing was changed",
+            changes.len()
+        );
+        return Ok(());
+    }
+
+    apply(repo, &changes)?;
+    eprintln!(
+        "Rewrote {} setting(s) from {} to {}",
+        changes.len(),
+        from,
+        to
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// Branches of `origin`, of which `fetched` was fetched from `upstream` already, one of
+    /// `fork`, and one pushing to the URL of the old remote
+    fn configured(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, None, "root", &[], &[]);
+        repo.remote("upstream", "https://example.com/new.git")
+            .unwrap();
+        repo.reference("refs/remotes/upstream/fetched", tip, false, "test")
+            .unwrap();
+
+        let mut config = repo
+            .config()
+            .unwrap()
+            .open_level(ConfigLevel::Local)
+            .unwrap();
+        for (key, value) in [
+            ("branch.fetched.remote", "origin"),
+            ("branch.fetched.merge", "refs/heads/fetched"),
+            ("branch.new.remote", "origin"),
+            ("branch.new.merge", "refs/heads/new"),
+            ("branch.other.remote", "fork"),
+            ("branch.url.pushRemote", "https://example.com/old.git"),
+        ] {
+            config.set_str(key, value).unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_plan_to_missing_or_same_remote() {
+        let repo = configured("migrate-invalid");
+        assert!(plan(&repo, "origin", "nope").is_err());
+        assert!(plan(&repo, "upstream", "upstream").is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let repo = configured("migrate");
+        let changes = plan(&repo, "origin", "upstream").unwrap();
+        let keys: Vec<(&str, bool)> = changes
+            .iter()
+            .map(|change| (change.key.as_str(), change.missing))
+            .collect();
+        // `new` was never fetched from `upstream`
+        assert_eq!(
+            keys,
+            [
+                ("branch.fetched.remote", false),
+                ("branch.new.remote", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_from_url() {
+        let repo = configured("migrate-url");
+        let changes = plan(&repo, "https://example.com/old.git", "upstream").unwrap();
+        assert_eq!(changes[0].key, "branch.url.pushremote");
+
+        apply(&repo, &changes).unwrap();
+        let config = repo.config().unwrap().snapshot().unwrap();
+        assert_eq!(config.get_str("branch.url.pushRemote").unwrap(), "upstream");
+        assert_eq!(config.get_str("branch.other.remote").unwrap(), "fork");
+    }
+}
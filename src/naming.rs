@@ -0,0 +1,85 @@
+//! Names of newly created branches, following the configured convention.
+use crate::config::NamingConfig;
+use crate::template;
+use anyhow::{bail, Context, Result};
+use git2::Branch;
+use regex::Regex;
+
+/// Put the issue prefix in front of the last segment of `name`, so that `feat/add-login` becomes
+/// `feat/ABC-123-add-login`. Names that already mention the issue are left as they are.
+pub fn with_issue_prefix(config: &NamingConfig, name: &str, issue: &str) -> String {
+    if name.contains(issue) {
+        return name.to_string();
+    }
+
+    let prefix = template::expand(&config.issue_prefix, &[("issue", issue.to_string())]);
+    match name.rsplit_once('/') {
+        Some((dir, last)) => format!("{}/{}{}", dir, prefix, last),
+        None => format!("{}{}", prefix, name),
+    }
+}
+
+/// Check that `name` is a valid branch name that follows the configured convention.
+pub fn validate(config: &NamingConfig, name: &str) -> Result<()> {
+    if !Branch::name_is_valid(name).unwrap_or(false) {
+        bail!("{} is not a valid branch name", name);
+    }
+
+    let pattern = match &config.branch_name_pattern {
+        Some(pattern) => pattern,
+        None => return Ok(()),
+    };
+    let regex =
+        Regex::new(pattern).with_context(|| format!("Invalid branch_name_pattern: {}", pattern))?;
+    if !regex.is_match(name) {
+        bail!(
+            "{} doesn't follow the branch naming convention: {}",
+            name,
+            config.convention.as_deref().unwrap_or(pattern)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_issue_prefix() {
+        let config = NamingConfig::default();
+        assert_eq!(
+            with_issue_prefix(&config, "feat/add-login", "ABC-123"),
+            "feat/ABC-123-add-login"
+        );
+        assert_eq!(
+            with_issue_prefix(&config, "add-login", "ABC-123"),
+            "ABC-123-add-login"
+        );
+        assert_eq!(
+            with_issue_prefix(&config, "feat/ABC-123-add-login", "ABC-123"),
+            "feat/ABC-123-add-login"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut config = NamingConfig::default();
+        assert!(validate(&config, "anything-goes").is_ok());
+        assert!(validate(&config, "no..dots").is_err());
+
+        config.branch_name_pattern = Some("^(feat|fix)/[A-Z]+-[0-9]+-".to_string());
+        assert!(validate(&config, "feat/ABC-123-add-login").is_ok());
+        assert!(validate(&config, "add-login")
+            .unwrap_err()
+            .to_string()
+            .contains("^(feat|fix)/"));
+
+        config.convention = Some("type/TICKET-description".to_string());
+        assert!(validate(&config, "add-login")
+            .unwrap_err()
+            .to_string()
+            .contains("type/TICKET-description"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Who owns the changes of each branch after the CODEOWNERS file of the default branch
+//! (`--owners`), and listing only the branches that change what a team owns (`--owned-by`).
+//!
+//! A branch is owned by the owners of each path it changed since it forked from the default
+//! branch, the last matching line of CODEOWNERS telling them, as on GitHub and GitLab.
+use crate::diffstat;
+use crate::meta::RepoMeta;
+use crate::Branch;
+use anyhow::{bail, Context, Result};
+use git2::{Delta, Oid, Repository};
+use regex::Regex;
+use std::{collections::BTreeSet, path::Path};
+
+/// Where CODEOWNERS is looked for, in this order
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// The number of owners named next to a branch, the others are only counted
+const MAX_SHOWN: usize = 2;
+
+/// A line of CODEOWNERS
+#[derive(Debug)]
+struct Rule {
+    pattern: Regex,
+    /// Empty when the paths have no owner
+    owners: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+/// The regex of a pattern of CODEOWNERS, which are those of gitignore: a pattern with a slash
+/// before its end matches from the root, and one without matches at any depth. A directory owns
+/// everything below it, except with `dir/*` which only owns the files right in it.
+fn pattern_regex(pattern: &str) -> Option<Regex> {
+    let directory = pattern.ends_with('/');
+    let pattern = pattern.trim_end_matches('/');
+    let anchored = pattern.contains('/');
+    let pattern = pattern.trim_start_matches('/');
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut chars = pattern.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            ch => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    regex.push_str(if directory {
+        "/.*$"
+    } else if pattern == "*" || pattern.ends_with("/*") {
+        "$"
+    } else {
+        "(?:/.*)?$"
+    });
+    Regex::new(&regex).ok()
+}
+
+/// Whether `owner` is `wanted`, which may leave out the `@`
+fn is_owner(owner: &str, wanted: &str) -> bool {
+    owner
+        .trim_start_matches('@')
+        .eq_ignore_ascii_case(wanted.trim_start_matches('@'))
+}
+
+impl CodeOwners {
+    /// Read the rules of a CODEOWNERS file, skipping the sections of GitLab and the patterns it
+    /// can't match
+    fn parse(content: &str) -> Self {
+        let rules = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('['))
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                let pattern = pattern_regex(words.next()?)?;
+                let owners = words
+                    .take_while(|word| !word.starts_with('#'))
+                    .map(str::to_string)
+                    .collect();
+                Some(Rule { pattern, owners })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// The CODEOWNERS file of the commit `oid`
+    fn of_commit(repo: &Repository, oid: Oid) -> Option<Self> {
+        let tree = repo.find_commit(oid).ok()?.tree().ok()?;
+        LOCATIONS.iter().find_map(|location| {
+            let entry = tree.get_path(Path::new(location)).ok()?;
+            let blob = entry.to_object(repo).ok()?.peel_to_blob().ok()?;
+            Some(Self::parse(&String::from_utf8_lossy(blob.content())))
+        })
+    }
+
+    fn owners_of(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.pattern.is_match(path))
+            .map(|rule| rule.owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// The owners of the paths that `tip` changed since it forked from `base`
+fn owners(
+    repo: &Repository,
+    codeowners: &CodeOwners,
+    tip: Oid,
+    base: Oid,
+) -> Result<BTreeSet<String>> {
+    let fork_point = repo
+        .merge_base(tip, base)
+        .with_context(|| "Failed to find the merge base")?;
+    let old_tree = repo.find_commit(fork_point)?.tree()?;
+    let new_tree = repo.find_commit(tip)?.tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), None)?;
+
+    let mut owners = BTreeSet::new();
+    for delta in diff.deltas() {
+        let file = match delta.status() {
+            Delta::Deleted => delta.old_file(),
+            _ => delta.new_file(),
+        };
+        if let Some(path) = file.path() {
+            owners.extend(
+                codeowners
+                    .owners_of(&path.to_string_lossy())
+                    .iter()
+                    .cloned(),
+            );
+        }
+    }
+    Ok(owners)
+}
+
+/// The label of the owners, e.g. `@acme/api, @alice +1`
+pub fn label(owners: &[String]) -> String {
+    let shown = owners
+        .iter()
+        .take(MAX_SHOWN)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    match owners.len().saturating_sub(MAX_SHOWN) {
+        0 => shown,
+        more => format!("{} +{}", shown, more),
+    }
+}
+
+/// Attach the owners to `branches`, after the CODEOWNERS file of the default branch. With
+/// `owned_by`, the branches that change nothing it owns are dropped.
+pub fn apply(meta: &RepoMeta, owned_by: Option<&str>, branches: &mut Vec<Branch>) -> Result<()> {
+    let repo = meta.repo();
+    let codeowners = diffstat::base_commit(meta)
+        .and_then(|base| Some((base, CodeOwners::of_commit(repo, base)?)));
+    let (base, codeowners) = match (codeowners, owned_by) {
+        (Some(codeowners), _) => codeowners,
+        (None, Some(owner)) => bail!(
+            "There is no CODEOWNERS file on the default branch to tell what {} owns",
+            owner
+        ),
+        (None, None) => {
+            warn!("There is no CODEOWNERS file on the default branch");
+            return Ok(());
+        }
+    };
+
+    branches.retain_mut(|branch| {
+        let owners: Vec<String> = diffstat::tip(repo, branch)
+            .and_then(|tip| owners(repo, &codeowners, tip, base).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        if let Some(wanted) = owned_by {
+            if !owners.iter().any(|owner| is_owner(owner, wanted)) {
+                return false;
+            }
+        }
+
+        let owners = Some(owners);
+        match branch {
+            Branch::Local(branch) => branch.owners = owners,
+            Branch::Remote(branch) => branch.owners = owners,
+            Branch::Ref(reference) => reference.owners = owners,
+        }
+        true
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_regex() {
+        let matches = |pattern: &str, path: &str| pattern_regex(pattern).unwrap().is_match(path);
+        assert!(matches("*", "src/main.rs"));
+        assert!(matches("*.js", "web/app.js"));
+        assert!(!matches("*.js", "web/app.jsx"));
+        assert!(matches("/build/logs/", "build/logs/a/b.log"));
+        assert!(!matches("/build/logs/", "src/build/logs/a.log"));
+        assert!(matches("apps/", "services/apps/main.rs"));
+        assert!(matches("docs/*", "docs/index.md"));
+        assert!(!matches("docs/*", "docs/guide/index.md"));
+        assert!(matches("**/logs", "deep/down/logs/a.log"));
+        assert!(matches("services/**/api", "services/a/b/api/main.rs"));
+        assert!(matches("services/payments", "services/payments/main.rs"));
+        assert!(!matches(
+            "services/payments",
+            "services/payments-v2/main.rs"
+        ));
+        assert!(pattern_regex("/").is_none());
+    }
+
+    #[test]
+    fn test_owners_of() {
+        let codeowners = CodeOwners::parse(
+            "# Owners\n\
+             *       @acme/core\n\
+             \n\
+             [Payments]\n\
+             services/payments/  @acme/payments @alice # billing too\n\
+             *.md    @acme/docs\n\
+             services/payments/vendor/\n",
+        );
+        assert_eq!(codeowners.owners_of("src/main.rs"), ["@acme/core"]);
+        assert_eq!(
+            codeowners.owners_of("services/payments/charge.rs"),
+            ["@acme/payments", "@alice"]
+        );
+        assert_eq!(
+            codeowners.owners_of("services/payments/README.md"),
+            ["@acme/docs"]
+        );
+        assert!(codeowners
+            .owners_of("services/payments/vendor/lib.rs")
+            .is_empty());
+
+        assert!(is_owner("@acme/payments", "acme/Payments"));
+        assert!(!is_owner("@acme/payments", "@acme/pay"));
+        let names: Vec<String> = ["@a", "@b", "@c"].map(String::from).to_vec();
+        assert_eq!(label(&names[..2]), "@a, @b");
+        assert_eq!(label(&names), "@a, @b +1");
+    }
+}
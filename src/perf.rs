@@ -0,0 +1,327 @@
+//! How long listing the branches and checking out take, recorded when `record = true` is set
+//! under `[perf]`, and summed up by `gibra perf`.
+//!
+//! The timings never leave the machine: they are appended to `perf.jsonl` in the cache
+//! directory, one line per measurement, with the repository it was taken in. Only the most recent
+//! ones are kept.
+use crate::config;
+use crate::context;
+use anyhow::{Context, Result};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+const FILE_NAME: &str = "perf.jsonl";
+
+/// How many measurements are kept, the oldest being dropped first
+const MAX_SAMPLES: usize = 10_000;
+
+/// What was measured
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Reading the branches, their upstreams and ahead/behind counts
+    Collect,
+    /// Adding the pull requests, the fields, the columns and the sizes
+    Enrich,
+    /// Checking out the picked branch, with the steps around it
+    Checkout,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Collect => "collect",
+            Stage::Enrich => "enrich",
+            Stage::Checkout => "checkout",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Sample {
+    /// The working directory of the repository
+    repo: String,
+    stage: Stage,
+    millis: u64,
+    /// How many branches were listed, for the stages that list them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branches: Option<usize>,
+    /// When it was measured, in seconds since the epoch
+    at: u64,
+}
+
+fn path() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join(FILE_NAME))
+}
+
+fn load() -> Vec<Sample> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|content| {
+            content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn append(sample: &Sample) -> Result<()> {
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    let mut samples = load();
+    if samples.len() >= MAX_SAMPLES {
+        samples.drain(..=samples.len() - MAX_SAMPLES);
+        samples.push(sample.clone());
+        let mut content = String::new();
+        for sample in &samples {
+            content.push_str(&serde_json::to_string(sample)?);
+            content.push('\n');
+        }
+        return fs::write(&path, content)
+            .with_context(|| format!("Failed to write {}", path.display()));
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(sample)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Measures a stage in a repository, and records it when recording is turned on
+pub struct Timer {
+    started: Instant,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Record the time since the timer started as `stage` in `repo`, if `enabled`. A failure to
+    /// write it is only logged, as it must not get in the way.
+    pub fn record(self, enabled: bool, repo: &Repository, stage: Stage, branches: Option<usize>) {
+        if !enabled {
+            return;
+        }
+        let workdir = repo.workdir().unwrap_or_else(|| repo.path());
+        let sample = Sample {
+            repo: context::canonical(workdir)
+                .to_string_lossy()
+                .trim_end_matches('/')
+                .to_string(),
+            stage,
+            millis: self.elapsed().as_millis() as u64,
+            branches,
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0),
+        };
+        if let Err(err) = append(&sample) {
+            warn!("Failed to record the timing: {:#}", err);
+        }
+    }
+}
+
+/// The value that `percent` percent of the `sorted` values don't exceed, by the nearest-rank
+/// method
+fn percentile(sorted: &[u64], percent: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (percent * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// e.g. `340ms` or `1.2s`
+fn format_millis(millis: u64) -> String {
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else {
+        format!("{:.1}s", Duration::from_millis(millis).as_secs_f64())
+    }
+}
+
+/// The timings of a stage in a repository
+#[derive(Debug, PartialEq, Eq)]
+struct Summary {
+    runs: usize,
+    p50: u64,
+    p90: u64,
+    p99: u64,
+    max: u64,
+    /// The most branches listed in a run
+    branches: Option<usize>,
+}
+
+fn summarize(samples: &[&Sample]) -> Summary {
+    let mut millis: Vec<u64> = samples.iter().map(|sample| sample.millis).collect();
+    millis.sort_unstable();
+    Summary {
+        runs: millis.len(),
+        p50: percentile(&millis, 50),
+        p90: percentile(&millis, 90),
+        p99: percentile(&millis, 99),
+        max: millis.last().copied().unwrap_or(0),
+        branches: samples.iter().filter_map(|sample| sample.branches).max(),
+    }
+}
+
+/// The summaries of each repository, the slowest first by the 90th percentile of any stage
+fn summaries(samples: &[Sample]) -> Vec<(&str, BTreeMap<Stage, Summary>)> {
+    let mut by_repo: BTreeMap<&str, BTreeMap<Stage, Vec<&Sample>>> = BTreeMap::new();
+    for sample in samples {
+        by_repo
+            .entry(&sample.repo)
+            .or_default()
+            .entry(sample.stage)
+            .or_default()
+            .push(sample);
+    }
+
+    let mut summaries: Vec<(&str, BTreeMap<Stage, Summary>)> = by_repo
+        .into_iter()
+        .map(|(repo, stages)| {
+            let stages = stages
+                .into_iter()
+                .map(|(stage, samples)| (stage, summarize(&samples)))
+                .collect();
+            (repo, stages)
+        })
+        .collect();
+    let slowest = |stages: &BTreeMap<Stage, Summary>| {
+        stages
+            .values()
+            .map(|summary| summary.p90)
+            .max()
+            .unwrap_or(0)
+    };
+    summaries.sort_by_key(|(_, stages)| Reverse(slowest(stages)));
+    summaries
+}
+
+/// Print the percentiles of the timings of each repository, or how to turn recording on when
+/// there are none
+pub fn run(enabled: bool) -> Result<()> {
+    let samples = load();
+    if samples.is_empty() {
+        if enabled {
+            eprintln!("No timings were recorded yet, they are recorded as gibra is used");
+        } else {
+            eprintln!(
+                "No timings were recorded. Set `record = true` under `[perf]` in the config file \
+                 to record them"
+            );
+        }
+        return Ok(());
+    }
+
+    for (repo, stages) in summaries(&samples) {
+        println!("{}", repo);
+        for (stage, summary) in stages {
+            let branches = summary
+                .branches
+                .map(|branches| format!("  up to {} branches", branches))
+                .unwrap_or_default();
+            println!(
+                "  {:<8}  {:>4} {:<4}  p50 {:>6}  p90 {:>6}  p99 {:>6}  max {:>6}{}",
+                stage.name(),
+                summary.runs,
+                if summary.runs == 1 { "run" } else { "runs" },
+                format_millis(summary.p50),
+                format_millis(summary.p90),
+                format_millis(summary.p99),
+                format_millis(summary.max),
+                branches
+            );
+        }
+    }
+    if !enabled {
+        eprintln!("Recording is turned off, set `record = true` under `[perf]` to turn it on");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(repo: &str, stage: Stage, millis: u64) -> Sample {
+        Sample {
+            repo: repo.to_string(),
+            stage,
+            millis,
+            branches: (stage != Stage::Checkout).then_some(millis as usize / 10),
+            at: 0,
+        }
+    }
+
+    #[test]
+    fn test_percentile() {
+        let values: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&values, 50), 50);
+        assert_eq!(percentile(&values, 90), 90);
+        assert_eq!(percentile(&values, 99), 99);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[], 50), 0);
+        assert_eq!(format_millis(340), "340ms");
+        assert_eq!(format_millis(1234), "1.2s");
+    }
+
+    #[test]
+    fn test_summaries() {
+        let samples = vec![
+            sample("/fast", Stage::Collect, 10),
+            sample("/slow", Stage::Collect, 100),
+            sample("/slow", Stage::Collect, 300),
+            sample("/slow", Stage::Checkout, 2000),
+        ];
+        let summaries = summaries(&samples);
+        assert_eq!(summaries[0].0, "/slow");
+        assert_eq!(
+            summaries[0].1[&Stage::Collect],
+            Summary {
+                runs: 2,
+                p50: 100,
+                p90: 300,
+                p99: 300,
+                max: 300,
+                branches: Some(30),
+            }
+        );
+        assert_eq!(summaries[0].1[&Stage::Checkout].branches, None);
+        assert_eq!(summaries[1].0, "/fast");
+
+        let line = serde_json::to_string(&samples[0]).unwrap();
+        assert_eq!(
+            line,
+            r#"{"repo":"/fast","stage":"collect","millis":10,"branches":1,"at":0}"#
+        );
+        assert_eq!(serde_json::from_str::<Sample>(&line).unwrap(), samples[0]);
+    }
+}
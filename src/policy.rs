@@ -0,0 +1,284 @@
+//! The guardrails of a team (`[policies]`): rules that block, or ask to confirm, an operation on
+//! the branches they apply to, e.g. checking out the frozen release branches or deleting the
+//! branches with an open pull request.
+//!
+//! A rule applies to a branch when its name matches `branches` and all of `when` hold for it.
+//! Remote branches are matched by their name on the remote, e.g. `release/1.4` for
+//! `origin/release/1.4`, as the rules are about the branches rather than where they are.
+use crate::config::{Condition, Level, Operation, PoliciesConfig};
+use crate::prompt;
+use crate::remote;
+use anyhow::{Context, Result};
+use git2::Repository;
+use regex::Regex;
+use std::collections::HashSet;
+
+impl Operation {
+    fn verb(self) -> &'static str {
+        match self {
+            Operation::Checkout => "check out",
+            Operation::Delete => "delete",
+            Operation::Rename => "rename",
+            Operation::Finish => "finish",
+        }
+    }
+}
+
+/// A branch an operation is about to be run on
+#[derive(Clone, Copy, Debug)]
+pub struct Target<'a> {
+    /// The name of a local branch, or of a remote branch on its remote
+    pub name: &'a str,
+
+    /// The number of its open pull request
+    pub pull: Option<u64>,
+}
+
+struct Rule {
+    on: Vec<Operation>,
+    branches: Option<Regex>,
+    when: Vec<Condition>,
+    level: Level,
+    message: String,
+}
+
+/// The rules of the config, ready to be checked
+#[derive(Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    protected: Vec<Regex>,
+    /// The default branches of the remotes, for `is_default`
+    defaults: HashSet<String>,
+}
+
+fn pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("Invalid pattern of [policies]: {}", pattern))
+}
+
+impl Policy {
+    pub fn new(config: &PoliciesConfig, repo: &Repository) -> Result<Policy> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    on: rule.on.clone(),
+                    branches: rule.branches.as_deref().map(pattern).transpose()?,
+                    when: rule.when.clone(),
+                    level: rule.level,
+                    message: rule.message.clone(),
+                })
+            })
+            .collect::<Result<_>>()?;
+        let protected = config
+            .protected
+            .iter()
+            .map(|protected| pattern(protected))
+            .collect::<Result<_>>()?;
+        // only looked up when a rule needs them
+        let is_default = config
+            .rules
+            .iter()
+            .any(|rule| rule.when.contains(&Condition::IsDefault));
+        let defaults = match (is_default, repo.remotes()) {
+            (true, Ok(remotes)) => remotes
+                .iter()
+                .flatten()
+                .map(|remote| remote::default_branch(repo, remote))
+                .collect(),
+            _ => HashSet::new(),
+        };
+        Ok(Policy {
+            rules,
+            protected,
+            defaults,
+        })
+    }
+
+    fn holds(&self, condition: Condition, target: Target) -> bool {
+        match condition {
+            Condition::IsProtected => self
+                .protected
+                .iter()
+                .any(|protected| protected.is_match(target.name)),
+            Condition::HasOpenPr => target.pull.is_some(),
+            Condition::IsDefault => self.defaults.contains(target.name),
+        }
+    }
+
+    /// The level and the message of each rule applying to `operation` on `target`
+    pub fn check(&self, operation: Operation, target: Target) -> Vec<(Level, &str)> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.on.contains(&operation))
+            .filter(|rule| {
+                rule.branches
+                    .as_ref()
+                    .is_none_or(|branches| branches.is_match(target.name))
+            })
+            .filter(|rule| rule.when.iter().all(|&when| self.holds(when, target)))
+            .map(|rule| (rule.level, rule.message.as_str()))
+            .collect()
+    }
+
+    /// Whether `operation` may go on with `target`: not when a rule blocks it, and after
+    /// confirming with the messages of the rules that warn about it
+    pub fn allow(&self, operation: Operation, target: Target) -> Result<bool> {
+        let applying = self.check(operation, target);
+        if let Some((_, message)) = applying.iter().find(|(level, _)| *level == Level::Block) {
+            eprintln!("Can't {} {}: {}", operation.verb(), target.name, message);
+            return Ok(false);
+        }
+        if applying.is_empty() {
+            return Ok(true);
+        }
+
+        let messages: Vec<&str> = applying.iter().map(|(_, message)| *message).collect();
+        let question = format!(
+            "{}. {} {} anyway?",
+            messages.join("; "),
+            capitalize(operation.verb()),
+            target.name
+        );
+        prompt::confirm(&question, false)
+    }
+
+    /// Same as [`Policy::allow`] without asking, for the actions run while the picker stays
+    /// open: the branch is kept when any rule applies, with why
+    pub fn allow_quietly(&self, operation: Operation, target: Target) -> Result<(), String> {
+        let applying = self.check(operation, target);
+        let blocking = applying.iter().find(|(level, _)| *level == Level::Block);
+        match (blocking, applying.first()) {
+            (Some((_, message)), _) => Err(format!(
+                "Can't {} {}: {}",
+                operation.verb(),
+                target.name,
+                message
+            )),
+            (None, Some((_, message))) => Err(format!("Kept {}: {}", target.name, message)),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RuleConfig;
+    use crate::testing::TempRepo;
+
+    fn rule(on: Operation, branches: Option<&str>, when: &[Condition], level: Level) -> RuleConfig {
+        RuleConfig {
+            on: vec![on],
+            branches: branches.map(str::to_string),
+            when: when.to_vec(),
+            level,
+            message: format!("{:?}", level),
+        }
+    }
+
+    /// Checking out `release/1.*` is blocked, and deleting a branch with an open pull request
+    /// warned about, or blocked when it is protected like `release/*`
+    fn config() -> PoliciesConfig {
+        PoliciesConfig {
+            protected: vec!["^release/".to_string()],
+            rules: vec![
+                rule(
+                    Operation::Checkout,
+                    Some("^release/1\\."),
+                    &[],
+                    Level::Block,
+                ),
+                rule(
+                    Operation::Delete,
+                    None,
+                    &[Condition::HasOpenPr],
+                    Level::Warn,
+                ),
+                rule(
+                    Operation::Delete,
+                    None,
+                    &[Condition::IsProtected, Condition::HasOpenPr],
+                    Level::Block,
+                ),
+            ],
+        }
+    }
+
+    fn target(name: &str, pull: Option<u64>) -> Target<'_> {
+        Target { name, pull }
+    }
+
+    #[test]
+    fn test_check_branches() {
+        let repo = TempRepo::new("policy");
+        let policy = Policy::new(&config(), &repo).unwrap();
+        assert_eq!(
+            policy.check(Operation::Checkout, target("release/1.4", None)),
+            vec![(Level::Block, "Block")]
+        );
+        assert!(policy
+            .check(Operation::Checkout, target("release/2.0", None))
+            .is_empty());
+        assert!(policy
+            .check(Operation::Rename, target("release/1.4", Some(4)))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_check_conditions() {
+        let repo = TempRepo::new("policy-conditions");
+        let policy = Policy::new(&config(), &repo).unwrap();
+        assert!(policy
+            .check(Operation::Delete, target("feature/x", None))
+            .is_empty());
+        assert_eq!(
+            policy.check(Operation::Delete, target("feature/x", Some(4))),
+            vec![(Level::Warn, "Warn")]
+        );
+        assert_eq!(
+            policy.check(Operation::Delete, target("release/2.0", Some(4))),
+            vec![(Level::Warn, "Warn"), (Level::Block, "Block")]
+        );
+    }
+
+    #[test]
+    fn test_allow() {
+        let repo = TempRepo::new("policy-allow");
+        let policy = Policy::new(&config(), &repo).unwrap();
+        assert!(policy
+            .allow(Operation::Checkout, target("release/1.4", None))
+            .is_ok_and(|allowed| !allowed));
+        assert_eq!(
+            policy.allow_quietly(Operation::Delete, target("feature/x", Some(4))),
+            Err("Kept feature/x: Warn".to_string())
+        );
+        assert_eq!(
+            policy.allow_quietly(Operation::Delete, target("feature/x", None)),
+            Ok(())
+        );
+        // blocked rather than kept, whichever rule comes first
+        assert_eq!(
+            policy.allow_quietly(Operation::Delete, target("release/2.0", Some(4))),
+            Err("Can't delete release/2.0: Block".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let repo = TempRepo::new("policy-invalid");
+        let invalid = PoliciesConfig {
+            protected: vec!["(".to_string()],
+            rules: vec![],
+        };
+        assert!(Policy::new(&invalid, &repo).is_err());
+    }
+}
@@ -0,0 +1,414 @@
+//! Local branches grouped by their prefix, e.g. `feature/` or `user/alice/` (`gibra prefix`),
+//! with how many of each group are merged or stale, and actions on all the branches of a group at
+//! once, after listing them and confirming.
+use crate::collate;
+use crate::delete;
+use crate::history;
+use crate::meta::{LocalBranchMeta, RepoMeta};
+use crate::policy::Policy;
+use crate::prompt;
+use crate::pulls::OpenPulls;
+use crate::remote;
+use crate::rename::Upstream;
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeZone};
+use git2::{BranchType, Repository};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Branches without a commit in this many days are stale
+const STALE_DAYS: i64 = 90;
+
+/// Where archived branches are kept, out of the branch list
+const ARCHIVE_REFS: &str = "refs/archive/";
+
+/// What to do with all the branches of a prefix
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum BulkAction {
+    /// Delete the branches that are fully merged into HEAD
+    DeleteMerged,
+
+    /// Move the branches to `refs/archive/`, out of the branch list
+    Archive,
+
+    /// Fetch the upstream of the branches
+    Fetch,
+}
+
+/// The part of `name` up to its last `/`, e.g. `user/alice/` for `user/alice/fix`, or nothing
+/// when it has no `/`
+fn prefix_of(name: &str) -> &str {
+    name.rfind('/').map_or("", |index| &name[..=index])
+}
+
+/// How `prefix` is shown, the branches without a prefix being shown as `(none)`
+fn label(prefix: &str) -> &str {
+    if prefix.is_empty() {
+        "(none)"
+    } else {
+        prefix
+    }
+}
+
+/// The local branches sharing a prefix
+#[derive(Debug, PartialEq, Eq)]
+struct Group {
+    prefix: String,
+    branches: usize,
+    merged: usize,
+    stale: usize,
+    /// The time of the newest commit of the branches
+    newest: Option<i64>,
+}
+
+/// The time of the last commit of `branch`
+fn last_commit(repo: &Repository, branch: &LocalBranchMeta) -> Option<i64> {
+    let tip = branch.tip?;
+    Some(repo.find_commit(tip).ok()?.time().seconds())
+}
+
+fn is_stale(time: Option<i64>, now: i64) -> bool {
+    time.is_some_and(|time| now - time > STALE_DAYS * 24 * 60 * 60)
+}
+
+/// The groups of the local branches, ordered by prefix with the collation of the branch list.
+/// `merged` are the branches fully merged into HEAD.
+fn groups(meta: &RepoMeta, merged: &HashSet<&str>, now: i64) -> Vec<Group> {
+    let mut groups: HashMap<&str, Group> = HashMap::new();
+    for branch in meta.local_branches() {
+        let prefix = prefix_of(&branch.name);
+        let group = groups.entry(prefix).or_insert_with(|| Group {
+            prefix: prefix.to_string(),
+            branches: 0,
+            merged: 0,
+            stale: 0,
+            newest: None,
+        });
+        let time = last_commit(meta.repo(), branch);
+        group.branches += 1;
+        if merged.contains(branch.name.as_str()) {
+            group.merged += 1;
+        }
+        if is_stale(time, now) {
+            group.stale += 1;
+        }
+        group.newest = group.newest.max(time);
+    }
+
+    let mut groups: Vec<Group> = groups.into_values().collect();
+    groups.sort_by(|a, b| collate::compare(&a.prefix, &b.prefix));
+    groups
+}
+
+/// e.g. `3 days ago`
+fn age(time: Option<i64>) -> String {
+    let now = Local::now();
+    match time.and_then(|time| Local.timestamp_opt(time, 0).single()) {
+        Some(time) => history::relative(time, now),
+        None => "no commits".to_string(),
+    }
+}
+
+fn print_groups(groups: &[Group]) {
+    let width = groups
+        .iter()
+        .map(|group| label(&group.prefix).len())
+        .max()
+        .unwrap_or(0);
+    for group in groups {
+        println!(
+            "{:<width$}  {:>3} {:<8}  {:>3} merged  {:>3} stale  last commit {}",
+            label(&group.prefix),
+            group.branches,
+            if group.branches == 1 {
+                "branch"
+            } else {
+                "branches"
+            },
+            group.merged,
+            group.stale,
+            age(group.newest),
+        );
+    }
+}
+
+fn print_branches(
+    meta: &RepoMeta,
+    branches: &[&LocalBranchMeta],
+    merged: &HashSet<&str>,
+    now: i64,
+) {
+    let width = branches
+        .iter()
+        .map(|branch| branch.name.len())
+        .max()
+        .unwrap_or(0);
+    for branch in branches {
+        let time = last_commit(meta.repo(), branch);
+        let mut tags = vec![];
+        if merged.contains(branch.name.as_str()) {
+            tags.push("merged");
+        }
+        if is_stale(time, now) {
+            tags.push("stale");
+        }
+        println!(
+            "{:<width$}  {:<12}  {}",
+            branch.name,
+            tags.join(", "),
+            age(time)
+        );
+    }
+}
+
+/// Move the local branches `names` to `refs/archive/`, where git keeps their commits but doesn't
+/// list them as branches
+fn archive(repo: &Repository, names: &[String]) -> Result<()> {
+    for name in names {
+        let mut branch = repo
+            .find_branch(name, BranchType::Local)
+            .with_context(|| format!("Failed to find {}", name))?;
+        let tip = branch
+            .get()
+            .target()
+            .with_context(|| format!("{} does not point to a commit", name))?;
+        let refname = format!("{}{}", ARCHIVE_REFS, name);
+        repo.reference(&refname, tip, false, &format!("gibra: archive {}", name))
+            .with_context(|| format!("Failed to archive {}, is {} taken?", name, refname))?;
+        branch
+            .delete()
+            .with_context(|| format!("Failed to delete {}", name))?;
+        eprintln!("Archived {} to {}", name, refname);
+    }
+
+    Ok(())
+}
+
+/// Fetch the upstream of each of the local branches `names`
+fn fetch(repo: &Repository, names: &[String]) -> Result<()> {
+    let mut failed = 0;
+    for name in names {
+        let upstream = match Upstream::of(repo, name) {
+            Some(upstream) => upstream,
+            None => continue,
+        };
+        eprintln!("Fetching {}/{}...", upstream.remote, upstream.branch);
+        if let Err(err) = remote::fetch_branch(repo, &upstream.remote, &upstream.branch) {
+            eprintln!("{:#}", err);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("Failed to fetch {} branch(es)", failed);
+    }
+
+    Ok(())
+}
+
+/// Without `prefix`, print the groups of local branches. With it, print the branches starting
+/// with `prefix`, or run `action` on them after listing them and confirming.
+pub fn run(
+    meta: &RepoMeta,
+    workdir: &Path,
+    prefix: Option<&str>,
+    action: Option<BulkAction>,
+    pulls: &OpenPulls,
+    policy: &Policy,
+) -> Result<()> {
+    let merged = delete::merged_branches(meta).unwrap_or_default();
+    let merged: HashSet<&str> = merged.iter().map(String::as_str).collect();
+    let now = Local::now().timestamp();
+
+    let prefix = match prefix {
+        Some(prefix) => prefix,
+        None => {
+            let groups = groups(meta, &merged, now);
+            if groups.is_empty() {
+                eprintln!("There are no local branches");
+            }
+            print_groups(&groups);
+            return Ok(());
+        }
+    };
+
+    let mut branches: Vec<&LocalBranchMeta> = meta
+        .local_branches()
+        .iter()
+        .filter(|branch| branch.name.starts_with(prefix))
+        .collect();
+    branches.sort_by(|a, b| collate::compare(&a.name, &b.name));
+    if branches.is_empty() {
+        bail!("There are no local branches starting with {}", prefix);
+    }
+    let action = match action {
+        Some(action) => action,
+        None => {
+            print_branches(meta, &branches, &merged, now);
+            return Ok(());
+        }
+    };
+
+    let repo = meta.repo();
+    let (verb, names): (&str, Vec<String>) = match action {
+        BulkAction::DeleteMerged => (
+            "Delete",
+            branches
+                .iter()
+                .filter(|branch| merged.contains(branch.name.as_str()))
+                .map(|branch| branch.name.clone())
+                .collect(),
+        ),
+        BulkAction::Archive => (
+            "Archive",
+            branches
+                .iter()
+                .filter(|branch| !branch.is_head)
+                .map(|branch| branch.name.clone())
+                .collect(),
+        ),
+        BulkAction::Fetch => (
+            "Fetch the upstream of",
+            branches
+                .iter()
+                .filter(|branch| Upstream::of(repo, &branch.name).is_some())
+                .map(|branch| branch.name.clone())
+                .collect(),
+        ),
+    };
+    if names.is_empty() {
+        eprintln!("None of the branches starting with {} qualify", prefix);
+        return Ok(());
+    }
+
+    for name in &names {
+        match pulls.of_local(name) {
+            Some(number) => eprintln!("  {} (open pull request #{})", name, number),
+            None => eprintln!("  {}", name),
+        }
+    }
+    let question = format!(
+        "{} {} branch(es) starting with {}?",
+        verb,
+        names.len(),
+        prefix
+    );
+    if !prompt::confirm(&question, false)? {
+        return Ok(());
+    }
+
+    match action {
+        BulkAction::DeleteMerged => delete::delete_merged(meta, workdir, &names, pulls, policy),
+        BulkAction::Archive => {
+            archive(repo, &names)?;
+            eprintln!(
+                "Restore a branch with `git branch <name> {}<name>`",
+                ARCHIVE_REFS
+            );
+            Ok(())
+        }
+        BulkAction::Fetch => fetch(repo, &names),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Oid;
+
+    #[test]
+    fn test_prefix_of() {
+        assert_eq!(prefix_of("feature/login"), "feature/");
+        assert_eq!(prefix_of("user/alice/fix"), "user/alice/");
+        assert_eq!(prefix_of("main"), "");
+    }
+
+    const DAY: i64 = 24 * 60 * 60;
+    const NOW: i64 = 1000 * DAY;
+
+    /// `main` from 200 days ago checked out, `feature/merged` at it, `feature/new` a day old and
+    /// `user/alice/old` 100 days old
+    fn aged(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let commit = |refname: &str, days_ago: i64, parents: &[Oid]| {
+            testing::commit_at(
+                &repo,
+                Some(refname),
+                "commit",
+                NOW - days_ago * DAY,
+                parents,
+            )
+        };
+        let base = commit("refs/heads/main", 200, &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        repo.reference("refs/heads/feature/merged", base, false, "test")
+            .unwrap();
+        commit("refs/heads/feature/new", 1, &[base]);
+        commit("refs/heads/user/alice/old", 100, &[base]);
+        repo
+    }
+
+    #[test]
+    fn test_groups() {
+        let repo = aged("prefix");
+        let meta = RepoMeta::new(&repo);
+        let merged = delete::merged_branches(&meta).unwrap();
+        assert_eq!(merged, vec!["feature/merged"]);
+        let merged = merged.iter().map(String::as_str).collect();
+        assert_eq!(
+            groups(&meta, &merged, NOW),
+            vec![
+                Group {
+                    prefix: "".to_string(),
+                    branches: 1,
+                    merged: 0,
+                    stale: 1,
+                    newest: Some(NOW - 200 * DAY),
+                },
+                Group {
+                    prefix: "feature/".to_string(),
+                    branches: 2,
+                    merged: 1,
+                    stale: 1,
+                    newest: Some(NOW - DAY),
+                },
+                Group {
+                    prefix: "user/alice/".to_string(),
+                    branches: 1,
+                    merged: 0,
+                    stale: 1,
+                    newest: Some(NOW - 100 * DAY),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archive() {
+        let repo = aged("prefix-archive");
+        archive(&repo, &["user/alice/old".to_string()]).unwrap();
+        assert!(repo
+            .find_branch("user/alice/old", BranchType::Local)
+            .is_err());
+        assert!(repo.find_reference("refs/archive/user/alice/old").is_ok());
+    }
+
+    #[test]
+    fn test_groups_collated() {
+        let repo = TempRepo::new("prefix-collated");
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        for name in ["zeta/x", "Beta/x", "alpha/x"] {
+            repo.reference(&format!("refs/heads/{}", name), base, false, "test")
+                .unwrap();
+        }
+
+        let meta = RepoMeta::new(&repo);
+        let prefixes: Vec<String> = groups(&meta, &HashSet::new(), 0)
+            .into_iter()
+            .map(|group| group.prefix)
+            .collect();
+        assert_eq!(prefixes, vec!["", "alpha/", "Beta/", "zeta/"]);
+    }
+}
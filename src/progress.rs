@@ -0,0 +1,315 @@
+//! Feedback while checking out a large working tree: the progress git reports is drawn as a bar
+//! of the files updated so far, and ctrl-c cancels the checkout, putting the files it already
+//! updated back as they were on the branch checked out before.
+//!
+//! git updates HEAD and the index only once all the files are written, and drops its lock on
+//! the index when interrupted, so a cancelled checkout leaves both as they were. The files it
+//! changes are those that differ between the two trees, which git refuses to check out over when
+//! they have local changes, so they are restored from the index without losing any.
+use crate::git;
+use anyhow::{Context, Result};
+use git2::{Delta, Oid, Repository};
+use nix::libc;
+use std::{
+    fs,
+    io::{self, Write},
+    os::unix::ffi::OsStrExt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// How many characters wide the bar is
+const BAR_WIDTH: usize = 30;
+
+/// How many files git updated so far, out of how many, from a progress line like `Updating
+/// files:  45% (4500/10000)`
+fn parse(line: &str) -> Option<(u64, u64)> {
+    let (_, counts) = line.split_once('(')?;
+    let (counts, _) = counts.split_once(')')?;
+    let (done, total) = counts.split_once('/')?;
+    Some((done.parse().ok()?, total.parse().ok()?))
+}
+
+/// The bar for `done` of `total` files, e.g. `[=======>      ] 4500/10000 files`
+fn bar(done: u64, total: u64) -> String {
+    let filled = match total {
+        0 => BAR_WIDTH,
+        _ => (done.min(total) as usize * BAR_WIDTH) / total as usize,
+    };
+    let head = match filled {
+        BAR_WIDTH => String::new(),
+        _ => format!(">{}", " ".repeat(BAR_WIDTH - filled - 1)),
+    };
+    format!(
+        "[{}{}] {}/{} files, ctrl-c to cancel",
+        "=".repeat(filled),
+        head,
+        done,
+        total
+    )
+}
+
+/// The bar drawn on the last line of the terminal while git checks out
+#[derive(Debug, Default)]
+pub struct Bar {
+    drawn: bool,
+}
+
+impl Bar {
+    /// Redraw the bar after a progress line of git
+    pub fn update(&mut self, line: &str) {
+        if let Some((done, total)) = parse(line) {
+            eprint!("\r\x1b[2K{}", bar(done, total));
+            let _ = io::stderr().flush();
+            self.drawn = true;
+        }
+    }
+
+    /// Remove the bar, once git is done
+    pub fn clear(&mut self) {
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+            self.drawn = false;
+        }
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches ctrl-c while it lives, so that gibra outlives git and can clean up after it. git is
+/// interrupted as usual, since a caught signal is reset for the commands gibra runs.
+pub struct Interrupt {
+    previous: libc::sighandler_t,
+}
+
+impl Interrupt {
+    pub fn catch() -> Self {
+        INTERRUPTED.store(false, Ordering::SeqCst);
+        let handler = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        let previous = unsafe { libc::signal(libc::SIGINT, handler) };
+        Self { previous }
+    }
+
+    /// Whether ctrl-c was pressed since [`Interrupt::catch`]
+    pub fn caught(&self) -> bool {
+        INTERRUPTED.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for Interrupt {
+    fn drop(&mut self) {
+        unsafe { libc::signal(libc::SIGINT, self.previous) };
+    }
+}
+
+/// The tree a checkout with `args` (after `checkout`) goes to: that of the branch checked out, or
+/// of the start point of the branch `-b` or `-B` creates. `None` when it creates a branch from
+/// HEAD, which changes no file.
+pub fn target(repo: &Repository, args: &[&str]) -> Option<Oid> {
+    let mut args = args.iter();
+    let mut rev = None;
+    while let Some(&arg) = args.next() {
+        match arg {
+            // followed by the name of the new branch, not what is checked out
+            "-b" | "-B" => {
+                args.next();
+            }
+            "--" => break,
+            arg if arg.starts_with('-') => {}
+            arg => {
+                rev = Some(arg);
+                break;
+            }
+        }
+    }
+    let tree = repo.revparse_single(rev?).ok()?.peel_to_tree().ok()?;
+    Some(tree.id())
+}
+
+/// Put the files that a cancelled checkout of the tree `target` changed back as they are in the
+/// index, removing those only `target` has. Returns how many files were put back.
+pub fn restore(repo: &Repository, target: Oid) -> Result<usize> {
+    let head = repo
+        .head()
+        .and_then(|head| head.peel_to_tree())
+        .with_context(|| "Failed to get the tree of HEAD")?;
+    let target = repo.find_tree(target)?;
+    let diff = repo.diff_tree_to_tree(Some(&head), Some(&target), None)?;
+    let workdir = repo.workdir().with_context(|| "There is no working tree")?;
+
+    let mut removed = 0;
+    let mut restored = 0;
+    // NUL-separated, for `git checkout-index -z --stdin`
+    let mut paths = vec![];
+    for delta in diff.deltas() {
+        match (
+            delta.status(),
+            delta.new_file().path(),
+            delta.old_file().path(),
+        ) {
+            (Delta::Added, Some(path), _) => {
+                removed += usize::from(fs::remove_file(workdir.join(path)).is_ok());
+                // and the directories git made for it, once empty, which it may make before
+                // writing the file
+                let dirs = path.ancestors().skip(1);
+                let _ = dirs
+                    .take_while(|dir| !dir.as_os_str().is_empty())
+                    .try_for_each(|dir| fs::remove_dir(workdir.join(dir)));
+            }
+            (_, _, Some(path)) => {
+                paths.extend_from_slice(path.as_os_str().as_bytes());
+                paths.push(0);
+                restored += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if restored > 0 {
+        git::run_with_input(
+            workdir,
+            &["checkout-index", "--force", "-z", "--stdin"],
+            &paths,
+        )
+        .with_context(|| "Failed to put the files back")?;
+    }
+    Ok(removed + restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("Updating files:  45% (4500/10000)"),
+            Some((4500, 10000))
+        );
+        assert_eq!(
+            parse("Updating files: 100% (10000/10000), done."),
+            Some((10000, 10000))
+        );
+        assert_eq!(parse("Switched to branch 'main'"), None);
+    }
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(
+            bar(1, 2),
+            format!(
+                "[{}>{}] 1/2 files, ctrl-c to cancel",
+                "=".repeat(15),
+                " ".repeat(14)
+            )
+        );
+        assert_eq!(
+            bar(0, 2),
+            format!("[>{}] 0/2 files, ctrl-c to cancel", " ".repeat(29))
+        );
+        assert_eq!(
+            bar(2, 2),
+            format!("[{}] 2/2 files, ctrl-c to cancel", "=".repeat(30))
+        );
+    }
+
+    /// Commit just `files` on `refname`, and return the tree
+    fn commit_tree(repo: &TempRepo, refname: &str, files: &[(&str, &str)]) -> Oid {
+        let oid = testing::commit(repo, Some(refname), "commit", files, &[]);
+        repo.find_commit(oid).unwrap().tree_id()
+    }
+
+    /// Check out `main` at `files`
+    fn check_out_main(repo: &TempRepo, files: &[(&str, &str)]) {
+        commit_tree(repo, "refs/heads/main", files);
+        repo.set_head("refs/heads/main").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_restore() {
+        let repo = TempRepo::new("progress");
+        let dir = repo.dir();
+        let big = commit_tree(
+            &repo,
+            "refs/heads/big",
+            &[
+                ("same", "same"),
+                ("changed", "theirs"),
+                ("added", "new"),
+                ("[ab]", "theirs"),
+                ("a", "same"),
+                ("dir/sub/added", "new"),
+            ],
+        );
+        check_out_main(
+            &repo,
+            &[
+                ("same", "same"),
+                ("changed", "ours"),
+                ("removed", "old"),
+                ("[ab]", "ours"),
+                ("a", "same"),
+            ],
+        );
+
+        // a checkout of `big` cancelled halfway, over a local change it keeps
+        fs::write(dir.join("same"), "local change").unwrap();
+        fs::write(dir.join("changed"), "theirs").unwrap();
+        fs::write(dir.join("added"), "new").unwrap();
+        fs::create_dir_all(dir.join("dir/sub")).unwrap();
+        fs::write(dir.join("dir/sub/added"), "new").unwrap();
+        fs::remove_file(dir.join("removed")).unwrap();
+        fs::write(dir.join("[ab]"), "theirs").unwrap();
+        // a file the checkout doesn't touch, but matched by `[ab]` as a pattern
+        fs::write(dir.join("a"), "local change").unwrap();
+
+        assert_eq!(restore(&repo, big).unwrap(), 5);
+        let read = |path: &str| fs::read_to_string(dir.join(path)).unwrap();
+        assert_eq!(read("same"), "local change");
+        assert_eq!(read("changed"), "ours");
+        assert_eq!(read("removed"), "old");
+        assert_eq!(read("[ab]"), "ours");
+        assert_eq!(read("a"), "local change");
+        assert!(!dir.join("added").exists());
+        assert!(!dir.join("dir").exists());
+    }
+
+    #[test]
+    fn test_target() {
+        let repo = TempRepo::new("progress-target");
+        let big = commit_tree(&repo, "refs/heads/big", &[("file", "big")]);
+        check_out_main(&repo, &[("file", "main")]);
+        assert_eq!(target(&repo, &["big", "--force"]), Some(big));
+        assert_eq!(target(&repo, &["-b", "new"]), None);
+        assert_eq!(target(&repo, &["-B", "main", "--track", "big"]), Some(big));
+    }
+
+    #[test]
+    fn test_cancel_tracking_checkout() {
+        let repo = TempRepo::new("progress-track");
+        let dir = repo.dir();
+        let remote = commit_tree(
+            &repo,
+            "refs/remotes/origin/x",
+            &[("f", "theirs"), ("added", "new")],
+        );
+        check_out_main(&repo, &[("f", "ours")]);
+
+        // `checkout -b x --track origin/x` cancelled halfway
+        let args = ["-b", "x", "--track", "origin/x"];
+        fs::write(dir.join("f"), "theirs").unwrap();
+        fs::write(dir.join("added"), "new").unwrap();
+
+        let target = target(&repo, &args);
+        assert_eq!(target, Some(remote));
+        assert_eq!(restore(&repo, target.unwrap()).unwrap(), 2);
+        assert_eq!(fs::read_to_string(dir.join("f")).unwrap(), "ours");
+        assert!(!dir.join("added").exists());
+    }
+}
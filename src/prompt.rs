@@ -0,0 +1,99 @@
+use anyhow::{Context, Result};
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+/// Show `prompt` on stderr and read a line from stdin, without the surrounding whitespace.
+fn read_answer(prompt: &str) -> Result<String> {
+    eprint!("{} ", prompt);
+    io::stderr()
+        .flush()
+        .with_context(|| "Failed to flush stderr")?;
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .with_context(|| "Failed to read answer")?;
+
+    Ok(answer.trim().to_string())
+}
+
+/// Ask a yes/no question on stderr and read the answer from stdin.
+///
+/// When stdin is not a terminal there is nobody to answer, so `false` is returned.
+pub fn confirm(question: &str, default: bool) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = read_answer(&format!("{} {}", question, hint))?;
+
+    Ok(match answer.to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    })
+}
+
+/// Ask for a secret on stderr and read it from stdin without showing it. When stdin is not a
+/// terminal, e.g. when the secret is piped in, the first line is read as is.
+pub fn secret(question: &str) -> Result<String> {
+    if !io::stdin().is_terminal() {
+        let mut answer = String::new();
+        io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .with_context(|| "Failed to read answer")?;
+        return Ok(answer.trim().to_string());
+    }
+
+    let stty = |arg: &str| {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .status()
+    };
+    let hidden = cfg!(unix) && stty("-echo").is_ok_and(|status| status.success());
+    let answer = read_answer(question);
+    if hidden {
+        let _ = stty("echo");
+        eprintln!();
+    }
+
+    answer
+}
+
+/// Ask for a line of text on stderr and read it from stdin.
+///
+/// When stdin is not a terminal there is nobody to answer, so an empty string is returned.
+pub fn input(question: &str) -> Result<String> {
+    if !io::stdin().is_terminal() {
+        return Ok(String::new());
+    }
+
+    read_answer(question)
+}
+
+/// Ask which of `choices` to take, listed with their numbers on stderr, and return its index.
+///
+/// `None` when the answer is empty or the number of no choice, and when stdin is not a terminal.
+pub fn choose(question: &str, choices: &[String]) -> Result<Option<usize>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+
+    eprintln!("{}", question);
+    for (index, choice) in choices.iter().enumerate() {
+        eprintln!("  {}) {}", index + 1, choice);
+    }
+    let answer = read_answer(&format!("[1-{}, empty to cancel]", choices.len()))?;
+
+    Ok(answer
+        .parse::<usize>()
+        .ok()
+        .filter(|number| (1..=choices.len()).contains(number))
+        .map(|number| number - 1))
+}
@@ -0,0 +1,327 @@
+//! Open pull requests of the branches on GitHub.
+//!
+//! The picker never waits for the network: it shows what is cached, and a stale cache is
+//! refreshed in the background, after which the picker reloads its branches.
+use crate::config::{self, GithubConfig};
+use crate::meta::RepoMeta;
+use crate::remote::{self, RemoteUrl};
+use crate::secrets::{self, Provider};
+use anyhow::{Context, Result};
+use crossbeam::channel::Sender;
+use nix::libc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_FILE_NAME: &str = "pulls.toml";
+
+/// How long (in seconds) the fetched pull requests are considered fresh
+const CACHE_TTL: u64 = 5 * 60;
+
+/// The GitHub repository that pull requests are looked up in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GithubRepo {
+    /// The remote pointing at it, usually `origin`
+    pub remote: String,
+    /// `owner/repo`
+    pub slug: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct RepoPulls {
+    fetched_at: u64,
+    by_branch: HashMap<String, u64>,
+}
+
+/// The pull request caches of all repositories, keyed by `owner/repo`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PullCache {
+    repos: HashMap<String, RepoPulls>,
+}
+
+/// The open pull requests of a repository, by the name of their head branch
+#[derive(Clone, Debug, Default)]
+pub struct OpenPulls {
+    remote: String,
+    by_branch: HashMap<String, u64>,
+}
+
+impl OpenPulls {
+    /// The number of the open pull request of the local branch `name`
+    pub fn of_local(&self, name: &str) -> Option<u64> {
+        self.by_branch.get(name).copied()
+    }
+
+    /// The number of the open pull request of the remote-tracking branch `name`, e.g. `origin/foo`
+    pub fn of_remote(&self, name: &str) -> Option<u64> {
+        let name = name.strip_prefix(&self.remote)?.strip_prefix('/')?;
+        self.by_branch.get(name).copied()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+fn load_cache() -> PullCache {
+    cache_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(slug: &str, pulls: RepoPulls) -> Result<()> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+
+    // other gibra processes refresh the caches of their own repositories at the same time, so
+    // the file is read, updated and replaced while holding the lock, released once `lock` is closed
+    let lock_path = path.with_extension("toml.lock");
+    let lock = File::create(&lock_path)
+        .with_context(|| format!("Failed to create {}", lock_path.display()))?;
+    if unsafe { libc::flock(lock.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error())
+            .with_context(|| format!("Failed to lock {}", lock_path.display()));
+    }
+
+    let mut cache = load_cache();
+    cache.repos.insert(slug.to_string(), pulls);
+    let content = toml::to_string(&cache).with_context(|| "Failed to serialize cache")?;
+
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    fs::rename(&partial, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The URL of the next page in a `Link` header like `<https://…&page=2>; rel="next", …`
+fn next_page(link: &str) -> Option<&str> {
+    link.split(',').find_map(|entry| {
+        let (url, params) = entry.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"")
+            .then(|| url.trim().strip_prefix('<')?.strip_suffix('>'))
+            .flatten()
+    })
+}
+
+/// `owner/repo` from a remote URL like `git@github.com:owner/repo.git`
+pub fn slug(url: &str) -> Option<String> {
+    let url = RemoteUrl::parse(url)?;
+    let path = url.path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    match path.split('/').collect::<Vec<_>>()[..] {
+        [owner, repo] if !owner.is_empty() && !repo.is_empty() => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+/// The repository behind `origin`, or behind the first remote when there is no `origin`
+pub fn github_repo(meta: &RepoMeta) -> Option<GithubRepo> {
+    let name = meta.primary_remote()?;
+    let endpoint = remote::resolve(meta.repo(), name).ok()?;
+    Some(GithubRepo {
+        remote: name.to_string(),
+        slug: slug(&endpoint.url)?,
+    })
+}
+
+#[derive(Deserialize)]
+struct GithubPull {
+    number: u64,
+    head: GithubHead,
+}
+
+#[derive(Deserialize)]
+struct GithubHead {
+    #[serde(rename = "ref")]
+    branch: String,
+    repo: Option<GithubHeadRepo>,
+}
+
+#[derive(Deserialize)]
+struct GithubHeadRepo {
+    full_name: String,
+}
+
+/// The open pull requests whose head branch lives in the repository itself, not in a fork
+fn fetch(config: &GithubConfig, slug: &str) -> Result<RepoPulls> {
+    let token = secrets::token(Provider::Github, config.token.as_ref())?;
+    let agent = remote::http_agent();
+    let mut url = format!(
+        "{}/repos/{}/pulls?state=open&per_page=100",
+        config.api_url.trim_end_matches('/'),
+        slug
+    );
+
+    // GitHub returns at most 100 pull requests a page, and links to the next one
+    let mut pulls: Vec<GithubPull> = vec![];
+    loop {
+        let response = agent
+            .get(&url)
+            .set("Accept", "application/vnd.github+json")
+            .set("Authorization", &format!("Bearer {}", token.expose()))
+            .call()
+            .with_context(|| format!("Failed to fetch pull requests of {}", slug))?;
+        let next = response
+            .header("link")
+            .and_then(next_page)
+            .map(str::to_string);
+        let page: Vec<GithubPull> = response
+            .into_json()
+            .with_context(|| format!("Failed to parse pull requests of {}", slug))?;
+        pulls.extend(page);
+
+        match next {
+            Some(next) => url = next,
+            None => break,
+        }
+    }
+
+    let by_branch = pulls
+        .into_iter()
+        .filter(|pull| {
+            pull.head
+                .repo
+                .as_ref()
+                .is_some_and(|repo| repo.full_name == slug)
+        })
+        .map(|pull| (pull.head.branch, pull.number))
+        .collect();
+
+    Ok(RepoPulls {
+        fetched_at: now(),
+        by_branch,
+    })
+}
+
+/// The cached open pull requests, no matter how old. Nothing is fetched.
+pub fn cached(meta: &RepoMeta) -> OpenPulls {
+    let github_repo = match github_repo(meta) {
+        Some(github_repo) => github_repo,
+        None => return OpenPulls::default(),
+    };
+
+    let by_branch = load_cache()
+        .repos
+        .remove(&github_repo.slug)
+        .map(|pulls| pulls.by_branch)
+        .unwrap_or_default();
+
+    OpenPulls {
+        remote: github_repo.remote,
+        by_branch,
+    }
+}
+
+fn is_fresh(slug: &str) -> bool {
+    load_cache()
+        .repos
+        .get(slug)
+        .is_some_and(|pulls| now().saturating_sub(pulls.fetched_at) < CACHE_TTL)
+}
+
+/// Refresh a stale cache in the background, sending on `done` once the new pull requests are
+/// cached. Returns whether a refresh was started.
+pub fn refresh_in_background(meta: &RepoMeta, config: &GithubConfig, done: Sender<()>) -> bool {
+    let github_repo = match github_repo(meta) {
+        Some(github_repo) if !is_fresh(&github_repo.slug) => github_repo,
+        _ => return false,
+    };
+
+    let config = config.clone();
+    thread::spawn(move || {
+        match fetch(&config, &github_repo.slug)
+            .and_then(|pulls| save_cache(&github_repo.slug, pulls))
+        {
+            Ok(()) => {
+                let _ = done.send(());
+            }
+            Err(err) => debug!("{:#}", err),
+        }
+    });
+
+    true
+}
+
+/// The open pull requests, fetched first when the cache is stale. When fetching fails, the
+/// cached ones are used.
+pub fn current(meta: &RepoMeta, config: Option<&GithubConfig>) -> OpenPulls {
+    if let (Some(config), Some(github_repo)) = (config, github_repo(meta)) {
+        if !is_fresh(&github_repo.slug) {
+            let fetched = fetch(config, &github_repo.slug)
+                .and_then(|pulls| save_cache(&github_repo.slug, pulls));
+            if let Err(err) = fetched {
+                eprintln!("warning: {:#}", err);
+            }
+        }
+    }
+
+    cached(meta)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(
+            slug("git@github.com:owner/repo.git").as_deref(),
+            Some("owner/repo")
+        );
+        assert_eq!(
+            slug("https://github.com/owner/repo").as_deref(),
+            Some("owner/repo")
+        );
+        assert_eq!(slug("https://example.com/a/b/c.git"), None);
+        assert_eq!(slug("/local/path"), None);
+    }
+
+    #[test]
+    fn test_open_pulls() {
+        let pulls = OpenPulls {
+            remote: "origin".to_string(),
+            by_branch: HashMap::from([("feature".to_string(), 12)]),
+        };
+        assert_eq!(pulls.of_local("feature"), Some(12));
+        assert_eq!(pulls.of_local("other"), None);
+        assert_eq!(pulls.of_remote("origin/feature"), Some(12));
+        assert_eq!(pulls.of_remote("upstream/feature"), None);
+        assert_eq!(pulls.of_remote("originfeature"), None);
+    }
+
+    #[test]
+    fn test_next_page() {
+        let link = "<https://api.github.com/repositories/1/pulls?page=2>; rel=\"next\", \
+                    <https://api.github.com/repositories/1/pulls?page=5>; rel=\"last\"";
+        assert_eq!(
+            next_page(link),
+            Some("https://api.github.com/repositories/1/pulls?page=2")
+        );
+
+        let last = "<https://api.github.com/repositories/1/pulls?page=4>; rel=\"prev\", \
+                    <https://api.github.com/repositories/1/pulls?page=1>; rel=\"first\"";
+        assert_eq!(next_page(last), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! Triangular workflows, where a branch is fetched from the remote of its upstream
+//! (`branch.<name>.remote`) and pushed to another one, its push remote (`branch.<name>.pushRemote`,
+//! or `remote.pushDefault` for every branch), e.g. fetched from `upstream` and pushed to a fork at
+//! `origin`. The preview shows both, and the push remote is set with a key of its own.
+use crate::rename::Upstream;
+use anyhow::{Context, Result};
+use git2::{BranchType, ConfigLevel, Repository};
+
+/// The remote the local branch `name` is pushed to, as configured
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PushRemote {
+    pub remote: String,
+    /// Whether it comes from `remote.pushDefault` rather than from the branch
+    pub default: bool,
+}
+
+impl PushRemote {
+    pub fn of(repo: &Repository, name: &str) -> Option<Self> {
+        let config = repo.config().ok()?.snapshot().ok()?;
+        if let Ok(remote) = config.get_string(&format!("branch.{}.pushRemote", name)) {
+            return Some(Self {
+                remote,
+                default: false,
+            });
+        }
+        let remote = config.get_string("remote.pushDefault").ok()?;
+        Some(Self {
+            remote,
+            default: true,
+        })
+    }
+}
+
+/// Where the local branch `name` is fetched from and pushed to, e.g.
+/// `fetch from upstream/main · push to origin`, `None` when it is neither or isn't a local branch
+pub fn summary(repo: &Repository, name: &str) -> Option<String> {
+    repo.find_branch(name, BranchType::Local).ok()?;
+    let upstream = Upstream::of(repo, name);
+    let push = match PushRemote::of(repo, name) {
+        Some(PushRemote {
+            remote,
+            default: true,
+        }) => Some(format!("{} (remote.pushDefault)", remote)),
+        Some(PushRemote { remote, .. }) => Some(remote),
+        None => upstream.as_ref().map(|upstream| upstream.remote.clone()),
+    };
+    let fetch = upstream.map(|upstream| format!("{}/{}", upstream.remote, upstream.branch));
+    match (fetch, push) {
+        (Some(fetch), Some(push)) => Some(format!("fetch from {} · push to {}", fetch, push)),
+        (None, Some(push)) => Some(format!("push to {}", push)),
+        (fetch, None) => fetch.map(|fetch| format!("fetch from {}", fetch)),
+    }
+}
+
+/// Push the local branches `names` to `remote`, leaving their upstreams as they are. The setting
+/// is removed from those whose upstream is on `remote`, which they are pushed to anyway unless
+/// `remote.pushDefault` says otherwise.
+pub fn set(repo: &Repository, names: &[String], remote: &str) -> Result<()> {
+    let mut config = repo
+        .config()
+        .and_then(|config| config.open_level(ConfigLevel::Local))
+        .with_context(|| "Failed to open the config of the repository")?;
+    let default = repo
+        .config()
+        .and_then(|config| config.get_string("remote.pushDefault"))
+        .ok();
+    let pushed_to_upstream = default.is_none() || default.as_deref() == Some(remote);
+    for name in names {
+        let key = format!("branch.{}.pushRemote", name);
+        let upstream = Upstream::of(repo, name);
+        if pushed_to_upstream && upstream.is_some_and(|upstream| upstream.remote == remote) {
+            // there may be nothing to remove
+            let _ = config.remove(&key);
+            eprintln!("  ✓ {} → {}, as its upstream", name, remote);
+        } else {
+            config
+                .set_str(&key, remote)
+                .with_context(|| format!("Failed to set {}", key))?;
+            eprintln!("  ✓ {} → {}", name, remote);
+        }
+    }
+    eprintln!(
+        "Set the push remote of {} branch(es) to {}",
+        names.len(),
+        remote
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `feature` and `fix` tracking `upstream/main`, `local` tracking nothing, and a `fork` remote
+    fn tracking(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, None, "root", &[], &[]);
+        for name in ["feature", "fix", "local"] {
+            repo.reference(&format!("refs/heads/{}", name), tip, false, "test")
+                .unwrap();
+        }
+        repo.remote("upstream", "https://example.com/repo.git")
+            .unwrap();
+        repo.remote("fork", "https://example.com/fork.git").unwrap();
+        let mut config = local_config(&repo);
+        for name in ["feature", "fix"] {
+            config
+                .set_str(&format!("branch.{}.remote", name), "upstream")
+                .unwrap();
+            config
+                .set_str(&format!("branch.{}.merge", name), "refs/heads/main")
+                .unwrap();
+        }
+        repo
+    }
+
+    fn local_config(repo: &Repository) -> git2::Config {
+        repo.config()
+            .unwrap()
+            .open_level(ConfigLevel::Local)
+            .unwrap()
+    }
+
+    fn push_remote(repo: &Repository) -> Option<String> {
+        repo.config()
+            .unwrap()
+            .get_string("branch.feature.pushRemote")
+            .ok()
+    }
+
+    #[test]
+    fn test_summary() {
+        let repo = tracking("push-remote");
+        assert_eq!(PushRemote::of(&repo, "feature"), None);
+        assert_eq!(
+            summary(&repo, "feature").unwrap(),
+            "fetch from upstream/main · push to upstream"
+        );
+        assert_eq!(summary(&repo, "local"), None);
+        // a remote branch
+        assert_eq!(summary(&repo, "upstream/main"), None);
+    }
+
+    #[test]
+    fn test_summary_of_push_default() {
+        let repo = tracking("push-remote-default");
+        local_config(&repo)
+            .set_str("remote.pushDefault", "fork")
+            .unwrap();
+        assert_eq!(
+            summary(&repo, "fix").unwrap(),
+            "fetch from upstream/main · push to fork (remote.pushDefault)"
+        );
+        assert_eq!(
+            summary(&repo, "local").unwrap(),
+            "push to fork (remote.pushDefault)"
+        );
+    }
+
+    #[test]
+    fn test_set() {
+        let repo = tracking("push-remote-set");
+        set(&repo, &["feature".to_string()], "fork").unwrap();
+        assert_eq!(
+            PushRemote::of(&repo, "feature"),
+            Some(PushRemote {
+                remote: "fork".to_string(),
+                default: false,
+            })
+        );
+        set(&repo, &["feature".to_string()], "upstream").unwrap();
+        assert_eq!(push_remote(&repo), None);
+    }
+
+    #[test]
+    fn test_set_against_push_default() {
+        let repo = tracking("push-remote-against");
+        let mut config = local_config(&repo);
+        config.set_str("remote.pushDefault", "fork").unwrap();
+        // the default would push it to the fork
+        set(&repo, &["feature".to_string()], "upstream").unwrap();
+        assert_eq!(push_remote(&repo).as_deref(), Some("upstream"));
+
+        config.remove("remote.pushDefault").unwrap();
+        set(&repo, &["feature".to_string()], "upstream").unwrap();
+        assert_eq!(push_remote(&repo), None);
+    }
+}
@@ -0,0 +1,67 @@
+//! Grouping branches by how recently they were committed to, under section headers.
+use crate::skim::prelude::*;
+use crate::{group, last_commit_time, Branch};
+use chrono::{DateTime, Duration, Local, TimeZone};
+use git2::Repository;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Recency {
+    /// Committed to since midnight
+    Today,
+    /// Committed to within the last 7 days, but not today
+    ThisWeek,
+    Older,
+}
+
+impl Recency {
+    pub fn of(time: DateTime<Local>, now: DateTime<Local>) -> Recency {
+        if time.date_naive() == now.date_naive() {
+            Recency::Today
+        } else if now - time < Duration::days(7) {
+            Recency::ThisWeek
+        } else {
+            Recency::Older
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Recency::Today => "Today",
+            Recency::ThisWeek => "This week",
+            Recency::Older => "Older",
+        }
+    }
+}
+
+/// Put `branches` in groups of the same recency, each under a header. Within a group, the
+/// branches keep their order.
+pub fn grouped(repo: &Repository, branches: Vec<Branch>) -> Vec<Arc<dyn SkimItem>> {
+    let now = Local::now();
+    let branches: Vec<(Recency, Branch)> = branches
+        .into_iter()
+        .map(|branch| {
+            let time = Local
+                .timestamp_opt(last_commit_time(repo, &branch), 0)
+                .single()
+                .unwrap_or(now);
+            (Recency::of(time, now), branch)
+        })
+        .collect();
+
+    group::grouped(branches, Recency::label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recency() {
+        let now = Local.with_ymd_and_hms(2024, 5, 15, 10, 0, 0).unwrap();
+        let at = |day, hour| Local.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap();
+        assert_eq!(Recency::of(at(15, 0), now), Recency::Today);
+        assert_eq!(Recency::of(at(14, 23), now), Recency::ThisWeek);
+        assert_eq!(Recency::of(at(8, 11), now), Recency::ThisWeek);
+        assert_eq!(Recency::of(at(8, 9), now), Recency::Older);
+    }
+}
@@ -0,0 +1,370 @@
+//! Refs listed besides the branches: the changes of Gerrit under `refs/changes/`, the branches of
+//! the namespace in `GIT_NAMESPACE`, and the refs matching `--ref-glob`, e.g. `refs/pull/*/head`.
+//! They are checked out on a detached HEAD.
+//!
+//! With `--special-refs`, the pseudo-refs git keeps in `.git` like `HEAD`, `FETCH_HEAD` and
+//! `MERGE_HEAD`, and the notes refs, are listed too, to see the state of the repository at a
+//! glance. They are badged as such, only the pseudo-refs can be checked out, and the actions run
+//! on several items leave them out.
+use git2::{Oid, Repository};
+use std::{
+    collections::{BTreeMap, HashSet},
+    env, fs,
+};
+
+const CHANGES: &str = "refs/changes/";
+
+const NOTES: &str = "refs/notes/";
+
+/// The pseudo-refs, in the order they are listed
+const PSEUDO_REFS: [&str; 7] = [
+    "HEAD",
+    "ORIG_HEAD",
+    "FETCH_HEAD",
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "REBASE_HEAD",
+];
+
+/// A ref that is not a branch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Listed {
+    /// How it is shown, e.g. `changes/1234/2` or `review:main`
+    pub name: String,
+    pub refname: String,
+    pub tip: Oid,
+}
+
+/// The change and the patch set of a Gerrit ref like `refs/changes/34/1234/2`. `None` for the
+/// other refs below `refs/changes/`, like `refs/changes/34/1234/meta`
+fn change(refname: &str) -> Option<(u64, u64)> {
+    let mut parts = refname.strip_prefix(CHANGES)?.split('/');
+    let (_, change, patch_set) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((change.parse().ok()?, patch_set.parse().ok()?))
+}
+
+/// Where git keeps the refs of `namespace`, e.g. `refs/namespaces/a/refs/namespaces/b/` for `a/b`
+fn namespace_prefix(namespace: &str) -> String {
+    namespace
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| format!("refs/namespaces/{}/", part))
+        .collect()
+}
+
+/// The refs matching `glob` that point to an object, where `*` matches slashes too
+fn matching(repo: &Repository, glob: &str) -> Vec<(String, Oid)> {
+    let references = match repo.references_glob(glob) {
+        Ok(references) => references,
+        Err(_) => return vec![],
+    };
+    references
+        .flatten()
+        .filter_map(|reference| Some((reference.name()?.to_string(), reference.target()?)))
+        .collect()
+}
+
+/// The latest patch set of each change, the newest change first
+fn changes(repo: &Repository) -> Vec<Listed> {
+    let mut latest: BTreeMap<u64, (u64, String, Oid)> = BTreeMap::new();
+    for (refname, tip) in matching(repo, &format!("{}*", CHANGES)) {
+        let (change, patch_set) = match change(&refname) {
+            Some(change) => change,
+            None => continue,
+        };
+        match latest.get(&change) {
+            Some((newest, _, _)) if *newest >= patch_set => {}
+            _ => {
+                latest.insert(change, (patch_set, refname, tip));
+            }
+        }
+    }
+
+    latest
+        .into_iter()
+        .rev()
+        .map(|(change, (patch_set, refname, tip))| Listed {
+            name: format!("changes/{}/{}", change, patch_set),
+            refname,
+            tip,
+        })
+        .collect()
+}
+
+/// The branches of `namespace`, shown as `<namespace>:<branch>`
+fn namespaced(repo: &Repository, namespace: &str) -> Vec<Listed> {
+    let prefix = format!("{}refs/heads/", namespace_prefix(namespace));
+    matching(repo, &format!("{}*", prefix))
+        .into_iter()
+        .map(|(refname, tip)| Listed {
+            name: format!("{}:{}", namespace, &refname[prefix.len()..]),
+            refname,
+            tip,
+        })
+        .collect()
+}
+
+fn collect(repo: &Repository, namespace: Option<&str>, globs: &[String]) -> Vec<Listed> {
+    let mut listed = changes(repo);
+    if let Some(namespace) = namespace {
+        listed.extend(namespaced(repo, namespace));
+    }
+    for glob in globs {
+        let glob = match glob.strip_prefix("refs/") {
+            Some(_) => glob.clone(),
+            None => format!("refs/{}", glob),
+        };
+        for (refname, tip) in matching(repo, &glob) {
+            // the branches are listed already
+            if refname.starts_with("refs/heads/") || refname.starts_with("refs/remotes/") {
+                continue;
+            }
+            listed.push(Listed {
+                name: refname["refs/".len()..].to_string(),
+                refname,
+                tip,
+            });
+        }
+    }
+
+    // a glob may match the refs listed above
+    let mut seen = HashSet::new();
+    listed.retain(|reference| seen.insert(reference.refname.clone()));
+    listed
+}
+
+/// What kind of special ref an item is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Special {
+    /// `HEAD`, `FETCH_HEAD` and the like
+    PseudoRef,
+    /// A ref below `refs/notes/`, pointing to the commits holding the notes rather than to a
+    /// commit of the history
+    Notes,
+}
+
+impl Special {
+    /// The badge shown next to the name
+    pub fn badge(self) -> &'static str {
+        match self {
+            Special::PseudoRef => "pseudo-ref",
+            Special::Notes => "notes",
+        }
+    }
+
+    /// Whether it can be checked out, on a detached HEAD
+    pub fn checkout(self) -> bool {
+        self == Special::PseudoRef
+    }
+}
+
+/// A pseudo-ref or a notes ref
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecialRef {
+    pub kind: Special,
+    pub listed: Listed,
+    /// What it is about, e.g. `→ main` for `HEAD` or `branch 'main' of origin` for `FETCH_HEAD`
+    pub note: Option<String>,
+}
+
+/// The commit `name` points to, and what its file tells besides. `FETCH_HEAD` and `MERGE_HEAD`
+/// may hold several lines, the first one is taken, as git does.
+fn pseudo_ref(repo: &Repository, name: &str) -> Option<(Oid, Option<String>)> {
+    if name == "HEAD" {
+        let head = repo.head().ok()?;
+        let note = match repo.head_detached() {
+            Ok(true) => "detached".to_string(),
+            _ => format!("→ {}", head.shorthand()?),
+        };
+        return Some((head.target()?, Some(note)));
+    }
+    let content = fs::read_to_string(repo.path().join(name)).ok()?;
+    let mut fields = content.lines().next()?.split('\t');
+    let tip = Oid::from_str(fields.next()?.trim()).ok()?;
+    // FETCH_HEAD: `<oid>\t[not-for-merge]\t<description>`
+    let note = fields
+        .next_back()
+        .map(str::trim)
+        .filter(|note| !note.is_empty())
+        .map(str::to_string);
+    Some((tip, note))
+}
+
+/// The pseudo-refs that exist, then the notes refs
+pub fn special(repo: &Repository) -> Vec<SpecialRef> {
+    let mut listed: Vec<SpecialRef> = PSEUDO_REFS
+        .iter()
+        .filter_map(|&name| {
+            let (tip, note) = pseudo_ref(repo, name)?;
+            Some(SpecialRef {
+                kind: Special::PseudoRef,
+                listed: Listed {
+                    name: name.to_string(),
+                    refname: name.to_string(),
+                    tip,
+                },
+                note,
+            })
+        })
+        .collect();
+    for (refname, tip) in matching(repo, &format!("{}*", NOTES)) {
+        let count = repo
+            .notes(Some(&refname))
+            .map(|notes| notes.count())
+            .unwrap_or(0);
+        listed.push(SpecialRef {
+            kind: Special::Notes,
+            listed: Listed {
+                name: refname["refs/".len()..].to_string(),
+                refname,
+                tip,
+            },
+            note: Some(format!("notes on {} object(s)", count)),
+        });
+    }
+    listed
+}
+
+/// The refs to list besides the branches, `globs` being the patterns given with `--ref-glob`
+pub fn list(repo: &Repository, globs: &[String]) -> Vec<Listed> {
+    let namespace = env::var("GIT_NAMESPACE").ok();
+    collect(
+        repo,
+        namespace
+            .as_deref()
+            .filter(|namespace| !namespace.is_empty()),
+        globs,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Signature;
+    use std::fs;
+
+    #[test]
+    fn test_change() {
+        assert_eq!(change("refs/changes/34/1234/2"), Some((1234, 2)));
+        assert_eq!(change("refs/changes/34/1234/meta"), None);
+        assert_eq!(change("refs/changes/34/1234"), None);
+        assert_eq!(change("refs/heads/34/1234/2"), None);
+    }
+
+    #[test]
+    fn test_namespace_prefix() {
+        assert_eq!(namespace_prefix("review"), "refs/namespaces/review/");
+        assert_eq!(
+            namespace_prefix("a/b"),
+            "refs/namespaces/a/refs/namespaces/b/"
+        );
+    }
+
+    /// Gerrit changes, a namespaced branch and a pull request, all at one commit
+    fn reviewed(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, Some("refs/heads/main"), "commit", &[], &[]);
+        for refname in [
+            "refs/changes/34/1234/1",
+            "refs/changes/34/1234/2",
+            "refs/changes/34/1234/meta",
+            "refs/changes/35/1235/1",
+            "refs/namespaces/review/refs/heads/feature",
+            "refs/pull/7/head",
+        ] {
+            repo.reference(refname, tip, false, "test").unwrap();
+        }
+        repo
+    }
+
+    fn names(listed: Vec<Listed>) -> Vec<String> {
+        listed.into_iter().map(|reference| reference.name).collect()
+    }
+
+    #[test]
+    fn test_collect_latest_patch_sets() {
+        let repo = reviewed("refs");
+        assert_eq!(
+            names(collect(&repo, None, &[])),
+            vec!["changes/1235/1", "changes/1234/2"]
+        );
+    }
+
+    #[test]
+    fn test_collect_namespace_and_patterns() {
+        let repo = reviewed("refs-patterns");
+        assert_eq!(
+            names(collect(
+                &repo,
+                Some("review"),
+                &["pull/*/head".to_string(), "refs/heads/*".to_string()]
+            )),
+            vec![
+                "changes/1235/1",
+                "changes/1234/2",
+                "review:feature",
+                "pull/7/head"
+            ]
+        );
+        assert_eq!(
+            collect(&repo, None, &["refs/changes/*/1234/*".to_string()]).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_special_of_empty_repo() {
+        let repo = TempRepo::new("special-empty");
+        assert_eq!(special(&repo), vec![]);
+    }
+
+    #[test]
+    fn test_special() {
+        let repo = TempRepo::new("special");
+        let tip = testing::commit(&repo, Some("HEAD"), "commit", &[], &[]);
+        fs::write(
+            repo.path().join("FETCH_HEAD"),
+            format!(
+                "{}\t\tbranch 'main' of origin\n{}\tnot-for-merge\tbranch 'fix' of origin\n",
+                tip, tip
+            ),
+        )
+        .unwrap();
+        fs::write(repo.path().join("MERGE_HEAD"), format!("{}\n", tip)).unwrap();
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        repo.note(&signature, &signature, None, tip, "a note", false)
+            .unwrap();
+
+        let listed: Vec<(Special, String, Option<String>)> = special(&repo)
+            .into_iter()
+            .map(|special| (special.kind, special.listed.name, special.note))
+            .collect();
+        let head = repo.head().unwrap().shorthand().unwrap().to_string();
+        assert_eq!(
+            listed,
+            [
+                (
+                    Special::PseudoRef,
+                    "HEAD".to_string(),
+                    Some(format!("→ {}", head))
+                ),
+                (
+                    Special::PseudoRef,
+                    "FETCH_HEAD".to_string(),
+                    Some("branch 'main' of origin".to_string())
+                ),
+                (Special::PseudoRef, "MERGE_HEAD".to_string(), None),
+                (
+                    Special::Notes,
+                    "notes/commits".to_string(),
+                    Some("notes on 1 object(s)".to_string())
+                ),
+            ]
+        );
+    }
+}
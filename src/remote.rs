@@ -0,0 +1,735 @@
+//! Resolution of remotes for the features that talk to the network.
+//!
+//! Everything that fetches, prunes or talks to a hosting provider goes through this module so that
+//! `url.<base>.insteadOf`, `http.proxy` and `~/.ssh/config` host aliases are honored consistently.
+use crate::config::expand_tilde;
+use crate::error;
+use crate::git;
+use anyhow::{bail, Context, Result};
+use git2::{
+    BranchType, Cred, CredentialType, Direction, FetchOptions, FetchPrune, Oid, ProxyOptions,
+    RemoteCallbacks, Repository,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+/// A remote URL split into its parts.
+///
+/// Both URL style (`ssh://git@host:22/owner/repo.git`) and scp style (`git@host:owner/repo.git`)
+/// addresses are supported.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteUrl {
+    pub scheme: String,
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: String,
+}
+
+impl RemoteUrl {
+    pub fn parse(url: &str) -> Option<RemoteUrl> {
+        if let Some((scheme, rest)) = url.split_once("://") {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let (user, host_port) = match authority.rsplit_once('@') {
+                Some((user, host_port)) => (Some(user.to_string()), host_port),
+                None => (None, authority),
+            };
+            let (host, port) = match host_port.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port.parse().ok()?)),
+                None => (host_port, None),
+            };
+
+            if host.is_empty() {
+                return None;
+            }
+
+            return Some(RemoteUrl {
+                scheme: scheme.to_lowercase(),
+                user,
+                host: host.to_string(),
+                port,
+                path: path.to_string(),
+            });
+        }
+
+        // scp-like syntax: [user@]host:path
+        let (authority, path) = url.split_once(':')?;
+        if authority.is_empty() || authority.contains('/') {
+            return None;
+        }
+        let (user, host) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_string()), host),
+            None => (None, authority),
+        };
+
+        Some(RemoteUrl {
+            scheme: "ssh".to_string(),
+            user,
+            host: host.to_string(),
+            port: None,
+            path: path.trim_start_matches('/').to_string(),
+        })
+    }
+
+    pub fn is_ssh(&self) -> bool {
+        self.scheme == "ssh" || self.scheme == "git+ssh"
+    }
+}
+
+impl std::fmt::Display for RemoteUrl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}://", self.scheme)?;
+        if let Some(user) = &self.user {
+            write!(f, "{}@", user)?;
+        }
+        write!(f, "{}", self.host)?;
+        if let Some(port) = self.port {
+            write!(f, ":{}", port)?;
+        }
+        write!(f, "/{}", self.path)
+    }
+}
+
+/// The settings of a `Host` block in `~/.ssh/config` that matter for connecting.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct SshHost {
+    host_name: Option<String>,
+    user: Option<String>,
+    port: Option<u16>,
+    identity_file: Option<PathBuf>,
+}
+
+/// Look up `alias` in an ssh config. Like ssh, the first value found for each option wins.
+fn parse_ssh_config(content: &str, alias: &str) -> SshHost {
+    let mut host = SshHost::default();
+    let mut matches = true;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once(|c: char| c.is_whitespace() || c == '=') {
+            Some((key, value)) => (key.to_lowercase(), value.trim_start_matches('=').trim()),
+            None => continue,
+        };
+
+        match key.as_str() {
+            "host" => {
+                matches = host_matches(value, alias);
+            }
+            "match" => matches = false,
+            _ if !matches => {}
+            "hostname" if host.host_name.is_none() => host.host_name = Some(value.to_string()),
+            "user" if host.user.is_none() => host.user = Some(value.to_string()),
+            "port" if host.port.is_none() => host.port = value.parse().ok(),
+            "identityfile" if host.identity_file.is_none() => {
+                host.identity_file = Some(expand_tilde(Path::new(value)))
+            }
+            _ => {}
+        }
+    }
+
+    host
+}
+
+/// Match a host against the patterns of an ssh `Host` line, supporting `*` and `?` wildcards.
+/// Like ssh, a matching `!pattern` excludes the host whatever the other patterns and their order.
+fn host_matches(patterns: &str, host: &str) -> bool {
+    fn matches(pattern: &[u8], host: &[u8]) -> bool {
+        match (pattern.first(), host.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                matches(&pattern[1..], host) || (!host.is_empty() && matches(pattern, &host[1..]))
+            }
+            (Some(b'?'), Some(_)) => matches(&pattern[1..], &host[1..]),
+            (Some(p), Some(h)) if p.eq_ignore_ascii_case(h) => matches(&pattern[1..], &host[1..]),
+            _ => false,
+        }
+    }
+
+    let mut matched = false;
+    for pattern in patterns.split_whitespace() {
+        match pattern.strip_prefix('!') {
+            Some(negated) if matches(negated.as_bytes(), host.as_bytes()) => return false,
+            Some(_) => {}
+            None => matched |= matches(pattern.as_bytes(), host.as_bytes()),
+        }
+    }
+    matched
+}
+
+fn ssh_host(alias: &str) -> SshHost {
+    let path = expand_tilde(Path::new("~/.ssh/config"));
+    fs::read_to_string(path)
+        .map(|content| parse_ssh_config(&content, alias))
+        .unwrap_or_default()
+}
+
+/// Rewrite an ssh URL whose host is an alias from `~/.ssh/config`, which libgit2 does not read.
+fn apply_ssh_host(url: &mut RemoteUrl, host: &SshHost) {
+    if let Some(host_name) = &host.host_name {
+        url.host = host_name.clone();
+    }
+    if url.user.is_none() {
+        url.user = host.user.clone();
+    }
+    if url.port.is_none() {
+        url.port = host.port;
+    }
+}
+
+/// A remote with its URLs resolved the way git would resolve them.
+#[derive(Clone, Debug)]
+pub struct RemoteEndpoint {
+    /// fetch URL, after `insteadOf` rewrites and ssh alias resolution
+    pub url: String,
+    identity_file: Option<PathBuf>,
+}
+
+/// Resolve the remote `name`.
+///
+/// libgit2 already applies `url.<base>.insteadOf` when a remote is looked up; ssh host aliases are
+/// resolved here.
+pub fn resolve(repo: &Repository, name: &str) -> Result<RemoteEndpoint> {
+    let remote = repo
+        .find_remote(name)
+        .with_context(|| format!("Failed to find remote {}", name))?;
+    let url = remote
+        .url()
+        .with_context(|| format!("Remote {} has no valid URL", name))?;
+
+    let mut identity_file = None;
+    let url = match RemoteUrl::parse(url) {
+        Some(mut parsed) if parsed.is_ssh() => {
+            let host = ssh_host(&parsed.host);
+            if host == SshHost::default() {
+                url.to_string()
+            } else {
+                apply_ssh_host(&mut parsed, &host);
+                identity_file = host.identity_file;
+                parsed.to_string()
+            }
+        }
+        _ => url.to_string(),
+    };
+
+    Ok(RemoteEndpoint { url, identity_file })
+}
+
+/// Callbacks that pick up credentials from the ssh agent, the configured identity file or the git
+/// credential helpers.
+fn remote_callbacks<'a>(repo: &Repository, endpoint: &RemoteEndpoint) -> RemoteCallbacks<'a> {
+    let config = repo.config().ok();
+    let identity_file = endpoint.identity_file.clone();
+    let mut attempts = 0;
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        let username = username.unwrap_or("git");
+
+        if allowed.contains(CredentialType::SSH_KEY) {
+            match (attempts, identity_file.as_deref()) {
+                (1, _) => return Cred::ssh_key_from_agent(username),
+                (2, Some(identity_file)) => {
+                    return Cred::ssh_key(username, None, identity_file, None)
+                }
+                _ => {}
+            }
+        }
+
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && attempts <= 2 {
+            if let Some(config) = config.as_ref() {
+                return Cred::credential_helper(config, url, Some(username));
+            }
+        }
+
+        if allowed.contains(CredentialType::DEFAULT) && attempts <= 3 {
+            return Cred::default();
+        }
+
+        Err(git2::Error::from_str("no usable credentials found"))
+    });
+
+    callbacks
+}
+
+/// Proxy options that honor `http.proxy` and the proxy environment variables
+fn proxy_options<'a>() -> ProxyOptions<'a> {
+    let mut proxy_options = ProxyOptions::new();
+    proxy_options.auto();
+    proxy_options
+}
+
+/// Fetch options that honor `http.proxy` (and the proxy environment variables) and pick up
+/// credentials from the ssh agent, the configured identity file or the git credential helpers.
+pub fn fetch_options<'a>(repo: &Repository, endpoint: &RemoteEndpoint) -> FetchOptions<'a> {
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(remote_callbacks(repo, endpoint));
+    options.proxy_options(proxy_options());
+    options
+}
+
+/// An HTTP client for the APIs of hosting providers that honors `http.proxy` from the global git
+/// config and the proxy environment variables, like fetches do.
+pub fn http_agent() -> ureq::Agent {
+    let mut builder = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(10))
+        .user_agent(concat!("gibra/", env!("CARGO_PKG_VERSION")))
+        .try_proxy_from_env(true);
+
+    let proxy = git2::Config::open_default()
+        .and_then(|config| config.get_string("http.proxy"))
+        .ok()
+        .and_then(|proxy| ureq::Proxy::new(proxy).ok());
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+
+    builder.build()
+}
+
+/// Fetch the configured refspecs of the remote `name`, optionally pruning stale remote-tracking
+/// branches.
+pub fn fetch(repo: &Repository, name: &str, prune: bool) -> Result<()> {
+    let refspecs: Vec<String> = repo
+        .find_remote(name)
+        .with_context(|| format!("Failed to find remote {}", name))?
+        .fetch_refspecs()
+        .with_context(|| format!("Failed to get refspecs of remote {}", name))?
+        .iter()
+        .flatten()
+        .map(|refspec| refspec.to_string())
+        .collect();
+
+    fetch_refspecs(repo, name, &refspecs, prune)
+}
+
+/// Fetch just `branch` from the remote `name` into its remote-tracking branch
+pub fn fetch_branch(repo: &Repository, name: &str, branch: &str) -> Result<()> {
+    let refspec = format!("+refs/heads/{}:refs/remotes/{}/{}", branch, name, branch);
+    fetch_refspecs(repo, name, &[refspec], false)
+}
+
+fn fetch_refspecs(repo: &Repository, name: &str, refspecs: &[String], prune: bool) -> Result<()> {
+    let endpoint = resolve(repo, name)?;
+    let mut options = fetch_options(repo, &endpoint);
+    if prune {
+        options.prune(FetchPrune::On);
+    }
+
+    let mut remote = repo
+        .remote_anonymous(&endpoint.url)
+        .with_context(|| format!("Failed to connect to remote {}", name))?;
+    remote
+        .fetch(refspecs, Some(&mut options), None)
+        .map_err(|err| error::remote_error(name, err))
+        .with_context(|| format!("Failed to fetch from {}", name))?;
+
+    Ok(())
+}
+
+/// Fetch the full history of a shallow clone. libgit2 can't deepen a clone, so this runs
+/// `git fetch --unshallow`.
+pub fn unshallow(repo: &Repository, workdir: &Path) -> Result<()> {
+    if !repo.is_shallow() {
+        eprintln!("The repository has its full history already");
+        return Ok(());
+    }
+
+    eprintln!("Fetching the full history...");
+    let status = git::command(workdir)
+        .args(["fetch", "--unshallow"])
+        .stdout(io::stderr())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| "Failed to execute fetch command")?;
+    if !status.success() {
+        bail!("Failed to fetch the full history");
+    }
+
+    Ok(())
+}
+
+/// The branch that `HEAD` of `remote` points to, e.g. `main`
+pub fn default_branch(repo: &Repository, remote: &str) -> String {
+    let head = repo
+        .find_reference(&format!("refs/remotes/{}/HEAD", remote))
+        .ok()
+        .and_then(|head| head.symbolic_target().map(str::to_string));
+    if let Some(name) = head
+        .as_deref()
+        .and_then(|target| target.strip_prefix(&format!("refs/remotes/{}/", remote)))
+    {
+        return name.to_string();
+    }
+
+    let exists = |name: &str| {
+        repo.find_reference(&format!("refs/remotes/{}/{}", remote, name))
+            .is_ok()
+    };
+    if !exists("main") && exists("master") {
+        "master".to_string()
+    } else {
+        "main".to_string()
+    }
+}
+
+/// The remote a new branch starts from with `fresh_base`: the one of the upstream of the current
+/// branch, or `origin`, or the only one
+pub fn base_remote(repo: &Repository) -> Option<String> {
+    let upstream = repo.head().ok().and_then(|head| {
+        let remote = repo.branch_upstream_remote(head.name()?).ok()?;
+        remote.as_str().map(str::to_string)
+    });
+    if upstream.is_some() {
+        return upstream;
+    }
+    let remotes = repo.remotes().ok()?;
+    let remotes: Vec<&str> = remotes.iter().flatten().collect();
+    match remotes.as_slice() {
+        [only] => Some(only.to_string()),
+        remotes => remotes.contains(&"origin").then(|| "origin".to_string()),
+    }
+}
+
+/// Fetch the default branch of the remote that new branches start from, and return it as the
+/// start point, e.g. `origin/main`, so that a new branch starts from the latest commit of the
+/// remote rather than from a local branch that is behind. When the fetch fails, e.g. offline, the
+/// remote-tracking branch fetched before is used.
+pub fn fresh_base(repo: &Repository) -> Result<String> {
+    let remote =
+        base_remote(repo).with_context(|| "There is no remote to start the branch from")?;
+    let branch = default_branch(repo, &remote);
+    let base = format!("{}/{}", remote, branch);
+    eprintln!("Fetching {}...", base);
+    if let Err(err) = fetch_branch(repo, &remote, &branch) {
+        if repo.find_branch(&base, BranchType::Remote).is_err() {
+            return Err(err);
+        }
+        eprintln!(
+            "warning: {:#}, starting from {} as fetched before",
+            err, base
+        );
+    }
+    Ok(base)
+}
+
+/// The names of the branches on the remote `name`, like `git ls-remote --heads`
+pub fn list_heads(repo: &Repository, name: &str) -> Result<Vec<String>> {
+    let heads = ls_remote(repo, name)?
+        .into_iter()
+        .map(|(branch, _)| branch)
+        .collect();
+    Ok(heads)
+}
+
+/// The branches on the remote `name` with their tips, which may not have been fetched
+pub fn ls_remote(repo: &Repository, name: &str) -> Result<Vec<(String, Oid)>> {
+    let endpoint = resolve(repo, name)?;
+    let mut remote = repo
+        .remote_anonymous(&endpoint.url)
+        .with_context(|| format!("Failed to connect to remote {}", name))?;
+    let connection = remote
+        .connect_auth(
+            Direction::Fetch,
+            Some(remote_callbacks(repo, &endpoint)),
+            Some(proxy_options()),
+        )
+        .map_err(|err| error::remote_error(name, err))
+        .with_context(|| format!("Failed to connect to remote {}", name))?;
+
+    let heads = connection
+        .list()
+        .with_context(|| format!("Failed to list the branches of {}", name))?
+        .iter()
+        .filter_map(|head| {
+            let branch = head.name().strip_prefix("refs/heads/")?;
+            Some((branch.to_string(), head.oid()))
+        })
+        .collect();
+
+    Ok(heads)
+}
+
+/// The remote and the branch of the remote branch `name`, e.g. `origin` and `feature` of
+/// `origin/feature`. Remote names may contain slashes, the longest one wins.
+pub fn split(repo: &Repository, name: &str) -> Result<(String, String)> {
+    let remotes = repo.remotes()?;
+    remotes
+        .iter()
+        .flatten()
+        .filter_map(|remote| Some((remote, name.strip_prefix(remote)?.strip_prefix('/')?)))
+        .max_by_key(|(remote, _)| remote.len())
+        .map(|(remote, branch)| (remote.to_string(), branch.to_string()))
+        .with_context(|| format!("There is no remote to fetch {} from", name))
+}
+
+/// Fetch the remote branch `name`, e.g. `origin/feature`, as listed by [`ls_remote`] and maybe
+/// never fetched. The remote fetches it from now on, when its refspecs leave it out, as those of
+/// a single-branch clone do, so that the branch can track it.
+pub fn fetch_listed(repo: &Repository, name: &str) -> Result<()> {
+    let (remote, branch) = split(repo, name)?;
+    let (remote, branch) = (remote.as_str(), branch.as_str());
+
+    eprintln!("Fetching {}...", name);
+    fetch_branch(repo, remote, branch)?;
+
+    let refname = format!("refs/heads/{}", branch);
+    let fetched = repo
+        .find_remote(remote)
+        .with_context(|| format!("Failed to find remote {}", remote))?
+        .refspecs()
+        .any(|refspec| refspec.direction() == Direction::Fetch && refspec.src_matches(&refname));
+    if !fetched {
+        let refspec = format!("+{}:refs/remotes/{}/{}", refname, remote, branch);
+        repo.remote_add_fetch(remote, &refspec)
+            .with_context(|| format!("Failed to add {} to the refspecs of {}", refspec, remote))?;
+        eprintln!("{} fetches {} from now on", remote, branch);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempDir, TempRepo};
+    use git2::Oid;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            RemoteUrl::parse("git@github.com:neoki07/gibra.git"),
+            Some(RemoteUrl {
+                scheme: "ssh".to_string(),
+                user: Some("git".to_string()),
+                host: "github.com".to_string(),
+                port: None,
+                path: "neoki07/gibra.git".to_string(),
+            })
+        );
+        assert_eq!(
+            RemoteUrl::parse("ssh://git@example.com:2222/team/repo.git").map(|url| url.port),
+            Some(Some(2222))
+        );
+
+        let url = RemoteUrl::parse("https://github.com/neoki07/gibra.git").unwrap();
+        assert_eq!(url.scheme, "https");
+        assert_eq!(url.host, "github.com");
+        assert_eq!(url.path, "neoki07/gibra.git");
+
+        assert_eq!(RemoteUrl::parse("/srv/git/repo.git"), None);
+        assert_eq!(RemoteUrl::parse("../repo"), None);
+    }
+
+    #[test]
+    fn test_display_url() {
+        let url = RemoteUrl::parse("git@github.com:neoki07/gibra.git").unwrap();
+        assert_eq!(url.to_string(), "ssh://git@github.com/neoki07/gibra.git");
+    }
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let config = "
+            Host work
+              HostName git.corp.example.com
+              Port 2222
+            Host *
+              User git
+              Port 22
+        ";
+        assert_eq!(
+            parse_ssh_config(config, "work"),
+            SshHost {
+                host_name: Some("git.corp.example.com".to_string()),
+                user: Some("git".to_string()),
+                port: Some(2222),
+                identity_file: None,
+            }
+        );
+        assert_eq!(parse_ssh_config(config, "github.com").host_name, None);
+        assert_eq!(parse_ssh_config(config, "github.com").port, Some(22));
+    }
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("*", "github.com"));
+        assert!(host_matches("*.example.com", "git.example.com"));
+        assert!(host_matches("git?", "git1"));
+        assert!(!host_matches("*.example.com", "example.org"));
+        assert!(!host_matches("!github.com", "github.com"));
+        assert!(host_matches(
+            "*.example.com !internal.example.com",
+            "git.example.com"
+        ));
+        assert!(!host_matches(
+            "*.example.com !internal.example.com",
+            "internal.example.com"
+        ));
+        // a negated pattern excludes the host in whichever order
+        assert!(!host_matches(
+            "!internal.example.com *.example.com",
+            "internal.example.com"
+        ));
+        assert!(host_matches(
+            "!internal.example.com *.example.com",
+            "git.example.com"
+        ));
+        assert!(!host_matches("!internal.example.com", "git.example.com"));
+    }
+
+    #[test]
+    fn test_resolve_applies_instead_of() {
+        let repo = TempRepo::new("remote");
+        repo.config()
+            .unwrap()
+            .set_str("url.https://git.corp.example.com/.insteadOf", "corp:")
+            .unwrap();
+        repo.remote("origin", "corp:team/repo.git").unwrap();
+
+        let endpoint = resolve(&repo, "origin").unwrap();
+        assert_eq!(endpoint.url, "https://git.corp.example.com/team/repo.git");
+    }
+
+    /// A bare `upstream.git` at `trunk` and a `clone` of it with `origin/HEAD` at `origin/trunk`,
+    /// which was never fetched
+    fn cloned(dir: &TempDir) -> (Repository, Repository, Oid) {
+        let upstream = Repository::init_bare(dir.join("upstream.git")).unwrap();
+        let old = testing::commit(&upstream, Some("refs/heads/trunk"), "old", &[], &[]);
+        let repo = Repository::init(dir.join("clone")).unwrap();
+        repo.remote("origin", dir.join("upstream.git").to_str().unwrap())
+            .unwrap();
+        repo.remote("fork", "https://example.com/fork.git").unwrap();
+        repo.reference_symbolic(
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/trunk",
+            false,
+            "test",
+        )
+        .unwrap();
+        (upstream, repo, old)
+    }
+
+    #[test]
+    fn test_fresh_base_without_remote() {
+        let dir = TempDir::new("fresh-none");
+        let repo = Repository::init(dir.join("clone")).unwrap();
+        assert!(fresh_base(&repo).is_err());
+    }
+
+    #[test]
+    fn test_fresh_base() {
+        let dir = TempDir::new("fresh");
+        let (upstream, repo, old) = cloned(&dir);
+        // the remote moved on since the last fetch, if any
+        let new = testing::commit(&upstream, Some("refs/heads/trunk"), "new", &[], &[old]);
+
+        assert_eq!(fresh_base(&repo).unwrap(), "origin/trunk");
+        assert_eq!(
+            repo.refname_to_id("refs/remotes/origin/trunk").unwrap(),
+            new
+        );
+    }
+
+    #[test]
+    fn test_fresh_base_offline() {
+        let dir = TempDir::new("fresh-offline");
+        let (_upstream, repo, old) = cloned(&dir);
+        fresh_base(&repo).unwrap();
+
+        // offline, the branch fetched before is used
+        repo.remote_set_url("origin", dir.join("gone.git").to_str().unwrap())
+            .unwrap();
+        assert_eq!(fresh_base(&repo).unwrap(), "origin/trunk");
+        assert_eq!(
+            repo.refname_to_id("refs/remotes/origin/trunk").unwrap(),
+            old
+        );
+    }
+
+    /// A bare `upstream.git` with `main` and `feature/login`, and a single-branch `clone` of
+    /// `main`
+    fn single_branch(dir: &TempDir) -> (Repository, Oid) {
+        let upstream = Repository::init_bare(dir.join("upstream.git")).unwrap();
+        let tip = testing::commit(&upstream, None, "commit", &[], &[]);
+        for name in ["main", "feature/login"] {
+            upstream
+                .reference(&format!("refs/heads/{}", name), tip, false, "test")
+                .unwrap();
+        }
+
+        let repo = Repository::init(dir.join("clone")).unwrap();
+        repo.remote_with_fetch(
+            "origin",
+            dir.join("upstream.git").to_str().unwrap(),
+            "+refs/heads/main:refs/remotes/origin/main",
+        )
+        .unwrap();
+        (repo, tip)
+    }
+
+    fn refspecs(repo: &Repository) -> Vec<String> {
+        let remote = repo.find_remote("origin").unwrap();
+        let refspecs = remote.fetch_refspecs().unwrap();
+        refspecs.iter().flatten().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_ls_remote() {
+        let dir = TempDir::new("ls-remote");
+        let (repo, tip) = single_branch(&dir);
+        let mut heads = ls_remote(&repo, "origin").unwrap();
+        heads.sort();
+        assert_eq!(
+            heads,
+            [
+                ("feature/login".to_string(), tip),
+                ("main".to_string(), tip)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split() {
+        let dir = TempDir::new("ls-remote-split");
+        let (repo, _) = single_branch(&dir);
+        assert_eq!(
+            split(&repo, "origin/feature/login").unwrap(),
+            ("origin".to_string(), "feature/login".to_string())
+        );
+        assert!(fetch_listed(&repo, "nowhere/main").is_err());
+    }
+
+    #[test]
+    fn test_fetch_listed() {
+        let dir = TempDir::new("ls-remote-fetch");
+        let (repo, tip) = single_branch(&dir);
+        fetch_listed(&repo, "origin/feature/login").unwrap();
+        assert_eq!(
+            repo.refname_to_id("refs/remotes/origin/feature/login")
+                .unwrap(),
+            tip
+        );
+        assert_eq!(
+            refspecs(&repo),
+            [
+                "+refs/heads/main:refs/remotes/origin/main",
+                "+refs/heads/feature/login:refs/remotes/origin/feature/login"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fetch_listed_already() {
+        let dir = TempDir::new("ls-remote-fetched");
+        let (repo, _) = single_branch(&dir);
+        fetch_listed(&repo, "origin/main").unwrap();
+        assert_eq!(refspecs(&repo).len(), 1);
+    }
+}
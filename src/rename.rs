@@ -0,0 +1,301 @@
+//! Renaming the current branch, on its remote too (`gibra rename`).
+//!
+//! The branch is renamed locally, then the new name is pushed, set as upstream, and the old name
+//! is deleted from the remote. These run as one [`Transaction`], so when pushing or deleting
+//! fails, the remote and the local name are put back as they were. A branch with a push remote
+//! other than the remote of its upstream is renamed there instead, and keeps its upstream.
+use crate::audit::{self, Loss};
+use crate::config::{NamingConfig, Operation};
+use crate::context::RepoContext;
+use crate::meta::RepoMeta;
+use crate::naming;
+use crate::policy::{Policy, Target};
+use crate::prompt;
+use crate::pulls;
+use crate::push_remote::PushRemote;
+use crate::transaction::Transaction;
+use anyhow::{bail, Result};
+use git2::Repository;
+use std::path::Path;
+
+/// Where a branch is pushed to: its remote and the name of the branch there
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Upstream {
+    pub remote: String,
+    pub branch: String,
+}
+
+impl Upstream {
+    pub fn of(repo: &Repository, local: &str) -> Option<Upstream> {
+        let refname = format!("refs/heads/{}", local);
+        let remote = repo.branch_upstream_remote(&refname).ok()?;
+        let merge = repo
+            .config()
+            .ok()?
+            .get_string(&format!("branch.{}.merge", local))
+            .ok()?;
+        Some(Upstream {
+            remote: remote.as_str()?.to_string(),
+            branch: merge.strip_prefix("refs/heads/")?.to_string(),
+        })
+    }
+
+    /// Whether the branch exists on the remote, as far as the last fetch knows
+    fn exists(&self, repo: &Repository) -> bool {
+        repo.find_reference(&format!("refs/remotes/{}/{}", self.remote, self.branch))
+            .is_ok()
+    }
+}
+
+/// Where `local` is pushed to, and whether that is its upstream: its push remote, under the same
+/// name, when it has one other than the remote of its upstream
+fn push_target(repo: &Repository, local: &str) -> Option<(Upstream, bool)> {
+    let upstream = Upstream::of(repo, local);
+    match PushRemote::of(repo, local) {
+        Some(push) if upstream.as_ref().map(|upstream| &upstream.remote) != Some(&push.remote) => {
+            let target = Upstream {
+                remote: push.remote,
+                branch: local.to_string(),
+            };
+            Some((target, false))
+        }
+        _ => upstream.map(|upstream| (upstream, true)),
+    }
+}
+
+/// Rename the local branch `old` to `new`. With `push`, `new` is pushed to the remote of `old` and
+/// becomes its upstream, or to its push remote, and the old name is deleted from the remote.
+pub fn rename(
+    repo: &Repository,
+    workdir: &Path,
+    old: &str,
+    new: &str,
+    push: bool,
+    skip_hooks: bool,
+) -> Result<()> {
+    let target = push_target(repo, old);
+    let mut transaction = Transaction::begin(repo, workdir, "rename");
+    if skip_hooks {
+        transaction.skip_hooks();
+    }
+    // recorded once the rename went through
+    let mut losses = vec![];
+
+    transaction.run(
+        &format!("rename {} to {}", old, new),
+        &["branch", "--move", old, new],
+        vec![vec![
+            "branch".to_string(),
+            "--move".to_string(),
+            new.to_string(),
+            old.to_string(),
+        ]],
+    )?;
+
+    if let (true, Some((upstream, tracked))) = (push, target) {
+        let existed = upstream.exists(repo);
+        let mut undo = vec![vec![
+            "push".to_string(),
+            upstream.remote.clone(),
+            "--delete".to_string(),
+            new.to_string(),
+        ]];
+        if existed && tracked {
+            undo.push(vec![
+                "branch".to_string(),
+                format!("--set-upstream-to={}/{}", upstream.remote, upstream.branch),
+                new.to_string(),
+            ]);
+        }
+        let refspec = format!("{}:refs/heads/{}", new, new);
+        let mut args = vec!["push", &upstream.remote, &refspec];
+        if tracked {
+            args.insert(1, "--set-upstream");
+        }
+        transaction.run(&format!("push {} to {}", new, upstream.remote), &args, undo)?;
+
+        if existed && upstream.branch != new {
+            losses.extend(Loss::remote_branch(
+                repo,
+                &upstream.remote,
+                &upstream.branch,
+            ));
+            transaction.run(
+                &format!("delete {} from {}", upstream.branch, upstream.remote),
+                &["push", &upstream.remote, "--delete", &upstream.branch],
+                vec![],
+            )?;
+        }
+    }
+
+    transaction.commit()?;
+    audit::record(repo, losses);
+    Ok(())
+}
+
+/// Rename the current branch, asking for the new name when it isn't given, and whether to rename
+/// it on its remote too unless `push` is set. The rules of `policy` are checked first.
+pub fn run(
+    context: &RepoContext,
+    naming_config: &NamingConfig,
+    policy: &Policy,
+    new: Option<String>,
+    push: bool,
+    skip_hooks: bool,
+) -> Result<()> {
+    let (repo, workdir) = (&context.repo, context.workdir.as_path());
+    let old = match &context.head.branch {
+        Some(branch) => branch.clone(),
+        None => bail!("HEAD is detached, there is no branch to rename"),
+    };
+    let target = Target {
+        name: &old,
+        pull: pulls::cached(&RepoMeta::new(repo)).of_local(&old),
+    };
+    if !policy.allow(Operation::Rename, target)? {
+        bail!("Cancelled, {} was not renamed", old);
+    }
+
+    let new = match new {
+        Some(new) => new,
+        None => prompt::input(&format!("Rename {} to:", old))?,
+    };
+    if new.is_empty() {
+        bail!("No new name given");
+    }
+    if new == old {
+        bail!("{} is already the name of the branch", old);
+    }
+    naming::validate(naming_config, &new)?;
+    if repo.find_branch(&new, git2::BranchType::Local).is_ok() {
+        bail!("A branch named {} exists already", new);
+    }
+
+    let push = match push_target(repo, &old) {
+        Some((upstream, _)) if !push => {
+            let question = format!(
+                "Push {} to {} and delete {} there?",
+                new, upstream.remote, upstream.branch
+            );
+            prompt::confirm(&question, true)?
+        }
+        Some(_) => true,
+        None => {
+            if push {
+                eprintln!("{} has no upstream, renaming it locally only", old);
+            }
+            false
+        }
+    };
+
+    rename(repo, workdir, &old, &new, push, skip_hooks)?;
+    eprintln!("Renamed {} to {}", old, new);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git;
+    use crate::testing::TempDir;
+    use git2::BranchType;
+    use std::{fs, path::PathBuf};
+
+    fn run(clone: &Path, args: &[&str]) {
+        git::run_without_hooks(clone, args).unwrap();
+    }
+
+    /// A `clone` on `feat/old`, pushed to the bare `origin.git` and tracking it there
+    fn pushed(dir: &TempDir) -> (Repository, PathBuf) {
+        let origin = dir.join("origin.git");
+        let clone = dir.join("clone");
+        fs::create_dir_all(&clone).unwrap();
+        Repository::init_bare(&origin).unwrap();
+
+        run(&clone, &["init", "--quiet"]);
+        run(
+            &clone,
+            &[
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "--quiet",
+                "--allow-empty",
+                "-m",
+                "commit",
+            ],
+        );
+        run(&clone, &["checkout", "--quiet", "-b", "feat/old"]);
+        run(
+            &clone,
+            &["remote", "add", "origin", &origin.to_string_lossy()],
+        );
+        run(
+            &clone,
+            &["push", "--quiet", "--set-upstream", "origin", "feat/old"],
+        );
+        (Repository::open(&clone).unwrap(), clone)
+    }
+
+    fn upstream(remote: &str, branch: &str) -> Option<Upstream> {
+        Some(Upstream {
+            remote: remote.to_string(),
+            branch: branch.to_string(),
+        })
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = TempDir::new("rename");
+        let (repo, clone) = pushed(&dir);
+        rename(&repo, &clone, "feat/old", "feat/new", true, true).unwrap();
+
+        assert!(repo.find_branch("feat/new", BranchType::Local).is_ok());
+        assert_eq!(
+            Upstream::of(&repo, "feat/new"),
+            upstream("origin", "feat/new")
+        );
+        let origin = Repository::open_bare(dir.join("origin.git")).unwrap();
+        assert!(origin.find_reference("refs/heads/feat/new").is_ok());
+        assert!(origin.find_reference("refs/heads/feat/old").is_err());
+    }
+
+    #[test]
+    fn test_rename_with_failing_push() {
+        let dir = TempDir::new("rename-failing");
+        let (repo, clone) = pushed(&dir);
+        repo.config()
+            .unwrap()
+            .set_str("remote.origin.url", &dir.join("gone").to_string_lossy())
+            .unwrap();
+
+        // the local name is put back
+        assert!(rename(&repo, &clone, "feat/old", "feat/new", true, true).is_err());
+        assert!(repo.find_branch("feat/old", BranchType::Local).is_ok());
+        assert!(repo.find_branch("feat/new", BranchType::Local).is_err());
+    }
+
+    #[test]
+    fn test_rename_with_push_remote() {
+        let dir = TempDir::new("rename-push-remote");
+        let (repo, clone) = pushed(&dir);
+        let fork = dir.join("fork.git");
+        Repository::init_bare(&fork).unwrap();
+        run(&clone, &["remote", "add", "fork", &fork.to_string_lossy()]);
+        run(&clone, &["push", "--quiet", "fork", "feat/old"]);
+        run(&clone, &["config", "branch.feat/old.pushRemote", "fork"]);
+
+        // it is renamed on the push remote, and the upstream is kept
+        rename(&repo, &clone, "feat/old", "feat/new", true, true).unwrap();
+        assert_eq!(
+            Upstream::of(&repo, "feat/new"),
+            upstream("origin", "feat/old")
+        );
+        let fork = Repository::open_bare(&fork).unwrap();
+        assert!(fork.find_reference("refs/heads/feat/new").is_ok());
+        assert!(fork.find_reference("refs/heads/feat/old").is_err());
+    }
+}
@@ -0,0 +1,265 @@
+//! Triaging the conflicts of a merge that gibra started, when it stops on them: instead of
+//! aborting right away, the conflicted files are listed in a picker, to open each in `$EDITOR` or
+//! take our or their side of it, then conclude the merge, or abort it as before.
+use crate::skim::{event::Event, prelude::*};
+use crate::transaction::Transaction;
+use crate::{git, keymap, PickerOptions};
+use anyhow::{Context, Result};
+use git2::{IndexConflict, Repository};
+use std::{
+    fs,
+    io::{self, IsTerminal},
+    path::Path,
+    process::Command,
+};
+
+/// Takes our side of the file, as it is on the branch merged into
+const OURS_KEY: &str = "alt-o";
+
+/// Takes their side of the file, as it is on the branch being merged
+const THEIRS_KEY: &str = "alt-t";
+
+/// Concludes the merge once no file conflicts anymore
+const CONTINUE_KEY: &str = "alt-c";
+
+/// A file of the merge, which conflicts until it is resolved
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ConflictedFile {
+    path: String,
+    /// e.g. `both modified`, `None` once resolved
+    conflict: Option<&'static str>,
+}
+
+impl SkimItem for ConflictedFile {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.path)
+    }
+
+    fn display<'a>(&'a self, _context: DisplayContext<'a>) -> AnsiString<'a> {
+        let text = match self.conflict {
+            Some(conflict) => format!("✗ {} ({})", self.path, conflict),
+            None => format!("✓ {} (resolved)", self.path),
+        };
+        AnsiString::parse(&text)
+    }
+}
+
+/// How the sides of a conflicted file differ, after the entries of the index that are there
+fn describe(conflict: &IndexConflict) -> &'static str {
+    match (&conflict.ancestor, &conflict.our, &conflict.their) {
+        (_, Some(_), Some(_)) if conflict.ancestor.is_none() => "both added",
+        (_, Some(_), Some(_)) => "both modified",
+        (_, None, Some(_)) => "deleted by us",
+        (_, Some(_), None) => "deleted by them",
+        (_, None, None) => "both deleted",
+    }
+}
+
+/// The files that conflict in the index, with how
+fn conflicts(repo: &Repository) -> Result<Vec<(String, &'static str)>> {
+    let mut index = repo.index().with_context(|| "Failed to read the index")?;
+    // the merge ran in git, so what libgit2 read before is stale
+    index.read(true)?;
+    let mut conflicts = vec![];
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = [&conflict.our, &conflict.their, &conflict.ancestor]
+            .into_iter()
+            .find_map(|entry| entry.as_ref());
+        if let Some(entry) = entry {
+            let path = String::from_utf8_lossy(&entry.path).into_owned();
+            conflicts.push((path, describe(&conflict)));
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Whether `content` still has the conflict markers git wrote into it
+fn has_markers(content: &str) -> bool {
+    content.lines().any(|line| {
+        line.starts_with("<<<<<<< ") || line.starts_with(">>>>>>> ") || line == "======="
+    })
+}
+
+/// Open `path` in `$VISUAL` or `$EDITOR`, then mark it resolved when no conflict marker is left
+fn edit(workdir: &Path, path: &str) -> Result<Option<String>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .current_dir(workdir)
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        return Ok(Some(format!("{} exited with {}", editor, status)));
+    }
+
+    let content = fs::read_to_string(workdir.join(path)).unwrap_or_default();
+    if has_markers(&content) {
+        return Ok(Some(format!("{} still has conflict markers", path)));
+    }
+    git::run_quietly(workdir, &["add", "--", path])?;
+    Ok(None)
+}
+
+/// Resolve `path` with one side of the merge, `--ours` or `--theirs`. A side that deleted the
+/// file deletes it.
+fn take(workdir: &Path, path: &str, side: &str) -> Result<()> {
+    if git::run_quietly(workdir, &["checkout", side, "--", path]).is_ok() {
+        git::run_quietly(workdir, &["add", "--", path])
+    } else {
+        git::run_quietly(workdir, &["rm", "--quiet", "--", path])
+    }
+}
+
+/// What was chosen in the picker of the conflicts
+enum Choice {
+    Edit(String),
+    Take(String, &'static str),
+    Continue,
+    Abort,
+}
+
+fn select(
+    files: &[ConflictedFile],
+    what: &str,
+    message: Option<&str>,
+    options: &PickerOptions,
+) -> Result<Choice> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for file in files {
+        let _ = tx.send(Arc::new(file.clone()));
+    }
+    drop(tx);
+
+    let left = files.iter().filter(|file| file.conflict.is_some()).count();
+    let mut header = format!(
+        "{} stopped on conflicts, {} of {} files left · enter: edit · {}: ours · {}: theirs · {}: \
+         continue · esc: abort",
+        what,
+        left,
+        files.len(),
+        OURS_KEY,
+        THEIRS_KEY,
+        CONTINUE_KEY
+    );
+    if let Some(message) = message {
+        header.push('\n');
+        header.push_str(message);
+    }
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, options.keys.keymap, vec![]);
+    options.theme.apply(&mut builder);
+    let skim_options = builder
+        .header(Some(&header))
+        .nosort(true)
+        .expect(Some([OURS_KEY, THEIRS_KEY, CONTINUE_KEY].join(",")))
+        .accessible(options.accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    let output = match Skim::run_with(&skim_options, Some(rx)) {
+        Some(output) => output,
+        None => return Ok(Choice::Abort),
+    };
+    let path = output
+        .selected_items
+        .first()
+        .and_then(|item| (**item).as_any().downcast_ref::<ConflictedFile>())
+        .map(|file| file.path.clone());
+    Ok(match (&output.final_event, path) {
+        (Event::EvActAccept(Some(key)), _) if key == CONTINUE_KEY => Choice::Continue,
+        (Event::EvActAccept(Some(key)), Some(path)) if key == OURS_KEY => {
+            Choice::Take(path, "--ours")
+        }
+        (Event::EvActAccept(Some(key)), Some(path)) if key == THEIRS_KEY => {
+            Choice::Take(path, "--theirs")
+        }
+        (Event::EvActAccept(_), Some(path)) => Choice::Edit(path),
+        _ => Choice::Abort,
+    })
+}
+
+/// Whether the conflicts were resolved, or the merge is to be aborted
+fn triage(repo: &Repository, workdir: &Path, what: &str, options: &PickerOptions) -> Result<bool> {
+    let mut files: Vec<ConflictedFile> = conflicts(repo)?
+        .into_iter()
+        .map(|(path, conflict)| ConflictedFile {
+            path,
+            conflict: Some(conflict),
+        })
+        .collect();
+    let mut message = None;
+    loop {
+        let choice = select(&files, what, message.as_deref(), options)?;
+        message = None;
+        match choice {
+            Choice::Edit(path) => message = edit(workdir, &path)?,
+            Choice::Take(path, side) => {
+                if let Err(err) = take(workdir, &path, side) {
+                    message = Some(format!("{:#}", err));
+                }
+            }
+            Choice::Continue => {
+                let left = conflicts(repo)?;
+                if left.is_empty() {
+                    return Ok(true);
+                }
+                message = Some(format!("{} files still conflict", left.len()));
+            }
+            Choice::Abort => return Ok(false),
+        }
+
+        let left = conflicts(repo)?;
+        for file in &mut files {
+            file.conflict = left
+                .iter()
+                .find(|(path, _)| *path == file.path)
+                .map(|(_, conflict)| *conflict);
+        }
+    }
+}
+
+/// Deal with `failed`, the merge step `what` of `transaction` that failed. When it stopped on
+/// conflicts and there is someone to ask, they are triaged, and the merge is concluded as a step
+/// that `undo` reverts. Otherwise, or when aborted, the merge is aborted and `failed` returned.
+pub fn conclude(
+    transaction: &mut Transaction,
+    repo: &Repository,
+    workdir: &Path,
+    what: &str,
+    undo: Vec<Vec<String>>,
+    options: &PickerOptions,
+    failed: anyhow::Error,
+) -> Result<()> {
+    let conflicted = io::stdin().is_terminal() && !conflicts(repo)?.is_empty();
+    if conflicted && triage(repo, workdir, what, options)? {
+        transaction.run(
+            &format!("conclude {}", what),
+            &["commit", "--no-edit"],
+            undo,
+        )?;
+        return Ok(());
+    }
+    let _ = transaction.run("abort the merge", &["merge", "--abort"], vec![]);
+    Err(failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_markers() {
+        assert!(has_markers(
+            "fn main() {\n<<<<<<< HEAD\n    ours();\n=======\n    theirs();\n>>>>>>> topic\n}\n"
+        ));
+        assert!(!has_markers("fn main() {\n    both();\n}\n"));
+        // a line of `=` in a document isn't a marker on its own
+        assert!(!has_markers("Title\n==========\n"));
+    }
+}
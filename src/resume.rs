@@ -0,0 +1,120 @@
+//! Where the picker was left, to open it there again with `resume = true` under `[picker]`: the
+//! query, the kind of branches shown and the branch under the cursor. It is kept per worktree in
+//! `.git/gibra/picker.json`.
+use crate::skim::prelude::SkimOutput;
+use anyhow::{Context, Result};
+use git2::{BranchType, Repository};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf};
+
+/// The kind of branches shown, when not all of them are
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Filter {
+    Local,
+    Remote,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub query: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    filter: Option<Filter>,
+    /// The text of the item under the cursor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current: Option<String>,
+}
+
+impl State {
+    /// The state of the picker that showed the branches of `branch_filter` when it closed
+    pub fn of(output: &SkimOutput, branch_filter: Option<BranchType>) -> Self {
+        Self {
+            query: output.query.clone(),
+            filter: branch_filter.map(|branch_type| match branch_type {
+                BranchType::Local => Filter::Local,
+                BranchType::Remote => Filter::Remote,
+            }),
+            current: output
+                .current_item
+                .as_ref()
+                .map(|item| item.text().into_owned()),
+        }
+    }
+
+    pub fn branch_filter(&self) -> Option<BranchType> {
+        self.filter.map(|filter| match filter {
+            Filter::Local => BranchType::Local,
+            Filter::Remote => BranchType::Remote,
+        })
+    }
+}
+
+fn path(repo: &Repository) -> PathBuf {
+    repo.path().join("gibra").join("picker.json")
+}
+
+/// The state the picker was left in, or the initial one when none was saved
+pub fn load(repo: &Repository) -> State {
+    fs::read_to_string(path(repo))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write(repo: &Repository, state: &State) -> Result<()> {
+    let path = path(repo);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, serde_json::to_string(state)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Save the state the picker was left in. A failure is only logged, as the picker is closed
+/// already.
+pub fn save(repo: &Repository, state: &State) {
+    if let Err(err) = write(repo, state) {
+        warn!("Failed to save the state of the picker: {:#}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+
+    fn state() -> State {
+        State {
+            query: "login".to_string(),
+            filter: Some(Filter::Local),
+            current: Some("feat/login".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_load_unsaved() {
+        let repo = TempRepo::new("resume-unsaved");
+        assert_eq!(load(&repo), State::default());
+    }
+
+    #[test]
+    fn test_save() {
+        let repo = TempRepo::new("resume");
+        save(&repo, &state());
+        assert_eq!(load(&repo), state());
+        assert_eq!(load(&repo).branch_filter(), Some(BranchType::Local));
+        assert_eq!(
+            fs::read_to_string(path(&repo)).unwrap(),
+            r#"{"query":"login","filter":"local","current":"feat/login"}"#
+        );
+    }
+
+    #[test]
+    fn test_load_broken() {
+        let repo = TempRepo::new("resume-broken");
+        save(&repo, &state());
+        fs::write(path(&repo), "{").unwrap();
+        assert_eq!(load(&repo), State::default());
+    }
+}
@@ -0,0 +1,530 @@
+//! Triaging the pull requests waiting for your review on GitHub (`gibra review`).
+//!
+//! The open pull requests of the repository that request a review from you, or from one of your
+//! teams, are listed oldest first, since those have waited the longest. The preview shows the
+//! description and the diffstat of each; picking one checks its branch out, and the web key opens
+//! it in the browser instead.
+use crate::alternates;
+use crate::config::{self, GithubConfig};
+use crate::exec;
+use crate::git;
+use crate::history;
+use crate::keymap;
+use crate::meta::RepoMeta;
+use crate::pulls;
+use crate::remote;
+use crate::secrets::{self, Provider};
+use crate::skim::{event::Event, prelude::*};
+use crate::state;
+use crate::theme::{self, Theme};
+use crate::web;
+use crate::PickerOptions;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_FILE_NAME: &str = "reviews.toml";
+
+/// How long (in seconds) the fetched review requests are reused
+const CACHE_TTL: u64 = 5 * 60;
+
+/// A pull request that requests a review from you
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Review {
+    number: u64,
+    title: String,
+    author: String,
+    /// The head branch, e.g. `feat/login`
+    branch: String,
+    /// The head branch lives in a fork, so it is fetched from `refs/pull/<number>/head`
+    fork: bool,
+    /// The branch it is merged into, e.g. `main`
+    base: String,
+    /// When it was opened, in RFC 3339
+    created_at: String,
+    url: String,
+    /// The description, in Markdown
+    #[serde(default)]
+    body: String,
+}
+
+impl Review {
+    fn age(&self) -> String {
+        DateTime::parse_from_rfc3339(&self.created_at)
+            .map(|time| history::relative(time.with_timezone(&Local), Local::now()))
+            .unwrap_or_default()
+    }
+
+    /// Where the head of the pull request is fetched to
+    fn head_ref(&self, remote: &str) -> String {
+        if self.fork {
+            format!("refs/pull/{}/head", self.number)
+        } else {
+            format!("refs/remotes/{}/{}", remote, self.branch)
+        }
+    }
+}
+
+impl SkimItem for Review {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Owned(format!(
+            "#{} {} ({} by @{}, {})",
+            self.number,
+            self.title,
+            self.branch,
+            self.author,
+            self.age()
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct RepoReviews {
+    fetched_at: u64,
+    reviews: Vec<Review>,
+}
+
+/// The review requests of all repositories, keyed by `owner/repo`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ReviewCache {
+    repos: HashMap<String, RepoReviews>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+fn load_cache() -> ReviewCache {
+    cache_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(slug: &str, reviews: &[Review]) -> Result<()> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let mut cache = load_cache();
+    cache.repos.insert(
+        slug.to_string(),
+        RepoReviews {
+            fetched_at: now(),
+            reviews: reviews.to_vec(),
+        },
+    );
+    let content = toml::to_string(&cache).with_context(|| "Failed to serialize cache")?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct GithubSearch {
+    items: Vec<GithubIssue>,
+}
+
+#[derive(Deserialize)]
+struct GithubIssue {
+    number: u64,
+    title: String,
+    body: Option<String>,
+    created_at: String,
+    html_url: String,
+    user: GithubUser,
+}
+
+#[derive(Deserialize)]
+struct GithubUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GithubPull {
+    number: u64,
+    head: GithubRef,
+    base: GithubRef,
+}
+
+#[derive(Deserialize)]
+struct GithubRef {
+    #[serde(rename = "ref")]
+    branch: String,
+    repo: Option<GithubRepo>,
+}
+
+#[derive(Deserialize)]
+struct GithubRepo {
+    full_name: String,
+}
+
+/// Join the pull requests found by the search, which lack their branches, with the open pull
+/// requests of `slug`, oldest first
+fn join(slug: &str, issues: Vec<GithubIssue>, pulls: Vec<GithubPull>) -> Vec<Review> {
+    let pulls: HashMap<u64, GithubPull> =
+        pulls.into_iter().map(|pull| (pull.number, pull)).collect();
+    let mut reviews: Vec<Review> = issues
+        .into_iter()
+        .filter_map(|issue| {
+            let pull = pulls.get(&issue.number)?;
+            Some(Review {
+                number: issue.number,
+                title: issue.title,
+                author: issue.user.login,
+                branch: pull.head.branch.clone(),
+                fork: pull
+                    .head
+                    .repo
+                    .as_ref()
+                    .is_none_or(|repo| repo.full_name != slug),
+                base: pull.base.branch.clone(),
+                created_at: issue.created_at,
+                url: issue.html_url,
+                body: issue.body.unwrap_or_default(),
+            })
+        })
+        .collect();
+    reviews.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    reviews
+}
+
+/// The open pull requests of `slug` that request a review from the owner of the token
+fn fetch(config: &GithubConfig, slug: &str) -> Result<Vec<Review>> {
+    let token = secrets::token(Provider::Github, config.token.as_ref())?;
+    let api_url = config.api_url.trim_end_matches('/');
+    let agent = remote::http_agent();
+    let authorization = format!("Bearer {}", token.expose());
+
+    let search: GithubSearch = agent
+        .get(&format!("{}/search/issues", api_url))
+        .query(
+            "q",
+            &format!("repo:{} is:pr is:open review-requested:@me", slug),
+        )
+        .query("per_page", "100")
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &authorization)
+        .call()
+        .with_context(|| format!("Failed to fetch review requests of {}", slug))?
+        .into_json()
+        .with_context(|| format!("Failed to parse review requests of {}", slug))?;
+    if search.items.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let pulls: Vec<GithubPull> = agent
+        .get(&format!("{}/repos/{}/pulls", api_url, slug))
+        .query("state", "open")
+        .query("per_page", "100")
+        .set("Accept", "application/vnd.github+json")
+        .set("Authorization", &authorization)
+        .call()
+        .with_context(|| format!("Failed to fetch pull requests of {}", slug))?
+        .into_json()
+        .with_context(|| format!("Failed to parse pull requests of {}", slug))?;
+
+    Ok(join(slug, search.items, pulls))
+}
+
+/// The review requests, from the cache while it is fresh. When fetching fails, e.g. while
+/// offline, the cached ones are used no matter how old they are.
+fn reviews(config: &GithubConfig, slug: &str, refresh: bool) -> Result<Vec<Review>> {
+    let cached = load_cache().repos.remove(slug);
+    if let Some(cached) = &cached {
+        let is_fresh = now().saturating_sub(cached.fetched_at) < CACHE_TTL;
+        if is_fresh && !refresh {
+            return Ok(cached.reviews.clone());
+        }
+    }
+
+    match fetch(config, slug) {
+        Ok(reviews) => {
+            save_cache(slug, &reviews)?;
+            Ok(reviews)
+        }
+        Err(err) => match cached {
+            Some(cached) => {
+                eprintln!("warning: {:#}, showing the cached review requests", err);
+                Ok(cached.reviews)
+            }
+            None => Err(err),
+        },
+    }
+}
+
+/// The command previewing the review requests, by their number
+fn preview_command(workdir: &Path, theme: &Theme) -> String {
+    let workdir = exec::shell_quote(&workdir.to_string_lossy());
+    match std::env::current_exe() {
+        Ok(exe) => format!(
+            "exec {} review-preview{} {} {{1}}",
+            exec::shell_quote(&exe.to_string_lossy()),
+            theme.preview_flag(),
+            workdir
+        ),
+        Err(_) => "echo {}".to_string(),
+    }
+}
+
+/// Print the description of the review request `number` of the repository at `workdir`, and the
+/// files it changes when its branch was fetched, in the diff colors of `color`
+pub fn preview(workdir: &Path, number: &str, color: Option<&str>) -> Result<()> {
+    let repo = alternates::open(workdir).with_context(|| "Failed to open repository")?;
+    let meta = RepoMeta::new(&repo);
+    let github_repo = pulls::github_repo(&meta).with_context(|| "No GitHub remote")?;
+    let number: u64 = number
+        .trim_start_matches('#')
+        .parse()
+        .with_context(|| format!("{} is not a pull request", number))?;
+    let review = load_cache()
+        .repos
+        .remove(&github_repo.slug)
+        .and_then(|cached| {
+            cached
+                .reviews
+                .into_iter()
+                .find(|review| review.number == number)
+        })
+        .with_context(|| format!("#{} is not cached", number))?;
+
+    println!("#{} {}", review.number, review.title);
+    println!(
+        "{} into {}, opened by @{} {}",
+        review.branch,
+        review.base,
+        review.author,
+        review.age()
+    );
+    println!("{}\n", review.url);
+    let body = review.body.trim();
+    if !body.is_empty() {
+        println!("{}\n", body.replace("\r\n", "\n"));
+    }
+
+    let head = review.head_ref(&github_repo.remote);
+    if repo.find_reference(&head).is_err() {
+        println!("Not fetched yet, it is fetched when picked");
+        return Ok(());
+    }
+    let base = format!("refs/remotes/{}/{}", github_repo.remote, review.base);
+    git::command(workdir)
+        .args(theme::diff_options(color))
+        .args(["diff", "--stat", "--color=always"])
+        .arg(format!("{}...{}", base, head))
+        .status()
+        .with_context(|| "Failed to execute git diff")?;
+    Ok(())
+}
+
+/// Fetch the branch of `review` from `remote` and check it out: a local branch tracking it, or
+/// the head of a pull request from a fork on a detached HEAD
+fn check_out(repo: &Repository, git_root: &Path, remote: &str, review: &Review) -> Result<()> {
+    let head = review.head_ref(remote);
+    if review.fork {
+        git::run(
+            git_root,
+            &[
+                "fetch",
+                remote,
+                &format!("+refs/pull/{}/head:{}", review.number, head),
+            ],
+        )?;
+        return git::run(git_root, &["checkout", "--detach", &head]);
+    }
+
+    git::run(git_root, &["fetch", remote, &review.branch])?;
+    if repo
+        .find_branch(&review.branch, git2::BranchType::Local)
+        .is_ok()
+    {
+        return git::run(git_root, &["checkout", &review.branch]);
+    }
+    git::run(
+        git_root,
+        &["checkout", "-b", &review.branch, "--track", &head],
+    )
+}
+
+/// The picked review request, and whether the web key picked it
+fn select_review(
+    reviews: Vec<Review>,
+    workdir: &Path,
+    options: &PickerOptions,
+) -> Result<Option<(Review, bool)>> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for review in reviews {
+        let _ = tx.send(Arc::new(review));
+    }
+    drop(tx);
+
+    let preview = preview_command(workdir, &options.theme);
+    let header = format!(
+        "Review requests · {} opens in the browser",
+        options.keys.web
+    );
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, options.keys.keymap, vec![]);
+    options.theme.apply(&mut builder);
+    let skim_options = builder
+        .header(Some(&header))
+        .preview(Some(&preview))
+        .nosort(true)
+        .expect(Some(options.keys.web.clone()))
+        .accessible(options.accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    let output = match Skim::run_with(&skim_options, Some(rx)) {
+        Some(output) => output,
+        None => return Ok(None),
+    };
+    let web = match &output.final_event {
+        Event::EvActAbort => std::process::exit(130),
+        Event::EvActAccept(Some(key)) => *key == options.keys.web,
+        _ => false,
+    };
+    Ok(output
+        .selected_items
+        .first()
+        .and_then(|item| (**item).as_any().downcast_ref::<Review>())
+        .map(|review| (review.clone(), web)))
+}
+
+/// Pick one of the pull requests of the repository that wait for your review, then check out its
+/// branch, or open it in the browser
+pub fn run(
+    meta: &RepoMeta,
+    git_root: &Path,
+    config: Option<&GithubConfig>,
+    options: &PickerOptions,
+    refresh: bool,
+) -> Result<()> {
+    let config = match config {
+        Some(config) => config,
+        None => bail!(
+            "GitHub is not configured. Add it to the config file:\n\n[issues.github]\n\nand log in with `gibra auth login github`"
+        ),
+    };
+    let github_repo =
+        pulls::github_repo(meta).with_context(|| "The repository has no remote on GitHub")?;
+
+    let reviews = reviews(config, &github_repo.slug, refresh)?;
+    if reviews.is_empty() {
+        eprintln!(
+            "No pull request of {} waits for your review",
+            github_repo.slug
+        );
+        return Ok(());
+    }
+
+    let (review, web) = match select_review(reviews, git_root, options)? {
+        Some(picked) => picked,
+        None => return Ok(()),
+    };
+    if web {
+        eprintln!("Opening {}", review.url);
+        return web::open_url(&review.url);
+    }
+
+    let repo = meta.repo();
+    state::ensure_no_operation_in_progress(repo, git_root, options.checkout.force)?;
+    check_out(repo, git_root, &github_repo.remote, &review)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn issue(number: u64, created_at: &str) -> GithubIssue {
+        GithubIssue {
+            number,
+            title: format!("Pull {}", number),
+            body: None,
+            created_at: created_at.to_string(),
+            html_url: format!("https://github.com/owner/repo/pull/{}", number),
+            user: GithubUser {
+                login: "alice".to_string(),
+            },
+        }
+    }
+
+    fn pull(number: u64, branch: &str, repo: Option<&str>) -> GithubPull {
+        GithubPull {
+            number,
+            head: GithubRef {
+                branch: branch.to_string(),
+                repo: repo.map(|full_name| GithubRepo {
+                    full_name: full_name.to_string(),
+                }),
+            },
+            base: GithubRef {
+                branch: "main".to_string(),
+                repo: None,
+            },
+        }
+    }
+
+    /// The review of a branch of the repository itself, and of one from a fork
+    fn reviews() -> Vec<Review> {
+        join(
+            "owner/repo",
+            vec![
+                issue(3, "2024-05-03T10:00:00Z"),
+                issue(1, "2024-05-01T10:00:00Z"),
+                issue(2, "2024-05-02T10:00:00Z"),
+            ],
+            vec![
+                pull(1, "feat/login", Some("owner/repo")),
+                pull(3, "fix", Some("alice/repo")),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            reviews()
+                .iter()
+                .map(|review| (review.number, review.fork))
+                .collect::<Vec<_>>(),
+            vec![(1, false), (3, true)]
+        );
+    }
+
+    #[test]
+    fn test_head_ref() {
+        let reviews = reviews();
+        assert_eq!(
+            reviews[0].head_ref("origin"),
+            "refs/remotes/origin/feat/login"
+        );
+        assert_eq!(reviews[1].head_ref("origin"), "refs/pull/3/head");
+    }
+
+    #[test]
+    fn test_text() {
+        assert!(reviews()[0]
+            .text()
+            .starts_with("#1 Pull 1 (feat/login by @alice, "));
+    }
+}
@@ -0,0 +1,219 @@
+//! Listing only the branches that change a part of a monorepo (`--scope`).
+//!
+//! A branch is in scope when the changes it made since it forked from the default branch touch
+//! the scoped directory. The directories right below the scope that it touches are shown next to
+//! its name, e.g. `[api, web]`, to tell apart the areas the branches work on.
+use crate::context;
+use crate::diffstat;
+use crate::meta::RepoMeta;
+use crate::Branch;
+use anyhow::{bail, Context, Result};
+use git2::{Delta, DiffOptions, Oid, Repository};
+use std::{
+    collections::BTreeSet,
+    path::{Component, Path},
+};
+
+/// The number of components named next to a branch, the others are only counted
+const MAX_SHOWN: usize = 3;
+
+/// A directory of the repository, relative to its root
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scope {
+    /// `/`-separated and without a trailing slash, empty for the whole repository
+    path: String,
+}
+
+/// Resolve `.` and `..` in `path` without touching the file system, since the directory may only
+/// exist on other branches. `None` when it leaves the root.
+fn normalize(path: &Path) -> Option<Vec<String>> {
+    let mut parts: Vec<String> = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(parts)
+}
+
+impl Scope {
+    /// The scope of `path`, which is relative to the current directory like the paths given to git
+    pub fn new(repo: &Repository, path: &Path) -> Result<Self> {
+        let workdir = repo
+            .workdir()
+            .with_context(|| "--scope needs a working directory")?;
+        let workdir = context::canonical(workdir);
+        let current_dir = std::env::current_dir()
+            .with_context(|| "Failed to get current directory")?
+            .canonicalize()
+            .with_context(|| "Failed to get current directory")?;
+        let absolute = current_dir.join(path);
+        let relative = match absolute.strip_prefix(&workdir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => bail!("{} is outside the repository", path.display()),
+        };
+
+        match normalize(&relative) {
+            Some(parts) => Ok(Self {
+                path: parts.join("/"),
+            }),
+            None => bail!("{} is outside the repository", path.display()),
+        }
+    }
+
+    /// The component below the scope that `path` is in, e.g. `api` for `services/api/main.rs`
+    /// with the scope `services`. `None` when it is outside the scope.
+    fn component_of(&self, path: &str) -> Option<String> {
+        let rest = if self.path.is_empty() {
+            path
+        } else {
+            path.strip_prefix(&self.path)?.strip_prefix('/')?
+        };
+        rest.split('/').next().map(str::to_string)
+    }
+}
+
+/// The components below `scope` that `tip` changed since it forked from `base`
+fn components(repo: &Repository, scope: &Scope, tip: Oid, base: Oid) -> Result<BTreeSet<String>> {
+    let fork_point = repo
+        .merge_base(tip, base)
+        .with_context(|| "Failed to find the merge base")?;
+    let old_tree = repo.find_commit(fork_point)?.tree()?;
+    let new_tree = repo.find_commit(tip)?.tree()?;
+    let mut options = DiffOptions::new();
+    if !scope.path.is_empty() {
+        options.pathspec(&scope.path);
+    }
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut options))?;
+
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| {
+            let file = match delta.status() {
+                Delta::Deleted => delta.old_file(),
+                _ => delta.new_file(),
+            };
+            scope.component_of(&file.path()?.to_string_lossy())
+        })
+        .collect())
+}
+
+/// The label of the components, e.g. `[api, web +2]`
+pub fn label(components: &[String]) -> String {
+    let shown = components
+        .iter()
+        .take(MAX_SHOWN)
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(", ");
+    match components.len().saturating_sub(MAX_SHOWN) {
+        0 => format!("[{}]", shown),
+        more => format!("[{} +{}]", shown, more),
+    }
+}
+
+/// Drop the branches that don't change `scope`, and attach the components to the others. Without
+/// a default branch to compare with, all branches are kept.
+pub fn apply(meta: &RepoMeta, scope: &Scope, branches: &mut Vec<Branch>) {
+    let repo = meta.repo();
+    let base = match diffstat::base_commit(meta) {
+        Some(base) => base,
+        None => return,
+    };
+
+    branches.retain_mut(|branch| {
+        let components = diffstat::tip(repo, branch)
+            .and_then(|tip| components(repo, scope, tip, base).ok())
+            .unwrap_or_default();
+        if components.is_empty() {
+            return false;
+        }
+
+        let components = Some(components.into_iter().collect());
+        match branch {
+            Branch::Local(branch) => branch.components = components,
+            Branch::Remote(branch) => branch.components = components,
+            Branch::Ref(reference) => reference.components = components,
+        }
+        true
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("services/./api/../web")),
+            Some(vec!["services".to_string(), "web".to_string()])
+        );
+        assert_eq!(normalize(Path::new("")), Some(vec![]));
+        assert_eq!(normalize(Path::new("../elsewhere")), None);
+    }
+
+    /// `feature` changing `services/api` and `docs`, and adding `services/web`, since `main`
+    fn changed(name: &str) -> (TempRepo, Oid, Oid) {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "base",
+            &[("services/api/main.rs", "1\n"), ("docs/index.md", "1\n")],
+            &[],
+        );
+        let tip = testing::commit(
+            &repo,
+            Some("refs/heads/feature"),
+            "tip",
+            &[
+                ("services/api/main.rs", "2\n"),
+                ("services/web/app.ts", "1\n"),
+                ("docs/index.md", "2\n"),
+            ],
+            &[base],
+        );
+        (repo, tip, base)
+    }
+
+    fn scope(path: &str) -> Scope {
+        Scope {
+            path: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_components_of_whole_repo() {
+        let (repo, tip, base) = changed("scope");
+        assert_eq!(
+            components(&repo, &scope(""), tip, base).unwrap(),
+            BTreeSet::from(["docs".to_string(), "services".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_components_of_directory() {
+        let (repo, tip, base) = changed("scope-dir");
+        assert_eq!(
+            components(&repo, &scope("services"), tip, base).unwrap(),
+            BTreeSet::from(["api".to_string(), "web".to_string()])
+        );
+        assert!(components(&repo, &scope("services/db"), tip, base)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_label() {
+        let names: Vec<String> = ["a", "b", "c", "d", "e"].map(String::from).to_vec();
+        assert_eq!(label(&names[..2]), "[a, b]");
+        assert_eq!(label(&names), "[a, b, c +2]");
+    }
+}
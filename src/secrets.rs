@@ -0,0 +1,315 @@
+//! The tokens of the GitHub and Jira integrations, looked up in the environment, the keychain of
+//! the OS and the GitHub CLI, in that order. gibra stores them in the keychain only, never in the
+//! config file, and a [`Secret`] doesn't show its value when it is printed or logged.
+//!
+//! The keychain is reached through the `security` tool on macOS and `secret-tool` (libsecret) on
+//! Linux, which get the token on stdin so that it doesn't show up in the list of processes.
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{
+    env, fmt,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// The service the tokens are stored under in the keychain
+const KEYCHAIN_SERVICE: &str = "gibra";
+
+/// A token, which only gives away its value through [`Secret::expose`]
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    /// The first and last characters, enough to tell tokens apart, e.g. `ghp_…1a2b`
+    pub fn redacted(&self) -> String {
+        let chars: Vec<char> = self.0.chars().collect();
+        if chars.len() < 12 {
+            return "…".to_string();
+        }
+        let head: String = chars[..4].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}…{}", head, tail)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provider {
+    Github,
+    Jira,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 2] = [Provider::Github, Provider::Jira];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::Github => "github",
+            Provider::Jira => "jira",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Provider::Github => "GitHub",
+            Provider::Jira => "Jira",
+        }
+    }
+
+    /// The environment variables the token is read from, in order
+    fn env_vars(self) -> &'static [&'static str] {
+        match self {
+            Provider::Github => &["GIBRA_GITHUB_TOKEN", "GH_TOKEN", "GITHUB_TOKEN"],
+            Provider::Jira => &["GIBRA_JIRA_TOKEN"],
+        }
+    }
+}
+
+/// Where a token was found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Env(&'static str),
+    Keychain,
+    GithubCli,
+    /// The deprecated `token` of the config file
+    Config,
+}
+
+impl Source {
+    pub fn describe(self) -> String {
+        match self {
+            Source::Env(name) => format!("from ${}", name),
+            Source::Keychain => "from the keychain".to_string(),
+            Source::GithubCli => "from `gh auth token`".to_string(),
+            Source::Config => "from the config file, in plain text".to_string(),
+        }
+    }
+}
+
+/// The output of `command`, trimmed, when it succeeded and printed something
+fn output(command: &mut Command) -> Option<String> {
+    let output = command.stderr(Stdio::null()).output().ok()?;
+    let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    (output.status.success() && !value.is_empty()).then_some(value)
+}
+
+fn keychain_get(provider: Provider) -> Option<String> {
+    if cfg!(target_os = "macos") {
+        output(Command::new("security").args([
+            "find-generic-password",
+            "-s",
+            KEYCHAIN_SERVICE,
+            "-a",
+            provider.name(),
+            "-w",
+        ]))
+    } else if cfg!(unix) {
+        output(Command::new("secret-tool").args([
+            "lookup",
+            "service",
+            KEYCHAIN_SERVICE,
+            "account",
+            provider.name(),
+        ]))
+    } else {
+        None
+    }
+}
+
+/// Run `program` with `args`, writing `input` to its stdin
+fn run_with_input(program: &str, args: &[&str], input: &str) -> Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(input.as_bytes())
+            .with_context(|| format!("Failed to write to {}", program))?;
+    }
+    let status = child
+        .wait()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !status.success() {
+        bail!("{} failed ({})", program, status);
+    }
+
+    Ok(())
+}
+
+fn keychain_set(provider: Provider, token: &Secret) -> Result<()> {
+    if cfg!(target_os = "macos") {
+        // the interactive mode reads the command from stdin, keeping the token out of the arguments
+        let command = format!(
+            "add-generic-password -U -s {} -a {} -w \"{}\"\n",
+            KEYCHAIN_SERVICE,
+            provider.name(),
+            token.expose().replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        run_with_input("security", &["-i"], &command)
+    } else if cfg!(unix) {
+        let label = format!("gibra {} token", provider.label());
+        run_with_input(
+            "secret-tool",
+            &[
+                "store",
+                "--label",
+                &label,
+                "service",
+                KEYCHAIN_SERVICE,
+                "account",
+                provider.name(),
+            ],
+            token.expose(),
+        )
+    } else {
+        bail!(
+            "Storing tokens in the keychain is not supported on this platform, set ${} instead",
+            provider.env_vars()[0]
+        )
+    }
+}
+
+fn keychain_delete(provider: Provider) -> bool {
+    let status = if cfg!(target_os = "macos") {
+        Command::new("security")
+            .args([
+                "delete-generic-password",
+                "-s",
+                KEYCHAIN_SERVICE,
+                "-a",
+                provider.name(),
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    } else if cfg!(unix) {
+        Command::new("secret-tool")
+            .args([
+                "clear",
+                "service",
+                KEYCHAIN_SERVICE,
+                "account",
+                provider.name(),
+            ])
+            .status()
+    } else {
+        return false;
+    };
+
+    status.is_ok_and(|status| status.success())
+}
+
+/// The token of `provider` and where it was found. `configured` is the `token` of the config
+/// file, which is only used when there is no other.
+pub fn lookup(provider: Provider, configured: Option<&Secret>) -> Option<(Secret, Source)> {
+    for &name in provider.env_vars() {
+        if let Some(value) = env::var(name).ok().filter(|value| !value.is_empty()) {
+            return Some((Secret::new(value), Source::Env(name)));
+        }
+    }
+    if let Some(value) = keychain_get(provider) {
+        return Some((Secret::new(value), Source::Keychain));
+    }
+    if provider == Provider::Github {
+        if let Some(value) = output(Command::new("gh").args(["auth", "token"])) {
+            return Some((Secret::new(value), Source::GithubCli));
+        }
+    }
+
+    configured.map(|token| (token.clone(), Source::Config))
+}
+
+/// The token of `provider`, see [`lookup`]
+pub fn token(provider: Provider, configured: Option<&Secret>) -> Result<Secret> {
+    match lookup(provider, configured) {
+        Some((token, _)) => Ok(token),
+        None => bail!(
+            "No {} token found. Run `gibra auth login {}` or set ${}",
+            provider.label(),
+            provider.name(),
+            provider.env_vars()[0]
+        ),
+    }
+}
+
+/// Store the token of `provider` in the keychain, reading it from stdin
+pub fn login(provider: Provider) -> Result<()> {
+    let value = crate::prompt::secret(&format!("{} token:", provider.label()))?;
+    if value.is_empty() {
+        bail!("No token given");
+    }
+    let token = Secret::new(value);
+    keychain_set(provider, &token)?;
+    eprintln!(
+        "Stored the {} token {} in the keychain",
+        provider.label(),
+        token.redacted()
+    );
+
+    Ok(())
+}
+
+/// Remove the token of `provider` from the keychain
+pub fn logout(provider: Provider) -> Result<()> {
+    if !keychain_delete(provider) {
+        bail!("There is no {} token in the keychain", provider.label());
+    }
+    eprintln!("Removed the {} token from the keychain", provider.label());
+
+    Ok(())
+}
+
+/// Print where the token of each provider comes from
+pub fn status(configured: impl Fn(Provider) -> Option<Secret>) {
+    for provider in Provider::ALL {
+        let configured = configured(provider);
+        match lookup(provider, configured.as_ref()) {
+            Some((token, source)) => {
+                println!(
+                    "{}: {} {}",
+                    provider.name(),
+                    token.redacted(),
+                    source.describe()
+                );
+                if source == Source::Config {
+                    println!(
+                        "  move it to the keychain with `gibra auth login {}` and remove it from the config file",
+                        provider.name()
+                    );
+                }
+            }
+            None => println!("{}: not logged in", provider.name()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let token = Secret::new("ghp_0123456789abcdef1a2b".to_string());
+        assert_eq!(token.redacted(), "ghp_…1a2b");
+        assert_eq!(format!("{:?}", token), "Secret(<redacted>)");
+        assert_eq!(token.expose(), "ghp_0123456789abcdef1a2b");
+        assert_eq!(Secret::new("short".to_string()).redacted(), "…");
+    }
+}
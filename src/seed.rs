@@ -0,0 +1,258 @@
+//! Seeding a branch created with `gibra new --template <name>` with the changes of a template of
+//! the config file, e.g. a new section of the changelog or a feature flag, which are committed
+//! right away.
+use crate::config::{TemplateConfig, TemplateMode};
+use crate::{git, template};
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// The values substituted for `{branch}`, `{issue}` and `{date}`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Values {
+    pub branch: String,
+    /// The ID given with `--issue`, or nothing
+    pub issue: String,
+    /// Today, e.g. `2024-05-01`
+    pub date: String,
+}
+
+impl Values {
+    pub fn new(branch: &str, issue: Option<&str>) -> Self {
+        Self {
+            branch: branch.to_string(),
+            issue: issue.unwrap_or_default().to_string(),
+            date: Local::now().format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    fn expand(&self, template: &str) -> String {
+        template::expand(
+            template,
+            &[
+                ("branch", self.branch.clone()),
+                ("issue", self.issue.clone()),
+                ("date", self.date.clone()),
+            ],
+        )
+    }
+}
+
+/// The template `name`, or an error telling the ones there are
+pub fn find<'a>(
+    templates: &'a HashMap<String, TemplateConfig>,
+    name: &str,
+) -> Result<&'a TemplateConfig> {
+    if let Some(template) = templates.get(name) {
+        return Ok(template);
+    }
+    let mut names: Vec<&str> = templates.keys().map(String::as_str).collect();
+    if names.is_empty() {
+        bail!(
+            "There is no template {}, none are set under [templates]",
+            name
+        );
+    }
+    names.sort_unstable();
+    bail!(
+        "There is no template {}, the templates are {}",
+        name,
+        names.join(", ")
+    )
+}
+
+/// Where `path` is in `workdir`, refusing the paths that lead out of it
+fn target(workdir: &Path, path: &str) -> Result<PathBuf> {
+    let relative = Path::new(path);
+    let inside = relative
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if path.is_empty() || !inside {
+        bail!("{} is not a path in the working tree", path);
+    }
+    Ok(workdir.join(relative))
+}
+
+/// `top` above `bottom`, a blank line apart
+fn stack(top: &str, bottom: &str) -> String {
+    let top = top.trim_end_matches('\n');
+    let bottom = bottom.trim_start_matches('\n');
+    if bottom.is_empty() {
+        format!("{}\n", top)
+    } else {
+        format!("{}\n\n{}", top, bottom)
+    }
+}
+
+/// What a file contains once `content` is written into it with `mode`, `existing` being what it
+/// contained before. `None` when it must not exist yet.
+fn seeded(existing: Option<&str>, content: &str, mode: TemplateMode) -> Option<String> {
+    match (mode, existing) {
+        (_, None) => Some(content.to_string()),
+        (TemplateMode::Create, Some(_)) => None,
+        (TemplateMode::Append, Some(existing)) => {
+            if existing.is_empty() || existing.ends_with('\n') {
+                Some(format!("{}{}", existing, content))
+            } else {
+                Some(format!("{}\n{}", existing, content))
+            }
+        }
+        (TemplateMode::Prepend, Some(existing)) => match existing.split_once('\n') {
+            Some((title, rest)) if title.starts_with("# ") => {
+                Some(format!("{}\n\n{}", title, stack(content, rest)))
+            }
+            _ => Some(stack(content, existing)),
+        },
+    }
+}
+
+/// Write the files of `template` into the working tree at `workdir`, and commit them. Nothing is
+/// written unless all of them can be.
+pub fn apply(workdir: &Path, template: &TemplateConfig, values: &Values) -> Result<()> {
+    let mut writes = vec![];
+    for file in &template.files {
+        let path = values.expand(&file.path);
+        let target = target(workdir, &path)?;
+        let existing = match fs::read_to_string(&target) {
+            Ok(existing) => Some(existing),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", target.display()))
+            }
+        };
+        let content = seeded(
+            existing.as_deref(),
+            &values.expand(&file.content),
+            file.mode,
+        )
+        .with_context(|| format!("{} exists already", path))?;
+        writes.push((path, target, content));
+    }
+
+    for (_, target, content) in &writes {
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        fs::write(target, content)
+            .with_context(|| format!("Failed to write {}", target.display()))?;
+    }
+
+    let message = values.expand(&template.message);
+    let paths: Vec<&str> = writes.iter().map(|(path, _, _)| path.as_str()).collect();
+    if paths.is_empty() {
+        return git::run(workdir, &["commit", "--allow-empty", "-m", &message]);
+    }
+    let mut add = vec!["add", "--"];
+    add.extend(&paths);
+    git::run(workdir, &add)?;
+    let mut commit = vec!["commit", "-m", &message, "--"];
+    commit.extend(&paths);
+    git::run(workdir, &commit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TemplateFileConfig;
+    use crate::testing::TempRepo;
+
+    #[test]
+    fn test_seeded() {
+        let changelog = "# Changelog\n\n## 1.0\n";
+        assert_eq!(
+            seeded(Some(changelog), "## feat/login\n", TemplateMode::Prepend).unwrap(),
+            "# Changelog\n\n## feat/login\n\n## 1.0\n"
+        );
+        assert_eq!(
+            seeded(Some("## 1.0\n"), "## next\n", TemplateMode::Prepend).unwrap(),
+            "## next\n\n## 1.0\n"
+        );
+        assert_eq!(
+            seeded(Some("a"), "b\n", TemplateMode::Append).unwrap(),
+            "a\nb\n"
+        );
+        assert_eq!(seeded(Some("a\n"), "b\n", TemplateMode::Create), None);
+        assert_eq!(seeded(None, "b\n", TemplateMode::Prepend).unwrap(), "b\n");
+    }
+
+    #[test]
+    fn test_target() {
+        let workdir = Path::new("/repo");
+        assert_eq!(
+            target(workdir, "flags/login.toml").unwrap(),
+            PathBuf::from("/repo/flags/login.toml")
+        );
+        assert!(target(workdir, "../outside").is_err());
+        assert!(target(workdir, "/etc/passwd").is_err());
+        assert!(target(workdir, "").is_err());
+    }
+
+    /// A repository with a changelog, a template prepending to it and creating a flag, and the
+    /// values to fill it in with
+    fn seeding(name: &str) -> (TempRepo, TemplateConfig, Values) {
+        let repo = TempRepo::new(name);
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        fs::write(repo.dir().join("CHANGELOG.md"), "# Changelog\n").unwrap();
+
+        let template = TemplateConfig {
+            files: vec![
+                TemplateFileConfig {
+                    path: "CHANGELOG.md".to_string(),
+                    content: "## {branch} ({date})\n".to_string(),
+                    mode: TemplateMode::Prepend,
+                },
+                TemplateFileConfig {
+                    path: "flags/{issue}.toml".to_string(),
+                    content: "enabled = false\n".to_string(),
+                    mode: TemplateMode::Create,
+                },
+            ],
+            message: "chore: start {branch}".to_string(),
+        };
+        let values = Values {
+            branch: "feat/login".to_string(),
+            issue: "ABC-1".to_string(),
+            date: "2024-05-01".to_string(),
+        };
+        (repo, template, values)
+    }
+
+    #[test]
+    fn test_apply() {
+        let (repo, template, values) = seeding("seed");
+        let dir = repo.dir();
+        apply(dir, &template, &values).unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.message(), Some("chore: start feat/login\n"));
+        let tree = head.tree().unwrap();
+        assert!(tree.get_path(Path::new("flags/ABC-1.toml")).is_ok());
+        assert_eq!(
+            fs::read_to_string(dir.join("CHANGELOG.md")).unwrap(),
+            "# Changelog\n\n## feat/login (2024-05-01)\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_over_created_file() {
+        let (repo, template, values) = seeding("seed-again");
+        let dir = repo.dir();
+        fs::create_dir_all(dir.join("flags")).unwrap();
+        fs::write(dir.join("flags/ABC-1.toml"), "enabled = true\n").unwrap();
+
+        // the flag exists, so nothing is written
+        assert!(apply(dir, &template, &values).is_err());
+        assert_eq!(
+            fs::read_to_string(dir.join("CHANGELOG.md")).unwrap(),
+            "# Changelog\n"
+        );
+        assert!(repo.head().is_err());
+    }
+}
@@ -0,0 +1,427 @@
+//! `gibra prompt-segment`: the state of the repository in a few characters for the shell prompt,
+//! e.g. `main* ↑1 ↓2 · wip`: the current branch, `*` when its tracked files have changes, how far
+//! it diverged from its upstream, and the branches checked out before it.
+//!
+//! The prompt is drawn before every command, so however large the repository, the segment waits
+//! no longer than [`BUDGET`] for what is slow to find out. The branch is read from HEAD, and the
+//! rest is found in a thread: when it isn't found by then, it's taken from the last segment,
+//! cached in `.git/gibra/prompt.toml`, if that was found for the same commits, or else shown as
+//! `…`. Either way, a gibra in the background finds it for the next prompt.
+use crate::alternates;
+use crate::context::Head;
+use crate::cursor;
+use crate::meta::RepoMeta;
+use crate::upstream::Divergence;
+use anyhow::{Context, Result};
+use crossbeam::channel::bounded;
+use git2::{BranchType, Oid, Repository, StatusOptions};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the segment waits for what is slow to find out
+pub const BUDGET: Duration = Duration::from_millis(10);
+
+const CACHE_FILE_NAME: &str = "prompt.toml";
+
+/// Created while a gibra in the background finds the segment, so that the prompts drawn meanwhile
+/// don't start another
+const LOCK_FILE_NAME: &str = "prompt.lock";
+
+/// A lock older than this was left by a gibra that died
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// The shell whose prompt the segment goes in, for the colors not to count in its width
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+}
+
+/// What the segment is found for: the branch checked out, and the commits of HEAD and of its
+/// upstream
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Key {
+    branch: Option<String>,
+    head: Option<String>,
+    upstream: Option<String>,
+}
+
+impl Key {
+    fn of(repo: &Repository, head: &Head) -> Self {
+        let upstream = head
+            .branch
+            .as_ref()
+            .and_then(|name| repo.find_branch(name, BranchType::Local).ok())
+            .and_then(|branch| branch.upstream().ok())
+            .and_then(|upstream| upstream.get().target());
+        Self {
+            branch: head.branch.clone(),
+            head: head.oid.map(|oid| oid.to_string()),
+            upstream: upstream.map(|oid| oid.to_string()),
+        }
+    }
+
+    fn oid(hex: &Option<String>) -> Option<Oid> {
+        hex.as_deref().and_then(|hex| Oid::from_str(hex).ok())
+    }
+}
+
+/// What is slow to find out
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Found {
+    key: Key,
+    dirty: bool,
+    /// How far HEAD is ahead of and behind its upstream, when it has one
+    divergence: Option<(usize, usize)>,
+    /// The branches checked out before the current one, most recent first
+    recent: Vec<String>,
+}
+
+fn find(repo: &Repository, key: &Key, recent: usize) -> Found {
+    let divergence = Key::oid(&key.head)
+        .zip(Key::oid(&key.upstream))
+        .and_then(|(tip, upstream)| RepoMeta::new(repo).divergence(tip, upstream))
+        .map(|divergence| (divergence.ahead, divergence.behind));
+    Found {
+        key: key.clone(),
+        dirty: is_dirty(repo),
+        divergence,
+        recent: recent_branches(repo, key.branch.as_deref(), recent),
+    }
+}
+
+/// Whether the tracked files have changes, staged or not. Untracked files are left out, as
+/// finding them walks the whole working tree.
+fn is_dirty(repo: &Repository) -> bool {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+    repo.statuses(Some(&mut options))
+        .is_ok_and(|statuses| !statuses.is_empty())
+}
+
+/// The `count` local branches most recently left, other than `current`
+fn recent_branches(repo: &Repository, current: Option<&str>, count: usize) -> Vec<String> {
+    let mut seen = HashSet::new();
+    cursor::left_branches(repo)
+        .into_iter()
+        .filter(|name| Some(name.as_str()) != current)
+        .filter(|name| seen.insert(name.clone()))
+        .filter(|name| repo.find_branch(name, BranchType::Local).is_ok())
+        .take(count)
+        .collect()
+}
+
+fn cache_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("gibra").join(CACHE_FILE_NAME)
+}
+
+fn load_cache(git_dir: &Path) -> Option<Found> {
+    fs::read_to_string(cache_path(git_dir))
+        .ok()
+        .and_then(|content| toml::from_str(&content).ok())
+}
+
+fn save_cache(git_dir: &Path, found: &Found) -> Result<()> {
+    if load_cache(git_dir).as_ref() == Some(found) {
+        return Ok(());
+    }
+    let path = cache_path(git_dir);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(found).with_context(|| "Failed to serialize the segment")?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Start a gibra that finds the segment and caches it, unless one is running already
+fn refresh_in_background(git_dir: &Path, recent: usize) {
+    let lock = git_dir.join("gibra").join(LOCK_FILE_NAME);
+    let running = fs::metadata(&lock)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < STALE_LOCK);
+    if running {
+        return;
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(_) => return,
+    };
+    if fs::create_dir_all(git_dir.join("gibra"))
+        .and_then(|_| fs::write(&lock, ""))
+        .is_err()
+    {
+        return;
+    }
+    let spawned = Command::new(exe)
+        .args([
+            "prompt-segment",
+            "--refresh",
+            "--recent",
+            &recent.to_string(),
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if spawned.is_err() {
+        let _ = fs::remove_file(&lock);
+    }
+}
+
+/// How the parts of the segment are colored
+struct Paint {
+    shell: Option<Shell>,
+    plain: bool,
+}
+
+impl Paint {
+    /// `text` in the SGR color `code`, e.g. `1;32` for bold green
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.plain {
+            return text.to_string();
+        }
+        format!(
+            "{}{}{}",
+            self.escape(&format!("\x1b[{}m", code)),
+            text,
+            self.escape("\x1b[0m")
+        )
+    }
+
+    fn escape(&self, sequence: &str) -> String {
+        match self.shell {
+            Some(Shell::Bash) => format!("\\[{}\\]", sequence),
+            Some(Shell::Zsh) => format!("%{{{}%}}", sequence),
+            None => sequence.to_string(),
+        }
+    }
+}
+
+/// The segment of `key`, with `…` for what wasn't `found`
+fn render(key: &Key, found: Option<&Found>, shallow: bool, paint: &Paint) -> String {
+    let name = match (&key.branch, &key.head) {
+        (Some(branch), _) => branch.clone(),
+        (None, Some(head)) => format!("({})", &head[..7]),
+        (None, None) => return String::new(),
+    };
+    let mut segment = paint.paint("1;32", &name);
+    let found = match found {
+        Some(found) => found,
+        None => return format!("{} {}", segment, paint.paint("2", "…")),
+    };
+
+    if found.dirty {
+        segment.push_str(&paint.paint("33", "*"));
+    }
+    if let Some((ahead, behind)) = found.divergence {
+        let label = Divergence {
+            ahead,
+            behind,
+            shallow,
+            pending: false,
+        }
+        .label();
+        if !label.is_empty() {
+            segment.push_str(&format!(" {}", paint.paint("36", &label)));
+        }
+    }
+    if !found.recent.is_empty() {
+        segment.push_str(&format!(" · {}", paint.paint("2", &found.recent.join(" "))));
+    }
+    segment
+}
+
+/// Print the segment of `repo`, whose HEAD is `head`, or with `refresh`, find it and cache it for
+/// the next prompt
+pub fn run(
+    repo: &Repository,
+    head: &Head,
+    shell: Option<Shell>,
+    plain: bool,
+    recent: usize,
+    refresh: bool,
+) -> Result<()> {
+    let started = Instant::now();
+    let git_dir = repo.path();
+    let key = Key::of(repo, head);
+    if refresh {
+        let saved = save_cache(git_dir, &find(repo, &key, recent));
+        let _ = fs::remove_file(git_dir.join("gibra").join(LOCK_FILE_NAME));
+        return saved;
+    }
+
+    let (tx, rx) = bounded(1);
+    let worker_dir = git_dir.to_path_buf();
+    let worker_key = key.clone();
+    thread::spawn(move || {
+        if let Ok(repo) = alternates::open(&worker_dir) {
+            let _ = tx.send(find(&repo, &worker_key, recent));
+        }
+    });
+    let found = match rx.recv_timeout(BUDGET.saturating_sub(started.elapsed())) {
+        Ok(found) => {
+            if let Err(err) = save_cache(git_dir, &found) {
+                debug!("{:#}", err);
+            }
+            Some(found)
+        }
+        Err(_) => {
+            refresh_in_background(git_dir, recent);
+            load_cache(git_dir).filter(|found| found.key == key)
+        }
+    };
+
+    let paint = Paint { shell, plain };
+    println!(
+        "{}",
+        render(&key, found.as_ref(), repo.is_shallow(), &paint)
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    const PLAIN: Paint = Paint {
+        shell: None,
+        plain: true,
+    };
+
+    fn key() -> Key {
+        Key {
+            branch: Some("main".to_string()),
+            head: Some("1234567890abcdef".to_string()),
+            upstream: None,
+        }
+    }
+
+    fn found(dirty: bool, divergence: (usize, usize), recent: &[&str]) -> Found {
+        Found {
+            key: key(),
+            dirty,
+            divergence: Some(divergence),
+            recent: recent.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_unfound() {
+        assert_eq!(render(&key(), None, false, &PLAIN), "main …");
+    }
+
+    #[test]
+    fn test_render() {
+        let diverged = found(true, (1, 2), &["wip"]);
+        assert_eq!(
+            render(&key(), Some(&diverged), false, &PLAIN),
+            "main* ↑1 ↓2 · wip"
+        );
+        assert_eq!(
+            render(&key(), Some(&diverged), true, &PLAIN),
+            "main* ↑? ↓? · wip"
+        );
+        let clean = found(false, (0, 0), &[]);
+        assert_eq!(render(&key(), Some(&clean), false, &PLAIN), "main");
+    }
+
+    #[test]
+    fn test_render_detached() {
+        let detached = Key {
+            branch: None,
+            ..key()
+        };
+        let clean = found(false, (0, 0), &[]);
+        assert_eq!(render(&detached, Some(&clean), false, &PLAIN), "(1234567)");
+    }
+
+    #[test]
+    fn test_render_for_shells() {
+        let zsh = Paint {
+            shell: Some(Shell::Zsh),
+            plain: false,
+        };
+        let clean = found(false, (0, 0), &[]);
+        assert_eq!(
+            render(&key(), Some(&clean), false, &zsh),
+            "%{\x1b[1;32m%}main%{\x1b[0m%}"
+        );
+        let bash = Paint {
+            shell: Some(Shell::Bash),
+            plain: false,
+        };
+        assert_eq!(bash.paint("33", "*"), "\\[\x1b[33m\\]*\\[\x1b[0m\\]");
+    }
+
+    /// The current branch checked out a commit ahead of `wip`, which it tracks and was checked out
+    /// before. Returns its key and the commit of `wip`.
+    fn switched(name: &str) -> (TempRepo, Key, Oid) {
+        let repo = TempRepo::new(name);
+        let first = testing::commit(&repo, Some("HEAD"), "first", &[("file", "one")], &[]);
+        let current = repo.head().unwrap().shorthand().unwrap().to_string();
+        repo.branch("wip", &repo.find_commit(first).unwrap(), false)
+            .unwrap();
+        repo.set_head("refs/heads/wip").unwrap();
+        repo.reference_symbolic(
+            "HEAD",
+            &format!("refs/heads/{}", current),
+            true,
+            &format!("checkout: moving from wip to {}", current),
+        )
+        .unwrap();
+        testing::commit(&repo, Some("HEAD"), "second", &[], &[first]);
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        let mut config = repo.config().unwrap();
+        config
+            .set_str(&format!("branch.{}.remote", current), ".")
+            .unwrap();
+        config
+            .set_str(&format!("branch.{}.merge", current), "refs/heads/wip")
+            .unwrap();
+
+        let head = Head {
+            branch: Some(current),
+            oid: repo.head().unwrap().target(),
+        };
+        let key = Key::of(&repo, &head);
+        (repo, key, first)
+    }
+
+    #[test]
+    fn test_find() {
+        let (repo, key, first) = switched("prompt");
+        assert_eq!(key.upstream, Some(first.to_string()));
+        let found = find(&repo, &key, 1);
+        assert!(!found.dirty);
+        assert_eq!(found.divergence, Some((1, 0)));
+        assert_eq!(found.recent, vec!["wip".to_string()]);
+        assert!(find(&repo, &key, 0).recent.is_empty());
+    }
+
+    #[test]
+    fn test_find_dirty() {
+        let (repo, key, _) = switched("prompt-dirty");
+        fs::write(repo.dir().join("file"), "two").unwrap();
+        assert!(find(&repo, &key, 1).dirty);
+    }
+
+    #[test]
+    fn test_cache() {
+        let (repo, key, _) = switched("prompt-cache");
+        let found = find(&repo, &key, 1);
+        // read back as found, with the commits it was found for
+        save_cache(repo.path(), &found).unwrap();
+        assert_eq!(load_cache(repo.path()), Some(found));
+    }
+}
@@ -0,0 +1,108 @@
+//! Named sets of branches saved from the picker, e.g. the branches of a release under review.
+//!
+//! A set is a file under `.git/gibra/sets/` with one branch name per line. Loading a set
+//! pre-selects its branches in the picker, to delete or compare them in one go.
+use anyhow::{bail, Context, Result};
+use git2::Repository;
+use std::{fs, path::PathBuf};
+
+fn sets_dir(repo: &Repository) -> PathBuf {
+    repo.path().join("gibra").join("sets")
+}
+
+fn set_path(repo: &Repository, name: &str) -> Result<PathBuf> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(|ch: char| ch == '/' || ch == '\\' || ch.is_whitespace())
+    {
+        bail!(
+            "Invalid set name {:?}, use letters, digits, '-' or '_' (e.g. release-review)",
+            name
+        );
+    }
+    Ok(sets_dir(repo).join(name))
+}
+
+/// The names of the saved sets, sorted
+pub fn list(repo: &Repository) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(sets_dir(repo))
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// Save `branches` as the set `name`, replacing an existing set of that name
+pub fn save(repo: &Repository, name: &str, branches: &[String]) -> Result<PathBuf> {
+    let path = set_path(repo, name)?;
+    fs::create_dir_all(sets_dir(repo)).with_context(|| "Failed to create the sets directory")?;
+
+    let mut content = branches.join("\n");
+    content.push('\n');
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// The branches of the set `name`
+pub fn load(repo: &Repository, name: &str) -> Result<Vec<String>> {
+    let path = set_path(repo, name)?;
+    if !path.is_file() {
+        let saved = list(repo);
+        if saved.is_empty() {
+            bail!("There is no set named {}, save one with --save-set", name);
+        }
+        bail!(
+            "There is no set named {}, the saved sets are: {}",
+            name,
+            saved.join(", ")
+        );
+    }
+
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempRepo;
+
+    #[test]
+    fn test_save_and_load() {
+        let repo = TempRepo::new("sets");
+        let branches = vec!["feature/a".to_string(), "origin/feature/b".to_string()];
+        save(&repo, "release-review", &branches).unwrap();
+        save(&repo, "cleanup", &["old".to_string()]).unwrap();
+        assert_eq!(load(&repo, "release-review").unwrap(), branches);
+        assert_eq!(list(&repo), vec!["cleanup", "release-review"]);
+    }
+
+    #[test]
+    fn test_load_missing() {
+        let repo = TempRepo::new("sets-missing");
+        assert!(load(&repo, "release-review").is_err());
+
+        save(&repo, "release-review", &[]).unwrap();
+        save(&repo, "cleanup", &[]).unwrap();
+        let err = load(&repo, "other").unwrap_err().to_string();
+        assert!(err.contains("cleanup, release-review"));
+    }
+
+    #[test]
+    fn test_save_outside() {
+        let repo = TempRepo::new("sets-outside");
+        assert!(save(&repo, "../escape", &[]).is_err());
+    }
+}
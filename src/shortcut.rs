@@ -0,0 +1,273 @@
+//! Branches created from the query of the picker (`[shortcuts]`). A query starting with a
+//! shortcut followed by words, e.g. `f ticket-123 fix parser` with `f = "feature/{words}"`, lists
+//! `create feature/ticket-123-fix-parser from main` before the matched branches, and accepting it
+//! creates the branch and checks it out.
+//!
+//! The name is checked against the naming convention while typing, so a name that breaks it, or
+//! a branch that exists already, is shown as such and can't be picked.
+use crate::config::{NamingConfig, ShortcutConfig};
+use crate::meta::RepoMeta;
+use crate::naming;
+use crate::remote;
+use crate::skim::prelude::*;
+use crate::skim::QueryItem;
+use crate::template::Template;
+use anyhow::{bail, Result};
+use git2::BranchType;
+use std::{
+    collections::{BTreeMap, HashSet},
+    rc::Rc,
+};
+
+/// The placeholders of the templates of the shortcuts
+pub const PLACEHOLDERS: [&str; 3] = ["words", "first", "rest"];
+
+/// Characters that can't be part of a branch name, replaced by dashes in the typed words
+const INVALID_CHARS: &[char] = &['~', '^', ':', '?', '*', '[', '\\'];
+
+/// A shortcut of `[shortcuts]`
+#[derive(Clone, Debug)]
+pub struct Shortcut {
+    key: String,
+    template: Template,
+    base: Option<String>,
+}
+
+impl Shortcut {
+    /// The shortcuts of the config, each a single word, as the query is split on whitespace
+    pub fn all(config: &BTreeMap<String, ShortcutConfig>) -> Result<Vec<Shortcut>> {
+        config
+            .iter()
+            .map(|(key, shortcut)| {
+                if key.is_empty() || key.contains(char::is_whitespace) {
+                    bail!("The shortcut {:?} must be a single word", key);
+                }
+                Ok(Shortcut {
+                    key: key.clone(),
+                    template: Template::parse_strict(
+                        &shortcut.template,
+                        &PLACEHOLDERS,
+                        "the template of a shortcut",
+                    )?,
+                    base: shortcut.base.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// The name of the branch `query` makes, when it is this shortcut followed by words
+    fn name(&self, query: &str) -> Option<String> {
+        let mut words = query.split_whitespace();
+        if words.next()? != self.key {
+            return None;
+        }
+        let words: Vec<String> = words.filter_map(sanitize).collect();
+        let (first, rest) = words.split_first()?;
+        Some(self.template.render(&[
+            ("words", words.join("-")),
+            ("first", first.clone()),
+            ("rest", rest.join("-")),
+        ]))
+    }
+}
+
+/// `word` with the characters a branch name can't have replaced by dashes, or `None` when
+/// nothing is left of it
+fn sanitize(word: &str) -> Option<String> {
+    let word: String = word
+        .chars()
+        .map(|c| {
+            if c.is_control() || INVALID_CHARS.contains(&c) {
+                '-'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let word = word.trim_matches(|c| c == '-' || c == '.' || c == '/');
+    (!word.is_empty()).then(|| word.to_string())
+}
+
+/// A branch to create, made from the query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewBranch {
+    pub name: String,
+    /// Where it starts, e.g. `main`, or `HEAD` when there is no default branch
+    pub base: String,
+    /// Whether `base` is the default branch of the remote, fetched right before with
+    /// `fresh_base`
+    pub fresh: bool,
+    /// Why it can't be created, e.g. as it exists already
+    pub invalid: Option<String>,
+}
+
+impl SkimItem for NewBranch {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.name)
+    }
+
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let (text, fg) = match &self.invalid {
+            Some(reason) => (
+                format!("can't create {}: {}", self.name, reason),
+                Color::RED,
+            ),
+            None => (
+                format!("create {} from {}", self.name, self.base),
+                Color::GREEN,
+            ),
+        };
+        if context.accessible {
+            return AnsiString::from(text);
+        }
+        let end = text.chars().count() as u32;
+        AnsiString::new_string(
+            text,
+            vec![(
+                Attr {
+                    fg,
+                    ..Attr::default()
+                },
+                (0, end),
+            )],
+        )
+    }
+
+    fn is_selectable(&self) -> bool {
+        self.invalid.is_none()
+    }
+}
+
+/// Where the branches of the shortcuts without a base start: the default branch, or with
+/// `fresh_base` the one of the remote, and whether it is fetched right before
+fn default_base(meta: &RepoMeta, fresh_base: bool) -> (String, bool) {
+    let repo = meta.repo();
+    if fresh_base {
+        if let Some(remote) = remote::base_remote(repo) {
+            return (format!("{}/{}", remote, meta.default_branch(&remote)), true);
+        }
+    }
+    let exists = |name: &str, branch_type| repo.find_branch(name, branch_type).is_ok();
+    if let Some(remote) = meta.primary_remote() {
+        let name = meta.default_branch(remote);
+        let tracking = format!("{}/{}", remote, name);
+        if exists(&name, BranchType::Local) {
+            return (name, false);
+        }
+        if exists(&tracking, BranchType::Remote) {
+            return (tracking, false);
+        }
+    }
+    let local = ["main", "master"]
+        .into_iter()
+        .find(|name| exists(name, BranchType::Local));
+    (local.unwrap_or("HEAD").to_string(), false)
+}
+
+/// The item listed first while the query starts with one of `shortcuts`, see
+/// [`SkimOptions::query_item`]
+pub fn query_item(
+    meta: &RepoMeta,
+    shortcuts: &[Shortcut],
+    naming: &NamingConfig,
+    fresh_base: bool,
+) -> Option<QueryItem> {
+    if shortcuts.is_empty() {
+        return None;
+    }
+
+    let existing: HashSet<String> = meta
+        .local_branches()
+        .iter()
+        .map(|branch| branch.name.clone())
+        .collect();
+    let default = default_base(meta, fresh_base);
+    let (shortcuts, naming) = (shortcuts.to_vec(), naming.clone());
+    Some(Rc::new(move |query: &str| {
+        let (shortcut, name) = shortcuts
+            .iter()
+            .find_map(|shortcut| Some((shortcut, shortcut.name(query)?)))?;
+        let invalid = if existing.contains(&name) {
+            Some("it exists already".to_string())
+        } else {
+            naming::validate(&naming, &name)
+                .err()
+                .map(|err| format!("{:#}", err))
+        };
+        let (base, fresh) = match &shortcut.base {
+            Some(base) => (base.clone(), false),
+            None => default.clone(),
+        };
+        Some(Arc::new(NewBranch {
+            name,
+            base,
+            fresh,
+            invalid,
+        }) as Arc<dyn SkimItem>)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcuts(config: &[(&str, &str)]) -> Result<Vec<Shortcut>> {
+        Shortcut::all(
+            &config
+                .iter()
+                .map(|(key, template)| {
+                    (
+                        key.to_string(),
+                        ShortcutConfig {
+                            template: template.to_string(),
+                            base: None,
+                        },
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_name() {
+        let all =
+            shortcuts(&[("f", "feature/{words}"), ("b", "bug/{first}/{rest:upper}")]).unwrap();
+        let name = |query: &str| all.iter().find_map(|shortcut| shortcut.name(query));
+
+        assert_eq!(
+            name("f ticket-123 fix parser").as_deref(),
+            Some("feature/ticket-123-fix-parser")
+        );
+        assert_eq!(name("  f   one  ").as_deref(), Some("feature/one"));
+        assert_eq!(
+            name("b 42 crash on start").as_deref(),
+            Some("bug/42/CRASH-ON-START")
+        );
+        assert_eq!(name("f fix: a~b?").as_deref(), Some("feature/fix-a-b"));
+        // the shortcut alone, another word or words without a shortcut
+        assert_eq!(name("f"), None);
+        assert_eq!(name("f ::"), None);
+        assert_eq!(name("fix parser"), None);
+        assert_eq!(name("parser f x"), None);
+
+        assert!(shortcuts(&[("f f", "feature/{words}")]).is_err());
+        assert!(shortcuts(&[("f", "feature/{name}")]).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let branch = NewBranch {
+            name: "feature/x".to_string(),
+            base: "main".to_string(),
+            fresh: false,
+            invalid: None,
+        };
+        assert_eq!(branch.text(), "feature/x");
+        assert!(branch.is_selectable());
+        let invalid = NewBranch {
+            invalid: Some("it exists already".to_string()),
+            ..branch
+        };
+        assert!(!invalid.is_selectable());
+    }
+}
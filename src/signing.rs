@@ -0,0 +1,283 @@
+//! Whether the tip of each branch is signed, with GPG or SSH, by a key that checks out
+//! (`--signatures`), and listing only those that are (`--verified-only`).
+//!
+//! Git checks the signatures, as it knows the keys and the trusted signers of `gpg.ssh`. Like the
+//! sizes, they never hold up the picker: it shows the cached ones, and the missing ones are
+//! checked in the background, after which the picker reloads its branches. `--verified-only`
+//! can't list a branch before knowing, so it waits for them. A state is cached by the commit it
+//! is about, except when the key is unknown, which is checked again the next time in case the key
+//! was imported meanwhile.
+use crate::alternates;
+use crate::git;
+use crate::meta::RepoMeta;
+use crate::{diffstat, Branch, LocalBranch, RefBranch, RemoteBranch};
+use anyhow::{bail, Context, Result};
+use crossbeam::channel::Sender;
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    thread,
+};
+
+const CACHE_FILE_NAME: &str = "signatures.toml";
+
+/// What git says of the signature of a commit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Signing {
+    /// Signed with a key that is trusted, or an allowed signer with SSH
+    Good,
+    /// The signature doesn't match, or the key expired or was revoked
+    Bad,
+    Unsigned,
+    /// Signed with a key that is missing, or that isn't trusted
+    UnknownKey,
+}
+
+impl Signing {
+    /// The state of `%G?` of `git log`
+    fn from_code(code: &str) -> Option<Signing> {
+        match code {
+            "G" => Some(Signing::Good),
+            "B" | "X" | "Y" | "R" => Some(Signing::Bad),
+            "N" => Some(Signing::Unsigned),
+            "U" | "E" => Some(Signing::UnknownKey),
+            _ => None,
+        }
+    }
+
+    /// e.g. `✓ signed`
+    pub fn label(self) -> &'static str {
+        match self {
+            Signing::Good => "✓ signed",
+            Signing::Bad => "✗ bad signature",
+            Signing::Unsigned => "unsigned",
+            Signing::UnknownKey => "? unknown key",
+        }
+    }
+
+    /// e.g. `signed with an unknown key`, for `--accessible`
+    pub fn describe(self) -> &'static str {
+        match self {
+            Signing::Good => "signed",
+            Signing::Bad => "bad signature",
+            Signing::Unsigned => "unsigned",
+            Signing::UnknownKey => "signed with an unknown key",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SigningCache {
+    /// By commit
+    states: HashMap<String, Signing>,
+}
+
+fn cache_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("gibra").join(CACHE_FILE_NAME)
+}
+
+fn load_cache(git_dir: &Path) -> SigningCache {
+    fs::read_to_string(cache_path(git_dir))
+        .ok()
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(git_dir: &Path, cache: &SigningCache) -> Result<()> {
+    let path = cache_path(git_dir);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(cache).with_context(|| "Failed to serialize signatures")?;
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+impl SigningCache {
+    fn get(&self, tip: Oid) -> Option<Signing> {
+        self.states.get(&tip.to_string()).copied()
+    }
+
+    /// Whether `tip` needs checking, as it isn't cached or its key was unknown
+    fn is_missing(&self, tip: Oid) -> bool {
+        matches!(self.get(tip), None | Some(Signing::UnknownKey))
+    }
+}
+
+/// Parse the lines of `git log --format='%H %G?'`
+fn parse(output: &str) -> HashMap<Oid, Signing> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (oid, code) = line.split_once(' ')?;
+            Some((Oid::from_str(oid).ok()?, Signing::from_code(code.trim())?))
+        })
+        .collect()
+}
+
+/// Have git check the signatures of `tips`, in the repository at `git_dir`, with one process
+fn check(git_dir: &Path, tips: &[Oid]) -> Result<HashMap<Oid, Signing>> {
+    if tips.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let mut child = git::command(git_dir)
+        .args(["log", "--no-walk=unsorted", "--stdin", "--format=%H %G?"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| "Failed to execute git log")?;
+    let input: String = tips.iter().map(|tip| format!("{}\n", tip)).collect();
+    // dropped once written, so that git sees the end of the list
+    child
+        .stdin
+        .take()
+        .with_context(|| "Failed to open the standard input of git")?
+        .write_all(input.as_bytes())
+        .with_context(|| "Failed to write to git")?;
+    let output = child
+        .wait_with_output()
+        .with_context(|| "Failed to wait for git log")?;
+    if !output.status.success() {
+        bail!(
+            "git log failed to check the signatures: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Check the signatures of `tips` that need it, keeping those of the others, and cache the
+/// states of `tips` alone, so the cache doesn't grow
+fn check_cached(git_dir: &Path, tips: &[Oid]) -> Result<HashMap<Oid, Signing>> {
+    let cache = load_cache(git_dir);
+    let missing: Vec<Oid> = tips
+        .iter()
+        .copied()
+        .filter(|&tip| cache.is_missing(tip))
+        .collect();
+    let mut states = check(git_dir, &missing)?;
+    for &tip in tips {
+        if let Some(state) = cache.get(tip).filter(|_| !states.contains_key(&tip)) {
+            states.insert(tip, state);
+        }
+    }
+    let cache = SigningCache {
+        states: states
+            .iter()
+            .map(|(tip, state)| (tip.to_string(), *state))
+            .collect(),
+    };
+    save_cache(git_dir, &cache)?;
+    Ok(states)
+}
+
+fn set(branch: &mut Branch, state: Option<Signing>) {
+    match branch {
+        Branch::Local(LocalBranch { signing, .. })
+        | Branch::Remote(RemoteBranch { signing, .. })
+        | Branch::Ref(RefBranch { signing, .. }) => *signing = state,
+    }
+}
+
+fn tips(repo: &Repository, branches: &[Branch]) -> Vec<Oid> {
+    branches
+        .iter()
+        .filter_map(|branch| diffstat::tip(repo, branch))
+        .collect()
+}
+
+/// Attach the cached states to `branches`. Nothing is checked.
+pub fn attach_cached(meta: &RepoMeta, branches: &mut [Branch]) {
+    let repo = meta.repo();
+    let cache = load_cache(repo.path());
+    for branch in branches {
+        let state = diffstat::tip(repo, branch).and_then(|tip| cache.get(tip));
+        set(branch, state);
+    }
+}
+
+/// Check the signatures of `branches` that aren't cached yet in the background, sending on
+/// `done` once they are cached. Returns whether anything is checked.
+pub fn check_in_background(meta: &RepoMeta, branches: &[Branch], done: Sender<()>) -> bool {
+    let repo = meta.repo();
+    let tips = tips(repo, branches);
+    let cache = load_cache(repo.path());
+    if !tips.iter().any(|&tip| cache.is_missing(tip)) {
+        return false;
+    }
+
+    let git_dir = repo.path().to_path_buf();
+    thread::spawn(move || {
+        // opened to make sure the repository is still there, as the picker may be gone
+        let checked = alternates::open(&git_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| check_cached(&git_dir, &tips));
+        match checked {
+            Ok(_) => {
+                let _ = done.send(());
+            }
+            Err(err) => debug!("{:#}", err),
+        }
+    });
+
+    true
+}
+
+/// Drop the `branches` whose tip isn't signed by a key that checks out, after checking those
+/// that aren't cached, and attach the state to the others
+pub fn retain_verified(meta: &RepoMeta, branches: &mut Vec<Branch>) -> Result<()> {
+    let repo = meta.repo();
+    let states = check_cached(repo.path(), &tips(repo, branches))?;
+    branches.retain_mut(|branch| {
+        let state = diffstat::tip(repo, branch).and_then(|tip| states.get(&tip).copied());
+        set(branch, state);
+        state == Some(Signing::Good)
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_parse() {
+        let good = "1111111111111111111111111111111111111111";
+        let unknown = "2222222222222222222222222222222222222222";
+        let output = format!("{} G\n{} E\nnot a line\n", good, unknown);
+        let states = parse(&output);
+        assert_eq!(states.len(), 2);
+        assert_eq!(
+            states.get(&Oid::from_str(good).unwrap()),
+            Some(&Signing::Good)
+        );
+        assert_eq!(
+            states.get(&Oid::from_str(unknown).unwrap()),
+            Some(&Signing::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(Signing::from_code("Y"), Some(Signing::Bad));
+        assert_eq!(Signing::from_code("?"), None);
+    }
+
+    #[test]
+    fn test_check_cached() {
+        let repo = TempRepo::new("signing");
+        let tip = testing::commit(&repo, Some("HEAD"), "one", &[], &[]);
+
+        let states = check_cached(repo.path(), &[tip]).unwrap();
+        assert_eq!(states.get(&tip), Some(&Signing::Unsigned));
+        assert_eq!(load_cache(repo.path()).get(tip), Some(Signing::Unsigned));
+        assert!(!load_cache(repo.path()).is_missing(tip));
+    }
+}
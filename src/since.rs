@@ -0,0 +1,201 @@
+//! Listing only the branches committed to within a window of time (`--active-since 2w`), or not
+//! (`--inactive-since 3mo`), e.g. to see what is being worked on, or what was left behind.
+use crate::{last_commit_time, Branch};
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+use git2::Repository;
+
+/// A point in time given on the command line, as a date or as how long ago it was
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Since {
+    /// As given, e.g. `2w`
+    text: String,
+    /// In seconds since the epoch
+    time: i64,
+}
+
+/// How long `unit` is, e.g. `w`, `week` or `weeks`. Months are 30 days and years 365 days.
+fn unit(unit: &str) -> Option<Duration> {
+    let unit = unit
+        .strip_suffix('s')
+        .filter(|unit| unit.len() > 1)
+        .unwrap_or(unit);
+    match unit {
+        "m" | "min" | "minute" => Some(Duration::minutes(1)),
+        "h" | "hour" => Some(Duration::hours(1)),
+        "d" | "day" => Some(Duration::days(1)),
+        "w" | "week" => Some(Duration::weeks(1)),
+        "mo" | "month" => Some(Duration::days(30)),
+        "y" | "year" => Some(Duration::days(365)),
+        _ => None,
+    }
+}
+
+/// The time `text` means at `now`: a date like `2024-05-01` (its midnight), `today`,
+/// `yesterday`, or a duration like `2w`, `3 days` or `6 months ago`
+fn parse_at(text: &str, now: DateTime<Local>) -> Option<i64> {
+    let text = text.trim().to_lowercase();
+    let midnight = |date: NaiveDate| {
+        let time = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        Some(time.timestamp())
+    };
+    match text.as_str() {
+        "today" => return midnight(now.date_naive()),
+        "yesterday" => return midnight(now.date_naive().pred_opt()?),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+        return midnight(date);
+    }
+
+    let duration = text.strip_suffix("ago").unwrap_or(&text).trim_end();
+    let digits = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (count, rest) = duration.split_at(digits);
+    let count: i32 = count.parse().ok()?;
+    let duration = unit(rest.trim_start())? * count;
+    Some((now - duration).timestamp())
+}
+
+impl Since {
+    /// In seconds since the epoch
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+}
+
+/// Parse `--active-since` and `--inactive-since`
+pub fn parse(text: &str) -> Result<Since, String> {
+    match parse_at(text, Local::now()) {
+        Some(time) => Ok(Since {
+            text: text.to_string(),
+            time,
+        }),
+        None => Err(
+            "expected a date like 2024-05-01, today, yesterday, or a duration like 2w, 3 days or \
+             6mo"
+            .to_string(),
+        ),
+    }
+}
+
+/// Drop the `branches` last committed to before `active`, or since `inactive`, the current one
+/// included
+pub fn apply(
+    repo: &Repository,
+    active: Option<&Since>,
+    inactive: Option<&Since>,
+    branches: &mut Vec<Branch>,
+) {
+    branches.retain(|branch| {
+        let time = last_commit_time(repo, branch);
+        active.is_none_or(|since| time >= since.time)
+            && inactive.is_none_or(|since| time < since.time)
+    });
+}
+
+/// How the info line of the picker tells the window, e.g. `active since 2w`
+pub fn describe(active: Option<&Since>, inactive: Option<&Since>) -> Vec<String> {
+    [("active since", active), ("inactive since", inactive)]
+        .into_iter()
+        .filter_map(|(what, since)| Some(format!("{} {}", what, since?.text)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use crate::LocalBranch;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    fn now() -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 5, 15, 12, 0, 0).unwrap()
+    }
+
+    fn since(days_ago: i64) -> Since {
+        Since {
+            text: format!("{}d", days_ago),
+            time: now().timestamp() - days_ago * DAY,
+        }
+    }
+
+    #[test]
+    fn test_parse_durations() {
+        let ago = |duration: Duration| Some((now() - duration).timestamp());
+        assert_eq!(parse_at("2w", now()), ago(Duration::weeks(2)));
+        assert_eq!(parse_at("3 days", now()), ago(Duration::days(3)));
+        assert_eq!(parse_at("6 Months ago", now()), ago(Duration::days(180)));
+        assert_eq!(parse_at("1h", now()), ago(Duration::hours(1)));
+        assert_eq!(parse_at("90min", now()), ago(Duration::minutes(90)));
+    }
+
+    #[test]
+    fn test_parse_dates() {
+        let midnight = |day: u32| {
+            Local
+                .with_ymd_and_hms(2024, 5, day, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        };
+        assert_eq!(parse_at("yesterday", now()), Some(midnight(14)));
+        assert_eq!(parse_at("2024-05-01", now()), Some(midnight(1)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for text in ["", "2", "w", "2 fortnights", "-2w", "2024-13-01"] {
+            assert_eq!(parse_at(text, now()), None, "{}", text);
+        }
+    }
+
+    /// The branches last committed to 1, 10 and 100 days ago
+    fn aged(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        for (name, days_ago) in [("fresh", 1), ("recent", 10), ("stale", 100)] {
+            let time = now().timestamp() - days_ago * DAY;
+            let refname = format!("refs/heads/{}", name);
+            testing::commit_at(&repo, Some(&refname), name, time, &[]);
+        }
+        repo
+    }
+
+    fn listed(repo: &Repository, active: Option<&Since>, inactive: Option<&Since>) -> Vec<String> {
+        let mut branches: Vec<Branch> = ["fresh", "recent", "stale"]
+            .iter()
+            .map(|name| {
+                Branch::Local(LocalBranch {
+                    name: name.to_string(),
+                    ..LocalBranch::default()
+                })
+            })
+            .collect();
+        apply(repo, active, inactive, &mut branches);
+        branches.into_iter().map(Branch::name).collect()
+    }
+
+    #[test]
+    fn test_apply() {
+        let repo = aged("since");
+        assert_eq!(listed(&repo, Some(&since(14)), None), ["fresh", "recent"]);
+        assert_eq!(listed(&repo, None, Some(&since(14))), ["stale"]);
+        assert_eq!(listed(&repo, None, None), ["fresh", "recent", "stale"]);
+    }
+
+    #[test]
+    fn test_apply_both() {
+        let repo = aged("since-both");
+        assert_eq!(listed(&repo, Some(&since(30)), Some(&since(7))), ["recent"]);
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe(Some(&since(14)), Some(&since(7))),
+            ["active since 14d", "inactive since 7d"]
+        );
+    }
+}
@@ -292,6 +292,18 @@ impl<'a> AnsiString<'a> {
         &self.stripped
     }
 
+    /// Append `text`, drawn with `attr`
+    pub fn append(&mut self, text: &str, attr: Attr) {
+        let start = self.stripped.chars().count() as u32;
+        let end = start + text.chars().count() as u32;
+        let mut stripped = std::mem::take(&mut self.stripped).into_owned();
+        stripped.push_str(text);
+        self.stripped = Cow::owned(stripped);
+        self.fragments
+            .get_or_insert_with(Vec::new)
+            .push((attr, (start, end)));
+    }
+
     pub fn override_attrs(&mut self, attrs: Vec<(Attr, (u32, u32))>) {
         if attrs.is_empty() {
             // pass
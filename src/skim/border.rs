@@ -0,0 +1,69 @@
+//! The border of the preview
+use tuikit::prelude::*;
+
+/// How the preview is set apart from the items. Without one, a line is drawn between them.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum BorderStyle {
+    /// A box with round corners
+    Rounded,
+    /// A box with square corners
+    Sharp,
+    /// Only a blank column or row
+    None,
+}
+
+/// A `Win` whose box gets round corners, as it only draws square ones
+pub struct Corners<W> {
+    pub win: W,
+    /// How the round corners are drawn, `None` to keep the ones of `win`
+    pub round: Option<Attr>,
+}
+
+impl<W: Draw> Draw for Corners<W> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        self.win.draw(canvas)?;
+        let (width, height) = canvas.size()?;
+        let attr = match self.round {
+            Some(attr) if width >= 2 && height >= 2 => attr,
+            _ => return Ok(()),
+        };
+        let (bottom, right) = (height - 1, width - 1);
+        for (row, col, ch) in [
+            (0, 0, '╭'),
+            (0, right, '╮'),
+            (bottom, 0, '╰'),
+            (bottom, right, '╯'),
+        ] {
+            canvas.put_cell(row, col, Cell::default().ch(ch).attribute(attr))?;
+        }
+        Ok(())
+    }
+}
+
+impl<Message, W: Widget<Message>> Widget<Message> for Corners<W> {
+    fn size_hint(&self) -> (Option<usize>, Option<usize>) {
+        self.win.size_hint()
+    }
+
+    fn on_event(&self, event: Event, rect: Rectangle) -> Vec<Message> {
+        self.win.on_event(event, rect)
+    }
+
+    fn on_event_mut(&mut self, event: Event, rect: Rectangle) -> Vec<Message> {
+        self.win.on_event_mut(event, rect)
+    }
+}
+
+impl<Message, W: Split<Message>> Split<Message> for Corners<W> {
+    fn get_basis(&self) -> Size {
+        self.win.get_basis()
+    }
+
+    fn get_grow(&self) -> usize {
+        self.win.get_grow()
+    }
+
+    fn get_shrink(&self) -> usize {
+        self.win.get_shrink()
+    }
+}
@@ -0,0 +1,137 @@
+//! Chains of actions that go on once the actions they emit are done, e.g.
+//! `ctrl-d:emit(delete)+down` moving to the next item only once the item under the cursor was
+//! deleted. The steps after an `emit(<name>)` wait for it to succeed, and are cancelled when it
+//! fails, except for the `if-failed(<action>)` ones, which only run then.
+//!
+//! The chains started while one waits run after it, in the order of their keys.
+use crate::skim::event::Event;
+use crate::skim::input::parse_action_arg;
+use std::collections::VecDeque;
+use tuikit::key::Key;
+
+/// Whether `chain` has steps after an `emit(<name>)`, which have to wait for it
+pub fn waits(chain: &[Event]) -> bool {
+    chain
+        .iter()
+        .position(|event| matches!(event, Event::EvActEmit(_)))
+        .is_some_and(|emit| emit + 1 < chain.len())
+}
+
+#[derive(Default)]
+pub struct Chains {
+    /// The chains left to run with the key that started them, the first one running
+    queue: VecDeque<(Key, VecDeque<Event>)>,
+    /// The emitted action the first chain waits for, by its number
+    waiting_for: Option<usize>,
+    /// How many actions were emitted, and how many of them are done
+    emitted: usize,
+    done: usize,
+}
+
+impl Chains {
+    pub fn push(&mut self, key: Key, chain: Vec<Event>) {
+        self.queue.push_back((key, chain.into()));
+    }
+
+    /// The next step to run, `None` while the running chain waits or when there is none left
+    pub fn next(&mut self) -> Option<(Key, Event)> {
+        if self.waiting_for.is_some() {
+            return None;
+        }
+        loop {
+            let (key, steps) = self.queue.front_mut()?;
+            let key = *key;
+            match steps.pop_front() {
+                // only for when the action before failed
+                Some(Event::EvActIfFailed(_)) => continue,
+                Some(event) => {
+                    if let Event::EvActEmit(_) = event {
+                        self.waiting_for = Some(self.emitted + 1);
+                    }
+                    return Some((key, event));
+                }
+                None => {
+                    self.queue.pop_front();
+                }
+            }
+        }
+    }
+
+    /// An action was emitted, by a chain or not
+    pub fn emitted(&mut self) {
+        self.emitted += 1;
+    }
+
+    /// The action emitted first of those not done yet is done, and `succeeded` or not
+    pub fn done(&mut self, succeeded: bool) {
+        self.done += 1;
+        if self.waiting_for != Some(self.done) {
+            return;
+        }
+        self.waiting_for = None;
+        if succeeded {
+            return;
+        }
+        if let Some((_, steps)) = self.queue.front_mut() {
+            *steps = steps
+                .drain(..)
+                .filter_map(|event| match event {
+                    Event::EvActIfFailed(action) => parse_action_arg(&action),
+                    _ => None,
+                })
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn steps(chains: &mut Chains) -> Vec<Event> {
+        std::iter::from_fn(|| chains.next())
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    #[test]
+    fn test_waits() {
+        let emit = Event::EvActEmit("delete".to_string());
+        assert!(waits(&[emit.clone(), Event::EvActDown(1)]));
+        assert!(!waits(&[Event::EvActDown(1), emit.clone()]));
+        assert!(!waits(&[Event::EvActToggle, Event::EvActDown(1)]));
+    }
+
+    #[test]
+    fn test_chains() {
+        let delete = Event::EvActEmit("delete".to_string());
+        let chain = vec![
+            delete.clone(),
+            Event::EvActDown(1),
+            Event::EvActIfFailed("up".to_string()),
+        ];
+        let mut chains = Chains::default();
+
+        // an action emitted before the chain is done first
+        chains.emitted();
+        chains.push(Key::Ctrl('d'), chain.clone());
+        assert_eq!(chains.next(), Some((Key::Ctrl('d'), delete.clone())));
+        chains.emitted();
+        assert_eq!(chains.next(), None);
+        chains.done(true);
+        assert_eq!(chains.next(), None);
+        chains.done(true);
+        assert_eq!(steps(&mut chains), [Event::EvActDown(1)]);
+
+        // the second chain waits for the first one
+        chains.push(Key::Ctrl('d'), chain.clone());
+        chains.push(Key::Ctrl('d'), chain);
+        assert_eq!(steps(&mut chains).first(), Some(&delete));
+        chains.emitted();
+        chains.done(false);
+        assert_eq!(steps(&mut chains), [Event::EvActUp(1), delete]);
+        chains.emitted();
+        chains.done(true);
+        assert_eq!(steps(&mut chains), [Event::EvActDown(1)]);
+    }
+}
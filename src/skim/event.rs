@@ -11,7 +11,19 @@ pub type EventSender = Sender<(Key, Event)>;
 pub enum Event {
     EvInputKey(Key),
     EvInputInvalid,
+    /// A key typed while the preview pane has the keyboard focus
+    EvPreviewSearchKey(Key),
+    /// A key typed while the menu is open
+    EvMenuKey(Key),
     EvHeartBeat,
+    /// What an action running in the background of the picker did, see `SkimOptions::status`
+    EvStatus(String),
+    /// Whether the action emitted first of those not done yet succeeded, see `SkimOptions::done`
+    EvEmitDone(bool),
+    /// The process was continued after it was stopped, see [`crate::skim::suspend`]
+    EvResumed,
+    /// The actions of a key that wait for the ones they emit, see [`crate::skim::chain`]
+    EvActChain(Vec<Event>),
 
     // user bind actions
     EvActAbort,
@@ -29,16 +41,21 @@ pub enum Event {
     EvActDeleteCharEOF,
     EvActDeselectAll,
     EvActDown(i32),
+    EvActEmit(String),
     EvActEndOfLine,
     EvActExecute(String),
     EvActExecuteSilent(String),
+    EvActFirst,
     EvActForwardChar,
     EvActForwardWord,
+    EvActIfFailed(String),
     EvActIfQueryEmpty(String),
     EvActIfQueryNotEmpty(String),
     EvActIfNonMatched(String),
     EvActIgnore,
     EvActKillLine,
+    EvActLast,
+    EvActMenu,
     EvActKillWord,
     EvActNextHistory,
     EvActHalfPageDown(i32),
@@ -51,13 +68,16 @@ pub enum Event {
     EvActPreviewRight(i32),
     EvActPreviewPageUp(i32),
     EvActPreviewPageDown(i32),
+    EvActPreviewSearch,
     EvActPreviousHistory,
     EvActRedraw,
     EvActRefreshCmd,
     EvActRefreshPreview,
+    EvActReload,
     EvActRotateMode,
     EvActScrollLeft(i32),
     EvActScrollRight(i32),
+    EvActSuspend,
     EvActSelectAll,
     EvActSelectRow(usize),
     EvActToggle,
@@ -107,17 +127,22 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "delete-charEOF"       =>   Some(Event::EvActDeleteCharEOF),
         "deselect-all"         =>   Some(Event::EvActDeselectAll),
         "down"                 =>   Some(Event::EvActDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
+        "emit"                 =>   Some(Event::EvActEmit(arg.expect("emit event should have argument"))),
         "end-of-line"          =>   Some(Event::EvActEndOfLine),
         "execute"              =>   Some(Event::EvActExecute(arg.expect("execute event should have argument"))),
         "execute-silent"       =>   Some(Event::EvActExecuteSilent(arg.expect("execute-silent event should have argument"))),
+        "first"                =>   Some(Event::EvActFirst),
         "forward-char"         =>   Some(Event::EvActForwardChar),
         "forward-word"         =>   Some(Event::EvActForwardWord),
+        "if-failed"            =>   Some(Event::EvActIfFailed(arg.expect("no arg specified for event if-failed"))),
         "if-non-matched"       =>   Some(Event::EvActIfNonMatched(arg.expect("no arg specified for event if-non-matched"))),
         "if-query-empty"       =>   Some(Event::EvActIfQueryEmpty(arg.expect("no arg specified for event if-query-empty"))),
         "if-query-not-empty"   =>   Some(Event::EvActIfQueryNotEmpty(arg.expect("no arg specified for event if-query-not-empty"))),
         "ignore"               =>   Some(Event::EvActIgnore),
         "kill-line"            =>   Some(Event::EvActKillLine),
         "kill-word"            =>   Some(Event::EvActKillWord),
+        "last"                 =>   Some(Event::EvActLast),
+        "menu"                 =>   Some(Event::EvActMenu),
         "next-history"         =>   Some(Event::EvActNextHistory),
         "half-page-down"       =>   Some(Event::EvActHalfPageDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "half-page-up"         =>   Some(Event::EvActHalfPageUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
@@ -129,12 +154,14 @@ pub fn parse_event(action: &str, arg: Option<String>) -> Option<Event> {
         "preview-right"        =>   Some(Event::EvActPreviewRight(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "preview-page-up"      =>   Some(Event::EvActPreviewPageUp(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "preview-page-down"    =>   Some(Event::EvActPreviewPageDown(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
+        "preview-search"       =>   Some(Event::EvActPreviewSearch),
         "previous-history"     =>   Some(Event::EvActPreviousHistory),
         "refresh-cmd"          =>   Some(Event::EvActRefreshCmd),
         "refresh-preview"      =>   Some(Event::EvActRefreshPreview),
         "scroll-left"          =>   Some(Event::EvActScrollLeft(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "scroll-right"         =>   Some(Event::EvActScrollRight(arg.and_then(|s|s.parse().ok()).unwrap_or(1))),
         "select-all"           =>   Some(Event::EvActSelectAll),
+        "suspend"              =>   Some(Event::EvActSuspend),
         "toggle"               =>   Some(Event::EvActToggle),
         "toggle-all"           =>   Some(Event::EvActToggleAll),
         "toggle-in"            =>   Some(Event::EvActToggleIn),
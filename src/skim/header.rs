@@ -6,7 +6,7 @@ use crate::skim::item::ItemPool;
 use crate::skim::theme::ColorTheme;
 use crate::skim::theme::DEFAULT_THEME;
 use crate::skim::util::{clear_canvas, print_item, str_lines, LinePrinter};
-use crate::skim::{DisplayContext, Matches, SkimOptions};
+use crate::skim::{DisplayContext, Matches, SkimOptions, Truncate};
 use defer_drop::DeferDrop;
 use std::cmp::max;
 use std::sync::Arc;
@@ -16,6 +16,7 @@ pub struct Header {
     header: Vec<AnsiString<'static>>,
     tabstop: usize,
     reverse: bool,
+    accessible: bool,
     theme: Arc<ColorTheme>,
 
     // for reserved header items
@@ -28,6 +29,7 @@ impl Header {
             header: vec![],
             tabstop: 8,
             reverse: false,
+            accessible: false,
             theme: Arc::new(*DEFAULT_THEME),
             item_pool: Arc::new(DeferDrop::new(ItemPool::new())),
         }
@@ -53,6 +55,8 @@ impl Header {
             self.reverse = true;
         }
 
+        self.accessible = options.accessible;
+
         match options.header {
             None => {}
             Some("") => {}
@@ -129,6 +133,8 @@ impl Draw for Header {
                 matches: Matches::None,
                 container_width: screen_width - 2,
                 highlight_attr: self.theme.header(),
+                accessible: self.accessible,
+                truncate: Truncate::default(),
             };
 
             print_item(
@@ -11,6 +11,14 @@ pub struct DefaultSkimSelector {
     preset: Option<HashSet<String>>,
 }
 
+impl DefaultSkimSelector {
+    /// Pre-select the items whose text is one of `preset`
+    pub fn preset(mut self, preset: impl IntoIterator<Item = String>) -> Self {
+        self.preset.get_or_insert_with(HashSet::new).extend(preset);
+        self
+    }
+}
+
 impl Selector for DefaultSkimSelector {
     fn should_select(&self, index: usize, item: &dyn SkimItem) -> bool {
         if self.first_n > index {
@@ -21,7 +29,7 @@ impl Selector for DefaultSkimSelector {
             && self
                 .preset
                 .as_ref()
-                .map(|preset| preset.contains(item.text().as_ref()))
+                .map(|preset| preset.contains(item.output().as_ref()))
                 .unwrap_or(false)
         {
             return true;
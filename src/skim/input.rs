@@ -10,16 +10,84 @@ pub type ActionChain = Vec<Event>;
 
 pub struct Input {
     keymap: HashMap<Key, ActionChain>,
+    vi: Option<ViState>,
+}
+
+/// The state of the vi mode: `esc` switches from typing the query to moving through the list
+#[derive(Default)]
+struct ViState {
+    normal: bool,
+    /// `g` was typed, waiting for the second `g`
+    pending_g: bool,
+    /// The list is drawn top-down, so its top is the first item
+    reverse: bool,
+}
+
+impl ViState {
+    /// The actions for `key` in normal mode, `None` for keys that go to the keymap
+    fn translate(&mut self, key: Key) -> Option<ActionChain> {
+        let pending_g = std::mem::take(&mut self.pending_g);
+        let chain = match key {
+            Key::Char('j') => vec![Event::EvActDown(1)],
+            Key::Char('k') => vec![Event::EvActUp(1)],
+            Key::Char('g') if !pending_g => {
+                self.pending_g = true;
+                vec![]
+            }
+            Key::Char('g') => vec![if self.reverse {
+                Event::EvActFirst
+            } else {
+                Event::EvActLast
+            }],
+            Key::Char('G') => vec![if self.reverse {
+                Event::EvActLast
+            } else {
+                Event::EvActFirst
+            }],
+            Key::Char('/') | Key::Char('i') | Key::Char('a') => {
+                self.normal = false;
+                vec![]
+            }
+            Key::Char('q') | Key::ESC => vec![Event::EvActAbort],
+            // other letters would edit the query, which normal mode doesn't
+            Key::Char(_) => vec![],
+            _ => return None,
+        };
+
+        Some(chain)
+    }
 }
 
 impl Input {
     pub fn new() -> Self {
         Input {
             keymap: get_default_key_map(),
+            vi: None,
         }
     }
 
-    pub fn translate_event(&self, event: TermEvent) -> (Key, ActionChain) {
+    /// Make `esc` switch to a normal mode where `j`/`k` move the cursor, `gg`/`G` jump to the top
+    /// and bottom of the list and `/` goes back to the query, like in vi. `reverse` is whether the
+    /// list is drawn top-down.
+    pub fn enable_vi_mode(&mut self, reverse: bool) {
+        self.vi = Some(ViState {
+            reverse,
+            ..ViState::default()
+        });
+    }
+
+    pub fn translate_event(&mut self, event: TermEvent) -> (Key, ActionChain) {
+        if let (TermEvent::Key(key), Some(vi)) = (&event, &mut self.vi) {
+            if vi.normal {
+                if let Some(chain) = vi.translate(*key) {
+                    return (*key, chain);
+                }
+            } else if *key == Key::ESC {
+                vi.normal = true;
+                return (*key, vec![]);
+            }
+        }
+
         match event {
             // search event from keymap
             TermEvent::Key(key) => (
@@ -38,7 +106,7 @@ impl Input {
     }
 
     pub fn bind(&mut self, key: &str, action_chain: ActionChain) {
-        let key = from_keyname(key);
+        let key = from_keyname(key).or_else(|| alt_digit(key));
         if key == None || action_chain.is_empty() {
             return;
         }
@@ -78,6 +146,15 @@ impl Input {
     }
 }
 
+/// `alt-0` to `alt-9`, which tuikit has no names of
+fn alt_digit(key: &str) -> Option<Key> {
+    let digit = key.strip_prefix("alt-")?;
+    match digit.chars().next() {
+        Some(ch) if digit.len() == 1 && ch.is_ascii_digit() => Some(Key::Alt(ch)),
+        _ => None,
+    }
+}
+
 type KeyActions<'a> = (&'a str, Vec<(&'a str, Option<String>)>);
 
 /// parse key action string to `(key, action, argument)` tuple
@@ -141,6 +218,7 @@ fn get_default_key_map() -> HashMap<Key, ActionChain> {
     ret.insert(Key::ESC,          vec![Event::EvActAbort]);
     ret.insert(Key::Ctrl('c'),    vec![Event::EvActAbort]);
     ret.insert(Key::Ctrl('g'),    vec![Event::EvActAbort]);
+    ret.insert(Key::Ctrl('z'),    vec![Event::EvActSuspend]);
     ret.insert(Key::Enter,        vec![Event::EvActAccept(None)]);
     ret.insert(Key::Left,         vec![Event::EvActBackwardChar]);
     ret.insert(Key::Ctrl('b'),    vec![Event::EvActBackwardChar]);
@@ -260,4 +338,22 @@ mod test {
             key_action[1]
         );
     }
+
+    #[test]
+    fn test_vi_mode() {
+        let mut input = Input::new();
+        input.enable_vi_mode(false);
+        let mut press = |key| input.translate_event(TermEvent::Key(key)).1;
+
+        assert_eq!(press(Key::Char('j')), vec![Event::EvActAddChar('j')]);
+        assert!(press(Key::ESC).is_empty());
+        assert_eq!(press(Key::Char('j')), vec![Event::EvActDown(1)]);
+        assert!(press(Key::Char('x')).is_empty());
+        assert!(press(Key::Char('g')).is_empty());
+        assert_eq!(press(Key::Char('g')), vec![Event::EvActLast]);
+        assert_eq!(press(Key::Char('G')), vec![Event::EvActFirst]);
+        assert_eq!(press(Key::Enter), vec![Event::EvActAccept(None)]);
+        assert!(press(Key::Char('/')).is_empty());
+        assert_eq!(press(Key::Char('k')), vec![Event::EvActAddChar('k')]);
+    }
 }
@@ -0,0 +1,262 @@
+//! A small popup menu drawn over the list next to the cursor, listing the ways to accept the
+//! current item. It is opened with the `menu` action and takes the keys until it is closed.
+use std::cmp::min;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tuikit::canvas::BoundedCanvas;
+use tuikit::prelude::*;
+use unicode_width::UnicodeWidthStr;
+
+use crate::skim::event::Event;
+use crate::skim::selection::Selection;
+use crate::skim::theme::ColorTheme;
+
+/// An entry of the menu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MenuEntry {
+    pub label: String,
+    /// The key reported by the accept event, like an `expect` key, or `None` to accept like enter
+    pub key: Option<String>,
+    /// Whether `key` is a key that is typed, else it only tells the entry apart and isn't shown
+    pub bound: bool,
+}
+
+impl MenuEntry {
+    pub fn new(label: impl Into<String>, key: Option<String>) -> Self {
+        Self {
+            label: label.into(),
+            key,
+            bound: true,
+        }
+    }
+
+    /// An entry that no key runs besides the menu, reporting `id` in the accept event
+    pub fn unbound(label: impl Into<String>, id: String) -> Self {
+        Self {
+            label: label.into(),
+            key: Some(id),
+            bound: false,
+        }
+    }
+
+    /// The key shown after the label
+    fn hint(&self) -> &str {
+        match self.bound {
+            true => self.key.as_deref().unwrap_or("enter"),
+            false => "",
+        }
+    }
+}
+
+pub struct Menu {
+    entries: Vec<MenuEntry>,
+    /// The highlighted entry while the menu is open
+    cursor: Option<usize>,
+    // set while the menu is open and takes the keys
+    focus: Arc<AtomicBool>,
+    theme: Arc<ColorTheme>,
+}
+
+impl Menu {
+    pub fn new(entries: Vec<MenuEntry>, focus: Arc<AtomicBool>, theme: Arc<ColorTheme>) -> Self {
+        Self {
+            entries,
+            cursor: None,
+            focus,
+            theme,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    pub fn open(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        self.cursor = Some(0);
+        self.focus.store(true, Ordering::SeqCst);
+    }
+
+    fn close(&mut self) {
+        self.cursor = None;
+        self.focus.store(false, Ordering::SeqCst);
+    }
+
+    /// Handle a key typed while the menu is open, returning the accept event of the entry picked
+    /// with enter
+    pub fn act_key(&mut self, key: Key) -> Option<Event> {
+        let cursor = self.cursor?;
+        let last = self.entries.len() - 1;
+        match key {
+            Key::Down | Key::Ctrl('n') | Key::Ctrl('j') | Key::Tab => {
+                self.cursor = Some(if cursor == last { 0 } else { cursor + 1 });
+            }
+            Key::Up | Key::Ctrl('p') | Key::Ctrl('k') | Key::BackTab => {
+                self.cursor = Some(if cursor == 0 { last } else { cursor - 1 });
+            }
+            Key::Home | Key::PageUp => self.cursor = Some(0),
+            Key::End | Key::PageDown => self.cursor = Some(last),
+            Key::Enter | Key::Ctrl('m') => {
+                let key = self.entries[cursor].key.clone();
+                self.close();
+                return Some(Event::EvActAccept(key));
+            }
+            Key::ESC | Key::Ctrl('g') | Key::Ctrl('c') | Key::Ctrl('q') => self.close(),
+            _ => {}
+        }
+        None
+    }
+
+    fn label_width(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.label.width())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The size of the menu with its border: the labels and the keys, a space around each
+    fn size(&self) -> (usize, usize) {
+        let hint_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.hint().width())
+            .max()
+            .unwrap_or(0);
+        (self.label_width() + hint_width + 6, self.entries.len() + 2)
+    }
+}
+
+/// Where the menu of `(width, height)` goes on a screen of `(screen_width, screen_height)`, as
+/// `(top, left)`: below the cursor `row` when it fits there, else above it, else as low as it
+/// fits. It is indented like the items, past the cursor label of `indent` columns.
+fn place(
+    (width, height): (usize, usize),
+    (screen_width, screen_height): (usize, usize),
+    row: usize,
+    indent: usize,
+) -> (usize, usize) {
+    let top = if row + 1 + height <= screen_height {
+        row + 1
+    } else if row >= height {
+        row - height
+    } else {
+        screen_height.saturating_sub(height)
+    };
+    let left = min(indent, screen_width.saturating_sub(width));
+    (top, left)
+}
+
+/// The entries of an open menu, inside its border
+struct Entries<'a> {
+    menu: &'a Menu,
+}
+
+impl<'a> Draw for Entries<'a> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        let (width, _) = canvas.size()?;
+        canvas.clear()?;
+        let label_width = self.menu.label_width();
+        for (row, entry) in self.menu.entries.iter().enumerate() {
+            let (attr, hint_attr) = if self.menu.cursor == Some(row) {
+                (self.menu.theme.current(), self.menu.theme.current())
+            } else {
+                (self.menu.theme.normal(), self.menu.theme.info())
+            };
+            for col in 0..width {
+                canvas.put_char_with_attr(row, col, ' ', attr)?;
+            }
+            canvas.print_with_attr(row, 1, &entry.label, attr)?;
+            canvas.print_with_attr(row, label_width + 3, entry.hint(), hint_attr)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Widget<Event> for Entries<'a> {}
+
+/// The open menu drawn over the list, next to the cursor line of `selection`. Draws nothing while
+/// the menu is closed.
+pub struct MenuOverlay<'a> {
+    pub menu: &'a Menu,
+    pub selection: &'a Selection,
+}
+
+impl<'a> Draw for MenuOverlay<'a> {
+    fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
+        if !self.menu.is_open() {
+            return Ok(());
+        }
+        let screen = canvas.size()?;
+        let row = match self.selection.cursor_row(screen.1) {
+            Some(row) => row,
+            None => return Ok(()),
+        };
+
+        let (width, height) = self.menu.size();
+        let (top, left) = place((width, height), screen, row, self.selection.indent());
+        let (width, height) = (min(width, screen.0), min(height, screen.1));
+        let mut bounded = BoundedCanvas::new(top, left, width, height, canvas);
+        Win::new(Entries { menu: self.menu })
+            .border(true)
+            .border_attr(self.menu.theme.border())
+            .draw(&mut bounded)
+    }
+}
+
+impl<'a> Widget<Event> for MenuOverlay<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn menu() -> Menu {
+        Menu::new(
+            vec![
+                MenuEntry::new("Check out", None),
+                MenuEntry::new("Delete", Some("ctrl-d".to_string())),
+            ],
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(ColorTheme::default()),
+        )
+    }
+
+    #[test]
+    fn test_act_key() {
+        let mut menu = menu();
+        assert_eq!(menu.act_key(Key::Enter), None);
+        menu.open();
+        assert!(menu.focus.load(Ordering::SeqCst));
+        menu.act_key(Key::Up);
+        assert_eq!(menu.cursor, Some(1));
+        assert_eq!(
+            menu.act_key(Key::Enter),
+            Some(Event::EvActAccept(Some("ctrl-d".to_string())))
+        );
+        assert!(!menu.is_open());
+        assert!(!menu.focus.load(Ordering::SeqCst));
+
+        menu.open();
+        menu.act_key(Key::ESC);
+        assert!(!menu.is_open());
+        menu.open();
+        assert_eq!(menu.act_key(Key::Enter), Some(Event::EvActAccept(None)));
+
+        assert_eq!(menu.size(), (9 + 6 + 6, 4));
+    }
+
+    #[test]
+    fn test_place() {
+        // below the cursor
+        assert_eq!(place((10, 4), (80, 20), 3, 2), (4, 2));
+        // above it at the bottom of the screen
+        assert_eq!(place((10, 4), (80, 20), 19, 2), (15, 2));
+        // neither fits
+        assert_eq!(place((10, 4), (80, 5), 2, 2), (1, 2));
+        // a narrow screen
+        assert_eq!(place((10, 4), (11, 20), 0, 2), (1, 1));
+    }
+}
@@ -1,6 +1,7 @@
 use std::any::Any;
 use std::borrow::Cow;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread;
@@ -9,14 +10,22 @@ use crossbeam::channel::{Receiver, Sender};
 use tuikit::prelude::{Event as TermEvent, *};
 
 pub use ansi::AnsiString;
+pub use border::BorderStyle;
+use engine::factory::{AndOrEngineFactory, ExactOrFuzzyEngineFactory, RegexEngineFactory};
 pub use engine::fuzzy::FuzzyAlgorithm;
 use event::{EventReceiver, EventSender};
+use item::{parse_criteria, RankBuilder};
+pub use menu::MenuEntry;
 use model::Model;
-pub use options::SkimOptions;
+pub use options::{Emitted, QueryItem, SkimOptions};
 pub use output::SkimOutput;
 use reader::Reader;
+pub use theme::ColorTheme;
+pub use tuikit::prelude::Term;
 
 mod ansi;
+mod border;
+mod chain;
 mod engine;
 pub mod event;
 pub mod field;
@@ -26,6 +35,7 @@ mod helper;
 mod input;
 mod item;
 mod matcher;
+mod menu;
 mod model;
 mod options;
 mod orderedvec;
@@ -36,6 +46,7 @@ mod query;
 mod reader;
 mod selection;
 mod spinlock;
+mod suspend;
 mod theme;
 mod util;
 
@@ -123,6 +134,12 @@ pub trait SkimItem: AsAny + Send + Sync + 'static {
     fn get_matching_ranges(&self) -> Option<&[(usize, usize)]> {
         None
     }
+
+    /// Items that are not selectable, e.g. section headers, are skipped by the cursor, can't be
+    /// toggled and are drawn with the header color.
+    fn is_selectable(&self) -> bool {
+        true
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -148,6 +165,20 @@ pub struct DisplayContext<'a> {
     pub matches: Matches<'a>,
     pub container_width: usize,
     pub highlight_attr: Attr,
+    /// Express everything as text rather than colors, see `SkimOptions::accessible`
+    pub accessible: bool,
+    /// Where to cut a text that is wider than `container_width`, see `SkimOptions::truncate`
+    pub truncate: Truncate,
+}
+
+/// The part of an item's text that is cut when it doesn't fit, replaced with an ellipsis. Left to
+/// the item to honor in `display`, skim itself scrolls to the matches instead.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub enum Truncate {
+    #[default]
+    Middle,
+    End,
+    Start,
 }
 
 impl<'a> From<DisplayContext<'a>> for AnsiString<'a> {
@@ -278,6 +309,61 @@ impl Skim {
     /// - None: on internal errors.
     /// - SkimOutput: the collected key, event, query, selected items, etc.
     pub fn run_with(options: &SkimOptions, source: Option<SkimItemReceiver>) -> Option<SkimOutput> {
+        let term = Skim::init_term(options);
+        Skim::run_with_term(term, options, source)
+    }
+
+    /// Match `items` against `query` without a terminal, like `fzf --filter`.
+    ///
+    /// return: the matching items, best match first. Items that rank the same keep their order.
+    pub fn filter(
+        options: &SkimOptions,
+        query: &str,
+        items: Vec<Arc<dyn SkimItem>>,
+    ) -> Vec<Arc<dyn SkimItem>> {
+        let criterion = match options.tiebreak {
+            Some(ref tie_breaker) => tie_breaker.split(',').filter_map(parse_criteria).collect(),
+            None => model::DEFAULT_CRITERION.clone(),
+        };
+        let rank_builder = Arc::new(RankBuilder::new(criterion));
+
+        let engine = if options.regex {
+            RegexEngineFactory::builder()
+                .rank_builder(rank_builder)
+                .build()
+                .create_engine_with_case(query, options.case)
+        } else {
+            AndOrEngineFactory::new(
+                ExactOrFuzzyEngineFactory::builder()
+                    .exact_mode(options.exact)
+                    .rank_builder(rank_builder)
+                    .build(),
+            )
+            .create_engine_with_case(query, options.case)
+        };
+
+        let mut matched: Vec<(Rank, Arc<dyn SkimItem>)> = items
+            .into_iter()
+            .filter_map(|item| {
+                engine
+                    .match_item(item.clone())
+                    .map(|result| (result.rank, item))
+            })
+            .collect();
+        if !options.nosort {
+            matched.sort_by_key(|(rank, _)| *rank);
+        }
+        if options.tac {
+            matched.reverse();
+        }
+        matched.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Set up the terminal for skim.
+    ///
+    /// The terminal could be shared by several consecutive `run_with_term` calls so that chained
+    /// sessions don't flicker. It is restored once the last reference is dropped.
+    pub fn init_term(options: &SkimOptions) -> Arc<Term> {
         let min_height = options
             .min_height
             .map(Skim::parse_height_string)
@@ -287,7 +373,6 @@ impl Skim {
             .map(Skim::parse_height_string)
             .expect("height should have default values");
 
-        let (tx, rx): (EventSender, EventReceiver) = channel();
         let term = Arc::new(
             Term::with_options(
                 TermOptions::default()
@@ -303,28 +388,119 @@ impl Skim {
         if !options.no_mouse {
             let _ = term.enable_mouse_support();
         }
+        term
+    }
+
+    /// Same as `run_with`, but draws on a terminal set up by `init_term`
+    pub fn run_with_term(
+        term: Arc<Term>,
+        options: &SkimOptions,
+        source: Option<SkimItemReceiver>,
+    ) -> Option<SkimOutput> {
+        let (tx, rx): (EventSender, EventReceiver) = channel();
 
         //------------------------------------------------------------------------------
         // input
         let mut input = input::Input::new();
         input.parse_keymaps(&options.bind);
         input.parse_expect_keys(options.expect.as_deref());
+        if options.vi_mode {
+            input.enable_vi_mode(options.layout.starts_with("reverse"));
+        }
+
+        // while searching the preview, the keys go to the previewer instead of the key bindings
+        let preview_focus = Arc::new(AtomicBool::new(false));
+        // and while the menu is open, to the menu
+        let menu_focus = Arc::new(AtomicBool::new(false));
 
         let tx_clone = tx.clone();
         let term_clone = term.clone();
+        let preview_focus_clone = preview_focus.clone();
+        let menu_focus_clone = menu_focus.clone();
         let input_thread = thread::spawn(move || loop {
             if let Ok(key) = term_clone.poll_event() {
                 if key == TermEvent::User(()) {
                     break;
                 }
 
+                if let TermEvent::Key(key) = key {
+                    if menu_focus_clone.load(Ordering::SeqCst) {
+                        let _ = tx_clone.send((key, event::Event::EvMenuKey(key)));
+                        continue;
+                    }
+                    if preview_focus_clone.load(Ordering::SeqCst) {
+                        let _ = tx_clone.send((key, event::Event::EvPreviewSearchKey(key)));
+                        continue;
+                    }
+                }
+
                 let (key, action_chain) = input.translate_event(key);
+                if chain::waits(&action_chain) {
+                    let _ = tx_clone.send((key, event::Event::EvActChain(action_chain)));
+                    continue;
+                }
                 for event in action_chain.into_iter() {
                     let _ = tx_clone.send((key, event));
                 }
             }
         });
 
+        //------------------------------------------------------------------------------
+        // reload: forward the caller's signals until it stops sending them
+        if let Some(reload) = options.reload.clone() {
+            let tx_clone = tx.clone();
+            thread::spawn(move || {
+                while reload.recv().is_ok() {
+                    if tx_clone
+                        .send((Key::Null, event::Event::EvActReload))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        //------------------------------------------------------------------------------
+        // done: go on with the chains waiting for the emitted actions, and list the items again
+        if let Some(done) = options.done.clone() {
+            let tx_clone = tx.clone();
+            thread::spawn(move || {
+                while let Ok(succeeded) = done.recv() {
+                    let events = [
+                        event::Event::EvEmitDone(succeeded),
+                        event::Event::EvActReload,
+                    ];
+                    if events
+                        .into_iter()
+                        .any(|event| tx_clone.send((Key::Null, event)).is_err())
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        //------------------------------------------------------------------------------
+        // status: show the caller's messages until it stops sending them
+        if let Some(status) = options.status.clone() {
+            let tx_clone = tx.clone();
+            thread::spawn(move || {
+                while let Ok(text) = status.recv() {
+                    if tx_clone
+                        .send((Key::Null, event::Event::EvStatus(text)))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        //------------------------------------------------------------------------------
+        // suspend: ctrl-z and SIGTSTP give the terminal back until the process is continued
+        let _suspend = suspend::Listener::start(tx.clone());
+
         //------------------------------------------------------------------------------
         // reader
 
@@ -332,7 +508,15 @@ impl Skim {
 
         //------------------------------------------------------------------------------
         // model + previewer
-        let mut model = Model::new(rx, tx, reader, term.clone(), options);
+        let mut model = Model::new(
+            rx,
+            tx,
+            reader,
+            term.clone(),
+            preview_focus,
+            menu_focus,
+            options,
+        );
         let ret = model.start();
         let _ = term.send_event(TermEvent::User(())); // interrupt the input thread
         let _ = input_thread.join();
@@ -349,3 +533,32 @@ impl Skim {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let items: Vec<Arc<dyn SkimItem>> = ["main", "feature/login", "fix/logging", "login"]
+            .iter()
+            .map(|text| Arc::new(text.to_string()) as Arc<dyn SkimItem>)
+            .collect();
+        let options = SkimOptions::default();
+        let texts = |query| -> Vec<String> {
+            Skim::filter(&options, query, items.clone())
+                .iter()
+                .map(|item| item.text().into_owned())
+                .collect()
+        };
+
+        assert_eq!(
+            texts("login"),
+            vec!["login", "feature/login", "fix/logging"]
+        );
+        assert_eq!(texts("'login"), vec!["login", "feature/login"]);
+        assert_eq!(texts("fix | main"), vec!["main", "fix/logging"]);
+        assert!(texts("xyz").is_empty());
+        assert_eq!(texts("").len(), 4);
+    }
+}
@@ -3,15 +3,19 @@ use std::env;
 
 use std::process::Command;
 use std::rc::Rc;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use chrono::Duration as TimerDuration;
+use crossbeam::channel::Sender;
 use defer_drop::DeferDrop;
 use regex::Regex;
 use timer::{Guard as TimerGuard, Timer};
 use tuikit::prelude::{Event as TermEvent, *};
 
+use crate::skim::border::{BorderStyle, Corners};
+use crate::skim::chain::Chains;
 use crate::skim::engine::factory::{
     AndOrEngineFactory, ExactOrFuzzyEngineFactory, RegexEngineFactory,
 };
@@ -21,13 +25,15 @@ use crate::skim::header::Header;
 use crate::skim::input::parse_action_arg;
 use crate::skim::item::{parse_criteria, ItemPool, MatchedItem, RankBuilder, RankCriteria};
 use crate::skim::matcher::{Matcher, MatcherControl};
-use crate::skim::options::SkimOptions;
+use crate::skim::menu::{Menu, MenuOverlay};
+use crate::skim::options::{Emitted, QueryItem, SkimOptions};
 use crate::skim::output::SkimOutput;
 use crate::skim::previewer::Previewer;
 use crate::skim::query::Query;
 use crate::skim::reader::{Reader, ReaderControl};
 use crate::skim::selection::Selection;
 use crate::skim::spinlock::SpinLock;
+use crate::skim::suspend;
 use crate::skim::theme::ColorTheme;
 use crate::skim::util::clear_canvas;
 use crate::skim::util::{
@@ -47,7 +53,7 @@ lazy_static! {
     static ref RE_FIELDS: Regex = Regex::new(r"\\?(\{-?[0-9.,q]*?})").unwrap();
     static ref RE_PREVIEW_OFFSET: Regex =
         Regex::new(r"^\+([0-9]+|\{-?[0-9]+\})(-[0-9]+|-/[1-9][0-9]*)?$").unwrap();
-    static ref DEFAULT_CRITERION: Vec<RankCriteria> =
+    pub(crate) static ref DEFAULT_CRITERION: Vec<RankCriteria> =
         vec![RankCriteria::Score, RankCriteria::Begin, RankCriteria::End,];
 }
 
@@ -81,8 +87,13 @@ pub struct Model {
 
     preview_hidden: bool,
     previewer: Option<Previewer>,
+    // set while the previewer takes the keys, see `Previewer::focus`
+    preview_focus: Arc<AtomicBool>,
     preview_direction: Direction,
     preview_size: Size,
+    preview_border: Option<BorderStyle>,
+
+    menu: Menu,
 
     margin_top: Size,
     margin_right: Size,
@@ -92,6 +103,9 @@ pub struct Model {
     layout: String,
     delimiter: Regex,
     inline_info: bool,
+    info: Option<String>,
+    status: Option<String>,
+    accessible: bool,
     no_clear_if_empty: bool,
     theme: Arc<ColorTheme>,
 
@@ -101,6 +115,17 @@ pub struct Model {
 
     // for AppendAndSelect action
     rank_builder: Arc<RankBuilder>,
+
+    // the item the cursor was on before a reload, or the `current` one of the options at the
+    // start, restored once the items are processed
+    reload_cursor: Option<String>,
+
+    // where the keys bound to `emit(<name>)` send the picked items
+    emit: Option<Sender<Emitted>>,
+    // makes the item listed first for the query, if any
+    query_item: Option<QueryItem>,
+    // the chains of actions waiting for the ones they emitted
+    chains: Chains,
 }
 
 impl Model {
@@ -109,6 +134,8 @@ impl Model {
         tx: EventSender,
         reader: Reader,
         term: Arc<Term>,
+        preview_focus: Arc<AtomicBool>,
+        menu_focus: Arc<AtomicBool>,
         options: &SkimOptions,
     ) -> Self {
         let default_command = match env::var("SKIM_DEFAULT_COMMAND")
@@ -167,6 +194,8 @@ impl Model {
             .item_pool(item_pool.clone())
             .theme(theme.clone());
 
+        let menu = Menu::new(options.menu.clone(), menu_focus, theme.clone());
+
         let margins = options
             .margin
             .map(parse_margin)
@@ -198,8 +227,12 @@ impl Model {
             header,
             preview_hidden: true,
             previewer: None,
+            preview_focus,
             preview_direction: Direction::Right,
             preview_size: Size::Default,
+            preview_border: None,
+
+            menu,
 
             margin_top,
             margin_right,
@@ -209,12 +242,19 @@ impl Model {
             layout: "default".to_string(),
             delimiter: Regex::new(DELIMITER_STR).unwrap(),
             inline_info: false,
+            info: None,
+            status: None,
+            accessible: false,
             no_clear_if_empty: false,
             theme,
             timer: Timer::new(),
             hb_timer_guard: None,
 
             rank_builder,
+            reload_cursor: None,
+            emit: None,
+            query_item: None,
+            chains: Chains::default(),
         };
         ret.parse_options(options);
         ret
@@ -232,6 +272,11 @@ impl Model {
             self.inline_info = true;
         }
 
+        self.info = options.info.map(|info| info.to_string());
+        self.emit = options.emit.clone();
+        self.query_item = options.query_item.clone();
+        self.accessible = options.accessible;
+
         if options.regex {
             self.use_regex = true;
         }
@@ -246,6 +291,7 @@ impl Model {
         self.preview_direction = preview_direction;
         self.preview_size = preview_size;
         self.preview_hidden = !preview_shown;
+        self.preview_border = options.border;
 
         if let Some(preview_cmd) = options.preview {
             let tx = Arc::new(SpinLock::new(self.tx.clone()));
@@ -254,6 +300,8 @@ impl Model {
                     let _ = tx.lock().send((Key::Null, Event::EvHeartBeat));
                 })
                 .wrap(preview_wrap)
+                .focus(self.preview_focus.clone())
+                .attr(self.theme.preview())
                 .delimiter(self.delimiter.clone())
                 .preview_offset(
                     options
@@ -268,6 +316,7 @@ impl Model {
         self.exit0 = options.exit0;
         self.sync = options.sync;
         self.no_clear_if_empty = options.no_clear_if_empty;
+        self.reload_cursor = options.current.map(str::to_string);
     }
 
     // -> (direction, size, wrap, shown)
@@ -334,7 +383,7 @@ impl Model {
             let ctrl = self.matcher_control.take().unwrap();
             let lock = ctrl.into_items();
             let mut items = lock.lock();
-            let matched = std::mem::take(&mut *items);
+            let mut matched = std::mem::take(&mut *items);
 
             match env.clear_selection {
                 ClearStrategy::DontClear => {}
@@ -350,6 +399,22 @@ impl Model {
                 }
             };
             self.num_options += matched.len();
+            // ranked before any match
+            let made = self
+                .query_item
+                .as_ref()
+                .and_then(|make| make(&self.query.get_fz_query()));
+            if let Some(item) = made {
+                matched.insert(
+                    0,
+                    MatchedItem {
+                        item,
+                        rank: [i32::MIN; 4],
+                        matched_range: None,
+                        item_idx: u32::MAX,
+                    },
+                );
+            }
             self.selection.append_sorted_items(matched);
         }
 
@@ -366,6 +431,13 @@ impl Model {
             self.restart_matcher();
         }
 
+        // put the cursor back on the item it was on before the reload
+        if processed && self.matcher_control.is_none() {
+            if let Some(text) = self.reload_cursor.take() {
+                self.selection.act_move_cursor_to(&text);
+            }
+        }
+
         // send next heart beat if matcher is still running or there are items not been processed.
         if self.matcher_control.is_some() || !processed {
             let tx = self.tx.clone();
@@ -448,6 +520,14 @@ impl Model {
         self.reader_timer = Instant::now();
     }
 
+    fn act_reload(&mut self, env: &mut ModelEnv) {
+        self.reload_cursor = self
+            .selection
+            .get_current_item()
+            .map(|item| item.text().into_owned());
+        self.on_cmd_query_change(env);
+    }
+
     fn on_query_change(&mut self, env: &mut ModelEnv) {
         // restart matcher
         if let Some(ctrl) = self.matcher_control.take() {
@@ -459,6 +539,23 @@ impl Model {
         self.restart_matcher();
     }
 
+    fn act_emit(&mut self, name: &str) {
+        // sent even without items, for the actions that need none
+        let items = self.selection.get_selected_indices_and_items().1;
+        self.chains.emitted();
+        let sent = self.emit.as_ref().is_some_and(|emit| {
+            emit.send(Emitted {
+                name: name.to_string(),
+                items,
+            })
+            .is_ok()
+        });
+        if !sent {
+            // nothing will tell that it is done
+            self.chains.done(false);
+        }
+    }
+
     fn act_execute(&mut self, cmd: &str) {
         let item = self.selection.get_current_item();
         if depends_on_items(cmd) && item.is_none() {
@@ -507,6 +604,16 @@ impl Model {
         let _ = Command::new(shell).arg("-c").arg(cmd).status();
     }
 
+    /// Give the terminal back, and stop until continued with `stop`, then set it up again for
+    /// the picker to be drawn in full
+    fn act_suspend(&mut self, stop: bool) {
+        let _ = self.term.pause();
+        if stop {
+            suspend::stop();
+        }
+        let _ = self.term.restart();
+    }
+
     #[allow(clippy::trivial_regex)]
     fn act_append_and_select(&mut self, env: &mut ModelEnv) {
         let query = self.query.get_fz_query();
@@ -545,7 +652,10 @@ impl Model {
         // In the event loop, there might need
         let mut next_event = Some((Key::Null, Event::EvHeartBeat));
         loop {
-            let (key, ev) = next_event.take().or_else(|| self.rx.recv().ok())?;
+            let (key, ev) = next_event
+                .take()
+                .or_else(|| self.chains.next())
+                .or_else(|| self.rx.recv().ok())?;
 
             debug!("handle event: {:?}", ev);
 
@@ -588,6 +698,21 @@ impl Model {
                     self.preview_hidden = !self.preview_hidden;
                 }
 
+                Event::EvActPreviewSearch => {
+                    self.preview_hidden = false;
+                }
+
+                Event::EvActMenu if self.selection.get_current_item().is_some() => {
+                    self.menu.open();
+                }
+
+                Event::EvMenuKey(menu_key) => {
+                    if let Some(accept) = self.menu.act_key(menu_key) {
+                        next_event = Some((key, accept));
+                        continue;
+                    }
+                }
+
                 Event::EvActRotateMode => {
                     self.act_rotate_mode(&mut env);
                 }
@@ -607,6 +732,7 @@ impl Model {
                         query: self.query.get_fz_query(),
                         cmd: self.query.get_cmd_query(),
                         selected_items: self.selection.get_selected_indices_and_items().1,
+                        current_item: self.selection.get_current_item(),
                     });
                 }
 
@@ -625,6 +751,7 @@ impl Model {
                         query: self.query.get_fz_query(),
                         cmd: self.query.get_cmd_query(),
                         selected_items: self.selection.get_selected_indices_and_items().1,
+                        current_item: self.selection.get_current_item(),
                     });
                 }
 
@@ -645,10 +772,34 @@ impl Model {
                     self.act_execute_silent(cmd);
                 }
 
+                Event::EvActSuspend => {
+                    self.act_suspend(true);
+                }
+
+                Event::EvResumed => {
+                    self.act_suspend(false);
+                }
+
                 Event::EvActAppendAndSelect => {
                     self.act_append_and_select(&mut env);
                 }
 
+                Event::EvActEmit(ref name) => {
+                    self.act_emit(name);
+                }
+
+                Event::EvActChain(ref chain) => {
+                    self.chains.push(key, chain.clone());
+                }
+
+                Event::EvEmitDone(succeeded) => {
+                    self.chains.done(succeeded);
+                }
+
+                Event::EvStatus(ref text) => {
+                    self.status = Some(text.clone());
+                }
+
                 Event::EvInputKey(key) => {
                     // dispatch key(normally the mouse keys) to sub-widgets
                     self.do_with_widget(|root| {
@@ -670,6 +821,10 @@ impl Model {
                     self.on_cmd_query_change(&mut env);
                 }
 
+                Event::EvActReload => {
+                    self.act_reload(&mut env);
+                }
+
                 Event::EvActRefreshPreview => {
                     self.draw_preview(&env, true);
                 }
@@ -827,10 +982,17 @@ impl Model {
             matcher_mode,
             theme: self.theme.clone(),
             inline_info: self.inline_info,
+            info: self.info.clone(),
+            status: self.status.clone(),
+            accessible: self.accessible,
         };
         let status_inline = status.clone();
 
-        let win_selection = Win::new(&self.selection);
+        // the menu is drawn over the list, next to the cursor
+        let win_selection = Win::new(Stack::new().top(&self.selection).top(MenuOverlay {
+            menu: &self.menu,
+            selection: &self.selection,
+        }));
         let win_query = Win::new(&self.query)
             .basis(if self.inline_info { 0 } else { 1 })
             .grow(0)
@@ -875,13 +1037,23 @@ impl Model {
                 .basis(self.preview_size)
                 .grow(0)
                 .shrink(0)
-                .border_attr(self.theme.border());
-
-            let win_preview = match self.preview_direction {
-                Direction::Up => win.border_bottom(true),
-                Direction::Right => win.border_left(true),
-                Direction::Down => win.border_top(true),
-                Direction::Left => win.border_right(true),
+                .border_attr(self.theme.preview_border());
+
+            let win = match (self.preview_border, self.preview_direction) {
+                (Some(BorderStyle::Rounded | BorderStyle::Sharp), _) => win.border(true),
+                // a blank column keeps the items and the preview apart
+                (Some(BorderStyle::None), Direction::Right) => win.padding_left(1),
+                (Some(BorderStyle::None), Direction::Left) => win.padding_right(1),
+                (Some(BorderStyle::None), Direction::Up | Direction::Down) => win,
+                (None, Direction::Up) => win.border_bottom(true),
+                (None, Direction::Right) => win.border_left(true),
+                (None, Direction::Down) => win.border_top(true),
+                (None, Direction::Left) => win.border_right(true),
+            };
+            let win_preview = Corners {
+                win,
+                round: (self.preview_border == Some(BorderStyle::Rounded))
+                    .then(|| self.theme.preview_border()),
             };
 
             match self.preview_direction {
@@ -926,6 +1098,9 @@ struct Status {
     matcher_mode: String,
     theme: Arc<ColorTheme>,
     inline_info: bool,
+    info: Option<String>,
+    status: Option<String>,
+    accessible: bool,
 }
 
 #[allow(unused_assignments)]
@@ -964,8 +1139,8 @@ impl Draw for Status {
             col += canvas.put_char_with_attr(0, col, ' ', info_attr)?;
         }
 
-        // draw the spinner
-        if self.reading && a_while_since_read {
+        // draw the spinner, which is left out when accessible as it redraws constantly
+        if self.reading && a_while_since_read && !self.accessible {
             let mills = (self.time_since_read.as_secs() * 1000) as u32
                 + self.time_since_read.subsec_millis();
             let index = (mills / SPINNER_DURATION) % (spinner_set.len() as u32);
@@ -995,8 +1170,23 @@ impl Draw for Status {
             )?;
         }
 
+        // display the application provided description of the items
+        if let Some(info) = &self.info {
+            col += canvas.print_with_attr(0, col, format!(" {}", info).as_ref(), info_attr)?;
+        }
+
+        // what the actions running in the background did
+        if let Some(status) = &self.status {
+            col += canvas.print_with_attr(0, col, format!(" · {}", status).as_ref(), info_attr)?;
+        }
+
+        // state which would otherwise only be visible through the spinner
+        if self.accessible && (self.reading || self.matcher_running) {
+            col += canvas.print_with_attr(0, col, " (loading)", info_attr)?;
+        }
+
         // display the percentage of the number of processed items
-        if self.matcher_running && a_while_since_match {
+        if self.matcher_running && a_while_since_match && !self.accessible {
             col += canvas.print_with_attr(
                 0,
                 col,
@@ -1007,12 +1197,12 @@ impl Draw for Status {
 
         // selected number
         if self.multi_selection && self.selected > 0 {
-            col += canvas.print_with_attr(
-                0,
-                col,
-                format!(" [{}]", self.selected).as_ref(),
-                info_attr_bold,
-            )?;
+            let selected = if self.accessible {
+                format!(" {} selected", self.selected)
+            } else {
+                format!(" [{}]", self.selected)
+            };
+            col += canvas.print_with_attr(0, col, selected.as_ref(), info_attr_bold)?;
         }
 
         Ok(())
@@ -3,9 +3,22 @@ use std::rc::Rc;
 use derive_builder::Builder;
 
 use crate::skim::helper::item_reader::SkimItemReader;
+use crate::skim::menu::MenuEntry;
 use crate::skim::reader::CommandCollector;
-use crate::skim::{CaseMatching, FuzzyAlgorithm, MatchEngineFactory, Selector};
+use crate::skim::SkimItem;
+use crate::skim::{
+    BorderStyle, CaseMatching, FuzzyAlgorithm, MatchEngineFactory, Selector, Truncate,
+};
+use crossbeam::channel::{Receiver, Sender};
 use std::cell::RefCell;
+use std::sync::Arc;
+
+/// What a key bound to `emit(<name>)` sends: the name, and the selected items or the one under
+/// the cursor, as on accept
+pub struct Emitted {
+    pub name: String,
+    pub items: Vec<Arc<dyn SkimItem>>,
+}
 
 #[derive(Builder)]
 #[builder(build_fn(name = "final_build"))]
@@ -15,6 +28,7 @@ pub struct SkimOptions<'a> {
     pub multi: bool,
     pub prompt: Option<&'a str>,
     pub cursor: Option<&'a str>,
+    pub marker: Option<&'a str>,
     pub cmd_prompt: Option<&'a str>,
     pub expect: Option<String>,
     pub tac: bool,
@@ -37,11 +51,14 @@ pub struct SkimOptions<'a> {
     pub height: Option<&'a str>,
     pub preview: Option<&'a str>,
     pub preview_window: Option<&'a str>,
+    /// The border around the preview, a line between it and the items when not set
+    pub border: Option<BorderStyle>,
     pub reverse: bool,
     pub tabstop: Option<&'a str>,
     pub no_hscroll: bool,
     pub no_mouse: bool,
     pub inline_info: bool,
+    pub info: Option<&'a str>,
     pub header: Option<&'a str>,
     pub header_lines: usize,
     pub layout: &'a str,
@@ -58,8 +75,28 @@ pub struct SkimOptions<'a> {
     pub sync: bool,
     pub selector: Option<Rc<dyn Selector>>,
     pub no_clear_if_empty: bool,
+    pub reload: Option<Receiver<()>>,
+    /// Where the keys bound to `emit(<name>)` send the picked items, leaving the picker open
+    pub emit: Option<Sender<Emitted>>,
+    /// Whether each action sent on `emit` succeeded, in the order they were sent. The steps bound
+    /// after an `emit(<name>)` wait for it, and the items are listed again after each.
+    pub done: Option<Receiver<bool>>,
+    /// Messages shown on the info line after `info`, each replacing the previous one
+    pub status: Option<Receiver<String>>,
+    pub accessible: bool,
+    pub truncate: Truncate,
+    pub vi_mode: bool,
+    pub menu: Vec<MenuEntry>,
+    /// The text of the item to put the cursor on once the items are read
+    pub current: Option<&'a str>,
+    /// Makes an item of the query, listed before the matched ones while the query makes one, e.g.
+    /// to create a branch named after it
+    pub query_item: Option<QueryItem>,
 }
 
+/// See `SkimOptions::query_item`
+pub type QueryItem = Rc<dyn Fn(&str) -> Option<Arc<dyn SkimItem>>>;
+
 impl<'a> Default for SkimOptions<'a> {
     fn default() -> Self {
         Self {
@@ -67,6 +104,7 @@ impl<'a> Default for SkimOptions<'a> {
             multi: false,
             prompt: Some("❯ "),
             cursor: Some("❯"),
+            marker: Some(">"),
             cmd_prompt: Some("c> "),
             expect: None,
             tac: false,
@@ -89,11 +127,13 @@ impl<'a> Default for SkimOptions<'a> {
             height: Some("100%"),
             preview: None,
             preview_window: Some("right:50%"),
+            border: None,
             reverse: false,
             tabstop: None,
             no_hscroll: false,
             no_mouse: false,
             inline_info: false,
+            info: None,
             header: None,
             header_lines: 0,
             layout: "",
@@ -110,6 +150,16 @@ impl<'a> Default for SkimOptions<'a> {
             sync: false,
             selector: None,
             no_clear_if_empty: false,
+            reload: None,
+            emit: None,
+            done: None,
+            status: None,
+            accessible: false,
+            truncate: Truncate::default(),
+            vi_mode: false,
+            menu: vec![],
+            current: None,
+            query_item: None,
         }
     }
 }
@@ -124,6 +174,12 @@ impl<'a> SkimOptionsBuilder<'a> {
             self.layout = Some("reverse");
         }
 
+        if let Some(true) = self.accessible {
+            self.color.get_or_insert(Some("bw"));
+            self.cursor = Some(Some(">>"));
+            self.marker = Some(Some("[x]"));
+        }
+
         self.final_build()
     }
 }
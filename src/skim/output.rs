@@ -24,4 +24,7 @@ pub struct SkimOutput {
 
     /// The selected items.
     pub selected_items: Vec<Arc<dyn SkimItem>>,
+
+    /// The item under the cursor.
+    pub current_item: Option<Arc<dyn SkimItem>>,
 }
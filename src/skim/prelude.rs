@@ -1,5 +1,8 @@
+pub use crate::skim::helper::selector::DefaultSkimSelector;
 pub use crate::skim::options::SkimOptionsBuilder;
+pub use crate::skim::reader::CommandCollector;
 pub use crate::skim::*;
 pub use crossbeam::channel::unbounded;
 pub use std::borrow::Cow;
 pub use std::sync::Arc;
+pub use tuikit::attr::{Attr, Color};
@@ -16,7 +16,7 @@ use tuikit::prelude::{Event as TermEvent, *};
 use crate::skim::ansi::AnsiString;
 use crate::skim::event::{Event, EventHandler, UpdateScreen};
 use crate::skim::spinlock::SpinLock;
-use crate::skim::util::{atoi, clear_canvas, depends_on_items, inject_command, InjectContext};
+use crate::skim::util::{atoi, depends_on_items, inject_command, InjectContext};
 use crate::skim::{ItemPreview, PreviewContext, PreviewPosition, SkimItem};
 
 const TAB_STOP: usize = 8;
@@ -31,6 +31,12 @@ pub struct Previewer {
     hscroll_offset: Arc<AtomicUsize>,
     vscroll_offset: Arc<AtomicUsize>,
     wrap: bool,
+    /// The colors the content is drawn in where it has none of its own
+    attr: Attr,
+
+    search: Option<PreviewSearch>,
+    // set while the search takes the keys instead of the item list
+    focus: Arc<AtomicBool>,
 
     prev_item: Option<Arc<dyn SkimItem>>,
     prev_query: Option<String>,
@@ -89,6 +95,10 @@ impl Previewer {
             hscroll_offset,
             vscroll_offset,
             wrap: false,
+            attr: Attr::default(),
+
+            search: None,
+            focus: Arc::new(AtomicBool::new(false)),
 
             prev_item: None,
             prev_query: None,
@@ -107,6 +117,17 @@ impl Previewer {
         self
     }
 
+    pub fn attr(mut self, attr: Attr) -> Self {
+        self.attr = attr;
+        self
+    }
+
+    /// The flag which routes the keys to the previewer while searching
+    pub fn focus(mut self, focus: Arc<AtomicBool>) -> Self {
+        self.focus = focus;
+        self
+    }
+
     pub fn delimiter(mut self, delimiter: Regex) -> Self {
         self.delimiter = delimiter;
         self
@@ -266,6 +287,73 @@ impl Previewer {
         self.wrap = !self.wrap;
     }
 
+    fn act_search(&mut self) {
+        let search = self.search.get_or_insert_with(PreviewSearch::default);
+        search.focused = true;
+        self.focus.store(true, Ordering::SeqCst);
+    }
+
+    /// Handle a key typed while searching, returning the focus to the list on enter or escape
+    fn act_search_key(&mut self, key: Key) {
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return,
+        };
+
+        match key {
+            Key::Char(ch) => {
+                search.query.push(ch);
+                self.jump_to_match(Jump::First);
+            }
+            Key::Backspace => {
+                search.query.pop();
+                self.jump_to_match(Jump::First);
+            }
+            Key::Ctrl('n') | Key::Down => self.jump_to_match(Jump::Next),
+            Key::Ctrl('p') | Key::Up => self.jump_to_match(Jump::Previous),
+            Key::Enter => {
+                // keep the matches highlighted
+                search.focused = false;
+                self.focus.store(false, Ordering::SeqCst);
+            }
+            Key::ESC | Key::Ctrl('g') | Key::Ctrl('c') => {
+                self.search = None;
+                self.focus.store(false, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+    }
+
+    /// Scroll so that a line matching the search is at the top of the preview
+    fn jump_to_match(&mut self, jump: Jump) {
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return,
+        };
+        let lines = matching_lines(&self.content_lines.lock(), &search.query);
+        if lines.is_empty() {
+            search.current = None;
+            return;
+        }
+
+        let top = self.vscroll_offset.load(Ordering::SeqCst) - 1;
+        let line = match (jump, search.current) {
+            (Jump::Next, Some(current)) => lines.iter().find(|&&line| line > current),
+            (Jump::Previous, Some(current)) => lines.iter().rev().find(|&&line| line < current),
+            (Jump::Previous, None) => lines.iter().rev().find(|&&line| line < top),
+            (_, _) => lines.iter().find(|&&line| line >= top),
+        };
+        // wrap around at either end
+        let line = match (line, jump) {
+            (Some(&line), _) => line,
+            (None, Jump::Previous) => lines[lines.len() - 1],
+            (None, _) => lines[0],
+        };
+
+        search.current = Some(line);
+        self.vscroll_offset.store(line + 1, Ordering::SeqCst);
+    }
+
     fn eval_scroll_offset(&self, context: InjectContext) -> PreviewPosition {
         // currently, only h_scroll and h_offset is supported
         // The syntax follows fzf's
@@ -334,6 +422,8 @@ impl EventHandler for Previewer {
             EvActPreviewRight(diff) => self.act_scroll_right(*diff),
             EvActPreviewPageUp(diff) => self.act_scroll_down(-(height as i32 * *diff)),
             EvActPreviewPageDown(diff) => self.act_scroll_down(height as i32 * *diff),
+            EvActPreviewSearch => self.act_search(),
+            EvPreviewSearchKey(key) => self.act_search_key(*key),
             _ => return UpdateScreen::DONT_REDRAW,
         }
         UpdateScreen::REDRAW
@@ -344,7 +434,9 @@ impl Draw for Previewer {
     fn draw(&self, canvas: &mut dyn Canvas) -> DrawResult<()> {
         canvas.clear()?;
         let (screen_width, screen_height) = canvas.size()?;
-        clear_canvas(canvas)?;
+        for row in 0..screen_height {
+            canvas.print_with_attr(row, 0, &" ".repeat(screen_width), self.attr)?;
+        }
 
         if screen_width == 0 || screen_height == 0 {
             return Ok(());
@@ -364,9 +456,19 @@ impl Draw for Previewer {
             .skip_rows(max(1, vscroll_offset) - 1)
             .skip_cols(max(1, hscroll_offset) - 1)
             .wrap(self.wrap)
+            .attr(self.attr)
             .build()
             .unwrap();
-        printer.print_lines(canvas, &content);
+        match &self.search {
+            Some(search) if !search.query.is_empty() => {
+                let highlighted: Vec<AnsiString> = content
+                    .iter()
+                    .map(|line| highlight_matches(line, &search.query))
+                    .collect();
+                printer.print_lines(canvas, &highlighted);
+            }
+            _ => printer.print_lines(canvas, &content),
+        }
 
         // print the vscroll info
         let status = format!("{}/{}", vscroll_offset, content.len());
@@ -377,10 +479,36 @@ impl Draw for Previewer {
             &status,
             Attr {
                 effect: Effect::REVERSE,
-                ..Attr::default()
+                ..self.attr
             },
         )?;
 
+        if let Some(search) = &self.search {
+            let lines = matching_lines(&content, &search.query);
+            let position = search
+                .current
+                .and_then(|current| lines.iter().position(|&line| line == current));
+            let matches = match position {
+                _ if search.query.is_empty() => "".to_string(),
+                _ if lines.is_empty() => " (no matches)".to_string(),
+                Some(position) => format!(" ({}/{})", position + 1, lines.len()),
+                None => format!(" ({} matches)", lines.len()),
+            };
+            let prompt = format!("/{}{}", search.query, matches);
+            let attr = if search.focused {
+                Attr {
+                    effect: Effect::REVERSE,
+                    ..self.attr
+                }
+            } else {
+                self.attr
+            };
+            canvas.print_with_attr(screen_height - 1, 0, &prompt, attr)?;
+            if search.focused {
+                let _ = canvas.set_cursor(screen_height - 1, 1 + search.query.chars().count());
+            }
+        }
+
         Ok(())
     }
 }
@@ -401,6 +529,74 @@ impl Widget<Event> for Previewer {
     }
 }
 
+/// An incremental search within the preview content
+#[derive(Debug, Default)]
+struct PreviewSearch {
+    query: String,
+    /// The line of the match scrolled to
+    current: Option<usize>,
+    /// Whether the keys go to the search rather than the item list
+    focused: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    First,
+    Next,
+    Previous,
+}
+
+/// The char ranges in `line` that match `query`, ignoring case unless the query has an
+/// uppercase letter (smart case)
+fn find_matches(line: &str, query: &str) -> Vec<(u32, u32)> {
+    let query: Vec<char> = query.chars().collect();
+    if query.is_empty() {
+        return vec![];
+    }
+    let ignore_case = !query.iter().any(|ch| ch.is_uppercase());
+    let fold = |ch: char| {
+        if ignore_case {
+            ch.to_lowercase().next().unwrap_or(ch)
+        } else {
+            ch
+        }
+    };
+
+    let line: Vec<char> = line.chars().map(fold).collect();
+    let query: Vec<char> = query.into_iter().map(fold).collect();
+    let mut matches = vec![];
+    let mut start = 0;
+    while start + query.len() <= line.len() {
+        if line[start..start + query.len()] == query[..] {
+            matches.push((start as u32, (start + query.len()) as u32));
+            start += query.len();
+        } else {
+            start += 1;
+        }
+    }
+    matches
+}
+
+fn matching_lines(content: &[AnsiString], query: &str) -> Vec<usize> {
+    content
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !find_matches(line.stripped(), query).is_empty())
+        .map(|(line_no, _)| line_no)
+        .collect()
+}
+
+fn highlight_matches<'a>(line: &AnsiString<'a>, query: &str) -> AnsiString<'a> {
+    let attr = Attr {
+        effect: Effect::REVERSE,
+        ..Attr::default()
+    };
+    let mut line = line.clone();
+    let matches = find_matches(line.stripped(), query);
+    line.override_attrs(matches.into_iter().map(|range| (attr, range)).collect());
+    line
+}
+
 #[derive(Debug, Ord, PartialOrd, PartialEq, Eq)]
 pub struct PreviewCommand {
     pub cmd: String,
@@ -553,6 +749,8 @@ struct Printer {
     wrap: bool,
     width: usize,
     height: usize,
+    /// Extended by the attributes of the content
+    attr: Attr,
 }
 
 impl Printer {
@@ -566,7 +764,7 @@ impl Printer {
             }
 
             for (ch, attr) in line.iter() {
-                let _ = self.print_char_with_attr(canvas, ch, attr);
+                let _ = self.print_char_with_attr(canvas, ch, self.attr.extend(attr));
 
                 // skip if the content already exceeded the canvas
                 if !self.wrap && self.col >= self.width + self.skip_cols {
@@ -648,3 +846,19 @@ impl Printer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_matches() {
+        assert_eq!(find_matches(" src/main.rs | 12 ++--", "main"), vec![(5, 9)]);
+        assert_eq!(find_matches("Main main", "main"), vec![(0, 4), (5, 9)]);
+        assert_eq!(find_matches("Main main", "Main"), vec![(0, 4)]);
+        assert_eq!(find_matches("aaaa", "aa"), vec![(0, 2), (2, 4)]);
+        assert_eq!(find_matches("ünïcode ü", "ü"), vec![(0, 1), (8, 9)]);
+        assert!(find_matches("main", "").is_empty());
+        assert!(find_matches("ma", "main").is_empty());
+    }
+}
@@ -14,7 +14,7 @@ use crate::skim::orderedvec::OrderedVec;
 use crate::skim::theme::{ColorTheme, DEFAULT_THEME};
 use crate::skim::util::clear_canvas;
 use crate::skim::util::{print_item, reshape_string, LinePrinter};
-use crate::skim::{DisplayContext, MatchRange, Matches, Selector, SkimItem, SkimOptions};
+use crate::skim::{DisplayContext, MatchRange, Matches, Selector, SkimItem, SkimOptions, Truncate};
 use regex::Regex;
 use std::rc::Rc;
 use unicode_width::UnicodeWidthStr;
@@ -52,6 +52,9 @@ pub struct Selection {
     reverse: bool,
     no_hscroll: bool,
     cursor_label: String,
+    marker: String,
+    accessible: bool,
+    truncate: Truncate,
     theme: Arc<ColorTheme>,
 
     // Pre-selection will be performed the first time an item was seen by Selection.
@@ -77,6 +80,9 @@ impl Selection {
             reverse: false,
             no_hscroll: false,
             cursor_label: ">".to_string(),
+            marker: ">".to_string(),
+            accessible: false,
+            truncate: Truncate::default(),
             theme: Arc::new(*DEFAULT_THEME),
             latest_select_run_num: 0,
             pre_selected_watermark: 0,
@@ -124,6 +130,13 @@ impl Selection {
             self.cursor_label = cursor.to_string();
         }
 
+        if let Some(marker) = options.marker {
+            self.marker = marker.to_string();
+        }
+
+        self.accessible = options.accessible;
+        self.truncate = options.truncate;
+
         self.keep_right = options.keep_right;
         self.selector = options.selector.clone();
     }
@@ -158,6 +171,8 @@ impl Selection {
             // if not enough items, scroll the cursor a page down
             self.item_cursor = max(self.items.len(), height) - height;
         }
+
+        self.skip_unselectable(1);
     }
 
     pub fn clear(&mut self) {
@@ -187,7 +202,12 @@ impl Selection {
     // > 0 means move up, < 0 means move down
     pub fn act_move_line_cursor(&mut self, diff: i32) {
         let diff = if self.reverse { -diff } else { diff };
+        self.move_line_cursor(diff);
+        self.skip_unselectable(if diff < 0 { -1 } else { 1 });
+    }
 
+    // > 0 means towards the last item, regardless of the layout
+    fn move_line_cursor(&mut self, diff: i32) {
         let mut line_cursor = self.line_cursor as i32;
         let mut item_cursor = self.item_cursor as i32;
         let item_len = self.items.len() as i32;
@@ -213,6 +233,57 @@ impl Selection {
         self.line_cursor = line_cursor as usize;
     }
 
+    /// Move the cursor to the first item, or the last one with `last`, regardless of the layout
+    pub fn act_move_to_end(&mut self, last: bool) {
+        let cursor = (self.item_cursor + self.line_cursor) as i32;
+        if last {
+            self.move_line_cursor(self.items.len() as i32 - 1 - cursor);
+            self.skip_unselectable(-1);
+        } else {
+            self.move_line_cursor(-cursor);
+            self.skip_unselectable(1);
+        }
+    }
+
+    fn is_selectable_at(&self, index: usize) -> bool {
+        self.items
+            .get(index)
+            .map(|item| item.item.is_selectable())
+            .unwrap_or(true)
+    }
+
+    /// Move the cursor off an item that isn't selectable, to the next selectable one in the
+    /// direction of `step`, or else in the opposite direction
+    fn skip_unselectable(&mut self, step: i32) {
+        let cursor = (self.item_cursor + self.line_cursor) as i32;
+        if self.is_selectable_at(cursor as usize) {
+            return;
+        }
+
+        for step in [step, -step] {
+            let mut index = cursor + step;
+            while index >= 0 && (index as usize) < self.items.len() {
+                if self.is_selectable_at(index as usize) {
+                    self.move_line_cursor(index - cursor);
+                    return;
+                }
+                index += step;
+            }
+        }
+    }
+
+    /// Move the cursor to the item with the given text, keeping its screen row where possible
+    pub fn act_move_cursor_to(&mut self, text: &str) {
+        let index = match self.items.iter().position(|item| item.item.text() == text) {
+            Some(index) => index,
+            None => return,
+        };
+
+        self.line_cursor = min(self.line_cursor, index);
+        self.item_cursor = index - self.line_cursor;
+        self.skip_unselectable(1);
+    }
+
     pub fn act_select_screen_row(&mut self, rows_to_top: usize) {
         let height = self.height.load(Ordering::Relaxed);
         let diff = if self.reverse {
@@ -234,6 +305,9 @@ impl Selection {
             .items
             .get(cursor)
             .unwrap_or_else(|| panic!("model:act_toggle: failed to get item {}", cursor));
+        if !current_item.item.is_selectable() {
+            return;
+        }
         let index = (current_run_num(), current_item.item_idx);
         if !self.selected.contains_key(&index) {
             self.selected.insert(index, current_item.item.clone());
@@ -250,6 +324,9 @@ impl Selection {
 
         let run_num = current_run_num();
         for current_item in self.items.iter() {
+            if !current_item.item.is_selectable() {
+                continue;
+            }
             let index = (run_num, current_item.item_idx);
             if !self.selected.contains_key(&index) {
                 self.selected.insert(index, current_item.item.clone());
@@ -264,7 +341,7 @@ impl Selection {
     }
 
     pub fn act_select_raw_item(&mut self, run_num: u32, item_index: u32, item: Arc<dyn SkimItem>) {
-        if !self.multi_selection {
+        if !self.multi_selection || !item.is_selectable() {
             return;
         }
         self.selected.insert((run_num, item_index), item);
@@ -277,6 +354,9 @@ impl Selection {
 
         let run_num = current_run_num();
         for current_item in self.items.iter() {
+            if !current_item.item.is_selectable() {
+                continue;
+            }
             let item = current_item.item.clone();
             self.selected.insert((run_num, current_item.item_idx), item);
         }
@@ -306,9 +386,10 @@ impl Selection {
                 .items
                 .get(cursor)
                 .unwrap_or_else(|| panic!("model:act_output: failed to get item {}", cursor));
-            let item = current_item.item.clone();
-            item_indices.push(cursor);
-            selected.push(item);
+            if current_item.item.is_selectable() {
+                item_indices.push(cursor);
+                selected.push(current_item.item.clone());
+            }
         }
 
         (item_indices, selected)
@@ -335,6 +416,23 @@ impl Selection {
         self.items.get(item_idx).map(|item| item.item.clone())
     }
 
+    /// The row of the cursor on a screen of `screen_height` rows, `None` when the list is empty
+    pub fn cursor_row(&self, screen_height: usize) -> Option<usize> {
+        if self.items.is_empty() || self.line_cursor >= screen_height {
+            return None;
+        }
+        Some(if self.reverse {
+            self.line_cursor
+        } else {
+            screen_height - 1 - self.line_cursor
+        })
+    }
+
+    /// The width of the cursor label and the selection marker, where the items start
+    pub fn indent(&self) -> usize {
+        self.cursor_label.width() + self.marker.width()
+    }
+
     pub fn get_num_options(&self) -> usize {
         self.items.len()
     }
@@ -345,7 +443,7 @@ impl Selection {
         } else {
             let regex = self.skip_to_pattern.as_ref().unwrap();
             if let Some(mat) = regex.find(text) {
-                text[..mat.start()].width_cjk()
+                text[..mat.start()].width()
             } else {
                 0
             }
@@ -364,6 +462,12 @@ impl EventHandler for Selection {
             EvActDown(diff) => {
                 self.act_move_line_cursor(-*diff);
             }
+            EvActFirst => {
+                self.act_move_to_end(false);
+            }
+            EvActLast => {
+                self.act_move_to_end(true);
+            }
             EvActToggle => {
                 self.act_toggle();
             }
@@ -420,11 +524,17 @@ impl Selection {
         // update item heights
         self.height.store(screen_height, Ordering::Relaxed);
 
-        if screen_width < 3 {
+        // the cursor label and the selection marker are followed by the item
+        let cursor_width = self.cursor_label.width();
+        let marker_width = self.marker.width();
+        let text_col = cursor_width + marker_width;
+        if screen_width <= text_col {
             return Err("screen width is too small".into());
         }
 
-        let default_attr = if is_current {
+        let default_attr = if !matched_item.item.is_selectable() {
+            self.theme.header()
+        } else if is_current {
             self.theme.current()
         } else {
             self.theme.normal()
@@ -439,14 +549,20 @@ impl Selection {
         // print selection cursor
         let index = (current_run_num(), matched_item.item_idx);
         if self.selected.contains_key(&index) {
-            let _ = canvas.print_with_attr(row, 1, ">", default_attr.extend(self.theme.selected()));
+            let _ = canvas.print_with_attr(
+                row,
+                cursor_width,
+                &self.marker,
+                default_attr.extend(self.theme.selected()),
+            );
         } else {
-            let _ = canvas.print_with_attr(row, 1, " ", default_attr);
+            let _ =
+                canvas.print_with_attr(row, cursor_width, &" ".repeat(marker_width), default_attr);
         }
 
         let item = &matched_item.item;
         let item_text = item.text();
-        let container_width = screen_width - 2;
+        let container_width = screen_width - text_col;
 
         let matches = match matched_item.matched_range {
             Some(MatchRange::Chars(ref matched_indices)) => Matches::CharIndices(matched_indices),
@@ -460,6 +576,8 @@ impl Selection {
             matches,
             container_width,
             highlight_attr: matched_attr,
+            accessible: self.accessible,
+            truncate: self.truncate,
         };
 
         let display_content = item.display(context);
@@ -508,7 +626,7 @@ impl Selection {
 
             LinePrinter::builder()
                 .row(row)
-                .col(2)
+                .col(text_col)
                 .tabstop(self.tabstop)
                 .container_width(container_width)
                 .shift(shift)
@@ -518,10 +636,12 @@ impl Selection {
         } else {
             LinePrinter::builder()
                 .row(row)
-                .col(2)
+                .col(text_col)
                 .tabstop(self.tabstop)
                 .container_width(container_width)
-                .text_width(display_content.stripped().width_cjk())
+                // measured like `print_item` draws it, in which ambiguous characters like `…`
+                // take one column
+                .text_width(display_content.stripped().width())
                 .hscroll_offset(self.hscroll_offset)
                 .build()
         };
@@ -556,12 +676,12 @@ impl Draw for Selection {
 
             // print the cursor label
             let label = if line_cursor == self.line_cursor {
-                self.cursor_label.as_str()
+                self.cursor_label.clone()
             } else {
-                " "
+                " ".repeat(self.cursor_label.width())
             };
             let _next_col = canvas
-                .print_with_attr(line_no, 0, label, self.theme.cursor())
+                .print_with_attr(line_no, 0, &label, self.theme.cursor())
                 .unwrap();
 
             let item = self
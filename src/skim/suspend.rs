@@ -0,0 +1,117 @@
+//! Suspending the picker with ctrl-z, or with SIGTSTP sent by another process, like the other
+//! programs of the terminal. The terminal is given back as it was before the process stops, and
+//! raw mode and the whole picker are set up again once it is continued with `fg`, also after a
+//! SIGSTOP, which can't be caught.
+//!
+//! A signal handler can do next to nothing safely, so it only writes the signal to a pipe, which
+//! a thread reads and turns into an event of the picker.
+use crate::skim::event::{Event, EventSender};
+use nix::libc;
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use tuikit::key::Key;
+
+/// The end of the pipe the handler writes to, -1 while no picker listens
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let fd = PIPE.load(Ordering::SeqCst);
+    if fd >= 0 {
+        let byte = signal as u8;
+        unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+    }
+}
+
+fn catch(signal: libc::c_int) {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe { libc::signal(signal, handler) };
+}
+
+/// Turns SIGTSTP and SIGCONT into events of the picker while it lives
+pub struct Listener {
+    write: libc::c_int,
+}
+
+impl Listener {
+    /// Send SIGTSTP as [`Event::EvActSuspend`] and SIGCONT as [`Event::EvResumed`] on `tx`.
+    /// `None` when there is no pipe to be had, and the signals keep doing what they do.
+    pub fn start(tx: EventSender) -> Option<Self> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let [read, write] = fds;
+        let mut pipe = unsafe { File::from_raw_fd(read) };
+        thread::spawn(move || {
+            let mut byte = [0u8];
+            // until the write end is closed
+            while let Ok(1) = pipe.read(&mut byte) {
+                let event = match byte[0] as libc::c_int {
+                    libc::SIGTSTP => Event::EvActSuspend,
+                    _ => Event::EvResumed,
+                };
+                if tx.send((Key::Null, event)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        PIPE.store(write, Ordering::SeqCst);
+        catch(libc::SIGTSTP);
+        catch(libc::SIGCONT);
+        Some(Self { write })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        unsafe {
+            libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            libc::signal(libc::SIGCONT, libc::SIG_DFL);
+        }
+        PIPE.store(-1, Ordering::SeqCst);
+        unsafe { libc::close(self.write) };
+    }
+}
+
+/// Stop the process as SIGTSTP does by default, returning once it is continued. The terminal has
+/// to be given back first.
+pub fn stop() {
+    let listening = PIPE.load(Ordering::SeqCst) >= 0;
+    // the picker is set up again anyway, without waiting for SIGCONT
+    unsafe {
+        libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+        libc::signal(libc::SIGCONT, libc::SIG_DFL);
+        libc::raise(libc::SIGTSTP);
+    }
+    if listening {
+        catch(libc::SIGTSTP);
+        catch(libc::SIGCONT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_listener() {
+        let (tx, rx) = channel();
+        let listener = Listener::start(tx).unwrap();
+        // continuing a running process does nothing else
+        unsafe { libc::raise(libc::SIGCONT) };
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(5)).unwrap(),
+            (Key::Null, Event::EvResumed)
+        );
+
+        drop(listener);
+        assert_eq!(PIPE.load(Ordering::SeqCst), -1);
+        assert!(rx.recv_timeout(Duration::from_secs(5)).is_err());
+    }
+}
@@ -1,5 +1,7 @@
 ///! Handle the color theme
 use crate::skim::options::SkimOptions;
+use std::env;
+use term::terminfo::TermInfo;
 use tuikit::prelude::*;
 
 #[rustfmt::skip]
@@ -18,6 +20,9 @@ lazy_static! {
 /// |> query         |  --> prompt & query
 /// +----------------+
 /// </pre>
+///
+/// The preview has colors of its own, and so do the lines added and removed by the diffs of the
+/// built-in previews, which skim leaves to the preview command to use.
 #[rustfmt::skip]
 #[derive(Copy, Clone, Debug)]
 pub struct ColorTheme {
@@ -43,17 +48,57 @@ pub struct ColorTheme {
     selected:             Color,
     header:               Color,
     border:               Color,
+    preview_fg:           Color,
+    preview_bg:           Color,
+    preview_border:       Color,
+    diff_add:             Color,
+    diff_remove:          Color,
 }
 
 #[rustfmt::skip]
 #[allow(dead_code)]
 impl ColorTheme {
     pub fn init_from_options(options: &SkimOptions) -> ColorTheme {
-        // register
-        if let Some(color) = options.color {
-            ColorTheme::from_options(color)
-        } else {
-            ColorTheme::default()
+        ColorTheme::from_color(options.color)
+    }
+
+    /// The theme of `--color`, e.g. `preview_bg:#fdf6e3,diff_add:28`, in colors the terminal can
+    /// show
+    pub fn from_color(color: Option<&str>) -> ColorTheme {
+        let theme = match color {
+            Some(color) => ColorTheme::from_options(color),
+            None => ColorTheme::default(),
+        };
+        theme.with_depth(ColorDepth::detect())
+    }
+
+    /// Convert the colors to ones the terminal can show
+    fn with_depth(self, depth: ColorDepth) -> Self {
+        let c = |color| depth.convert(color);
+        ColorTheme {
+            fg:               c(self.fg),
+            bg:               c(self.bg),
+            matched:          c(self.matched),
+            matched_bg:       c(self.matched_bg),
+            current:          c(self.current),
+            current_bg:       c(self.current_bg),
+            current_match:    c(self.current_match),
+            current_match_bg: c(self.current_match_bg),
+            query_fg:         c(self.query_fg),
+            query_bg:         c(self.query_bg),
+            spinner:          c(self.spinner),
+            info:             c(self.info),
+            prompt:           c(self.prompt),
+            cursor:           c(self.cursor),
+            selected:         c(self.selected),
+            header:           c(self.header),
+            border:           c(self.border),
+            preview_fg:       c(self.preview_fg),
+            preview_bg:       c(self.preview_bg),
+            preview_border:   c(self.preview_border),
+            diff_add:         c(self.diff_add),
+            diff_remove:      c(self.diff_remove),
+            ..self
         }
     }
     
@@ -81,6 +126,21 @@ impl ColorTheme {
             selected:             Color::Default,
             header:               Color::Default,
             border:               Color::Default,
+            preview_fg:           Color::Default,
+            preview_bg:           Color::Default,
+            preview_border:       Color::Default,
+            diff_add:             Color::Default,
+            diff_remove:          Color::Default,
+        }
+    }
+
+    /// No colors at all: the current line is reversed and matches are underlined instead
+    fn monochrome() -> Self {
+        ColorTheme {
+            current_effect:       Effect::BOLD | Effect::REVERSE,
+            matched_effect:       Effect::UNDERLINE,
+            current_match_effect: Effect::BOLD | Effect::REVERSE | Effect::UNDERLINE,
+            ..ColorTheme::empty()
         }
     }
 
@@ -88,10 +148,18 @@ impl ColorTheme {
     fn from_options(color: &str) -> Self {
         let mut theme = ColorTheme::default();
         for pair in color.split(',') {
+            if pair == "bw" {
+                theme = ColorTheme::monochrome();
+                continue;
+            }
+
             let color: Vec<&str> = pair.split(':').collect();
+            if color.len() < 2 {
+                continue;
+            }
 
             let new_color = if color[1].len() == 7 {
-                // 256 color
+                // #rrggbb, converted later if the terminal lacks true color
                 let r = u8::from_str_radix(&color[1][1..3], 16).unwrap_or(255);
                 let g = u8::from_str_radix(&color[1][3..5], 16).unwrap_or(255);
                 let b = u8::from_str_radix(&color[1][5..7], 16).unwrap_or(255);
@@ -120,6 +188,11 @@ impl ColorTheme {
                 "selected" | "marker"   => theme.selected         = new_color,
                 "header"                => theme.header           = new_color,
                 "border"                => theme.border           = new_color,
+                "preview_fg"            => theme.preview_fg       = new_color,
+                "preview_bg"            => theme.preview_bg       = new_color,
+                "preview_border"        => theme.preview_border   = new_color,
+                "diff_add"              => theme.diff_add         = new_color,
+                "diff_remove"           => theme.diff_remove      = new_color,
                 _ => {}
             }
         }
@@ -221,6 +294,41 @@ impl ColorTheme {
             effect: Effect::empty(),
         }
     }
+
+    /// The text of the preview, in the colors of the list unless it has its own
+    pub fn preview(&self) -> Attr {
+        Attr {
+            fg: unless_default(self.preview_fg, self.fg),
+            bg: unless_default(self.preview_bg, self.bg),
+            effect: Effect::empty(),
+        }
+    }
+
+    /// The border around the preview, `border` unless it has its own color
+    pub fn preview_border(&self) -> Attr {
+        Attr {
+            fg: unless_default(self.preview_border, self.border),
+            ..self.preview()
+        }
+    }
+
+    /// The color of the lines a diff adds, `Color::Default` to leave it to the preview command
+    pub fn diff_add(&self) -> Color {
+        self.diff_add
+    }
+
+    /// The color of the lines a diff removes, `Color::Default` to leave it to the preview command
+    pub fn diff_remove(&self) -> Color {
+        self.diff_remove
+    }
+}
+
+/// `color`, or `fallback` when it is left to the terminal
+fn unless_default(color: Color, fallback: Color) -> Color {
+    match color {
+        Color::Default => fallback,
+        color => color,
+    }
 }
 
 impl Default for ColorTheme {
@@ -237,3 +345,203 @@ impl Default for ColorTheme {
         }
     }
 }
+
+/// How many colors the terminal can show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+/// The 16 basic colors as xterm shows them
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// The levels of each channel in the 6x6x6 color cube of the 256 colors
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The color of a 256 color palette entry
+fn ansi256_rgb(value: u8) -> (u8, u8, u8) {
+    match value {
+        0..=15 => ANSI16_RGB[value as usize],
+        16..=231 => {
+            let index = value - 16;
+            (
+                CUBE_LEVELS[(index / 36) as usize],
+                CUBE_LEVELS[(index / 6 % 6) as usize],
+                CUBE_LEVELS[(index % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (value - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+/// The nearest entry of the color cube or the grayscale ramp of the 256 colors
+fn nearest_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let cube_index = |v: u8| match v {
+        0..=47 => 0,
+        48..=114 => 1,
+        _ => (v - 35) / 40,
+    };
+    let (r, g, b) = rgb;
+    let cube = 16 + 36 * cube_index(r) + 6 * cube_index(g) + cube_index(b);
+
+    let average = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+    let gray = 232 + (average.saturating_sub(3) / 10).min(23);
+
+    if distance(rgb, ansi256_rgb(gray)) < distance(rgb, ansi256_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn nearest_ansi16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16u8)
+        .min_by_key(|&value| distance(rgb, ANSI16_RGB[value as usize]))
+        .unwrap_or(0)
+}
+
+impl ColorDepth {
+    /// True color is announced by `COLORTERM`, otherwise terminfo tells the number of colors
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        let colors = TermInfo::from_env()
+            .ok()
+            .and_then(|info| info.numbers.get("colors").copied());
+        Self::from_env(&colorterm, &term, colors)
+    }
+
+    fn from_env(colorterm: &str, term: &str, colors: Option<u32>) -> Self {
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+
+        match colors {
+            Some(colors) if colors >= 1 << 24 => ColorDepth::TrueColor,
+            Some(colors) if colors >= 256 => ColorDepth::Ansi256,
+            Some(_) => ColorDepth::Ansi16,
+            // without a terminfo entry, trust the name of the terminal
+            None if term.contains("256color") => ColorDepth::Ansi256,
+            None => ColorDepth::Ansi16,
+        }
+    }
+
+    /// The nearest color that can be shown at this depth
+    pub fn convert(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorDepth::Ansi256, Color::Rgb(r, g, b)) => {
+                Color::AnsiValue(nearest_ansi256((r, g, b)))
+            }
+            (ColorDepth::Ansi16, Color::Rgb(r, g, b)) => {
+                Color::AnsiValue(nearest_ansi16((r, g, b)))
+            }
+            (ColorDepth::Ansi16, Color::AnsiValue(value)) if value >= 16 => {
+                Color::AnsiValue(nearest_ansi16(ansi256_rgb(value)))
+            }
+            _ => color,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_depth_from_env() {
+        assert_eq!(
+            ColorDepth::from_env("truecolor", "xterm", Some(8)),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "xterm-256color", Some(256)),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "xterm-direct", Some(1 << 24)),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "xterm", Some(8)),
+            ColorDepth::Ansi16
+        );
+        assert_eq!(
+            ColorDepth::from_env("", "screen-256color", None),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(ColorDepth::from_env("", "", None), ColorDepth::Ansi16);
+    }
+
+    #[test]
+    fn test_preview_colors() {
+        let theme = ColorTheme::from_options("fg:7,border:8");
+        assert_eq!(theme.preview().fg, Color::AnsiValue(7));
+        assert_eq!(theme.preview_border().fg, Color::AnsiValue(8));
+        assert_eq!(theme.diff_add(), Color::Default);
+
+        let theme = ColorTheme::from_options(
+            "fg:7,border:8,preview_fg:0,preview_bg:#fdf6e3,preview_border:4,diff_add:28,diff_remove:1",
+        );
+        assert_eq!(theme.preview().fg, Color::AnsiValue(0));
+        assert_eq!(theme.preview().bg, Color::Rgb(0xfd, 0xf6, 0xe3));
+        assert_eq!(theme.preview_border().fg, Color::AnsiValue(4));
+        assert_eq!(theme.preview_border().bg, Color::Rgb(0xfd, 0xf6, 0xe3));
+        assert_eq!(theme.diff_add(), Color::AnsiValue(28));
+        assert_eq!(theme.diff_remove(), Color::AnsiValue(1));
+        // the list keeps its colors
+        assert_eq!(theme.normal().fg, Color::AnsiValue(7));
+        assert_eq!(theme.border().fg, Color::AnsiValue(8));
+
+        let ansi16 = theme.with_depth(ColorDepth::Ansi16);
+        assert_eq!(ansi16.preview().bg, Color::WHITE);
+        assert_eq!(ansi16.diff_add(), Color::GREEN);
+    }
+
+    #[test]
+    fn test_convert() {
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(ColorDepth::TrueColor.convert(orange), orange);
+        assert_eq!(ColorDepth::Ansi256.convert(orange), Color::AnsiValue(208));
+        assert_eq!(
+            ColorDepth::Ansi256.convert(Color::Rgb(128, 128, 128)),
+            Color::AnsiValue(244)
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.convert(Color::Rgb(250, 10, 10)),
+            Color::LIGHT_RED
+        );
+        assert_eq!(
+            ColorDepth::Ansi16.convert(Color::AnsiValue(21)),
+            Color::BLUE
+        );
+        assert_eq!(ColorDepth::Ansi16.convert(Color::CYAN), Color::CYAN);
+        assert_eq!(ColorDepth::Ansi16.convert(Color::Default), Color::Default);
+    }
+}
@@ -0,0 +1,546 @@
+//! Where the items of the picker come from: the branches, or with `--ls-remote` the branches on
+//! the remotes as they list them, the refs besides them (see [`refs`]),
+//! and the commands registered under `[[sources]]`, which list items of their own, e.g. tags,
+//! worktrees or the heads of stashes, one per line as `<id>\t<display>\t<metadata>`. The items of
+//! a command are checked out on a detached HEAD, like the refs.
+//!
+//! The tabs of the picker other than the branches list the items of a source of their own: the
+//! tags, the stashes, the other worktrees and the branches with an open pull request, see
+//! [`crate::tabs`].
+//!
+//! [`refs`]: crate::refs
+use crate::alternates;
+use crate::config::SourceConfig;
+use crate::meta::RepoMeta;
+use crate::pulls;
+use crate::refs::{self, Special};
+use crate::tabs::Tab;
+use crate::{get_branches, remote, Branch, Listing, RefBranch, RemoteBranch};
+use anyhow::{bail, Context, Result};
+use git2::{BranchType, Repository};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Lists items for the picker
+pub trait ItemSource {
+    /// How errors and warnings tell the source, e.g. `branches`
+    fn name(&self) -> &str;
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>>;
+
+    /// Whether the picker still opens without the items when listing them fails
+    fn optional(&self) -> bool {
+        false
+    }
+}
+
+/// The local branches having an upstream, and the remote branches, or only one kind with `filter`.
+/// With `--unpushed`, the local branches without an upstream too.
+struct Branches {
+    filter: Option<BranchType>,
+    untracked: bool,
+}
+
+impl ItemSource for Branches {
+    fn name(&self) -> &str {
+        "branches"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        get_branches(meta, self.filter, self.untracked)
+    }
+}
+
+/// The branches on every remote, listed over the network like `git ls-remote --heads`, so that
+/// the ones never fetched are there too, e.g. in a single-branch clone. The remotes that can't be
+/// listed are warned about and skipped.
+struct LsRemote;
+
+impl ItemSource for LsRemote {
+    fn name(&self) -> &str {
+        "branches on the remotes"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let repo = meta.repo();
+        let local_names: HashMap<&str, &str> = meta
+            .local_branches()
+            .iter()
+            .filter_map(|branch| Some((branch.upstream.as_deref()?, branch.name.as_str())))
+            .collect();
+
+        let remotes = repo
+            .remotes()
+            .with_context(|| "Failed to get the remotes")?;
+        let mut items = vec![];
+        let mut failure = None;
+        for remote in remotes.iter().flatten() {
+            let heads = match remote::ls_remote(repo, remote) {
+                Ok(heads) => heads,
+                Err(err) => {
+                    eprintln!("warning: skipped the branches of {}: {:#}", remote, err);
+                    failure = Some(err);
+                    continue;
+                }
+            };
+            items.extend(heads.into_iter().map(|(branch, _)| {
+                let name = format!("{}/{}", remote, branch);
+                // the tip as fetched, if it was
+                let tip = repo.refname_to_id(&format!("refs/remotes/{}", name)).ok();
+                let divergence = meta
+                    .base()
+                    .zip(tip)
+                    .and_then(|(base, tip)| meta.divergence(tip, base.oid));
+                Branch::Remote(RemoteBranch {
+                    local_name: local_names.get(name.as_str()).map(|name| name.to_string()),
+                    name,
+                    tip,
+                    divergence,
+                    pull_request: None,
+                    diffstat: None,
+                    signing: None,
+                    details: None,
+                    projection: None,
+                    components: None,
+                    owners: None,
+                    age: None,
+                })
+            }));
+        }
+        match failure {
+            Some(err) if items.is_empty() => Err(err),
+            _ => Ok(items),
+        }
+    }
+}
+
+/// The refs that are not branches, see [`refs`]
+struct Refs<'a> {
+    globs: &'a [String],
+}
+
+/// An item for `refname`, a ref or any other revision pointing to `tip`, shown as `name`
+fn ref_item(
+    meta: &RepoMeta,
+    name: String,
+    refname: String,
+    tip: git2::Oid,
+    note: Option<String>,
+    special: Option<Special>,
+) -> Branch {
+    let divergence = meta.base().and_then(|base| meta.divergence(tip, base.oid));
+    Branch::Ref(RefBranch {
+        name,
+        refname,
+        tip: Some(tip),
+        note,
+        special,
+        divergence,
+        diffstat: None,
+        signing: None,
+        details: None,
+        projection: None,
+        components: None,
+        owners: None,
+        age: None,
+    })
+}
+
+impl ItemSource for Refs<'_> {
+    fn name(&self) -> &str {
+        "refs"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let refs = refs::list(meta.repo(), self.globs);
+        Ok(refs
+            .into_iter()
+            .map(|reference| {
+                ref_item(
+                    meta,
+                    reference.name,
+                    reference.refname,
+                    reference.tip,
+                    None,
+                    None,
+                )
+            })
+            .collect())
+    }
+}
+
+/// The pseudo-refs and the notes refs, with `--special-refs`, see [`refs::special`]
+struct SpecialRefs;
+
+impl ItemSource for SpecialRefs {
+    fn name(&self) -> &str {
+        "special refs"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let refs = refs::special(meta.repo());
+        Ok(refs
+            .into_iter()
+            .map(|special| {
+                let listed = special.listed;
+                ref_item(
+                    meta,
+                    listed.name,
+                    listed.refname,
+                    listed.tip,
+                    special.note,
+                    Some(special.kind),
+                )
+            })
+            .collect())
+    }
+}
+
+/// The tags pointing to commits, with the first line of the message of the annotated ones
+struct Tags;
+
+impl ItemSource for Tags {
+    fn name(&self) -> &str {
+        "tags"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let repo = meta.repo();
+        let names = repo
+            .tag_names(None)
+            .with_context(|| "Failed to get the tags")?;
+        Ok(names
+            .iter()
+            .flatten()
+            .filter_map(|name| {
+                let refname = format!("refs/tags/{}", name);
+                let reference = repo.find_reference(&refname).ok()?;
+                let tip = reference.peel_to_commit().ok()?.id();
+                let note = reference.peel_to_tag().ok().and_then(|tag| {
+                    let message = tag.message()?.lines().next()?.trim().to_string();
+                    (!message.is_empty()).then_some(message)
+                });
+                Some(ref_item(meta, name.to_string(), refname, tip, note, None))
+            })
+            .collect())
+    }
+}
+
+/// The stashes, newest first, as `stash@{n}` with their message
+struct Stashes;
+
+impl ItemSource for Stashes {
+    fn name(&self) -> &str {
+        "stashes"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let repo = meta.repo();
+        if repo.find_reference("refs/stash").is_err() {
+            return Ok(vec![]);
+        }
+        let reflog = repo
+            .reflog("refs/stash")
+            .with_context(|| "Failed to read the stashes")?;
+        Ok(reflog
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let name = format!("stash@{{{}}}", index);
+                let note = entry.message().map(str::to_string);
+                ref_item(meta, name.clone(), name, entry.id_new(), note, None)
+            })
+            .collect())
+    }
+}
+
+/// The worktrees other than this one, by the branch they have checked out, with their path as
+/// the note
+struct Worktrees;
+
+impl Worktrees {
+    fn item(meta: &RepoMeta, path: &Path) -> Option<Branch> {
+        let repo = alternates::open(path).ok()?;
+        let head = repo.head().ok()?;
+        let tip = head.target()?;
+        let (name, refname) = match head.shorthand().filter(|_| head.is_branch()) {
+            Some(branch) => (branch.to_string(), format!("refs/heads/{}", branch)),
+            None => (format!("{:.7}", tip), tip.to_string()),
+        };
+        let note = Some(path.display().to_string());
+        Some(ref_item(meta, name, refname, tip, note, None))
+    }
+}
+
+impl ItemSource for Worktrees {
+    fn name(&self) -> &str {
+        "worktrees"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let repo = meta.repo();
+        let here = repo.workdir().map(crate::context::canonical);
+        let names = repo
+            .worktrees()
+            .with_context(|| "Failed to get the worktrees")?;
+        let linked = names
+            .iter()
+            .flatten()
+            .filter_map(|name| Some(repo.find_worktree(name).ok()?.path().to_path_buf()));
+        // the main worktree, when this is a linked one, whose git dir is
+        // `<main>/.git/worktrees/<name>`
+        let main = repo
+            .is_worktree()
+            .then(|| repo.path().ancestors().nth(3).map(Path::to_path_buf))
+            .flatten();
+        Ok(main
+            .into_iter()
+            .chain(linked)
+            .map(|path: PathBuf| crate::context::canonical(&path))
+            .filter(|path| Some(path) != here.as_ref())
+            .filter_map(|path| Self::item(meta, &path))
+            .collect())
+    }
+}
+
+/// The local and remote branches having an open pull request on GitHub, as cached
+struct Pulls;
+
+impl ItemSource for Pulls {
+    fn name(&self) -> &str {
+        "branches with a pull request"
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let pulls = pulls::cached(meta);
+        Ok(get_branches(meta, None, true)?
+            .into_iter()
+            .filter(|branch| match branch {
+                Branch::Local(branch) => pulls.of_local(&branch.name).is_some(),
+                Branch::Remote(branch) => pulls.of_remote(&branch.name).is_some(),
+                Branch::Ref(_) => false,
+            })
+            .collect())
+    }
+}
+
+/// A command of `[[sources]]`
+struct Extension<'a> {
+    config: &'a SourceConfig,
+}
+
+/// An item listed by a command: the revision to check out, how it is shown, and a note shown
+/// after it. Only the revision is required.
+fn parse_line(line: &str) -> Option<(&str, &str, Option<&str>)> {
+    let mut fields = line.splitn(3, '\t');
+    let id = fields.next()?.trim();
+    if id.is_empty() {
+        return None;
+    }
+    let display = fields
+        .next()
+        .map(str::trim)
+        .filter(|display| !display.is_empty())
+        .unwrap_or(id);
+    let note = fields.next().map(str::trim).filter(|note| !note.is_empty());
+    Some((id, display, note))
+}
+
+/// Run `command` with the shell in the working tree, and return what it printed
+fn run(repo: &Repository, command: &str) -> Result<String> {
+    let dir = repo.workdir().unwrap_or_else(|| repo.path());
+    let output = Command::new("sh")
+        .args(["-c", command])
+        .current_dir(dir)
+        .output()
+        .with_context(|| format!("Failed to run {}", command))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match stderr.trim() {
+            "" => bail!("{} exited with {}", command, output.status),
+            stderr => bail!("{} exited with {}: {}", command, output.status, stderr),
+        }
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl ItemSource for Extension<'_> {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn items(&self, meta: &RepoMeta) -> Result<Vec<Branch>> {
+        let repo = meta.repo();
+        let output = run(repo, &self.config.command)?;
+        // the lines whose revision isn't a commit of the repository can't be checked out
+        let items = output
+            .lines()
+            .filter_map(parse_line)
+            .filter_map(|(id, display, note)| {
+                let tip = repo.revparse_single(id).ok()?.peel_to_commit().ok()?.id();
+                let note = note.map(str::to_string);
+                Some(ref_item(
+                    meta,
+                    display.to_string(),
+                    id.to_string(),
+                    tip,
+                    note,
+                    None,
+                ))
+            });
+        Ok(items.collect())
+    }
+
+    fn optional(&self) -> bool {
+        true
+    }
+}
+
+/// The sources listing the items, the branches first. Only the branches of `filter` are listed
+/// with one. With `--ls-remote`, the remote branches are those the remotes list. The tabs other
+/// than the branches have a source of their own.
+fn sources<'a>(filter: Option<BranchType>, listing: &'a Listing) -> Vec<Box<dyn ItemSource + 'a>> {
+    match listing.tab {
+        Tab::Branches => {}
+        Tab::Tags => return vec![Box::new(Tags)],
+        Tab::Stashes => return vec![Box::new(Stashes)],
+        Tab::Worktrees => return vec![Box::new(Worktrees)],
+        Tab::Pulls => return vec![Box::new(Pulls)],
+    }
+    let mut sources: Vec<Box<dyn ItemSource + 'a>> = vec![];
+    match (listing.ls_remote, filter) {
+        (true, Some(BranchType::Remote)) => {}
+        (true, _) => sources.push(Box::new(Branches {
+            filter: Some(BranchType::Local),
+            untracked: listing.unpushed,
+        })),
+        (false, _) => sources.push(Box::new(Branches {
+            filter,
+            untracked: listing.unpushed,
+        })),
+    }
+    if listing.ls_remote && filter != Some(BranchType::Local) {
+        sources.push(Box::new(LsRemote));
+    }
+    if filter.is_none() {
+        sources.push(Box::new(Refs {
+            globs: &listing.ref_globs,
+        }));
+        if listing.special_refs {
+            sources.push(Box::new(SpecialRefs));
+        }
+        sources.extend(
+            listing
+                .sources
+                .iter()
+                .map(|config| Box::new(Extension { config }) as Box<dyn ItemSource + 'a>),
+        );
+    }
+    sources
+}
+
+/// The items of all the sources, in their order. An optional source that fails is warned about
+/// and skipped.
+pub fn items(
+    meta: &RepoMeta,
+    filter: Option<BranchType>,
+    listing: &Listing,
+) -> Result<Vec<Branch>> {
+    let mut items = vec![];
+    for source in sources(filter, listing) {
+        match source.items(meta) {
+            Ok(listed) => items.extend(listed),
+            Err(err) if source.optional() => {
+                eprintln!("warning: skipped the items of {}: {:#}", source.name(), err)
+            }
+            Err(err) => {
+                return Err(err.context(format!("Failed to get the {}", source.name())));
+            }
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Oid;
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("v1.0\tRelease 1.0\t2 days ago"),
+            Some(("v1.0", "Release 1.0", Some("2 days ago")))
+        );
+        assert_eq!(parse_line("v1.0"), Some(("v1.0", "v1.0", None)));
+        assert_eq!(parse_line("v1.0\t\t"), Some(("v1.0", "v1.0", None)));
+        assert_eq!(
+            parse_line("v1.0\tRelease\tnote\twith a tab"),
+            Some(("v1.0", "Release", Some("note\twith a tab")))
+        );
+        assert_eq!(parse_line("\tRelease"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    /// A commit tagged `v1`
+    fn tagged(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let tip = testing::commit(&repo, Some("HEAD"), "commit", &[], &[]);
+        repo.tag_lightweight("v1", &repo.find_object(tip, None).unwrap(), false)
+            .unwrap();
+        (repo, tip)
+    }
+
+    fn extension(meta: &RepoMeta, command: &str) -> Result<Vec<Branch>> {
+        let config = SourceConfig {
+            name: "tags".to_string(),
+            command: command.to_string(),
+        };
+        Extension { config: &config }.items(meta)
+    }
+
+    #[test]
+    fn test_extension() {
+        let (repo, tip) = tagged("source");
+        let meta = RepoMeta::new(&repo);
+        let listed = extension(
+            &meta,
+            "printf 'v1\\tRelease 1\\tstable\\nnowhere\\tGone\\n'",
+        )
+        .unwrap();
+        assert_eq!(listed.len(), 1);
+        match &listed[0] {
+            Branch::Ref(reference) => {
+                assert_eq!(reference.name, "Release 1");
+                assert_eq!(reference.refname, "v1");
+                assert_eq!(reference.tip, Some(tip));
+                assert_eq!(reference.note.as_deref(), Some("stable"));
+            }
+            item => panic!("expected a ref, got {:?}", item),
+        }
+    }
+
+    #[test]
+    fn test_failing_extension() {
+        let (repo, _) = tagged("source-failing");
+        let meta = RepoMeta::new(&repo);
+        assert!(extension(&meta, "echo oops >&2; exit 3")
+            .unwrap_err()
+            .to_string()
+            .contains("oops"));
+
+        let listing = Listing {
+            sources: vec![SourceConfig {
+                name: "broken".to_string(),
+                command: "exit 1".to_string(),
+            }],
+            ..Listing::default()
+        };
+        // the current branch has no upstream, and a failing command is skipped
+        assert!(items(&meta, None, &listing).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+//! Narrowing the working tree to the directories a branch needs after checking it out, for
+//! monorepos. The directories come from `[[checkout.sparse]]` in the config file, e.g.
+//! `services/payments` for the `payments/*` branches, and are set with `git sparse-checkout` in
+//! cone mode. An entry without directories widens the working tree back to all of it.
+use crate::config::SparseConfig;
+use crate::transaction::Transaction;
+use anyhow::Result;
+use git2::{Config, ConfigLevel, Repository};
+use std::fs;
+
+/// Match `name` against `pattern`, where `*` matches any part of the name, slashes included,
+/// and `?` one character
+fn matches(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(n)) if p == n => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches(&pattern, &name)
+}
+
+/// The directories of the first entry of `config` matching `branch`, `None` when there is none
+fn dirs_for<'a>(config: &'a [SparseConfig], branch: &str) -> Option<&'a [String]> {
+    config
+        .iter()
+        .find(|sparse| matches(&sparse.branches, branch))
+        .map(|sparse| sparse.dirs.as_slice())
+}
+
+/// The configuration of `repo` along with `config.worktree`, where `git sparse-checkout` puts
+/// its settings but which libgit2 doesn't read. It is opened apart from the configuration libgit2
+/// keeps for `repo`, which would get the file added again each time.
+fn worktree_config(repo: &Repository) -> Result<Config, git2::Error> {
+    let mut config = repo.config()?.open_level(ConfigLevel::Local)?;
+    let path = repo.path().join("config.worktree");
+    if path.exists() {
+        config.add_file(&path, ConfigLevel::App, false)?;
+    }
+    Ok(config)
+}
+
+/// Which part of the working tree is checked out
+#[derive(Debug, PartialEq, Eq)]
+enum Sparse {
+    /// All of it
+    Whole,
+    /// The directories of cone mode, sorted
+    Cone(Vec<String>),
+    /// The patterns of non-cone mode
+    Patterns(Vec<String>),
+}
+
+impl Sparse {
+    fn cone(dirs: &[String]) -> Self {
+        if dirs.is_empty() {
+            return Sparse::Whole;
+        }
+        let mut dirs: Vec<String> = dirs
+            .iter()
+            .map(|dir| dir.trim_matches('/').to_string())
+            .collect();
+        dirs.sort();
+        dirs.dedup();
+        Sparse::Cone(dirs)
+    }
+
+    /// How the working tree of `repo` is checked out now
+    fn of(repo: &Repository) -> Self {
+        let config = match worktree_config(repo) {
+            Ok(config) => config,
+            Err(_) => return Sparse::Whole,
+        };
+        if !config.get_bool("core.sparseCheckout").unwrap_or(false) {
+            return Sparse::Whole;
+        }
+        // per worktree, like the sparse-checkout itself
+        let patterns = fs::read_to_string(repo.path().join("info").join("sparse-checkout"))
+            .unwrap_or_default();
+        if config.get_bool("core.sparseCheckoutCone").unwrap_or(false) {
+            Sparse::Cone(cone_dirs(&patterns))
+        } else {
+            Sparse::Patterns(
+                patterns
+                    .lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string)
+                    .collect(),
+            )
+        }
+    }
+
+    /// The git arguments that check out this part of the working tree
+    fn args(&self) -> Vec<String> {
+        let mut args = vec!["sparse-checkout".to_string()];
+        match self {
+            Sparse::Whole => args.push("disable".to_string()),
+            Sparse::Cone(dirs) => {
+                args.extend(["set".to_string(), "--cone".to_string()]);
+                args.extend(dirs.iter().cloned());
+            }
+            Sparse::Patterns(patterns) => {
+                args.extend(["set".to_string(), "--no-cone".to_string()]);
+                args.extend(patterns.iter().cloned());
+            }
+        }
+        args
+    }
+}
+
+/// The directories checked out in full by the patterns git writes in cone mode, e.g.
+/// `services/payments` for `/services/` `!/services/*/` `/services/payments/`. The parents of a
+/// directory are listed too, but only with their files, which a `!/<dir>/*/` line tells.
+fn cone_dirs(patterns: &str) -> Vec<String> {
+    let lines: Vec<&str> = patterns.lines().map(str::trim).collect();
+    let mut dirs: Vec<String> = lines
+        .iter()
+        .filter_map(|line| line.strip_prefix('/')?.strip_suffix('/'))
+        .filter(|dir| !lines.contains(&format!("!/{}/*/", dir).as_str()))
+        .map(str::to_string)
+        .collect();
+    dirs.sort();
+    dirs
+}
+
+/// After checking out a branch, narrow or widen the working tree to the directories `config`
+/// gives for it. Nothing changes for branches that no entry matches, or when HEAD is detached.
+pub fn apply(
+    transaction: &mut Transaction,
+    repo: &Repository,
+    config: &[SparseConfig],
+) -> Result<()> {
+    if config.is_empty() {
+        return Ok(());
+    }
+    let head = match repo.head() {
+        Ok(head) if head.is_branch() => head,
+        _ => return Ok(()),
+    };
+    let branch = head.shorthand().unwrap_or_default();
+    let wanted = match dirs_for(config, branch) {
+        Some(dirs) => Sparse::cone(dirs),
+        None => return Ok(()),
+    };
+    let current = Sparse::of(repo);
+    if wanted == current {
+        return Ok(());
+    }
+
+    let description = match &wanted {
+        Sparse::Cone(dirs) => format!("check out only {}", dirs.join(", ")),
+        _ => "check out the whole working tree".to_string(),
+    };
+    let args = wanted.args();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    transaction.run(&description, &args, vec![current.args()])?;
+    match &wanted {
+        Sparse::Cone(dirs) => eprintln!("Checked out only {}", dirs.join(", ")),
+        _ => eprintln!("Checked out the whole working tree"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("payments/*", "payments/fix-login"));
+        assert!(matches("*/payments/*", "user/alice/payments/refund"));
+        assert!(matches("release-?", "release-2"));
+        assert!(!matches("payments/*", "search/payments"));
+        assert!(!matches("release-?", "release-10"));
+    }
+
+    #[test]
+    fn test_dirs_for() {
+        let config = vec![
+            SparseConfig {
+                branches: "payments/*".to_string(),
+                dirs: vec!["services/payments".to_string()],
+            },
+            SparseConfig {
+                branches: "*".to_string(),
+                dirs: vec![],
+            },
+        ];
+        assert_eq!(
+            dirs_for(&config, "payments/refund"),
+            Some(&["services/payments".to_string()][..])
+        );
+        assert_eq!(dirs_for(&config, "main"), Some(&[][..]));
+        assert_eq!(dirs_for(&config[..1], "main"), None);
+    }
+
+    #[test]
+    fn test_cone_dirs() {
+        let patterns = "/*\n!/*/\n/libs/\n/services/\n!/services/*/\n/services/payments/\n";
+        assert_eq!(cone_dirs(patterns), vec!["libs", "services/payments"]);
+        assert_eq!(cone_dirs("/*\n!/*/\n"), Vec::<String>::new());
+    }
+
+    /// `payments/refund` checked out, with services for payments and search
+    fn services(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        testing::commit(
+            &repo,
+            Some("refs/heads/payments/refund"),
+            "services",
+            &[
+                ("services/payments/main.rs", "fn main() {}\n"),
+                ("services/search/main.rs", "fn main() {}\n"),
+            ],
+            &[],
+        );
+        repo.set_head("refs/heads/payments/refund").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        repo
+    }
+
+    /// Apply `dirs` for the payments branches
+    fn apply_dirs(repo: &TempRepo, dirs: &[&str]) {
+        let config = vec![SparseConfig {
+            branches: "payments/*".to_string(),
+            dirs: dirs.iter().map(|dir| dir.to_string()).collect(),
+        }];
+        let mut transaction = Transaction::begin(repo, repo.dir(), "test");
+        apply(&mut transaction, repo, &config).unwrap();
+        transaction.commit().unwrap();
+    }
+
+    #[test]
+    fn test_apply() {
+        let repo = services("sparse");
+        let dir = repo.dir();
+        apply_dirs(&repo, &["services/payments/"]);
+        assert_eq!(
+            Sparse::of(&repo),
+            Sparse::Cone(vec!["services/payments".to_string()])
+        );
+        assert!(dir.join("services/payments/main.rs").exists());
+        assert!(!dir.join("services/search/main.rs").exists());
+    }
+
+    #[test]
+    fn test_apply_whole() {
+        let repo = services("sparse-whole");
+        apply_dirs(&repo, &["services/payments/"]);
+        apply_dirs(&repo, &[]);
+        assert_eq!(Sparse::of(&repo), Sparse::Whole);
+        assert!(repo.dir().join("services/search/main.rs").exists());
+    }
+}
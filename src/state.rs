@@ -0,0 +1,189 @@
+//! Checks that switching branches is safe in the current state of the repository.
+use crate::error::GibraError;
+use crate::git;
+use crate::headless::{self, Within};
+use crate::prompt;
+use anyhow::{bail, Context, Result};
+use git2::{Repository, RepositoryState};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+/// An operation that moves HEAD around and has to be finished or aborted before switching
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Operation {
+    name: &'static str,
+    abort_args: &'static [&'static str],
+}
+
+fn operation_in_progress(state: RepositoryState) -> Option<Operation> {
+    let (name, abort_args): (_, &[_]) = match state {
+        RepositoryState::Clean => return None,
+        RepositoryState::Merge => ("merge", &["merge", "--abort"]),
+        RepositoryState::Revert | RepositoryState::RevertSequence => {
+            ("revert", &["revert", "--abort"])
+        }
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            ("cherry-pick", &["cherry-pick", "--abort"])
+        }
+        RepositoryState::Bisect => ("bisect", &["bisect", "reset"]),
+        RepositoryState::Rebase
+        | RepositoryState::RebaseInteractive
+        | RepositoryState::RebaseMerge => ("rebase", &["rebase", "--abort"]),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+            ("am", &["am", "--abort"])
+        }
+    };
+
+    Some(Operation { name, abort_args })
+}
+
+/// e.g. `[rebase in progress]`
+fn operation_badge(operation: Operation) -> String {
+    match operation.name {
+        "bisect" => "[bisecting]".to_string(),
+        name => format!("[{} in progress]", name),
+    }
+}
+
+/// e.g. `[3 stashes]`
+fn stash_badge(count: usize) -> Option<String> {
+    match count {
+        0 => None,
+        1 => Some("[1 stash]".to_string()),
+        count => Some(format!("[{} stashes]", count)),
+    }
+}
+
+/// Badges for the picker header about what could get in the way of switching branches: the
+/// stashed changes and the operation in progress, e.g. `[3 stashes] [rebase in progress]`
+pub fn badges(repo: &Repository) -> Vec<String> {
+    // every stash is an entry in the reflog of refs/stash
+    let stashes = repo
+        .reflog("refs/stash")
+        .map(|reflog| reflog.len())
+        .unwrap_or(0);
+
+    stash_badge(stashes)
+        .into_iter()
+        .chain(operation_in_progress(repo.state()).map(operation_badge))
+        .collect()
+}
+
+/// Refuse to go on while a rebase, merge, cherry-pick and the like is in progress, offering to
+/// abort it first. With `force`, only warn about it. Within `git rebase --exec`, aborting isn't
+/// offered, and while git holds the locks of the repository, e.g. running gibra from a hook,
+/// there is nothing to do but wait.
+pub fn ensure_no_operation_in_progress(
+    repo: &Repository,
+    workdir: &Path,
+    force: bool,
+) -> Result<()> {
+    if let Some(lock) = headless::held_lock(repo) {
+        let holder = match Within::current() {
+            Some(within) => format!("gibra runs within {}", within.describe()),
+            None => "another git process is running".to_string(),
+        };
+        bail!(
+            "Cannot switch branches while git holds {}, as {}. Remove it if no git process is left",
+            lock.display(),
+            holder
+        );
+    }
+    let operation = match operation_in_progress(repo.state()) {
+        Some(operation) => operation,
+        None => return Ok(()),
+    };
+    if force {
+        eprintln!(
+            "warning: a {} is in progress, switching branches anyway (--force)",
+            operation.name
+        );
+        return Ok(());
+    }
+
+    if let Some(within @ Within::RebaseExec) = Within::current() {
+        bail!(
+            "Cannot switch branches within {}, the rebase would go on from the other branch. Pass --force to switch anyway",
+            within.describe()
+        );
+    }
+    eprintln!(
+        "A {} is in progress, so switching branches could lose its state.",
+        operation.name
+    );
+    if !prompt::confirm(&format!("Abort the {}?", operation.name), false)? {
+        return Err(GibraError::OperationInProgress {
+            operation: operation.name.to_string(),
+        }
+        .into());
+    }
+
+    let status = git::command(workdir)
+        .args(operation.abort_args)
+        .stdout(io::stderr())
+        .stderr(Stdio::inherit())
+        .status()
+        .with_context(|| format!("Failed to execute {} command", operation.name))?;
+    if !status.success() {
+        bail!("Failed to abort the {}", operation.name);
+    }
+
+    Ok(())
+}
+
+/// The path of another worktree that has `refname` checked out, if any
+pub fn worktree_holding(repo: &Repository, refname: &str) -> Option<PathBuf> {
+    let worktrees = repo.worktrees().ok()?;
+
+    worktrees
+        .iter()
+        .flatten()
+        .filter_map(|name| repo.find_worktree(name).ok())
+        .find(|worktree| {
+            Repository::open_from_worktree(worktree)
+                .and_then(|worktree_repo| {
+                    worktree_repo
+                        .head()
+                        .map(|head| head.name() == Some(refname))
+                })
+                .unwrap_or(false)
+        })
+        .map(|worktree| worktree.path().to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operation_in_progress() {
+        assert_eq!(operation_in_progress(RepositoryState::Clean), None);
+        assert_eq!(
+            operation_in_progress(RepositoryState::RebaseInteractive).map(|op| op.name),
+            Some("rebase")
+        );
+        assert_eq!(
+            operation_in_progress(RepositoryState::CherryPickSequence).map(|op| op.abort_args),
+            Some(&["cherry-pick", "--abort"][..])
+        );
+    }
+
+    #[test]
+    fn test_badges() {
+        assert_eq!(stash_badge(0), None);
+        assert_eq!(stash_badge(1).as_deref(), Some("[1 stash]"));
+        assert_eq!(stash_badge(3).as_deref(), Some("[3 stashes]"));
+        let badge = |state| operation_in_progress(state).map(operation_badge);
+        assert_eq!(
+            badge(RepositoryState::RebaseMerge).as_deref(),
+            Some("[rebase in progress]")
+        );
+        assert_eq!(
+            badge(RepositoryState::Bisect).as_deref(),
+            Some("[bisecting]")
+        );
+    }
+}
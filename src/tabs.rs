@@ -0,0 +1,137 @@
+//! The tabs of the picker that checks out (`--tabs`, or `tabs = true` under `[picker]`): the
+//! branches, the tags, the stashes, the other worktrees and the branches with an open pull
+//! request, each listed by its source (see [`crate::source`]). They are shown in the header, and
+//! switched with tab and shift-tab, or alt-1 to alt-5.
+//!
+//! A tab lists its items when it is shown, so the others cost nothing until they are, and keeps
+//! the query and the item under the cursor it was left with while switching.
+use crate::resume;
+use std::collections::HashMap;
+
+const NEXT_KEY: &str = "tab";
+const PREVIOUS_KEY: &str = "btab";
+const NUMBER_KEYS: [&str; 5] = ["alt-1", "alt-2", "alt-3", "alt-4", "alt-5"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Tab {
+    /// What the picker lists without tabs
+    #[default]
+    Branches,
+    Tags,
+    Stashes,
+    /// The other worktrees, whose path is printed when one is picked
+    Worktrees,
+    /// The branches having an open pull request on GitHub, as cached
+    Pulls,
+}
+
+impl Tab {
+    const ALL: [Tab; 5] = [
+        Tab::Branches,
+        Tab::Tags,
+        Tab::Stashes,
+        Tab::Worktrees,
+        Tab::Pulls,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Tab::Branches => "Branches",
+            Tab::Tags => "Tags",
+            Tab::Stashes => "Stashes",
+            Tab::Worktrees => "Worktrees",
+            Tab::Pulls => "PRs",
+        }
+    }
+
+    /// What the picker shows when the tab lists nothing
+    pub fn empty(self) -> &'static str {
+        match self {
+            Tab::Branches => "No branches",
+            Tab::Tags => "No tags",
+            Tab::Stashes => "No stashes",
+            Tab::Worktrees => "No other worktrees",
+            Tab::Pulls => "No branches with an open pull request — is [issues.github] set up?",
+        }
+    }
+
+    fn index(self) -> usize {
+        Tab::ALL.iter().position(|&tab| tab == self).unwrap_or(0)
+    }
+
+    /// The tab after pressing `key`, `None` when it is no key of the tabs
+    pub fn switched(self, key: &str) -> Option<Tab> {
+        let count = Tab::ALL.len();
+        let index = match key {
+            NEXT_KEY => (self.index() + 1) % count,
+            PREVIOUS_KEY => (self.index() + count - 1) % count,
+            key => NUMBER_KEYS.iter().position(|&number| number == key)?,
+        };
+        Some(Tab::ALL[index])
+    }
+
+    /// The keys to pass to skim's `expect` option
+    pub fn expect_keys() -> impl Iterator<Item = &'static str> {
+        [NEXT_KEY, PREVIOUS_KEY].into_iter().chain(NUMBER_KEYS)
+    }
+
+    /// The line of the header naming the tabs, this one in brackets, e.g. `[Branches]  Tags`
+    pub fn header(self) -> String {
+        Tab::ALL
+            .iter()
+            .map(|&tab| match tab == self {
+                true => format!("[{}]", tab.title()),
+                false => format!(" {} ", tab.title()),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The query and the item under the cursor each tab was left with
+#[derive(Default)]
+pub struct Memory {
+    states: HashMap<Tab, resume::State>,
+}
+
+impl Memory {
+    pub fn leave(&mut self, tab: Tab, state: resume::State) {
+        self.states.insert(tab, state);
+    }
+
+    pub fn state(&self, tab: Tab) -> Option<&resume::State> {
+        self.states.get(&tab)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switched() {
+        assert_eq!(Tab::Branches.switched("tab"), Some(Tab::Tags));
+        assert_eq!(Tab::Pulls.switched("tab"), Some(Tab::Branches));
+        assert_eq!(Tab::Branches.switched("btab"), Some(Tab::Pulls));
+        assert_eq!(Tab::Tags.switched("alt-4"), Some(Tab::Worktrees));
+        assert_eq!(Tab::Tags.switched("alt-6"), None);
+        assert_eq!(Tab::Tags.switched("enter"), None);
+        assert_eq!(Tab::expect_keys().count(), 7);
+
+        assert_eq!(
+            Tab::Stashes.header(),
+            " Branches   Tags  [Stashes]  Worktrees   PRs "
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut memory = Memory::default();
+        assert_eq!(memory.state(Tab::Tags), None);
+        let mut state = resume::State::default();
+        state.query = "v1".to_string();
+        memory.leave(Tab::Tags, state.clone());
+        assert_eq!(memory.state(Tab::Tags), Some(&state));
+        assert_eq!(memory.state(Tab::Branches), None);
+    }
+}
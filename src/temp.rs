@@ -0,0 +1,211 @@
+//! Throwaway worktrees (`--temp`): the picked branch is checked out on a detached HEAD in a new
+//! worktree under the temp directory, named after the repository and the branch, and its path is
+//! printed, so that a branch can be looked at without touching the working tree, e.g. with
+//! `cd "$(gibra --temp)"`.
+//!
+//! `gibra temp` lists them, and `gibra temp --gc` removes the old ones. Those with changes are
+//! kept unless `--force` is given, and those whose directory is gone, e.g. after a reboot, are
+//! pruned.
+use crate::context;
+use crate::git;
+use crate::history;
+use crate::since::Since;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use git2::{Oid, Repository, StatusOptions, Worktree};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The directory of the throwaway worktrees, under the temp directory
+const DIR: &str = "gibra-temp";
+
+fn root() -> PathBuf {
+    context::canonical(&env::temp_dir()).join(DIR)
+}
+
+/// `text` for a directory name, with the characters other than letters, digits, `.` and `_`
+/// replaced by dashes
+fn slug(text: &str) -> String {
+    let slug: String = text
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    slug.trim_matches(|c| c == '-' || c == '.').to_string()
+}
+
+/// A directory under `root` that doesn't exist yet, for `branch` of the repository at `workdir`
+/// at `tip`, e.g. `gibra-feature-login-1a2b3c4`
+fn new_path(root: &Path, workdir: &Path, branch: &str, tip: Oid) -> PathBuf {
+    let repo = workdir
+        .file_name()
+        .map(|name| slug(&name.to_string_lossy()))
+        .unwrap_or_default();
+    let name = format!("{}-{}-{:.7}", repo, slug(branch), tip);
+    (1..)
+        .map(|count| match count {
+            1 => root.join(&name),
+            count => root.join(format!("{}-{}", name, count)),
+        })
+        .find(|path| !path.exists())
+        .expect("a free name")
+}
+
+/// Check out `tip` of `branch` in a new throwaway worktree of the repository at `workdir`, and
+/// return its path
+pub fn create(workdir: &Path, branch: &str, tip: Oid) -> Result<PathBuf> {
+    let root = root();
+    fs::create_dir_all(&root).with_context(|| format!("Failed to create {}", root.display()))?;
+    let path = new_path(&root, workdir, branch, tip);
+    let path_arg = path.to_string_lossy();
+    git::run(
+        workdir,
+        &["worktree", "add", "--detach", &path_arg, &tip.to_string()],
+    )?;
+    Ok(path)
+}
+
+/// A throwaway worktree of the repository
+struct Temp {
+    worktree: Worktree,
+    path: PathBuf,
+    /// When it was created, `None` when its directory is gone
+    created: Option<SystemTime>,
+    /// Whether it has changes, untracked files included, which removing it loses
+    changed: bool,
+}
+
+fn has_changes(worktree: &Worktree) -> bool {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).include_ignored(false);
+    Repository::open_from_worktree(worktree)
+        .ok()
+        .and_then(|repo| Some(!repo.statuses(Some(&mut options)).ok()?.is_empty()))
+        .unwrap_or(false)
+}
+
+/// The worktrees of `repo` under [`root`]
+fn list(repo: &Repository) -> Result<Vec<Temp>> {
+    let root = root();
+    let names = repo
+        .worktrees()
+        .with_context(|| "Failed to get the worktrees")?;
+    Ok(names
+        .iter()
+        .flatten()
+        .filter_map(|name| repo.find_worktree(name).ok())
+        .filter(|worktree| worktree.path().starts_with(&root))
+        .map(|worktree| {
+            let path = worktree.path().to_path_buf();
+            // written once, when the worktree was added
+            let created = fs::metadata(path.join(".git"))
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let changed = created.is_some() && has_changes(&worktree);
+            Temp {
+                worktree,
+                path,
+                created,
+                changed,
+            }
+        })
+        .collect())
+}
+
+/// List the throwaway worktrees of `repo`, or with `gc` remove those created before
+/// `older_than`, also when they have changes with `force`
+pub fn run(
+    repo: &Repository,
+    workdir: &Path,
+    gc: bool,
+    older_than: &Since,
+    force: bool,
+) -> Result<()> {
+    let temps = list(repo)?;
+    if !gc {
+        if temps.is_empty() {
+            eprintln!("No throwaway worktrees, create one with --temp");
+        }
+        let now = Local::now();
+        for temp in &temps {
+            let age = match temp.created {
+                Some(created) => history::relative(DateTime::from(created), now),
+                None => "gone".to_string(),
+            };
+            let changed = if temp.changed { "\tchanged" } else { "" };
+            println!("{}\t{}{}", temp.path.display(), age, changed);
+        }
+        return Ok(());
+    }
+
+    let (mut removed, mut kept) = (0, 0);
+    for temp in temps {
+        let created = match temp.created {
+            Some(created) => created,
+            None => {
+                temp.worktree
+                    .prune(None)
+                    .with_context(|| format!("Failed to prune {}", temp.path.display()))?;
+                eprintln!("Pruned {}, which is gone", temp.path.display());
+                removed += 1;
+                continue;
+            }
+        };
+        if DateTime::<Local>::from(created).timestamp() >= older_than.time() {
+            continue;
+        }
+        if temp.changed && !force {
+            eprintln!(
+                "Kept {}, which has changes (run with --force to remove it anyway)",
+                temp.path.display()
+            );
+            kept += 1;
+            continue;
+        }
+        let path = temp.path.to_string_lossy();
+        let mut args = vec!["worktree", "remove", &path];
+        if force {
+            args.push("--force");
+        }
+        git::run(workdir, &args)?;
+        eprintln!("Removed {}", temp.path.display());
+        removed += 1;
+    }
+    if removed + kept == 0 {
+        eprintln!("No throwaway worktrees to remove");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_new_path() {
+        let dir = TempDir::new("temp");
+        let tip = Oid::from_str("1a2b3c4d5e6f").unwrap();
+
+        let path = new_path(&dir, Path::new("/src/my repo"), "feature/log-in", tip);
+        assert_eq!(path, dir.join("my-repo-feature-log-in-1a2b3c4"));
+        fs::create_dir(&path).unwrap();
+        assert_eq!(
+            new_path(&dir, Path::new("/src/my repo"), "feature/log-in", tip),
+            dir.join("my-repo-feature-log-in-1a2b3c4-2")
+        );
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("../v1.2~rc"), "v1.2-rc");
+    }
+}
@@ -0,0 +1,462 @@
+//! The templates of gibra: the user supplied prompt and header of the picker, the template of
+//! `--print-format`, which may use the placeholders of the branches too, the commands of
+//! `[commands]`, the files of `[templates]` and the names and links made from the config.
+//!
+//! A placeholder is a name in braces, `{branch}`, which may be followed by filters changing its
+//! value, `{branch:upper}` or `{ref:short:upper}`:
+//!
+//! - `short`: the first 7 digits of a SHA, and a refname without `refs/heads/`, `refs/remotes/`
+//!   and the like, as `git for-each-ref` shortens them
+//! - `upper` and `lower`: the value in upper or lower case
+//!
+//! They are replaced in one pass, so that the braces in a value are left alone. Other text in
+//! braces is left as it is, except in `--print-format`, which is parsed strictly.
+use anyhow::{bail, Result};
+use git2::{Oid, Repository};
+
+/// A filter of a placeholder, e.g. `short` in `{sha:short}`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Short,
+    Upper,
+    Lower,
+}
+
+impl Filter {
+    const NAMES: [&'static str; 3] = ["short", "upper", "lower"];
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "short" => Some(Self::Short),
+            "upper" => Some(Self::Upper),
+            "lower" => Some(Self::Lower),
+            _ => None,
+        }
+    }
+
+    fn apply(self, value: &str) -> String {
+        match self {
+            Self::Short if value.len() == 40 && value.chars().all(|ch| ch.is_ascii_hexdigit()) => {
+                value[..7].to_string()
+            }
+            Self::Short => ["refs/heads/", "refs/remotes/", "refs/tags/", "refs/"]
+                .iter()
+                .find_map(|prefix| value.strip_prefix(prefix))
+                .unwrap_or(value)
+                .to_string(),
+            Self::Upper => value.to_uppercase(),
+            Self::Lower => value.to_lowercase(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Placeholder {
+        name: String,
+        filters: Vec<Filter>,
+        /// How it was written, kept when there is no value for it
+        raw: String,
+    },
+}
+
+/// The name and the filters of the placeholder written `{inner}`, `None` when it isn't one
+fn parse_placeholder(inner: &str) -> Option<(String, Vec<Filter>)> {
+    let mut pieces = inner.split(':');
+    let name = pieces.next()?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        return None;
+    }
+    let filters = pieces.map(Filter::parse).collect::<Option<Vec<_>>>()?;
+    Some((name.to_string(), filters))
+}
+
+/// A parsed template
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse `text`, where anything in braces that isn't a placeholder is text
+    pub fn parse(text: &str) -> Self {
+        let mut template = Self { parts: vec![] };
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            literal.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let placeholder = rest
+                .find('}')
+                .and_then(|end| Some((end, parse_placeholder(&rest[1..end])?)));
+            match placeholder {
+                Some((end, (name, filters))) => {
+                    template.push_text(&mut literal);
+                    template.parts.push(Part::Placeholder {
+                        name,
+                        filters,
+                        raw: rest[..=end].to_string(),
+                    });
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    literal.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        literal.push_str(rest);
+        template.push_text(&mut literal);
+        template
+    }
+
+    /// Parse the template of `option` strictly, where only `names` are placeholders. `\t`, `\n`
+    /// and `\\` stand for a tab, a newline and a backslash, and `{{` and `}}` for braces.
+    pub fn parse_strict(text: &str, names: &[&str], option: &str) -> Result<Self> {
+        let mut template = Self { parts: vec![] };
+        let mut literal = String::new();
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => bail!("Unknown escape \\{} in {}", other, option),
+                    None => bail!("{} ends with a lone \\", option),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = match rest.find('}') {
+                        Some(end) => end,
+                        None => bail!("An unclosed {{ in {}, write {{{{ for a brace", option),
+                    };
+                    let inner = &rest[..end];
+                    let name = inner.split(':').next().unwrap_or_default();
+                    if !names.contains(&name) {
+                        bail!(
+                            "Unknown placeholder {{{}}} in {}, the placeholders are {}",
+                            name,
+                            option,
+                            names
+                                .iter()
+                                .map(|name| format!("{{{}}}", name))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        );
+                    }
+                    let filters = match parse_placeholder(inner) {
+                        Some((_, filters)) => filters,
+                        None => bail!(
+                            "Unknown filter in {{{}}} in {}, the filters are {}",
+                            inner,
+                            option,
+                            Filter::NAMES.join(", ")
+                        ),
+                    };
+                    template.push_text(&mut literal);
+                    template.parts.push(Part::Placeholder {
+                        name: name.to_string(),
+                        filters,
+                        raw: format!("{{{}}}", inner),
+                    });
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!("A lone }} in {}, write }}}} for a brace", option),
+                ch => literal.push(ch),
+            }
+        }
+        template.push_text(&mut literal);
+        Ok(template)
+    }
+
+    fn push_text(&mut self, literal: &mut String) {
+        if !literal.is_empty() {
+            self.parts.push(Part::Text(std::mem::take(literal)));
+        }
+    }
+
+    /// The template with the placeholders replaced by their `values`
+    pub fn render(&self, values: &[(&str, String)]) -> String {
+        self.render_with(values, str::to_string)
+    }
+
+    /// The template with the placeholders replaced by their `values`, each passed through
+    /// `escape` once filtered, e.g. to quote them for the shell
+    pub fn render_with(
+        &self,
+        values: &[(&str, String)],
+        escape: impl Fn(&str) -> String,
+    ) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Placeholder { name, filters, raw } => {
+                    match values.iter().find(|(value_name, _)| value_name == name) {
+                        Some((_, value)) => {
+                            let value = filters
+                                .iter()
+                                .fold(value.clone(), |value, filter| filter.apply(&value));
+                            rendered.push_str(&escape(&value));
+                        }
+                        None => rendered.push_str(raw),
+                    }
+                }
+            }
+        }
+        rendered
+    }
+}
+
+/// Replace the placeholders of `values` in `template`, leaving other text untouched
+pub fn expand(template: &str, values: &[(&str, String)]) -> String {
+    Template::parse(template).render(values)
+}
+
+/// The values substituted for `{repo}` and `{branch}`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Placeholders {
+    /// The name of the repository's directory
+    pub repo: String,
+    /// The checked out branch, or `HEAD` when detached
+    pub branch: String,
+}
+
+impl Placeholders {
+    pub fn of(repo: &Repository) -> Self {
+        let root = repo.workdir().unwrap_or_else(|| repo.path());
+        let name = root
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let head = repo.head().ok();
+        let branch = head
+            .as_ref()
+            .and_then(|head| head.shorthand())
+            .unwrap_or("HEAD");
+
+        Self {
+            repo: name,
+            branch: branch.to_string(),
+        }
+    }
+
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        vec![("repo", self.repo.clone()), ("branch", self.branch.clone())]
+    }
+
+    /// Replace `{repo}` and `{branch}` in `template`, leaving other text untouched
+    pub fn expand(&self, template: &str) -> String {
+        expand(template, &self.values())
+    }
+
+    /// The values as environment variables of the hooks, `GIBRA_REPO` and `GIBRA_BRANCH`
+    pub fn env(&self) -> Vec<(String, String)> {
+        let name = Template::parse("GIBRA_{name:upper}");
+        self.values()
+            .into_iter()
+            .map(|(key, value)| (name.render(&[("name", key.to_string())]), value))
+            .collect()
+    }
+}
+
+/// What is known of a branch printed with `--print-format`, unknown values being printed empty
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BranchValues {
+    /// The name it is printed with without `--print-format`, e.g. `origin/main`
+    pub name: String,
+    pub refname: String,
+    /// `local`, `remote` or `ref`
+    pub kind: &'static str,
+    pub upstream: Option<String>,
+    pub tip: Option<Oid>,
+    /// How far it is ahead of and behind its upstream, or `--base`
+    pub divergence: Option<(usize, usize)>,
+}
+
+/// The placeholders of `--print-format`
+const FIELDS: [&str; 10] = [
+    "name",
+    "ref",
+    "kind",
+    "upstream",
+    "sha",
+    "short_sha",
+    "ahead",
+    "behind",
+    "repo",
+    "branch",
+];
+
+impl BranchValues {
+    pub fn values(&self) -> Vec<(&'static str, String)> {
+        let sha = self.tip.map(|tip| tip.to_string()).unwrap_or_default();
+        let (ahead, behind) = match self.divergence {
+            Some((ahead, behind)) => (ahead.to_string(), behind.to_string()),
+            None => Default::default(),
+        };
+        vec![
+            ("name", self.name.clone()),
+            ("ref", self.refname.clone()),
+            ("kind", self.kind.to_string()),
+            ("upstream", self.upstream.clone().unwrap_or_default()),
+            ("short_sha", sha.chars().take(7).collect()),
+            ("sha", sha),
+            ("ahead", ahead),
+            ("behind", behind),
+        ]
+    }
+}
+
+/// The template of `--print-format`, e.g. `{name}\t{upstream}\t{sha}`. `\t`, `\n` and `\\` stand
+/// for a tab, a newline and a backslash, and `{{` and `}}` for braces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Format {
+    template: Template,
+}
+
+impl Format {
+    pub fn parse(template: &str) -> Result<Self> {
+        Ok(Self {
+            template: Template::parse_strict(template, &FIELDS, "--print-format")?,
+        })
+    }
+
+    /// The line printed for `branch`
+    pub fn render(&self, placeholders: &Placeholders, branch: &BranchValues) -> String {
+        let mut values = branch.values();
+        values.extend(placeholders.values());
+        self.template.render(&values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let values = vec![
+            ("branch", "feat/{sha}".to_string()),
+            ("ref", "refs/remotes/origin/feat".to_string()),
+            (
+                "sha",
+                "0123456789abcdef0123456789abcdef01234567".to_string(),
+            ),
+        ];
+        assert_eq!(
+            expand(
+                "{branch:upper} {ref:short} {sha:short} {ref:short:upper}",
+                &values
+            ),
+            "FEAT/{SHA} origin/feat 0123456 ORIGIN/FEAT"
+        );
+        assert_eq!(
+            expand("{other} ${HOME:-/} {branch:nope} {unclosed", &values),
+            "{other} ${HOME:-/} {branch:nope} {unclosed"
+        );
+        assert_eq!(
+            Template::parse("at {sha:short}").render_with(&values, |value| format!("<{}>", value)),
+            "at <0123456>"
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let placeholders = placeholders();
+        assert_eq!(
+            placeholders.expand("{repo} ({branch}) ⎇ "),
+            "gibra (main) ⎇ "
+        );
+        assert_eq!(placeholders.expand("Switch branch"), "Switch branch");
+        assert_eq!(placeholders.expand("{other}"), "{other}");
+        assert_eq!(
+            placeholders.env(),
+            [
+                ("GIBRA_REPO".to_string(), "gibra".to_string()),
+                ("GIBRA_BRANCH".to_string(), "main".to_string()),
+            ]
+        );
+    }
+
+    fn placeholders() -> Placeholders {
+        Placeholders {
+            repo: "gibra".to_string(),
+            branch: "main".to_string(),
+        }
+    }
+
+    fn branch() -> BranchValues {
+        BranchValues {
+            name: "feat/login".to_string(),
+            refname: "refs/heads/feat/login".to_string(),
+            kind: "local",
+            upstream: Some("origin/feat/login".to_string()),
+            tip: Some(Oid::from_str("0123456789abcdef0123456789abcdef01234567").unwrap()),
+            divergence: Some((2, 0)),
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let format = Format::parse(r"{name}\t{upstream}\t{short_sha} +{ahead} -{behind}").unwrap();
+        assert_eq!(
+            format.render(&placeholders(), &branch()),
+            "feat/login\torigin/feat/login\t0123456 +2 -0"
+        );
+        assert_eq!(
+            Format::parse("{upstream}:{sha}")
+                .unwrap()
+                .render(&placeholders(), &BranchValues::default()),
+            ":"
+        );
+    }
+
+    #[test]
+    fn test_format_escapes() {
+        let format = Format::parse(r"{{{repo}}} {branch}\\{ref}\n").unwrap();
+        assert_eq!(
+            format.render(&placeholders(), &BranchValues::default()),
+            "{gibra} main\\\n"
+        );
+    }
+
+    #[test]
+    fn test_format_filters() {
+        assert_eq!(
+            Format::parse("{kind:upper} {name:short}")
+                .unwrap()
+                .render(&placeholders(), &branch()),
+            "LOCAL feat/login"
+        );
+    }
+
+    #[test]
+    fn test_invalid_format() {
+        assert!(Format::parse("{nope}")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown placeholder {nope}"));
+        assert!(Format::parse("{name:nope}")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unknown filter in {name:nope}"));
+        assert!(Format::parse("{name").is_err());
+        assert!(Format::parse("name}").is_err());
+        assert!(Format::parse(r"\x").is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Scratch directories and repositories for the tests, removed once dropped, even when an
+//! assertion panics, and named apart so that tests running at the same time never share one.
+use git2::{Index, IndexEntry, IndexTime, Oid, Repository, Signature, Time};
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Tells apart the directories of one test process
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of its own under the temporary directory, removed with its content once dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// e.g. `gibra-rename-1234-0` for `rename`
+    pub fn new(name: &str) -> TempDir {
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("gibra-{}-{}-{}", name, process::id(), count));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A repository initialized in a [`TempDir`], which it derefs to
+pub struct TempRepo {
+    // dropped before its directory
+    repo: Repository,
+    dir: TempDir,
+}
+
+impl TempRepo {
+    pub fn new(name: &str) -> TempRepo {
+        let dir = TempDir::new(name);
+        let repo = Repository::init(&dir).unwrap();
+        TempRepo { repo, dir }
+    }
+
+    /// The working tree
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Deref for TempRepo {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        &self.repo
+    }
+}
+
+/// Commit `files`, which may be in directories like `docs/index.md`, on top of the tree of the
+/// first of `parents`, by `test`, and update `refname`
+pub fn commit(
+    repo: &Repository,
+    refname: Option<&str>,
+    message: &str,
+    files: &[(&str, &str)],
+    parents: &[Oid],
+) -> Oid {
+    let signature = Signature::now("test", "test@example.com").unwrap();
+    commit_by(repo, refname, &signature, message, files, parents)
+}
+
+/// Like [`commit`] with nothing changed, made at `time`, in seconds since the epoch
+pub fn commit_at(
+    repo: &Repository,
+    refname: Option<&str>,
+    message: &str,
+    time: i64,
+    parents: &[Oid],
+) -> Oid {
+    let signature = Signature::new("test", "test@example.com", &Time::new(time, 0)).unwrap();
+    commit_by(repo, refname, &signature, message, &[], parents)
+}
+
+fn commit_by(
+    repo: &Repository,
+    refname: Option<&str>,
+    signature: &Signature,
+    message: &str,
+    files: &[(&str, &str)],
+    parents: &[Oid],
+) -> Oid {
+    let parents: Vec<_> = parents
+        .iter()
+        .map(|&oid| repo.find_commit(oid).unwrap())
+        .collect();
+    // an index rather than a tree builder, for the files in directories
+    let mut index = Index::new().unwrap();
+    if let Some(parent) = parents.first() {
+        index.read_tree(&parent.tree().unwrap()).unwrap();
+    }
+    for (path, content) in files {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        index
+            .add(&IndexEntry {
+                ctime: IndexTime::new(0, 0),
+                mtime: IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o100644,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: blob,
+                flags: path.len() as u16,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            })
+            .unwrap();
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+    let parents: Vec<_> = parents.iter().collect();
+    repo.commit(refname, signature, signature, message, &tree, &parents)
+        .unwrap()
+}
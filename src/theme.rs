@@ -0,0 +1,99 @@
+//! The colors of the pickers (`--color`, or `color` under `[layout]`) and the border of the
+//! preview (`--border`, or `border` under `[layout]`).
+//!
+//! The colors are skim's, e.g. `fg:252,bg:235`, along with the ones of the preview, `preview_fg`,
+//! `preview_bg` and `preview_border`, and `diff_add` and `diff_remove` for the lines added and
+//! removed in the diffs of the built-in previews, so that they stay readable on a light terminal.
+use crate::config::Border;
+use crate::exec;
+use crate::skim::prelude::*;
+
+/// The colors and the border of the pickers
+#[derive(Clone, Debug, Default)]
+pub struct Theme {
+    pub color: Option<String>,
+    pub border: Option<Border>,
+}
+
+impl Theme {
+    /// Set up `builder` with the colors and the border
+    pub fn apply<'a>(&'a self, builder: &mut SkimOptionsBuilder<'a>) {
+        builder
+            .color(self.color.as_deref())
+            .border(self.border.map(BorderStyle::from));
+    }
+
+    /// The flag passing the colors on to a built-in preview, which gibra runs as another process
+    pub fn preview_flag(&self) -> String {
+        self.color
+            .as_ref()
+            .map(|color| format!(" --color={}", exec::shell_quote(color)))
+            .unwrap_or_default()
+    }
+}
+
+impl From<Border> for BorderStyle {
+    fn from(border: Border) -> Self {
+        match border {
+            Border::Rounded => BorderStyle::Rounded,
+            Border::Sharp => BorderStyle::Sharp,
+            Border::None => BorderStyle::None,
+        }
+    }
+}
+
+/// `color` as git's config takes it, e.g. `28` or `#00af00`
+fn git_color(color: Color) -> Option<String> {
+    match color {
+        Color::AnsiValue(value) => Some(value.to_string()),
+        Color::Rgb(r, g, b) => Some(format!("#{:02x}{:02x}{:02x}", r, g, b)),
+        _ => None,
+    }
+}
+
+/// The options of git coloring the lines its diffs add and remove after `diff_add` and
+/// `diff_remove` of `color`, e.g. `-c color.diff.new=28`. None when `color` has neither.
+pub fn diff_options(color: Option<&str>) -> Vec<String> {
+    let theme = ColorTheme::from_color(color);
+    [
+        ("color.diff.new", theme.diff_add()),
+        ("color.diff.old", theme.diff_remove()),
+    ]
+    .into_iter()
+    .filter_map(|(key, color)| Some(format!("{}={}", key, git_color(color)?)))
+    .flat_map(|option| ["-c".to_string(), option])
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_options() {
+        assert_eq!(git_color(Color::AnsiValue(28)), Some("28".to_string()));
+        assert_eq!(
+            git_color(Color::Rgb(0, 175, 0)),
+            Some("#00af00".to_string())
+        );
+        assert_eq!(git_color(Color::Default), None);
+
+        // basic colors, which every terminal shows as they are
+        assert_eq!(
+            diff_options(Some("fg:7,diff_add:2,diff_remove:9")),
+            ["-c", "color.diff.new=2", "-c", "color.diff.old=9"]
+        );
+        assert_eq!(
+            diff_options(Some("preview_bg:15,diff_remove:1")),
+            ["-c", "color.diff.old=1"]
+        );
+        assert!(diff_options(None).is_empty());
+
+        let theme = Theme {
+            color: Some("preview_bg:#fdf6e3".to_string()),
+            border: None,
+        };
+        assert_eq!(theme.preview_flag(), " --color='preview_bg:#fdf6e3'");
+        assert_eq!(Theme::default().preview_flag(), "");
+    }
+}
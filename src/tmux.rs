@@ -0,0 +1,122 @@
+use crate::exec::shell_quote;
+use anyhow::{bail, Context, Result};
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+pub const DEFAULT_SIZE: &str = "50%";
+
+/// Whether we are running inside a tmux session
+pub fn is_available() -> bool {
+    env::var_os("TMUX").is_some_and(|tmux| !tmux.is_empty())
+}
+
+/// Split a popup size like fzf-tmux's: `80%` for both dimensions, or `80%,60%` for width and
+/// height.
+fn parse_size(size: &str) -> Result<(&str, &str)> {
+    let (width, height) = size.split_once(',').unwrap_or((size, size));
+    for dimension in [width, height] {
+        let number = dimension.strip_suffix('%').unwrap_or(dimension);
+        if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+            bail!("Invalid tmux popup size: {}", size);
+        }
+    }
+
+    Ok((width, height))
+}
+
+/// The arguments to run ourselves with inside the popup, i.e. without `--tmux`. Those after `--`
+/// are passed on as they are.
+fn popup_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut options = true;
+    args.filter(|arg| {
+        if arg == "--" {
+            options = false;
+        }
+        !options || (arg != "--tmux" && !arg.starts_with("--tmux="))
+    })
+    .collect()
+}
+
+/// Run gibra again inside a tmux popup, leaving the current pane untouched.
+///
+/// What the popup prints on stdout is passed back through a temporary file and printed here, so
+/// `cd "$(gibra ws --tmux)"` works as usual. The exit code of the popup is returned.
+pub fn run_in_popup(size: &str) -> Result<i32> {
+    let (width, height) = parse_size(size)?;
+
+    let exe = env::current_exe().with_context(|| "Failed to find the gibra executable")?;
+    let current_dir = env::current_dir().with_context(|| "Failed to get current directory")?;
+
+    // private to the user and of a name that can't be guessed, as others could otherwise prepare
+    // it to read or forge the output; removed once dropped
+    let dir = tempfile::Builder::new()
+        .prefix("gibra-tmux-")
+        .tempdir()
+        .with_context(|| "Failed to create a temporary directory")?;
+    let output_path = dir.path().join("output");
+    let status_path = dir.path().join("status");
+
+    let quote_path = |path: &Path| shell_quote(&path.to_string_lossy());
+    let command = std::iter::once(quote_path(&exe))
+        .chain(
+            popup_args(env::args().skip(1))
+                .iter()
+                .map(|arg| shell_quote(arg)),
+        )
+        .collect::<Vec<_>>()
+        .join(" ");
+    let shell_command = format!(
+        "cd {} && {} > {}; echo $? > {}",
+        quote_path(&current_dir),
+        command,
+        quote_path(&output_path),
+        quote_path(&status_path),
+    );
+
+    let result = Command::new("tmux")
+        .args(["display-popup", "-E", "-w", width, "-h", height, "-d"])
+        .arg(&current_dir)
+        .arg(shell_command)
+        .stdin(Stdio::null())
+        .status()
+        .with_context(|| "Failed to execute tmux")
+        .and_then(|_| {
+            let status = fs::read_to_string(&status_path)
+                .with_context(|| "The tmux popup was closed before gibra finished")?;
+            let output = fs::read_to_string(&output_path).unwrap_or_default();
+            print!("{}", output);
+
+            Ok(status.trim().parse().unwrap_or(1))
+        });
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("80%").unwrap(), ("80%", "80%"));
+        assert_eq!(parse_size("80%,20").unwrap(), ("80%", "20"));
+        assert!(parse_size("big").is_err());
+        assert!(parse_size("%").is_err());
+    }
+
+    #[test]
+    fn test_popup_args() {
+        let args = ["ws", "--tmux=80%", "-r", "--tmux"]
+            .iter()
+            .map(|arg| arg.to_string());
+        assert_eq!(popup_args(args), vec!["ws", "-r"]);
+
+        let args = ["exec", "--tmux", "--", "git", "--tmux=1"]
+            .iter()
+            .map(|arg| arg.to_string());
+        assert_eq!(popup_args(args), vec!["exec", "--", "git", "--tmux=1"]);
+    }
+}
@@ -0,0 +1,266 @@
+//! The TODO and FIXME comments a branch adds, shown above its commits in the preview
+//! (`--preview`), so that unfinished work stands out before a branch is reviewed or deleted.
+//!
+//! The preview runs `gibra preview` for the branch under the cursor, and the picker kills it as
+//! soon as the cursor moves on, which cancels a scan that is no longer needed. Finished scans are
+//! cached by the commits they were made for, so moving back to a branch shows them at once.
+use crate::diffstat;
+use crate::meta::RepoMeta;
+use anyhow::{Context, Result};
+use git2::{DiffFormat, DiffOptions, Oid, Repository};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_FILE_NAME: &str = "todos.toml";
+
+/// The number of scans kept in the cache, the least recent ones are dropped
+const MAX_CACHED_SCANS: usize = 200;
+
+/// The number of comments listed in the preview, the others are only counted
+const MAX_LISTED: usize = 10;
+
+lazy_static! {
+    static ref MARKER: Regex = Regex::new(r"\b(TODO|FIXME)\b").unwrap();
+}
+
+/// A line with a marker that was added by a branch
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Todo {
+    pub path: String,
+    pub line: u32,
+    /// `TODO` or `FIXME`
+    pub marker: String,
+    /// The line from the marker on, e.g. `TODO: handle errors`
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Scan {
+    scanned_at: u64,
+    todos: Vec<Todo>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TodoCache {
+    /// By `<branch commit>..<default branch commit>`
+    scans: HashMap<String, Scan>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("gibra").join(CACHE_FILE_NAME)
+}
+
+fn load_cache(git_dir: &Path) -> TodoCache {
+    fs::read_to_string(cache_path(git_dir))
+        .ok()
+        .and_then(|content| toml::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save_cache(git_dir: &Path, cache: &TodoCache) -> Result<()> {
+    let path = cache_path(git_dir);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let content = toml::to_string(cache).with_context(|| "Failed to serialize TODOs")?;
+
+    // the preview can be killed at any moment, so the cache is replaced rather than rewritten
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&partial, content)
+        .with_context(|| format!("Failed to write {}", partial.display()))?;
+    fs::rename(&partial, &path).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The comments with a marker on the lines that `tip` added since it forked from `base`
+fn scan(repo: &Repository, tip: Oid, base: Oid) -> Result<Vec<Todo>> {
+    let fork_point = repo
+        .merge_base(tip, base)
+        .with_context(|| "Failed to find the merge base")?;
+    let old_tree = repo.find_commit(fork_point)?.tree()?;
+    let new_tree = repo.find_commit(tip)?.tree()?;
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let diff = repo.diff_tree_to_tree(Some(&old_tree), Some(&new_tree), Some(&mut options))?;
+
+    let mut todos = vec![];
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        if line.origin() != '+' {
+            return true;
+        }
+        let content = String::from_utf8_lossy(line.content());
+        if let Some(found) = MARKER.find(&content) {
+            todos.push(Todo {
+                path: delta
+                    .new_file()
+                    .path()
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                line: line.new_lineno().unwrap_or(0),
+                marker: found.as_str().to_string(),
+                text: content[found.start()..].trim_end().to_string(),
+            });
+        }
+        true
+    })?;
+
+    Ok(todos)
+}
+
+/// The comments with a marker that the branch `name` added, local or remote, from the cache when
+/// they were scanned before
+pub fn of_branch(meta: &RepoMeta, name: &str) -> Result<Vec<Todo>> {
+    let repo = meta.repo();
+    let tip = repo
+        .refname_to_id(&format!("refs/heads/{}", name))
+        .or_else(|_| repo.refname_to_id(&format!("refs/remotes/{}", name)))
+        .with_context(|| format!("Failed to find {}", name))?;
+    let base = match diffstat::base_commit(meta) {
+        Some(base) => base,
+        None => return Ok(vec![]),
+    };
+
+    let key = format!("{}..{}", tip, base);
+    let mut cache = load_cache(repo.path());
+    if let Some(scan) = cache.scans.get(&key) {
+        return Ok(scan.todos.clone());
+    }
+
+    let todos = scan(repo, tip, base)?;
+    cache.scans.insert(
+        key,
+        Scan {
+            scanned_at: now(),
+            todos: todos.clone(),
+        },
+    );
+    while cache.scans.len() > MAX_CACHED_SCANS {
+        let oldest = cache
+            .scans
+            .iter()
+            .min_by_key(|(_, scan)| scan.scanned_at)
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => cache.scans.remove(&key),
+            None => break,
+        };
+    }
+    save_cache(repo.path(), &cache)?;
+
+    Ok(todos)
+}
+
+/// The lines shown above the commits, e.g. `2 TODO, 1 FIXME added by this branch` and where they
+/// are. Nothing when the branch added none.
+pub fn summary(todos: &[Todo]) -> Vec<String> {
+    if todos.is_empty() {
+        return vec![];
+    }
+
+    let counts: Vec<String> = ["TODO", "FIXME"]
+        .iter()
+        .map(|&marker| {
+            let count = todos.iter().filter(|todo| todo.marker == marker).count();
+            (count, marker)
+        })
+        .filter(|&(count, _)| count > 0)
+        .map(|(count, marker)| format!("{} {}", count, marker))
+        .collect();
+    let mut lines = vec![format!("{} added by this branch:", counts.join(", "))];
+    lines.extend(
+        todos
+            .iter()
+            .take(MAX_LISTED)
+            .map(|todo| format!("  {}:{}  {}", todo.path, todo.line, todo.text)),
+    );
+    if todos.len() > MAX_LISTED {
+        lines.push(format!("  … and {} more", todos.len() - MAX_LISTED));
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    /// `feature` adding a FIXME and a TODO since it forked from `main`, which added a TODO since
+    fn forked() -> (TempRepo, Oid, Oid) {
+        let repo = TempRepo::new("todos");
+        let base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "base",
+            &[("a", "// TODO: old\n")],
+            &[],
+        );
+        let tip = testing::commit(
+            &repo,
+            Some("refs/heads/feature"),
+            "tip",
+            &[
+                ("a", "// TODO: old\nlet x = 1; // FIXME overflows\n"),
+                ("b", "# TODOS are not markers\n# TODO(me): write b\n"),
+            ],
+            &[base],
+        );
+        let newer_base = testing::commit(
+            &repo,
+            Some("refs/heads/main"),
+            "newer base",
+            &[("c", "TODO\n")],
+            &[base],
+        );
+        (repo, tip, newer_base)
+    }
+
+    fn todos() -> Vec<Todo> {
+        vec![
+            Todo {
+                path: "a".to_string(),
+                line: 2,
+                marker: "FIXME".to_string(),
+                text: "FIXME overflows".to_string(),
+            },
+            Todo {
+                path: "b".to_string(),
+                line: 2,
+                marker: "TODO".to_string(),
+                text: "TODO(me): write b".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_scan() {
+        let (repo, tip, newer_base) = forked();
+        // markers added on main after the fork don't count
+        assert_eq!(scan(&repo, tip, newer_base).unwrap(), todos());
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            summary(&todos()),
+            vec![
+                "1 TODO, 1 FIXME added by this branch:",
+                "  a:2  FIXME overflows",
+                "  b:2  TODO(me): write b",
+            ]
+        );
+        assert!(summary(&[]).is_empty());
+    }
+}
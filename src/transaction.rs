@@ -0,0 +1,424 @@
+//! Multi-step git operations that are rolled back when a step fails.
+//!
+//! Every step and its outcome is written to `.git/gibra/operation.log`, so that a failed
+//! operation can be looked into afterwards.
+use crate::{git, hooks, progress};
+use anyhow::{bail, Context, Result};
+use git2::{Oid, Repository};
+use std::{
+    fs::{self, File},
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const LOG_FILE_NAME: &str = "operation.log";
+
+/// Where HEAD pointed to before the operation
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Head {
+    Branch(String),
+    Detached(Oid),
+}
+
+impl Head {
+    fn of(repo: &Repository) -> Option<Head> {
+        let head = repo.head().ok()?;
+        if head.is_branch() {
+            head.shorthand().map(|name| Head::Branch(name.to_string()))
+        } else {
+            head.target().map(Head::Detached)
+        }
+    }
+
+    fn checkout_args(&self) -> Vec<String> {
+        match self {
+            Head::Branch(name) => vec!["checkout".to_string(), name.clone()],
+            Head::Detached(oid) => vec![
+                "checkout".to_string(),
+                "--detach".to_string(),
+                oid.to_string(),
+            ],
+        }
+    }
+}
+
+/// A step that went through, with the git commands that undo it
+struct Step {
+    description: String,
+    undo: Vec<Vec<String>>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+/// The commit HEAD points to, or zeros when it is unborn, as passed to hooks
+fn head_oid(repo: &Repository) -> Oid {
+    repo.head()
+        .ok()
+        .and_then(|head| head.target())
+        .unwrap_or_else(Oid::zero)
+}
+
+fn stash_id(repo: &Repository) -> Option<Oid> {
+    repo.refname_to_id("refs/stash").ok()
+}
+
+/// Runs the steps of an operation in `workdir`. Unless [`Transaction::commit`] is called, the
+/// steps that went through are undone in reverse order when the transaction is dropped.
+pub struct Transaction<'a> {
+    repo: &'a Repository,
+    workdir: PathBuf,
+    head: Option<Head>,
+    stash: Option<Oid>,
+    stashed: bool,
+    run_hooks: bool,
+    steps: Vec<Step>,
+    log: Option<File>,
+    log_path: PathBuf,
+    done: bool,
+}
+
+impl<'a> Transaction<'a> {
+    /// Record where HEAD and the stash are, and start a new log
+    pub fn begin(repo: &'a Repository, workdir: &Path, name: &str) -> Self {
+        let log_dir = repo.path().join("gibra");
+        let log_path = log_dir.join(LOG_FILE_NAME);
+        let log = fs::create_dir_all(&log_dir)
+            .and_then(|_| File::create(&log_path))
+            .ok();
+
+        let mut transaction = Self {
+            repo,
+            workdir: workdir.to_path_buf(),
+            head: Head::of(repo),
+            stash: stash_id(repo),
+            stashed: false,
+            run_hooks: true,
+            steps: vec![],
+            log,
+            log_path,
+            done: false,
+        };
+        transaction.log(&format!("begin {} (HEAD: {:?})", name, transaction.head));
+        transaction
+    }
+
+    fn log(&mut self, message: &str) {
+        if let Some(log) = &mut self.log {
+            let _ = writeln!(log, "[{}] {}", now(), message);
+        }
+    }
+
+    /// Run none of the hooks of the repository, like `--no-verify`
+    pub fn skip_hooks(&mut self) {
+        self.run_hooks = false;
+        self.log("  hooks are skipped");
+    }
+
+    fn git(&mut self, args: &[String]) -> Result<()> {
+        self.git_with_hooks(args, self.run_hooks)
+    }
+
+    fn git_with_hooks(&mut self, args: &[String], run_hooks: bool) -> Result<()> {
+        let result = if run_hooks {
+            git::run(&self.workdir, args)
+        } else {
+            git::run_without_hooks(&self.workdir, args)
+        };
+        match &result {
+            Ok(()) => self.log(&format!("  git {}: ok", args.join(" "))),
+            Err(err) => self.log(&format!("  {:#}", err)),
+        }
+        result
+    }
+
+    /// Run `git <args>` as a step, which `undo` reverts if a later step fails
+    pub fn run(&mut self, description: &str, args: &[&str], undo: Vec<Vec<String>>) -> Result<()> {
+        self.step(description, args, undo, self.run_hooks)
+    }
+
+    fn step(
+        &mut self,
+        description: &str,
+        args: &[&str],
+        undo: Vec<Vec<String>>,
+        run_hooks: bool,
+    ) -> Result<()> {
+        self.log(&format!("step: {}", description));
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        self.git_with_hooks(&args, run_hooks)
+            .with_context(|| format!("Failed to {}", description))?;
+
+        self.steps.push(Step {
+            description: description.to_string(),
+            undo,
+        });
+        Ok(())
+    }
+
+    /// Check out `args` (e.g. a branch, or `-b` and a new branch), returning to the prior HEAD
+    /// on rollback. A branch created with `-b` is deleted again.
+    ///
+    /// The `post-checkout` hook is run afterwards, with the same arguments as git would pass. As
+    /// with git, the checkout stands when the hook fails.
+    pub fn checkout(&mut self, description: &str, args: &[&str]) -> Result<()> {
+        let previous = head_oid(self.repo);
+        let mut undo = vec![];
+        if let Some(head) = &self.head {
+            undo.push(head.checkout_args());
+        }
+        if let ["-b", name, ..] = args {
+            undo.push(vec![
+                "branch".to_string(),
+                "-D".to_string(),
+                name.to_string(),
+            ]);
+        }
+        // `-B` resets a branch that exists, which is put back where it was
+        if let ["-B", name, ..] = args {
+            if let Ok(tip) = self.repo.refname_to_id(&format!("refs/heads/{}", name)) {
+                undo.push(vec![
+                    "branch".to_string(),
+                    "--force".to_string(),
+                    name.to_string(),
+                    tip.to_string(),
+                ]);
+            }
+        }
+
+        // git doesn't run the hook itself, so that it runs once
+        if io::stderr().is_terminal() {
+            self.checkout_with_progress(description, args, undo)?;
+        } else {
+            let mut checkout_args = vec!["checkout"];
+            checkout_args.extend_from_slice(args);
+            self.step(description, &checkout_args, undo, false)?;
+        }
+
+        if self.run_hooks {
+            let current = head_oid(self.repo);
+            let hook_args = [previous.to_string(), current.to_string(), "1".to_string()];
+            let hook_args: Vec<&str> = hook_args.iter().map(String::as_str).collect();
+            if let Err(err) = hooks::run(self.repo, &self.workdir, "post-checkout", &hook_args) {
+                self.log(&format!("  {:#}", err));
+                eprintln!("warning: {:#}", err);
+            }
+        }
+        Ok(())
+    }
+
+    /// The checkout step of [`Transaction::checkout`], drawing how many files git updated so far.
+    /// When it is cancelled with ctrl-c, the files it updated are put back, see [`progress`].
+    fn checkout_with_progress(
+        &mut self,
+        description: &str,
+        args: &[&str],
+        undo: Vec<Vec<String>>,
+    ) -> Result<()> {
+        self.log(&format!("step: {}", description));
+        let target = progress::target(self.repo, args);
+        let mut checkout_args = vec!["checkout", "--progress"];
+        checkout_args.extend_from_slice(args);
+
+        let interrupt = progress::Interrupt::catch();
+        let mut bar = progress::Bar::default();
+        let result = git::run_with_progress(&self.workdir, &checkout_args, |line| bar.update(line));
+        bar.clear();
+        let cancelled = interrupt.caught();
+        drop(interrupt);
+
+        match result {
+            Ok(()) => self.log(&format!("  git {}: ok", checkout_args.join(" "))),
+            Err(err) if cancelled => {
+                self.log(&format!("  cancelled: {:#}", err));
+                if let Some(target) = target {
+                    let restored = progress::restore(self.repo, target)
+                        .with_context(|| format!("Failed to clean up after {}", description))?;
+                    self.log(&format!("  put {} files back", restored));
+                }
+                bail!("Cancelled, the working tree is as it was before");
+            }
+            Err(err) => {
+                self.log(&format!("  {:#}", err));
+                return Err(err).with_context(|| format!("Failed to {}", description));
+            }
+        }
+
+        self.steps.push(Step {
+            description: description.to_string(),
+            undo,
+        });
+        Ok(())
+    }
+
+    /// Stash local changes, including untracked files. They are put back by
+    /// [`Transaction::commit`], or on rollback.
+    pub fn stash(&mut self) -> Result<()> {
+        self.run(
+            "stash local changes",
+            &[
+                "stash",
+                "push",
+                "--include-untracked",
+                "-m",
+                "gibra: autostash",
+            ],
+            vec![],
+        )?;
+
+        // `git stash` succeeds without creating a stash when there is nothing to stash
+        self.stashed = stash_id(self.repo) != self.stash;
+        self.log(&format!("  stashed: {}", self.stashed));
+        Ok(())
+    }
+
+    fn pop_stash(&mut self) -> Result<()> {
+        self.stashed = false;
+        self.log("step: restore stashed changes");
+        self.git(&["stash".to_string(), "pop".to_string()])
+            .with_context(|| "Failed to restore the stashed changes, they are kept in the stash")
+    }
+
+    /// Keep the changes made by the steps, putting back stashed changes
+    pub fn commit(mut self) -> Result<()> {
+        self.done = true;
+        if self.stashed {
+            self.pop_stash()?;
+        }
+        self.log("commit");
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+        self.log("rollback");
+        while let Some(step) = self.steps.pop() {
+            eprintln!("Rolling back: {}", step.description);
+            for args in step.undo {
+                if let Err(err) = self.git(&args) {
+                    eprintln!("warning: {:#}", err);
+                }
+            }
+        }
+
+        if self.stashed {
+            if let Err(err) = self.pop_stash() {
+                eprintln!("warning: {:#}", err);
+            }
+        }
+        eprintln!(
+            "See {} for the steps that were run",
+            self.log_path.display()
+        );
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.rollback();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    fn init(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.reference("refs/heads/feature", base, false, "test")
+            .unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        repo
+    }
+
+    fn head(repo: &Repository) -> String {
+        repo.head().unwrap().shorthand().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_rollback() {
+        let repo = init("transaction");
+        {
+            let mut transaction = Transaction::begin(&repo, repo.dir(), "test");
+            transaction
+                .checkout("check out new", &["-b", "new"])
+                .unwrap();
+            assert_eq!(head(&repo), "new");
+            assert!(transaction
+                .run("fail", &["checkout", "does-not-exist"], vec![])
+                .is_err());
+        }
+        assert_eq!(head(&repo), "main");
+        assert!(repo.find_branch("new", git2::BranchType::Local).is_err());
+
+        let log = fs::read_to_string(repo.path().join("gibra").join(LOG_FILE_NAME)).unwrap();
+        assert!(log.contains("step: fail"));
+        assert!(log.contains("rollback"));
+    }
+
+    #[test]
+    fn test_commit() {
+        let repo = init("transaction-commit");
+        let mut transaction = Transaction::begin(&repo, repo.dir(), "test");
+        transaction
+            .checkout("check out feature", &["feature"])
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(head(&repo), "feature");
+    }
+
+    /// A `post-checkout` hook appending its arguments to `post-checkout.log`, which is returned
+    #[cfg(unix)]
+    fn hook(repo: &TempRepo) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let hooks_dir = repo.path().join("hooks");
+        fs::create_dir_all(&hooks_dir).unwrap();
+        let hook = hooks_dir.join("post-checkout");
+        fs::write(&hook, "#!/bin/sh\necho \"$@\" >> post-checkout.log\n").unwrap();
+        fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+        repo.dir().join("post-checkout.log")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_checkout_hook() {
+        let repo = init("transaction-hook");
+        let log = hook(&repo);
+        let main = repo.refname_to_id("refs/heads/main").unwrap();
+
+        let mut transaction = Transaction::begin(&repo, repo.dir(), "test");
+        transaction
+            .checkout("check out feature", &["feature"])
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            format!("{} {} 1\n", main, main)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_skip_hooks() {
+        let repo = init("transaction-skip-hooks");
+        let log = hook(&repo);
+
+        let mut transaction = Transaction::begin(&repo, repo.dir(), "test");
+        transaction.skip_hooks();
+        transaction
+            .checkout("check out feature", &["feature"])
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(head(&repo), "feature");
+        assert!(!log.exists());
+    }
+}
@@ -0,0 +1,97 @@
+//! Listing only the local branches having commits that aren't pushed (`--unpushed`): those ahead
+//! of their upstream, and those without one, e.g. to see what still needs pushing before
+//! switching machines. The local branches without an upstream, which are left out otherwise, are
+//! listed then.
+//!
+//! They are compared with their upstream also with `--base`, which only changes what is shown.
+use crate::meta::RepoMeta;
+use crate::{Branch, LocalBranch};
+
+/// Drop the `branches` that have nothing to push, the remote ones and the refs included, and mark
+/// the others
+pub fn apply(meta: &RepoMeta, branches: &mut Vec<Branch>) {
+    let locals = meta.local_branches();
+    branches.retain_mut(|branch| {
+        let branch = match branch {
+            Branch::Local(branch) => branch,
+            _ => return false,
+        };
+        let unpushed = locals.iter().any(|local| {
+            local.name == branch.name
+                && match (&local.upstream, local.divergence) {
+                    (Some(_), Some(divergence)) => divergence.ahead > 0,
+                    // an upstream that is gone is no upstream
+                    _ => true,
+                }
+        });
+        branch.unpushed = unpushed;
+        unpushed
+    });
+}
+
+/// Whether the badge of `branch` tells that it isn't pushed, as it has no upstream whose
+/// divergence would tell it
+pub fn has_badge(branch: &LocalBranch) -> bool {
+    branch.unpushed && branch.remote_name.is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use crate::RemoteBranch;
+
+    #[test]
+    fn test_apply() {
+        let repo = TempRepo::new("unpushed");
+        let root = testing::commit(&repo, None, "root", &[], &[]);
+        let ahead = testing::commit(&repo, None, "ahead", &[], &[root]);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        for (refname, tip) in [
+            ("refs/remotes/origin/pushed", root),
+            ("refs/remotes/origin/ahead", root),
+            ("refs/heads/pushed", root),
+            ("refs/heads/ahead", ahead),
+            ("refs/heads/local", ahead),
+        ] {
+            repo.reference(refname, tip, false, "test").unwrap();
+        }
+        for name in ["pushed", "ahead"] {
+            repo.find_branch(name, git2::BranchType::Local)
+                .unwrap()
+                .set_upstream(Some(&format!("origin/{}", name)))
+                .unwrap();
+        }
+
+        let meta = RepoMeta::new(&repo);
+        let local = |name: &str, upstream: Option<&str>| {
+            Branch::Local(LocalBranch {
+                name: name.to_string(),
+                remote_name: upstream.map(str::to_string),
+                ..LocalBranch::default()
+            })
+        };
+        let mut branches = vec![
+            local("pushed", Some("origin/pushed")),
+            local("ahead", Some("origin/ahead")),
+            local("local", None),
+            Branch::Remote(RemoteBranch {
+                name: "origin/ahead".to_string(),
+                ..RemoteBranch::default()
+            }),
+        ];
+        apply(&meta, &mut branches);
+        let badges: Vec<(String, bool)> = branches
+            .iter()
+            .map(|branch| match branch {
+                Branch::Local(branch) => (branch.name.clone(), has_badge(branch)),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            badges,
+            [("ahead".to_string(), false), ("local".to_string(), true)]
+        );
+    }
+}
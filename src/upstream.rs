@@ -0,0 +1,783 @@
+//! Divergence of local branches from their upstream, fast-forwarding them, and picking a new
+//! upstream.
+use crate::error::GibraError;
+use crate::git;
+use crate::keymap;
+use crate::meta::RepoMeta;
+use crate::remote;
+use crate::skim::{event::Event, prelude::*};
+use crate::state;
+use crate::PickerOptions;
+use anyhow::{bail, Context, Result};
+use git2::{Branch, BranchType, Oid, Repository};
+use std::{collections::BTreeSet, io, path::Path, process::Stdio};
+
+/// How many commits a local branch is ahead of and behind its upstream
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Divergence {
+    pub ahead: usize,
+    pub behind: usize,
+    /// The repository is a shallow clone, so the counts can't be trusted unless both are zero
+    pub shallow: bool,
+    /// Not counted yet, as the time budget was spent, see [`crate::budget`]
+    pub pending: bool,
+}
+
+impl Divergence {
+    /// `None` when the branch has no upstream, or the upstream is gone
+    pub fn of(repo: &Repository, branch: &Branch) -> Option<Divergence> {
+        let local = branch.get().target()?;
+        let upstream = branch.upstream().ok()?.get().target()?;
+        Self::between(repo, local, upstream)
+    }
+
+    /// How many commits `tip` is ahead of and behind `base`
+    pub fn between(repo: &Repository, tip: Oid, base: Oid) -> Option<Divergence> {
+        let (ahead, behind) = repo.graph_ahead_behind(tip, base).ok()?;
+
+        Some(Divergence {
+            ahead,
+            behind,
+            shallow: repo.is_shallow(),
+            pending: false,
+        })
+    }
+
+    /// Left for later once the time budget is spent
+    pub fn pending() -> Divergence {
+        Divergence {
+            pending: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_in_sync(&self) -> bool {
+        !self.pending && self.ahead == 0 && self.behind == 0
+    }
+
+    /// Strictly behind the upstream, so the branch can be fast-forwarded
+    pub fn is_behind_only(&self) -> bool {
+        !self.pending && self.ahead == 0 && self.behind > 0
+    }
+
+    /// e.g. `↑2 ↓3`, `↑? ↓?` in a shallow clone, `…` until counted, or an empty string when in
+    /// sync
+    pub fn label(&self) -> String {
+        if self.pending {
+            return "…".to_string();
+        }
+        if self.shallow && !self.is_in_sync() {
+            return "↑? ↓?".to_string();
+        }
+
+        let mut parts = vec![];
+        if self.ahead > 0 {
+            parts.push(format!("↑{}", self.ahead));
+        }
+        if self.behind > 0 {
+            parts.push(format!("↓{}", self.behind));
+        }
+        parts.join(" ")
+    }
+
+    /// e.g. `2 ahead, 3 behind`, for screen readers that skip the arrows of `label`
+    pub fn describe(&self) -> String {
+        if self.pending {
+            return "ahead and behind not counted yet".to_string();
+        }
+        if self.shallow && !self.is_in_sync() {
+            return "ahead and behind unknown in a shallow clone".to_string();
+        }
+
+        let mut parts = vec![];
+        if self.ahead > 0 {
+            parts.push(format!("{} ahead", self.ahead));
+        }
+        if self.behind > 0 {
+            parts.push(format!("{} behind", self.behind));
+        }
+        parts.join(", ")
+    }
+}
+
+/// The result of fast-forwarding a single branch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FastForward {
+    Updated(usize),
+    UpToDate,
+    Diverged,
+    NoUpstream,
+    CheckedOutElsewhere,
+    /// Not known to be behind, as the history of a shallow clone is incomplete
+    Shallow,
+}
+
+impl FastForward {
+    pub fn describe(&self, name: &str) -> String {
+        match self {
+            FastForward::Updated(commits) => {
+                format!("Fast-forwarded {} by {} commit(s)", name, commits)
+            }
+            FastForward::UpToDate => format!("{} is up to date", name),
+            FastForward::Diverged => format!("{} has diverged from its upstream, skipping", name),
+            FastForward::NoUpstream => format!("{} has no upstream, skipping", name),
+            FastForward::CheckedOutElsewhere => {
+                format!("{} is checked out in another worktree, skipping", name)
+            }
+            FastForward::Shallow => format!(
+                "{} may have diverged, which can't be told in a shallow clone (run with --deepen), skipping",
+                name
+            ),
+        }
+    }
+
+    /// Whether the branch is at its upstream now
+    pub fn is_synced(&self) -> bool {
+        matches!(self, FastForward::Updated(_) | FastForward::UpToDate)
+    }
+
+    pub fn report(&self, name: &str) {
+        eprintln!("{}", self.describe(name));
+    }
+}
+
+/// Fast-forward the local branch `name` to its upstream if it is strictly behind.
+///
+/// The branch ref is updated without touching the working tree. The branch checked out in
+/// `workdir` is the exception: it goes through `git merge --ff-only` so the working tree follows,
+/// and the `post-merge` hook runs unless `skip_hooks` is set.
+pub fn fast_forward(
+    repo: &Repository,
+    workdir: &Path,
+    name: &str,
+    skip_hooks: bool,
+) -> Result<FastForward> {
+    fast_forward_with(repo, workdir, name, skip_hooks, false)
+}
+
+/// Same as [`fast_forward`], but the messages of `git merge` are kept off the terminal
+pub fn fast_forward_quietly(
+    repo: &Repository,
+    workdir: &Path,
+    name: &str,
+    skip_hooks: bool,
+) -> Result<FastForward> {
+    fast_forward_with(repo, workdir, name, skip_hooks, true)
+}
+
+fn fast_forward_with(
+    repo: &Repository,
+    workdir: &Path,
+    name: &str,
+    skip_hooks: bool,
+    quiet: bool,
+) -> Result<FastForward> {
+    let branch = repo
+        .find_branch(name, BranchType::Local)
+        .map_err(|_| GibraError::RefMissing {
+            name: name.to_string(),
+        })?;
+
+    let divergence = match Divergence::of(repo, &branch) {
+        Some(divergence) => divergence,
+        None => return Ok(FastForward::NoUpstream),
+    };
+
+    let upstream = branch
+        .upstream()
+        .with_context(|| format!("Failed to get upstream of {}", name))?;
+
+    if divergence.shallow {
+        // the counts can't be trusted, but an ancestry that was found is certain
+        if divergence.is_in_sync() {
+            return Ok(FastForward::UpToDate);
+        }
+        let is_behind = match (branch.get().target(), upstream.get().target()) {
+            (Some(local), Some(upstream)) => {
+                repo.graph_descendant_of(upstream, local).unwrap_or(false)
+            }
+            _ => false,
+        };
+        if !is_behind {
+            return Ok(FastForward::Shallow);
+        }
+    } else {
+        if divergence.behind == 0 {
+            return Ok(FastForward::UpToDate);
+        }
+        if !divergence.is_behind_only() {
+            return Ok(FastForward::Diverged);
+        }
+    }
+
+    if branch.is_head() {
+        let upstream_name = upstream
+            .name()
+            .ok()
+            .flatten()
+            .with_context(|| format!("Failed to get upstream name of {}", name))?;
+        let no_hooks: &[&str] = if skip_hooks { &git::NO_HOOKS } else { &[] };
+        if quiet {
+            git::run_quietly(
+                workdir,
+                &[no_hooks, &["merge", "--ff-only", upstream_name]].concat(),
+            )?;
+            return Ok(FastForward::Updated(divergence.behind));
+        }
+        let status = git::command(workdir)
+            .args(no_hooks)
+            .args(["merge", "--ff-only", upstream_name])
+            .stdout(io::stderr())
+            .stderr(Stdio::inherit())
+            .status()
+            .with_context(|| "Failed to execute merge command")?;
+        if !status.success() {
+            bail!("Failed to fast-forward {}", name);
+        }
+        return Ok(FastForward::Updated(divergence.behind));
+    }
+
+    let refname = branch
+        .get()
+        .name()
+        .with_context(|| format!("Failed to get ref name of {}", name))?
+        .to_string();
+    if state::worktree_holding(repo, &refname).is_some() {
+        return Ok(FastForward::CheckedOutElsewhere);
+    }
+
+    let target = upstream
+        .get()
+        .target()
+        .with_context(|| format!("Failed to resolve upstream of {}", name))?;
+    let message = format!("gibra: fast-forward to {}", target);
+    branch
+        .into_reference()
+        .set_target(target, &message)
+        .with_context(|| format!("Failed to update {}", name))?;
+
+    Ok(FastForward::Updated(divergence.behind))
+}
+
+/// Fast-forward each of the local branches `names`, going on with the others when one fails and
+/// failing at the end if any did
+pub fn fast_forward_each(
+    repo: &Repository,
+    workdir: &Path,
+    names: &[String],
+    skip_hooks: bool,
+) -> Result<()> {
+    let mut failed = 0;
+    for name in names {
+        match fast_forward(repo, workdir, name, skip_hooks) {
+            Ok(result) => result.report(name),
+            Err(err) => {
+                eprintln!("{:#}", err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("Failed to fast-forward {} branch(es)", failed);
+    }
+
+    Ok(())
+}
+
+/// Fast-forward every local branch that is strictly behind its upstream.
+pub fn fast_forward_all(meta: &RepoMeta, workdir: &Path, skip_hooks: bool) -> Result<()> {
+    let names: Vec<String> = meta
+        .local_branches()
+        .iter()
+        .filter(|branch| {
+            branch.divergence.is_some_and(|divergence| {
+                divergence.is_behind_only() || (divergence.shallow && !divergence.is_in_sync())
+            })
+        })
+        .map(|branch| branch.name.clone())
+        .collect();
+    fast_forward_each(meta.repo(), workdir, &names, skip_hooks)
+}
+
+/// A remote branch that can become the upstream of a local branch
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Candidate {
+    remote: String,
+    branch: String,
+    /// Whether its remote-tracking branch exists locally
+    fetched: bool,
+}
+
+impl Candidate {
+    fn name(&self) -> String {
+        format!("{}/{}", self.remote, self.branch)
+    }
+}
+
+impl SkimItem for Candidate {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Owned(self.name())
+    }
+
+    fn display<'a>(&'a self, context: DisplayContext<'a>) -> AnsiString<'a> {
+        let mut display = AnsiString::from(context);
+        if !self.fetched {
+            let attr = Attr {
+                fg: Color::LIGHT_BLACK,
+                ..Attr::default()
+            };
+            display.append(" (not fetched)", attr);
+        }
+        display
+    }
+}
+
+/// The branches of `remote`, both the fetched ones and the ones only listed by the remote. When
+/// the remote can't be reached, only the fetched ones are returned.
+fn remote_candidates(repo: &Repository, remote: &str) -> Result<Vec<Candidate>> {
+    let prefix = format!("{}/", remote);
+    let mut fetched = BTreeSet::new();
+    for branch in repo
+        .branches(Some(BranchType::Remote))
+        .with_context(|| "Failed to get remote branches")?
+    {
+        let (branch, _) = branch.with_context(|| "Failed to get remote branch")?;
+        if let Ok(Some(name)) = branch.name() {
+            match name.strip_prefix(&prefix) {
+                Some("HEAD") | None => {}
+                Some(name) => {
+                    fetched.insert(name.to_string());
+                }
+            }
+        }
+    }
+
+    let listed = remote::list_heads(repo, remote).unwrap_or_else(|err| {
+        eprintln!("warning: {:#}", err);
+        vec![]
+    });
+
+    let mut names = fetched.clone();
+    names.extend(listed);
+
+    Ok(names
+        .into_iter()
+        .map(|branch| Candidate {
+            remote: remote.to_string(),
+            fetched: fetched.contains(&branch),
+            branch,
+        })
+        .collect())
+}
+
+/// Candidates with the same name as the local branch come first, the rest keep their order
+fn sort_candidates(candidates: &mut [Candidate], local: &str) {
+    candidates.sort_by_key(|candidate| candidate.branch != local);
+}
+
+/// Make `candidate` the upstream of the local branch `local`, fetching it first when its
+/// remote-tracking branch doesn't exist yet.
+fn set_upstream(repo: &Repository, local: &str, candidate: &Candidate) -> Result<()> {
+    if !candidate.fetched {
+        eprintln!("Fetching {}...", candidate.name());
+        remote::fetch_branch(repo, &candidate.remote, &candidate.branch)?;
+    }
+
+    let mut config = repo.config().with_context(|| "Failed to open config")?;
+    config
+        .set_str(&format!("branch.{}.remote", local), &candidate.remote)
+        .with_context(|| format!("Failed to set the remote of {}", local))?;
+    config
+        .set_str(
+            &format!("branch.{}.merge", local),
+            &format!("refs/heads/{}", candidate.branch),
+        )
+        .with_context(|| format!("Failed to set the upstream branch of {}", local))?;
+
+    Ok(())
+}
+
+fn select_candidate(
+    candidates: Vec<Candidate>,
+    local: &str,
+    options: &PickerOptions,
+) -> Result<Candidate> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for candidate in candidates {
+        let _ = tx.send(Arc::new(candidate));
+    }
+    drop(tx);
+
+    let header = format!("Select the upstream of {}", local);
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, options.keys.keymap, vec![]);
+    options.theme.apply(&mut builder);
+    let skim_options = builder
+        .header(Some(&header))
+        .accessible(options.accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    Skim::run_with(&skim_options, Some(rx))
+        .map(|out| match out.final_event {
+            Event::EvActAbort => std::process::exit(130),
+            _ => out.selected_items,
+        })
+        .unwrap_or_default()
+        .first()
+        .and_then(|selected_item| (**selected_item).as_any().downcast_ref::<Candidate>())
+        .cloned()
+        .with_context(|| "Failed to get selected upstream")
+}
+
+/// Pick a branch of any remote, with the ones named like the current branch first, and set it as
+/// the upstream of the current branch.
+pub fn run(meta: &RepoMeta, options: &PickerOptions) -> Result<()> {
+    let repo = meta.repo();
+    let head = repo.head().with_context(|| "Failed to get HEAD")?;
+    if !head.is_branch() {
+        bail!("HEAD is detached, check out a branch first");
+    }
+    let local = head
+        .shorthand()
+        .with_context(|| "Failed to get branch name")?
+        .to_string();
+
+    let mut candidates = vec![];
+    for name in meta.remotes() {
+        candidates.extend(remote_candidates(repo, name)?);
+    }
+    if candidates.is_empty() {
+        bail!("No remote branches found");
+    }
+    sort_candidates(&mut candidates, &local);
+
+    let candidate = select_candidate(candidates, &local, options)?;
+    set_upstream(repo, &local, &candidate)?;
+    eprintln!("{} now tracks {}", local, candidate.name());
+
+    Ok(())
+}
+
+/// Make the same-named branches among `candidates` the upstreams of the local branches `names`,
+/// returning the new upstream or the failure for each branch
+fn track_same_names(
+    repo: &Repository,
+    candidates: &[Candidate],
+    names: &[String],
+) -> Vec<(String, Result<String>)> {
+    names
+        .iter()
+        .map(|name| {
+            let tracked = candidates
+                .iter()
+                .find(|candidate| candidate.branch == *name)
+                .with_context(|| "There is no such branch on the remote")
+                .and_then(|candidate| {
+                    set_upstream(repo, name, candidate).map(|()| candidate.name())
+                });
+            (name.clone(), tracked)
+        })
+        .collect()
+}
+
+/// Make the branches of `remote` named like the local branches `names` their upstreams, e.g.
+/// after the repository moved to a new remote, and sum up how that went for each branch.
+pub fn track_all(repo: &Repository, remote: &str, names: &[String]) -> Result<()> {
+    let candidates = remote_candidates(repo, remote)?;
+    let results = track_same_names(repo, &candidates, names);
+
+    eprintln!();
+    let mut failed = 0;
+    for (name, tracked) in &results {
+        match tracked {
+            Ok(upstream) => eprintln!("  ✓ {} → {}", name, upstream),
+            Err(err) => {
+                failed += 1;
+                eprintln!("  ✗ {}: {:#}", name, err);
+            }
+        }
+    }
+    eprintln!(
+        "Set the upstream of {} of {} branch(es) to {}",
+        results.len() - failed,
+        results.len(),
+        remote
+    );
+    if failed > 0 {
+        bail!("Failed to set the upstream of {} branch(es)", failed);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+
+    #[test]
+    fn test_label() {
+        assert_eq!(Divergence::default().label(), "");
+        assert_eq!(
+            Divergence {
+                ahead: 2,
+                behind: 3,
+                ..Default::default()
+            }
+            .label(),
+            "↑2 ↓3"
+        );
+        assert_eq!(
+            Divergence {
+                ahead: 0,
+                behind: 1,
+                ..Default::default()
+            }
+            .label(),
+            "↓1"
+        );
+    }
+
+    #[test]
+    fn test_label_shallow() {
+        let divergence = Divergence {
+            ahead: 2,
+            behind: 3,
+            shallow: true,
+            pending: false,
+        };
+        assert_eq!(divergence.label(), "↑? ↓?");
+        assert_eq!(
+            divergence.describe(),
+            "ahead and behind unknown in a shallow clone"
+        );
+        let in_sync = Divergence {
+            shallow: true,
+            ..Default::default()
+        };
+        assert_eq!(in_sync.label(), "");
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(Divergence::default().describe(), "");
+        assert_eq!(
+            Divergence {
+                ahead: 2,
+                behind: 3,
+                ..Default::default()
+            }
+            .describe(),
+            "2 ahead, 3 behind"
+        );
+    }
+
+    /// `behind` two commits behind `origin/main`, which it tracks, and `diverged` tracking it too
+    /// with a commit of its own, all forked from `main`
+    fn forked(name: &str) -> TempRepo {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        let newer = testing::commit(
+            &repo,
+            Some("refs/remotes/origin/main"),
+            "newer",
+            &[],
+            &[base],
+        );
+        testing::commit(
+            &repo,
+            Some("refs/remotes/origin/main"),
+            "newest",
+            &[],
+            &[newer],
+        );
+        repo.reference("refs/heads/behind", base, false, "test")
+            .unwrap();
+        testing::commit(&repo, Some("refs/heads/diverged"), "local", &[], &[base]);
+        repo.set_head("refs/heads/main").unwrap();
+
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        for name in ["behind", "diverged"] {
+            repo.find_branch(name, BranchType::Local)
+                .unwrap()
+                .set_upstream(Some("origin/main"))
+                .unwrap();
+        }
+        repo
+    }
+
+    #[test]
+    fn test_between() {
+        let repo = forked("upstream");
+        let local = repo.refname_to_id("refs/heads/diverged").unwrap();
+        let remote = repo.refname_to_id("refs/remotes/origin/main").unwrap();
+        assert_eq!(
+            Divergence::between(&repo, local, remote).map(|divergence| divergence.label()),
+            Some("↑1 ↓2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let repo = forked("fast-forward");
+        let dir = repo.dir();
+        assert_eq!(
+            fast_forward(&repo, dir, "behind", false).unwrap(),
+            FastForward::Updated(2)
+        );
+        assert_eq!(
+            fast_forward(&repo, dir, "behind", false).unwrap(),
+            FastForward::UpToDate
+        );
+    }
+
+    #[test]
+    fn test_fast_forward_diverged() {
+        let repo = forked("fast-forward-diverged");
+        assert_eq!(
+            fast_forward(&repo, repo.dir(), "diverged", false).unwrap(),
+            FastForward::Diverged
+        );
+    }
+
+    #[test]
+    fn test_fast_forward_without_upstream() {
+        let repo = forked("fast-forward-untracked");
+        assert_eq!(
+            fast_forward(&repo, repo.dir(), "main", false).unwrap(),
+            FastForward::NoUpstream
+        );
+    }
+
+    #[test]
+    fn test_fast_forward_missing() {
+        let repo = forked("fast-forward-missing");
+        let err = fast_forward(&repo, repo.dir(), "gone", false).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<GibraError>(),
+            Some(&GibraError::RefMissing {
+                name: "gone".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_fast_forward_each_goes_on_after_a_failure() {
+        let repo = TempRepo::new("fast-forward-each");
+        let dir = repo.dir();
+
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        testing::commit(
+            &repo,
+            Some("refs/remotes/origin/main"),
+            "newer",
+            &[],
+            &[base],
+        );
+        repo.set_head("refs/heads/main").unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/heads/behind", base, false, "test")
+            .unwrap();
+        repo.find_branch("behind", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/main"))
+            .unwrap();
+
+        let names = vec!["gone".to_string(), "behind".to_string()];
+        let err = fast_forward_each(&repo, dir, &names, false).unwrap_err();
+        assert_eq!(err.to_string(), "Failed to fast-forward 1 branch(es)");
+        assert_eq!(
+            repo.refname_to_id("refs/heads/behind").unwrap(),
+            repo.refname_to_id("refs/remotes/origin/main").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sort_candidates() {
+        let candidate = |remote: &str, branch: &str| Candidate {
+            remote: remote.to_string(),
+            branch: branch.to_string(),
+            fetched: true,
+        };
+        let mut candidates = vec![
+            candidate("origin", "main"),
+            candidate("origin", "feature"),
+            candidate("upstream", "feature-2"),
+            candidate("upstream", "feature"),
+        ];
+        sort_candidates(&mut candidates, "feature");
+        let names: Vec<_> = candidates.iter().map(Candidate::name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "origin/feature",
+                "upstream/feature",
+                "origin/main",
+                "upstream/feature-2"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_upstream() {
+        let repo = TempRepo::new("set-upstream");
+
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.reference("refs/remotes/origin/trunk", base, false, "test")
+            .unwrap();
+
+        let candidate = Candidate {
+            remote: "origin".to_string(),
+            branch: "trunk".to_string(),
+            fetched: true,
+        };
+        set_upstream(&repo, "main", &candidate).unwrap();
+
+        let branch = repo.find_branch("main", BranchType::Local).unwrap();
+        let upstream = branch.upstream().unwrap();
+        assert_eq!(upstream.name().unwrap(), Some("origin/trunk"));
+    }
+
+    #[test]
+    fn test_track_same_names() {
+        let repo = TempRepo::new("track-all");
+
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.reference("refs/heads/feature", base, false, "test")
+            .unwrap();
+        repo.reference("refs/heads/local-only", base, false, "test")
+            .unwrap();
+        repo.remote("new", "https://example.com/new.git").unwrap();
+        for name in ["main", "feature"] {
+            repo.reference(&format!("refs/remotes/new/{}", name), base, false, "test")
+                .unwrap();
+        }
+        let candidates: Vec<_> = ["main", "feature"]
+            .iter()
+            .map(|branch| Candidate {
+                remote: "new".to_string(),
+                branch: branch.to_string(),
+                fetched: true,
+            })
+            .collect();
+
+        let names = ["feature".to_string(), "local-only".to_string()];
+        let results = track_same_names(&repo, &candidates, &names);
+        assert_eq!(results[0].1.as_ref().unwrap(), "new/feature");
+        assert!(results[1].1.is_err());
+
+        let branch = repo.find_branch("feature", BranchType::Local).unwrap();
+        assert_eq!(
+            branch.upstream().unwrap().name().unwrap(),
+            Some("new/feature")
+        );
+        let branch = repo.find_branch("local-only", BranchType::Local).unwrap();
+        assert!(branch.upstream().is_err());
+    }
+}
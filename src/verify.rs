@@ -0,0 +1,286 @@
+//! `gibra verify <branch>`: whether a branch exists, locally or on a remote, and where its tip
+//! is, for scripts that used to grep the output of `git branch`. Nothing is drawn: the result is
+//! printed as `key=value` lines, or as JSON with `--json`, and gibra exits with 7 when the branch
+//! doesn't exist.
+//!
+//! A name without a remote, e.g. `feature`, is looked up among the local branches first, then
+//! among the branches of the remotes, e.g. `origin/feature`.
+use crate::remote;
+use crate::upstream::Divergence;
+use anyhow::{bail, Context, Result};
+use git2::{BranchType, Repository};
+use serde::Serialize;
+
+/// What is known of the branch
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Verified {
+    /// As given, e.g. `feature`
+    pub branch: String,
+    pub exists: bool,
+    /// `local` or `remote`
+    pub kind: Option<&'static str>,
+    #[serde(rename = "ref")]
+    pub refname: Option<String>,
+    pub sha: Option<String>,
+    /// The upstream of a local branch, e.g. `origin/feature`
+    pub upstream: Option<String>,
+    /// How far a local branch is ahead of and behind its upstream
+    pub ahead: Option<usize>,
+    pub behind: Option<usize>,
+    /// Whether the branch was fetched, with `--fetch`
+    pub fetched: Option<bool>,
+}
+
+impl Verified {
+    /// The `key=value` lines, empty values included, so that scripts can rely on every key
+    pub fn lines(&self) -> Vec<String> {
+        let text = |value: Option<String>| value.unwrap_or_default();
+        [
+            ("branch", self.branch.clone()),
+            ("exists", self.exists.to_string()),
+            ("kind", text(self.kind.map(str::to_string))),
+            ("ref", text(self.refname.clone())),
+            ("sha", text(self.sha.clone())),
+            ("upstream", text(self.upstream.clone())),
+            ("ahead", text(self.ahead.map(|ahead| ahead.to_string()))),
+            ("behind", text(self.behind.map(|behind| behind.to_string()))),
+            (
+                "fetched",
+                text(self.fetched.map(|fetched| fetched.to_string())),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect()
+    }
+}
+
+/// The remote-tracking branches named `name` on a remote, e.g. `origin/name`
+fn remote_branches(repo: &Repository, name: &str) -> Vec<String> {
+    let remotes = match repo.remotes() {
+        Ok(remotes) => remotes,
+        Err(_) => return vec![],
+    };
+    remotes
+        .iter()
+        .flatten()
+        .map(|remote| format!("{}/{}", remote, name))
+        .filter(|branch| repo.find_branch(branch, BranchType::Remote).is_ok())
+        .collect()
+}
+
+/// Where `name` would be fetched from: the remote it names, e.g. `origin` for `origin/feature`,
+/// the one of the upstream of a local branch, or every remote
+fn fetch_sources(repo: &Repository, name: &str) -> Vec<(String, String)> {
+    let remotes: Vec<String> = repo
+        .remotes()
+        .map(|remotes| remotes.iter().flatten().map(str::to_string).collect())
+        .unwrap_or_default();
+    let named = remotes.iter().find_map(|remote| {
+        let branch = name.strip_prefix(&format!("{}/", remote))?;
+        Some((remote.clone(), branch.to_string()))
+    });
+    if let Some(source) = named {
+        return vec![source];
+    }
+    let upstream = repo
+        .find_branch(name, BranchType::Local)
+        .ok()
+        .and_then(|branch| {
+            let refname = branch.get().name()?.to_string();
+            let remote = repo.branch_upstream_remote(&refname).ok()?;
+            let remote = remote.as_str()?.to_string();
+            let upstream = branch.upstream().ok()?.name().ok()??.to_string();
+            let merge = upstream.strip_prefix(&format!("{}/", remote))?.to_string();
+            Some((remote, merge))
+        });
+    match upstream {
+        Some(source) => vec![source],
+        None => remotes
+            .into_iter()
+            .map(|remote| (remote, name.to_string()))
+            .collect(),
+    }
+}
+
+/// Fetch `name` from where it comes from, see [`fetch_sources`]. Whether it was fetched from at
+/// least one remote.
+fn fetch(repo: &Repository, name: &str) -> bool {
+    let mut fetched = false;
+    for (remote, branch) in fetch_sources(repo, name) {
+        match remote::fetch_branch(repo, &remote, &branch) {
+            Ok(()) => fetched = true,
+            Err(err) => eprintln!("warning: {:#}", err),
+        }
+    }
+    fetched
+}
+
+/// Look `name` up, after fetching it with `fetch`
+pub fn verify(repo: &Repository, name: &str, fetch_first: bool) -> Result<Verified> {
+    let fetched = fetch_first.then(|| fetch(repo, name));
+    let mut verified = Verified {
+        branch: name.to_string(),
+        fetched,
+        ..Verified::default()
+    };
+
+    let (kind, branch) = match repo.find_branch(name, BranchType::Local) {
+        Ok(branch) => ("local", branch),
+        Err(_) => {
+            let remote_name = match repo.find_branch(name, BranchType::Remote) {
+                Ok(_) => name.to_string(),
+                Err(_) => match remote_branches(repo, name).as_slice() {
+                    [] => return Ok(verified),
+                    [only] => only.clone(),
+                    several => bail!(
+                        "{} is on several remotes, verify one of {}",
+                        name,
+                        several.join(", ")
+                    ),
+                },
+            };
+            let branch = repo.find_branch(&remote_name, BranchType::Remote)?;
+            ("remote", branch)
+        }
+    };
+    let reference = branch.get();
+    let tip = reference
+        .peel_to_commit()
+        .with_context(|| format!("Failed to resolve the tip of {}", name))?
+        .id();
+    verified.exists = true;
+    verified.kind = Some(kind);
+    verified.refname = reference.name().map(str::to_string);
+    verified.sha = Some(tip.to_string());
+    if kind == "local" {
+        verified.upstream = branch
+            .upstream()
+            .ok()
+            .and_then(|upstream| upstream.name().ok().flatten().map(str::to_string));
+        if let Some(divergence) = Divergence::of(repo, &branch) {
+            verified.ahead = Some(divergence.ahead);
+            verified.behind = Some(divergence.behind);
+        }
+    }
+    Ok(verified)
+}
+
+/// Print what `verify` found out about `name`. Whether the branch exists.
+pub fn run(repo: &Repository, name: &str, fetch_first: bool, json: bool) -> Result<bool> {
+    let verified = verify(repo, name, fetch_first)?;
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&verified)?),
+        false => println!("{}", verified.lines().join("\n")),
+    }
+    Ok(verified.exists)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Oid;
+
+    /// `feature` a commit ahead of `origin/feature`, which it tracks, a branch only on `origin`,
+    /// and one on both `origin` and `fork`. Returns the commits of `origin` and of `feature`.
+    fn branches(name: &str) -> (TempRepo, Oid, Oid) {
+        let repo = TempRepo::new(name);
+        let root = testing::commit(&repo, None, "root", &[], &[]);
+        let local = testing::commit(&repo, None, "local", &[], &[root]);
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        repo.remote("fork", "https://example.com/fork.git").unwrap();
+        for (refname, tip) in [
+            ("refs/heads/feature", local),
+            ("refs/remotes/origin/feature", root),
+            ("refs/remotes/origin/only-remote", root),
+            ("refs/remotes/origin/both", root),
+            ("refs/remotes/fork/both", root),
+        ] {
+            repo.reference(refname, tip, false, "test").unwrap();
+        }
+        repo.find_branch("feature", BranchType::Local)
+            .unwrap()
+            .set_upstream(Some("origin/feature"))
+            .unwrap();
+        (repo, root, local)
+    }
+
+    #[test]
+    fn test_verify_local() {
+        let (repo, _, local) = branches("verify");
+        assert_eq!(
+            verify(&repo, "feature", false).unwrap().lines(),
+            [
+                "branch=feature".to_string(),
+                "exists=true".to_string(),
+                "kind=local".to_string(),
+                "ref=refs/heads/feature".to_string(),
+                format!("sha={}", local),
+                "upstream=origin/feature".to_string(),
+                "ahead=1".to_string(),
+                "behind=0".to_string(),
+                "fetched=".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_remote() {
+        let (repo, root, _) = branches("verify-remote");
+        let remote = verify(&repo, "only-remote", false).unwrap();
+        assert_eq!(remote.kind, Some("remote"));
+        assert_eq!(
+            remote.refname.as_deref(),
+            Some("refs/remotes/origin/only-remote")
+        );
+        assert_eq!(remote.upstream, None);
+        assert_eq!(
+            verify(&repo, "origin/feature", false).unwrap().sha,
+            Some(root.to_string())
+        );
+    }
+
+    #[test]
+    fn test_verify_missing() {
+        let (repo, _, _) = branches("verify-missing");
+        let missing = verify(&repo, "missing", false).unwrap();
+        assert!(!missing.exists);
+        assert_eq!(
+            serde_json::to_value(&missing).unwrap(),
+            serde_json::json!({
+                "branch": "missing",
+                "exists": false,
+                "kind": null,
+                "ref": null,
+                "sha": null,
+                "upstream": null,
+                "ahead": null,
+                "behind": null,
+                "fetched": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_verify_ambiguous() {
+        let (repo, _, _) = branches("verify-ambiguous");
+        let ambiguous = verify(&repo, "both", false).unwrap_err().to_string();
+        assert!(ambiguous.contains("origin/both") && ambiguous.contains("fork/both"));
+    }
+
+    #[test]
+    fn test_fetch_sources() {
+        let (repo, _, _) = branches("verify-sources");
+        assert_eq!(
+            fetch_sources(&repo, "fork/both"),
+            [("fork".to_string(), "both".to_string())]
+        );
+        assert_eq!(
+            fetch_sources(&repo, "feature"),
+            [("origin".to_string(), "feature".to_string())]
+        );
+        assert_eq!(fetch_sources(&repo, "new").len(), 2);
+    }
+}
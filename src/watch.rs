@@ -0,0 +1,190 @@
+use crate::age;
+use crate::alternates;
+use crate::config::GitflowConfig;
+use crate::empty::{self, Widen};
+use crate::fields::Fields;
+use crate::skim::prelude::*;
+use crate::tabs::Tab;
+use crate::{diffstat, list_branches, picker_items, repo_meta, signing, Listing, SortOrder};
+use crossbeam::channel::{select, Sender};
+use git2::{BranchType, Repository};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::atomic::AtomicUsize,
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_INTERVAL: &str = "2";
+
+/// A hash of every ref and where it points to, so that a change to any branch can be noticed
+/// without watching the files under `.git/refs` and `.git/packed-refs`.
+fn refs_fingerprint(repo: &Repository) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    for reference in repo.references().ok()?.flatten() {
+        reference.name_bytes().hash(&mut hasher);
+        reference.target().hash(&mut hasher);
+        reference.symbolic_target_bytes().hash(&mut hasher);
+    }
+    if let Ok(head) = repo.find_reference("HEAD") {
+        head.symbolic_target_bytes().hash(&mut hasher);
+    }
+
+    Some(hasher.finish())
+}
+
+/// Polls the refs of a repository in the background, and sends on `reload` at most once per
+/// interval when they changed. Polling stops when the watcher is dropped.
+pub struct Watcher {
+    _stop: Sender<()>,
+}
+
+impl Watcher {
+    pub fn spawn(git_dir: &Path, interval: Duration, reload: Sender<()>) -> Self {
+        let (tx_stop, rx_stop) = unbounded::<()>();
+        let git_dir = git_dir.to_path_buf();
+
+        thread::spawn(move || {
+            let fingerprint = |git_dir: &Path| {
+                alternates::open(git_dir)
+                    .ok()
+                    .and_then(|repo| refs_fingerprint(&repo))
+            };
+
+            let mut last = fingerprint(&git_dir);
+            loop {
+                select! {
+                    recv(rx_stop) -> _ => break,
+                    default(interval) => {
+                        let current = fingerprint(&git_dir);
+                        if current != last {
+                            last = current;
+                            if reload.send(()).is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { _stop: tx_stop }
+    }
+}
+
+/// Lists the branches again each time the picker reloads its items
+pub struct BranchCollector {
+    pub git_dir: PathBuf,
+    pub branch_filter: Option<BranchType>,
+    pub remote: Option<String>,
+    pub sort: SortOrder,
+    pub gitflow: Option<GitflowConfig>,
+    pub size: bool,
+    pub signatures: bool,
+    pub age: Option<age::View>,
+    pub fields: Fields,
+    pub listing: Listing,
+    pub base: Option<String>,
+}
+
+impl CommandCollector for BranchCollector {
+    fn invoke(
+        &mut self,
+        _cmd: &str,
+        _components_to_stop: Arc<AtomicUsize>,
+    ) -> (SkimItemReceiver, Sender<i32>) {
+        let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+        let items = alternates::open(&self.git_dir)
+            .map_err(anyhow::Error::from)
+            .and_then(|repo| {
+                let meta = repo_meta(&repo, self.base.as_deref())?;
+                list_branches(
+                    &meta,
+                    self.branch_filter,
+                    self.remote.as_deref(),
+                    self.sort,
+                    &self.fields,
+                    &self.listing,
+                )
+                .map(|mut branches| {
+                    if branches.is_empty() && self.listing.tab != Tab::Branches {
+                        return empty::notice(self.listing.tab.empty());
+                    }
+                    if branches.is_empty() {
+                        let has_remotes = !meta.remotes().is_empty();
+                        let widen = Widen::offered(self.branch_filter, has_remotes);
+                        return empty::items(
+                            self.branch_filter,
+                            self.remote.as_deref(),
+                            has_remotes,
+                            &widen,
+                        );
+                    }
+                    if self.size {
+                        diffstat::attach_cached(&meta, &mut branches);
+                    }
+                    if self.signatures {
+                        signing::attach_cached(&meta, &mut branches);
+                    }
+                    if let Some(view) = &self.age {
+                        age::attach(&repo, view, &mut branches);
+                    }
+                    picker_items(&repo, branches, self.sort, self.gitflow.as_ref())
+                })
+            });
+        match items {
+            Ok(items) => {
+                for item in items {
+                    let _ = tx_item.send(item);
+                }
+            }
+            Err(err) => warn!("Failed to reload branches: {:#}", err),
+        }
+
+        // the branches are listed up front, so there is nothing to interrupt
+        let (tx_interrupt, _) = unbounded();
+        (rx_item, tx_interrupt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TempRepo};
+    use git2::Oid;
+
+    /// `main` checked out, and the commit it is at
+    fn checked_out(name: &str) -> (TempRepo, Oid) {
+        let repo = TempRepo::new(name);
+        let base = testing::commit(&repo, Some("refs/heads/main"), "base", &[], &[]);
+        repo.set_head("refs/heads/main").unwrap();
+        (repo, base)
+    }
+
+    #[test]
+    fn test_refs_fingerprint_unchanged() {
+        let (repo, _) = checked_out("watch");
+        assert_eq!(refs_fingerprint(&repo), refs_fingerprint(&repo));
+    }
+
+    #[test]
+    fn test_refs_fingerprint_of_fetch() {
+        let (repo, base) = checked_out("watch-fetch");
+        let before = refs_fingerprint(&repo);
+        repo.reference("refs/remotes/origin/feature", base, false, "test")
+            .unwrap();
+        assert_ne!(refs_fingerprint(&repo), before);
+    }
+
+    #[test]
+    fn test_refs_fingerprint_of_checkout() {
+        let (repo, base) = checked_out("watch-checkout");
+        repo.reference("refs/remotes/origin/feature", base, false, "test")
+            .unwrap();
+        let before = refs_fingerprint(&repo);
+        repo.set_head("refs/remotes/origin/feature").unwrap();
+        assert_ne!(refs_fingerprint(&repo), before);
+    }
+}
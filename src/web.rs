@@ -0,0 +1,224 @@
+//! Opening branches in the browser, on the page of the remote's host (GitHub, GitLab, Bitbucket
+//! or a self-hosted server with URL templates in the config).
+use crate::config::{WebConfig, WebHostConfig, WebView};
+use crate::meta::RepoMeta;
+use crate::remote::{self, RemoteUrl};
+use crate::template;
+use crate::Branch;
+use anyhow::{bail, Context, Result};
+use std::{
+    env,
+    process::{Command, Stdio},
+};
+
+/// The URL templates of a well-known host, see [`WebHostConfig`]
+fn builtin_templates(host: &str) -> Option<(&'static str, &'static str)> {
+    if host.contains("github") {
+        Some((
+            "https://{host}/{repo}/tree/{branch}",
+            "https://{host}/{repo}/compare/{base}...{branch}",
+        ))
+    } else if host.contains("gitlab") {
+        Some((
+            "https://{host}/{repo}/-/tree/{branch}",
+            "https://{host}/{repo}/-/compare/{base}...{branch}",
+        ))
+    } else if host.contains("bitbucket") {
+        Some((
+            "https://{host}/{repo}/branch/{branch}",
+            "https://{host}/{repo}/branches/compare/{branch}%0D{base}",
+        ))
+    } else {
+        None
+    }
+}
+
+/// Percent-encode `text` for a URL path, keeping `/` between the segments of a branch name
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The page of `branch` of the repository at `remote_url`
+fn page_url(
+    config: &WebConfig,
+    remote_url: &str,
+    branch: &str,
+    base: &str,
+    view: WebView,
+) -> Option<String> {
+    let url = RemoteUrl::parse(remote_url)?;
+    let path = url.path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+
+    let (branch_template, compare_template) = match config.hosts.get(&url.host) {
+        Some(WebHostConfig { branch, compare }) => (branch.as_str(), compare.as_str()),
+        None => builtin_templates(&url.host)?,
+    };
+    let template = match view {
+        WebView::Branch => branch_template,
+        WebView::Compare => compare_template,
+    };
+
+    Some(template::expand(
+        template,
+        &[
+            ("host", url.host.clone()),
+            ("repo", path.to_string()),
+            ("branch", encode(branch)),
+            ("base", encode(base)),
+        ],
+    ))
+}
+
+/// The remote that `branch` is on, and its name there. Local branches without an upstream are
+/// looked for on `origin`, or on the first remote.
+fn locate(meta: &RepoMeta, branch: &Branch) -> Result<(String, String)> {
+    let repo = meta.repo();
+    let remote_branch = match branch {
+        Branch::Local(local_branch) => local_branch.remote_name.as_ref(),
+        Branch::Remote(remote_branch) => Some(&remote_branch.name),
+        Branch::Ref(reference) => bail!("{} is not a branch, it has no page", reference.name),
+    };
+
+    if let Some(remote_branch) = remote_branch {
+        let remote = repo
+            .branch_remote_name(&format!("refs/remotes/{}", remote_branch))
+            .ok()
+            .and_then(|name| name.as_str().map(str::to_string));
+        if let Some(remote) = remote {
+            let name = remote_branch
+                .strip_prefix(&format!("{}/", remote))
+                .unwrap_or(remote_branch);
+            return Ok((remote, name.to_string()));
+        }
+    }
+
+    let name = match branch {
+        Branch::Local(local_branch) => &local_branch.name,
+        Branch::Remote(remote_branch) => &remote_branch.name,
+        Branch::Ref(reference) => &reference.name,
+    };
+    eprintln!("{} has no upstream, its page may not exist", name);
+    let remote = match meta.primary_remote() {
+        Some(remote) => remote,
+        None => bail!("This repository has no remotes to open {} on", name),
+    };
+    Ok((remote.to_string(), name.clone()))
+}
+
+/// The web page of `branch`
+fn url(meta: &RepoMeta, config: &WebConfig, branch: &Branch) -> Result<String> {
+    let (remote, name) = locate(meta, branch)?;
+    let endpoint = remote::resolve(meta.repo(), &remote)?;
+    // `--base`, named like on the remote, e.g. `release-2.1` for `origin/release-2.1`
+    let base = match meta.base() {
+        Some(base) => base
+            .name
+            .strip_prefix(&format!("{}/", remote))
+            .unwrap_or(&base.name)
+            .to_string(),
+        None => meta.default_branch(&remote),
+    };
+
+    match page_url(config, &endpoint.url, &name, &base, config.view) {
+        Some(url) => Ok(url),
+        None => bail!(
+            "Don't know the web pages of {}, add URL templates for its host under [web.hosts]",
+            endpoint.url
+        ),
+    }
+}
+
+/// Open `url` with `$BROWSER`, or with the default browser of the system
+pub fn open_url(url: &str) -> Result<()> {
+    let mut command = match env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => Command::new(browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+
+    let status = command
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to open a browser, the page is {}", url))?;
+    if !status.success() {
+        bail!("Failed to open a browser, the page is {}", url);
+    }
+
+    Ok(())
+}
+
+/// Open the pages of `branches` in the browser
+pub fn open(meta: &RepoMeta, config: &WebConfig, branches: &[Branch]) -> Result<()> {
+    for branch in branches {
+        let url = url(meta, config, branch)?;
+        eprintln!("Opening {}", url);
+        open_url(&url)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WebHostConfig;
+
+    #[test]
+    fn test_page_url() {
+        let config = WebConfig::default();
+        let page = |url, view| page_url(&config, url, "feature/a b", "main", view);
+        assert_eq!(
+            page("git@github.com:owner/repo.git", WebView::Branch).as_deref(),
+            Some("https://github.com/owner/repo/tree/feature/a%20b")
+        );
+        assert_eq!(
+            page("https://gitlab.com/group/sub/repo.git", WebView::Compare).as_deref(),
+            Some("https://gitlab.com/group/sub/repo/-/compare/main...feature/a%20b")
+        );
+        assert_eq!(
+            page("git@bitbucket.org:owner/repo.git", WebView::Branch).as_deref(),
+            Some("https://bitbucket.org/owner/repo/branch/feature/a%20b")
+        );
+        assert_eq!(
+            page("ssh://git@git.example.com/owner/repo", WebView::Branch),
+            None
+        );
+
+        let mut config = WebConfig::default();
+        config.hosts.insert(
+            "git.example.com".to_string(),
+            WebHostConfig {
+                branch: "https://{host}/{repo}/src/branch/{branch}".to_string(),
+                compare: "https://{host}/{repo}/compare/{base}...{branch}".to_string(),
+            },
+        );
+        assert_eq!(
+            page_url(
+                &config,
+                "ssh://git@git.example.com:2222/owner/repo.git",
+                "fix/#12",
+                "main",
+                WebView::Compare
+            )
+            .as_deref(),
+            Some("https://git.example.com/owner/repo/compare/main...fix/%2312")
+        );
+    }
+}
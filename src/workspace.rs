@@ -0,0 +1,233 @@
+use crate::config::{self, Keymap, WorkspaceConfig};
+use crate::context::RepoContext;
+use crate::keymap;
+use crate::skim::{event::Event, prelude::*};
+use crate::PickerOptions;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const CACHE_FILE_NAME: &str = "workspace.toml";
+
+#[derive(Clone, Debug)]
+struct Repo {
+    path: PathBuf,
+    display: String,
+}
+
+impl SkimItem for Repo {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.display)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScanCache {
+    roots: Vec<PathBuf>,
+    max_depth: usize,
+    scanned_at: u64,
+    repos: Vec<PathBuf>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn cache_path() -> Option<PathBuf> {
+    config::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+fn load_cache(config: &WorkspaceConfig) -> Option<Vec<PathBuf>> {
+    let content = fs::read_to_string(cache_path()?).ok()?;
+    let cache: ScanCache = toml::from_str(&content).ok()?;
+
+    let is_fresh = now().saturating_sub(cache.scanned_at) < config.cache_ttl;
+    if !is_fresh || cache.roots != config.roots || cache.max_depth != config.max_depth {
+        return None;
+    }
+
+    Some(
+        cache
+            .repos
+            .into_iter()
+            .filter(|repo| repo.join(".git").exists())
+            .collect(),
+    )
+}
+
+fn save_cache(config: &WorkspaceConfig, repos: &[PathBuf]) -> Result<()> {
+    let path = match cache_path() {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let cache = ScanCache {
+        roots: config.roots.clone(),
+        max_depth: config.max_depth,
+        scanned_at: now(),
+        repos: repos.to_vec(),
+    };
+    let content = toml::to_string(&cache).with_context(|| "Failed to serialize cache")?;
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Find the git repositories under `root`, descending at most `max_depth` levels.
+///
+/// Hidden directories and symlinks are skipped, and repositories are not descended into.
+fn scan_root(root: &Path, max_depth: usize) -> Vec<PathBuf> {
+    let mut repos = vec![];
+    let mut dirs = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = dirs.pop() {
+        if dir.join(".git").exists() {
+            repos.push(dir);
+            continue;
+        }
+
+        if depth >= max_depth {
+            continue;
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let is_dir = entry
+                .file_type()
+                .map(|file_type| file_type.is_dir())
+                .unwrap_or(false);
+            let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if is_dir && !is_hidden {
+                dirs.push((entry.path(), depth + 1));
+            }
+        }
+    }
+
+    repos.sort();
+    repos
+}
+
+fn scan(config: &WorkspaceConfig) -> Vec<PathBuf> {
+    config
+        .roots
+        .iter()
+        .flat_map(|root| scan_root(root, config.max_depth))
+        .collect()
+}
+
+fn display_path(path: &Path) -> String {
+    let home = config::expand_tilde(Path::new("~"));
+    match path.strip_prefix(&home) {
+        Ok(rest) if home != Path::new("~") => format!("~/{}", rest.display()),
+        _ => path.display().to_string(),
+    }
+}
+
+fn select_repo(repos: Vec<PathBuf>, accessible: bool, keymap: Keymap) -> Result<PathBuf> {
+    let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for path in repos {
+        let display = display_path(&path);
+        let _ = tx.send(Arc::new(Repo { path, display }));
+    }
+    drop(tx);
+
+    let mut builder = SkimOptionsBuilder::default();
+    keymap::apply(&mut builder, keymap, vec![]);
+    let options = builder
+        .header(Some("Select a repository"))
+        .accessible(accessible)
+        .build()
+        .with_context(|| "Failed to set up")?;
+
+    Skim::run_with(&options, Some(rx))
+        .map(|out| match out.final_event {
+            Event::EvActAbort => std::process::exit(130),
+            _ => out.selected_items,
+        })
+        .unwrap_or_default()
+        .first()
+        .and_then(|selected_item| (**selected_item).as_any().downcast_ref::<Repo>())
+        .map(|repo| repo.path.clone())
+        .with_context(|| "Failed to get selected repository")
+}
+
+/// Pick a repository from the configured workspace roots, then switch branches within it.
+///
+/// The path of the picked repository is printed so that the caller can `cd` into it.
+pub fn run(config: &WorkspaceConfig, options: &PickerOptions, rescan: bool) -> Result<()> {
+    if config.roots.is_empty() {
+        bail!(
+            "No workspace roots configured. Add them to the config file:\n\n[workspace]\nroots = [\"~/src\"]"
+        );
+    }
+
+    let cached = if rescan { None } else { load_cache(config) };
+    let repos = match cached {
+        Some(repos) => repos,
+        None => {
+            let repos = scan(config);
+            save_cache(config, &repos)?;
+            repos
+        }
+    };
+
+    if repos.is_empty() {
+        bail!("No git repositories found under the workspace roots");
+    }
+
+    let repo_path = select_repo(repos, options.accessible, options.keys.keymap)?;
+    let context = RepoContext::discover(&repo_path)
+        .with_context(|| format!("Failed to open {}", repo_path.display()))?;
+    crate::switch_branch(&context, options)?;
+    println!("{}", repo_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_scan_root() {
+        let root = TempDir::new("scan");
+        for dir in [
+            "a/.git",
+            "a/nested/.git",
+            "group/b/.git",
+            "group/deep/er/c/.git",
+            ".hidden/d/.git",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+
+        assert_eq!(
+            scan_root(&root, 2),
+            vec![root.join("a"), root.join("group/b")]
+        );
+        assert_eq!(
+            scan_root(&root, 4),
+            vec![
+                root.join("a"),
+                root.join("group/b"),
+                root.join("group/deep/er/c")
+            ]
+        );
+    }
+}